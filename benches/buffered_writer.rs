@@ -0,0 +1,64 @@
+//! Compares inserting small documents one at a time with buffering them in a `BufferedWriter`.
+//!
+//! Requires a server listening on localhost:27017. Run with `cargo bench --bench buffered_writer`.
+#[macro_use(bson, doc)]
+extern crate bson;
+extern crate mongodb_cwal as mongodb;
+
+use mongodb::{Client, ThreadedClient};
+use mongodb::coll::Collection;
+use mongodb::coll::buffered::{BufferedWriter, BufferedWriterOptions};
+use mongodb::db::ThreadedDatabase;
+
+use std::time::{Duration, Instant};
+
+const DOCUMENTS: i32 = 20_000;
+
+fn collection(client: &Client, name: &str) -> Collection {
+    let coll = client.db("bench-buffered-writer").collection(name);
+    coll.drop().unwrap();
+    coll
+}
+
+fn metric(i: i32) -> bson::Document {
+    doc! { "sensor": i % 16, "value": f64::from(i) * 0.5, "seq": i }
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+    println!(
+        "{:<24} {:>8.3} s {:>10.0} documents/s",
+        name,
+        seconds,
+        f64::from(DOCUMENTS) / seconds
+    );
+}
+
+fn main() {
+    let client = Client::connect("localhost", 27017).unwrap();
+
+    let coll = collection(&client, "insert_one");
+    let start = Instant::now();
+    for i in 0..DOCUMENTS {
+        coll.insert_one(metric(i), None).unwrap();
+    }
+    report("insert_one loop", start.elapsed());
+
+    for &max_documents in &[100, 1000] {
+        let options = BufferedWriterOptions {
+            max_documents: max_documents,
+            ..Default::default()
+        };
+        let writer = BufferedWriter::new(collection(&client, "buffered"), Some(options));
+
+        let start = Instant::now();
+        for i in 0..DOCUMENTS {
+            writer.insert(metric(i)).unwrap();
+        }
+        writer.flush().unwrap();
+        report(&format!("buffered, {} per batch", max_documents), start.elapsed());
+
+        assert!(writer.take_errors().unwrap().is_empty());
+        assert_eq!(writer.collection().count(None, None).unwrap(), i64::from(DOCUMENTS));
+    }
+}
@@ -0,0 +1,63 @@
+//! Compares iterating a cursor that requests each batch when the previous one runs out with one
+//! that prefetches the next batch in the background, while every getMore is delayed as if the
+//! server were far away and every document takes a while to process.
+//!
+//! Requires a server listening on localhost:27017. Run with
+//! `cargo bench --bench cursor_prefetch`.
+#[macro_use(bson, doc)]
+extern crate bson;
+extern crate mongodb_cwal as mongodb;
+
+use mongodb::{Client, CommandStarted, ThreadedClient};
+use mongodb::coll::Collection;
+use mongodb::coll::options::FindOptions;
+use mongodb::db::ThreadedDatabase;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+const DOCUMENTS: i32 = 500;
+const BATCH_SIZE: i32 = 50;
+const GET_MORE_DELAY_MS: u64 = 40;
+const PROCESSING_DELAY_MS: u64 = 2;
+
+fn delay_get_more(_client: Client, command_started: &CommandStarted) {
+    if command_started.command_name == "get_more" {
+        thread::sleep(Duration::from_millis(GET_MORE_DELAY_MS));
+    }
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+    println!(
+        "{:<24} {:>8.3} s {:>10.0} documents/s",
+        name,
+        seconds,
+        f64::from(DOCUMENTS) / seconds
+    );
+}
+
+fn iterate(coll: &Collection, prefetch: bool) -> Duration {
+    let options = FindOptions::builder().batch_size(BATCH_SIZE).build();
+
+    let cursor = coll.find(None, Some(options)).unwrap().with_prefetch(prefetch);
+    let start = Instant::now();
+    for doc in cursor {
+        doc.unwrap();
+        thread::sleep(Duration::from_millis(PROCESSING_DELAY_MS));
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("bench-cursor-prefetch").collection("documents");
+    coll.drop().unwrap();
+
+    let docs = (0..DOCUMENTS).map(|i| doc! { "_id": i }).collect();
+    coll.insert_many(docs, None).unwrap();
+    client.add_start_hook(delay_get_more).unwrap();
+
+    report("without prefetch", iterate(&coll, false));
+    report("with prefetch", iterate(&coll, true));
+}
@@ -0,0 +1,55 @@
+//! Compares scanning a whole collection with a cursor that requests each batch with a getMore
+//! with an exhaust cursor, which has the server stream every batch without being asked.
+//!
+//! Requires a server listening on localhost:27017. Run with
+//! `cargo bench --bench exhaust_cursor`.
+#[macro_use(bson, doc)]
+extern crate bson;
+extern crate mongodb_cwal as mongodb;
+
+use mongodb::{Client, ThreadedClient};
+use mongodb::coll::Collection;
+use mongodb::coll::options::FindOptions;
+use mongodb::db::ThreadedDatabase;
+
+use std::time::{Duration, Instant};
+
+const DOCUMENTS: i32 = 1_000_000;
+const INSERT_BATCH_SIZE: i32 = 10_000;
+
+fn report(name: &str, elapsed: Duration) {
+    let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+    println!(
+        "{:<24} {:>8.3} s {:>10.0} documents/s",
+        name,
+        seconds,
+        f64::from(DOCUMENTS) / seconds
+    );
+}
+
+fn scan(coll: &Collection, exhaust: bool) -> Duration {
+    let options = FindOptions::builder().exhaust(exhaust).build();
+
+    let start = Instant::now();
+    let count = coll.find(None, Some(options)).unwrap().map(|doc| doc.unwrap()).count();
+    let elapsed = start.elapsed();
+
+    assert_eq!(count, DOCUMENTS as usize);
+    elapsed
+}
+
+fn main() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("bench-exhaust-cursor").collection("documents");
+    coll.drop().unwrap();
+
+    for start in (0..DOCUMENTS).step_by(INSERT_BATCH_SIZE as usize) {
+        let docs = (start..start + INSERT_BATCH_SIZE)
+            .map(|i| doc! { "_id": i, "x": i % 7 })
+            .collect();
+        coll.insert_many(docs, None).unwrap();
+    }
+
+    report("getMore per batch", scan(&coll, false));
+    report("exhaust", scan(&coll, true));
+}
@@ -0,0 +1,57 @@
+//! Compares reading a field of the first document of a reply of large documents, which only
+//! decodes that document, with decoding every document of the reply up front, as replies were
+//! before their documents were decoded lazily. Both include the scan that validates the reply.
+//!
+//! Doesn't need a server. Run with `cargo bench --bench lazy_reply`.
+#[macro_use(bson, doc)]
+extern crate bson;
+extern crate mongodb_cwal as mongodb;
+
+use mongodb::wire_protocol::operations::ReplyDocuments;
+
+use std::time::{Duration, Instant};
+
+const DOCUMENTS: usize = 100;
+const PADDING_BYTES: usize = 100_000;
+const ROUNDS: u32 = 50;
+
+fn report(name: &str, elapsed: Duration) {
+    let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+    println!(
+        "{:<24} {:>8.3} s {:>10.3} ms/reply",
+        name,
+        seconds,
+        seconds * 1e3 / f64::from(ROUNDS)
+    );
+}
+
+fn read_first<F>(bytes: &[u8], read: F) -> Duration
+where
+    F: Fn(ReplyDocuments) -> bson::Document,
+{
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let reply = ReplyDocuments::new(bytes.to_vec()).unwrap();
+        let first = read(reply);
+        assert_eq!(first.get_i32("_id").unwrap(), 0);
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let padding: String = (0..PADDING_BYTES).map(|_| 'x').collect();
+    let mut bytes = Vec::new();
+    for i in 0..DOCUMENTS {
+        let doc = doc! { "_id": i as i32, "padding": padding.clone() };
+        bson::encode_document(&mut bytes, &doc).unwrap();
+    }
+
+    report(
+        "lazy first document",
+        read_first(&bytes, |mut reply| reply.next().unwrap().unwrap()),
+    );
+    report(
+        "eager whole reply",
+        read_first(&bytes, |reply| reply.to_documents().unwrap().remove(0)),
+    );
+}
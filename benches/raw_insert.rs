@@ -0,0 +1,67 @@
+//! Compares inserting pre-encoded documents by decoding them for `insert_many` with passing them
+//! through to `insert_many_raw`.
+//!
+//! Requires a server listening on localhost:27017. Run with `cargo bench --bench raw_insert`.
+#[macro_use(bson, doc)]
+extern crate bson;
+extern crate mongodb_cwal as mongodb;
+
+use mongodb::{Client, ThreadedClient};
+use mongodb::coll::Collection;
+use mongodb::db::ThreadedDatabase;
+
+use std::io;
+use std::time::{Duration, Instant};
+
+const DOCUMENTS: i32 = 50_000;
+
+fn collection(client: &Client, name: &str) -> Collection {
+    let coll = client.db("bench-raw-insert").collection(name);
+    coll.drop().unwrap();
+    coll
+}
+
+fn encoded(i: i32) -> Vec<u8> {
+    let doc = doc! {
+        "sensor": i % 16,
+        "value": f64::from(i) * 0.5,
+        "seq": i,
+        "tags": ["a", "b", "c"],
+        "reading": { "min": 0, "max": f64::from(i) },
+    };
+
+    let mut bytes = Vec::new();
+    bson::encode_document(&mut bytes, &doc).unwrap();
+    bytes
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+    println!(
+        "{:<24} {:>8.3} s {:>10.0} documents/s",
+        name,
+        seconds,
+        f64::from(DOCUMENTS) / seconds
+    );
+}
+
+fn main() {
+    let client = Client::connect("localhost", 27017).unwrap();
+
+    let coll = collection(&client, "insert_many");
+    let docs: Vec<_> = (0..DOCUMENTS).map(encoded).collect();
+    let start = Instant::now();
+    let decoded: Vec<_> = docs.iter()
+        .map(|bytes| bson::decode_document(&mut io::Cursor::new(bytes)).unwrap())
+        .collect();
+    coll.insert_many(decoded, None).unwrap();
+    report("decode and insert_many", start.elapsed());
+    assert_eq!(coll.count(None, None).unwrap(), i64::from(DOCUMENTS));
+
+    let coll = collection(&client, "insert_many_raw");
+    let docs: Vec<_> = (0..DOCUMENTS).map(encoded).collect();
+    let start = Instant::now();
+    coll.insert_many_raw(docs, None).unwrap();
+    report("insert_many_raw", start.elapsed());
+    assert_eq!(coll.count(None, None).unwrap(), i64::from(DOCUMENTS));
+}
@@ -0,0 +1,55 @@
+//! Compares inserting documents one at a time with an unacknowledged write concern, which sends
+//! them back to back without waiting for replies, with the default acknowledged one.
+//!
+//! Requires a server listening on localhost:27017. Run with
+//! `cargo bench --bench unacknowledged_insert`.
+#[macro_use(bson, doc)]
+extern crate bson;
+extern crate mongodb_cwal as mongodb;
+
+use mongodb::{Client, ThreadedClient};
+use mongodb::coll::Collection;
+use mongodb::common::WriteConcern;
+use mongodb::db::ThreadedDatabase;
+
+use std::time::{Duration, Instant};
+
+const DOCUMENTS: i32 = 100_000;
+
+fn collection(client: &Client, name: &str) -> Collection {
+    let coll = client.db("bench-unacknowledged-insert").collection(name);
+    coll.drop().unwrap();
+    coll
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let seconds = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+    println!(
+        "{:<24} {:>8.3} s {:>10.0} documents/s",
+        name,
+        seconds,
+        f64::from(DOCUMENTS) / seconds
+    );
+}
+
+fn insert(coll: &Collection, write_concern: &WriteConcern) -> Duration {
+    let start = Instant::now();
+    for i in 0..DOCUMENTS {
+        coll.insert_one(doc! { "_id": i }, Some(write_concern.clone())).unwrap();
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let client = Client::connect("localhost", 27017).unwrap();
+
+    let mut unacknowledged = WriteConcern::new();
+    unacknowledged.w = 0;
+
+    let coll = collection(&client, "w0");
+    report("insert_one with w: 0", insert(&coll, &unacknowledged));
+
+    let coll = collection(&client, "w1");
+    report("insert_one with w: 1", insert(&coll, &WriteConcern::new()));
+    assert_eq!(coll.count(None, None).unwrap(), i64::from(DOCUMENTS));
+}
@@ -0,0 +1,18 @@
+use std::env;
+use std::process::Command;
+
+// Records the compiler version so that it can be reported to the server during the connection
+// handshake.
+fn main() {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|version| version.trim().to_owned())
+        .unwrap_or_else(|| String::from("rustc"));
+
+    println!("cargo:rustc-env=MONGODB_RUSTC_VERSION={}", version);
+}
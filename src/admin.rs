@@ -0,0 +1,487 @@
+//! Types returned by the administrative helpers on `ThreadedClient`.
+use bson::{self, Bson};
+use chrono::{DateTime, Duration, Utc};
+
+use Error::{ArgumentError, ResponseError};
+use Result;
+
+use std::fmt;
+
+// The `state` of a primary and a secondary in `replSetGetStatus`.
+const PRIMARY_STATE: i64 = 1;
+const SECONDARY_STATE: i64 = 2;
+
+/// A database on the server, as returned by `list_databases`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DatabaseInfo {
+    /// The name of the database.
+    pub name: String,
+    /// The size of the database's files, in bytes. None when only names were requested.
+    pub size_on_disk: Option<i64>,
+    /// Whether the database holds no data. None when only names were requested.
+    pub empty: Option<bool>,
+}
+
+impl DatabaseInfo {
+    /// Parses an entry of the `databases` array returned by `listDatabases`.
+    pub fn from_document(doc: &bson::Document) -> Result<DatabaseInfo> {
+        let name = match doc.get("name") {
+            Some(&Bson::String(ref name)) => name.to_owned(),
+            Some(value) => {
+                return Err(ResponseError(
+                    format!("Database entry has a non-string 'name': {}.", value),
+                ))
+            }
+            None => {
+                return Err(ResponseError(format!("Database entry is missing 'name': {}.", doc)))
+            }
+        };
+
+        let empty = match doc.get("empty") {
+            Some(&Bson::Boolean(empty)) => Some(empty),
+            _ => None,
+        };
+
+        Ok(DatabaseInfo {
+            name: name,
+            size_on_disk: doc.get("sizeOnDisk").and_then(bson_to_i64),
+            empty: empty,
+        })
+    }
+
+    /// Returns true if the database matches a `listDatabases` filter, for servers before
+    /// MongoDB 3.6, which ignore filters. Only exact values of `name`, `sizeOnDisk` and `empty`
+    /// can be matched; other fields and query operators are rejected.
+    pub fn matches(&self, filter: &bson::Document) -> Result<bool> {
+        for (field, value) in filter {
+            let matched = match (field.as_str(), value) {
+                ("name", &Bson::String(ref name)) => self.name == *name,
+                ("sizeOnDisk", value) if bson_to_i64(value).is_some() => {
+                    self.size_on_disk == bson_to_i64(value)
+                }
+                ("empty", &Bson::Boolean(empty)) => self.empty == Some(empty),
+                _ => {
+                    return Err(ArgumentError(format!(
+                        "Servers older than MongoDB 3.6 can only filter databases by exact \
+                         name, sizeOnDisk or empty, not by {}: {}.",
+                        field,
+                        value
+                    )))
+                }
+            };
+
+            if !matched {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Identifies an operation in progress on a server.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum OpId {
+    /// An operation on a standalone server or replica set member.
+    Int(i64),
+    /// An operation reported by a mongos, of the form `shard:opid`.
+    Sharded(String),
+}
+
+impl OpId {
+    /// Parses an operation id as reported by `currentOp`.
+    pub fn from_bson(bson: &Bson) -> Option<OpId> {
+        match *bson {
+            Bson::I32(opid) => Some(OpId::Int(i64::from(opid))),
+            Bson::I64(opid) => Some(OpId::Int(opid)),
+            Bson::String(ref opid) => Some(OpId::Sharded(opid.to_owned())),
+            _ => None,
+        }
+    }
+
+    /// Returns the operation id in the form expected by `killOp`.
+    pub fn to_bson(&self) -> Bson {
+        match *self {
+            OpId::Int(opid) => Bson::I64(opid),
+            OpId::Sharded(ref opid) => Bson::String(opid.to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for OpId {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OpId::Int(opid) => write!(fmt, "{}", opid),
+            OpId::Sharded(ref opid) => fmt.write_str(opid),
+        }
+    }
+}
+
+/// An operation in progress, as reported by `currentOp`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CurrentOp {
+    /// The id to pass to `kill_op` to terminate the operation.
+    pub opid: OpId,
+    /// The type of operation, e.g. `query`, `insert`, `command` or `none` for idle connections.
+    pub op: String,
+    /// The namespace the operation runs against; empty if it has none.
+    pub ns: String,
+    /// How long the operation has been running, in seconds.
+    pub secs_running: Option<i64>,
+    /// The command or query being run.
+    pub query: Option<bson::Document>,
+    /// The address of the client that started the operation.
+    pub client: Option<String>,
+    /// The full `currentOp` document.
+    pub doc: bson::Document,
+}
+
+impl CurrentOp {
+    /// Parses an entry of the `inprog` array returned by `currentOp`.
+    pub fn from_document(doc: bson::Document) -> Result<CurrentOp> {
+        let opid = match doc.get("opid").and_then(OpId::from_bson) {
+            Some(opid) => opid,
+            None => return Err(ResponseError(String::from("Operation is missing 'opid'."))),
+        };
+
+        let op = match doc.get("op") {
+            Some(&Bson::String(ref op)) => op.to_owned(),
+            _ => return Err(ResponseError(String::from("Operation is missing 'op'."))),
+        };
+
+        let ns = match doc.get("ns") {
+            Some(&Bson::String(ref ns)) => ns.to_owned(),
+            _ => String::new(),
+        };
+
+        let secs_running = match doc.get("secs_running") {
+            Some(&Bson::I32(secs)) => Some(i64::from(secs)),
+            Some(&Bson::I64(secs)) => Some(secs),
+            _ => None,
+        };
+
+        // Servers since 3.6 report the full command, older ones the query.
+        let query = match (doc.get("command"), doc.get("query")) {
+            (Some(&Bson::Document(ref command)), _) => Some(command.clone()),
+            (_, Some(&Bson::Document(ref query))) => Some(query.clone()),
+            _ => None,
+        };
+
+        // A mongos reports the client address as `client_s`.
+        let client = match (doc.get("client"), doc.get("client_s")) {
+            (Some(&Bson::String(ref client)), _) |
+            (_, Some(&Bson::String(ref client))) => Some(client.to_owned()),
+            _ => None,
+        };
+
+        Ok(CurrentOp {
+            opid: opid,
+            op: op,
+            ns: ns,
+            secs_running: secs_running,
+            query: query,
+            client: client,
+            doc: doc,
+        })
+    }
+}
+
+/// A member of a replica set, as configured and as last reported by `replSetGetStatus`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplicaSetMember {
+    /// The member's `_id` in the replica set configuration.
+    pub id: i64,
+    /// The member's address, as `host:port`.
+    pub host: String,
+    /// The member's priority in elections; members with priority 0 never become primary.
+    pub priority: f64,
+    /// The number of votes the member casts in elections.
+    pub votes: i64,
+    /// Whether the member is hidden from clients.
+    pub hidden: bool,
+    /// Whether the member is an arbiter, which holds no data.
+    pub arbiter_only: bool,
+    /// The member's state, e.g. 1 for a primary or 2 for a secondary.
+    pub state: Option<i32>,
+    /// The name of the member's state, e.g. `PRIMARY` or `SECONDARY`.
+    pub state_str: Option<String>,
+    /// Whether the member is reachable from the server that reported the status.
+    pub healthy: Option<bool>,
+    /// The timestamp of the last operation applied by the member.
+    pub optime: Option<i64>,
+    /// The wall clock time of the last operation applied by the member.
+    pub optime_date: Option<DateTime<Utc>>,
+}
+
+/// The configuration and status of a replica set.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplicaSetConfig {
+    /// The name of the replica set.
+    pub set_name: String,
+    /// The version of the configuration, incremented on every reconfiguration.
+    pub version: i64,
+    /// Every member of the replica set, including arbiters and hidden members.
+    pub members: Vec<ReplicaSetMember>,
+}
+
+impl ReplicaSetConfig {
+    /// Merges the `config` document returned by `replSetGetConfig` with the reply to
+    /// `replSetGetStatus`.
+    pub fn from_documents(
+        config: &bson::Document,
+        status: &bson::Document,
+    ) -> Result<ReplicaSetConfig> {
+        let set_name = match config.get("_id") {
+            Some(&Bson::String(ref name)) => name.to_owned(),
+            _ => return Err(ResponseError(String::from("Configuration is missing '_id'."))),
+        };
+
+        let version = match config.get("version").and_then(bson_to_i64) {
+            Some(version) => version,
+            None => return Err(ResponseError(String::from("Configuration is missing 'version'."))),
+        };
+
+        let statuses: &[Bson] = match status.get("members") {
+            Some(&Bson::Array(ref members)) => members,
+            _ => &[],
+        };
+
+        let members = match config.get("members") {
+            Some(&Bson::Array(ref members)) => members,
+            _ => return Err(ResponseError(String::from("Configuration is missing 'members'."))),
+        };
+
+        let members = members
+            .iter()
+            .map(|member| match *member {
+                Bson::Document(ref member) => ReplicaSetMember::from_documents(member, statuses),
+                _ => Err(ResponseError(
+                    String::from("Received a non-document member from the server."),
+                )),
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(ReplicaSetConfig {
+            set_name: set_name,
+            version: version,
+            members: members,
+        })
+    }
+
+    /// Returns the member reported as primary, if any.
+    pub fn primary(&self) -> Option<&ReplicaSetMember> {
+        self.members.iter().find(|member| member.state == Some(1))
+    }
+
+    /// Returns how far `member` lags behind the primary, or None if there is no primary or
+    /// either optime is unknown.
+    pub fn replication_lag(&self, member: &ReplicaSetMember) -> Option<Duration> {
+        let primary_date = self.primary().and_then(|primary| primary.optime_date)?;
+        let member_date = member.optime_date?;
+        Some(primary_date.signed_duration_since(member_date))
+    }
+}
+
+impl ReplicaSetMember {
+    // Parses a member of the configuration and merges in the status entry with the same `_id`.
+    fn from_documents(config: &bson::Document, statuses: &[Bson]) -> Result<ReplicaSetMember> {
+        let id = match config.get("_id").and_then(bson_to_i64) {
+            Some(id) => id,
+            None => return Err(ResponseError(String::from("Member is missing '_id'."))),
+        };
+
+        let host = match config.get("host") {
+            Some(&Bson::String(ref host)) => host.to_owned(),
+            _ => return Err(ResponseError(String::from("Member is missing 'host'."))),
+        };
+
+        let priority = match config.get("priority") {
+            Some(&Bson::FloatingPoint(priority)) => priority,
+            Some(value) => bson_to_i64(value).map_or(1.0, |priority| priority as f64),
+            None => 1.0,
+        };
+
+        let votes = config.get("votes").and_then(bson_to_i64).unwrap_or(1);
+
+        let hidden = match config.get("hidden") {
+            Some(&Bson::Boolean(hidden)) => hidden,
+            _ => false,
+        };
+
+        let arbiter_only = match config.get("arbiterOnly") {
+            Some(&Bson::Boolean(arbiter_only)) => arbiter_only,
+            _ => false,
+        };
+
+        let status = statuses
+            .iter()
+            .filter_map(|status| match *status {
+                Bson::Document(ref status) => Some(status),
+                _ => None,
+            })
+            .find(|status| status.get("_id").and_then(bson_to_i64) == Some(id));
+
+        let mut member = ReplicaSetMember {
+            id: id,
+            host: host,
+            priority: priority,
+            votes: votes,
+            hidden: hidden,
+            arbiter_only: arbiter_only,
+            state: None,
+            state_str: None,
+            healthy: None,
+            optime: None,
+            optime_date: None,
+        };
+
+        let status = match status {
+            Some(status) => status,
+            None => return Ok(member),
+        };
+
+        member.state = status.get("state").and_then(bson_to_i64).map(|state| state as i32);
+
+        if let Some(&Bson::String(ref state_str)) = status.get("stateStr") {
+            member.state_str = Some(state_str.to_owned());
+        }
+
+        member.healthy = match status.get("health") {
+            Some(&Bson::FloatingPoint(health)) => Some(health != 0.0),
+            Some(value) => bson_to_i64(value).map(|health| health != 0),
+            None => None,
+        };
+
+        // Members running protocol version 1 report the optime together with their term.
+        member.optime = match status.get("optime") {
+            Some(&Bson::TimeStamp(ts)) => Some(ts),
+            Some(&Bson::Document(ref optime)) => match optime.get("ts") {
+                Some(&Bson::TimeStamp(ts)) => Some(ts),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        if let Some(&Bson::UtcDatetime(date)) = status.get("optimeDate") {
+            member.optime_date = Some(date);
+        }
+
+        Ok(member)
+    }
+}
+
+/// How far a replica set member lags behind the primary, as returned by `replication_lag`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemberLag {
+    /// The member's address, as `host:port`.
+    pub host: String,
+    /// The name of the member's state, e.g. `PRIMARY`, `SECONDARY` or `RECOVERING`.
+    pub state: Option<String>,
+    /// How many seconds the last operation the member applied is behind the primary's last
+    /// operation. None for members that are neither primary nor secondary, or when there is
+    /// no primary or either optime is unknown.
+    pub lag_seconds: Option<i64>,
+    /// Whether the member is reachable from the server that reported the status.
+    pub health: Option<bool>,
+}
+
+impl MemberLag {
+    /// Computes the lag of every member listed in a `replSetGetStatus` reply, from the
+    /// `optimeDate` of each member or, if absent, the seconds of its `optime`. Optimes are
+    /// read as timestamps (before protocol version 1), as `{ ts, t }` documents (protocol
+    /// version 1), or as `{ t, i }` pairs of seconds and increment (before MongoDB 2.6).
+    pub fn from_status(status: &bson::Document) -> Result<Vec<MemberLag>> {
+        let members = match status.get("members") {
+            Some(&Bson::Array(ref members)) => members,
+            _ => return Err(ResponseError(String::from("Status is missing 'members'."))),
+        };
+
+        let members: Vec<&bson::Document> = members
+            .iter()
+            .map(|member| match *member {
+                Bson::Document(ref member) => Ok(member),
+                _ => Err(ResponseError(
+                    String::from("Received a non-document member from the server."),
+                )),
+            })
+            .collect::<Result<_>>()?;
+
+        let primary_millis = members
+            .iter()
+            .find(|member| member_state(member) == Some(PRIMARY_STATE))
+            .and_then(|primary| optime_millis(primary));
+
+        members
+            .iter()
+            .map(|member| {
+                let host = match member.get("name") {
+                    Some(&Bson::String(ref host)) => host.to_owned(),
+                    _ => return Err(ResponseError(String::from("Member is missing 'name'."))),
+                };
+
+                let state = match member.get("stateStr") {
+                    Some(&Bson::String(ref state)) => Some(state.to_owned()),
+                    _ => None,
+                };
+
+                let health = match member.get("health") {
+                    Some(&Bson::FloatingPoint(health)) => Some(health != 0.0),
+                    Some(value) => bson_to_i64(value).map(|health| health != 0),
+                    None => None,
+                };
+
+                let lag_seconds = match member_state(member) {
+                    Some(PRIMARY_STATE) | Some(SECONDARY_STATE) => {
+                        primary_millis.and_then(|primary| {
+                            // A member may briefly report an optime ahead of the primary's.
+                            optime_millis(member).map(|optime| (primary - optime).max(0) / 1000)
+                        })
+                    }
+                    _ => None,
+                };
+
+                Ok(MemberLag {
+                    host: host,
+                    state: state,
+                    lag_seconds: lag_seconds,
+                    health: health,
+                })
+            })
+            .collect()
+    }
+}
+
+fn member_state(member: &bson::Document) -> Option<i64> {
+    member.get("state").and_then(bson_to_i64)
+}
+
+// Returns the wall clock time of the last operation a member applied, in milliseconds since
+// the epoch.
+fn optime_millis(member: &bson::Document) -> Option<i64> {
+    if let Some(&Bson::UtcDatetime(date)) = member.get("optimeDate") {
+        return Some(date.timestamp_millis());
+    }
+
+    let seconds = match member.get("optime") {
+        Some(&Bson::TimeStamp(ts)) => ts >> 32,
+        Some(&Bson::Document(ref optime)) => {
+            match (optime.get("ts"), optime.get("t"), optime.get("i")) {
+                (Some(&Bson::TimeStamp(ts)), _, _) => ts >> 32,
+                (None, Some(seconds), Some(_)) => bson_to_i64(seconds)?,
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+
+    Some(seconds * 1000)
+}
+
+// Reads a number that the server may send as an integer of either width or a double.
+fn bson_to_i64(bson: &Bson) -> Option<i64> {
+    match *bson {
+        Bson::I32(value) => Some(i64::from(value)),
+        Bson::I64(value) => Some(value),
+        Bson::FloatingPoint(value) => Some(value as i64),
+        _ => None,
+    }
+}
@@ -1,8 +1,11 @@
 use std::fmt::{Display, Error, Formatter};
 
 use bson::Document;
+use connstring::Host;
 use error::Error as MongoError;
 use separator::Separatable;
+use topology::TopologyInfo;
+use topology::server::ServerDescription;
 
 /// Contains the information about a given command that started.
 #[derive(Debug, Clone, PartialEq)]
@@ -12,6 +15,7 @@ pub struct CommandStarted {
     pub command_name: String,
     pub request_id: i64,
     pub connection_string: String,
+    pub host: Host,
 }
 
 impl Display for CommandStarted {
@@ -35,6 +39,7 @@ pub enum CommandResult<'a> {
         command_name: String,
         request_id: i64,
         connection_string: String,
+        host: Host,
     },
     Failure {
         duration: u64,
@@ -42,6 +47,7 @@ pub enum CommandResult<'a> {
         failure: &'a MongoError,
         request_id: i64,
         connection_string: String,
+        host: Host,
     },
 }
 
@@ -83,3 +89,39 @@ impl<'a> Display for CommandResult<'a> {
         }
     }
 }
+
+/// Describes a change in the driver's view of the deployment, or a check of a single server by
+/// its monitor.
+#[derive(Debug, Clone)]
+pub enum SdamEvent<'a> {
+    /// A server was added to the topology and is now being monitored.
+    ServerOpened { host: Host },
+    /// A server was removed from the topology and is no longer monitored.
+    ServerClosed { host: Host },
+    /// A server check changed the description of a server. Round trip times are not compared.
+    ServerDescriptionChanged {
+        host: Host,
+        previous_description: ServerDescription,
+        new_description: ServerDescription,
+    },
+    /// The topology changed, e.g. because a primary was elected or a server was discovered.
+    /// Round trip times are not compared.
+    TopologyDescriptionChanged {
+        previous_description: TopologyInfo,
+        new_description: TopologyInfo,
+    },
+    /// A monitor is about to send isMaster to a server.
+    ServerHeartbeatStarted { host: Host },
+    /// A server responded to isMaster.
+    ServerHeartbeatSucceeded {
+        host: Host,
+        duration: u64,
+        reply: Document,
+    },
+    /// A server check failed.
+    ServerHeartbeatFailed {
+        host: Host,
+        duration: u64,
+        failure: &'a MongoError,
+    },
+}
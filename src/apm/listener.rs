@@ -2,18 +2,20 @@ use std::ops::{Deref, DerefMut};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::RwLock;
 
-use apm::event::{CommandStarted, CommandResult};
+use apm::event::{CommandStarted, CommandResult, SdamEvent};
 use Client;
 use error::Result;
 
 pub type StartHook = fn(Client, &CommandStarted);
 pub type CompletionHook = fn(Client, &CommandResult);
+pub type SdamListener = fn(Client, &SdamEvent);
 
 pub struct Listener {
     no_start_hooks: AtomicBool,
     no_completion_hooks: AtomicBool,
     start_hooks: RwLock<Vec<StartHook>>,
     completion_hooks: RwLock<Vec<CompletionHook>>,
+    sdam_listener: RwLock<Option<SdamListener>>,
 }
 
 impl Listener {
@@ -23,6 +25,7 @@ impl Listener {
             no_completion_hooks: AtomicBool::new(true),
             start_hooks: RwLock::new(Vec::new()),
             completion_hooks: RwLock::new(Vec::new()),
+            sdam_listener: RwLock::new(None),
         }
     }
 
@@ -38,6 +41,20 @@ impl Listener {
         Ok(guard.deref_mut().push(hook))
     }
 
+    pub fn set_sdam_listener(&self, listener: SdamListener) -> Result<()> {
+        let mut guard = self.sdam_listener.write()?;
+        *guard = Some(listener);
+        Ok(())
+    }
+
+    pub fn has_start_hooks(&self) -> bool {
+        !self.no_start_hooks.load(Ordering::SeqCst)
+    }
+
+    pub fn has_completion_hooks(&self) -> bool {
+        !self.no_completion_hooks.load(Ordering::SeqCst)
+    }
+
     pub fn run_start_hooks(&self, client: Client, started: &CommandStarted) -> Result<()> {
         if self.no_start_hooks.load(Ordering::SeqCst) {
             return Ok(());
@@ -65,4 +82,14 @@ impl Listener {
 
         Ok(())
     }
+
+    pub fn run_sdam_listener(&self, client: Client, event: &SdamEvent) -> Result<()> {
+        let listener = *self.sdam_listener.read()?;
+
+        if let Some(listener) = listener {
+            listener(client, event);
+        }
+
+        Ok(())
+    }
 }
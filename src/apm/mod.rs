@@ -4,10 +4,13 @@
 //! information about commands being executed on the server. All non-suppressed commands trigger
 //! start and completion hooks defined on the client. Each non-suppressed command is also logged,
 //! if a log file was specified during instantiation of the client.
+//!
+//! Server discovery and monitoring events, such as servers being added to the topology or
+//! heartbeats failing, are reported to an optional SDAM listener on the client.
 pub mod client;
 mod event;
 mod listener;
 
 pub use self::client::EventRunner;
-pub use self::event::{CommandStarted, CommandResult};
+pub use self::event::{CommandStarted, CommandResult, SdamEvent};
 pub use self::listener::Listener;
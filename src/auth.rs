@@ -10,7 +10,7 @@ use coll::options::FindOptions;
 use cursor::Cursor;
 use data_encoding::BASE64;
 use error::{
-    Error::{DefaultError, MaliciousServerError, OperationError, ResponseError},
+    Error::{CommandError, DefaultError, MaliciousServerError, OperationError, ResponseError},
     MaliciousServerErrorType, Result,
 };
 use hex;
@@ -79,7 +79,7 @@ impl Authenticator<'_> {
         };
 
         let nonce = format!("{}", text_nonce);
-        let message = format!("n={},r={}", user, nonce);
+        let message = format!("n={},r={}", escape_sasl_name(user), nonce);
         let bytes = format!("n,,{}", message).into_bytes();
         let binary = Binary(Generic, bytes);
 
@@ -320,6 +320,9 @@ impl Authenticator<'_> {
 
         match cursor.next() {
             Some(Ok(bson)) => Ok(bson),
+            // Rejected credentials are reported as an `OperationError` with the server's
+            // message, as they were before command failures kept their codes.
+            Some(Err(CommandError(failure))) => Err(OperationError(failure.message)),
             Some(Err(err)) => Err(err),
             None => Err(OperationError(
                 "(Auth) failed to execute command".to_owned(),
@@ -327,3 +330,22 @@ impl Authenticator<'_> {
         }
     }
 }
+
+// Escapes a username for use in a SCRAM message, where ',' separates attributes and '=' starts an
+// escape sequence (RFC 5802, section 5.1).
+fn escape_sasl_name(name: &str) -> String {
+    name.replace('=', "=3D").replace(',', "=2C")
+}
+
+#[cfg(test)]
+mod test {
+    use super::escape_sasl_name;
+
+    #[test]
+    fn escape_username() {
+        assert_eq!("saghm", escape_sasl_name("saghm"));
+        assert_eq!("a=2Cb=3Dc", escape_sasl_name("a,b=c"));
+        assert_eq!("=3D2C", escape_sasl_name("=2C"));
+        assert_eq!("o'brien{}", escape_sasl_name("o'brien{}"));
+    }
+}
@@ -1,5 +1,5 @@
 //! Models for collection-level batch operations.
-use super::options::WriteModel;
+use super::options::{Collation, Hint, WriteModel};
 
 use bson::{Bson, bson, Document, doc};
 use std::convert::From;
@@ -8,6 +8,8 @@ use std::convert::From;
 pub struct DeleteModel {
     pub filter: Document,
     pub multi: bool,
+    pub hint: Option<Hint>,
+    pub collation: Option<Collation>,
 }
 
 impl DeleteModel {
@@ -15,6 +17,8 @@ impl DeleteModel {
         DeleteModel {
             filter: filter,
             multi: multi,
+            hint: None,
+            collation: None,
         }
     }
 }
@@ -25,6 +29,9 @@ pub struct UpdateModel {
     pub update: Document,
     pub upsert: Option<bool>,
     pub multi: bool,
+    pub hint: Option<Hint>,
+    pub collation: Option<Collation>,
+    pub array_filters: Option<Vec<Document>>,
 }
 
 impl UpdateModel {
@@ -39,6 +46,9 @@ impl UpdateModel {
             update: update,
             upsert: upsert,
             multi: multi,
+            hint: None,
+            collation: None,
+            array_filters: None,
         }
     }
 }
@@ -59,85 +69,168 @@ impl From<UpdateModel> for Document {
             document.insert("multi", Bson::Boolean(true));
         }
 
+        if let Some(hint) = model.hint {
+            document.insert("hint", hint.to_bson());
+        }
+
+        if let Some(collation) = model.collation {
+            document.insert("collation", collation.to_document());
+        }
+
+        if let Some(array_filters) = model.array_filters {
+            let array_filters: Vec<_> = array_filters.into_iter().map(Bson::Document).collect();
+            document.insert("arrayFilters", array_filters);
+        }
+
         document
     }
 }
 
+/// A single write, converted to the entry sent for it in a write command.
 #[derive(Debug, Clone, PartialEq)]
-pub enum Batch {
-    Insert(Vec<Document>),
-    Delete(Vec<DeleteModel>),
-    Update(Vec<UpdateModel>),
+pub enum BatchEntry {
+    Insert(Document),
+    Delete(DeleteModel),
+    Update(UpdateModel),
 }
 
-impl From<WriteModel> for Batch {
-    fn from(model: WriteModel) -> Batch {
+impl From<WriteModel> for BatchEntry {
+    fn from(model: WriteModel) -> BatchEntry {
         match model {
-            WriteModel::InsertOne { document } => Batch::Insert(vec![document]),
-            WriteModel::DeleteOne { filter } => {
-                Batch::Delete(vec![
-                    DeleteModel {
-                        filter: filter,
-                        multi: false,
-                    },
-                ])
+            WriteModel::InsertOne { document } => BatchEntry::Insert(document),
+            WriteModel::DeleteOne { filter, collation, hint } => {
+                BatchEntry::Delete(DeleteModel {
+                    filter: filter,
+                    multi: false,
+                    hint: hint,
+                    collation: collation,
+                })
             }
-            WriteModel::DeleteMany { filter } => {
-                Batch::Delete(vec![
-                    DeleteModel {
-                        filter: filter,
-                        multi: true,
-                    },
-                ])
+            WriteModel::DeleteMany { filter, collation, hint } => {
+                BatchEntry::Delete(DeleteModel {
+                    filter: filter,
+                    multi: true,
+                    hint: hint,
+                    collation: collation,
+                })
             }
             WriteModel::ReplaceOne {
                 filter,
-                replacement: update,
+                replacement,
                 upsert,
-            } |
+                collation,
+                hint,
+            } => {
+                BatchEntry::Update(UpdateModel {
+                    filter: filter,
+                    update: replacement,
+                    upsert: upsert,
+                    multi: false,
+                    hint: hint,
+                    collation: collation,
+                    array_filters: None,
+                })
+            }
             WriteModel::UpdateOne {
                 filter,
                 update,
                 upsert,
+                collation,
+                hint,
+                array_filters,
             } => {
-                Batch::Update(vec![
-                    UpdateModel {
-                        filter: filter,
-                        update: update,
-                        upsert: upsert,
-                        multi: false,
-                    },
-                ])
+                BatchEntry::Update(UpdateModel {
+                    filter: filter,
+                    update: update,
+                    upsert: upsert,
+                    multi: false,
+                    hint: hint,
+                    collation: collation,
+                    array_filters: array_filters,
+                })
             }
             WriteModel::UpdateMany {
                 filter,
                 update,
                 upsert,
+                collation,
+                hint,
+                array_filters,
             } => {
-                Batch::Update(vec![
-                    UpdateModel {
-                        filter: filter,
-                        update: update,
-                        upsert: upsert,
-                        multi: true,
-                    },
-                ])
+                BatchEntry::Update(UpdateModel {
+                    filter: filter,
+                    update: update,
+                    upsert: upsert,
+                    multi: true,
+                    hint: hint,
+                    collation: collation,
+                    array_filters: array_filters,
+                })
             }
         }
     }
 }
 
-impl Batch {
-    pub fn len(&self) -> i64 {
-        let length = match *self {
-            Batch::Insert(ref v) => v.len(),
-            Batch::Delete(ref v) => v.len(),
-            Batch::Update(ref v) => v.len(),
-        };
+impl From<DeleteModel> for WriteModel {
+    fn from(model: DeleteModel) -> WriteModel {
+        if model.multi {
+            WriteModel::DeleteMany {
+                filter: model.filter,
+                collation: model.collation,
+                hint: model.hint,
+            }
+        } else {
+            WriteModel::DeleteOne {
+                filter: model.filter,
+                collation: model.collation,
+                hint: model.hint,
+            }
+        }
+    }
+}
+
+impl From<UpdateModel> for WriteModel {
+    fn from(model: UpdateModel) -> WriteModel {
+        if model.multi {
+            WriteModel::UpdateMany {
+                filter: model.filter,
+                update: model.update,
+                upsert: model.upsert,
+                collation: model.collation,
+                hint: model.hint,
+                array_filters: model.array_filters,
+            }
+        } else {
+            WriteModel::UpdateOne {
+                filter: model.filter,
+                update: model.update,
+                upsert: model.upsert,
+                collation: model.collation,
+                hint: model.hint,
+                array_filters: model.array_filters,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Batch {
+    Insert(Vec<Document>),
+    Delete(Vec<DeleteModel>),
+    Update(Vec<UpdateModel>),
+}
 
-        length as i64
+impl From<WriteModel> for Batch {
+    fn from(model: WriteModel) -> Batch {
+        match BatchEntry::from(model) {
+            BatchEntry::Insert(document) => Batch::Insert(vec![document]),
+            BatchEntry::Delete(model) => Batch::Delete(vec![model]),
+            BatchEntry::Update(model) => Batch::Update(vec![model]),
+        }
     }
+}
 
+impl Batch {
     /// Attempts to merge another model into this batch.
     ///
     /// # Arguments
@@ -149,64 +242,25 @@ impl Batch {
     /// Returns `None` on success, or the model that couldn't be merged on
     /// failure.
     pub fn merge_model(&mut self, model: WriteModel) -> Option<WriteModel> {
-        match *self {
-            Batch::Insert(ref mut docs) => {
-                match model {
-                    WriteModel::InsertOne { document } => docs.push(document),
-                    _ => return Some(model),
-                }
-            }
-            Batch::Delete(ref mut models) => {
-                match model {
-                    WriteModel::DeleteOne { filter } => {
-                        models.push(DeleteModel {
-                            filter: filter,
-                            multi: false,
-                        })
-                    }
-                    WriteModel::DeleteMany { filter } => {
-                        models.push(DeleteModel {
-                            filter: filter,
-                            multi: true,
-                        })
-                    }
-                    _ => return Some(model),
-                }
-            }
-            Batch::Update(ref mut models) => {
-                match model {
-                    WriteModel::ReplaceOne {
-                        filter,
-                        replacement: update,
-                        upsert,
-                    } |
-                    WriteModel::UpdateOne {
-                        filter,
-                        update,
-                        upsert,
-                    } => {
-                        models.push(UpdateModel {
-                            filter: filter,
-                            update: update,
-                            upsert: upsert,
-                            multi: false,
-                        })
-                    }
-                    WriteModel::UpdateMany {
-                        filter,
-                        update,
-                        upsert,
-                    } => {
-                        models.push(UpdateModel {
-                            filter: filter,
-                            update: update,
-                            upsert: upsert,
-                            multi: true,
-                        })
-                    }
-                    _ => return Some(model),
-                }
-            }
+        let mergeable = match (&*self, &model) {
+            (&Batch::Insert(_), &WriteModel::InsertOne { .. }) |
+            (&Batch::Delete(_), &WriteModel::DeleteOne { .. }) |
+            (&Batch::Delete(_), &WriteModel::DeleteMany { .. }) |
+            (&Batch::Update(_), &WriteModel::ReplaceOne { .. }) |
+            (&Batch::Update(_), &WriteModel::UpdateOne { .. }) |
+            (&Batch::Update(_), &WriteModel::UpdateMany { .. }) => true,
+            _ => false,
+        };
+
+        if !mergeable {
+            return Some(model);
+        }
+
+        match (self, BatchEntry::from(model)) {
+            (&mut Batch::Insert(ref mut docs), BatchEntry::Insert(document)) => docs.push(document),
+            (&mut Batch::Delete(ref mut models), BatchEntry::Delete(model)) => models.push(model),
+            (&mut Batch::Update(ref mut models), BatchEntry::Update(model)) => models.push(model),
+            _ => unreachable!(),
         }
 
         None
@@ -0,0 +1,264 @@
+//! Client-side buffering of high-frequency inserts.
+//!
+//! A `BufferedWriter` accumulates single documents and inserts them in unordered batches, which
+//! takes far fewer round trips than inserting them one at a time.
+use bson::{self, oid, Bson};
+use logging::LogLevel;
+use wire_protocol::operations::ByteLength;
+use Error::{self, BulkWriteError, PoisonLockError};
+use Result;
+
+use super::Collection;
+use super::options::InsertManyOptions;
+
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// When a `BufferedWriter` flushes the documents it holds; whichever threshold is reached first
+/// triggers the flush.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BufferedWriterOptions {
+    /// The most documents held before they are flushed.
+    pub max_documents: usize,
+    /// The most bytes of documents held before they are flushed.
+    pub max_bytes: usize,
+    /// How long the oldest document is held before the buffer is flushed, if limited.
+    pub flush_interval: Option<Duration>,
+}
+
+impl Default for BufferedWriterOptions {
+    fn default() -> BufferedWriterOptions {
+        BufferedWriterOptions {
+            max_documents: 1000,
+            max_bytes: 8 * 1024 * 1024,
+            flush_interval: Some(Duration::from_secs(1)),
+        }
+    }
+}
+
+impl BufferedWriterOptions {
+    pub fn new() -> BufferedWriterOptions {
+        Default::default()
+    }
+}
+
+// The documents waiting to be flushed, and the errors of earlier automatic flushes.
+#[derive(Default)]
+struct Buffer {
+    documents: Vec<bson::Document>,
+    bytes: usize,
+    // When the oldest document held was buffered.
+    oldest: Option<Instant>,
+    errors: Vec<Error>,
+    closed: bool,
+}
+
+impl Buffer {
+    fn take(&mut self) -> Vec<bson::Document> {
+        self.bytes = 0;
+        self.oldest = None;
+        mem::replace(&mut self.documents, Vec::new())
+    }
+}
+
+// The state shared with the background flusher.
+struct Shared {
+    coll: Collection,
+    options: BufferedWriterOptions,
+    buffer: Mutex<Buffer>,
+    // Wakes the flusher when the first document is buffered or the writer is dropped.
+    wakeup: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> Result<MutexGuard<Buffer>> {
+        self.buffer.lock().map_err(|_| PoisonLockError)
+    }
+
+    fn write(&self, documents: Vec<bson::Document>) -> Result<()> {
+        if documents.is_empty() {
+            return Ok(());
+        }
+
+        let options = InsertManyOptions {
+            ordered: Some(false),
+            ..Default::default()
+        };
+
+        let result = self.coll.insert_many(documents, Some(options))?;
+        match result.bulk_write_exception {
+            Some(exception) => Err(BulkWriteError(exception)),
+            None => Ok(()),
+        }
+    }
+
+    // Writes a batch flushed automatically, keeping any error for `take_errors`.
+    fn write_in_background(&self, documents: Vec<bson::Document>) {
+        if let Err(err) = self.write(documents) {
+            if let Ok(mut buffer) = self.lock() {
+                buffer.errors.push(err);
+            }
+        }
+    }
+
+    // Flushes the buffer whenever its oldest document has been held for `interval`, until the
+    // writer is dropped.
+    fn run_flusher(&self, interval: Duration) {
+        let mut buffer = match self.lock() {
+            Ok(buffer) => buffer,
+            Err(_) => return,
+        };
+
+        loop {
+            if buffer.closed {
+                return;
+            }
+
+            let oldest = buffer.oldest;
+            let wait = match oldest {
+                Some(oldest) if oldest.elapsed() >= interval => {
+                    let documents = buffer.take();
+                    drop(buffer);
+                    self.write_in_background(documents);
+
+                    buffer = match self.lock() {
+                        Ok(buffer) => buffer,
+                        Err(_) => return,
+                    };
+                    continue;
+                }
+                Some(oldest) => interval - oldest.elapsed().min(interval),
+                None => interval,
+            };
+
+            buffer = match self.wakeup.wait_timeout(buffer, wait) {
+                Ok((buffer, _)) => buffer,
+                Err(_) => return,
+            };
+        }
+    }
+}
+
+/// Buffers inserted documents and writes them to a collection in unordered batches.
+///
+/// The buffer is flushed when it holds `max_documents` documents or `max_bytes` bytes, when its
+/// oldest document has been held for `flush_interval`, when `flush` is called, and when the
+/// writer is dropped. Threshold flushes run on the thread that inserts the document reaching
+/// the threshold, and interval flushes on a background thread that stops when the writer is
+/// dropped.
+///
+/// The errors of automatic flushes are kept until they are collected with `take_errors`; the
+/// errors of the final flush on drop are logged.
+pub struct BufferedWriter {
+    shared: Arc<Shared>,
+    flusher: Option<JoinHandle<()>>,
+}
+
+impl BufferedWriter {
+    /// Creates a writer inserting into `coll`, starting the background flusher if the options
+    /// set a flush interval.
+    pub fn new(coll: Collection, options: Option<BufferedWriterOptions>) -> BufferedWriter {
+        let options = options.unwrap_or_default();
+        let interval = options.flush_interval;
+
+        let shared = Arc::new(Shared {
+            coll: coll,
+            options: options,
+            buffer: Mutex::new(Buffer::default()),
+            wakeup: Condvar::new(),
+        });
+
+        let flusher = interval.map(|interval| {
+            let shared = shared.clone();
+            thread::spawn(move || shared.run_flusher(interval))
+        });
+
+        BufferedWriter {
+            shared: shared,
+            flusher: flusher,
+        }
+    }
+
+    /// Returns the collection the documents are inserted into.
+    pub fn collection(&self) -> &Collection {
+        &self.shared.coll
+    }
+
+    /// Buffers a document to be inserted, generating its `_id` if it has none, and returns the
+    /// id. Flushes the buffer if the document brings it to one of its thresholds.
+    pub fn insert(&self, mut doc: bson::Document) -> Result<Bson> {
+        let id = match doc.get("_id").cloned() {
+            Some(id) => id,
+            None => {
+                let id = Bson::ObjectId(oid::ObjectId::new()?);
+                doc.insert("_id", id.clone());
+                id
+            }
+        };
+
+        let bytes = doc.byte_length()? as usize;
+
+        let documents = {
+            let mut buffer = self.shared.lock()?;
+            if buffer.oldest.is_none() {
+                buffer.oldest = Some(Instant::now());
+                self.shared.wakeup.notify_all();
+            }
+
+            buffer.documents.push(doc);
+            buffer.bytes += bytes;
+
+            let options = &self.shared.options;
+            if buffer.documents.len() < options.max_documents && buffer.bytes < options.max_bytes {
+                return Ok(id);
+            }
+
+            buffer.take()
+        };
+
+        self.shared.write_in_background(documents);
+        Ok(id)
+    }
+
+    /// Returns how many documents are waiting to be flushed.
+    pub fn buffered(&self) -> Result<usize> {
+        Ok(self.shared.lock()?.documents.len())
+    }
+
+    /// Inserts every buffered document now, returning the error of this batch, if any.
+    pub fn flush(&self) -> Result<()> {
+        let documents = self.shared.lock()?.take();
+        self.shared.write(documents)
+    }
+
+    /// Returns the errors of the batches flushed automatically since the last call, oldest first.
+    pub fn take_errors(&self) -> Result<Vec<Error>> {
+        Ok(mem::replace(&mut self.shared.lock()?.errors, Vec::new()))
+    }
+}
+
+impl Drop for BufferedWriter {
+    fn drop(&mut self) {
+        if let Ok(mut buffer) = self.shared.lock() {
+            buffer.closed = true;
+        }
+        self.shared.wakeup.notify_all();
+
+        if let Some(flusher) = self.flusher.take() {
+            let _ = flusher.join();
+        }
+
+        let mut errors = self.take_errors().unwrap_or_default();
+        if let Err(err) = self.flush() {
+            errors.push(err);
+        }
+
+        for err in errors {
+            self.shared.coll.db.client.log(LogLevel::Error, "operation", || {
+                format!("Buffered insert into {} failed: {}", self.shared.coll.namespace, err)
+            });
+        }
+    }
+}
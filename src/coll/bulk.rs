@@ -0,0 +1,192 @@
+//! A fluent builder for bulk writes.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::db::ThreadedDatabase;
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! # let coll = client.db("test").collection("users");
+//! #
+//! let mut bulk = coll.initialize_ordered_bulk_op();
+//! bulk.find(doc! { "name": "Jo" }).upsert().update_one(doc! { "$set": { "age": 30 } });
+//! bulk.find(doc! { "inactive": true }).remove();
+//! bulk.insert(doc! { "name": "Sam" });
+//! let result = bulk.execute(None).unwrap();
+//! # }
+//! ```
+use bson::Document;
+use common::WriteConcern;
+use Error::ArgumentError;
+use Result;
+
+use super::Collection;
+use super::options::{Collation, Hint, WriteModel};
+use super::results::BulkWriteResult;
+
+/// Collects writes to send to a collection as a single bulk write, in the order they are added.
+///
+/// The writes are run by the same machinery as `Collection::bulk_write`, so the indexes of
+/// write errors and of inserted and upserted ids are the positions of the writes in the order
+/// they were added, whether the bulk write is ordered or not.
+#[derive(Debug)]
+pub struct BulkOperationBuilder<'a> {
+    coll: &'a Collection,
+    ordered: bool,
+    requests: Vec<WriteModel>,
+}
+
+impl<'a> BulkOperationBuilder<'a> {
+    /// Starts an empty bulk write to `coll`.
+    pub fn new(coll: &'a Collection, ordered: bool) -> BulkOperationBuilder<'a> {
+        BulkOperationBuilder {
+            coll: coll,
+            ordered: ordered,
+            requests: Vec::new(),
+        }
+    }
+
+    /// Returns true if the writes are applied in order, stopping at the first failure.
+    pub fn is_ordered(&self) -> bool {
+        self.ordered
+    }
+
+    /// Returns the writes added so far, in the order they were added.
+    pub fn requests(&self) -> &[WriteModel] {
+        &self.requests
+    }
+
+    /// Returns how many writes were added.
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    /// Returns true if no writes were added.
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Adds an insert of `document`.
+    pub fn insert(&mut self, document: Document) {
+        self.requests.push(WriteModel::InsertOne { document: document });
+    }
+
+    /// Selects the documents matching `filter` for the update, replacement or removal that
+    /// follows.
+    pub fn find(&mut self, filter: Document) -> BulkFindOperation {
+        BulkFindOperation {
+            requests: &mut self.requests,
+            filter: filter,
+            upsert: None,
+            collation: None,
+            hint: None,
+            array_filters: None,
+        }
+    }
+
+    /// Sends the writes with the given write concern, or the collection's. Fails without
+    /// sending anything if no writes were added.
+    pub fn execute(self, write_concern: Option<WriteConcern>) -> Result<BulkWriteResult> {
+        if self.requests.is_empty() {
+            return Err(ArgumentError(
+                String::from("A bulk write must contain at least one operation."),
+            ));
+        }
+
+        Ok(self.coll.execute_bulk_write(self.requests, self.ordered, write_concern))
+    }
+}
+
+/// The documents selected by `BulkOperationBuilder::find`, waiting for the write to apply to
+/// them. Options that don't apply to the write are ignored: `upsert` by removals, and
+/// `array_filters` by replacements and removals.
+#[derive(Debug)]
+pub struct BulkFindOperation<'b> {
+    requests: &'b mut Vec<WriteModel>,
+    filter: Document,
+    upsert: Option<bool>,
+    collation: Option<Collation>,
+    hint: Option<Hint>,
+    array_filters: Option<Vec<Document>>,
+}
+
+impl<'b> BulkFindOperation<'b> {
+    /// Inserts a document if the update or replacement matches none.
+    pub fn upsert(mut self) -> BulkFindOperation<'b> {
+        self.upsert = Some(true);
+        self
+    }
+
+    /// Matches the filter with the given collation.
+    pub fn collation(mut self, collation: Collation) -> BulkFindOperation<'b> {
+        self.collation = Some(collation);
+        self
+    }
+
+    /// Selects the documents with the given index.
+    pub fn hint(mut self, hint: Hint) -> BulkFindOperation<'b> {
+        self.hint = Some(hint);
+        self
+    }
+
+    /// Selects the array elements an update modifies.
+    pub fn array_filters(mut self, array_filters: Vec<Document>) -> BulkFindOperation<'b> {
+        self.array_filters = Some(array_filters);
+        self
+    }
+
+    /// Applies `update` to the first matching document.
+    pub fn update_one(self, update: Document) {
+        self.requests.push(WriteModel::UpdateOne {
+            filter: self.filter,
+            update: update,
+            upsert: self.upsert,
+            collation: self.collation,
+            hint: self.hint,
+            array_filters: self.array_filters,
+        });
+    }
+
+    /// Applies `update` to every matching document.
+    pub fn update(self, update: Document) {
+        self.requests.push(WriteModel::UpdateMany {
+            filter: self.filter,
+            update: update,
+            upsert: self.upsert,
+            collation: self.collation,
+            hint: self.hint,
+            array_filters: self.array_filters,
+        });
+    }
+
+    /// Replaces the first matching document with `replacement`.
+    pub fn replace_one(self, replacement: Document) {
+        self.requests.push(WriteModel::ReplaceOne {
+            filter: self.filter,
+            replacement: replacement,
+            upsert: self.upsert,
+            collation: self.collation,
+            hint: self.hint,
+        });
+    }
+
+    /// Removes every matching document.
+    pub fn remove(self) {
+        self.requests.push(WriteModel::DeleteMany {
+            filter: self.filter,
+            collation: self.collation,
+            hint: self.hint,
+        });
+    }
+
+    /// Removes the first matching document.
+    pub fn remove_one(self) {
+        self.requests.push(WriteModel::DeleteOne {
+            filter: self.filter,
+            collation: self.collation,
+            hint: self.hint,
+        });
+    }
+}
@@ -0,0 +1,168 @@
+//! Change streams over collection-level events.
+use bson::{self, Bson, bson, doc};
+
+use super::options::ChangeStreamOptions;
+
+use CommandType;
+use Result;
+use Error::{ChangeStreamInvalidatedError, ResponseError};
+use common::ReadPreference;
+use cursor::Cursor;
+use db::{Database, ThreadedDatabase};
+
+/// A cursor over the change events of a collection, built on the `$changeStream` aggregation
+/// stage.
+///
+/// Each call to `next` returns the next available event, transparently fetching further
+/// batches from the server. `None` is returned when no new events are currently available;
+/// the stream may be polled again later. The resume token of the latest event is tracked so
+/// that the stream can be reopened after a failure.
+#[derive(Debug)]
+pub struct ChangeStream {
+    db: Database,
+    coll_name: String,
+    pipeline: Vec<bson::Document>,
+    options: ChangeStreamOptions,
+    read_preference: ReadPreference,
+    cursor: Cursor,
+    resume_token: Option<bson::Document>,
+    invalidated: bool,
+}
+
+impl ChangeStream {
+    /// Opens a change stream on the given collection.
+    pub fn open(
+        db: Database,
+        coll_name: String,
+        pipeline: Vec<bson::Document>,
+        options: ChangeStreamOptions,
+        read_preference: ReadPreference,
+    ) -> Result<ChangeStream> {
+        let resume_token = options.resume_after.clone();
+        let cursor = ChangeStream::aggregate(
+            &db,
+            &coll_name,
+            &pipeline,
+            &options,
+            resume_token.as_ref(),
+            &read_preference,
+        )?;
+
+        Ok(ChangeStream {
+            db: db,
+            coll_name: coll_name,
+            pipeline: pipeline,
+            options: options,
+            read_preference: read_preference,
+            cursor: cursor,
+            resume_token: resume_token,
+            invalidated: false,
+        })
+    }
+
+    /// Returns the resume token of the most recently returned event, or the token the stream
+    /// was started with if no events have been returned yet.
+    pub fn resume_token(&self) -> Option<&bson::Document> {
+        self.resume_token.as_ref()
+    }
+
+    fn aggregate(
+        db: &Database,
+        coll_name: &str,
+        pipeline: &[bson::Document],
+        options: &ChangeStreamOptions,
+        resume_token: Option<&bson::Document>,
+        read_preference: &ReadPreference,
+    ) -> Result<Cursor> {
+        let mut stage = bson::Document::new();
+
+        if let Some(ref full_document) = options.full_document {
+            stage.insert("fullDocument", full_document.to_owned());
+        }
+
+        // A resume token takes precedence over the starting operation time, since it is
+        // always at least as recent.
+        if let Some(token) = resume_token {
+            stage.insert("resumeAfter", token.clone());
+        } else if let Some(time) = options.start_at_operation_time {
+            stage.insert("startAtOperationTime", Bson::TimeStamp(time));
+        }
+
+        let mut pipeline_map = vec![Bson::Document(doc! { "$changeStream": stage })];
+        pipeline_map.extend(pipeline.iter().cloned().map(Bson::Document));
+
+        let mut cursor = bson::Document::new();
+        if let Some(batch_size) = options.batch_size {
+            cursor.insert("batchSize", batch_size);
+        }
+
+        let spec = doc! {
+            "aggregate": coll_name,
+            "pipeline": pipeline_map,
+            "cursor": cursor,
+        };
+
+        db.command_cursor(spec, CommandType::Aggregate, read_preference.to_owned())
+    }
+
+    // Reopens the stream from the latest resume token.
+    fn resume(&mut self) -> Result<()> {
+        self.cursor = ChangeStream::aggregate(
+            &self.db,
+            &self.coll_name,
+            &self.pipeline,
+            &self.options,
+            self.resume_token.as_ref(),
+            &self.read_preference,
+        )?;
+
+        Ok(())
+    }
+
+    fn process_event(&mut self, event: bson::Document) -> Result<bson::Document> {
+        match event.get("_id") {
+            Some(&Bson::Document(ref token)) => self.resume_token = Some(token.clone()),
+            _ => {
+                return Err(ResponseError(String::from(
+                    "Change stream event is missing its resume token.",
+                )))
+            }
+        }
+
+        if let Some(&Bson::String(ref op)) = event.get("operationType") {
+            if op == "invalidate" {
+                self.invalidated = true;
+                return Err(ChangeStreamInvalidatedError(event));
+            }
+        }
+
+        Ok(event)
+    }
+}
+
+impl Iterator for ChangeStream {
+    type Item = Result<bson::Document>;
+
+    /// Attempts to read the next change event, resuming the stream once if the server reports
+    /// a resumable error.
+    fn next(&mut self) -> Option<Result<bson::Document>> {
+        if self.invalidated {
+            return None;
+        }
+
+        let result = match self.cursor.next() {
+            Some(Err(ref err)) if err.is_resumable_change_stream_error() => {
+                if let Err(err) = self.resume() {
+                    return Some(Err(err));
+                }
+                self.cursor.next()
+            }
+            result => result,
+        };
+
+        match result {
+            Some(Ok(event)) => Some(self.process_event(event)),
+            result => result,
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! The distribution of a sharded collection's chunks across shards, as reported by
+//! `Collection::chunk_distribution`.
+//!
+//! Chunk counts are read from `config.chunks` through a mongos. Before MongoDB 5.0, chunks name
+//! their collection by namespace; from 5.0 on, they name it by the UUID recorded for it in
+//! `config.collections`. Chunks may be filled unevenly, so the counts alone can misrepresent
+//! how much data each shard holds. The sizes of a bounded sample of chunks can be measured with
+//! `dataSize` and extrapolated to every chunk of their shard.
+use bson::{self, Bson};
+
+use std::collections::HashMap;
+
+/// The chunks of a collection held by one shard.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShardChunks {
+    /// The name of the shard.
+    pub shard: String,
+    /// The number of chunks the shard holds.
+    pub chunks: i64,
+    /// The shard's share of the collection's chunks, from 0 to 100.
+    pub chunk_percent: f64,
+    /// The number of the shard's chunks whose size was measured.
+    pub sampled_chunks: usize,
+    /// The estimated size of the shard's documents in bytes, if any of its chunks were sampled.
+    pub estimated_bytes: Option<i64>,
+    /// The estimated number of the shard's documents, if any of its chunks were sampled.
+    pub estimated_documents: Option<i64>,
+    /// The shard's share of the estimated data, from 0 to 100, if every shard was sampled.
+    pub data_percent: Option<f64>,
+}
+
+/// The report of `Collection::chunk_distribution`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChunkDistribution {
+    /// The namespace of the collection.
+    pub namespace: String,
+    /// The collection's shard key.
+    pub key: bson::Document,
+    /// The number of chunks of the collection.
+    pub total_chunks: i64,
+    /// The estimated size of the collection in bytes, if every shard was sampled.
+    pub estimated_bytes: Option<i64>,
+    /// The estimated number of documents in the collection, if every shard was sampled.
+    pub estimated_documents: Option<i64>,
+    /// The shards holding chunks of the collection, by name.
+    pub shards: Vec<ShardChunks>,
+}
+
+/// The sizes measured by `dataSize` for the sampled chunks of a shard.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChunkSample {
+    /// The number of chunks measured.
+    pub chunks: usize,
+    /// The total size of their documents in bytes.
+    pub bytes: i64,
+    /// The total number of their documents.
+    pub documents: i64,
+}
+
+impl ChunkSample {
+    /// Adds the reply of a `dataSize` command for one chunk.
+    pub fn add(&mut self, reply: &bson::Document) {
+        self.chunks += 1;
+        self.bytes += number(reply.get("size"));
+        self.documents += number(reply.get("numObjects"));
+    }
+}
+
+impl ChunkDistribution {
+    /// Builds the report from the number of chunks of each shard, and the sizes measured for
+    /// the shards that were sampled. A shard's estimates scale its sampled sizes by its
+    /// number of chunks; totals and data shares are only given if every shard was sampled.
+    pub fn new(
+        namespace: String,
+        key: bson::Document,
+        mut counts: Vec<(String, i64)>,
+        samples: &HashMap<String, ChunkSample>,
+    ) -> ChunkDistribution {
+        counts.sort();
+        let total_chunks: i64 = counts.iter().map(|&(_, chunks)| chunks).sum();
+
+        let mut shards: Vec<_> = counts
+            .into_iter()
+            .map(|(shard, chunks)| {
+                let sample = samples.get(&shard).cloned().unwrap_or_default();
+                let scale = |measured: i64| if sample.chunks == 0 {
+                    None
+                } else {
+                    let per_chunk = measured as f64 / sample.chunks as f64;
+                    Some((per_chunk * chunks as f64).round() as i64)
+                };
+
+                ShardChunks {
+                    chunk_percent: percent(chunks, total_chunks),
+                    sampled_chunks: sample.chunks,
+                    estimated_bytes: scale(sample.bytes),
+                    estimated_documents: scale(sample.documents),
+                    data_percent: None,
+                    shard: shard,
+                    chunks: chunks,
+                }
+            })
+            .collect();
+
+        let every_shard_sampled = !shards.is_empty() &&
+            shards.iter().all(|shard| shard.estimated_bytes.is_some());
+
+        let (estimated_bytes, estimated_documents) = if every_shard_sampled {
+            let bytes: i64 = shards.iter().filter_map(|shard| shard.estimated_bytes).sum();
+            let documents: i64 =
+                shards.iter().filter_map(|shard| shard.estimated_documents).sum();
+            for shard in &mut shards {
+                shard.data_percent = shard.estimated_bytes.map(|size| percent(size, bytes));
+            }
+            (Some(bytes), Some(documents))
+        } else {
+            (None, None)
+        };
+
+        ChunkDistribution {
+            namespace: namespace,
+            key: key,
+            total_chunks: total_chunks,
+            estimated_bytes: estimated_bytes,
+            estimated_documents: estimated_documents,
+            shards: shards,
+        }
+    }
+
+    /// Returns the chunks held by the named shard.
+    pub fn shard(&self, name: &str) -> Option<&ShardChunks> {
+        self.shards.iter().find(|shard| shard.shard == name)
+    }
+}
+
+/// Splits a sample of at most `max_chunks` chunks evenly across `shards` shards, giving the
+/// remainder to the first ones.
+pub fn sample_quotas(max_chunks: usize, shards: usize) -> Vec<usize> {
+    if shards == 0 {
+        return Vec::new();
+    }
+
+    (0..shards)
+        .map(|i| max_chunks / shards + if i < max_chunks % shards { 1 } else { 0 })
+        .collect()
+}
+
+fn percent(part: i64, total: i64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 * 100.0 / total as f64
+    }
+}
+
+// Reads a size, which servers report as an integer or a double.
+fn number(value: Option<&Bson>) -> i64 {
+    match value {
+        Some(&Bson::I32(n)) => n as i64,
+        Some(&Bson::I64(n)) => n,
+        Some(&Bson::FloatingPoint(n)) => n as i64,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{bson, doc};
+    use super::{sample_quotas, ChunkDistribution, ChunkSample};
+
+    use std::collections::HashMap;
+
+    fn counts() -> Vec<(String, i64)> {
+        vec![(String::from("shard1"), 30), (String::from("shard0"), 10)]
+    }
+
+    #[test]
+    fn reports_chunk_shares() {
+        let report = ChunkDistribution::new(
+            String::from("test.coll"),
+            doc! { "a": 1 },
+            counts(),
+            &HashMap::new(),
+        );
+
+        assert_eq!(report.total_chunks, 40);
+        assert_eq!(report.shards[0].shard, "shard0");
+        assert_eq!(report.shards[0].chunk_percent, 25.0);
+        assert_eq!(report.shard("shard1").unwrap().chunk_percent, 75.0);
+        assert_eq!(report.shards[0].estimated_bytes, None);
+        assert_eq!(report.estimated_bytes, None);
+    }
+
+    #[test]
+    fn extrapolates_sampled_sizes() {
+        let mut samples = HashMap::new();
+        let mut sample = ChunkSample::default();
+        sample.add(&doc! { "size": 1000, "numObjects": 10, "ok": 1 });
+        sample.add(&doc! { "size": 3000.0, "numObjects": 30, "ok": 1 });
+        samples.insert(String::from("shard0"), sample);
+
+        // Shards that weren't sampled leave the totals unknown.
+        let report =
+            ChunkDistribution::new(String::from("test.coll"), doc! { "a": 1 }, counts(), &samples);
+        assert_eq!(report.shards[0].sampled_chunks, 2);
+        assert_eq!(report.shards[0].estimated_bytes, Some(20000));
+        assert_eq!(report.shards[0].estimated_documents, Some(200));
+        assert_eq!(report.shards[0].data_percent, None);
+        assert_eq!(report.estimated_bytes, None);
+
+        let mut sample = ChunkSample::default();
+        sample.add(&doc! { "size": 2000, "numObjects": 20, "ok": 1 });
+        samples.insert(String::from("shard1"), sample);
+
+        let report =
+            ChunkDistribution::new(String::from("test.coll"), doc! { "a": 1 }, counts(), &samples);
+        assert_eq!(report.shards[1].estimated_bytes, Some(60000));
+        assert_eq!(report.estimated_bytes, Some(80000));
+        assert_eq!(report.estimated_documents, Some(800));
+        assert_eq!(report.shards[0].data_percent, Some(25.0));
+        assert_eq!(report.shards[1].data_percent, Some(75.0));
+    }
+
+    #[test]
+    fn spreads_samples_across_shards() {
+        assert_eq!(sample_quotas(10, 3), vec![4, 3, 3]);
+        assert_eq!(sample_quotas(2, 3), vec![1, 1, 0]);
+        assert!(sample_quotas(10, 0).is_empty());
+    }
+}
@@ -0,0 +1,610 @@
+//! Client-side encryption of selected document fields.
+//!
+//! A `FieldEncryptor` registered on a collection encrypts the configured fields of the documents
+//! written through it before they leave the process, and decrypts them in the documents read
+//! back. Values are encrypted with AEAD_AES_256_CBC_HMAC_SHA_512 into BSON binaries of subtype
+//! 6, using data keys supplied by the application. Encrypting requires the `encryption` feature.
+use bson::{self, Bson, Document};
+use bson::spec::BinarySubtype;
+use Error::ArgumentError;
+use Result;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+
+/// The length of a data key: a MAC key, an encryption key and an IV key of 32 bytes each.
+pub const DATA_KEY_LENGTH: usize = 96;
+
+// The BSON binary subtype of encrypted values.
+const ENCRYPTED_SUBTYPE: u8 = 6;
+
+const KEY_ID_LENGTH: usize = 16;
+
+// Every encrypted value starts with the algorithm, the id of its key and the type of the
+// original value, which are authenticated along with the ciphertext.
+const HEADER_LENGTH: usize = 1 + KEY_ID_LENGTH + 1;
+
+/// How the values of an encrypted field are encrypted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncryptionAlgorithm {
+    /// Equal values always encrypt to the same ciphertext, so the field can be queried for
+    /// equality. Doubles, decimals, booleans, documents, arrays and code with scope can't be
+    /// encrypted deterministically.
+    Deterministic,
+    /// Each value is encrypted with a random IV, so the field can't be queried.
+    Random,
+}
+
+impl EncryptionAlgorithm {
+    fn to_byte(self) -> u8 {
+        match self {
+            EncryptionAlgorithm::Deterministic => 1,
+            EncryptionAlgorithm::Random => 2,
+        }
+    }
+}
+
+// The data key and algorithm of an encrypted field.
+#[derive(Clone)]
+struct EncryptedField {
+    key_id: Vec<u8>,
+    key: Vec<u8>,
+    algorithm: EncryptionAlgorithm,
+}
+
+impl EncryptedField {
+    // Encrypts a value, leaving values that are already encrypted as they are.
+    fn encrypt(&self, path: &str, value: Bson) -> Result<Bson> {
+        if is_encrypted(&value) {
+            return Ok(value);
+        }
+
+        let (element_type, plaintext) = encode_value(value)?;
+
+        let unsupported = match element_type {
+            // Undefined, null, min key and max key.
+            0x06 | 0x0A | 0xFF | 0x7F => true,
+            // Double, document, array, boolean, code with scope and decimal.
+            0x01 | 0x03 | 0x04 | 0x08 | 0x0F | 0x13 => {
+                self.algorithm == EncryptionAlgorithm::Deterministic
+            }
+            _ => false,
+        };
+
+        if unsupported {
+            return Err(ArgumentError(format!(
+                "Values of BSON type {:#04x} can't be encrypted in the field '{}' with the {:?} \
+                 algorithm.",
+                element_type,
+                path,
+                self.algorithm
+            )));
+        }
+
+        let mut payload = Vec::with_capacity(HEADER_LENGTH + plaintext.len() + 64);
+        payload.push(self.algorithm.to_byte());
+        payload.extend_from_slice(&self.key_id);
+        payload.push(element_type);
+
+        let deterministic = self.algorithm == EncryptionAlgorithm::Deterministic;
+        let ciphertext = crypto::encrypt(&self.key, &payload, &plaintext, deterministic)?;
+        payload.extend(ciphertext);
+
+        Ok(Bson::Binary(BinarySubtype::from(ENCRYPTED_SUBTYPE), payload))
+    }
+
+    // Encrypts the values a filter compares the field to.
+    fn encrypt_condition(&self, path: &str, condition: Bson) -> Result<Bson> {
+        if self.algorithm == EncryptionAlgorithm::Random {
+            return Err(ArgumentError(
+                format!("The randomly encrypted field '{}' can't be queried.", path),
+            ));
+        }
+
+        let condition = match condition {
+            Bson::Document(condition) => {
+                if !starts_with_operator(&condition) {
+                    return self.encrypt(path, Bson::Document(condition));
+                }
+                condition
+            }
+            value => return self.encrypt(path, value),
+        };
+
+        let mut encrypted = Document::new();
+        for (operator, operand) in condition {
+            let operand = match (operator.as_str(), operand) {
+                ("$eq", value) | ("$ne", value) => self.encrypt(path, value)?,
+                ("$in", Bson::Array(values)) | ("$nin", Bson::Array(values)) => {
+                    let values = values
+                        .into_iter()
+                        .map(|value| self.encrypt(path, value))
+                        .collect::<Result<Vec<_>>>()?;
+                    Bson::Array(values)
+                }
+                ("$exists", exists) => exists,
+                _ => {
+                    return Err(ArgumentError(format!(
+                        "The encrypted field '{}' can't be queried with {}.",
+                        path,
+                        operator
+                    )))
+                }
+            };
+
+            encrypted.insert(operator, operand);
+        }
+
+        Ok(Bson::Document(encrypted))
+    }
+}
+
+/// Encrypts and decrypts the configured fields of documents, each with its own data key.
+#[derive(Clone, Default)]
+pub struct FieldEncryptor {
+    fields: BTreeMap<String, EncryptedField>,
+}
+
+// Data keys are never printed.
+impl fmt::Debug for FieldEncryptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(self.fields.iter().map(|(path, field)| (path, field.algorithm)))
+            .finish()
+    }
+}
+
+impl FieldEncryptor {
+    /// Creates an encryptor with no encrypted fields.
+    pub fn new() -> FieldEncryptor {
+        Default::default()
+    }
+
+    /// Encrypts the field at the dotted `path`, e.g. `ssn` or `address.street`, with a data key
+    /// of `DATA_KEY_LENGTH` bytes. Ciphertexts identify their key by a hash of it, so any value
+    /// encrypted with one of the encryptor's keys is decrypted, wherever it is read from.
+    ///
+    /// Fails with an `ArgumentError` if the key has the wrong length, the path is `_id` or not a
+    /// valid field path, or the crate was built without the `encryption` feature.
+    pub fn add_field(
+        &mut self,
+        path: &str,
+        key: &[u8],
+        algorithm: EncryptionAlgorithm,
+    ) -> Result<()> {
+        if !cfg!(feature = "encryption") {
+            return Err(ArgumentError(
+                String::from("Field encryption requires the `encryption` feature."),
+            ));
+        }
+
+        if key.len() != DATA_KEY_LENGTH {
+            return Err(ArgumentError(format!(
+                "Data keys must be {} bytes long, not {}.",
+                DATA_KEY_LENGTH,
+                key.len()
+            )));
+        }
+
+        if path.split('.').any(|part| part.is_empty() || part.starts_with('$')) {
+            return Err(ArgumentError(format!("'{}' is not a valid field path.", path)));
+        }
+
+        if path == "_id" || path.starts_with("_id.") {
+            return Err(ArgumentError(String::from("The _id field can't be encrypted.")));
+        }
+
+        let field = EncryptedField {
+            key_id: crypto::key_id(key),
+            key: key.to_vec(),
+            algorithm: algorithm,
+        };
+
+        self.fields.insert(String::from(path), field);
+        Ok(())
+    }
+
+    /// Returns true if no fields are encrypted.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Encrypts the configured fields of a document to be inserted or used as a replacement.
+    /// A field such as `contacts.phone` is encrypted in every document of a `contacts` array.
+    pub fn encrypt_document(&self, doc: Document) -> Result<Document> {
+        self.encrypt_fields(doc, "")
+    }
+
+    /// Encrypts the values an update writes to the configured fields, or the fields of a
+    /// replacement if the document holds no update operators.
+    ///
+    /// The payloads of `$set` and `$setOnInsert` are encrypted and `$unset` is sent as is.
+    /// Other operators compute on the stored value, which the server can't do with ciphertexts,
+    /// so using them on an encrypted field fails with an `ArgumentError`.
+    pub fn encrypt_update(&self, update: Document) -> Result<Document> {
+        if !starts_with_operator(&update) {
+            return self.encrypt_document(update);
+        }
+
+        let mut encrypted = Document::new();
+        for (operator, payload) in update {
+            let payload = match (operator.as_str(), payload) {
+                ("$set", Bson::Document(fields)) |
+                ("$setOnInsert", Bson::Document(fields)) => {
+                    Bson::Document(self.encrypt_fields(fields, "")?)
+                }
+                ("$unset", payload) => payload,
+                (_, Bson::Document(fields)) => {
+                    if let Some(path) = fields.keys().find(|path| self.touches(path)) {
+                        return Err(ArgumentError(format!(
+                            "The {} operator can't modify the encrypted field '{}'.",
+                            operator,
+                            path
+                        )));
+                    }
+                    Bson::Document(fields)
+                }
+                (_, payload) => payload,
+            };
+
+            encrypted.insert(operator, payload);
+        }
+
+        Ok(encrypted)
+    }
+
+    /// Encrypts the values a filter compares deterministically encrypted fields to: equality
+    /// values and the operands of `$eq`, `$ne`, `$in` and `$nin`, including within `$and`, `$or`
+    /// and `$nor`. Any other condition on an encrypted field, any condition on a randomly
+    /// encrypted field, and any condition other than `$exists` on a field holding an encrypted
+    /// field, fails with an `ArgumentError`.
+    pub fn encrypt_filter(&self, filter: Document) -> Result<Document> {
+        if self.fields.is_empty() {
+            return Ok(filter);
+        }
+
+        let mut encrypted = Document::new();
+        for (key, value) in filter {
+            let value = match (key.as_str(), value) {
+                ("$and", Bson::Array(clauses)) |
+                ("$or", Bson::Array(clauses)) |
+                ("$nor", Bson::Array(clauses)) => {
+                    let clauses = clauses
+                        .into_iter()
+                        .map(|clause| match clause {
+                            Bson::Document(clause) => {
+                                self.encrypt_filter(clause).map(Bson::Document)
+                            }
+                            clause => Ok(clause),
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    Bson::Array(clauses)
+                }
+                (path, value) => {
+                    match self.fields.get(path) {
+                        Some(field) => field.encrypt_condition(path, value)?,
+                        None if self.inside_field(path) => {
+                            return Err(ArgumentError(format!(
+                                "'{}' is inside an encrypted field, so it can't be queried.",
+                                path
+                            )))
+                        }
+                        // Matching a whole subdocument would compare the plaintext of the
+                        // encrypted fields in it to their ciphertexts.
+                        None if self.fields.keys().any(|field| is_under(field, path)) &&
+                            !is_exists_condition(&value) =>
+                        {
+                            return Err(ArgumentError(format!(
+                                "'{}' holds an encrypted field, so it can only be queried with \
+                                 $exists.",
+                                path
+                            )))
+                        }
+                        None => value,
+                    }
+                }
+            };
+
+            encrypted.insert(key, value);
+        }
+
+        Ok(encrypted)
+    }
+
+    /// Decrypts every value in a document that was encrypted with one of the encryptor's keys,
+    /// wherever it appears. Other values, including ones encrypted with unknown keys, are left
+    /// as they are. Fails with an `EncryptionError` if a ciphertext was tampered with.
+    pub fn decrypt_document(&self, doc: Document) -> Result<Document> {
+        let mut decrypted = Document::new();
+        for (key, value) in doc {
+            decrypted.insert(key, self.decrypt_value(value)?);
+        }
+
+        Ok(decrypted)
+    }
+
+    /// Decrypts a value, and the values nested in it, as `decrypt_document` does.
+    pub fn decrypt_value(&self, value: Bson) -> Result<Bson> {
+        match value {
+            Bson::Document(doc) => self.decrypt_document(doc).map(Bson::Document),
+            Bson::Array(values) => {
+                values
+                    .into_iter()
+                    .map(|value| self.decrypt_value(value))
+                    .collect::<Result<Vec<_>>>()
+                    .map(Bson::Array)
+            }
+            Bson::Binary(subtype, payload) => {
+                let key = if u8::from(subtype) == ENCRYPTED_SUBTYPE &&
+                    payload.len() > HEADER_LENGTH
+                {
+                    self.key(&payload[1..HEADER_LENGTH - 1])
+                } else {
+                    None
+                };
+
+                let key = match key {
+                    Some(key) => key,
+                    None => return Ok(Bson::Binary(subtype, payload)),
+                };
+
+                let (header, ciphertext) = payload.split_at(HEADER_LENGTH);
+                let plaintext = crypto::decrypt(key, header, ciphertext)?;
+                decode_value(header[HEADER_LENGTH - 1], &plaintext)
+            }
+            value => Ok(value),
+        }
+    }
+
+    // Encrypts the configured fields of a document found at `prefix`. Keys may be dotted paths,
+    // as in the payload of `$set`.
+    fn encrypt_fields(&self, doc: Document, prefix: &str) -> Result<Document> {
+        if self.fields.is_empty() {
+            return Ok(doc);
+        }
+
+        let mut encrypted = Document::new();
+        for (key, value) in doc {
+            let path = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+
+            let value = match self.fields.get(&path) {
+                Some(field) => field.encrypt(&path, value)?,
+                None if self.inside_field(&path) => {
+                    return Err(ArgumentError(format!(
+                        "'{}' is inside an encrypted field, so it can't be written on its own.",
+                        path
+                    )))
+                }
+                None if self.fields.keys().any(|field| is_under(field, &path)) => {
+                    self.encrypt_nested(value, &path)?
+                }
+                None => value,
+            };
+
+            encrypted.insert(key, value);
+        }
+
+        Ok(encrypted)
+    }
+
+    // Encrypts the configured fields inside a value found at `path`, which is the parent of an
+    // encrypted field. The fields of documents in an array are reached through the array's
+    // path, as in queries.
+    fn encrypt_nested(&self, value: Bson, path: &str) -> Result<Bson> {
+        match value {
+            Bson::Document(nested) => self.encrypt_fields(nested, path).map(Bson::Document),
+            Bson::Array(values) => {
+                values
+                    .into_iter()
+                    .map(|value| self.encrypt_nested(value, path))
+                    .collect::<Result<Vec<_>>>()
+                    .map(Bson::Array)
+            }
+            value => Ok(value),
+        }
+    }
+
+    // Whether the path is nested inside an encrypted field.
+    fn inside_field(&self, path: &str) -> bool {
+        self.fields.keys().any(|field| is_under(path, field))
+    }
+
+    // Whether writing to the path would modify an encrypted field.
+    fn touches(&self, path: &str) -> bool {
+        self.fields.contains_key(path) || self.inside_field(path) ||
+            self.fields.keys().any(|field| is_under(field, path))
+    }
+
+    fn key(&self, key_id: &[u8]) -> Option<&[u8]> {
+        self.fields
+            .values()
+            .find(|field| field.key_id == key_id)
+            .map(|field| &field.key[..])
+    }
+}
+
+// Whether `path` is nested inside `parent`.
+fn is_under(path: &str, parent: &str) -> bool {
+    path.len() > parent.len() && path.starts_with(parent) && path[parent.len()..].starts_with('.')
+}
+
+// The server tells updates and replacements apart by their first key.
+fn starts_with_operator(doc: &Document) -> bool {
+    doc.keys().next().map_or(false, |key| key.starts_with('$'))
+}
+
+fn is_exists_condition(condition: &Bson) -> bool {
+    match *condition {
+        Bson::Document(ref condition) => {
+            !condition.is_empty() && condition.keys().all(|operator| operator == "$exists")
+        }
+        _ => false,
+    }
+}
+
+fn is_encrypted(value: &Bson) -> bool {
+    match *value {
+        Bson::Binary(subtype, _) => u8::from(subtype) == ENCRYPTED_SUBTYPE,
+        _ => false,
+    }
+}
+
+// Encodes a value as it appears in a document element, returning its BSON type and bytes.
+fn encode_value(value: Bson) -> Result<(u8, Vec<u8>)> {
+    let mut bytes = Vec::new();
+    let mut doc = Document::new();
+    doc.insert("v", value);
+    bson::encode_document(&mut bytes, &doc)?;
+
+    // The document length is followed by the element type and the key "v".
+    Ok((bytes[4], bytes[7..bytes.len() - 1].to_vec()))
+}
+
+fn decode_value(element_type: u8, value: &[u8]) -> Result<Bson> {
+    let mut bytes = Vec::with_capacity(value.len() + 8);
+    bytes.extend_from_slice(&((value.len() + 8) as i32).to_le_bytes());
+    bytes.push(element_type);
+    bytes.extend_from_slice(b"v\0");
+    bytes.extend_from_slice(value);
+    bytes.push(0);
+
+    let mut doc = bson::decode_document(&mut io::Cursor::new(bytes))?;
+    Ok(doc.remove("v").unwrap_or(Bson::Null))
+}
+
+#[cfg(feature = "encryption")]
+mod crypto {
+    use openssl::error::ErrorStack;
+    use openssl::hash::MessageDigest;
+    use openssl::memcmp;
+    use openssl::pkey::PKey;
+    use openssl::rand::rand_bytes;
+    use openssl::sha::sha512;
+    use openssl::sign::Signer;
+    use openssl::symm::{self, Cipher};
+    use Error::{self, EncryptionError};
+    use Result;
+
+    use super::KEY_ID_LENGTH;
+
+    const IV_LENGTH: usize = 16;
+    const TAG_LENGTH: usize = 32;
+
+    // A data key holds the MAC key, the encryption key and the key deterministic IVs are
+    // derived with, in that order.
+    fn mac_key(key: &[u8]) -> &[u8] {
+        &key[..32]
+    }
+
+    fn encryption_key(key: &[u8]) -> &[u8] {
+        &key[32..64]
+    }
+
+    fn iv_key(key: &[u8]) -> &[u8] {
+        &key[64..]
+    }
+
+    pub fn key_id(key: &[u8]) -> Vec<u8> {
+        sha512(key)[..KEY_ID_LENGTH].to_vec()
+    }
+
+    // Returns the IV, the AES-256-CBC ciphertext and the truncated HMAC-SHA-512 tag.
+    pub fn encrypt(
+        key: &[u8],
+        associated_data: &[u8],
+        plaintext: &[u8],
+        deterministic: bool,
+    ) -> Result<Vec<u8>> {
+        let length = associated_data_length(associated_data);
+        let mut iv = vec![0; IV_LENGTH];
+
+        if deterministic {
+            let hash = hmac(iv_key(key), &[associated_data, &length[..], plaintext])?;
+            iv.copy_from_slice(&hash[..IV_LENGTH]);
+        } else {
+            rand_bytes(&mut iv).map_err(crypto_error)?;
+        }
+
+        let ciphertext = symm::encrypt(
+            Cipher::aes_256_cbc(),
+            encryption_key(key),
+            Some(&iv),
+            plaintext,
+        ).map_err(crypto_error)?;
+
+        let mut output = iv;
+        output.extend(ciphertext);
+
+        let tag = hmac(mac_key(key), &[associated_data, &output[..], &length[..]])?;
+        output.extend_from_slice(&tag[..TAG_LENGTH]);
+        Ok(output)
+    }
+
+    pub fn decrypt(key: &[u8], associated_data: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+        if ciphertext.len() < IV_LENGTH + TAG_LENGTH {
+            return Err(EncryptionError(String::from("Encrypted value is truncated.")));
+        }
+
+        let length = associated_data_length(associated_data);
+        let (body, tag) = ciphertext.split_at(ciphertext.len() - TAG_LENGTH);
+        let expected = hmac(mac_key(key), &[associated_data, body, &length[..]])?;
+
+        if !memcmp::eq(&expected[..TAG_LENGTH], tag) {
+            return Err(EncryptionError(
+                String::from("Encrypted value failed authentication."),
+            ));
+        }
+
+        let (iv, encrypted) = body.split_at(IV_LENGTH);
+        symm::decrypt(Cipher::aes_256_cbc(), encryption_key(key), Some(iv), encrypted)
+            .map_err(crypto_error)
+    }
+
+    fn hmac(key: &[u8], parts: &[&[u8]]) -> Result<Vec<u8>> {
+        let key = PKey::hmac(key).map_err(crypto_error)?;
+        let mut signer = Signer::new(MessageDigest::sha512(), &key).map_err(crypto_error)?;
+        for part in parts {
+            signer.update(part).map_err(crypto_error)?;
+        }
+        signer.sign_to_vec().map_err(crypto_error)
+    }
+
+    // The length of the associated data in bits, as a big-endian 64-bit integer.
+    fn associated_data_length(associated_data: &[u8]) -> [u8; 8] {
+        ((associated_data.len() * 8) as u64).to_be_bytes()
+    }
+
+    fn crypto_error(err: ErrorStack) -> Error {
+        EncryptionError(format!("Field encryption failed: {}", err))
+    }
+}
+
+// Without the `encryption` feature no field can be added, so nothing is ever encrypted or
+// decrypted.
+#[cfg(not(feature = "encryption"))]
+mod crypto {
+    use Error::EncryptionError;
+    use Result;
+
+    pub fn key_id(_key: &[u8]) -> Vec<u8> {
+        Vec::new()
+    }
+
+    pub fn encrypt(
+        _key: &[u8],
+        _associated_data: &[u8],
+        _plaintext: &[u8],
+        _deterministic: bool,
+    ) -> Result<Vec<u8>> {
+        Err(EncryptionError(String::from("Field encryption requires the `encryption` feature.")))
+    }
+
+    pub fn decrypt(_key: &[u8], _associated_data: &[u8], _ciphertext: &[u8]) -> Result<Vec<u8>> {
+        Err(EncryptionError(String::from("Field encryption requires the `encryption` feature.")))
+    }
+}
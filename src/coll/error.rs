@@ -40,14 +40,26 @@ pub struct BulkWriteException {
 
 /// The error struct for a single bulk-write step, indicating the request
 /// and its index in the original bulk-write request.
+///
+/// Duplicate key errors also say which unique index was violated, and from MongoDB 4.2 the key
+/// pattern of that index and the values that collided.
 #[derive(Debug, Clone, PartialEq)]
 pub struct BulkWriteError {
     pub index: i32,
     pub code: i32,
     pub message: String,
     pub request: Option<WriteModel>,
+    /// The name of the unique index a duplicate key error violated.
+    pub index_name: Option<String>,
+    /// The key pattern of the unique index a duplicate key error violated.
+    pub key_pattern: Option<bson::Document>,
+    /// The values of the indexed fields that were already present.
+    pub key_value: Option<bson::Document>,
 }
 
+// The codes of duplicate key errors on inserts and on updates.
+const DUPLICATE_KEY_CODES: [i32; 2] = [11000, 11001];
+
 impl error::Error for WriteException {
     fn description(&self) -> &str {
         &self.message
@@ -226,21 +238,56 @@ impl BulkWriteError {
             code: code,
             message: message.to_string(),
             request: request,
+            index_name: None,
+            key_pattern: None,
+            key_value: None,
         }
     }
 
     /// Parses a Bson document into a BulkWriteError.
     pub fn parse(error: bson::Document) -> Result<BulkWriteError> {
-        match (error.get("index"), error.get("code"), error.get("errmsg")) {
+        let mut write_error = match (error.get("index"), error.get("code"), error.get("errmsg")) {
             (Some(&Bson::I32(index)),
              Some(&Bson::I32(code)),
              Some(&Bson::String(ref message))) => {
-                Ok(BulkWriteError::new(index, code, message, None))
+                BulkWriteError::new(index, code, message, None)
             }
-            _ => Err(Error::ResponseError(
+            _ => return Err(Error::ResponseError(
                 format!("WriteError document is invalid: {:?}", error),
             ))
+        };
+
+        if write_error.is_duplicate_key() {
+            write_error.index_name = duplicate_key_index_name(&write_error.message);
+
+            if let Some(&Bson::Document(ref key_pattern)) = error.get("keyPattern") {
+                write_error.key_pattern = Some(key_pattern.clone());
+            }
+            if let Some(&Bson::Document(ref key_value)) = error.get("keyValue") {
+                write_error.key_value = Some(key_value.clone());
+            }
         }
+
+        Ok(write_error)
+    }
+
+    /// Returns true if the write failed because it would have duplicated a key of a unique
+    /// index.
+    pub fn is_duplicate_key(&self) -> bool {
+        DUPLICATE_KEY_CODES.contains(&self.code)
+    }
+}
+
+// Parses the name of the violated index out of a duplicate key error message, which reads
+// "E11000 duplicate key error collection: db.coll index: name dup key: ..." since MongoDB 3.0,
+// and "... error index: db.coll.$name dup key: ..." before.
+fn duplicate_key_index_name(message: &str) -> Option<String> {
+    let start = message.find("index: ")? + "index: ".len();
+    let name = message[start..].split_whitespace().next()?;
+
+    match name.find(".$") {
+        Some(dollar) => Some(String::from(&name[dollar + 2..])),
+        None => Some(String::from(name)),
     }
 }
 
@@ -271,6 +318,16 @@ impl BulkWriteException {
         }
     }
 
+    /// Returns the indexes, within the bulk write, of the requests that failed with a duplicate
+    /// key error.
+    pub fn duplicate_key_indexes(&self) -> Vec<i64> {
+        self.write_errors
+            .iter()
+            .filter(|error| error.is_duplicate_key())
+            .map(|error| i64::from(error.index))
+            .collect()
+    }
+
     /// Adds a model to the vector of unprocessed models
     pub fn add_unproccessed_model(&mut self, model: WriteModel) {
         self.unprocessed_requests.push(model);
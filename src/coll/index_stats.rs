@@ -0,0 +1,135 @@
+//! Index usage statistics reported by the `$indexStats` aggregation stage.
+use bson::{self, Bson};
+use chrono::{DateTime, Utc};
+
+use Error::ResponseError;
+use Result;
+
+/// How often a single server used an index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexHostStats {
+    /// The server that reported the statistics, as `host:port`.
+    pub host: String,
+    /// The shard the server belongs to, when reported through a mongos.
+    pub shard: Option<String>,
+    /// The number of operations that used the index.
+    pub ops: i64,
+    /// When the server started counting, i.e. when the index was created or the server started.
+    pub since: DateTime<Utc>,
+}
+
+/// How often an index was used, merged across every server that reported it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexStats {
+    /// The name of the index.
+    pub name: String,
+    /// The key pattern of the index.
+    pub key: bson::Document,
+    /// The number of operations that used the index on any server.
+    pub ops: i64,
+    /// The latest time any server started counting. Usage is known for every server from then
+    /// on, so the index has been unused since at least then if `ops` is 0.
+    pub since: DateTime<Utc>,
+    /// The statistics of each server, e.g. one per shard of a sharded collection.
+    pub hosts: Vec<IndexHostStats>,
+}
+
+/// An index that no operation has used for a while, as returned by `unused_indexes`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UnusedIndex {
+    /// The name of the collection the index belongs to.
+    pub collection: String,
+    /// The usage statistics of the index.
+    pub stats: IndexStats,
+}
+
+impl IndexHostStats {
+    // Parses a `$indexStats` entry into the index name and key, and the server's statistics.
+    fn from_document(doc: &bson::Document) -> Result<(String, bson::Document, IndexHostStats)> {
+        let name = match doc.get("name") {
+            Some(&Bson::String(ref name)) => name.to_owned(),
+            _ => return Err(ResponseError(String::from("Index statistics are missing 'name'."))),
+        };
+
+        let key = match doc.get("key") {
+            Some(&Bson::Document(ref key)) => key.clone(),
+            _ => return Err(ResponseError(String::from("Index statistics are missing 'key'."))),
+        };
+
+        let host = match doc.get("host") {
+            Some(&Bson::String(ref host)) => host.to_owned(),
+            _ => return Err(ResponseError(String::from("Index statistics are missing 'host'."))),
+        };
+
+        let shard = match doc.get("shard") {
+            Some(&Bson::String(ref shard)) => Some(shard.to_owned()),
+            _ => None,
+        };
+
+        let accesses = match doc.get("accesses") {
+            Some(&Bson::Document(ref accesses)) => accesses,
+            _ => {
+                return Err(ResponseError(
+                    String::from("Index statistics are missing 'accesses'."),
+                ))
+            }
+        };
+
+        let ops = match accesses.get("ops") {
+            Some(&Bson::I32(ops)) => i64::from(ops),
+            Some(&Bson::I64(ops)) => ops,
+            _ => return Err(ResponseError(String::from("Index statistics are missing 'ops'."))),
+        };
+
+        let since = match accesses.get("since") {
+            Some(&Bson::UtcDatetime(since)) => since,
+            _ => return Err(ResponseError(String::from("Index statistics are missing 'since'."))),
+        };
+
+        let stats = IndexHostStats {
+            host: host,
+            shard: shard,
+            ops: ops,
+            since: since,
+        };
+
+        Ok((name, key, stats))
+    }
+}
+
+impl IndexStats {
+    /// Parses the documents returned by `$indexStats`, merging the entries that different
+    /// servers report for the same index. The result is sorted by index name.
+    pub fn from_documents(docs: &[bson::Document]) -> Result<Vec<IndexStats>> {
+        let mut indexes: Vec<IndexStats> = Vec::new();
+
+        for doc in docs {
+            let (name, key, host_stats) = IndexHostStats::from_document(doc)?;
+
+            if let Some(index) = indexes.iter_mut().find(|index| index.name == name) {
+                index.ops += host_stats.ops;
+                if host_stats.since > index.since {
+                    index.since = host_stats.since;
+                }
+                index.hosts.push(host_stats);
+                continue;
+            }
+
+            indexes.push(IndexStats {
+                name: name,
+                key: key,
+                ops: host_stats.ops,
+                since: host_stats.since,
+                hosts: vec![host_stats],
+            });
+        }
+
+        indexes.sort_by(|a, b| a.name.cmp(&b.name));
+
+        for index in &mut indexes {
+            index.hosts.sort_by(|a, b| (&a.shard, &a.host).cmp(&(&b.shard, &b.host)));
+        }
+
+        Ok(indexes)
+    }
+}
@@ -0,0 +1,144 @@
+//! Checks of the keys of documents stored by inserts and replacements.
+//!
+//! Servers before MongoDB 5.0 reject keys containing `.` or starting with `$` only in some
+//! positions, with errors that don't say where the key is, and some old versions store them,
+//! leaving documents that can't be queried or updated. Stored documents are therefore checked at
+//! every depth before they are sent. Filters and update documents, where such keys are
+//! legitimate, are not checked.
+use bson::{self, Bson};
+
+use std::result;
+
+// The keys of a DBRef, which are the only ones starting with '$' that servers store.
+const DBREF_KEYS: [&str; 3] = ["$ref", "$id", "$db"];
+
+/// Checks that no key of a document to be stored, at any depth, contains `.` or starts with
+/// `$`, other than the `$ref`, `$id` and `$db` keys of DBRefs. Returns the reason the first
+/// offending key is rejected, which gives its path from the top of the document, with array
+/// elements named by their index.
+pub fn check_keys(doc: &bson::Document) -> result::Result<(), String> {
+    check_document(doc, &mut Vec::new())
+}
+
+fn check_document(doc: &bson::Document, path: &mut Vec<String>) -> result::Result<(), String> {
+    for (key, value) in doc {
+        path.push(key.to_owned());
+
+        if key.starts_with('$') && !DBREF_KEYS.contains(&key.as_str()) {
+            return Err(invalid_key(path, "starts with '$'"));
+        }
+
+        if key.contains('.') {
+            return Err(invalid_key(path, "contains '.'"));
+        }
+
+        check_value(value, path)?;
+        path.pop();
+    }
+
+    Ok(())
+}
+
+fn check_value(value: &Bson, path: &mut Vec<String>) -> result::Result<(), String> {
+    match *value {
+        Bson::Document(ref doc) => check_document(doc, path),
+        Bson::Array(ref values) => {
+            for (index, value) in values.iter().enumerate() {
+                path.push(index.to_string());
+                check_value(value, path)?;
+                path.pop();
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn invalid_key(path: &[String], rule: &str) -> String {
+    let key = &path[path.len() - 1];
+    if path.len() == 1 {
+        format!("key {:?} {}", key, rule)
+    } else {
+        format!("key {:?} at {:?} {}", key, path.join("."), rule)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{Bson, bson, doc};
+    use super::check_keys;
+
+    #[test]
+    fn accepts_plain_keys() {
+        let doc = doc! {
+            "_id": 1,
+            "name": "a$b",
+            "nested": { "deeper": { "key": [1, { "x": 2 }] } },
+            "empty": {},
+        };
+        assert_eq!(check_keys(&doc), Ok(()));
+    }
+
+    #[test]
+    fn rejects_dotted_keys_with_path() {
+        assert_eq!(
+            check_keys(&doc! { "a.b": 1 }),
+            Err(String::from("key \"a.b\" contains '.'"))
+        );
+        assert_eq!(
+            check_keys(&doc! { "address": { "zip.code": "12345" } }),
+            Err(String::from("key \"zip.code\" at \"address.zip.code\" contains '.'"))
+        );
+    }
+
+    #[test]
+    fn rejects_dollar_keys_with_path() {
+        assert_eq!(
+            check_keys(&doc! { "$set": { "x": 1 } }),
+            Err(String::from("key \"$set\" starts with '$'"))
+        );
+        assert_eq!(
+            check_keys(&doc! { "a": { "b": { "$gt": 1 } } }),
+            Err(String::from("key \"$gt\" at \"a.b.$gt\" starts with '$'"))
+        );
+    }
+
+    #[test]
+    fn names_array_elements_by_index() {
+        let doc = doc! { "items": [{ "ok": 1 }, { "ok": 2 }, [{ "bad.key": 3 }]] };
+        assert_eq!(
+            check_keys(&doc),
+            Err(String::from("key \"bad.key\" at \"items.2.0.bad.key\" contains '.'"))
+        );
+    }
+
+    #[test]
+    fn allows_dbref_keys() {
+        let doc = doc! { "owner": { "$ref": "users", "$id": 5, "$db": "accounts" } };
+        assert_eq!(check_keys(&doc), Ok(()));
+
+        let doc = doc! { "owner": { "$ref": "users", "$id": 5, "$other": 1 } };
+        assert_eq!(
+            check_keys(&doc),
+            Err(String::from("key \"$other\" at \"owner.$other\" starts with '$'"))
+        );
+    }
+
+    #[test]
+    fn ignores_string_values_and_code_scopes() {
+        let doc = doc! {
+            "path": "a.b.$c",
+            "code": Bson::JavaScriptCodeWithScope(String::from("x"), doc! { "a.b": 1 }),
+        };
+        assert_eq!(check_keys(&doc), Ok(()));
+    }
+
+    #[test]
+    fn reports_first_offending_key() {
+        let doc = doc! { "ok": 1, "first.bad": 2, "$second": 3 };
+        assert_eq!(
+            check_keys(&doc),
+            Err(String::from("key \"first.bad\" contains '.'"))
+        );
+    }
+}
@@ -1,42 +1,145 @@
 //! Interface for collection-level operations.
 mod batch;
+pub mod buffered;
+pub mod bulk;
+pub mod change_stream;
+pub mod chunks;
+pub mod encryption;
 pub mod error;
+pub mod index_stats;
+mod keys;
 pub mod options;
 pub mod results;
+pub mod shard_key;
+pub mod snapshot;
+pub mod validator;
 
+use apm::{CommandResult, CommandStarted};
 use bson::{self, Bson, bson, doc, oid};
 use command_type::CommandType;
 
-use self::batch::{Batch, DeleteModel, UpdateModel};
+use self::batch::{Batch, BatchEntry, DeleteModel, UpdateModel};
+use self::bulk::BulkOperationBuilder;
+use self::change_stream::ChangeStream;
+use self::chunks::{sample_quotas, ChunkDistribution, ChunkSample};
+use self::encryption::FieldEncryptor;
 use self::error::{BulkWriteException, WriteException};
+use self::index_stats::IndexStats;
 use self::options::*;
 use self::results::*;
+use self::shard_key::ShardKeyAnalysis;
+use self::snapshot::SnapshotCache;
+use self::validator::WriteValidators;
 
-use ThreadedClient;
-use common::{merge_options, ReadPreference, WriteConcern};
-use cursor::Cursor;
+use {acquire_write_stream_for, run_command_with_stream, send_unacknowledged, ThreadedClient};
+use common::{merge_options, ReadConcern, ReadMode, ReadPreference, WriteConcern};
+use cursor::{Cursor, QueryResultMeta, DEFAULT_BATCH_SIZE};
 use db::{Database, ThreadedDatabase};
+use extjson;
+use op_ctx::OpCtx;
+use routing::RoutingProfile;
+use session::ClientSession;
+use topology::capabilities::ServerCapabilities;
 
 use Result;
-use Error::{ArgumentError, DecoderError, ResponseError, OperationError, BulkWriteError};
-
-use wire_protocol::flags::OpQueryFlags;
-use std::collections::{BTreeMap, VecDeque};
+use Error::{self, ArgumentError, BulkWriteError, CommandError, CopyError, DecoderError,
+            EventListenerError, OperationError, PolicyViolationError, ResponseError,
+            ViewWriteError};
+
+use error::{check_command_ok, check_get_last_error, ErrorCode,
+            COMMAND_NOT_SUPPORTED_ON_VIEW_CODE};
+use wire_protocol::flags::{OpDeleteFlags, OpInsertFlags, OpQueryFlags, OpUpdateFlags};
+use wire_protocol::raw;
+use wire_protocol::operations::{ByteLength, Message};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufRead, Write};
 use std::iter::FromIterator;
+use std::mem;
+use std::result;
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
+use time;
+
+// The largest total size of the ids sent in a single `$in` query by `find_by_ids`, leaving room
+// under the maximum document size for the rest of the query.
+const MAX_ID_BATCH_BYTES: usize = 16 * 1024 * 1024 - 16 * 1024;
+
+// The largest total size of the documents sent in a single legacy insert message, leaving room
+// under the maximum message size for the message header.
+const MAX_INSERT_MESSAGE_BYTES: i32 = 48_000_000 - 16 * 1024;
+
+// The largest total size of the raw documents sent in a single insert command, leaving room
+// under the maximum command size for the rest of the command.
+const MAX_RAW_INSERT_BATCH_BYTES: usize = 16 * 1024 * 1024;
+
+// The most raw documents sent in a single insert command, which every server version accepts.
+const MAX_RAW_INSERT_BATCH_SIZE: usize = 1000;
+
+// How many documents `copy_to` reads and inserts at a time by default.
+const DEFAULT_COPY_BATCH_SIZE: i32 = 1000;
+
+// How many documents `analyze_shard_key` samples from larger collections by default.
+const DEFAULT_SHARD_KEY_SAMPLE_SIZE: i64 = 100_000;
+
+// The fewest distinct values `analyze_shard_key` expects of a shard key by default.
+const DEFAULT_MIN_SHARD_KEY_VALUES: i64 = 100;
 
 /// Interfaces with a MongoDB collection.
-#[derive(Debug)]
+///
+/// Collections are cheap to clone: a clone shares the database handle and the client's
+/// connection pool, but has its own settings, so that each thread can own a handle to the same
+/// namespace. The `set_` methods take `&mut self`, as they only change the settings of this
+/// handle; settings shared by every handle to the namespace, such as its routing profile, are
+/// changed through the client.
+#[derive(Clone, Debug)]
 pub struct Collection {
     /// A reference to the database that spawned this collection.
     pub db: Database,
     /// The namespace of this collection, formatted as db_name.coll_name.
     pub namespace: String,
     read_preference: ReadPreference,
+    read_concern: Option<ReadConcern>,
     write_concern: WriteConcern,
+    write_validators: WriteValidators,
+    field_encryptor: Option<Arc<FieldEncryptor>>,
+    allow_dotted_keys: bool,
+    // Whether documents may be written to a `system.` collection, which only handles created
+    // with `new_unchecked` allow.
+    system_writes: bool,
+}
+
+// The only collection name containing '$' that can be used directly.
+const OPLOG_MAIN: &str = "oplog.$main";
+
+/// Checks that a collection name can be used by applications: it must not be empty, or contain
+/// '$' (except for `oplog.$main`) or a null byte. Collections starting with `system.` can be
+/// read, but writes to them are refused unless the handle was created with `new_unchecked`.
+pub fn validate_collection_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        return Err(ArgumentError(String::from("Collection names cannot be empty.")));
+    }
+
+    if name.contains('\0') {
+        return Err(ArgumentError(format!(
+            "Collection name {:?} cannot contain a null byte.",
+            name
+        )));
+    }
+
+    if name.contains('$') && name != OPLOG_MAIN {
+        return Err(ArgumentError(format!(
+            "Collection name '{}' cannot contain '$'.",
+            name
+        )));
+    }
+
+    Ok(())
 }
 
 impl Collection {
-    /// Creates a collection representation with optional read and write controls.
+    /// Creates a collection representation with optional read and write controls, after
+    /// checking the collection name with `validate_collection_name`.
     ///
     /// If `create` is specified, the collection will be explicitly created in the database.
     pub fn new(
@@ -45,6 +148,23 @@ impl Collection {
         create: bool,
         read_preference: Option<ReadPreference>,
         write_concern: Option<WriteConcern>,
+    ) -> Result<Collection> {
+        validate_collection_name(name)?;
+        let mut coll = Collection::new_unchecked(db, name, create, read_preference, write_concern);
+        coll.system_writes = false;
+        Ok(coll)
+    }
+
+    /// Creates a collection representation without checking the collection name, for special
+    /// namespaces such as `$cmd`, or to write to a system collection such as `system.js`.
+    ///
+    /// If `create` is specified, the collection will be explicitly created in the database.
+    pub fn new_unchecked(
+        db: Database,
+        name: &str,
+        create: bool,
+        read_preference: Option<ReadPreference>,
+        write_concern: Option<WriteConcern>,
     ) -> Collection {
 
         let rp = read_preference.unwrap_or_else(|| db.read_preference.to_owned());
@@ -59,7 +179,338 @@ impl Collection {
             db: db.clone(),
             namespace: format!("{}.{}", db.name, name),
             read_preference: rp,
+            read_concern: db.read_concern,
             write_concern: wc,
+            write_validators: WriteValidators::new(),
+            field_encryptor: None,
+            allow_dotted_keys: false,
+            system_writes: true,
+        }
+    }
+
+    /// Returns the read concern used by read operations on the collection that don't specify
+    /// their own.
+    pub fn read_concern(&self) -> Option<ReadConcern> {
+        self.read_concern
+    }
+
+    /// Sets the read concern used by read operations on the collection that don't specify
+    /// their own.
+    pub fn set_read_concern(&mut self, read_concern: Option<ReadConcern>) {
+        self.read_concern = read_concern;
+    }
+
+    /// Registers a check run on every document written through the collection: inserted and
+    /// replacement documents, and the `$set` and `$setOnInsert` payloads of updates. A rejection
+    /// fails the write with an `ArgumentError` naming the index of the rejected operation, and
+    /// nothing is sent to the server.
+    ///
+    /// Validators run in the order they were registered, after those of the database, which
+    /// are added with `ThreadedDatabase::with_write_validator`.
+    pub fn set_write_validator<F>(&mut self, validator: F)
+    where
+        F: Fn(&bson::Document) -> result::Result<(), String> + Send + Sync + 'static,
+    {
+        self.write_validators.push(Arc::new(validator));
+    }
+
+    /// Encrypts the fields configured in `encryptor` in every document written through the
+    /// collection, and in the filters of its queries, updates and deletes; documents read
+    /// through the collection are decrypted. Write validators see the documents before they are
+    /// encrypted. See `FieldEncryptor` for the supported writes and queries.
+    pub fn set_field_encryptor(&mut self, encryptor: FieldEncryptor) {
+        self.field_encryptor = if encryptor.is_empty() {
+            None
+        } else {
+            Some(Arc::new(encryptor))
+        };
+    }
+
+    /// Allows inserted and replacement documents to have keys containing '.' or starting with
+    /// '$', which MongoDB 5.0 and later store. Otherwise, such keys fail the write with an
+    /// `ArgumentError` giving their path in the document, and nothing is sent to the server.
+    /// Documents written to older servers are always checked.
+    pub fn set_allow_dotted_keys(&mut self, allow: bool) {
+        self.allow_dotted_keys = allow;
+    }
+
+    /// Returns the routing profile attached to the collection's namespace with
+    /// `ThreadedClient::set_routing_profile`, if any.
+    pub fn routing_profile(&self) -> Result<Option<RoutingProfile>> {
+        self.db.client.routing.profile(&self.namespace)
+    }
+
+    // Returns whether the keys of stored documents must be checked, which they must unless
+    // dotted keys are allowed and the server stores them.
+    fn checks_keys(&self) -> Result<bool> {
+        if !self.allow_dotted_keys {
+            return Ok(true);
+        }
+
+        Ok(!self.db.client.topology.supports(ServerCapabilities::supports_dotted_keys)?)
+    }
+
+    fn encrypt_filter(&self, filter: Option<bson::Document>) -> Result<Option<bson::Document>> {
+        match (filter, self.field_encryptor.as_ref()) {
+            (Some(filter), Some(encryptor)) => encryptor.encrypt_filter(filter).map(Some),
+            (filter, _) => Ok(filter),
+        }
+    }
+
+    // Encrypts an update or a replacement.
+    fn encrypt_update(&self, update: bson::Document) -> Result<bson::Document> {
+        match self.field_encryptor {
+            Some(ref encryptor) => encryptor.encrypt_update(update),
+            None => Ok(update),
+        }
+    }
+
+    fn decrypt_cursor(&self, cursor: Cursor) -> Cursor {
+        match self.field_encryptor {
+            Some(ref encryptor) => cursor.with_decryptor(encryptor.clone()),
+            None => cursor,
+        }
+    }
+
+    fn decrypt_document(&self, doc: bson::Document) -> Result<bson::Document> {
+        match self.field_encryptor {
+            Some(ref encryptor) => encryptor.decrypt_document(doc),
+            None => Ok(doc),
+        }
+    }
+
+    // Runs the write validators of the database and the collection over the documents written
+    // by an operation, each given along with its index in the operation. Every write that
+    // sends documents goes through here before anything is sent.
+    fn validate_writes<'a, I>(&self, docs: I) -> Result<()>
+    where
+        I: IntoIterator<Item = (usize, &'a bson::Document)>,
+    {
+        self.check_system_write()?;
+
+        let db_validators = &self.db.write_validators;
+        if db_validators.is_empty() && self.write_validators.is_empty() {
+            return Ok(());
+        }
+
+        for (index, doc) in docs {
+            db_validators
+                .validate(doc)
+                .and_then(|()| self.write_validators.validate(doc))
+                .map_err(|reason| {
+                    ArgumentError(format!("Write {} rejected by validator: {}", index, reason))
+                })?;
+        }
+
+        Ok(())
+    }
+
+    // Resolves the read concern document to send with a read operation, falling back to the
+    // collection's read concern. Read concerns are only sent to servers that support them
+    // (MongoDB 3.2 and later).
+    fn read_concern_document(
+        &self,
+        read_concern: Option<ReadConcern>,
+        read_preference: &ReadPreference,
+    ) -> Result<Option<bson::Document>> {
+        let read_concern = match read_concern.or(self.read_concern) {
+            Some(read_concern) => read_concern,
+            None => return Ok(None),
+        };
+
+        read_concern.validate(read_preference)?;
+
+        if !self.db.client.topology.supports(ServerCapabilities::supports_read_concern)? {
+            return Ok(None);
+        }
+
+        Ok(Some(read_concern.to_document()))
+    }
+
+    // Older servers silently ignore a collation, which would turn e.g. a case-insensitive match
+    // into a case-sensitive one, so reject it before sending anything.
+    fn check_collation(&self, collation: Option<&Collation>) -> Result<()> {
+        if collation.is_some() &&
+            !self.db.client.topology.supports(ServerCapabilities::supports_collation)?
+        {
+            return Err(ArgumentError(
+                String::from("Collation requires MongoDB 3.4 or later."),
+            ));
+        }
+        Ok(())
+    }
+
+    // The legacy getMore message can't carry a time limit, so awaiting getMores are sent as
+    // commands, which only tailable, awaiting cursors accept one for.
+    fn check_max_await_time(&self, options: &FindOptions) -> Result<()> {
+        if options.max_await_time_ms.is_none() {
+            return Ok(());
+        }
+
+        if options.cursor_type != CursorType::TailableAwait {
+            return Err(ArgumentError(
+                String::from("max_await_time_ms can only be set for TailableAwait cursors."),
+            ));
+        }
+
+        if !self.db.client.topology.supports(ServerCapabilities::supports_find_command)? {
+            return Err(ArgumentError(
+                String::from("max_await_time_ms requires MongoDB 3.2 or later."),
+            ));
+        }
+        Ok(())
+    }
+
+    // A snapshot scans the `_id` index and a natural sort scans the collection itself, so
+    // neither can be combined with another way of choosing the index.
+    fn check_scan_options(&self, options: &FindOptions) -> Result<()> {
+        if options.natural_sort.is_some() && options.hint.is_some() {
+            return Err(ArgumentError(
+                String::from("natural_sort can't be combined with a hint."),
+            ));
+        }
+
+        if !options.snapshot {
+            return Ok(());
+        }
+
+        if options.sort.is_some() || options.hint.is_some() || options.natural_sort.is_some() {
+            return Err(ArgumentError(
+                String::from("snapshot can't be combined with a sort, hint or natural_sort."),
+            ));
+        }
+
+        let removed = self.db
+            .client
+            .capabilities()?
+            .map_or(false, |capabilities| !capabilities.supports_snapshot_queries());
+        if removed {
+            return Err(ArgumentError(String::from(
+                "The snapshot option was removed in MongoDB 3.6; use a hint of { \"_id\": 1 } \
+                 instead, e.g. through FindOptions::consistent_scan.",
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_array_filters(&self, array_filters: Option<&Vec<bson::Document>>) -> Result<()> {
+        if array_filters.is_some() &&
+            !self.db.client.topology.supports(ServerCapabilities::supports_array_filters)?
+        {
+            return Err(ArgumentError(
+                String::from("Array filters require MongoDB 3.6 or later."),
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_delete_options(
+        &self,
+        hint: Option<&Hint>,
+        collation: Option<&Collation>,
+    ) -> Result<()> {
+        if hint.is_some() &&
+            !self.db.client.topology.supports(ServerCapabilities::supports_delete_hint)?
+        {
+            return Err(ArgumentError(
+                String::from("Delete hints require MongoDB 4.4 or later."),
+            ));
+        }
+
+        self.check_collation(collation)
+    }
+
+    fn check_update_options(
+        &self,
+        hint: Option<&Hint>,
+        collation: Option<&Collation>,
+        array_filters: Option<&Vec<bson::Document>>,
+    ) -> Result<()> {
+        if hint.is_some() &&
+            !self.db.client.topology.supports(ServerCapabilities::supports_update_hint)?
+        {
+            return Err(ArgumentError(
+                String::from("Update hints require MongoDB 4.2 or later."),
+            ));
+        }
+
+        self.check_collation(collation)?;
+        self.check_array_filters(array_filters)
+    }
+
+    // Refuses writes to system collections through handles created with a checked name.
+    fn check_system_write(&self) -> Result<()> {
+        if !self.system_writes && self.name().starts_with("system.") {
+            return Err(ArgumentError(format!(
+                "{} is a system collection, which applications can't write to.",
+                self.namespace
+            )));
+        }
+
+        Ok(())
+    }
+
+    // Checks bulk write models before anything is sent: their documents must match the kind of
+    // write, and the server must support the options they set.
+    fn validate_models(&self, models: &[WriteModel]) -> Result<()> {
+        for (index, model) in models.iter().enumerate() {
+            self.validate_model(model).map_err(|err| {
+                ArgumentError(format!("Write {} is invalid: {}", index, err))
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn validate_model(&self, model: &WriteModel) -> Result<()> {
+        match *model {
+            WriteModel::InsertOne { ref document } => {
+                if self.checks_keys()? {
+                    keys::check_keys(document).map_err(ArgumentError)?;
+                }
+                Ok(())
+            }
+            WriteModel::DeleteOne { ref collation, ref hint, .. } |
+            WriteModel::DeleteMany { ref collation, ref hint, .. } => {
+                self.check_delete_options(hint.as_ref(), collation.as_ref())
+            }
+            WriteModel::ReplaceOne {
+                ref filter,
+                ref replacement,
+                ref collation,
+                ref hint,
+                ..
+            } => {
+                if filter.is_empty() {
+                    return Err(ArgumentError(
+                        String::from("Replacement filter cannot be empty."),
+                    ));
+                }
+
+                self.validate_replace(replacement)?;
+                self.check_update_options(hint.as_ref(), collation.as_ref(), None)
+            }
+            WriteModel::UpdateOne {
+                ref update,
+                ref collation,
+                ref hint,
+                ref array_filters,
+                ..
+            } |
+            WriteModel::UpdateMany {
+                ref update,
+                ref collation,
+                ref hint,
+                ref array_filters,
+                ..
+            } => {
+                if update.is_empty() {
+                    return Err(ArgumentError(String::from("Update cannot be empty.")));
+                }
+
+                Collection::validate_update(update)?;
+                self.check_update_options(hint.as_ref(), collation.as_ref(), array_filters.as_ref())
+            }
         }
     }
 
@@ -91,11 +542,37 @@ impl Collection {
     }
 
     /// Runs an aggregation framework pipeline.
+    ///
+    /// Pipelines ending in an `$out` or `$merge` stage write to a collection and return no
+    /// documents. They are always run on the primary with the collection's write concern, and
+    /// fail with an `ArgumentError` if another read preference is requested; see
+    /// `aggregate_into`.
     pub fn aggregate(
         &self,
         pipeline: Vec<bson::Document>,
         options: Option<AggregateOptions>,
     ) -> Result<Cursor> {
+        self.aggregate_internal(pipeline, options, None)
+    }
+
+    /// Runs an aggregation framework pipeline under a logical session. Further batches are
+    /// fetched under the session too.
+    pub fn aggregate_with_session(
+        &self,
+        pipeline: Vec<bson::Document>,
+        options: Option<AggregateOptions>,
+        session: &mut ClientSession,
+    ) -> Result<Cursor> {
+        self.aggregate_internal(pipeline, options, Some(session))
+    }
+
+    fn aggregate_internal(
+        &self,
+        pipeline: Vec<bson::Document>,
+        options: Option<AggregateOptions>,
+        session: Option<&mut ClientSession>,
+    ) -> Result<Cursor> {
+        let writes_output = self.output_namespace(&pipeline)?.is_some();
         let pipeline_map: Vec<_> = pipeline.into_iter().map(Bson::Document).collect();
 
         let mut spec = doc! {
@@ -104,13 +581,26 @@ impl Collection {
         };
 
         let mut read_preference = self.read_preference.clone();
+        let mut read_concern = None;
+        let mut write_concern = None;
+        let hint = options.as_ref().and_then(|options| options.hint.clone());
 
         match options {
             Some(aggregate_options) => {
+                self.check_collation(aggregate_options.collation.as_ref())?;
+
                 if let Some(ref read_preference_option) = aggregate_options.read_preference {
+                    if writes_output && read_preference_option.mode != ReadMode::Primary {
+                        return Err(ArgumentError(String::from(
+                            "Pipelines with an $out or $merge stage must run on the primary.",
+                        )));
+                    }
+
                     read_preference = read_preference_option.clone();
                 }
 
+                read_concern = aggregate_options.read_concern;
+                write_concern = aggregate_options.write_concern.clone();
                 spec = merge_options(spec, aggregate_options);
             }
             None => {
@@ -118,9 +608,126 @@ impl Collection {
             }
         };
 
-        self.db.command_cursor(
-            spec,
-            CommandType::Aggregate,
+        if writes_output {
+            read_preference = ReadPreference::new(ReadMode::Primary, None);
+
+            let topology = &self.db.client.topology;
+            if topology.supports(ServerCapabilities::supports_aggregate_write_concern)? {
+                let wc = write_concern.unwrap_or_else(|| self.write_concern.clone());
+                spec.insert("writeConcern", wc.to_bson());
+            }
+        }
+
+        if let Some(read_concern) = self.read_concern_document(read_concern, &read_preference)? {
+            spec.insert("readConcern", read_concern);
+        }
+
+        let cursor = match session {
+            Some(session) => {
+                self.db.command_cursor_with_session(
+                    spec,
+                    CommandType::Aggregate,
+                    read_preference,
+                    session,
+                )
+            }
+            None => self.db.command_cursor(spec, CommandType::Aggregate, read_preference),
+        };
+
+        cursor
+            .map(|cursor| self.decrypt_cursor(cursor))
+            .map_err(|err| with_hint_context(err, hint.as_ref()))
+    }
+
+    /// Runs an aggregation pipeline ending in an `$out` or `$merge` stage, and returns a handle
+    /// to the collection the results were written to. Fails with an `ArgumentError` if the
+    /// pipeline doesn't end in one of those stages.
+    pub fn aggregate_into(
+        &self,
+        pipeline: Vec<bson::Document>,
+        options: Option<AggregateOptions>,
+    ) -> Result<Collection> {
+        let (db_name, coll_name) = match self.output_namespace(&pipeline)? {
+            Some(namespace) => namespace,
+            None => {
+                return Err(ArgumentError(String::from(
+                    "Pipeline must end with an $out or $merge stage.",
+                )))
+            }
+        };
+
+        // The results are written by the time the reply arrives, so there is nothing to read
+        // from the cursor.
+        self.aggregate(pipeline, options)?;
+
+        if db_name == self.db.name {
+            self.db.get_collection(&coll_name)
+        } else {
+            self.db.client.get_db(&db_name)?.get_collection(&coll_name)
+        }
+    }
+
+    // Returns the database and collection written to by a pipeline ending in an `$out` or
+    // `$merge` stage. Either stage may name a collection of the same database, or give the
+    // database and collection in a document.
+    fn output_namespace(&self, pipeline: &[bson::Document]) -> Result<Option<(String, String)>> {
+        let stage = match pipeline.last() {
+            Some(stage) => stage,
+            None => return Ok(None),
+        };
+
+        let target = match (stage.get("$out"), stage.get("$merge")) {
+            (Some(out), _) => out,
+            (None, Some(&Bson::Document(ref merge))) => {
+                match merge.get("into") {
+                    Some(into) => into,
+                    None => {
+                        return Err(ArgumentError(
+                            String::from("$merge stage is missing its 'into' field."),
+                        ))
+                    }
+                }
+            }
+            (None, Some(into)) => into,
+            (None, None) => return Ok(None),
+        };
+
+        match *target {
+            Bson::String(ref coll) => Ok(Some((self.db.name.clone(), coll.clone()))),
+            Bson::Document(ref namespace) => {
+                let db = namespace.get_str("db").unwrap_or(self.db.name.as_str()).to_owned();
+                match namespace.get_str("coll") {
+                    Ok(coll) => Ok(Some((db, coll.to_owned()))),
+                    Err(_) => Err(ArgumentError(format!(
+                        "Output stage has an invalid namespace: {}",
+                        namespace
+                    ))),
+                }
+            }
+            ref other => Err(ArgumentError(
+                format!("Output stage has an invalid namespace: {}", other),
+            )),
+        }
+    }
+
+    /// Opens a change stream over the collection, optionally filtered or transformed by the
+    /// aggregation stages in `pipeline`. Requires MongoDB 3.6 or later.
+    pub fn watch(
+        &self,
+        pipeline: Option<Vec<bson::Document>>,
+        options: Option<ChangeStreamOptions>,
+    ) -> Result<ChangeStream> {
+        let options = options.unwrap_or_default();
+        let read_preference = options
+            .read_preference
+            .clone()
+            .unwrap_or_else(|| self.read_preference.clone());
+
+        ChangeStream::open(
+            self.db.clone(),
+            self.name(),
+            pipeline.unwrap_or_default(),
+            options,
             read_preference,
         )
     }
@@ -130,30 +737,67 @@ impl Collection {
         &self,
         filter: Option<bson::Document>,
         options: Option<CountOptions>,
+    ) -> Result<i64> {
+        self.count_internal(filter, options, None)
+    }
+
+    /// Gets the number of documents matching the filter under a logical session.
+    pub fn count_with_session(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<CountOptions>,
+        session: &mut ClientSession,
+    ) -> Result<i64> {
+        self.count_internal(filter, options, Some(session))
+    }
+
+    fn count_internal(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<CountOptions>,
+        session: Option<&mut ClientSession>,
     ) -> Result<i64> {
         let mut spec = doc! {
             "count": self.name()
         };
 
-        if let Some(filter_doc) = filter {
+        if let Some(filter_doc) = self.encrypt_filter(filter)? {
             spec.insert("query", filter_doc);
         }
 
         let mut read_preference = self.read_preference.clone();
+        let mut read_concern = None;
+        let hint = options.as_ref().and_then(CountOptions::index_hint);
 
         if let Some(count_options) = options {
+            self.check_collation(count_options.collation.as_ref())?;
+
             if let Some(ref read_preference_option) = count_options.read_preference {
                 read_preference = read_preference_option.clone();
             }
 
+            read_concern = count_options.read_concern;
             spec = merge_options(spec, count_options);
         }
 
-        let result = self.db.command(
-            spec,
-            CommandType::Count,
-            Some(read_preference),
-        )?;
+        if let Some(read_concern) = self.read_concern_document(read_concern, &read_preference)? {
+            spec.insert("readConcern", read_concern);
+        }
+
+        let result = match session {
+            Some(session) => {
+                self.db.command_with_session(
+                    spec,
+                    CommandType::Count,
+                    Some(read_preference),
+                    session,
+                )
+            }
+            None => self.db.command(spec, CommandType::Count, Some(read_preference)),
+        };
+
+        let result = result.map_err(|err| with_hint_context(err, hint.as_ref()))?;
+
         match result.get("n") {
             Some(&Bson::I32(n)) => Ok(n as i64),
             Some(&Bson::I64(n)) => Ok(n),
@@ -163,46 +807,231 @@ impl Collection {
         }
     }
 
-    /// Finds the distinct values for a specified field across a single collection.
-    pub fn distinct(
+    /// Gets the number of documents matching the filter by counting them in an aggregation,
+    /// which, unlike the `count` command, is exact on sharded clusters with orphaned documents
+    /// or chunks being migrated. The `skip` and `limit` options apply to the matching documents,
+    /// and the hint options require MongoDB 3.6 or later. Returns 0 if nothing matches.
+    ///
+    /// See `estimated_document_count` to read the size of a whole collection from its metadata
+    /// instead.
+    pub fn count_documents(
         &self,
-        field_name: &str,
         filter: Option<bson::Document>,
-        options: Option<DistinctOptions>,
-    ) -> Result<Vec<Bson>> {
-        let mut spec = doc! {
-            "distinct": self.name(),
-            "key": field_name,
-        };
+        options: Option<CountOptions>,
+    ) -> Result<i64> {
+        let options = options.unwrap_or_default();
+        let filter = self.encrypt_filter(filter)?.unwrap_or_else(bson::Document::new);
 
-        if let Some(filter_doc) = filter {
-            spec.insert("query", filter_doc);
+        let mut pipeline = vec![doc! { "$match": filter }];
+
+        if let Some(skip) = options.skip {
+            if skip < 0 {
+                return Err(ArgumentError(format!("Skip must not be negative, got {}.", skip)));
+            }
+            if skip > 0 {
+                pipeline.push(doc! { "$skip": skip });
+            }
         }
 
-        let read_preference = options.and_then(|o| o.read_preference).unwrap_or_else(|| {
-            self.read_preference.clone()
+        // As with the `count` command, a limit of 0 means no limit and a negative limit is
+        // taken as its absolute value.
+        if let Some(limit) = options.limit {
+            if limit != 0 {
+                pipeline.push(doc! { "$limit": limit.abs() });
+            }
+        }
+
+        pipeline.push(doc! {
+            "$group": { "_id": Bson::Null, "n": { "$sum": 1 } }
         });
 
-        let result = self.db.command(
-            spec,
-            CommandType::Distinct,
-            Some(read_preference),
-        )?;
-        match result.get("values") {
-            Some(&Bson::Array(ref vals)) => Ok(vals.to_owned()),
+        let mut aggregate_options = AggregateOptions::new();
+        aggregate_options.hint = options.index_hint();
+        aggregate_options.collation = options.collation;
+        aggregate_options.max_time_ms = options.max_time_ms;
+        aggregate_options.comment = options.comment;
+        aggregate_options.read_preference = options.read_preference;
+        aggregate_options.read_concern = options.read_concern;
+
+        let mut cursor = self.aggregate(pipeline, Some(aggregate_options))?;
+
+        // The group stage returns nothing when no documents match.
+        let result = match cursor.next() {
+            Some(result) => result?,
+            None => return Ok(0),
+        };
+
+        match result.get("n") {
+            Some(&Bson::I32(n)) => Ok(n as i64),
+            Some(&Bson::I64(n)) => Ok(n),
             _ => Err(ResponseError(
-                String::from("No values received from server."),
+                String::from("No count received from server."),
             )),
         }
     }
 
-    /// Returns a list of documents within the collection that match the filter.
-    pub fn find(
-        &self,
-        filter: Option<bson::Document>,
-        options: Option<FindOptions>,
+    /// Gets the number of documents in the collection from its metadata, without scanning
+    /// it. This is fast, but may be inaccurate after an unclean shutdown or on sharded
+    /// clusters with orphaned documents; see `count_documents` for an exact count. The
+    /// `skip`, `limit` and hint options don't apply to a whole collection and are ignored.
+    pub fn estimated_document_count(&self, options: Option<CountOptions>) -> Result<i64> {
+        let options = options.map(|mut options| {
+            options.skip = None;
+            options.limit = None;
+            options.hint = None;
+            options.hint_doc = None;
+            options
+        });
+
+        self.count_internal(None, options, None)
+    }
+
+    /// Finds the distinct values for a specified field across a single collection.
+    pub fn distinct(
+        &self,
+        field_name: &str,
+        filter: Option<bson::Document>,
+        options: Option<DistinctOptions>,
+    ) -> Result<Vec<Bson>> {
+        self.distinct_internal(field_name, filter, options, None)
+    }
+
+    /// Finds the distinct values for a specified field across a single collection under a
+    /// logical session.
+    pub fn distinct_with_session(
+        &self,
+        field_name: &str,
+        filter: Option<bson::Document>,
+        options: Option<DistinctOptions>,
+        session: &mut ClientSession,
+    ) -> Result<Vec<Bson>> {
+        self.distinct_internal(field_name, filter, options, Some(session))
+    }
+
+    fn distinct_internal(
+        &self,
+        field_name: &str,
+        filter: Option<bson::Document>,
+        options: Option<DistinctOptions>,
+        session: Option<&mut ClientSession>,
+    ) -> Result<Vec<Bson>> {
+        let mut spec = doc! {
+            "distinct": self.name(),
+            "key": field_name,
+        };
+
+        if let Some(filter_doc) = self.encrypt_filter(filter)? {
+            spec.insert("query", filter_doc);
+        }
+
+        let options = options.unwrap_or_default();
+        self.check_collation(options.collation.as_ref())?;
+        spec = merge_options(spec, options.to_document());
+
+        let read_preference = options.read_preference.unwrap_or_else(|| {
+            self.read_preference.clone()
+        });
+
+        if let Some(read_concern) =
+            self.read_concern_document(options.read_concern, &read_preference)?
+        {
+            spec.insert("readConcern", read_concern);
+        }
+
+        let result = match session {
+            Some(session) => {
+                self.db.command_with_session(
+                    spec,
+                    CommandType::Distinct,
+                    Some(read_preference),
+                    session,
+                )?
+            }
+            None => self.db.command(spec, CommandType::Distinct, Some(read_preference))?,
+        };
+
+        match result.get("values") {
+            Some(&Bson::Array(ref vals)) => {
+                match self.field_encryptor {
+                    Some(ref encryptor) => {
+                        vals.iter().cloned().map(|val| encryptor.decrypt_value(val)).collect()
+                    }
+                    None => Ok(vals.to_owned()),
+                }
+            }
+            _ => Err(ResponseError(
+                String::from("No values received from server."),
+            )),
+        }
+    }
+
+    /// Returns a list of documents within the collection that match the filter.
+    pub fn find(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<FindOptions>,
+    ) -> Result<Cursor> {
+        self.find_with_command_type(filter, options, CommandType::Find, None)
+    }
+
+    /// Returns a list of documents within the collection that match the filter, reading under
+    /// a logical session. If the session is causally consistent, the read observes every
+    /// earlier operation run under the session. Further batches are fetched under the session
+    /// too.
+    pub fn find_with_session(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<FindOptions>,
+        session: &mut ClientSession,
+    ) -> Result<Cursor> {
+        let options = options.unwrap_or_default();
+        self.check_collation(options.collation.as_ref())?;
+
+        let read_preference = match options.read_preference {
+            Some(ref read_preference_option) => read_preference_option.clone(),
+            None => self.read_preference.clone(),
+        };
+
+        let read_concern = self.read_concern_document(options.read_concern, &read_preference)?;
+
+        let hint = options.hint.clone();
+        let filter = self.encrypt_filter(filter)?;
+        let mut spec = self.find_command_spec(filter, options);
+
+        if let Some(read_concern) = read_concern {
+            spec.insert("readConcern", read_concern);
+        }
+
+        self.db.command_cursor_with_session(spec, CommandType::Find, read_preference, session)
+            .map(|cursor| self.decrypt_cursor(cursor))
+            .map_err(|err| with_hint_context(err, hint.as_ref()))
+    }
+
+    /// Returns a list of documents within the collection that match the filter, within `ctx`.
+    /// The query and every getMore of the returned cursor fail once the context is cancelled
+    /// or its deadline has passed, and the server is asked to stop by then through `maxTimeMS`.
+    pub fn find_with_ctx(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<FindOptions>,
+        ctx: &OpCtx,
+    ) -> Result<Cursor> {
+        let mut options = options.unwrap_or_default();
+        options.max_time_ms = ctx.limit_max_time_ms(options.max_time_ms);
+        self.find_with_command_type(filter, Some(options), CommandType::Find, Some(ctx))
+    }
+
+    /// Returns a list of documents within the collection that match the filter, forcing the
+    /// server to use the index described by `hint`. This overrides any hint in `options`.
+    pub fn find_with_hint(
+        &self,
+        filter: Option<bson::Document>,
+        hint: Hint,
+        options: Option<FindOptions>,
     ) -> Result<Cursor> {
-        self.find_with_command_type(filter, options, CommandType::Find)
+        let mut options = options.unwrap_or_default();
+        options.hint = Some(hint);
+        self.find(filter, Some(options))
     }
 
     fn find_with_command_type(
@@ -210,26 +1039,84 @@ impl Collection {
         filter: Option<bson::Document>,
         options: Option<FindOptions>,
         cmd_type: CommandType,
+        ctx: Option<&OpCtx>,
     ) -> Result<Cursor> {
-        let find_options = options.unwrap_or_default();
-        let flags = OpQueryFlags::with_find_options(&find_options);
+        let hint = options.as_ref().and_then(|options| options.hint.clone());
+        let filter = self.encrypt_filter(filter)?;
 
-        let doc = match find_options.sort {
-            Some(ref sort_opt) => {
-                doc! {
-                    "$query": filter.unwrap_or_default(),
-                    "$orderby": sort_opt.clone(),
-                }
-            }
-            None => filter.unwrap_or_default(),
-        };
+        self.find_cursor(filter, options, cmd_type, ctx)
+            .map(|cursor| self.decrypt_cursor(cursor))
+            .map_err(|err| with_hint_context(err, hint.as_ref()))
+    }
+
+    fn find_cursor(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<FindOptions>,
+        cmd_type: CommandType,
+        ctx: Option<&OpCtx>,
+    ) -> Result<Cursor> {
+        let find_options = options.unwrap_or_default();
+        self.check_collation(find_options.collation.as_ref())?;
+        self.check_max_await_time(&find_options)?;
+        self.check_scan_options(&find_options)?;
 
         let read_preference = match find_options.read_preference {
             Some(ref read_preference_option) => read_preference_option.clone(),
             None => self.read_preference.clone(),
         };
 
-        Cursor::query(
+        let read_concern = if cmd_type == CommandType::Find {
+            self.read_concern_document(find_options.read_concern, &read_preference)?
+        } else {
+            None
+        };
+
+        // Legacy queries can't carry a read concern or collation, so use the find command
+        // instead.
+        if read_concern.is_some() || find_options.collation.is_some() {
+            return self.find_command(filter, find_options, read_concern, read_preference, ctx);
+        }
+
+        let flags = OpQueryFlags::with_find_options(&find_options);
+
+        // Legacy query modifiers require the filter to be wrapped in a $query document.
+        let doc = if find_options.sort.is_none() && find_options.comment.is_none() &&
+            find_options.hint.is_none() && find_options.max_time_ms.is_none() &&
+            find_options.natural_sort.is_none() && !find_options.snapshot
+        {
+            filter.unwrap_or_default()
+        } else {
+            let mut doc = doc! { "$query": filter.unwrap_or_default() };
+
+            if let Some(ref sort_opt) = find_options.sort {
+                doc.insert("$orderby", sort_opt.clone());
+            }
+
+            if let Some(ref comment) = find_options.comment {
+                doc.insert("$comment", comment.to_owned());
+            }
+
+            if let Some(ref hint) = find_options.hint {
+                doc.insert("$hint", hint.to_bson());
+            }
+
+            if let Some(direction) = find_options.natural_sort {
+                doc.insert("$hint", doc! { "$natural": direction.to_i32() });
+            }
+
+            if let Some(max_time_ms) = find_options.max_time_ms {
+                doc.insert("$maxTimeMS", max_time_ms);
+            }
+
+            if find_options.snapshot {
+                doc.insert("$snapshot", true);
+            }
+
+            doc
+        };
+
+        Cursor::query_with_ctx(
             self.db.client.clone(),
             self.namespace.to_owned(),
             flags,
@@ -238,9 +1125,75 @@ impl Collection {
             cmd_type,
             false,
             read_preference,
+            ctx,
         )
     }
 
+    // Runs a query using the find command, which supports options that legacy queries don't.
+    fn find_command(
+        &self,
+        filter: Option<bson::Document>,
+        options: FindOptions,
+        read_concern: Option<bson::Document>,
+        read_preference: ReadPreference,
+        ctx: Option<&OpCtx>,
+    ) -> Result<Cursor> {
+        let max_await_time_ms = options.max_await_time_ms;
+        let mut spec = self.find_command_spec(filter, options);
+
+        if let Some(read_concern) = read_concern {
+            spec.insert("readConcern", read_concern);
+        }
+
+        let mut cursor = Cursor::command_cursor_with_ctx(
+            self.db.client.clone(),
+            &self.db.name,
+            spec,
+            CommandType::Find,
+            read_preference,
+            ctx,
+        )?;
+        cursor.set_max_await_time_ms(max_await_time_ms)?;
+        Ok(cursor)
+    }
+
+    // Builds a find command from a filter and legacy query options.
+    fn find_command_spec(
+        &self,
+        filter: Option<bson::Document>,
+        options: FindOptions,
+    ) -> bson::Document {
+        let mut spec = doc! {
+            "find": self.name(),
+            "filter": filter.unwrap_or_default(),
+        };
+
+        match options.cursor_type {
+            CursorType::NonTailable => (),
+            CursorType::Tailable => {
+                spec.insert("tailable", true);
+            }
+            CursorType::TailableAwait => {
+                spec.insert("tailable", true);
+                spec.insert("awaitData", true);
+            }
+        }
+
+        if options.allow_partial_results {
+            spec.insert("allowPartialResults", true);
+        }
+
+        if options.no_cursor_timeout {
+            spec.insert("noCursorTimeout", true);
+        }
+
+        if options.oplog_replay {
+            spec.insert("oplogReplay", true);
+        }
+
+        merge_options(spec, options)
+    }
+
     /// Returns the first document within the collection that matches the filter, or None.
     pub fn find_one(
         &self,
@@ -250,6 +1203,38 @@ impl Collection {
         self.find_one_with_command_type(filter, options, CommandType::Find)
     }
 
+    /// Returns the first document within the collection that matches the filter, or None,
+    /// along with the server that answered the query and the role it had when it was selected.
+    pub fn find_one_with_meta(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<FindOptions>,
+    ) -> Result<(Option<bson::Document>, Option<QueryResultMeta>)> {
+        self.find_one_with_meta_and_command_type(filter, options, CommandType::Find)
+    }
+
+    /// Like `find_one_with_meta`, reporting the query to command hooks as `cmd_type`, e.g. to
+    /// learn which server ran a command sent as a query on the `$cmd` collection.
+    pub fn find_one_with_meta_and_command_type(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<FindOptions>,
+        cmd_type: CommandType,
+    ) -> Result<(Option<bson::Document>, Option<QueryResultMeta>)> {
+        let mut find_one_options = options.unwrap_or_default();
+        find_one_options.limit = Some(1);
+
+        let mut cursor =
+            self.find_with_command_type(filter, Some(find_one_options), cmd_type, None)?;
+        let meta = cursor.meta();
+
+        match cursor.next() {
+            Some(Ok(bson)) => Ok((Some(bson), meta)),
+            Some(Err(err)) => Err(err),
+            None => Ok((None, meta)),
+        }
+    }
+
     pub fn find_one_with_command_type(
         &self,
         filter: Option<bson::Document>,
@@ -263,6 +1248,7 @@ impl Collection {
             filter,
             Some(find_one_options),
             cmd_type,
+            None,
         )?;
 
         match cursor.next() {
@@ -272,15 +1258,138 @@ impl Collection {
         }
     }
 
+    /// Returns the first document within the collection that matches the filter, or None,
+    /// within `ctx`.
+    pub fn find_one_with_ctx(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<FindOptions>,
+        ctx: &OpCtx,
+    ) -> Result<Option<bson::Document>> {
+        let mut find_one_options = options.unwrap_or_default();
+        find_one_options.limit = Some(1);
+
+        match self.find_with_ctx(filter, Some(find_one_options), ctx)?.next() {
+            Some(Ok(bson)) => Ok(Some(bson)),
+            Some(Err(err)) => Err(err),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the first document within the collection that matches the filter, or None,
+    /// reading under a logical session. If the session is causally consistent, the read
+    /// observes every earlier operation run under the session.
+    pub fn find_one_with_session(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<FindOptions>,
+        session: &mut ClientSession,
+    ) -> Result<Option<bson::Document>> {
+        let mut find_options = options.unwrap_or_default();
+        find_options.limit = Some(1);
+
+        match self.find_with_session(filter, Some(find_options), session)?.next() {
+            Some(Ok(doc)) => Ok(Some(doc)),
+            Some(Err(err)) => Err(err),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the document with the given `_id`, or None.
+    pub fn find_one_by_id(
+        &self,
+        id: Bson,
+        options: Option<FindOptions>,
+    ) -> Result<Option<bson::Document>> {
+        self.find_one(Some(doc! { "_id": id }), options)
+    }
+
+    /// Returns the documents with the given `_id`s, in the same order as `ids`, with None in place
+    /// of ids that don't match any document.
+    ///
+    /// The ids are queried with `$in`, split over several queries if a single one would exceed
+    /// the maximum document size. Any `skip` or `limit` in the options is ignored, and a
+    /// projection must not exclude `_id`.
+    pub fn find_by_ids(
+        &self,
+        ids: Vec<Bson>,
+        options: Option<FindOptions>,
+    ) -> Result<Vec<Option<bson::Document>>> {
+        let mut find_options = options.unwrap_or_default();
+        find_options.skip = None;
+        find_options.limit = None;
+
+        let mut keys = Vec::with_capacity(ids.len());
+        let mut queued = HashSet::new();
+        let mut batches = vec![Vec::new()];
+        let mut batch_bytes = 0;
+
+        for id in ids {
+            let key = id_key(&id)?;
+            keys.push(key.clone());
+
+            // Each distinct id only needs to be queried once.
+            if !queued.insert(key.clone()) {
+                continue;
+            }
+
+            if batch_bytes + key.len() > MAX_ID_BATCH_BYTES && batch_bytes > 0 {
+                batches.push(Vec::new());
+                batch_bytes = 0;
+            }
+
+            batch_bytes += key.len();
+            if let Some(batch) = batches.last_mut() {
+                batch.push(id);
+            }
+        }
+
+        let mut found = HashMap::new();
+
+        for batch in batches {
+            if batch.is_empty() {
+                continue;
+            }
+
+            let filter = doc! { "_id": { "$in": batch } };
+
+            for result in self.find(Some(filter), Some(find_options.clone()))? {
+                let doc = result?;
+                let key = match doc.get("_id") {
+                    Some(id) => id_key(id)?,
+                    None => continue,
+                };
+                found.insert(key, doc);
+            }
+        }
+
+        Ok(keys.iter().map(|key| found.get(key).cloned()).collect())
+    }
+
     // Helper method for all findAndModify commands.
     fn find_and_modify(
         &self,
         filter: bson::Document,
-        options: bson::Document,
+        mut options: bson::Document,
         _max_time_ms: Option<i64>,
         write_concern: Option<WriteConcern>,
         cmd_type: CommandType,
     ) -> Result<Option<bson::Document>> {
+        self.check_system_write()?;
+
+        let update = match options.get("update") {
+            Some(&Bson::Document(ref update)) => {
+                self.validate_writes(Some((0, update)))?;
+                Some(self.encrypt_update(update.clone())?)
+            }
+            _ => None,
+        };
+
+        if let Some(update) = update {
+            options.insert("update", update);
+        }
+
+        let filter = self.encrypt_filter(Some(filter))?.unwrap_or_default();
         let mut cmd = doc! {
             "findAndModify": self.name(),
             "query": filter,
@@ -288,12 +1397,14 @@ impl Collection {
 
         cmd = merge_options(cmd, options);
 
-        let res = self.db.command(cmd, cmd_type, None)?;
+        let res = self.write_command(cmd, cmd_type, None)?;
         let wc = write_concern.unwrap_or_else(|| self.write_concern.clone());
         WriteException::validate_write_result(res.clone(), wc)?;
 
         let doc = match res.get("value") {
-            Some(&Bson::Document(ref nested_doc)) => Some(nested_doc.to_owned()),
+            Some(&Bson::Document(ref nested_doc)) => {
+                Some(self.decrypt_document(nested_doc.to_owned())?)
+            }
             _ => None,
         };
 
@@ -314,6 +1425,7 @@ impl Collection {
         let mut options_doc = doc! { "remove": true };
 
         if let Some(find_one_and_delete_options) = options {
+            self.check_collation(find_one_and_delete_options.collation.as_ref())?;
             options_doc = merge_options(options_doc, find_one_and_delete_options);
         }
 
@@ -334,7 +1446,7 @@ impl Collection {
         replacement: bson::Document,
         options: Option<FindOneAndUpdateOptions>,
     ) -> Result<Option<bson::Document>> {
-        Collection::validate_replace(&replacement)?;
+        self.validate_replace(&replacement)?;
 
         let (max_time_ms, write_concern) = match options {
             Some(ref opts) => (opts.max_time_ms, opts.write_concern.clone()),
@@ -344,6 +1456,8 @@ impl Collection {
         let mut options_doc = doc! { "update": replacement };
 
         if let Some(find_one_and_replace_options) = options {
+            self.check_collation(find_one_and_replace_options.collation.as_ref())?;
+            self.check_array_filters(find_one_and_replace_options.array_filters.as_ref())?;
             options_doc = merge_options(options_doc, find_one_and_replace_options);
         }
 
@@ -374,6 +1488,8 @@ impl Collection {
         let mut options_doc = doc! { "update": update };
 
         if let Some(find_one_and_update_options) = options {
+            self.check_collation(find_one_and_update_options.collation.as_ref())?;
+            self.check_array_filters(find_one_and_update_options.array_filters.as_ref())?;
             options_doc = merge_options(options_doc, find_one_and_update_options);
         }
 
@@ -386,86 +1502,56 @@ impl Collection {
         )
     }
 
-    fn get_unordered_batches(requests: Vec<WriteModel>) -> Vec<Batch> {
-        let mut inserts = Vec::new();
-        let mut deletes = Vec::new();
-        let mut updates = Vec::new();
-
-        for req in requests {
-            match req {
-                WriteModel::InsertOne { document } => inserts.push(document),
-                WriteModel::DeleteOne { filter } => {
-                    deletes.push(DeleteModel {
-                        filter: filter,
-                        multi: false,
-                    })
-                }
-                WriteModel::DeleteMany { filter } => {
-                    deletes.push(DeleteModel {
-                        filter: filter,
-                        multi: true,
-                    })
-                }
-                WriteModel::ReplaceOne {
-                    filter,
-                    replacement,
-                    upsert,
-                } => {
-                    updates.push(UpdateModel {
-                        filter: filter,
-                        update: replacement,
-                        upsert: upsert,
-                        multi: false,
-                    })
+    // Groups the requests by kind, along with the index of each request in `requests`.
+    fn get_unordered_batches(requests: Vec<WriteModel>) -> Vec<(Batch, Vec<i64>)> {
+        let (mut inserts, mut insert_indices) = (Vec::new(), Vec::new());
+        let (mut deletes, mut delete_indices) = (Vec::new(), Vec::new());
+        let (mut updates, mut update_indices) = (Vec::new(), Vec::new());
+
+        for (index, req) in requests.into_iter().enumerate() {
+            match BatchEntry::from(req) {
+                BatchEntry::Insert(document) => {
+                    inserts.push(document);
+                    insert_indices.push(index as i64);
                 }
-                WriteModel::UpdateOne {
-                    filter,
-                    update,
-                    upsert,
-                } => {
-                    updates.push(UpdateModel {
-                        filter: filter,
-                        update: update,
-                        upsert: upsert,
-                        multi: false,
-                    })
+                BatchEntry::Delete(model) => {
+                    deletes.push(model);
+                    delete_indices.push(index as i64);
                 }
-                WriteModel::UpdateMany {
-                    filter,
-                    update,
-                    upsert,
-                } => {
-                    updates.push(UpdateModel {
-                        filter: filter,
-                        update: update,
-                        upsert: upsert,
-                        multi: true,
-                    })
+                BatchEntry::Update(model) => {
+                    updates.push(model);
+                    update_indices.push(index as i64);
                 }
             }
         }
 
         vec![
-            Batch::Insert(inserts),
-            Batch::Delete(deletes),
-            Batch::Update(updates),
+            (Batch::Insert(inserts), insert_indices),
+            (Batch::Delete(deletes), delete_indices),
+            (Batch::Update(updates), update_indices),
         ]
     }
 
-    fn get_ordered_batches(mut requests: VecDeque<WriteModel>) -> Vec<Batch> {
-        let first_model = match requests.pop_front() {
-            Some(model) => model,
-            None => return Vec::new(),
-        };
-
-        let mut batches = vec![Batch::from(first_model)];
-
-        for model in requests {
-            let last_index = batches.len() - 1;
+    // Groups consecutive requests of the same kind, along with the index of each request in
+    // `requests`.
+    fn get_ordered_batches(requests: Vec<WriteModel>) -> Vec<(Batch, Vec<i64>)> {
+        let mut batches: Vec<(Batch, Vec<i64>)> = Vec::new();
+
+        for (index, model) in requests.into_iter().enumerate() {
+            let model = match batches.last_mut() {
+                Some(&mut (ref mut batch, ref mut indices)) => {
+                    match batch.merge_model(model) {
+                        Some(model) => model,
+                        None => {
+                            indices.push(index as i64);
+                            continue;
+                        }
+                    }
+                }
+                None => model,
+            };
 
-            if let Some(model) = batches[last_index].merge_model(model) {
-                batches.push(Batch::from(model));
-            }
+            batches.push((Batch::from(model), vec![index as i64]));
         }
 
         batches
@@ -474,8 +1560,8 @@ impl Collection {
     fn execute_insert_batch(
         &self,
         documents: Vec<bson::Document>,
-        start_index: i64,
         ordered: bool,
+        write_concern: Option<WriteConcern>,
         result: &mut BulkWriteResult,
         exception: &mut BulkWriteException,
     ) -> bool {
@@ -487,12 +1573,13 @@ impl Collection {
 
         let options = Some(InsertManyOptions {
             ordered: Some(ordered),
+            write_concern: write_concern,
             ..Default::default()
         });
 
         match self.insert_many(documents, options) {
             Ok(insert_result) => {
-                result.process_insert_many_result(insert_result, models, start_index, exception)
+                result.process_insert_many_result(insert_result, models, 0, exception)
             }
             Err(_) => {
                 exception.add_unproccessed_models(models);
@@ -505,19 +1592,13 @@ impl Collection {
         &self,
         models: Vec<DeleteModel>,
         ordered: bool,
+        write_concern: Option<WriteConcern>,
         result: &mut BulkWriteResult,
         exception: &mut BulkWriteException,
     ) -> bool {
-        let original_models = models
-            .iter()
-            .map(|model| if model.multi {
-                WriteModel::DeleteMany { filter: model.filter.clone() }
-            } else {
-                WriteModel::DeleteOne { filter: model.filter.clone() }
-            })
-            .collect();
+        let original_models = models.iter().cloned().map(WriteModel::from).collect();
 
-        match self.bulk_delete(models, ordered, None, CommandType::DeleteMany) {
+        match self.bulk_delete(models, ordered, write_concern, CommandType::DeleteMany, None) {
             Ok(bulk_delete_result) => {
                 result.process_bulk_delete_result(bulk_delete_result, original_models, exception)
             }
@@ -531,36 +1612,17 @@ impl Collection {
     fn execute_update_batch(
         &self,
         models: Vec<UpdateModel>,
-        start_index: i64,
         ordered: bool,
+        write_concern: Option<WriteConcern>,
         result: &mut BulkWriteResult,
         exception: &mut BulkWriteException,
     ) -> bool {
-        let original_models = models
-            .iter()
-            .map(|model| if model.multi {
-                WriteModel::UpdateMany {
-                    filter: model.filter.clone(),
-                    update: model.update.clone(),
-                    upsert: model.upsert.clone(),
-                }
-            } else {
-                WriteModel::UpdateOne {
-                    filter: model.filter.clone(),
-                    update: model.update.clone(),
-                    upsert: model.upsert.clone(),
-                }
-            })
-            .collect();
+        let original_models = models.iter().cloned().map(WriteModel::from).collect();
+        let cmd_type = CommandType::UpdateMany;
 
-        match self.bulk_update(models, ordered, None, CommandType::UpdateMany) {
+        match self.bulk_update(models, ordered, write_concern, None, cmd_type, None) {
             Ok(bulk_update_result) => {
-                result.process_bulk_update_result(
-                    bulk_update_result,
-                    original_models,
-                    start_index,
-                    exception,
-                )
+                result.process_bulk_update_result(bulk_update_result, original_models, 0, exception)
             }
             Err(_) => {
                 exception.add_unproccessed_models(original_models);
@@ -572,45 +1634,109 @@ impl Collection {
     fn execute_batch(
         &self,
         batch: Batch,
-        start_index: i64,
         ordered: bool,
+        write_concern: Option<WriteConcern>,
         result: &mut BulkWriteResult,
         exception: &mut BulkWriteException,
     ) -> bool {
+        let wc = write_concern;
         match batch {
-            Batch::Insert(docs) => {
-                self.execute_insert_batch(docs, start_index, ordered, result, exception)
+            Batch::Insert(docs) => self.execute_insert_batch(docs, ordered, wc, result, exception),
+            Batch::Delete(models) => {
+                self.execute_delete_batch(models, ordered, wc, result, exception)
             }
-            Batch::Delete(models) => self.execute_delete_batch(models, ordered, result, exception),
             Batch::Update(models) => {
-                self.execute_update_batch(models, start_index, ordered, result, exception)
+                self.execute_update_batch(models, ordered, wc, result, exception)
             }
         }
     }
 
     /// Sends a batch of writes to the server at the same time.
+    ///
+    /// Each request is checked before anything is sent: updates must be made of update
+    /// operators, replacements must not contain any and must have a non-empty filter, and the
+    /// server must support the collations, hints and array filters the requests set. If a request
+    /// fails these checks or a write validator rejects it, none of them are sent; they are all
+    /// reported as unprocessed, and the exception message names the rejected request.
     pub fn bulk_write(&self, requests: Vec<WriteModel>, ordered: bool) -> BulkWriteResult {
-        let batches = if ordered {
-            Collection::get_ordered_batches(VecDeque::from_iter(requests.into_iter()))
-        } else {
-            Collection::get_unordered_batches(requests)
-        };
+        self.execute_bulk_write(requests, ordered, None)
+    }
 
-        let mut result = BulkWriteResult::new();
-        let mut exception = BulkWriteException::new(Vec::new(), Vec::new(), Vec::new(), None);
+    /// Starts a fluent bulk write whose writes are applied in the order they are added,
+    /// stopping at the first one that fails.
+    pub fn initialize_ordered_bulk_op(&self) -> BulkOperationBuilder {
+        BulkOperationBuilder::new(self, true)
+    }
 
-        let mut start_index = 0;
+    /// Starts a fluent bulk write whose writes may be applied in any order, all of them being
+    /// attempted even if some fail.
+    pub fn initialize_unordered_bulk_op(&self) -> BulkOperationBuilder {
+        BulkOperationBuilder::new(self, false)
+    }
 
-        for batch in batches {
-            let length = batch.len();
-            let success =
-                self.execute_batch(batch, start_index, ordered, &mut result, &mut exception);
+    // Runs a bulk write with the given write concern, or the collection's. Write errors and
+    // the inserted and upserted ids are keyed by the index of their request in `requests`.
+    fn execute_bulk_write(
+        &self,
+        requests: Vec<WriteModel>,
+        ordered: bool,
+        write_concern: Option<WriteConcern>,
+    ) -> BulkWriteResult {
+        let written = requests.iter().enumerate().filter_map(|(index, model)| match *model {
+            WriteModel::InsertOne { ref document } => Some((index, document)),
+            WriteModel::ReplaceOne { ref replacement, .. } => Some((index, replacement)),
+            WriteModel::UpdateOne { ref update, .. } |
+            WriteModel::UpdateMany { ref update, .. } => Some((index, update)),
+            WriteModel::DeleteOne { .. } |
+            WriteModel::DeleteMany { .. } => None,
+        });
+
+        let validated = self.validate_models(&requests).and_then(
+            |()| self.validate_writes(written),
+        );
+
+        if let Err(err) = validated {
+            let mut exception = BulkWriteException::new(Vec::new(), requests, Vec::new(), None);
+            exception.message = err.to_string();
+
+            let mut result = BulkWriteResult::new();
+            result.bulk_write_exception = Some(exception);
+            return result;
+        }
+
+        let batches = if ordered {
+            Collection::get_ordered_batches(requests)
+        } else {
+            Collection::get_unordered_batches(requests)
+        };
+
+        let mut result = BulkWriteResult::new();
+        let mut exception = BulkWriteException::new(Vec::new(), Vec::new(), Vec::new(), None);
+
+        for (batch, indices) in batches {
+            let mut batch_result = BulkWriteResult::new();
+            let errors_before = exception.write_errors.len();
+
+            let success = self.execute_batch(
+                batch,
+                ordered,
+                write_concern.clone(),
+                &mut batch_result,
+                &mut exception,
+            );
+
+            // The server numbers write errors within their batch.
+            for err in &mut exception.write_errors[errors_before..] {
+                if let Some(&index) = indices.get(err.index as usize) {
+                    err.index = index as i32;
+                }
+            }
+
+            result.add_batch_result(batch_result, &indices);
 
             if !success && ordered {
                 break;
             }
-
-            start_index += length;
         }
 
         if !exception.unprocessed_requests.is_empty() {
@@ -627,10 +1753,11 @@ impl Collection {
         options: Option<InsertManyOptions>,
         write_concern: Option<WriteConcern>,
         cmd_type: CommandType,
+        session: Option<&mut ClientSession>,
     ) -> Result<(Vec<Bson>, Option<BulkWriteException>)> {
 
         let wc = write_concern.unwrap_or_else(|| self.write_concern.clone());
-        let mut converted_docs = Vec::with_capacity(docs.len());
+        let mut documents = Vec::with_capacity(docs.len());
         let mut ids = Vec::with_capacity(docs.len());
 
         for mut doc in docs {
@@ -643,19 +1770,58 @@ impl Collection {
                 },
             };
             ids.push(id);
-            converted_docs.push(Bson::Document(doc));
+            documents.push(doc);
         }
 
-        let mut cmd = doc! {
-            "insert": self.name(),
-            "documents": converted_docs
+        if self.checks_keys()? {
+            for (index, doc) in documents.iter().enumerate() {
+                keys::check_keys(doc).map_err(|reason| {
+                    ArgumentError(format!("Document {} is invalid: {}.", index, reason))
+                })?;
+            }
+        }
+
+        self.validate_writes(documents.iter().enumerate())?;
+
+        let documents = match self.field_encryptor {
+            Some(ref encryptor) => {
+                documents
+                    .into_iter()
+                    .map(|doc| encryptor.encrypt_document(doc))
+                    .collect::<Result<Vec<_>>>()?
+            }
+            None => documents,
         };
 
-        if let Some(insert_options) = options {
-            cmd = merge_options(cmd, insert_options);
+        // Unacknowledged writes outside of a session have no outcome to wait for. Legacy insert
+        // messages can't carry a comment or bypass document validation, though.
+        let options = options.unwrap_or_default();
+        if session.is_none() && wc.is_unacknowledged() && options.comment.is_none() &&
+            options.bypass_document_validation != Some(true)
+        {
+            let ordered = options.ordered.unwrap_or(true);
+            if self.insert_unacknowledged(&documents, ordered, &wc, cmd_type)? {
+                return Ok((ids, None));
+            }
+        }
+
+        // Servers before MongoDB 2.6 only accept legacy insert messages.
+        let topology = &self.db.client.topology;
+        if session.is_none() && !topology.supports(ServerCapabilities::supports_write_commands)? {
+            let ordered = options.ordered.unwrap_or(true);
+            let exception = self.insert_legacy(documents, ordered, &wc, cmd_type)?;
+            return Ok((ids, exception));
         }
 
-        let result = self.db.command(cmd, cmd_type, None)?;
+        let converted_docs: Vec<_> = documents.into_iter().map(Bson::Document).collect();
+
+        let cmd = doc! {
+            "insert": self.name(),
+            "documents": converted_docs
+        };
+        let cmd = merge_options(cmd, options);
+
+        let result = self.write_command(cmd, cmd_type, session)?;
 
         // Intercept bulk write exceptions and insert into the result
         let exception_res = BulkWriteException::validate_bulk_write_result(result.clone(), wc);
@@ -668,12 +1834,181 @@ impl Collection {
         Ok((ids, exception))
     }
 
+    // Sends each document as its own legacy insert message, followed by a getLastError over the
+    // same connection, so that a failure is reported with the index of the document causing it.
+    // An ordered insert stops at the first failure.
+    fn insert_legacy(
+        &self,
+        docs: Vec<bson::Document>,
+        ordered: bool,
+        write_concern: &WriteConcern,
+        cmd_type: CommandType,
+    ) -> Result<Option<BulkWriteException>> {
+        let client = &self.db.client;
+        let mut stream = acquire_write_stream_for(client, Some(&self.namespace))?;
+        let host = stream.host().clone();
+
+        let mut get_last_error = doc! { "getLastError": 1, "w": write_concern.w };
+        if write_concern.w_timeout > 0 {
+            get_last_error.insert("wtimeout", write_concern.w_timeout);
+        }
+        if write_concern.j {
+            get_last_error.insert("j", true);
+        }
+        if write_concern.fsync {
+            get_last_error.insert("fsync", true);
+        }
+
+        let mut write_errors = Vec::new();
+        for (index, doc) in docs.into_iter().enumerate() {
+            let message = Message::new_insert(
+                client.get_req_id(),
+                OpInsertFlags::empty(),
+                self.namespace.to_owned(),
+                vec![doc],
+            )?;
+
+            client.log_message(true, &host, &message);
+            if let Err(err) = message.write(stream.get_socket()) {
+                client.metrics.record_error(&err);
+                stream.set_dirty(true);
+                return Err(err);
+            }
+
+            let reply = run_command_with_stream(
+                client,
+                &mut stream,
+                &self.db.name,
+                get_last_error.clone(),
+                cmd_type,
+            )?;
+            check_get_last_error(&reply, write_concern)?;
+
+            // The failure of the insert itself is reported in `err`.
+            if let Some(&Bson::String(ref message)) = reply.get("err") {
+                let code = reply.get_i32("code").unwrap_or(0);
+                let error = self::error::BulkWriteError::new(index as i32, code, message, None);
+                write_errors.push(error);
+                if ordered {
+                    break;
+                }
+            }
+        }
+
+        if write_errors.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(BulkWriteException::new(Vec::new(), Vec::new(), write_errors, None)))
+        }
+    }
+
+    // Sends the documents as legacy insert messages without reading a reply. Returns false
+    // without sending anything if the server no longer accepts legacy insert messages.
+    fn insert_unacknowledged(
+        &self,
+        docs: &[bson::Document],
+        ordered: bool,
+        write_concern: &WriteConcern,
+        cmd_type: CommandType,
+    ) -> Result<bool> {
+        let client = &self.db.client;
+        let flags = if ordered {
+            OpInsertFlags::empty()
+        } else {
+            OpInsertFlags::CONTINUE_ON_ERROR
+        };
+
+        let mut batches = Vec::new();
+        let mut start = 0;
+        while start < docs.len() {
+            let mut end = start;
+            let mut batch_bytes = 0;
+
+            while end < docs.len() {
+                let doc_bytes = docs[end].byte_length()?;
+                if end > start && batch_bytes + doc_bytes > MAX_INSERT_MESSAGE_BYTES {
+                    break;
+                }
+
+                batch_bytes += doc_bytes;
+                end += 1;
+            }
+
+            batches.push(start..end);
+            start = end;
+        }
+
+        // Each message is only assembled once the previous one was sent.
+        let messages = batches.into_iter().map(|batch| {
+            Message::new_insert(
+                client.get_req_id(),
+                flags,
+                self.namespace.to_owned(),
+                docs[batch].to_vec(),
+            )
+        });
+
+        let command = || {
+            let documents: Vec<_> = docs.iter().cloned().map(Bson::Document).collect();
+            doc! {
+                "insert": self.name(),
+                "documents": documents,
+                "ordered": ordered,
+                "writeConcern": write_concern.to_bson(),
+            }
+        };
+
+        self.write_unacknowledged(messages, command, cmd_type)
+    }
+
+    // Sends legacy write messages on a single connection without reading replies, so that
+    // consecutive unacknowledged writes don't wait for each other. Returns false without taking
+    // a connection if the server no longer accepts legacy write messages.
+    fn write_unacknowledged<I, F>(
+        &self,
+        messages: I,
+        command: F,
+        cmd_type: CommandType,
+    ) -> Result<bool>
+    where
+        I: IntoIterator<Item = Result<Message>>,
+        F: FnOnce() -> bson::Document,
+    {
+        let client = &self.db.client;
+        if !client.topology.supports(ServerCapabilities::supports_legacy_writes)? {
+            return Ok(false);
+        }
+
+        let mut stream = acquire_write_stream_for(client, Some(&self.namespace))?;
+        send_unacknowledged(client, &mut stream, &self.db.name, messages, command, cmd_type)?;
+        Ok(true)
+    }
+
     /// Inserts the provided document. If the document is missing an identifier,
     /// the driver should generate one.
     pub fn insert_one(
         &self,
         doc: bson::Document,
         write_concern: Option<WriteConcern>,
+    ) -> Result<InsertOneResult> {
+        self.insert_one_internal(doc, write_concern, None)
+    }
+
+    /// Inserts the provided document under a logical session.
+    pub fn insert_one_with_session(
+        &self,
+        doc: bson::Document,
+        write_concern: Option<WriteConcern>,
+        session: &mut ClientSession,
+    ) -> Result<InsertOneResult> {
+        self.insert_one_internal(doc, write_concern, Some(session))
+    }
+
+    fn insert_one_internal(
+        &self,
+        doc: bson::Document,
+        write_concern: Option<WriteConcern>,
+        session: Option<&mut ClientSession>,
     ) -> Result<InsertOneResult> {
         let options = InsertManyOptions {
             write_concern: write_concern.clone(),
@@ -685,6 +2020,7 @@ impl Collection {
             Some(options),
             write_concern,
             CommandType::InsertOne,
+            session,
         )?;
 
         if ids.is_empty() {
@@ -718,6 +2054,25 @@ impl Collection {
         &self,
         docs: Vec<bson::Document>,
         options: Option<InsertManyOptions>,
+    ) -> Result<InsertManyResult> {
+        self.insert_many_internal(docs, options, None)
+    }
+
+    /// Inserts the provided documents under a logical session.
+    pub fn insert_many_with_session(
+        &self,
+        docs: Vec<bson::Document>,
+        options: Option<InsertManyOptions>,
+        session: &mut ClientSession,
+    ) -> Result<InsertManyResult> {
+        self.insert_many_internal(docs, options, Some(session))
+    }
+
+    fn insert_many_internal(
+        &self,
+        docs: Vec<bson::Document>,
+        options: Option<InsertManyOptions>,
+        session: Option<&mut ClientSession>,
     ) -> Result<InsertManyResult> {
         let write_concern = options.as_ref().and_then(
             |opts| opts.write_concern.clone(),
@@ -728,6 +2083,7 @@ impl Collection {
             options,
             write_concern,
             CommandType::InsertMany,
+            session,
         )?;
 
         let mut map = BTreeMap::from_iter(
@@ -743,6 +2099,234 @@ impl Collection {
         Ok(InsertManyResult::new(Some(map), exception))
     }
 
+    /// Inserts documents that are already encoded as BSON, without decoding them. Only the
+    /// length prefix of each document and its top-level elements are checked, to find its
+    /// `_id`; an ObjectId is generated and prepended to documents without one.
+    ///
+    /// Raw documents can't be inserted into a collection with write validators or field
+    /// encryption, which both need decoded documents, and are not retried.
+    pub fn insert_raw(
+        &self,
+        docs: Vec<Vec<u8>>,
+        write_concern: Option<WriteConcern>,
+    ) -> Result<InsertManyResult> {
+        let options = InsertManyOptions {
+            write_concern: write_concern,
+            ..Default::default()
+        };
+
+        self.insert_many_raw(docs, Some(options))
+    }
+
+    /// Inserts documents that are already encoded as BSON, like `insert_raw`, with the options
+    /// of `insert_many`. The documents are sent in as many insert commands as their size
+    /// requires, and ordered inserts stop at the first command with a write error.
+    pub fn insert_many_raw(
+        &self,
+        docs: Vec<Vec<u8>>,
+        options: Option<InsertManyOptions>,
+    ) -> Result<InsertManyResult> {
+        if !self.write_validators.is_empty() || self.field_encryptor.is_some() {
+            return Err(ArgumentError(String::from(
+                "Raw documents can't be inserted into a collection with write validators or \
+                 field encryption.",
+            )));
+        }
+
+        let mut documents = Vec::with_capacity(docs.len());
+        let mut ids = BTreeMap::new();
+
+        for (index, doc) in docs.into_iter().enumerate() {
+            let id = match raw::find_id(&doc) {
+                Ok(id) => id,
+                Err(err) => {
+                    let msg = format!("Raw document {} is invalid: {}.", index, err);
+                    return Err(ArgumentError(msg));
+                }
+            };
+
+            let (doc, id) = match id {
+                Some(id) => (doc, id),
+                None => {
+                    let id = oid::ObjectId::new()?;
+                    (raw::prepend_id(&doc, &id), Bson::ObjectId(id))
+                }
+            };
+
+            ids.insert(index as i64, id);
+            documents.push(doc);
+        }
+
+        let options = options.unwrap_or_default();
+        let ordered = options.ordered.unwrap_or(true);
+        let wc = options.write_concern.clone().unwrap_or_else(|| self.write_concern.clone());
+        let cmd = merge_options(doc! { "insert": self.name() }, options);
+
+        let mut exception = BulkWriteException::new(Vec::new(), Vec::new(), Vec::new(), None);
+        let mut failed = false;
+        let mut start = 0;
+
+        while start < documents.len() {
+            let mut end = start;
+            let mut batch_bytes = 0;
+
+            while end < documents.len() && end - start < MAX_RAW_INSERT_BATCH_SIZE {
+                let doc_bytes = documents[end].len();
+                if end > start && batch_bytes + doc_bytes > MAX_RAW_INSERT_BATCH_BYTES {
+                    break;
+                }
+
+                batch_bytes += doc_bytes;
+                end += 1;
+            }
+
+            let batch = &documents[start..end];
+            let reply = self.raw_write_command(&cmd, "documents", batch, CommandType::InsertMany)?;
+
+            match BulkWriteException::validate_bulk_write_result(reply, wc.clone()) {
+                Ok(()) => (),
+                Err(BulkWriteError(mut batch_exception)) => {
+                    // The server numbers write errors within the command.
+                    for err in &mut batch_exception.write_errors {
+                        err.index += start as i32;
+                        ids.remove(&(err.index as i64));
+                    }
+
+                    failed = true;
+                    let stop = ordered && !batch_exception.write_errors.is_empty();
+                    exception.add_bulk_write_exception(Some(batch_exception), Vec::new());
+
+                    if stop {
+                        // The documents after this command were not sent.
+                        ids = ids.into_iter().filter(|&(index, _)| index < end as i64).collect();
+                        break;
+                    }
+                }
+                Err(err) => return Err(err),
+            }
+
+            start = end;
+        }
+
+        let exception = if failed { Some(exception) } else { None };
+        Ok(InsertManyResult::new(Some(ids), exception))
+    }
+
+    // Sends a write command with raw documents appended under `key` to the primary, and
+    // returns its reply. Command monitoring sees the command with its documents decoded, which
+    // only happens if a start hook is registered.
+    fn raw_write_command(
+        &self,
+        cmd: &bson::Document,
+        key: &str,
+        documents: &[Vec<u8>],
+        cmd_type: CommandType,
+    ) -> Result<bson::Document> {
+        self.check_system_write()?;
+        let query = raw::encode_command(cmd, key, documents)?;
+
+        let client = &self.db.client;
+        let mut stream = acquire_write_stream_for(client, Some(&self.namespace))?;
+        let host = stream.host().clone();
+        let connstring = stream.get_socket().get_ref().peer_addr()?.to_string();
+        let cmd_name = cmd_type.to_str();
+        let request_id = client.get_req_id();
+
+        if client.listener.has_start_hooks() {
+            let command = bson::decode_document(&mut &query[..]).unwrap_or_else(|_| cmd.clone());
+            let hook_result = client.run_start_hooks(&CommandStarted {
+                command: command,
+                database_name: self.db.name.clone(),
+                command_name: String::from(cmd_name),
+                request_id: request_id as i64,
+                connection_string: connstring.clone(),
+                host: host.clone(),
+            });
+
+            if hook_result.is_err() {
+                return Err(EventListenerError(None));
+            }
+        }
+
+        let message = Message::new_query_raw(
+            request_id,
+            OpQueryFlags::empty(),
+            format!("{}.$cmd", self.db.name),
+            0,
+            -1,
+            query,
+        );
+
+        client.log_message(true, &host, &message);
+
+        let init_time = time::precise_time_ns();
+        let start = Instant::now();
+        let result = {
+            let socket = stream.get_socket();
+            message.write(socket).and_then(|()| {
+                Message::read_reply_to(socket, request_id, client.max_bson_depth)
+            })
+        };
+        client.record_round_trip(start, &result);
+        if let Ok(ref reply) = result {
+            client.slow_ops.record(start, &message, reply, &host);
+        }
+
+        // A connection left in the middle of an exchange must not be reused.
+        if result.is_err() {
+            stream.set_dirty(true);
+        }
+
+        let reply = result.and_then(|reply| {
+            client.log_message(false, &host, &reply);
+
+            let reply = match reply {
+                Message::OpReply { mut documents, .. } => {
+                    match documents.next() {
+                        Some(reply) => reply?,
+                        None => return Err(ResponseError(String::from("Empty command reply."))),
+                    }
+                }
+                _ => return Err(ResponseError(String::from("Invalid command reply."))),
+            };
+
+            check_command_ok(&reply)?;
+            Ok(reply)
+        });
+
+        let duration = time::precise_time_ns() - init_time;
+        match reply {
+            Ok(reply) => {
+                let _hook_result = client.run_completion_hooks(&CommandResult::Success {
+                    duration: duration,
+                    reply: reply.clone(),
+                    command_name: String::from(cmd_name),
+                    request_id: request_id as i64,
+                    connection_string: connstring,
+                    host: host,
+                });
+
+                Ok(reply)
+            }
+            Err(err) => {
+                let hook_result = client.run_completion_hooks(&CommandResult::Failure {
+                    duration: duration,
+                    command_name: String::from(cmd_name),
+                    failure: &err,
+                    request_id: request_id as i64,
+                    connection_string: connstring,
+                    host: host,
+                });
+
+                if hook_result.is_err() {
+                    return Err(EventListenerError(Some(Box::new(err))));
+                }
+
+                Err(err)
+            }
+        }
+    }
+
     // Sends a batch of delete ops to the server at once.
     fn bulk_delete(
         &self,
@@ -750,16 +2334,51 @@ impl Collection {
         ordered: bool,
         write_concern: Option<WriteConcern>,
         cmd_type: CommandType,
+        session: Option<&mut ClientSession>,
     ) -> Result<BulkDeleteResult> {
+        self.check_system_write()?;
 
         let wc = write_concern.unwrap_or_else(|| self.write_concern.clone());
-        let deletes: Vec<_> = models
-            .into_iter()
-            .map(|model| bson!({
-                "q": model.filter,
+
+        // Unacknowledged deletes outside of a session have no outcome to wait for. Legacy
+        // delete messages can't carry a hint or a collation, though.
+        let unacknowledged = session.is_none() && wc.is_unacknowledged() &&
+            models.iter().all(|model| model.hint.is_none() && model.collation.is_none());
+
+        let mut deletes = Vec::with_capacity(models.len());
+        let mut messages = Vec::new();
+        for model in models {
+            let filter = self.encrypt_filter(Some(model.filter))?.unwrap_or_default();
+            if unacknowledged {
+                let flags = if model.multi {
+                    OpDeleteFlags::empty()
+                } else {
+                    OpDeleteFlags::SINGLE_REMOVE
+                };
+
+                messages.push(Message::new_delete(
+                    self.db.client.get_req_id(),
+                    self.namespace.to_owned(),
+                    flags,
+                    filter.clone(),
+                ));
+            }
+
+            let mut delete = doc! {
+                "q": filter,
                 "limit": if model.multi { 0_i64 } else { 1_i64 },
-            }))
-            .collect();
+            };
+
+            if let Some(hint) = model.hint {
+                delete.insert("hint", hint.to_bson());
+            }
+
+            if let Some(collation) = model.collation {
+                delete.insert("collation", collation.to_document());
+            }
+
+            deletes.push(Bson::Document(delete));
+        }
 
         let cmd = doc! {
             "delete": self.name(),
@@ -767,7 +2386,12 @@ impl Collection {
             "ordered": ordered,
             "writeConcern": wc.to_bson(),
         };
-        let result = self.db.command(cmd, cmd_type, None)?;
+
+        if unacknowledged && self.write_unacknowledged(messages, || cmd.clone(), cmd_type)? {
+            return Ok(BulkDeleteResult::unacknowledged());
+        }
+
+        let result = self.write_command(cmd, cmd_type, session)?;
 
         // Intercept write exceptions and insert into the result
         let exception_res = BulkWriteException::validate_bulk_write_result(result.clone(), wc);
@@ -783,21 +2407,24 @@ impl Collection {
     // Internal deletion helper function.
     fn delete(
         &self,
-        filter: bson::Document,
-        multi: bool,
+        model: DeleteModel,
         write_concern: Option<WriteConcern>,
+        session: Option<&mut ClientSession>,
     ) -> Result<DeleteResult> {
-        let cmd_type = if multi {
+        let cmd_type = if model.multi {
             CommandType::DeleteMany
         } else {
             CommandType::DeleteOne
         };
 
+        self.check_delete_options(model.hint.as_ref(), model.collation.as_ref())?;
+
         self.bulk_delete(
-            vec![DeleteModel::new(filter, multi)],
+            vec![model],
             true,
             write_concern,
             cmd_type,
+            session,
         ).map(
             DeleteResult::with_bulk_result
         )
@@ -809,7 +2436,31 @@ impl Collection {
         filter: bson::Document,
         write_concern: Option<WriteConcern>,
     ) -> Result<DeleteResult> {
-        self.delete(filter, false, write_concern)
+        self.delete(DeleteModel::new(filter, false), write_concern, None)
+    }
+
+    /// Deletes a single document with the given options.
+    pub fn delete_one_with_options(
+        &self,
+        filter: bson::Document,
+        options: Option<DeleteOptions>,
+    ) -> Result<DeleteResult> {
+        let options = options.unwrap_or_default();
+        let mut model = DeleteModel::new(filter, false);
+        model.hint = options.hint;
+        model.collation = options.collation;
+
+        self.delete(model, options.write_concern, None)
+    }
+
+    /// Deletes a single document under a logical session.
+    pub fn delete_one_with_session(
+        &self,
+        filter: bson::Document,
+        write_concern: Option<WriteConcern>,
+        session: &mut ClientSession,
+    ) -> Result<DeleteResult> {
+        self.delete(DeleteModel::new(filter, false), write_concern, Some(session))
     }
 
     /// Deletes multiple documents.
@@ -818,7 +2469,31 @@ impl Collection {
         filter: bson::Document,
         write_concern: Option<WriteConcern>,
     ) -> Result<DeleteResult> {
-        self.delete(filter, true, write_concern)
+        self.delete(DeleteModel::new(filter, true), write_concern, None)
+    }
+
+    /// Deletes multiple documents with the given options.
+    pub fn delete_many_with_options(
+        &self,
+        filter: bson::Document,
+        options: Option<DeleteOptions>,
+    ) -> Result<DeleteResult> {
+        let options = options.unwrap_or_default();
+        let mut model = DeleteModel::new(filter, true);
+        model.hint = options.hint;
+        model.collation = options.collation;
+
+        self.delete(model, options.write_concern, None)
+    }
+
+    /// Deletes multiple documents under a logical session.
+    pub fn delete_many_with_session(
+        &self,
+        filter: bson::Document,
+        write_concern: Option<WriteConcern>,
+        session: &mut ClientSession,
+    ) -> Result<DeleteResult> {
+        self.delete(DeleteModel::new(filter, true), write_concern, Some(session))
     }
 
     // Sends a batch of replace and update ops to the server at once.
@@ -827,22 +2502,64 @@ impl Collection {
         models: Vec<UpdateModel>,
         ordered: bool,
         write_concern: Option<WriteConcern>,
+        comment: Option<String>,
         cmd_type: CommandType,
+        session: Option<&mut ClientSession>,
     ) -> Result<BulkUpdateResult> {
+        self.validate_writes(models.iter().map(|model| &model.update).enumerate())?;
+
         let wc = write_concern.unwrap_or_else(|| self.write_concern.clone());
-        let updates: Vec<_> = models
-            .into_iter()
-            .map(|model| Bson::Document(bson::Document::from(model)))
-            .collect();
 
-        let cmd = doc! {
+        // Unacknowledged updates outside of a session have no outcome to wait for. Legacy
+        // update messages can't carry a comment, a hint, a collation or array filters, though.
+        let unacknowledged = session.is_none() && wc.is_unacknowledged() && comment.is_none() &&
+            models.iter().all(|model| {
+                model.hint.is_none() && model.collation.is_none() && model.array_filters.is_none()
+            });
+
+        let mut updates = Vec::with_capacity(models.len());
+        let mut messages = Vec::new();
+        for mut model in models {
+            model.filter = self.encrypt_filter(Some(model.filter))?.unwrap_or_default();
+            model.update = self.encrypt_update(model.update)?;
+            if unacknowledged {
+                let mut flags = OpUpdateFlags::empty();
+                if model.upsert == Some(true) {
+                    flags.insert(OpUpdateFlags::UPSERT);
+                }
+
+                if model.multi {
+                    flags.insert(OpUpdateFlags::MULTI_UPDATE);
+                }
+
+                messages.push(Message::new_update(
+                    self.db.client.get_req_id(),
+                    self.namespace.to_owned(),
+                    flags,
+                    model.filter.clone(),
+                    model.update.clone(),
+                ));
+            }
+
+            updates.push(Bson::Document(bson::Document::from(model)));
+        }
+
+        let mut cmd = doc! {
             "update": self.name(),
             "updates": updates,
             "ordered": ordered,
             "writeConcern": wc.to_bson()
         };
 
-        let result = self.db.command(cmd, cmd_type, None)?;
+        if let Some(comment) = comment {
+            cmd.insert("comment", comment);
+        }
+
+        if unacknowledged && self.write_unacknowledged(messages, || cmd.clone(), cmd_type)? {
+            return Ok(BulkUpdateResult::unacknowledged());
+        }
+
+        let result = self.write_command(cmd, cmd_type, session)?;
 
         // Intercept write exceptions and insert into the result
         let exception_res = BulkWriteException::validate_bulk_write_result(result.clone(), wc);
@@ -858,24 +2575,31 @@ impl Collection {
     // Internal update helper function.
     fn update(
         &self,
-        filter: bson::Document,
-        update: bson::Document,
-        upsert: Option<bool>,
-        multi: bool,
+        model: UpdateModel,
         write_concern: Option<WriteConcern>,
+        comment: Option<String>,
+        session: Option<&mut ClientSession>,
     ) -> Result<UpdateResult> {
 
-        let cmd_type = if multi {
+        let cmd_type = if model.multi {
             CommandType::UpdateMany
         } else {
             CommandType::UpdateOne
         };
 
+        self.check_update_options(
+            model.hint.as_ref(),
+            model.collation.as_ref(),
+            model.array_filters.as_ref(),
+        )?;
+
         self.bulk_update(
-            vec![UpdateModel::new(filter, update, upsert, multi)],
+            vec![model],
             true,
             write_concern,
+            comment,
             cmd_type,
+            session,
         ).map(
             UpdateResult::with_bulk_result
         )
@@ -888,17 +2612,65 @@ impl Collection {
         replacement: bson::Document,
         options: Option<ReplaceOptions>,
     ) -> Result<UpdateResult> {
-        let options = options.unwrap_or_default();
-
-        Collection::validate_replace(&replacement)?;
-
-        self.update(
-            filter,
-            replacement,
-            options.upsert,
-            false,
-            options.write_concern,
-        )
+        self.replace_one_internal(filter, replacement, options, None)
+    }
+
+    /// Inserts the document, or replaces the document with the same `_id` if there is one. An
+    /// ObjectId is generated for documents without an `_id`.
+    ///
+    /// Only the `_id` field is matched, so this works on collections without an `_id` index,
+    /// although every replacement then scans the collection.
+    pub fn save(
+        &self,
+        mut doc: bson::Document,
+        write_concern: Option<WriteConcern>,
+    ) -> Result<UpdateResult> {
+        let id = match doc.get("_id").cloned() {
+            Some(id) => id,
+            None => {
+                let id = Bson::ObjectId(oid::ObjectId::new()?);
+                doc.insert("_id", id.clone());
+                id
+            }
+        };
+
+        let options = ReplaceOptions {
+            upsert: Some(true),
+            write_concern: write_concern,
+            ..Default::default()
+        };
+
+        self.replace_one(doc! { "_id": id }, doc, Some(options))
+    }
+
+    /// Replaces a single document under a logical session.
+    pub fn replace_one_with_session(
+        &self,
+        filter: bson::Document,
+        replacement: bson::Document,
+        options: Option<ReplaceOptions>,
+        session: &mut ClientSession,
+    ) -> Result<UpdateResult> {
+        self.replace_one_internal(filter, replacement, options, Some(session))
+    }
+
+    fn replace_one_internal(
+        &self,
+        filter: bson::Document,
+        replacement: bson::Document,
+        options: Option<ReplaceOptions>,
+        session: Option<&mut ClientSession>,
+    ) -> Result<UpdateResult> {
+        let options = options.unwrap_or_default();
+
+        self.validate_replace(&replacement)?;
+
+        let mut model = UpdateModel::new(filter, replacement, options.upsert, false);
+        model.hint = options.hint;
+        model.collation = options.collation;
+        model.array_filters = options.array_filters;
+
+        self.update(model, options.write_concern, options.comment, session)
     }
 
     /// Updates a single document.
@@ -908,17 +2680,18 @@ impl Collection {
         update: bson::Document,
         options: Option<UpdateOptions>,
     ) -> Result<UpdateResult> {
-        let options = options.unwrap_or_default();
-
-        Collection::validate_update(&update)?;
+        self.update_with_options(filter, update, options, false, None)
+    }
 
-        self.update(
-            filter,
-            update,
-            options.upsert,
-            false,
-            options.write_concern
-        )
+    /// Updates a single document under a logical session.
+    pub fn update_one_with_session(
+        &self,
+        filter: bson::Document,
+        update: bson::Document,
+        options: Option<UpdateOptions>,
+        session: &mut ClientSession,
+    ) -> Result<UpdateResult> {
+        self.update_with_options(filter, update, options, false, Some(session))
     }
 
     /// Updates multiple documents.
@@ -927,21 +2700,53 @@ impl Collection {
         filter: bson::Document,
         update: bson::Document,
         options: Option<UpdateOptions>,
+    ) -> Result<UpdateResult> {
+        self.update_with_options(filter, update, options, true, None)
+    }
+
+    /// Updates multiple documents under a logical session.
+    pub fn update_many_with_session(
+        &self,
+        filter: bson::Document,
+        update: bson::Document,
+        options: Option<UpdateOptions>,
+        session: &mut ClientSession,
+    ) -> Result<UpdateResult> {
+        self.update_with_options(filter, update, options, true, Some(session))
+    }
+
+    fn update_with_options(
+        &self,
+        filter: bson::Document,
+        update: bson::Document,
+        options: Option<UpdateOptions>,
+        multi: bool,
+        session: Option<&mut ClientSession>,
     ) -> Result<UpdateResult> {
         let options = options.unwrap_or_default();
 
         Collection::validate_update(&update)?;
 
-        self.update(
-            filter,
-            update,
-            options.upsert,
-            true,
-            options.write_concern
-        )
+        let mut model = UpdateModel::new(filter, update, options.upsert, multi);
+        model.hint = options.hint;
+        model.collation = options.collation;
+        model.array_filters = options.array_filters;
+
+        self.update(model, options.write_concern, options.comment, session)
+    }
+
+    // Runs a write command, attaching the session id if a session was provided. Writes to a
+    // view fail with a `ViewWriteError`.
+    fn write_command(
+        &self,
+        cmd: bson::Document,
+        cmd_type: CommandType,
+        session: Option<&mut ClientSession>,
+    ) -> Result<bson::Document> {
+        self.db.retryable_write_command(cmd, cmd_type, session).map_err(view_write_error)
     }
 
-    fn validate_replace(replacement: &bson::Document) -> Result<()> {
+    fn validate_replace(&self, replacement: &bson::Document) -> Result<()> {
         for key in replacement.keys() {
             if key.starts_with('$') {
                 return Err(ArgumentError(
@@ -949,6 +2754,12 @@ impl Collection {
                 ));
             }
         }
+
+        if self.checks_keys()? {
+            keys::check_keys(replacement).map_err(|reason| {
+                ArgumentError(format!("Replacement is invalid: {}.", reason))
+            })?;
+        }
         Ok(())
     }
 
@@ -979,26 +2790,107 @@ impl Collection {
         Ok(result.swap_remove(0))
     }
 
-    /// Create multiple indexes.
+    /// Create multiple indexes with a single `createIndexes` command.
+    ///
+    /// Every model is first checked against the features of the server; if any of them can't
+    /// be created, none are, and the `ArgumentError` names each such index and the reason.
+    /// Fails with a `PolicyViolationError` without sending anything if the client forbids
+    /// index creation.
     pub fn create_indexes(&self, models: Vec<IndexModel>) -> Result<Vec<String>> {
+        self.check_index_creation_allowed()?;
+        let (names, cmd) = self.create_indexes_command(models)?;
+        self.db.run_command_checked(cmd, CommandType::CreateIndexes, None)?;
+        Ok(names)
+    }
+
+    /// Returns the `createIndexes` command `create_indexes` would send for the models, after
+    /// the same checks, without sending it, e.g. to review or log an index build before
+    /// running it. This is allowed even if the client forbids index creation.
+    pub fn create_indexes_dry_run(&self, models: Vec<IndexModel>) -> Result<bson::Document> {
+        self.create_indexes_command(models).map(|(_, cmd)| cmd)
+    }
+
+    // Checks the models against the features of the server, and returns their names along
+    // with the command creating them.
+    fn create_indexes_command(
+        &self,
+        models: Vec<IndexModel>,
+    ) -> Result<(Vec<String>, bson::Document)> {
         let mut names = Vec::with_capacity(models.len());
         let mut indexes = Vec::with_capacity(models.len());
+        let mut invalid = Vec::new();
 
         for model in models {
-            names.push(model.name()?);
+            let name = model.name()?;
+            for (feature, version) in self.unsupported_index_features(&model)? {
+                invalid.push(format!(
+                    "Index {} is invalid: {} requires {}.",
+                    name,
+                    feature,
+                    version
+                ));
+            }
+
+            names.push(name);
             indexes.push(Bson::Document(model.to_bson()?));
         }
 
+        if !invalid.is_empty() {
+            return Err(ArgumentError(invalid.join(" ")));
+        }
+
         let cmd = doc! {
             "createIndexes": self.name(),
             "indexes": indexes,
         };
-        let mut result = self.db.command(cmd, CommandType::CreateIndexes, None)?;
+        Ok((names, cmd))
+    }
 
-        match result.remove("errmsg") {
-            Some(Bson::String(msg)) => Err(OperationError(msg)),
-            _ => Ok(names),
+    // Fails if the client forbids index creation, before anything is sent.
+    fn check_index_creation_allowed(&self) -> Result<()> {
+        if self.db.client.is_index_creation_forbidden() {
+            return Err(PolicyViolationError(format!(
+                "Creating indexes on {} is forbidden by the client.",
+                self.namespace
+            )));
         }
+        Ok(())
+    }
+
+    // Returns the features of the index model the server doesn't support, each with the first
+    // version that does.
+    fn unsupported_index_features(
+        &self,
+        model: &IndexModel,
+    ) -> Result<Vec<(&'static str, &'static str)>> {
+        let topology = &self.db.client.topology;
+        let mut unsupported = Vec::new();
+
+        if model.options.partial_filter_expression.is_some() &&
+            !topology.supports(ServerCapabilities::supports_partial_indexes)?
+        {
+            unsupported.push(("a partial filter expression", "MongoDB 3.2"));
+        }
+
+        if model.options.collation.is_some() &&
+            !topology.supports(ServerCapabilities::supports_collation)?
+        {
+            unsupported.push(("a collation", "MongoDB 3.4"));
+        }
+
+        if model.options.hidden == Some(true) &&
+            !topology.supports(ServerCapabilities::supports_hidden_indexes)?
+        {
+            unsupported.push(("hiding an index", "MongoDB 4.4"));
+        }
+
+        if model.is_compound_hashed() &&
+            !topology.supports(ServerCapabilities::supports_hidden_indexes)?
+        {
+            unsupported.push(("a compound hashed key", "MongoDB 4.4"));
+        }
+
+        Ok(unsupported)
     }
 
     /// Drop an index.
@@ -1022,30 +2914,711 @@ impl Collection {
             "dropIndexes": self.name(),
             "index": model.name()?,
         };
-        let mut result = self.db.command(cmd, CommandType::DropIndexes, None)?;
-        match result.remove("errmsg") {
-            Some(Bson::String(msg)) => Err(OperationError(msg)),
-            _ => Ok(()),
-        }
+        self.db.run_command_checked(cmd, CommandType::DropIndexes, None)?;
+        Ok(())
     }
 
-    /// Drop all indexes in the collection.
+    /// Drop all indexes in the collection except the `_id` index. Collections created without
+    /// an `_id` index, or with none at all, are not an error.
     pub fn drop_indexes(&self) -> Result<()> {
         let mut opts = IndexOptions::new();
         opts.name = Some(String::from("*"));
 
         let model = IndexModel::new(bson::Document::new(), Some(opts));
-        self.drop_index_model(model)
+        match self.drop_index_model(model) {
+            Err(CommandError(ref failure)) if failure.has_code(&[ErrorCode::IndexNotFound]) => {
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    /// Returns up to `num_cursors` independent cursors which together cover every document in
+    /// the collection exactly once.
+    ///
+    /// The server is free to return fewer cursors than requested; storage engines other than
+    /// mmapv1 typically return a single cursor. If the server does not support the
+    /// `parallelCollectionScan` command, a single cursor over the whole collection is returned.
+    pub fn parallel_scan(&self, num_cursors: u32) -> Result<Vec<Cursor>> {
+        if num_cursors == 0 || num_cursors > 10000 {
+            return Err(ArgumentError(format!(
+                "Number of cursors must be between 1 and 10000, got {}.",
+                num_cursors
+            )));
+        }
+
+        let cmd = doc! {
+            "parallelCollectionScan": self.name(),
+            "numCursors": num_cursors as i32,
+        };
+
+        let result = self.db.command(
+            cmd,
+            CommandType::ParallelCollectionScan,
+            Some(self.read_preference.to_owned()),
+        )?;
+
+        let cursors = match result.get("cursors") {
+            Some(&Bson::Array(ref cursors)) => cursors,
+            // Fall back to a regular scan on servers that don't support the command.
+            _ => return Ok(vec![self.find(None, None)?]),
+        };
+
+        let mut out = Vec::with_capacity(cursors.len());
+
+        for entry in cursors {
+            let cursor = match *entry {
+                Bson::Document(ref doc) => doc.get("cursor"),
+                _ => None,
+            };
+
+            let cursor = match cursor {
+                Some(&Bson::Document(ref cursor)) => cursor.clone(),
+                _ => {
+                    return Err(ResponseError(format!(
+                        "Invalid cursor entry returned by parallelCollectionScan: {}",
+                        entry
+                    )))
+                }
+            };
+
+            out.push(Cursor::from_cursor_document(
+                self.db.client.clone(),
+                cursor,
+                CommandType::ParallelCollectionScan,
+                self.read_preference.to_owned(),
+            )?);
+        }
+
+        Ok(out)
+    }
+
+    /// Scans the collection with up to `num_tasks` threads, calling `cb` on every document.
+    ///
+    /// Each thread drives one of the cursors returned by `parallel_scan` and stops at its first
+    /// error. The result of each thread is returned in the order the cursors were created.
+    pub fn scan_with<F>(&self, num_tasks: u32, cb: F) -> Result<Vec<Result<()>>>
+    where
+        F: Fn(bson::Document) -> Result<()> + Send + Sync + 'static,
+    {
+        let cb = Arc::new(cb);
+
+        let handles: Vec<_> = self.parallel_scan(num_tasks)?
+            .into_iter()
+            .map(|cursor| {
+                let cb = cb.clone();
+                thread::spawn(move || -> Result<()> {
+                    for doc in cursor {
+                        cb(doc?)?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+
+        Ok(handles
+            .into_iter()
+            .map(|handle| {
+                handle.join().unwrap_or_else(|_| {
+                    Err(OperationError(String::from("Scan task panicked.")))
+                })
+            })
+            .collect())
     }
 
     /// List all indexes in the collection.
     pub fn list_indexes(&self) -> Result<Cursor> {
-        let cmd = doc!{ "listIndexes": self.name() };
-        self.db.command_cursor(
+        self.list_indexes_with_batch_size(DEFAULT_BATCH_SIZE)
+    }
+
+    /// List all indexes in the collection, fetching them in batches of `batch_size`. A
+    /// collection that doesn't exist has no indexes, and neither may one created without an
+    /// `_id` index.
+    pub fn list_indexes_with_batch_size(&self, batch_size: i32) -> Result<Cursor> {
+        let cmd = doc!{
+            "listIndexes": self.name(),
+            "cursor": {
+                "batchSize": batch_size,
+            },
+        };
+
+        let result = self.db.command_cursor(
             cmd,
             CommandType::ListIndexes,
             self.read_preference.to_owned(),
-        )
+        );
+
+        // The server replies without a cursor if the collection doesn't exist.
+        match result {
+            Err(Error::CursorNotFoundError) => {
+                let cursor = doc! {
+                    "id": 0i64,
+                    "ns": &self.namespace,
+                    "firstBatch": [],
+                };
+
+                Cursor::from_cursor_document(
+                    self.db.client.clone(),
+                    cursor,
+                    CommandType::ListIndexes,
+                    self.read_preference.to_owned(),
+                )
+            }
+            result => result,
+        }
+    }
+
+    /// Copies the documents matching `filter` into the collection `target_coll` of `target_db`,
+    /// which may belong to a client connected to another server, keeping their `_id`s. The
+    /// documents are read in batches, each inserted as an unordered `insert_many`.
+    ///
+    /// If the copy fails, the error is a `CopyError` giving how many documents were written to
+    /// the target before the failure. Documents of the batch being inserted when a network
+    /// error occurred may also have been written.
+    pub fn copy_to(
+        &self,
+        target_db: &Database,
+        target_coll: &str,
+        filter: Option<bson::Document>,
+        options: Option<CopyOptions>,
+    ) -> Result<CopyResult> {
+        self.copy_to_with_progress(target_db, target_coll, filter, options, |_| ())
+    }
+
+    /// Copies documents like `copy_to`, calling `progress` with the number of documents copied
+    /// so far after each batch.
+    pub fn copy_to_with_progress<F>(
+        &self,
+        target_db: &Database,
+        target_coll: &str,
+        filter: Option<bson::Document>,
+        options: Option<CopyOptions>,
+        mut progress: F,
+    ) -> Result<CopyResult>
+    where
+        F: FnMut(i64),
+    {
+        let options = options.unwrap_or_default();
+        let batch_size = options.batch_size.unwrap_or(DEFAULT_COPY_BATCH_SIZE);
+        if batch_size <= 0 {
+            return Err(ArgumentError(
+                String::from("The batch size of a copy must be positive."),
+            ));
+        }
+
+        if options.dry_run {
+            return Ok(CopyResult {
+                copied_count: self.count(filter, None)?,
+                indexes_created: Vec::new(),
+            });
+        }
+
+        let target = target_db.get_collection(target_coll)?;
+        let failed = |copied: i64| move |err: Error| CopyError(copied, Box::new(err));
+
+        // Copying indexes is refused before any documents are copied.
+        if options.indexes != IndexCopy::None {
+            target.check_index_creation_allowed()?;
+        }
+
+        let mut indexes_created = Vec::new();
+        if options.indexes == IndexCopy::Before {
+            indexes_created = self.copy_indexes_to(&target).map_err(failed(0))?;
+        }
+
+        let find_options = FindOptions {
+            batch_size: Some(batch_size),
+            ..FindOptions::new()
+        };
+        let insert_options = InsertManyOptions {
+            ordered: Some(false),
+            write_concern: options.write_concern.clone(),
+            ..InsertManyOptions::new()
+        };
+
+        let mut cursor = self.find(filter, Some(find_options)).map_err(failed(0))?;
+        let mut copied = 0;
+
+        loop {
+            let batch = cursor.next_n(batch_size as usize).map_err(failed(copied))?;
+            if batch.is_empty() {
+                break;
+            }
+
+            let len = batch.len() as i64;
+            let result = target
+                .insert_many(batch, Some(insert_options.clone()))
+                .map_err(failed(copied))?;
+
+            // Unordered inserts go on past a failed document, so only those failed.
+            if let Some(exception) = result.bulk_write_exception {
+                let written = len - exception.write_errors.len() as i64;
+                return Err(failed(copied + written)(BulkWriteError(exception)));
+            }
+
+            copied += len;
+            progress(copied);
+        }
+
+        if options.indexes == IndexCopy::After {
+            indexes_created = self.copy_indexes_to(&target).map_err(failed(copied))?;
+        }
+
+        Ok(CopyResult {
+            copied_count: copied,
+            indexes_created: indexes_created,
+        })
+    }
+
+    // Creates the indexes of this collection, other than the `_id` index, on `target`, and
+    // returns their names.
+    fn copy_indexes_to(&self, target: &Collection) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        let mut indexes = Vec::new();
+
+        for index in self.list_indexes()? {
+            let mut index = index?;
+            let name = match index.get("name") {
+                Some(&Bson::String(ref name)) if name != "_id_" => name.to_owned(),
+                Some(&Bson::String(_)) => continue,
+                _ => return Err(ResponseError(String::from("Index is missing 'name'."))),
+            };
+
+            // The namespace of the source collection doesn't apply to the target.
+            index.remove("ns");
+            names.push(name);
+            indexes.push(Bson::Document(index));
+        }
+
+        if indexes.is_empty() {
+            return Ok(names);
+        }
+
+        let cmd = doc! {
+            "createIndexes": target.name(),
+            "indexes": indexes,
+        };
+        target.db.run_command_checked(cmd, CommandType::CreateIndexes, None)?;
+        Ok(names)
+    }
+
+    /// Imports documents from JSON Lines, one Extended JSON document per line, inserting them
+    /// in batches of `batch_size`. Blank lines are skipped.
+    ///
+    /// Lines that can't be parsed or inserted are reported in `ImportResult::errors` with their
+    /// line numbers. An ordered import stops at the first of them, after inserting the lines
+    /// that precede it; an unordered one carries on with the rest of the input.
+    pub fn import_jsonl<R: BufRead>(
+        &self,
+        reader: R,
+        batch_size: usize,
+        ordered: bool,
+    ) -> Result<ImportResult> {
+        if batch_size == 0 {
+            return Err(ArgumentError(
+                String::from("The batch size of an import must be positive."),
+            ));
+        }
+
+        let options = InsertManyOptions {
+            ordered: Some(ordered),
+            ..InsertManyOptions::new()
+        };
+        let mut result = ImportResult {
+            inserted_count: 0,
+            errors: Vec::new(),
+        };
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut batch_lines = Vec::with_capacity(batch_size);
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match extjson::document_from_extjson(&line) {
+                Ok(doc) => {
+                    batch.push(doc);
+                    batch_lines.push(index + 1);
+                }
+                Err(err) => {
+                    result.errors.push(ImportLineError {
+                        line: index + 1,
+                        message: err.to_string(),
+                    });
+
+                    if ordered {
+                        self.import_batch(&mut batch, &mut batch_lines, &options, &mut result)?;
+                        return Ok(result);
+                    }
+                }
+            }
+
+            if batch.len() == batch_size &&
+                !self.import_batch(&mut batch, &mut batch_lines, &options, &mut result)? &&
+                ordered
+            {
+                return Ok(result);
+            }
+        }
+
+        self.import_batch(&mut batch, &mut batch_lines, &options, &mut result)?;
+        Ok(result)
+    }
+
+    // Inserts the documents of an import batch, emptying it, and records those that failed
+    // against their lines. Returns whether every document was inserted.
+    fn import_batch(
+        &self,
+        batch: &mut Vec<bson::Document>,
+        batch_lines: &mut Vec<usize>,
+        options: &InsertManyOptions,
+        result: &mut ImportResult,
+    ) -> Result<bool> {
+        if batch.is_empty() {
+            return Ok(true);
+        }
+
+        let docs = mem::replace(batch, Vec::new());
+        let lines = mem::replace(batch_lines, Vec::new());
+        let len = docs.len() as i64;
+
+        let inserted = self.insert_many(docs, Some(options.clone()))?;
+        let exception = match inserted.bulk_write_exception {
+            Some(exception) => exception,
+            None => {
+                result.inserted_count += len;
+                return Ok(true);
+            }
+        };
+
+        // A write concern error leaves no document to blame.
+        if exception.write_errors.is_empty() {
+            return Err(BulkWriteError(exception));
+        }
+
+        // An ordered insert stops at its first failed document.
+        result.inserted_count += if options.ordered == Some(false) {
+            len - exception.write_errors.len() as i64
+        } else {
+            i64::from(exception.write_errors[0].index)
+        };
+
+        for error in &exception.write_errors {
+            result.errors.push(ImportLineError {
+                line: lines[error.index as usize],
+                message: error.message.to_owned(),
+            });
+        }
+
+        Ok(false)
+    }
+
+    /// Writes the documents matching `filter` to `writer` as JSON Lines, one Extended JSON
+    /// document per line, in canonical form if `canonical` is set and in relaxed form
+    /// otherwise. Returns the number of documents written.
+    ///
+    /// Canonical output keeps the type of every value, so that importing it with
+    /// `import_jsonl` gives back the same documents.
+    pub fn export_jsonl<W: Write>(
+        &self,
+        mut writer: W,
+        filter: Option<bson::Document>,
+        canonical: bool,
+    ) -> Result<i64> {
+        let mut count = 0;
+
+        for doc in self.find(filter, None)? {
+            writeln!(writer, "{}", extjson::document_to_extjson(&doc?, canonical))?;
+            count += 1;
+        }
+
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Returns how often each index of the collection was used, as reported by the `$indexStats`
+    /// aggregation stage. Statistics are kept in memory by each server and reset when it
+    /// restarts; on a sharded collection, those of every shard are merged.
+    pub fn index_stats(&self) -> Result<Vec<IndexStats>> {
+        if !self.db.client.topology.supports(ServerCapabilities::supports_index_stats)? {
+            return Err(ArgumentError(
+                String::from("Index statistics require MongoDB 3.2 or later."),
+            ));
+        }
+
+        let docs = self.aggregate(vec![doc! { "$indexStats": {} }], None)?
+            .collect::<Result<Vec<_>>>()?;
+
+        IndexStats::from_documents(&docs)
+    }
+
+    /// Reads the whole collection, or the documents matching the `filter` option, into a
+    /// read-only in-memory snapshot indexed by `key_fields`, for small collections of
+    /// configuration data that are looked up far more often than they change. Fails if the
+    /// collection holds more documents than the `max_documents` option allows, 10000 by
+    /// default.
+    pub fn load_snapshot(
+        &self,
+        key_fields: &[&str],
+        options: Option<SnapshotOptions>,
+    ) -> Result<SnapshotCache> {
+        SnapshotCache::load(self.clone(), key_fields, options)
+    }
+
+    /// Analyzes a proposed shard key before the collection is sharded on it: looks for an
+    /// index supporting the key, creating one if the options ask for it, estimates how many
+    /// distinct values the key has and how often the most frequent one occurs, and checks
+    /// whether its first field increases with every insert. Collections larger than the sample
+    /// size are analyzed through a random `$sample` of their documents on MongoDB 3.2 and later.
+    pub fn analyze_shard_key(
+        &self,
+        key: bson::Document,
+        options: Option<ShardKeyOptions>,
+    ) -> Result<ShardKeyAnalysis> {
+        shard_key::check_shard_key(&key)?;
+
+        let options = options.unwrap_or_default();
+        let sample_size = options.sample_size.unwrap_or(DEFAULT_SHARD_KEY_SAMPLE_SIZE);
+        if sample_size <= 0 {
+            return Err(ArgumentError(format!(
+                "The sample size must be positive, got {}.",
+                sample_size
+            )));
+        }
+
+        let mut index = None;
+        for doc in self.list_indexes()? {
+            let doc = doc?;
+            if shard_key::supports_shard_key(&doc, &key) {
+                index = doc.get_str("name").ok().map(String::from);
+                break;
+            }
+        }
+
+        let index_created = index.is_none() && options.create_index;
+        if index_created {
+            index = Some(self.create_index(key.clone(), None)?);
+        }
+
+        // Group the documents by the value of the key, then count the groups and their sizes.
+        let document_count = self.count(None, None)?;
+        let mut pipeline = Vec::new();
+        let topology = &self.db.client.topology;
+        if document_count > sample_size && topology.supports(ServerCapabilities::supports_sample)? {
+            pipeline.push(doc! { "$sample": { "size": sample_size } });
+        }
+
+        let fields: Vec<_> = key.keys().map(|field| Bson::String(format!("${}", field))).collect();
+        pipeline.push(doc! { "$group": { "_id": fields, "count": { "$sum": 1 } } });
+        pipeline.push(doc! {
+            "$group": {
+                "_id": Bson::Null,
+                "distinct": { "$sum": 1 },
+                "total": { "$sum": "$count" },
+                "max": { "$max": "$count" },
+            }
+        });
+
+        let mut aggregate_options = AggregateOptions::new();
+        aggregate_options.allow_disk_use = Some(true);
+        let counts = match self.aggregate(pipeline, Some(aggregate_options))?.next() {
+            Some(counts) => counts?,
+            None => bson::Document::new(),
+        };
+        let count = |field| match counts.get(field) {
+            Some(&Bson::I32(n)) => i64::from(n),
+            Some(&Bson::I64(n)) => n,
+            _ => 0,
+        };
+
+        // Hashing spreads monotonic values evenly.
+        let (first_field, first_kind) = key.iter().next().unwrap();
+        let monotonic = if *first_kind == Bson::String(String::from("hashed")) {
+            false
+        } else {
+            let mut filter = bson::Document::new();
+            filter.insert(first_field.to_owned(), doc! { "$exists": true });
+            self.find_one(Some(filter), None)?
+                .as_ref()
+                .and_then(|doc| shard_key::value_at(doc, first_field))
+                .map_or(false, shard_key::is_monotonic)
+        };
+
+        let mut analysis = ShardKeyAnalysis {
+            key: key,
+            index: index,
+            index_created: index_created,
+            document_count: document_count,
+            sampled_count: count("total"),
+            distinct_values: count("distinct"),
+            max_frequency: count("max"),
+            monotonic: monotonic,
+            warnings: Vec::new(),
+        };
+        analysis.add_warnings(options.min_distinct_values.unwrap_or(DEFAULT_MIN_SHARD_KEY_VALUES));
+        Ok(analysis)
+    }
+
+    /// Shards the collection on the key through a mongos, after analyzing the key with
+    /// `analyze_shard_key`, and returns the analysis. Unless `force` is set, a key with fewer
+    /// distinct values than the minimum is refused with an `ArgumentError` describing the
+    /// analysis. Sharding is enabled on the database first if it isn't already.
+    pub fn shard_collection(
+        &self,
+        key: bson::Document,
+        options: Option<ShardKeyOptions>,
+    ) -> Result<ShardKeyAnalysis> {
+        let options = options.unwrap_or_default();
+        let min_distinct_values =
+            options.min_distinct_values.unwrap_or(DEFAULT_MIN_SHARD_KEY_VALUES);
+
+        let analysis = self.analyze_shard_key(key.clone(), Some(options.clone()))?;
+        if !options.force && !analysis.has_enough_cardinality(min_distinct_values) {
+            return Err(ArgumentError(format!(
+                "Refusing to shard {}: {}.",
+                self.namespace,
+                analysis
+            )));
+        }
+
+        let admin = self.db.client.db("admin");
+        let enable = doc! { "enableSharding": self.db.name.to_owned() };
+        if let Err(err) = admin.run_command_checked(enable, CommandType::EnableSharding, None) {
+            // Older servers fail if sharding is already enabled on the database.
+            let already_enabled = match err {
+                CommandError(ref failure) => failure.has_code(&[ErrorCode::AlreadyInitialized]),
+                _ => false,
+            };
+            if !already_enabled {
+                return Err(err);
+            }
+        }
+
+        let spec = doc! {
+            "shardCollection": self.namespace.to_owned(),
+            "key": analysis.key.clone(),
+            "unique": options.unique,
+        };
+        admin.run_command_checked(spec, CommandType::ShardCollection, None)?;
+        Ok(analysis)
+    }
+
+    /// Reports how the collection's chunks are spread across shards, read from the config
+    /// database through a mongos. If `max_sampled_chunks` is set, the sizes of up to that many
+    /// chunks, spread evenly across shards, are measured with `dataSize` to estimate how much
+    /// data each shard holds. Fails with an `ArgumentError` if the collection isn't sharded.
+    pub fn chunk_distribution(
+        &self,
+        max_sampled_chunks: Option<usize>,
+    ) -> Result<ChunkDistribution> {
+        let config = self.db.client.db("config");
+        let entry = config.collection("collections").find_one(
+            Some(doc! { "_id": self.namespace.to_owned() }),
+            None,
+        )?;
+
+        let entry = match entry {
+            Some(ref entry) if entry.get("dropped") != Some(&Bson::Boolean(true)) => entry.clone(),
+            _ => {
+                return Err(ArgumentError(
+                    format!("Collection {} is not sharded.", self.namespace),
+                ))
+            }
+        };
+
+        let key = match entry.get("key") {
+            Some(&Bson::Document(ref key)) => key.clone(),
+            _ => {
+                return Err(ResponseError(
+                    format!("The config entry of {} has no shard key.", self.namespace),
+                ))
+            }
+        };
+
+        // Chunks name their collection by UUID as of MongoDB 5.0, and by namespace before.
+        // Servers record the UUID in config.collections from 3.6 on, while still keying chunks
+        // by namespace, so either may match.
+        let filter = match entry.get("uuid") {
+            Some(uuid) => {
+                doc! {
+                    "$or": [
+                        { "ns": self.namespace.to_owned() },
+                        { "uuid": uuid.clone() },
+                    ]
+                }
+            }
+            None => doc! { "ns": self.namespace.to_owned() },
+        };
+
+        let chunks = config.collection("chunks");
+        let pipeline = vec![
+            doc! { "$match": filter.clone() },
+            doc! { "$group": { "_id": "$shard", "chunks": { "$sum": 1 } } },
+        ];
+
+        let mut counts = Vec::new();
+        for result in chunks.aggregate(pipeline, None)? {
+            let result = result?;
+            let count = match result.get("chunks") {
+                Some(&Bson::I32(n)) => n as i64,
+                Some(&Bson::I64(n)) => n,
+                _ => 0,
+            };
+            if let Some(&Bson::String(ref shard)) = result.get("_id") {
+                counts.push((shard.to_owned(), count));
+            }
+        }
+
+        let mut samples = HashMap::new();
+        if let Some(max_sampled_chunks) = max_sampled_chunks {
+            let quotas = sample_quotas(max_sampled_chunks, counts.len());
+
+            for (&(ref shard, _), &quota) in counts.iter().zip(quotas.iter()) {
+                if quota == 0 {
+                    continue;
+                }
+
+                let mut shard_filter = filter.clone();
+                shard_filter.insert("shard", shard.to_owned());
+                let mut options = FindOptions::new();
+                options.sort = Some(doc! { "min": 1 });
+                options.limit = Some(quota as i64);
+
+                let mut sample = ChunkSample::default();
+                for chunk in chunks.find(Some(shard_filter), Some(options))? {
+                    let chunk = chunk?;
+                    let (min, max) = match (chunk.get("min"), chunk.get("max")) {
+                        (Some(min), Some(max)) => (min.clone(), max.clone()),
+                        _ => continue,
+                    };
+
+                    let spec = doc! {
+                        "dataSize": self.namespace.to_owned(),
+                        "keyPattern": key.clone(),
+                        "min": min,
+                        "max": max,
+                        "estimate": true,
+                    };
+                    let reply = self.db.run_command_checked(spec, CommandType::DataSize, None)?;
+                    sample.add(&reply);
+                }
+                samples.insert(shard.to_owned(), sample);
+            }
+        }
+
+        Ok(ChunkDistribution::new(self.namespace.to_owned(), key, counts, &samples))
+    }
+
+    /// Checks the structures of the collection and its indexes for corruption, scanning every
+    /// document if `full` is set. A collection that fails validation is reported through
+    /// `ValidateResult::valid` rather than as an error, which is reserved for the command
+    /// itself failing, e.g. because the collection doesn't exist.
+    pub fn validate(&self, full: bool) -> Result<ValidateResult> {
+        let spec = doc! { "validate": self.name(), "full": full };
+        let reply = self.db.run_command_checked(spec, CommandType::Validate, None)?;
+        ValidateResult::new(reply)
     }
 
     /// List all indexes in the collection as serialized `IndexModel`s.
@@ -1062,3 +3635,33 @@ impl Collection {
         })
     }
 }
+
+// Encodes an `_id` value so that returned documents can be matched to the requested ids, since
+// BSON values can't be hashed directly.
+fn id_key(id: &Bson) -> Result<Vec<u8>> {
+    let mut key = Vec::new();
+    bson::encode_document(&mut key, &doc! { "_id": id.clone() })?;
+    Ok(key)
+}
+
+// Echoes the hint in the BadValue errors the server returns for a hint that names no index,
+// since it doesn't always say which index it was asked to use.
+fn with_hint_context(err: Error, hint: Option<&Hint>) -> Error {
+    match (err, hint) {
+        (CommandError(mut failure), Some(hint)) if failure.has_code(&[ErrorCode::BadValue]) => {
+            failure.message = format!("{} (hint: {})", failure.message, hint);
+            CommandError(failure)
+        }
+        (err, _) => err,
+    }
+}
+
+// Reports writes the server rejected because the namespace is a view as `ViewWriteError`s.
+fn view_write_error(err: Error) -> Error {
+    match err {
+        CommandError(ref failure) if failure.code == Some(COMMAND_NOT_SUPPORTED_ON_VIEW_CODE) => {
+            ViewWriteError(failure.message.clone())
+        }
+        err => err,
+    }
+}
@@ -1,9 +1,110 @@
 //! Options for collection-level operations.
 use bson::{self, bson, Bson, doc};
-use common::{ReadPreference, WriteConcern};
+use common::{ReadConcern, ReadPreference, WriteConcern};
+use topology::capabilities::ServerCapabilities;
 use Error::ArgumentError;
 use Result;
 
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+// Declares an options struct along with its builder. Each field is private to the crate, and is
+// read through a getter and set through a builder method of the same name, which share the doc
+// comment of the field. The fields listed under `values` are stored as they are and must be
+// `Copy`; those listed under `options` are stored as an `Option` that is unset by default, whose
+// getter returns a reference and whose builder method takes the value itself.
+macro_rules! options_struct {
+    (
+        $(#[$attr:meta])*
+        pub struct $options:ident, $builder:ident {
+            values {
+                $(
+                    $(#[doc = $doc:literal])*
+                    $field:ident: $ty:ty,
+                )*
+            }
+            options {
+                $(
+                    $(#[doc = $optional_doc:literal])*
+                    $(#[serde($($serde:tt)*)])*
+                    $optional:ident: $optional_ty:ty,
+                )*
+            }
+        }
+    ) => {
+        $(#[$attr])*
+        pub struct $options {
+            $(
+                $(#[doc = $doc])*
+                pub(crate) $field: $ty,
+            )*
+            $(
+                $(#[doc = $optional_doc])*
+                $(#[serde($($serde)*)])*
+                pub(crate) $optional: Option<$optional_ty>,
+            )*
+        }
+
+        impl $options {
+            /// Starts building options from the defaults.
+            pub fn builder() -> $builder {
+                $builder::default()
+            }
+
+            $(
+                $(#[doc = $doc])*
+                pub fn $field(&self) -> $ty {
+                    self.$field
+                }
+            )*
+
+            $(
+                $(#[doc = $optional_doc])*
+                pub fn $optional(&self) -> Option<&$optional_ty> {
+                    self.$optional.as_ref()
+                }
+            )*
+
+            /// Returns a builder starting from these options, to change some of them.
+            pub fn to_builder(&self) -> $builder {
+                $builder {
+                    options: self.clone(),
+                }
+            }
+        }
+
+        /// Builds options one setting at a time, starting from the defaults.
+        #[derive(Clone, Debug, Default)]
+        pub struct $builder {
+            options: $options,
+        }
+
+        impl $builder {
+            $(
+                $(#[doc = $doc])*
+                pub fn $field(mut self, $field: $ty) -> $builder {
+                    self.options.$field = $field;
+                    self
+                }
+            )*
+
+            $(
+                $(#[doc = $optional_doc])*
+                pub fn $optional(mut self, $optional: $optional_ty) -> $builder {
+                    self.options.$optional = Some($optional);
+                    self
+                }
+            )*
+
+            /// Returns the options that were built.
+            pub fn build(self) -> $options {
+                self.options
+            }
+        }
+    };
+}
+
 /// Describes the type of cursor to return on collection queries.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum CursorType {
@@ -18,6 +119,25 @@ impl Default for CursorType {
     }
 }
 
+/// The order in which a collection scan visits documents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// The order the documents are stored in, which is usually insertion order.
+    Forward,
+    /// The reverse of the order the documents are stored in.
+    Reverse,
+}
+
+impl Direction {
+    /// Returns the direction as the value of a `$natural` sort or hint.
+    pub fn to_i32(&self) -> i32 {
+        match *self {
+            Direction::Forward => 1,
+            Direction::Reverse => -1,
+        }
+    }
+}
+
 /// Describes the type of document to return on write operations.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum ReturnDocument {
@@ -34,143 +154,713 @@ impl ReturnDocument {
     }
 }
 
+/// Forces the server to use a specific index for an operation.
+#[derive(Clone, Debug)]
+pub enum Hint {
+    /// The name of the index, e.g. `"a_1"`.
+    Name(String),
+    /// The key pattern of the index, e.g. `{ "a": 1 }`.
+    Keys(bson::Document),
+}
+
+impl Hint {
+    /// Returns the hint in the form expected by the server.
+    pub fn to_bson(&self) -> Bson {
+        match *self {
+            Hint::Name(ref name) => Bson::String(name.to_owned()),
+            Hint::Keys(ref keys) => Bson::Document(keys.clone()),
+        }
+    }
+
+    // Hints are compared and hashed by their BSON encoding, since documents can't be.
+    fn encoded(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let _ = bson::encode_document(&mut bytes, &doc! { "hint": self.to_bson() });
+        bytes
+    }
+}
+
+impl PartialEq for Hint {
+    fn eq(&self, other: &Hint) -> bool {
+        self.encoded() == other.encoded()
+    }
+}
+
+impl Eq for Hint {}
+
+impl PartialOrd for Hint {
+    fn partial_cmp(&self, other: &Hint) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Hint {
+    fn cmp(&self, other: &Hint) -> Ordering {
+        self.encoded().cmp(&other.encoded())
+    }
+}
+
+impl Hash for Hint {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.encoded().hash(state);
+    }
+}
+
+impl fmt::Display for Hint {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Hint::Name(ref name) => fmt.write_str(name),
+            Hint::Keys(ref keys) => write!(fmt, "{}", keys),
+        }
+    }
+}
+
+/// Language-specific rules for comparing strings, e.g. to match case-insensitively. Requires
+/// MongoDB 3.4 or later.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Collation {
+    /// The ICU locale, e.g. `"en_US"`, or `"simple"` for binary comparison.
+    pub locale: String,
+
+    #[serde(rename="caseLevel", skip_serializing_if="Option::is_none")]
+    pub case_level: Option<bool>,
+
+    /// One of `"upper"`, `"lower"` or `"off"`.
+    #[serde(rename="caseFirst", skip_serializing_if="Option::is_none")]
+    pub case_first: Option<String>,
+
+    /// The comparison level from 1 to 5; 1 and 2 ignore case.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub strength: Option<i32>,
+
+    #[serde(rename="numericOrdering", skip_serializing_if="Option::is_none")]
+    pub numeric_ordering: Option<bool>,
+
+    /// Either `"non-ignorable"` or `"shifted"`.
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub alternate: Option<String>,
+
+    /// Either `"punct"` or `"space"`; only used when `alternate` is `"shifted"`.
+    #[serde(rename="maxVariable", skip_serializing_if="Option::is_none")]
+    pub max_variable: Option<String>,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub normalization: Option<bool>,
+
+    #[serde(skip_serializing_if="Option::is_none")]
+    pub backwards: Option<bool>,
+}
+
+impl Collation {
+    /// Creates a collation for the given locale with the server's defaults for that locale.
+    pub fn new<S: Into<String>>(locale: S) -> Collation {
+        Collation {
+            locale: locale.into(),
+            case_level: None,
+            case_first: None,
+            strength: None,
+            numeric_ordering: None,
+            alternate: None,
+            max_variable: None,
+            normalization: None,
+            backwards: None,
+        }
+    }
+
+    /// Converts the collation to the sub-document sent with commands.
+    pub fn to_document(&self) -> bson::Document {
+        let mut doc = doc! { "locale": self.locale.to_owned() };
+
+        if let Some(val) = self.case_level {
+            doc.insert("caseLevel", val);
+        }
+        if let Some(ref val) = self.case_first {
+            doc.insert("caseFirst", val.to_owned());
+        }
+        if let Some(val) = self.strength {
+            doc.insert("strength", val);
+        }
+        if let Some(val) = self.numeric_ordering {
+            doc.insert("numericOrdering", val);
+        }
+        if let Some(ref val) = self.alternate {
+            doc.insert("alternate", val.to_owned());
+        }
+        if let Some(ref val) = self.max_variable {
+            doc.insert("maxVariable", val.to_owned());
+        }
+        if let Some(val) = self.normalization {
+            doc.insert("normalization", val);
+        }
+        if let Some(val) = self.backwards {
+            doc.insert("backwards", val);
+        }
+
+        doc
+    }
+}
+
+/// A dotted path to a field, which can address array elements through the positional operators
+/// used by updates.
+///
+/// ```
+/// use mongodb::coll::options::FieldPath;
+///
+/// let path = FieldPath::new("grades").filtered("g").field("score");
+/// assert_eq!(path.as_str(), "grades.$[g].score");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FieldPath {
+    path: String,
+}
+
+impl FieldPath {
+    /// Starts a path at a top-level field, or at the identifier of an array filter.
+    pub fn new<S: Into<String>>(field: S) -> FieldPath {
+        FieldPath { path: field.into() }
+    }
+
+    /// Appends a field of an embedded document.
+    pub fn field(self, name: &str) -> FieldPath {
+        self.push(name)
+    }
+
+    /// Appends an array index.
+    pub fn index(self, index: usize) -> FieldPath {
+        self.push(&index.to_string())
+    }
+
+    /// Appends `$`, the first array element matched by the query.
+    pub fn first_match(self) -> FieldPath {
+        self.push("$")
+    }
+
+    /// Appends `$[]`, every element of the array.
+    pub fn all_elements(self) -> FieldPath {
+        self.push("$[]")
+    }
+
+    /// Appends `$[identifier]`, the array elements matching the array filter for `identifier`.
+    pub fn filtered(self, identifier: &str) -> FieldPath {
+        self.push(&format!("$[{}]", identifier))
+    }
+
+    /// Returns a document setting the path to `value`, e.g. to build an array filter, or the
+    /// operand of an update operator.
+    pub fn to<T: Into<Bson>>(&self, value: T) -> bson::Document {
+        let mut doc = bson::Document::new();
+        doc.insert(self.path.to_owned(), value.into());
+        doc
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+
+    fn push(mut self, component: &str) -> FieldPath {
+        self.path.push('.');
+        self.path.push_str(component);
+        self
+    }
+}
+
+impl fmt::Display for FieldPath {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(&self.path)
+    }
+}
+
+impl From<FieldPath> for String {
+    fn from(path: FieldPath) -> String {
+        path.path
+    }
+}
+
+/// Builds the projection document of a query, checking the rules the server would otherwise
+/// only enforce when the query runs.
+///
+/// Apart from `_id`, a projection either includes or excludes fields. `$elemMatch` counts as an
+/// inclusion, while `$slice` and `$meta` can be used with either.
+///
+/// ```
+/// use mongodb::coll::options::Projection;
+///
+/// let projection = Projection::include(vec!["a", "b.c"]).exclude_id().slice("arr", 5);
+/// assert!(projection.build().is_ok());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Projection {
+    fields: Vec<(String, Bson)>,
+}
+
+impl Projection {
+    pub fn new() -> Projection {
+        Default::default()
+    }
+
+    /// Starts a projection returning only the given fields (and `_id`).
+    pub fn include<I, S>(fields: I) -> Projection
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        fields.into_iter().fold(Projection::new(), |projection, field| {
+            projection.include_field(field)
+        })
+    }
+
+    /// Starts a projection returning every field but the given ones.
+    pub fn exclude<I, S>(fields: I) -> Projection
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        fields.into_iter().fold(Projection::new(), |projection, field| {
+            projection.exclude_field(field)
+        })
+    }
+
+    pub fn include_field<S: Into<String>>(self, field: S) -> Projection {
+        self.set(field.into(), Bson::I32(1))
+    }
+
+    pub fn exclude_field<S: Into<String>>(self, field: S) -> Projection {
+        self.set(field.into(), Bson::I32(0))
+    }
+
+    /// Leaves `_id` out of the results, which is allowed in both kinds of projection.
+    pub fn exclude_id(self) -> Projection {
+        self.exclude_field("_id")
+    }
+
+    /// Returns the first `count` elements of an array, or the last ones if `count` is negative.
+    pub fn slice<S: Into<String>>(self, field: S, count: i32) -> Projection {
+        self.set(field.into(), Bson::Document(doc! { "$slice": count }))
+    }
+
+    /// Returns `limit` elements of an array after skipping `skip` of them.
+    pub fn slice_range<S: Into<String>>(self, field: S, skip: i32, limit: i32) -> Projection {
+        self.set(field.into(), Bson::Document(doc! { "$slice": [skip, limit] }))
+    }
+
+    /// Returns only the first array element matching `filter`.
+    pub fn elem_match<S: Into<String>>(self, field: S, filter: bson::Document) -> Projection {
+        self.set(field.into(), Bson::Document(doc! { "$elemMatch": filter }))
+    }
+
+    /// Adds the text search score of each document as `field`.
+    pub fn meta_text_score<S: Into<String>>(self, field: S) -> Projection {
+        self.set(field.into(), Bson::Document(doc! { "$meta": "textScore" }))
+    }
+
+    /// Checks the projection and returns it as a document for `FindOptions` or the `findOneAndX`
+    /// options.
+    pub fn build(self) -> Result<bson::Document> {
+        let mut inclusion = None;
+        let mut exclusion = None;
+
+        for &(ref field, ref value) in &self.fields {
+            if field == "_id" {
+                continue;
+            }
+
+            match *value {
+                Bson::I32(0) => exclusion = exclusion.or_else(|| Some(field)),
+                Bson::I32(_) => inclusion = inclusion.or_else(|| Some(field)),
+                Bson::Document(ref operator) if operator.contains_key("$elemMatch") => {
+                    inclusion = inclusion.or_else(|| Some(field))
+                }
+                _ => (),
+            }
+        }
+
+        if let (Some(included), Some(excluded)) = (inclusion, exclusion) {
+            return Err(ArgumentError(format!(
+                "Projection cannot both include '{}' and exclude '{}'.",
+                included,
+                excluded
+            )));
+        }
+
+        for &(ref field, _) in &self.fields {
+            for &(ref other, _) in &self.fields {
+                if other.len() > field.len() && other.starts_with(field.as_str()) &&
+                    other[field.len()..].starts_with('.')
+                {
+                    return Err(ArgumentError(format!(
+                        "Projection of '{}' collides with projection of '{}'.",
+                        other,
+                        field
+                    )));
+                }
+            }
+        }
+
+        let mut document = bson::Document::new();
+        for (field, value) in self.fields {
+            document.insert(field, value);
+        }
+        Ok(document)
+    }
+
+    // Later projections of a field replace earlier ones.
+    fn set(mut self, field: String, value: Bson) -> Projection {
+        self.fields.retain(|&(ref existing, _)| *existing != field);
+        self.fields.push((field, value));
+        self
+    }
+}
+
 /// Marker interface for writes that can be batched together.
 #[derive(Debug, Clone, PartialEq)]
 pub enum WriteModel {
     InsertOne { document: bson::Document },
-    DeleteOne { filter: bson::Document },
-    DeleteMany { filter: bson::Document },
+    DeleteOne {
+        filter: bson::Document,
+        collation: Option<Collation>,
+        hint: Option<Hint>,
+    },
+    DeleteMany {
+        filter: bson::Document,
+        collation: Option<Collation>,
+        hint: Option<Hint>,
+    },
     ReplaceOne {
         filter: bson::Document,
         replacement: bson::Document,
         upsert: Option<bool>,
+        collation: Option<Collation>,
+        hint: Option<Hint>,
     },
     UpdateOne {
         filter: bson::Document,
         update: bson::Document,
         upsert: Option<bool>,
+        collation: Option<Collation>,
+        hint: Option<Hint>,
+        array_filters: Option<Vec<bson::Document>>,
     },
     UpdateMany {
         filter: bson::Document,
         update: bson::Document,
         upsert: Option<bool>,
+        collation: Option<Collation>,
+        hint: Option<Hint>,
+        array_filters: Option<Vec<bson::Document>>,
     },
 }
 
-/// Options for aggregation queries.
-#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct AggregateOptions {
-    pub allow_disk_use: Option<bool>,
-    pub use_cursor: Option<bool>,
-    pub batch_size: i32,
-    pub max_time_ms: Option<i64>,
-    pub read_preference: Option<ReadPreference>,
+options_struct! {
+    /// Options for aggregation queries.
+    #[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct AggregateOptions, AggregateOptionsBuilder {
+        values {
+            /// The number of documents in each batch of the cursor, or 0 for the server's default.
+            batch_size: i32,
+        }
+        options {
+            /// Lets the stages of the pipeline write temporary files when they run out of memory.
+            allow_disk_use: bool,
+            /// Set to `false` to have servers before MongoDB 3.6 return the results in a single
+            /// document rather than through a cursor.
+            use_cursor: bool,
+            /// The time limit of the operation in milliseconds.
+            max_time_ms: i64,
+            /// The read preference of the operation; defaults to the collection's.
+            read_preference: ReadPreference,
+            /// The read concern of the operation; defaults to the collection's.
+            read_concern: ReadConcern,
+            /// A comment to attach to the operation, which shows up in the profiler and server
+            /// logs.
+            comment: String,
+            /// The index to use for the initial stages of the pipeline. Requires MongoDB 3.6 or
+            /// later.
+            hint: Hint,
+            /// The collation used to compare strings.
+            collation: Collation,
+            /// The write concern of pipelines ending in an `$out` or `$merge` stage; defaults to
+            /// the collection's.
+            write_concern: WriteConcern,
+        }
+    }
 }
 
 impl AggregateOptions {
     pub fn new() -> Self {
         Default::default()
     }
-}
 
-impl From<AggregateOptions> for bson::Document {
-    fn from(options: AggregateOptions) -> Self {
+    /// Returns the fields the options add to an `aggregate` command.
+    pub fn to_document(&self) -> bson::Document {
         let mut document = bson::Document::new();
 
-        if let Some(allow_disk_use) = options.allow_disk_use {
+        if let Some(allow_disk_use) = self.allow_disk_use {
             document.insert("allowDiskUse", allow_disk_use);
         }
 
-        let cursor = if let Some(false) = options.use_cursor {
-           doc! {}
+        let cursor = if let Some(false) = self.use_cursor {
+            doc! {}
         } else {
-            doc! { "batchSize": options.batch_size }
+            doc! { "batchSize": self.batch_size }
         };
 
         document.insert("cursor", cursor);
 
-        // maxTimeMS is not currently used by the driver.
+        if let Some(ref comment) = self.comment {
+            document.insert("comment", comment);
+        }
+
+        if let Some(ref hint) = self.hint {
+            document.insert("hint", hint.to_bson());
+        }
+
+        if let Some(ref collation) = self.collation {
+            document.insert("collation", collation.to_document());
+        }
+
+        if let Some(max_time_ms) = self.max_time_ms {
+            document.insert("maxTimeMS", max_time_ms);
+        }
 
-        // read_preference is used directly by Collection::aggregate.
+        // read_preference, read_concern and write_concern are used directly by
+        // Collection::aggregate.
 
         document
     }
 }
 
-/// Options for count queries.
-#[derive(Clone, Debug, Default, PartialEq)]
-pub struct CountOptions {
-    pub skip: Option<i64>,
-    pub limit: Option<i64>,
-    pub hint: Option<String>,
-    pub hint_doc: Option<bson::Document>,
-    pub max_time_ms: Option<i64>,
-    pub read_preference: Option<ReadPreference>,
+impl From<AggregateOptions> for bson::Document {
+    fn from(options: AggregateOptions) -> Self {
+        options.to_document()
+    }
 }
 
-impl CountOptions {
+options_struct! {
+    /// Options for change streams.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct ChangeStreamOptions, ChangeStreamOptionsBuilder {
+        values {}
+        options {
+            /// Set to `"updateLookup"` to include the current version of the whole document in
+            /// update events.
+            full_document: String,
+            /// The resume token of the event after which the stream should start.
+            resume_after: bson::Document,
+            /// The cluster time, encoded as a BSON timestamp, at which the stream should start.
+            /// Ignored if `resume_after` is set.
+            start_at_operation_time: i64,
+            /// The number of events in each batch the server returns.
+            batch_size: i32,
+            /// The read preference of the stream; defaults to the collection's.
+            read_preference: ReadPreference,
+        }
+    }
+}
+
+impl ChangeStreamOptions {
     pub fn new() -> Self {
         Default::default()
     }
 }
 
-impl From<CountOptions> for bson::Document {
-    fn from(options: CountOptions) -> Self {
+options_struct! {
+    /// Options for count queries.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct CountOptions, CountOptionsBuilder {
+        values {}
+        options {
+            /// The number of matching documents to skip before counting.
+            skip: i64,
+            /// The most documents to count.
+            limit: i64,
+            /// The name of the index to use for counting.
+            hint: String,
+            /// The key pattern of the index to use for counting, which takes precedence over
+            /// `hint`.
+            hint_doc: bson::Document,
+            /// The time limit of the operation in milliseconds.
+            max_time_ms: i64,
+            /// The read preference of the operation; defaults to the collection's.
+            read_preference: ReadPreference,
+            /// The read concern of the operation; defaults to the collection's.
+            read_concern: ReadConcern,
+            /// A comment to attach to the operation, which shows up in the profiler and server
+            /// logs.
+            comment: String,
+            /// The collation used to compare strings.
+            collation: Collation,
+        }
+    }
+}
+
+impl CountOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the index `hint_doc` or `hint` describes, if either is set.
+    pub fn index_hint(&self) -> Option<Hint> {
+        match (&self.hint_doc, &self.hint) {
+            (&Some(ref keys), _) => Some(Hint::Keys(keys.clone())),
+            (&None, &Some(ref name)) => Some(Hint::Name(name.clone())),
+            (&None, &None) => None,
+        }
+    }
+
+    /// Returns the fields the options add to a `count` command.
+    pub fn to_document(&self) -> bson::Document {
         let mut document = bson::Document::new();
 
-        if let Some(skip) = options.skip {
+        if let Some(skip) = self.skip {
             document.insert("skip", skip);
         }
 
-        if let Some(limit) = options.limit {
+        if let Some(limit) = self.limit {
             document.insert("limit", limit);
         }
 
-        if let Some(hint) = options.hint {
-            document.insert("hint", hint);
+        if let Some(hint) = self.index_hint() {
+            document.insert("hint", hint.to_bson());
         }
 
-        if let Some(hint_doc) = options.hint_doc {
-            document.insert("hint_doc", hint_doc);
+        if let Some(ref comment) = self.comment {
+            document.insert("comment", comment);
+        }
+
+        if let Some(ref collation) = self.collation {
+            document.insert("collation", collation.to_document());
         }
 
         // maxTimeMS is not currently used by the driver.
 
-        // read_preference is used directly by Collection::count.
+        // read_preference and read_concern are used directly by Collection::count.
 
         document
     }
 }
 
-/// Options for distinct queries.
-#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct DistinctOptions {
-    pub max_time_ms: Option<i64>,
-    pub read_preference: Option<ReadPreference>,
+impl From<CountOptions> for bson::Document {
+    fn from(options: CountOptions) -> Self {
+        options.to_document()
+    }
+}
+
+options_struct! {
+    /// Options for distinct queries.
+    #[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct DistinctOptions, DistinctOptionsBuilder {
+        values {}
+        options {
+            /// The time limit of the operation in milliseconds.
+            max_time_ms: i64,
+            /// The read preference of the operation; defaults to the collection's.
+            read_preference: ReadPreference,
+            /// The read concern of the operation; defaults to the collection's.
+            read_concern: ReadConcern,
+            /// A comment to attach to the operation, which shows up in the profiler and server
+            /// logs.
+            comment: String,
+            /// The collation used to compare strings.
+            collation: Collation,
+        }
+    }
 }
 
 impl DistinctOptions {
     pub fn new() -> Self {
         Default::default()
     }
+
+    /// Returns the fields the options add to a `distinct` command.
+    pub fn to_document(&self) -> bson::Document {
+        let mut document = bson::Document::new();
+
+        if let Some(ref comment) = self.comment {
+            document.insert("comment", comment);
+        }
+
+        if let Some(ref collation) = self.collation {
+            document.insert("collation", collation.to_document());
+        }
+
+        // maxTimeMS is not currently used by the driver.
+
+        // read_preference and read_concern are used directly by Collection::distinct.
+
+        document
+    }
 }
 
-/// Options for collection queries.
-#[derive(Clone, Debug, Default, PartialEq)]
-pub struct FindOptions {
-    pub allow_partial_results: bool,
-    pub no_cursor_timeout: bool,
-    pub oplog_replay: bool,
-    pub skip: Option<i64>,
-    pub limit: Option<i64>,
-    pub cursor_type: CursorType,
-    pub batch_size: Option<i32>,
-    pub comment: Option<String>,
-    pub max_time_ms: Option<i64>,
-    pub modifiers: Option<bson::Document>,
-    pub projection: Option<bson::Document>,
-    pub sort: Option<bson::Document>,
-    pub read_preference: Option<ReadPreference>,
+impl From<DistinctOptions> for bson::Document {
+    fn from(options: DistinctOptions) -> Self {
+        options.to_document()
+    }
+}
+
+options_struct! {
+    /// Options for collection queries.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct FindOptions, FindOptionsBuilder {
+        values {
+            /// Lets a query against a sharded cluster return the results of the available shards
+            /// when others are down, rather than failing.
+            allow_partial_results: bool,
+            /// Keeps the server from closing the cursor after it has been idle for 10 minutes.
+            no_cursor_timeout: bool,
+            /// Speeds up queries of the oplog on a timestamp range. Ignored by MongoDB 4.4 and
+            /// later.
+            oplog_replay: bool,
+            /// Streams every batch of the result set over a single connection without issuing
+            /// getMore requests. The connection is reserved by the cursor until the stream
+            /// completes.
+            exhaust: bool,
+            /// Whether the cursor stays open once it has returned every result, to return the
+            /// documents inserted later into a capped collection.
+            cursor_type: CursorType,
+            /// Scans the `_id` index so that documents moved by concurrent updates are returned
+            /// once. It is sent as `$snapshot` with legacy queries, and can't be combined with a
+            /// sort or hint. MongoDB 3.6 removed it; see `consistent_scan`.
+            snapshot: bool,
+        }
+        options {
+            /// The number of matching documents to skip before returning any.
+            skip: i64,
+            /// The most documents to return.
+            limit: i64,
+            /// The number of documents in each batch the server returns.
+            batch_size: i32,
+            /// A comment to attach to the query, which shows up in the profiler and server logs.
+            /// It is sent as `$comment` with legacy queries. Every getMore issued by the
+            /// resulting cursor reports it in its command events, and sends it to MongoDB 4.4
+            /// and later, which accept a getMore comment.
+            comment: String,
+            /// The time limit of the query in milliseconds.
+            max_time_ms: i64,
+            /// How long each getMore of a `TailableAwait` cursor waits for new documents before
+            /// returning an empty batch. Setting it for other cursor types is an error. Requires
+            /// MongoDB 3.2 or later.
+            max_await_time_ms: i64,
+            /// Legacy query modifiers, such as `$max` or `$min`. Not currently used by the driver.
+            modifiers: bson::Document,
+            /// The fields of the documents to return.
+            projection: bson::Document,
+            /// The order in which to return the documents.
+            sort: bson::Document,
+            /// The index to use for the query. It is sent as `$hint` with legacy queries.
+            hint: Hint,
+            /// The collation used to compare strings. Legacy queries can't carry a collation, so
+            /// setting one always runs the find command.
+            collation: Collation,
+            /// The read preference of the query; defaults to the collection's.
+            read_preference: ReadPreference,
+            /// The read concern of the query; defaults to the collection's.
+            read_concern: ReadConcern,
+            /// Scans the collection in the order its documents are stored, or the reverse,
+            /// without using an index. It is sent as a `$natural` hint, so it can't be combined
+            /// with `hint`.
+            natural_sort: Direction,
+        }
+    }
 }
 
 impl FindOptions {
@@ -178,184 +868,328 @@ impl FindOptions {
     pub fn new() -> Self {
         Default::default()
     }
-}
 
-impl From<FindOptions> for bson::Document {
-    fn from(options: FindOptions) -> Self {
+    /// Returns options for a scan that returns each document once even if documents move while
+    /// it runs: `snapshot` on servers before MongoDB 3.6, and a hint of the `_id` index on
+    /// later ones, which removed it. The capabilities are those of the deployment, as returned
+    /// by `ThreadedClient::capabilities`.
+    pub fn consistent_scan(capabilities: &ServerCapabilities) -> FindOptions {
+        let mut options = FindOptions::new();
+
+        if capabilities.supports_snapshot_queries() {
+            options.snapshot = true;
+        } else {
+            options.hint = Some(Hint::Keys(doc! { "_id": 1 }));
+        }
+        options
+    }
+
+    /// Returns the fields the options add to a `find` command.
+    pub fn to_document(&self) -> bson::Document {
         let mut document = bson::Document::new();
 
-        // `allow_partial_results`, `no_cursor_timeout`, `oplog_relay`, and `cursor_type` are used by
-        // wire_protocol::OpQueryFlags.
+        // `allow_partial_results`, `no_cursor_timeout`, `oplog_relay`, `exhaust`, and `cursor_type`
+        // are used by wire_protocol::OpQueryFlags.
+        //
+        // `modifiers` is not currently used by the driver.
         //
-        // `max_time_ms` and `modifiers` are not currently used by the driver.
+        // `max_await_time_ms` is sent with getMore commands by the cursor.
         //
-        // read_preference is used directly by Collection::find_with_command_type.
+        // read_preference and read_concern are used directly by
+        // Collection::find_with_command_type.
 
-        if let Some(projection) = options.projection {
-            document.insert("projection", projection);
+        if let Some(ref projection) = self.projection {
+            document.insert("projection", projection.clone());
         }
 
-        if let Some(skip) = options.skip {
+        if let Some(skip) = self.skip {
             document.insert("skip", skip);
         }
 
-        if let Some(limit) = options.limit {
+        if let Some(limit) = self.limit {
             document.insert("limit", limit);
         }
 
-        if let Some(batch_size) = options.batch_size {
+        if let Some(batch_size) = self.batch_size {
             document.insert("batchSize", batch_size);
         }
 
-        if let Some(sort) = options.sort {
-            document.insert("sort", sort);
+        if let Some(ref sort) = self.sort {
+            document.insert("sort", sort.clone());
+        }
+
+        if let Some(ref comment) = self.comment {
+            document.insert("comment", comment);
+        }
+
+        if let Some(max_time_ms) = self.max_time_ms {
+            document.insert("maxTimeMS", max_time_ms);
+        }
+
+        if let Some(ref hint) = self.hint {
+            document.insert("hint", hint.to_bson());
+        }
+
+        if let Some(direction) = self.natural_sort {
+            document.insert("hint", doc! { "$natural": direction.to_i32() });
+        }
+
+        if let Some(ref collation) = self.collation {
+            document.insert("collation", collation.to_document());
+        }
+
+        if self.snapshot {
+            document.insert("snapshot", true);
         }
 
         document
     }
 }
 
-/// Options for `findOneAndDelete` operations.
-#[derive(Clone, Debug, Default, PartialEq)]
-pub struct FindOneAndDeleteOptions {
-    pub max_time_ms: Option<i64>,
-    pub projection: Option<bson::Document>,
-    pub sort: Option<bson::Document>,
-    pub write_concern: Option<WriteConcern>,
+impl From<FindOptions> for bson::Document {
+    fn from(options: FindOptions) -> Self {
+        options.to_document()
+    }
+}
+
+options_struct! {
+    /// Options for `findOneAndDelete` operations.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct FindOneAndDeleteOptions, FindOneAndDeleteOptionsBuilder {
+        values {}
+        options {
+            /// The time limit of the operation in milliseconds. Not currently used by the driver.
+            max_time_ms: i64,
+            /// The fields of the deleted document to return.
+            projection: bson::Document,
+            /// The order deciding which document is deleted when several match.
+            sort: bson::Document,
+            /// The write concern of the operation; defaults to the collection's.
+            write_concern: WriteConcern,
+            /// A comment to attach to the operation, which shows up in the profiler and server
+            /// logs.
+            comment: String,
+            /// The collation used to compare strings.
+            collation: Collation,
+        }
+    }
 }
 
 impl FindOneAndDeleteOptions {
     pub fn new() -> Self {
         Default::default()
     }
-}
 
-impl From<FindOneAndDeleteOptions> for bson::Document {
-    fn from(options: FindOneAndDeleteOptions) -> Self {
+    /// Returns the fields the options add to a `findAndModify` command.
+    pub fn to_document(&self) -> bson::Document {
         let mut document = bson::Document::new();
 
         // max_time_ms is not currently used by the driver
 
-        if let Some(projection) = options.projection {
-            document.insert("fields", projection);
+        if let Some(ref projection) = self.projection {
+            document.insert("fields", projection.clone());
         }
 
-        if let Some(sort) = options.sort {
-            document.insert("sort", sort);
+        if let Some(ref sort) = self.sort {
+            document.insert("sort", sort.clone());
         }
 
-        if let Some(write_concern) = options.write_concern {
+        if let Some(ref write_concern) = self.write_concern {
             document.insert("writeConcern", write_concern.to_bson());
         }
 
+        if let Some(ref comment) = self.comment {
+            document.insert("comment", comment);
+        }
+
+        if let Some(ref collation) = self.collation {
+            document.insert("collation", collation.to_document());
+        }
+
         document
     }
 }
 
-/// Options for `findOneAndUpdate` operations.
-#[derive(Clone, Debug, Default, PartialEq)]
-pub struct FindOneAndUpdateOptions {
-    pub return_document: Option<ReturnDocument>,
-    pub max_time_ms: Option<i64>,
-    pub projection: Option<bson::Document>,
-    pub sort: Option<bson::Document>,
-    pub upsert: Option<bool>,
-    pub write_concern: Option<WriteConcern>,
+impl From<FindOneAndDeleteOptions> for bson::Document {
+    fn from(options: FindOneAndDeleteOptions) -> Self {
+        options.to_document()
+    }
+}
+
+options_struct! {
+    /// Options for `findOneAndUpdate` operations.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct FindOneAndUpdateOptions, FindOneAndUpdateOptionsBuilder {
+        values {}
+        options {
+            /// Whether to return the document as it was before the update, which is the default,
+            /// or after it.
+            return_document: ReturnDocument,
+            /// The time limit of the operation in milliseconds. Not currently used by the driver.
+            max_time_ms: i64,
+            /// The fields of the returned document.
+            projection: bson::Document,
+            /// The order deciding which document is updated when several match.
+            sort: bson::Document,
+            /// Inserts a document if none matches the filter.
+            upsert: bool,
+            /// The write concern of the operation; defaults to the collection's.
+            write_concern: WriteConcern,
+            /// A comment to attach to the operation, which shows up in the profiler and server
+            /// logs.
+            comment: String,
+            /// The collation used to compare strings.
+            collation: Collation,
+            /// Filters selecting the array elements updated through `$[identifier]`. Requires
+            /// MongoDB 3.6 or later.
+            array_filters: Vec<bson::Document>,
+        }
+    }
 }
 
 impl FindOneAndUpdateOptions {
     pub fn new() -> Self {
         Default::default()
     }
-}
 
-impl From<FindOneAndUpdateOptions> for bson::Document {
-    fn from(options: FindOneAndUpdateOptions) -> Self {
+    /// Returns the fields the options add to a `findAndModify` command.
+    pub fn to_document(&self) -> bson::Document {
         let mut document = bson::Document::new();
 
-        if let Some(return_document) = options.return_document {
+        if let Some(return_document) = self.return_document {
             document.insert("new", return_document.as_bool());
         }
 
         // max_time_ms is not currently used by the driver
 
-        if let Some(projection) = options.projection {
-            document.insert("fields", projection);
+        if let Some(ref projection) = self.projection {
+            document.insert("fields", projection.clone());
         }
 
-        if let Some(sort) = options.sort {
-            document.insert("sort", sort);
+        if let Some(ref sort) = self.sort {
+            document.insert("sort", sort.clone());
         }
 
-        if let Some(upsert) = options.upsert {
+        if let Some(upsert) = self.upsert {
             document.insert("upsert", upsert);
         }
 
-        if let Some(write_concern) = options.write_concern {
+        if let Some(ref write_concern) = self.write_concern {
             document.insert("writeConcern", write_concern.to_bson());
         }
 
-        document
-    }
-}
-
-/// Options for index operations.
-#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
-pub struct IndexOptions {
-    #[serde(skip_serializing_if="Option::is_none")]
-    pub background: Option<bool>,
-
-    #[serde(rename="expireAfterSeconds", skip_serializing_if="Option::is_none")]
-    pub expire_after_seconds: Option<i32>,
-
-    #[serde(skip_serializing_if="Option::is_none")]
-    pub name: Option<String>,
-
-    #[serde(rename="partialFilterExpression", skip_serializing_if="Option::is_none")]
-    pub partial_filter_expression: Option<bson::Document>,
-
-    #[serde(skip_serializing_if="Option::is_none")]
-    pub sparse: Option<bool>,
-
-    #[serde(rename="storageEngine", skip_serializing_if="Option::is_none")]
-    pub storage_engine: Option<bson::Document>,
-
-    #[serde(skip_serializing_if="Option::is_none")]
-    pub unique: Option<bool>,
-
-    #[serde(rename="v", skip_serializing_if="Option::is_none")]
-    pub version: Option<i32>,
-
-    // Options for text indexes
-    #[serde(skip_serializing_if="Option::is_none")]
-    pub default_language: Option<String>,
-
-    #[serde(skip_serializing_if="Option::is_none")]
-    pub language_override: Option<String>,
-
-    #[serde(rename="textIndexVersion", skip_serializing_if="Option::is_none")]
-    pub text_version: Option<i32>,
-
-    #[serde(skip_serializing_if="Option::is_none")]
-    pub weights: Option<bson::Document>,
+        if let Some(ref comment) = self.comment {
+            document.insert("comment", comment);
+        }
 
-    // Options for 2dsphere indexes
-    #[serde(rename="2dsphereIndexVersion", skip_serializing_if="Option::is_none")]
-    pub sphere_version: Option<i32>,
+        if let Some(ref collation) = self.collation {
+            document.insert("collation", collation.to_document());
+        }
 
-    // Options for 2d indexes
-    #[serde(skip_serializing_if="Option::is_none")]
-    pub bits: Option<i32>,
+        if let Some(ref array_filters) = self.array_filters {
+            let array_filters: Vec<_> = array_filters.iter().cloned().map(Bson::Document).collect();
+            document.insert("arrayFilters", array_filters);
+        }
 
-    #[serde(skip_serializing_if="Option::is_none")]
-    pub max: Option<f64>,
+        document
+    }
+}
 
-    #[serde(skip_serializing_if="Option::is_none")]
-    pub min: Option<f64>,
+impl From<FindOneAndUpdateOptions> for bson::Document {
+    fn from(options: FindOneAndUpdateOptions) -> Self {
+        options.to_document()
+    }
+}
 
-    // Options for geoHaystack indexes
-    #[serde(rename="bucketSize", skip_serializing_if="Option::is_none")]
-    pub bucket_size: Option<i32>,
+options_struct! {
+    /// Options for index operations.
+    #[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+    pub struct IndexOptions, IndexOptionsBuilder {
+        values {}
+        options {
+            /// Builds the index without blocking other operations on the database. Ignored by
+            /// MongoDB 4.2 and later.
+            #[serde(skip_serializing_if="Option::is_none")]
+            background: bool,
+
+            /// The number of seconds after which documents are removed, going by the date in the
+            /// indexed field.
+            #[serde(rename="expireAfterSeconds", skip_serializing_if="Option::is_none")]
+            expire_after_seconds: i32,
+
+            /// The name of the index; defaults to one generated from its keys.
+            #[serde(skip_serializing_if="Option::is_none")]
+            name: String,
+
+            /// Only the documents matching the filter are indexed.
+            #[serde(rename="partialFilterExpression", skip_serializing_if="Option::is_none")]
+            partial_filter_expression: bson::Document,
+
+            /// Only the documents holding the indexed field are indexed.
+            #[serde(skip_serializing_if="Option::is_none")]
+            sparse: bool,
+
+            /// Options passed to the storage engine, keyed by its name.
+            #[serde(rename="storageEngine", skip_serializing_if="Option::is_none")]
+            storage_engine: bson::Document,
+
+            /// Rejects documents whose indexed values are already indexed.
+            #[serde(skip_serializing_if="Option::is_none")]
+            unique: bool,
+
+            /// The collation used to compare strings.
+            #[serde(skip_serializing_if="Option::is_none")]
+            collation: Collation,
+
+            /// The version of the index format.
+            #[serde(rename="v", skip_serializing_if="Option::is_none")]
+            version: i32,
+
+            /// Keeps the query planner from using the index. Requires MongoDB 4.4 or later.
+            #[serde(skip_serializing_if="Option::is_none")]
+            hidden: bool,
+
+            // Options for text indexes
+            /// The language used for stemming and stop words; defaults to `"english"`.
+            #[serde(skip_serializing_if="Option::is_none")]
+            default_language: String,
+
+            /// The field holding the language of each document; defaults to `"language"`.
+            #[serde(skip_serializing_if="Option::is_none")]
+            language_override: String,
+
+            /// The version of the text index format.
+            #[serde(rename="textIndexVersion", skip_serializing_if="Option::is_none")]
+            text_version: i32,
+
+            /// The weight of each indexed field in the score of a match; defaults to 1.
+            #[serde(skip_serializing_if="Option::is_none")]
+            weights: bson::Document,
+
+            // Options for 2dsphere indexes
+            /// The version of the 2dsphere index format.
+            #[serde(rename="2dsphereIndexVersion", skip_serializing_if="Option::is_none")]
+            sphere_version: i32,
+
+            // Options for 2d indexes
+            /// The precision of the stored geohash values in bits; defaults to 26.
+            #[serde(skip_serializing_if="Option::is_none")]
+            bits: i32,
+
+            /// The upper bound of the coordinates; defaults to 180.
+            #[serde(skip_serializing_if="Option::is_none")]
+            max: f64,
+
+            /// The lower bound of the coordinates; defaults to -180.
+            #[serde(skip_serializing_if="Option::is_none")]
+            min: f64,
+
+            // Options for geoHaystack indexes
+            /// The distance between the locations grouped in the same bucket.
+            #[serde(rename="bucketSize", skip_serializing_if="Option::is_none")]
+            bucket_size: i32,
+        }
+    }
 }
 
 impl IndexOptions {
@@ -382,6 +1216,17 @@ impl IndexModel {
         }
     }
 
+    /// Starts building a model with no keys, to which keys are added in order.
+    pub fn builder() -> IndexModelBuilder {
+        IndexModelBuilder::default()
+    }
+
+    /// Returns true if the model has a hashed key as well as other keys.
+    pub fn is_compound_hashed(&self) -> bool {
+        self.keys.len() > 1 &&
+            self.keys.values().any(|value| *value == Bson::String(String::from("hashed")))
+    }
+
     /// Returns the name of the index as specified by the options, or
     /// as automatically generated using the keys.
     pub fn name(&self) -> Result<String> {
@@ -445,9 +1290,15 @@ impl IndexModel {
         if let Some(val) = self.options.unique {
             doc.insert("unique", val);
         }
+        if let Some(ref val) = self.options.collation {
+            doc.insert("collation", val.to_document());
+        }
         if let Some(val) = self.options.version {
             doc.insert("v", val);
         }
+        if let Some(val) = self.options.hidden {
+            doc.insert("hidden", val);
+        }
         if let Some(ref val) = self.options.default_language {
             doc.insert("default_language", val);
         }
@@ -480,40 +1331,262 @@ impl IndexModel {
     }
 }
 
-/// Options for insertMany operations.
-#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
-pub struct InsertManyOptions {
-    pub ordered: Option<bool>,
-    pub write_concern: Option<WriteConcern>,
+/// Builds an index model one key or option at a time, e.g.
+/// `IndexModel::builder().asc("a").desc("b").hashed("c").build()`.
+#[derive(Clone, Debug, Default)]
+pub struct IndexModelBuilder {
+    keys: bson::Document,
+    options: IndexOptions,
+}
+
+impl IndexModelBuilder {
+    /// Adds an ascending key on the field.
+    pub fn asc(self, field: &str) -> IndexModelBuilder {
+        self.key(field, Bson::I32(1))
+    }
+
+    /// Adds a descending key on the field.
+    pub fn desc(self, field: &str) -> IndexModelBuilder {
+        self.key(field, Bson::I32(-1))
+    }
+
+    /// Adds a hashed key on the field, as used by hashed shard keys. Compound indexes with a
+    /// hashed key require MongoDB 4.4.
+    pub fn hashed(self, field: &str) -> IndexModelBuilder {
+        self.key(field, Bson::String(String::from("hashed")))
+    }
+
+    /// Adds a key of any type on the field, e.g. "text" or "2dsphere".
+    pub fn key(mut self, field: &str, kind: Bson) -> IndexModelBuilder {
+        self.keys.insert(field, kind);
+        self
+    }
+
+    /// Names the index, rather than using the name generated from its keys.
+    pub fn name(mut self, name: &str) -> IndexModelBuilder {
+        self.options.name = Some(String::from(name));
+        self
+    }
+
+    pub fn unique(mut self, unique: bool) -> IndexModelBuilder {
+        self.options.unique = Some(unique);
+        self
+    }
+
+    pub fn sparse(mut self, sparse: bool) -> IndexModelBuilder {
+        self.options.sparse = Some(sparse);
+        self
+    }
+
+    /// Only indexes the documents matching the filter. Requires MongoDB 3.2.
+    pub fn partial_filter(mut self, filter: bson::Document) -> IndexModelBuilder {
+        self.options.partial_filter_expression = Some(filter);
+        self
+    }
+
+    /// Removes documents once the date in the indexed field is older than this many seconds.
+    pub fn expire_after(mut self, seconds: i32) -> IndexModelBuilder {
+        self.options.expire_after_seconds = Some(seconds);
+        self
+    }
+
+    /// Requires MongoDB 3.4.
+    pub fn collation(mut self, collation: Collation) -> IndexModelBuilder {
+        self.options.collation = Some(collation);
+        self
+    }
+
+    /// Hides the index from the query planner, while still maintaining it. Requires
+    /// MongoDB 4.4.
+    pub fn hidden(mut self, hidden: bool) -> IndexModelBuilder {
+        self.options.hidden = Some(hidden);
+        self
+    }
+
+    /// Options passed to the storage engine when the index is created.
+    pub fn storage_engine(mut self, options: bson::Document) -> IndexModelBuilder {
+        self.options.storage_engine = Some(options);
+        self
+    }
+
+    /// Returns the model that was built.
+    pub fn build(self) -> IndexModel {
+        IndexModel::new(self.keys, Some(self.options))
+    }
+}
+
+options_struct! {
+    /// Options for insertMany operations.
+    #[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct InsertManyOptions, InsertManyOptionsBuilder {
+        values {}
+        options {
+            /// Set to `false` to keep inserting the remaining documents after one fails.
+            ordered: bool,
+            /// The write concern of the operation; defaults to the collection's.
+            write_concern: WriteConcern,
+            /// A comment to attach to the operation, which shows up in the profiler and server
+            /// logs.
+            comment: String,
+            /// Lets the documents skip the validation rules of the collection.
+            bypass_document_validation: bool,
+        }
+    }
 }
 
 impl InsertManyOptions {
     pub fn new() -> Self {
         Default::default()
     }
-}
 
-impl From<InsertManyOptions> for bson::Document {
-    fn from(options: InsertManyOptions) -> Self {
+    /// Returns the fields the options add to an `insert` command.
+    pub fn to_document(&self) -> bson::Document {
         let mut document = bson::Document::new();
 
-        if let Some(ordered) = options.ordered {
+        if let Some(ordered) = self.ordered {
             document.insert("ordered", ordered);
         }
 
-        if let Some(write_concern) = options.write_concern {
+        if let Some(ref write_concern) = self.write_concern {
             document.insert("writeConcern", write_concern.to_bson());
         }
 
+        if let Some(ref comment) = self.comment {
+            document.insert("comment", comment);
+        }
+
+        if let Some(bypass) = self.bypass_document_validation {
+            document.insert("bypassDocumentValidation", bypass);
+        }
+
         document
     }
 }
 
-/// Options for update operations.
-#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
-pub struct UpdateOptions {
-    pub upsert: Option<bool>,
-    pub write_concern: Option<WriteConcern>,
+impl From<InsertManyOptions> for bson::Document {
+    fn from(options: InsertManyOptions) -> Self {
+        options.to_document()
+    }
+}
+
+/// Whether and when `Collection::copy_to` copies the indexes of the source collection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IndexCopy {
+    /// Only the `_id` index the target collection is created with.
+    None,
+    /// Before the documents, so that unique indexes are enforced as they are inserted.
+    Before,
+    /// After the documents, which builds each index once rather than updating it per batch.
+    After,
+}
+
+impl Default for IndexCopy {
+    fn default() -> Self {
+        IndexCopy::None
+    }
+}
+
+options_struct! {
+    /// Options for copying a collection with `Collection::copy_to`.
+    #[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct CopyOptions, CopyOptionsBuilder {
+        values {
+            /// Whether and when the indexes of the source collection are copied.
+            indexes: IndexCopy,
+            /// Counts the documents that would be copied without writing anything.
+            dry_run: bool,
+        }
+        options {
+            /// How many documents are read and then inserted at a time. Defaults to 1000.
+            batch_size: i32,
+            /// The write concern of the inserts into the target collection.
+            write_concern: WriteConcern,
+        }
+    }
+}
+
+impl CopyOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+options_struct! {
+    /// Options for `Collection::analyze_shard_key` and `Collection::shard_collection`.
+    #[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+    pub struct ShardKeyOptions, ShardKeyOptionsBuilder {
+        values {
+            /// Creates an index supporting the key if none does.
+            create_index: bool,
+            /// Shards the collection even if the key has fewer distinct values than the minimum.
+            force: bool,
+            /// Enforces that values of the key are unique, which requires a unique supporting
+            /// index.
+            unique: bool,
+        }
+        options {
+            /// Collections with more documents are analyzed through a random sample of this
+            /// many. Defaults to 100000.
+            sample_size: i64,
+            /// The fewest distinct values the key should have. Defaults to 100.
+            min_distinct_values: i64,
+        }
+    }
+}
+
+impl ShardKeyOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+options_struct! {
+    /// Options for `Collection::load_snapshot`.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct SnapshotOptions, SnapshotOptionsBuilder {
+        values {}
+        options {
+            /// Only the documents matching the filter are read into the snapshot.
+            filter: bson::Document,
+            /// The most documents the snapshot may hold; loading or refreshing fails if the
+            /// collection has more. Defaults to 10000.
+            max_documents: usize,
+            /// A field holding when each document was last updated, such as a date or timestamp
+            /// set on every write. Refreshes then only read the documents updated since the
+            /// latest update already in the snapshot.
+            updated_at_field: String,
+        }
+    }
+}
+
+impl SnapshotOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+options_struct! {
+    /// Options for update operations.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct UpdateOptions, UpdateOptionsBuilder {
+        values {}
+        options {
+            /// Inserts a document if none matches the filter.
+            upsert: bool,
+            /// The write concern of the operation; defaults to the collection's.
+            write_concern: WriteConcern,
+            /// A comment to attach to the operation, which shows up in the profiler and server
+            /// logs.
+            comment: String,
+            /// The index to use to find the documents to update. Requires MongoDB 4.2 or later.
+            hint: Hint,
+            /// The collation used to compare strings.
+            collation: Collation,
+            /// Filters selecting the array elements updated through `$[identifier]`. Requires
+            /// MongoDB 3.6 or later.
+            array_filters: Vec<bson::Document>,
+        }
+    }
 }
 
 impl UpdateOptions {
@@ -522,8 +1595,52 @@ impl UpdateOptions {
     }
 }
 
+impl Eq for UpdateOptions {}
+
+// Array filters are documents, which can't be hashed, so only their field names are.
+impl Hash for UpdateOptions {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.upsert.hash(state);
+        self.write_concern.hash(state);
+        self.comment.hash(state);
+        self.hint.hash(state);
+        self.collation.hash(state);
+
+        if let Some(ref array_filters) = self.array_filters {
+            for filter in array_filters {
+                filter.keys().count().hash(state);
+                for key in filter.keys() {
+                    key.hash(state);
+                }
+            }
+        }
+    }
+}
+
 pub type ReplaceOptions = UpdateOptions;
 
+options_struct! {
+    /// Options for delete operations.
+    #[derive(Clone, Debug, Default, PartialEq)]
+    pub struct DeleteOptions, DeleteOptionsBuilder {
+        values {}
+        options {
+            /// The write concern of the operation; defaults to the collection's.
+            write_concern: WriteConcern,
+            /// The index to use to find the documents to delete. Requires MongoDB 4.4 or later.
+            hint: Hint,
+            /// The collation used to compare strings.
+            collation: Collation,
+        }
+    }
+}
+
+impl DeleteOptions {
+    pub fn new() -> DeleteOptions {
+        Default::default()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -537,6 +1654,10 @@ mod test {
         opts.sparse = Some(true);
         opts.storage_engine = Some(doc!{"mmapv1": true}); // Not sure about the actual shape `:)`.
         opts.unique = Some(true);
+        let mut collation = Collation::new("en_US");
+        collation.strength = Some(2);
+        collation.alternate = Some("shifted".to_string());
+        opts.collation = Some(collation);
         opts.version = Some(2);
         opts.default_language = Some("en_us".to_string());
         opts.language_override = Some("en_us".to_string());
@@ -584,6 +1705,163 @@ mod test {
         assert_eq!(bson::Bson::Document(manual_ser), serde_ser);
     }
 
+    #[test]
+    fn hint_serialization() {
+        let mut find_options = FindOptions::new();
+        find_options.hint = Some(Hint::Keys(doc!{"a": 1}));
+        let find_doc = bson::Document::from(find_options);
+        assert_eq!(find_doc.get("hint"), Some(&Bson::Document(doc!{"a": 1})));
+
+        let mut count_options = CountOptions::new();
+        count_options.hint = Some("a_1".to_string());
+        let count_doc = bson::Document::from(count_options.clone());
+        assert_eq!(count_doc.get("hint"), Some(&Bson::String("a_1".to_string())));
+        assert_eq!(count_doc.get("hint_doc"), None);
+
+        count_options.hint_doc = Some(doc!{"a": 1});
+        let count_doc = bson::Document::from(count_options);
+        assert_eq!(count_doc.get("hint"), Some(&Bson::Document(doc!{"a": 1})));
+        assert_eq!(count_doc.get("hint_doc"), None);
+
+        let mut aggregate_options = AggregateOptions::new();
+        aggregate_options.hint = Some(Hint::Name("a_1".to_string()));
+        let aggregate_doc = bson::Document::from(aggregate_options);
+        assert_eq!(aggregate_doc.get("hint"), Some(&Bson::String("a_1".to_string())));
+
+        assert_eq!(format!("{}", Hint::Keys(doc!{"a": 1})), "{ a: 1 }");
+    }
+
+    #[test]
+    fn builders() {
+        let options = AggregateOptions::builder().allow_disk_use(true).batch_size(500).build();
+        assert_eq!(options.allow_disk_use(), Some(&true));
+        assert_eq!(options.batch_size(), 500);
+        assert_eq!(options.comment(), None);
+
+        let options = FindOptions::builder()
+            .limit(10)
+            .sort(doc!{"a": 1})
+            .cursor_type(CursorType::Tailable)
+            .build();
+
+        let mut expected = FindOptions::new();
+        expected.limit = Some(10);
+        expected.sort = Some(doc!{"a": 1});
+        expected.cursor_type = CursorType::Tailable;
+        assert_eq!(options, expected);
+
+        assert_eq!(CountOptions::builder().build(), CountOptions::default());
+
+        // A builder can start from existing options.
+        let options = options.to_builder().limit(20).build();
+        assert_eq!(options.limit(), Some(&20));
+        assert_eq!(options.cursor_type(), CursorType::Tailable);
+    }
+
+    #[test]
+    fn options_to_document() {
+        let options = AggregateOptions::builder()
+            .allow_disk_use(true)
+            .batch_size(500)
+            .comment(String::from("report"))
+            .build();
+        assert_eq!(
+            options.to_document(),
+            doc!{"allowDiskUse": true, "cursor": {"batchSize": 500}, "comment": "report"}
+        );
+
+        let options = AggregateOptions::builder().use_cursor(false).build();
+        assert_eq!(options.to_document(), doc!{"cursor": {}});
+
+        let options = CountOptions::builder().skip(5).limit(10).build();
+        assert_eq!(options.to_document(), doc!{"skip": 5i64, "limit": 10i64});
+
+        let options = DistinctOptions::builder()
+            .comment(String::from("report"))
+            .collation(Collation::new("fr"))
+            .build();
+        assert_eq!(options.to_document(), doc!{"comment": "report", "collation": {"locale": "fr"}});
+
+        let options = FindOptions::builder()
+            .projection(doc!{"a": 1})
+            .batch_size(2)
+            .exhaust(true)
+            .build();
+        assert_eq!(options.to_document(), doc!{"projection": {"a": 1}, "batchSize": 2});
+
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .upsert(true)
+            .array_filters(vec![doc!{"x": 1}])
+            .build();
+        assert_eq!(
+            options.to_document(),
+            doc!{"new": true, "upsert": true, "arrayFilters": [{"x": 1}]}
+        );
+
+        let options = InsertManyOptions::builder().ordered(false).build();
+        assert_eq!(options.to_document(), doc!{"ordered": false});
+
+        // Converting the options consumes them but produces the same document.
+        let options = FindOneAndDeleteOptions::builder().sort(doc!{"a": -1}).build();
+        assert_eq!(bson::Document::from(options.clone()), options.to_document());
+    }
+
+    #[test]
+    fn field_path() {
+        let path = FieldPath::new("a").index(0).field("b").first_match().all_elements();
+        assert_eq!(path.as_str(), "a.0.b.$.$[]");
+
+        let path = FieldPath::new("matrix").filtered("row").filtered("cell");
+        assert_eq!(format!("{}", path), "matrix.$[row].$[cell]");
+        assert_eq!(path.to(1), doc!{"matrix.$[row].$[cell]": 1});
+        assert_eq!(String::from(path), "matrix.$[row].$[cell]");
+    }
+
+    #[test]
+    fn projection_mixing() {
+        let projection = Projection::include(vec!["a", "b.c"]).exclude_id();
+        assert_eq!(projection.build().unwrap(), doc!{"a": 1, "b.c": 1, "_id": 0});
+
+        let projection = Projection::exclude(vec!["a"]).include_field("_id");
+        assert_eq!(projection.build().unwrap(), doc!{"a": 0, "_id": 1});
+
+        let projection = Projection::include(vec!["a"]).exclude_field("b");
+        assert!(projection.build().is_err());
+
+        // Only the last projection of a field counts.
+        let projection = Projection::include(vec!["a"]).exclude_field("b").include_field("b");
+        assert_eq!(projection.build().unwrap(), doc!{"a": 1, "b": 1});
+
+        let projection = Projection::include(vec!["a"]).include_field("a.b");
+        assert!(projection.build().is_err());
+
+        let projection = Projection::include(vec!["ab"]).include_field("a");
+        assert!(projection.build().is_ok());
+    }
+
+    #[test]
+    fn projection_operators() {
+        let projection = Projection::exclude(vec!["a"]).slice("arr", -5).meta_text_score("score");
+        assert_eq!(
+            projection.build().unwrap(),
+            doc!{"a": 0, "arr": {"$slice": -5}, "score": {"$meta": "textScore"}}
+        );
+
+        let projection = Projection::include(vec!["a"]).slice_range("arr", 10, 5);
+        assert_eq!(projection.build().unwrap(), doc!{"a": 1, "arr": {"$slice": [10, 5]}});
+
+        let projection = Projection::new().elem_match("arr", doc!{"x": 1}).exclude_id();
+        assert_eq!(
+            projection.build().unwrap(),
+            doc!{"arr": {"$elemMatch": {"x": 1}}, "_id": 0}
+        );
+
+        // $elemMatch is an inclusion.
+        let projection = Projection::exclude(vec!["a"]).elem_match("arr", doc!{"x": 1});
+        assert!(projection.build().is_err());
+    }
+
     #[test]
     fn to_and_from_serde_should_be_idempotent() {
         let keys = doc!{"test_field": "text"};
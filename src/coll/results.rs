@@ -4,6 +4,8 @@ use bson::Bson;
 use std::collections::BTreeMap;
 use super::error::{BulkWriteException, WriteException};
 use super::options::WriteModel;
+use Error::ResponseError;
+use Result;
 
 /// Results for a bulk write operation.
 #[derive(Debug, Clone, Default, PartialEq)]
@@ -53,6 +55,31 @@ pub struct InsertManyResult {
     pub bulk_write_exception: Option<BulkWriteException>,
 }
 
+/// Results for a collection copy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CopyResult {
+    /// The number of documents copied, or that would have been copied by a dry run.
+    pub copied_count: i64,
+    /// The names of the indexes created on the target collection.
+    pub indexes_created: Vec<String>,
+}
+
+/// A line of a JSON Lines import that couldn't be parsed or inserted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportLineError {
+    /// The line number, counting from 1.
+    pub line: usize,
+    pub message: String,
+}
+
+/// Results for a JSON Lines import.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportResult {
+    pub inserted_count: i64,
+    /// The lines that weren't imported, in order. An ordered import stops at the first.
+    pub errors: Vec<ImportLineError>,
+}
+
 /// Results for a deletion operation.
 #[derive(Debug, Clone, PartialEq)]
 pub struct DeleteResult {
@@ -71,6 +98,27 @@ pub struct UpdateResult {
     pub write_exception: Option<WriteException>,
 }
 
+/// Results for a validate command. A collection that fails validation is still reported as
+/// a successful command, with `valid` set to false.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidateResult {
+    /// Whether the collection and its indexes passed validation.
+    pub valid: bool,
+    /// The problems that make the collection invalid.
+    pub errors: Vec<String>,
+    /// Problems found that don't make the collection invalid.
+    pub warnings: Vec<String>,
+    /// The number of documents in the collection.
+    pub nrecords: Option<i64>,
+    /// The number of indexes on the collection.
+    pub nindexes: Option<i64>,
+    /// The number of keys in each index, by index name. Servers before 3.2 name the indexes
+    /// by their full namespace, e.g. `db.coll.$a_1`.
+    pub keys_per_index: BTreeMap<String, i64>,
+    /// The full reply, whose other fields vary widely between server versions.
+    pub doc: bson::Document,
+}
+
 impl BulkWriteResult {
     /// Extracts server reply information into a result.
     pub fn new() -> BulkWriteResult {
@@ -87,6 +135,36 @@ impl BulkWriteResult {
         }
     }
 
+    /// Returns the indexes of the requests that failed because they duplicated a key of a
+    /// unique index, in order.
+    pub fn duplicate_key_indexes(&self) -> Vec<i64> {
+        self.bulk_write_exception
+            .as_ref()
+            .map_or_else(Vec::new, BulkWriteException::duplicate_key_indexes)
+    }
+
+    /// Adds the counts and ids of a single batch of a bulk write to this result. The ids of the
+    /// batch are keyed by the position of their request within it, and are rekeyed with
+    /// `indices`, the index of each of these requests in the whole bulk write.
+    pub fn add_batch_result(&mut self, batch: BulkWriteResult, indices: &[i64]) {
+        let index = |i: i64| indices.get(i as usize).cloned().unwrap_or(i);
+
+        self.acknowledged = self.acknowledged && batch.acknowledged;
+        self.inserted_count += batch.inserted_count;
+        self.matched_count += batch.matched_count;
+        self.modified_count += batch.modified_count;
+        self.deleted_count += batch.deleted_count;
+        self.upserted_count += batch.upserted_count;
+
+        for (i, id) in batch.inserted_ids {
+            self.inserted_ids.insert(index(i), id);
+        }
+
+        for (i, id) in batch.upserted_ids {
+            self.upserted_ids.insert(index(i), id);
+        }
+    }
+
     /// Adds the data in a BulkDeleteResult to this result.
     pub fn process_bulk_delete_result(
         &mut self,
@@ -207,6 +285,15 @@ impl BulkDeleteResult {
             write_exception: exception,
         }
     }
+
+    /// Returns the result of deletes sent without waiting for the server to report on them.
+    pub fn unacknowledged() -> BulkDeleteResult {
+        BulkDeleteResult {
+            acknowledged: false,
+            deleted_count: 0,
+            write_exception: None,
+        }
+    }
 }
 
 impl BulkUpdateResult {
@@ -237,6 +324,17 @@ impl BulkUpdateResult {
             write_exception: exception,
         }
     }
+
+    /// Returns the result of updates sent without waiting for the server to report on them.
+    pub fn unacknowledged() -> BulkUpdateResult {
+        BulkUpdateResult {
+            acknowledged: false,
+            matched_count: 0,
+            modified_count: 0,
+            upserted_ids: None,
+            write_exception: None,
+        }
+    }
 }
 
 impl InsertOneResult {
@@ -262,6 +360,14 @@ impl InsertManyResult {
             bulk_write_exception: exception,
         }
     }
+
+    /// Returns the indexes of the documents that weren't inserted because they duplicated a
+    /// key of a unique index, in order.
+    pub fn duplicate_key_indexes(&self) -> Vec<i64> {
+        self.bulk_write_exception
+            .as_ref()
+            .map_or_else(Vec::new, BulkWriteException::duplicate_key_indexes)
+    }
 }
 
 impl DeleteResult {
@@ -337,3 +443,61 @@ impl UpdateResult {
         }
     }
 }
+
+impl ValidateResult {
+    /// Extracts server reply information into a result.
+    pub fn new(doc: bson::Document) -> Result<ValidateResult> {
+        let valid = match doc.get("valid") {
+            Some(&Bson::Boolean(valid)) => valid,
+            _ => return Err(ResponseError(
+                String::from("Server reply does not contain 'valid'."),
+            )),
+        };
+
+        let keys_per_index = match doc.get("keysPerIndex") {
+            Some(&Bson::Document(ref keys)) => {
+                keys.iter()
+                    .filter_map(|(name, count)| {
+                        number(count).map(|count| (name.to_owned(), count))
+                    })
+                    .collect()
+            }
+            _ => BTreeMap::new(),
+        };
+
+        Ok(ValidateResult {
+            valid: valid,
+            errors: strings(doc.get("errors")),
+            warnings: strings(doc.get("warnings")),
+            nrecords: doc.get("nrecords").and_then(number),
+            nindexes: doc.get("nIndexes").and_then(number),
+            keys_per_index: keys_per_index,
+            doc: doc,
+        })
+    }
+}
+
+// Reads a count, which the server may send as an integer of either width.
+fn number(bson: &Bson) -> Option<i64> {
+    match *bson {
+        Bson::I32(n) => Some(i64::from(n)),
+        Bson::I64(n) => Some(n),
+        _ => None,
+    }
+}
+
+// Reads an array of messages, ignoring anything that isn't a string.
+fn strings(bson: Option<&Bson>) -> Vec<String> {
+    match bson {
+        Some(&Bson::Array(ref values)) => {
+            values
+                .iter()
+                .filter_map(|value| match *value {
+                    Bson::String(ref value) => Some(value.to_owned()),
+                    _ => None,
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
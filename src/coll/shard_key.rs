@@ -0,0 +1,284 @@
+//! The analysis of a proposed shard key, as reported by `Collection::analyze_shard_key`.
+//!
+//! A shard key can't be changed once a collection is sharded, and a poor one only shows once the
+//! data grows: chunks that can't be split because too many documents share a key value, or
+//! inserts that all land on the shard owning the highest values. The analysis estimates both
+//! from the documents already in the collection.
+use bson::{self, Bson};
+
+use Error::ArgumentError;
+use Result;
+
+use std::fmt;
+
+/// The report of `Collection::analyze_shard_key` on a proposed shard key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShardKeyAnalysis {
+    /// The proposed shard key.
+    pub key: bson::Document,
+    /// The name of an index that supports the key, i.e. whose key pattern starts with it.
+    pub index: Option<String>,
+    /// Whether the analysis created the supporting index.
+    pub index_created: bool,
+    /// The number of documents in the collection.
+    pub document_count: i64,
+    /// The number of documents the estimates are based on, fewer than `document_count` if the
+    /// collection was sampled.
+    pub sampled_count: i64,
+    /// The number of distinct values of the key among the sampled documents.
+    pub distinct_values: i64,
+    /// The number of sampled documents sharing the most frequent value of the key.
+    pub max_frequency: i64,
+    /// Whether the first field of the key holds values that increase as documents are
+    /// inserted, such as ObjectIds or dates. Hashed fields never do.
+    pub monotonic: bool,
+    /// The problems found with the key, which are worth reviewing before sharding on it.
+    pub warnings: Vec<String>,
+}
+
+impl ShardKeyAnalysis {
+    /// Returns true if the key has at least `min_distinct_values` distinct values, or as many
+    /// as there are documents in a smaller collection.
+    pub fn has_enough_cardinality(&self, min_distinct_values: i64) -> bool {
+        self.distinct_values >= min_distinct_values.min(self.document_count)
+    }
+
+    /// Replaces the warnings with those that follow from the other fields of the report.
+    pub fn add_warnings(&mut self, min_distinct_values: i64) {
+        self.warnings.clear();
+
+        if self.index.is_none() {
+            self.warnings.push(String::from(
+                "No index supports the key, and one can only be created automatically for an \
+                 empty collection.",
+            ));
+        }
+
+        if !self.has_enough_cardinality(min_distinct_values) {
+            self.warnings.push(format!(
+                "The key has {} distinct values in {} sampled documents, fewer than {}, which \
+                 limits how many chunks the collection can be split into.",
+                self.distinct_values,
+                self.sampled_count,
+                min_distinct_values
+            ));
+        }
+
+        // A value shared by a quarter of the documents makes a chunk that can't be split.
+        if self.sampled_count > 0 && self.max_frequency * 4 > self.sampled_count {
+            self.warnings.push(format!(
+                "{}% of sampled documents share a single value of the key, which will form a \
+                 chunk too large to be split or moved.",
+                self.max_frequency * 100 / self.sampled_count
+            ));
+        }
+
+        if self.monotonic {
+            self.warnings.push(String::from(
+                "The first field of the key increases as documents are inserted, so every \
+                 insert goes to the shard owning the highest values; consider hashing it.",
+            ));
+        }
+    }
+}
+
+impl fmt::Display for ShardKeyAnalysis {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "shard key {} has {} distinct values in {} sampled of {} documents",
+            self.key,
+            self.distinct_values,
+            self.sampled_count,
+            self.document_count
+        )?;
+
+        for warning in &self.warnings {
+            write!(fmt, " {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that a shard key has at least one field, each ascending (1) or "hashed", with at
+/// most one hashed field.
+pub fn check_shard_key(key: &bson::Document) -> Result<()> {
+    if key.is_empty() {
+        return Err(ArgumentError(String::from("A shard key must have at least one field.")));
+    }
+
+    let mut hashed = 0;
+    for (field, value) in key {
+        match *value {
+            Bson::String(ref kind) if kind == "hashed" => hashed += 1,
+            ref value if is_ascending(value) => (),
+            _ => {
+                return Err(ArgumentError(format!(
+                    "Shard key field {:?} must be 1 or \"hashed\".",
+                    field
+                )))
+            }
+        }
+    }
+
+    if hashed > 1 {
+        return Err(ArgumentError(String::from("A shard key can have one hashed field at most.")));
+    }
+    Ok(())
+}
+
+/// Returns true if an index, as returned by `list_indexes`, can support the shard key: its key
+/// pattern starts with the fields of the shard key, of the same types, and it indexes every
+/// document with the simple collation.
+pub fn supports_shard_key(index: &bson::Document, key: &bson::Document) -> bool {
+    if index.contains_key("partialFilterExpression") ||
+        index.get("sparse") == Some(&Bson::Boolean(true))
+    {
+        return false;
+    }
+
+    if let Some(&Bson::Document(ref collation)) = index.get("collation") {
+        if collation.get("locale") != Some(&Bson::String(String::from("simple"))) {
+            return false;
+        }
+    }
+
+    let pattern = match index.get("key") {
+        Some(&Bson::Document(ref pattern)) => pattern,
+        _ => return false,
+    };
+
+    pattern.len() >= key.len() &&
+        pattern.iter().zip(key.iter()).all(|((index_field, index_value), (field, value))| {
+            index_field == field && same_key_type(index_value, value)
+        })
+}
+
+/// Returns the value at a dotted path of a document, without descending into arrays.
+pub fn value_at<'a>(doc: &'a bson::Document, path: &str) -> Option<&'a Bson> {
+    let mut parts = path.split('.');
+    let mut value = doc.get(parts.next()?)?;
+
+    for part in parts {
+        value = match *value {
+            Bson::Document(ref doc) => doc.get(part)?,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+/// Returns true if values of this type increase as documents are inserted.
+pub fn is_monotonic(value: &Bson) -> bool {
+    match *value {
+        Bson::ObjectId(_) | Bson::UtcDatetime(_) | Bson::TimeStamp(_) => true,
+        _ => false,
+    }
+}
+
+fn is_ascending(value: &Bson) -> bool {
+    match *value {
+        Bson::I32(n) => n == 1,
+        Bson::I64(n) => n == 1,
+        Bson::FloatingPoint(n) => n == 1.0,
+        _ => false,
+    }
+}
+
+fn same_key_type(index_value: &Bson, key_value: &Bson) -> bool {
+    match (index_value, key_value) {
+        (&Bson::String(ref a), &Bson::String(ref b)) => a == b,
+        (a, b) => is_ascending(a) && is_ascending(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{self, bson, doc, Bson};
+    use bson::oid::ObjectId;
+    use super::{check_shard_key, is_monotonic, supports_shard_key, value_at, ShardKeyAnalysis};
+
+    fn analysis(distinct_values: i64, max_frequency: i64) -> ShardKeyAnalysis {
+        ShardKeyAnalysis {
+            key: doc! { "a": 1 },
+            index: Some(String::from("a_1")),
+            index_created: false,
+            document_count: 1000,
+            sampled_count: 100,
+            distinct_values: distinct_values,
+            max_frequency: max_frequency,
+            monotonic: false,
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn checks_shard_keys() {
+        assert!(check_shard_key(&doc! { "a": 1, "b": "hashed" }).is_ok());
+        assert!(check_shard_key(&doc! { "a": 1.0 }).is_ok());
+        assert!(check_shard_key(&doc! {}).is_err());
+        assert!(check_shard_key(&doc! { "a": -1 }).is_err());
+        assert!(check_shard_key(&doc! { "a": "text" }).is_err());
+        assert!(check_shard_key(&doc! { "a": "hashed", "b": "hashed" }).is_err());
+    }
+
+    #[test]
+    fn finds_supporting_indexes() {
+        let key = doc! { "a": 1, "b": 1 };
+        let index = |pattern: bson::Document| doc! { "name": "index", "key": pattern };
+
+        assert!(supports_shard_key(&index(doc! { "a": 1, "b": 1 }), &key));
+        assert!(supports_shard_key(&index(doc! { "a": 1, "b": 1, "c": -1 }), &key));
+        assert!(!supports_shard_key(&index(doc! { "a": 1 }), &key));
+        assert!(!supports_shard_key(&index(doc! { "b": 1, "a": 1 }), &key));
+        assert!(!supports_shard_key(&index(doc! { "a": 1, "b": -1 }), &key));
+        assert!(supports_shard_key(&index(doc! { "h": "hashed" }), &doc! { "h": "hashed" }));
+        assert!(!supports_shard_key(&index(doc! { "h": 1 }), &doc! { "h": "hashed" }));
+
+        let mut partial = index(doc! { "a": 1, "b": 1 });
+        partial.insert("partialFilterExpression", doc! { "a": { "$gt": 1 } });
+        assert!(!supports_shard_key(&partial, &key));
+
+        let mut collated = index(doc! { "a": 1, "b": 1 });
+        collated.insert("collation", doc! { "locale": "fr" });
+        assert!(!supports_shard_key(&collated, &key));
+    }
+
+    #[test]
+    fn finds_values_at_paths() {
+        let doc = doc! { "a": { "b": { "c": 1 } }, "d": [{ "e": 2 }] };
+        assert_eq!(value_at(&doc, "a.b.c"), Some(&Bson::I32(1)));
+        assert_eq!(value_at(&doc, "a.x"), None);
+        assert_eq!(value_at(&doc, "d.e"), None);
+    }
+
+    #[test]
+    fn detects_monotonic_values() {
+        assert!(is_monotonic(&Bson::ObjectId(ObjectId::new().unwrap())));
+        assert!(is_monotonic(&Bson::TimeStamp(1)));
+        assert!(!is_monotonic(&Bson::I32(1)));
+        assert!(!is_monotonic(&Bson::String(String::from("a"))));
+    }
+
+    #[test]
+    fn warns_about_poor_keys() {
+        let mut good = analysis(90, 2);
+        good.add_warnings(50);
+        assert!(good.warnings.is_empty());
+        assert!(good.has_enough_cardinality(50));
+
+        let mut poor = analysis(3, 60);
+        poor.index = None;
+        poor.monotonic = true;
+        poor.add_warnings(50);
+        assert!(!poor.has_enough_cardinality(50));
+        assert_eq!(poor.warnings.len(), 4);
+        assert!(poor.warnings[2].starts_with("60% of sampled documents"));
+
+        // A collection smaller than the minimum only needs a value per document.
+        let mut small = analysis(10, 1);
+        small.document_count = 10;
+        small.sampled_count = 10;
+        assert!(small.has_enough_cardinality(50));
+    }
+}
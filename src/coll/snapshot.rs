@@ -0,0 +1,309 @@
+//! A read-only copy of a small collection held in memory, as returned by
+//! `Collection::load_snapshot`.
+//!
+//! Collections of configuration data are typically small, rarely written and looked up by one
+//! or two fields on every request. A snapshot reads the whole collection once and answers such
+//! lookups from memory, through an index on the key fields. It is never written back, and only
+//! sees changes made on the server when it is refreshed.
+use bson::{self, Bson, bson, doc};
+use chrono::{DateTime, Utc};
+
+use super::Collection;
+use super::options::SnapshotOptions;
+use super::shard_key::value_at;
+use Error::{ArgumentError, OperationError};
+use Result;
+
+use std::collections::HashMap;
+
+/// The largest number of documents a snapshot holds unless the options allow more.
+pub const DEFAULT_MAX_SNAPSHOT_DOCUMENTS: usize = 10000;
+
+/// A read-only copy of a collection, indexed by the values of its key fields.
+///
+/// Documents can't be inserted, changed or removed through the snapshot; `refresh` is the only
+/// way its contents change.
+#[derive(Clone, Debug)]
+pub struct SnapshotCache {
+    coll: Collection,
+    key_fields: Vec<String>,
+    options: SnapshotOptions,
+    docs: Vec<bson::Document>,
+    // The positions of the documents with each combination of key values.
+    index: HashMap<Vec<String>, Vec<usize>>,
+    // The latest value of the `updated_at_field` option among the documents.
+    last_updated: Option<Bson>,
+    loaded_at: DateTime<Utc>,
+}
+
+impl SnapshotCache {
+    /// Reads every document of the collection matching the `filter` option, and indexes them
+    /// by the values of `key_fields`, which may be dotted paths. Fails if the collection has
+    /// more documents than the `max_documents` option allows.
+    pub fn load(
+        coll: Collection,
+        key_fields: &[&str],
+        options: Option<SnapshotOptions>,
+    ) -> Result<SnapshotCache> {
+        if key_fields.is_empty() {
+            return Err(ArgumentError(String::from("A snapshot needs at least one key field.")));
+        }
+
+        let mut snapshot = SnapshotCache {
+            coll: coll,
+            key_fields: key_fields.iter().map(|field| String::from(*field)).collect(),
+            options: options.unwrap_or_default(),
+            docs: Vec::new(),
+            index: HashMap::new(),
+            last_updated: None,
+            loaded_at: Utc::now(),
+        };
+
+        snapshot.docs = snapshot.read(None)?;
+        snapshot.reindex();
+        Ok(snapshot)
+    }
+
+    /// Returns a document whose key fields have the given values, in the order of the key
+    /// fields.
+    pub fn get(&self, key: &[Bson]) -> Option<&bson::Document> {
+        self.get_all(key).into_iter().next()
+    }
+
+    /// Returns every document whose key fields have the given values.
+    pub fn get_all(&self, key: &[Bson]) -> Vec<&bson::Document> {
+        if key.len() != self.key_fields.len() {
+            return Vec::new();
+        }
+
+        let key: Vec<_> = key.iter().map(key_value).collect();
+        match self.index.get(&key) {
+            Some(positions) => positions.iter().map(|&i| &self.docs[i]).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the documents matching a filter of exact values for some or all of the key
+    /// fields, e.g. `{ "app": "billing" }` for a snapshot keyed on `app` and `env`. Filters on
+    /// other fields or with query operators are rejected, since the snapshot can't evaluate
+    /// them.
+    pub fn find_local(&self, filter: &bson::Document) -> Result<Vec<&bson::Document>> {
+        let mut wanted = Vec::with_capacity(filter.len());
+
+        for (field, value) in filter {
+            let position = match self.key_fields.iter().position(|key| key == field) {
+                Some(position) => position,
+                None => {
+                    return Err(ArgumentError(format!(
+                        "A snapshot can only be filtered on its key fields {:?}, not on {:?}.",
+                        self.key_fields,
+                        field
+                    )))
+                }
+            };
+
+            if is_operator(value) {
+                return Err(ArgumentError(format!(
+                    "A snapshot can only match exact values of {:?}, not {}.",
+                    field,
+                    value
+                )));
+            }
+            wanted.push((position, key_value(value)));
+        }
+
+        // A filter on every key field is a single lookup.
+        if wanted.len() == self.key_fields.len() {
+            let mut key = vec![String::new(); wanted.len()];
+            for (position, value) in wanted {
+                key[position] = value;
+            }
+            return Ok(match self.index.get(&key) {
+                Some(positions) => positions.iter().map(|&i| &self.docs[i]).collect(),
+                None => Vec::new(),
+            });
+        }
+
+        let mut found = Vec::new();
+        for (key, positions) in &self.index {
+            if wanted.iter().all(|&(position, ref value)| key[position] == *value) {
+                found.extend(positions.iter().map(|&i| &self.docs[i]));
+            }
+        }
+        Ok(found)
+    }
+
+    /// Brings the snapshot up to date with the collection, returning the number of documents
+    /// read. With the `updated_at_field` option, only the documents updated since the latest
+    /// update seen are read and replace their earlier copies by `_id`; documents deleted from
+    /// the collection stay in the snapshot until it is loaded again. Otherwise, the whole
+    /// collection is read again.
+    pub fn refresh(&mut self) -> Result<usize> {
+        let refreshed_at = Utc::now();
+
+        let since = match (self.options.updated_at_field.as_ref(), self.last_updated.as_ref()) {
+            (Some(field), Some(last_updated)) => {
+                let mut since = bson::Document::new();
+                since.insert(field.to_owned(), doc! { "$gte": last_updated.clone() });
+                Some(since)
+            }
+            _ => None,
+        };
+
+        let read = match since {
+            Some(since) => {
+                let changed = self.read(Some(since))?;
+                let count = changed.len();
+                self.apply(changed)?;
+                count
+            }
+            None => {
+                self.docs = self.read(None)?;
+                self.docs.len()
+            }
+        };
+
+        self.reindex();
+        self.loaded_at = refreshed_at;
+        Ok(read)
+    }
+
+    /// Returns the fields the snapshot is indexed by.
+    pub fn key_fields(&self) -> &[String] {
+        &self.key_fields
+    }
+
+    /// Returns every document of the snapshot, which can't be modified.
+    pub fn documents(&self) -> &[bson::Document] {
+        &self.docs
+    }
+
+    /// Returns the number of documents in the snapshot.
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    /// Returns true if the snapshot holds no documents.
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Returns when the snapshot was loaded or last refreshed, as the time the read started.
+    pub fn loaded_at(&self) -> DateTime<Utc> {
+        self.loaded_at
+    }
+
+    // Reads the documents matching the `filter` option and `extra`, failing once there are
+    // more than the snapshot may hold.
+    fn read(&self, extra: Option<bson::Document>) -> Result<Vec<bson::Document>> {
+        let max = self.options.max_documents.unwrap_or(DEFAULT_MAX_SNAPSHOT_DOCUMENTS);
+
+        let filter = match (self.options.filter.clone(), extra) {
+            (Some(filter), Some(extra)) => Some(doc! { "$and": [filter, extra] }),
+            (filter, None) => filter,
+            (None, extra) => extra,
+        };
+
+        let mut docs = Vec::new();
+        for doc in self.coll.find(filter, None)? {
+            if docs.len() == max {
+                return Err(OperationError(format!(
+                    "Collection {} has more than {} documents, the most a snapshot may hold.",
+                    self.coll.namespace,
+                    max
+                )));
+            }
+            docs.push(doc?);
+        }
+        Ok(docs)
+    }
+
+    // Replaces the documents with the same `_id` as the changed ones, and adds the others.
+    fn apply(&mut self, changed: Vec<bson::Document>) -> Result<()> {
+        let mut positions: HashMap<String, usize> = self.docs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, doc)| doc.get("_id").map(|id| (key_value(id), i)))
+            .collect();
+
+        for doc in changed {
+            let id = match doc.get("_id") {
+                Some(id) => key_value(id),
+                None => {
+                    self.docs.push(doc);
+                    continue;
+                }
+            };
+
+            match positions.get(&id) {
+                Some(&i) => self.docs[i] = doc,
+                None => {
+                    positions.insert(id, self.docs.len());
+                    self.docs.push(doc);
+                }
+            }
+        }
+
+        let max = self.options.max_documents.unwrap_or(DEFAULT_MAX_SNAPSHOT_DOCUMENTS);
+        if self.docs.len() > max {
+            return Err(OperationError(format!(
+                "Collection {} has more than {} documents, the most a snapshot may hold.",
+                self.coll.namespace,
+                max
+            )));
+        }
+        Ok(())
+    }
+
+    fn reindex(&mut self) {
+        self.index.clear();
+        self.last_updated = None;
+
+        for (i, doc) in self.docs.iter().enumerate() {
+            // Documents missing a key field are indexed under null, as the server would.
+            let key = self.key_fields
+                .iter()
+                .map(|field| key_value(value_at(doc, field).unwrap_or(&Bson::Null)))
+                .collect();
+            self.index.entry(key).or_insert_with(Vec::new).push(i);
+
+            if let Some(ref field) = self.options.updated_at_field {
+                if let Some(updated) = value_at(doc, field) {
+                    if self.last_updated.as_ref().map_or(true, |last| is_later(updated, last)) {
+                        self.last_updated = Some(updated.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The form of a value used to look it up in the index, under which numbers of different types
+// with the same value are equal.
+fn key_value(value: &Bson) -> String {
+    match *value {
+        Bson::I32(n) => n.to_string(),
+        Bson::I64(n) => n.to_string(),
+        Bson::FloatingPoint(n) if n.fract() == 0.0 && n.abs() < 1e15 => (n as i64).to_string(),
+        ref value => value.to_string(),
+    }
+}
+
+fn is_operator(value: &Bson) -> bool {
+    match *value {
+        Bson::Document(ref doc) => doc.keys().any(|key| key.starts_with('$')),
+        _ => false,
+    }
+}
+
+// Returns true if an update time is later than another of the same type.
+fn is_later(value: &Bson, than: &Bson) -> bool {
+    match (value, than) {
+        (&Bson::UtcDatetime(a), &Bson::UtcDatetime(b)) => a > b,
+        (&Bson::TimeStamp(a), &Bson::TimeStamp(b)) => a > b,
+        (&Bson::I32(a), &Bson::I32(b)) => a > b,
+        (&Bson::I64(a), &Bson::I64(b)) => a > b,
+        (&Bson::FloatingPoint(a), &Bson::FloatingPoint(b)) => a > b,
+        _ => false,
+    }
+}
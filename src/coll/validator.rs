@@ -0,0 +1,67 @@
+//! Application-defined checks run on documents before they are written.
+use bson::{self, Bson};
+
+use std::fmt;
+use std::result;
+use std::sync::Arc;
+
+/// Checks a document before it is written, returning the reason it is rejected.
+pub type WriteValidator = Arc<dyn Fn(&bson::Document) -> result::Result<(), String> + Send + Sync>;
+
+/// The write validators registered on a database or collection, run in the order they were
+/// registered.
+#[derive(Clone, Default)]
+pub struct WriteValidators {
+    validators: Vec<WriteValidator>,
+}
+
+impl fmt::Debug for WriteValidators {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WriteValidators")
+            .field("len", &self.validators.len())
+            .finish()
+    }
+}
+
+impl WriteValidators {
+    /// Creates an empty list of validators.
+    pub fn new() -> WriteValidators {
+        Default::default()
+    }
+
+    /// Adds a validator, to be run after the ones already registered.
+    pub fn push(&mut self, validator: WriteValidator) {
+        self.validators.push(validator);
+    }
+
+    /// Returns true if no validators are registered.
+    pub fn is_empty(&self) -> bool {
+        self.validators.is_empty()
+    }
+
+    /// Runs every validator over a written document, stopping at the first rejection.
+    ///
+    /// Documents made of update operators are validated through the payloads of their `$set`
+    /// and `$setOnInsert` operators, since those are the only fields they write verbatim.
+    pub fn validate(&self, doc: &bson::Document) -> result::Result<(), String> {
+        if !doc.keys().any(|key| key.starts_with('$')) {
+            return self.run(doc);
+        }
+
+        for operator in &["$set", "$setOnInsert"] {
+            if let Some(&Bson::Document(ref payload)) = doc.get(operator) {
+                self.run(payload)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn run(&self, doc: &bson::Document) -> result::Result<(), String> {
+        for validator in &self.validators {
+            validator(doc)?;
+        }
+
+        Ok(())
+    }
+}
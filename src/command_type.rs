@@ -1,14 +1,20 @@
 //! Monitorable command types.
+use bson::{self, Bson};
 
 /// Executable command types that can be monitored by the driver.
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum CommandType {
+    AbortTransaction,
     Aggregate,
     BuildInfo,
+    CloneCollection,
+    CommitTransaction,
     Count,
     CreateCollection,
     CreateIndexes,
     CreateUser,
+    CurrentOp,
+    DataSize,
     DeleteMany,
     DeleteOne,
     Distinct,
@@ -17,32 +23,59 @@ pub enum CommandType {
     DropDatabase,
     DropIndexes,
     DropUser,
+    EnableSharding,
+    EndSessions,
+    Eval,
     Find,
     FindOneAndDelete,
     FindOneAndReplace,
     FindOneAndUpdate,
+    Fsync,
+    FsyncUnlock,
+    GetLastError,
+    GetPrevError,
     GetUser,
     GetUsers,
     InsertMany,
     InsertOne,
     IsMaster,
+    KillAllSessionsByPattern,
+    KillCursors,
+    KillOp,
+    KillSessions,
     ListCollections,
     ListDatabases,
     ListIndexes,
+    ParallelCollectionScan,
+    Ping,
+    Profile,
+    RefreshSessions,
+    ReplSetFreeze,
+    ReplSetGetConfig,
+    ReplSetGetStatus,
+    ReplSetStepDown,
+    ResetError,
+    ShardCollection,
     Suppressed,
     UpdateMany,
     UpdateOne,
+    Validate,
 }
 
 impl CommandType {
     pub fn to_str(&self) -> &str {
         match *self {
+            CommandType::AbortTransaction => "abort_transaction",
             CommandType::Aggregate => "aggregate",
             CommandType::BuildInfo => "buildinfo",
+            CommandType::CloneCollection => "clone_collection",
+            CommandType::CommitTransaction => "commit_transaction",
             CommandType::Count => "count",
             CommandType::CreateCollection => "create_collection",
             CommandType::CreateIndexes => "create_indexes",
             CommandType::CreateUser => "create_user",
+            CommandType::CurrentOp => "current_op",
+            CommandType::DataSize => "data_size",
             CommandType::DeleteMany => "delete_many",
             CommandType::DeleteOne => "delete_one",
             CommandType::Distinct => "distinct",
@@ -51,26 +84,121 @@ impl CommandType {
             CommandType::DropDatabase => "drop_database",
             CommandType::DropIndexes => "drop_indexes",
             CommandType::DropUser => "drop_user",
+            CommandType::EnableSharding => "enable_sharding",
+            CommandType::EndSessions => "end_sessions",
+            CommandType::Eval => "eval",
             CommandType::Find => "find",
             CommandType::FindOneAndDelete => "find_one_and_delete",
             CommandType::FindOneAndReplace => "find_one_and_replace",
             CommandType::FindOneAndUpdate => "find_one_and_update",
+            CommandType::Fsync => "fsync",
+            CommandType::FsyncUnlock => "fsync_unlock",
+            CommandType::GetLastError => "get_last_error",
+            CommandType::GetPrevError => "get_prev_error",
             CommandType::GetUser => "get_user",
             CommandType::GetUsers => "get_users",
             CommandType::InsertMany => "insert_many",
             CommandType::InsertOne => "insert_one",
             CommandType::IsMaster => "is_master",
+            CommandType::KillAllSessionsByPattern => "kill_all_sessions_by_pattern",
+            CommandType::KillCursors => "kill_cursors",
+            CommandType::KillOp => "kill_op",
+            CommandType::KillSessions => "kill_sessions",
             CommandType::ListCollections => "list_collections",
             CommandType::ListDatabases => "list_databases",
             CommandType::ListIndexes => "list_indexes",
+            CommandType::ParallelCollectionScan => "parallel_collection_scan",
+            CommandType::Ping => "ping",
+            CommandType::Profile => "profile",
+            CommandType::RefreshSessions => "refresh_sessions",
+            CommandType::ReplSetFreeze => "repl_set_freeze",
+            CommandType::ReplSetGetConfig => "repl_set_get_config",
+            CommandType::ReplSetGetStatus => "repl_set_get_status",
+            CommandType::ReplSetStepDown => "repl_set_step_down",
+            CommandType::ResetError => "reset_error",
+            CommandType::ShardCollection => "shard_collection",
             CommandType::Suppressed => "suppressed",
             CommandType::UpdateMany => "update_many",
             CommandType::UpdateOne => "update_one",
+            CommandType::Validate => "validate",
+        }
+    }
+
+    /// Returns true if sending the command a second time after a failure cannot change
+    /// the outcome of the first successful execution.
+    pub fn is_idempotent(&self) -> bool {
+        match *self {
+            CommandType::CreateIndexes |
+            CommandType::DeleteMany |
+            CommandType::DropCollection |
+            CommandType::DropDatabase |
+            CommandType::DropIndexes => true,
+            _ => !self.is_write_command(),
+        }
+    }
+
+    /// Returns true if the command modifies at most one document per statement, so that it can
+    /// be retried safely using a transaction number.
+    pub fn is_retryable_write(&self) -> bool {
+        match *self {
+            CommandType::DeleteOne |
+            CommandType::FindOneAndDelete |
+            CommandType::FindOneAndReplace |
+            CommandType::FindOneAndUpdate |
+            CommandType::InsertMany |
+            CommandType::InsertOne |
+            CommandType::UpdateOne => true,
+            _ => false,
+        }
+    }
+
+    /// Returns true if the given command can be retried with a transaction number. Beyond
+    /// `is_retryable_write`, unacknowledged writes are excluded, since their outcome is never
+    /// known, and `insert_many` batches are only retried when they insert a single document.
+    pub fn is_retryable_write_command(&self, command: &bson::Document) -> bool {
+        if !self.is_retryable_write() {
+            return false;
+        }
+
+        if let Ok(wc) = command.get_document("writeConcern") {
+            let w_zero = match wc.get("w") {
+                Some(&Bson::I32(0)) | Some(&Bson::I64(0)) => true,
+                _ => false,
+            };
+            let j = wc.get_bool("j").unwrap_or(false);
+            let fsync = wc.get_bool("fsync").unwrap_or(false);
+
+            if w_zero && !j && !fsync {
+                return false;
+            }
+        }
+
+        match *self {
+            CommandType::InsertMany => {
+                command.get_array("documents").map_or(true, |documents| documents.len() <= 1)
+            }
+            _ => true,
+        }
+    }
+
+    /// Returns true if the command only reads, so that it can be retried once on another
+    /// server when it fails before returning any documents.
+    pub fn is_retryable_read(&self) -> bool {
+        match *self {
+            CommandType::Aggregate |
+            CommandType::Count |
+            CommandType::Distinct |
+            CommandType::Find |
+            CommandType::ListCollections => true,
+            _ => false,
         }
     }
 
     pub fn is_write_command(&self) -> bool {
         match *self {
+            CommandType::AbortTransaction |
+            CommandType::CloneCollection |
+            CommandType::CommitTransaction |
             CommandType::CreateCollection |
             CommandType::CreateIndexes |
             CommandType::CreateUser |
@@ -81,25 +209,49 @@ impl CommandType {
             CommandType::DropDatabase |
             CommandType::DropIndexes |
             CommandType::DropUser |
+            CommandType::EnableSharding |
+            CommandType::Eval |
             CommandType::FindOneAndDelete |
             CommandType::FindOneAndReplace |
             CommandType::FindOneAndUpdate |
             CommandType::InsertMany |
             CommandType::InsertOne |
+            CommandType::ShardCollection |
             CommandType::UpdateMany |
             CommandType::UpdateOne => true,
             CommandType::Aggregate |
             CommandType::BuildInfo |
             CommandType::Count |
+            CommandType::CurrentOp |
+            CommandType::DataSize |
             CommandType::Distinct |
+            CommandType::EndSessions |
             CommandType::Find |
+            CommandType::Fsync |
+            CommandType::FsyncUnlock |
+            CommandType::GetLastError |
+            CommandType::GetPrevError |
             CommandType::GetUser |
             CommandType::GetUsers |
             CommandType::IsMaster |
+            CommandType::KillAllSessionsByPattern |
+            CommandType::KillCursors |
+            CommandType::KillOp |
+            CommandType::KillSessions |
             CommandType::ListCollections |
             CommandType::ListDatabases |
             CommandType::ListIndexes |
-            CommandType::Suppressed => false,
+            CommandType::ParallelCollectionScan |
+            CommandType::Ping |
+            CommandType::Profile |
+            CommandType::RefreshSessions |
+            CommandType::ReplSetFreeze |
+            CommandType::ReplSetGetConfig |
+            CommandType::ReplSetGetStatus |
+            CommandType::ReplSetStepDown |
+            CommandType::ResetError |
+            CommandType::Suppressed |
+            CommandType::Validate => false,
         }
     }
 }
@@ -5,6 +5,7 @@ use Result;
 use bson::{self, bson, Bson, doc};
 use std::collections::BTreeMap;
 use std::str::FromStr;
+use std::time::Duration;
 
 /// Indicates how a server should be selected during read operations.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -40,6 +41,13 @@ pub struct ReadPreference {
     pub mode: ReadMode,
     /// Filters servers based on the first tag set that matches at least one server.
     pub tag_sets: Vec<BTreeMap<String, String>>,
+    /// Excludes secondaries whose estimated replication lag exceeds this many seconds. Must be
+    /// at least 90 seconds, and cannot be used with the primary read mode.
+    pub max_staleness_seconds: Option<i64>,
+    /// Sends `Nearest` reads to the two nearest members of a replica set at once and keeps the
+    /// first reply, trading extra load for lower tail latency. Routers are asked to hedge reads
+    /// themselves. Cannot be used with other read modes.
+    pub hedge: bool,
 }
 
 impl ReadPreference {
@@ -47,6 +55,22 @@ impl ReadPreference {
         ReadPreference {
             mode: mode,
             tag_sets: tag_sets.unwrap_or_else(Vec::new),
+            max_staleness_seconds: None,
+            hedge: false,
+        }
+    }
+
+    /// Returns a copy of the read preference that prefers the primary. Modes that may read
+    /// from a secondary become `PrimaryPreferred`; the tag sets apply when falling back.
+    pub fn prefer_primary(&self) -> ReadPreference {
+        match self.mode {
+            ReadMode::Secondary | ReadMode::SecondaryPreferred | ReadMode::Nearest => {
+                ReadPreference {
+                    mode: ReadMode::PrimaryPreferred,
+                    ..self.clone()
+                }
+            }
+            ReadMode::Primary | ReadMode::PrimaryPreferred => self.clone(),
         }
     }
 
@@ -64,11 +88,81 @@ impl ReadPreference {
             .collect();
 
         doc.insert("tag_sets", Bson::Array(bson_tag_sets));
+
+        if let Some(max_staleness_seconds) = self.max_staleness_seconds {
+            doc.insert("maxStalenessSeconds", max_staleness_seconds);
+        }
+
+        if self.hedge {
+            doc.insert("hedge", doc! { "enabled": true });
+        }
+
         doc
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+/// Describes the consistency and isolation properties of the data read from the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ReadConcern {
+    Local,
+    Majority,
+    Linearizable,
+    Available,
+    Snapshot,
+}
+
+impl ReadConcern {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            ReadConcern::Local => "local",
+            ReadConcern::Majority => "majority",
+            ReadConcern::Linearizable => "linearizable",
+            ReadConcern::Available => "available",
+            ReadConcern::Snapshot => "snapshot",
+        }
+    }
+
+    pub fn to_document(&self) -> bson::Document {
+        doc! { "level": self.as_str() }
+    }
+
+    /// Checks that the read concern can be used by an operation outside of a transaction with
+    /// the given read preference.
+    pub fn validate(&self, read_preference: &ReadPreference) -> Result<()> {
+        match *self {
+            ReadConcern::Snapshot => Err(ArgumentError(String::from(
+                "The snapshot read concern can only be used in a transaction.",
+            ))),
+            ReadConcern::Linearizable if read_preference.mode != ReadMode::Primary => {
+                Err(ArgumentError(String::from(
+                    "The linearizable read concern can only be used with a primary read \
+                     preference.",
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl FromStr for ReadConcern {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "local" => ReadConcern::Local,
+            "majority" => ReadConcern::Majority,
+            "linearizable" => ReadConcern::Linearizable,
+            "available" => ReadConcern::Available,
+            "snapshot" => ReadConcern::Snapshot,
+            _ => {
+                return Err(ArgumentError(
+                    format!("Could not convert '{}' to ReadConcern.", s),
+                ))
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct WriteConcern {
     /// Write replication
     pub w: i32,
@@ -90,6 +184,12 @@ impl WriteConcern {
         }
     }
 
+    /// Returns true if the server doesn't report the outcome of writes made with this write
+    /// concern, so that they can be sent without waiting for a reply.
+    pub fn is_unacknowledged(&self) -> bool {
+        self.w == 0 && !self.j && !self.fsync
+    }
+
     pub fn to_bson(&self) -> bson::Document {
         doc! {
             "w": self.w,
@@ -105,6 +205,41 @@ impl Default for WriteConcern {
     }
 }
 
+/// Describes how an operation should be retried after transient replica set failures.
+///
+/// Only "not master" errors and network errors are retried; between attempts the driver asks
+/// every server monitor to refresh its view of the topology so that a newly elected primary can
+/// be discovered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RetryPolicy {
+    /// The maximum number of times the operation will be attempted, including the first attempt.
+    pub max_attempts: u32,
+    /// The delay before the second attempt, in milliseconds. Each subsequent attempt waits
+    /// twice as long as the previous one.
+    pub backoff_ms: u64,
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, backoff_ms: u64) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: max_attempts,
+            backoff_ms: backoff_ms,
+        }
+    }
+
+    /// Returns how long to wait after the given (1-indexed) failed attempt.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u64.checked_shl(attempt.saturating_sub(1)).unwrap_or(::std::u64::MAX);
+        Duration::from_millis(self.backoff_ms.saturating_mul(factor))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(3, 500)
+    }
+}
+
 pub fn merge_options<T: Into<bson::Document>>(
     document: bson::Document,
     options: T,
@@ -1,13 +1,69 @@
 //! Connection string parsing and options.
 use Result;
-use Error::ArgumentError;
+use Error::{self, ArgumentError, DNSLookupError};
 use std::collections::BTreeMap;
+use std::fmt;
 use trust_dns_resolver::Resolver;
+use trust_dns_resolver::error::ResolveErrorKind;
 
 pub const DEFAULT_PORT: u16 = 27017;
 pub const URI_SCHEME: &str = "mongodb://";
 pub const URI_SCHEME_DNS_SEEDLIST: &str = "mongodb+srv://";
 
+// The only options that may be specified in the TXT record of a `mongodb+srv://` seed hostname.
+const TXT_OPTIONS: [&str; 2] = ["authSource", "replicaSet"];
+
+/// Looks up the DNS records used to discover the hosts of a `mongodb+srv://` connection string.
+pub trait SrvResolver {
+    /// Returns the target hostname and port of every SRV record with the given name.
+    fn lookup_srv(&self, name: &str) -> Result<Vec<(String, u16)>>;
+    /// Returns the text of every TXT record with the given name, or an empty list if there are
+    /// none.
+    fn lookup_txt(&self, name: &str) -> Result<Vec<String>>;
+}
+
+impl fmt::Debug for SrvResolver + Send + Sync {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SrvResolver { .. }")
+    }
+}
+
+/// Resolves records using the system DNS configuration.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl SrvResolver for SystemResolver {
+    fn lookup_srv(&self, name: &str) -> Result<Vec<(String, u16)>> {
+        let srv_lookup = Resolver::from_system_conf()?.lookup_srv(name)?;
+        Ok(srv_lookup
+            .iter()
+            .map(|srv| (srv.target().to_utf8(), srv.port()))
+            .collect())
+    }
+
+    fn lookup_txt(&self, name: &str) -> Result<Vec<String>> {
+        let txt_lookup = match Resolver::from_system_conf()?.txt_lookup(name) {
+            Ok(txt_lookup) => txt_lookup,
+            Err(err) => {
+                return match *err.kind() {
+                    ResolveErrorKind::NoRecordsFound { .. } => Ok(Vec::new()),
+                    _ => Err(Error::from(err)),
+                }
+            }
+        };
+
+        Ok(txt_lookup
+            .iter()
+            .map(|txt| {
+                txt.txt_data()
+                    .iter()
+                    .map(|data| String::from_utf8_lossy(data))
+                    .collect()
+            })
+            .collect())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct DNS {
     pub name: String,
@@ -22,13 +78,85 @@ impl DNS {
         }
     }
 
-    pub fn discover_hosts(&mut self) -> Result<()> {
-        let host = format!("_mongodb._tcp.{}", self.name);
-        let srv_lookup = Resolver::from_system_conf()?.lookup_srv(&host)?;
-        for srv in srv_lookup {
-            self.discovered_hosts.push(Host::new(srv.target().to_utf8(), srv.port()));
+    /// Resolves the SRV record of the seed hostname into the host list, and returns the options
+    /// specified in its TXT record.
+    ///
+    /// Every discovered host must belong to the parent domain of the seed hostname, and the TXT
+    /// record may only specify `authSource` and `replicaSet`.
+    pub fn discover_hosts<R: SrvResolver + ?Sized>(
+        &mut self,
+        resolver: &R,
+    ) -> Result<ConnectionOptions> {
+        let srv_name = format!("_mongodb._tcp.{}", self.name);
+        let records = resolver
+            .lookup_srv(&srv_name)
+            .map_err(|err| DNSLookupError(srv_name.clone(), Box::new(err)))?;
+
+        if records.is_empty() {
+            return Err(DNSLookupError(
+                srv_name,
+                Box::new(ArgumentError(String::from("No SRV records found."))),
+            ));
         }
-        Ok(())
+
+        let name = self.name.to_ascii_lowercase();
+        let parent_domain = match name.find('.') {
+            Some(idx) => &name[idx..],
+            None => &name[..],
+        };
+
+        let mut hosts = Vec::with_capacity(records.len());
+        for (target, port) in records {
+            let target = target.trim_end_matches('.').to_ascii_lowercase();
+            if !target.ends_with(parent_domain) {
+                return Err(ArgumentError(format!(
+                    "Host '{}' returned by the SRV record of '{}' is not in the parent domain \
+                     '{}'.",
+                    target,
+                    self.name,
+                    &parent_domain[1..]
+                )));
+            }
+            hosts.push(Host::new(target, port));
+        }
+
+        let records = resolver
+            .lookup_txt(&self.name)
+            .map_err(|err| DNSLookupError(self.name.clone(), Box::new(err)))?;
+
+        let options = match records.len() {
+            0 => ConnectionOptions::default(),
+            1 => {
+                let options = split_options(&records[0])?;
+                if let Some(key) = options
+                    .options
+                    .keys()
+                    .find(|key| !TXT_OPTIONS.contains(&key.as_str()))
+                {
+                    return Err(ArgumentError(format!(
+                        "Option '{}' is not allowed in the TXT record of '{}'.",
+                        key,
+                        self.name
+                    )));
+                }
+                if !options.read_pref_tags.is_empty() {
+                    return Err(ArgumentError(format!(
+                        "Option 'readPreferenceTags' is not allowed in the TXT record of '{}'.",
+                        self.name
+                    )));
+                }
+                options
+            }
+            _ => {
+                return Err(ArgumentError(format!(
+                    "Found more than one TXT record for '{}'.",
+                    self.name
+                )))
+            }
+        };
+
+        self.discovered_hosts = hosts;
+        Ok(options)
     }
 }
 
@@ -64,6 +192,15 @@ impl Host {
     pub fn has_ipc(&self) -> bool {
         !self.ipc.is_empty()
     }
+
+    /// Returns the address of the host as `host:port`, or its socket path for IPC hosts.
+    pub fn address(&self) -> String {
+        if self.has_ipc() {
+            self.ipc.to_owned()
+        } else {
+            format!("{}:{}", self.host_name, self.port)
+        }
+    }
 }
 
 /// Encapsulates the options and read preference tags of a MongoDB connection.
@@ -146,6 +283,24 @@ impl ConnectionString {
         ConnectionString::with_host(host)
     }
 
+    /// Creates a new ConnectionString for a list of hosts, such as several mongos routers.
+    pub fn with_hosts(hosts: &[(&str, u16)]) -> ConnectionString {
+        let hosts = hosts
+            .iter()
+            .map(|&(host_name, port)| Host::new(String::from(host_name), port))
+            .collect();
+
+        ConnectionString {
+            hosts: ConnectionProtocol::Hosts(hosts),
+            string: None,
+            user: None,
+            password: None,
+            database: Some(String::from("test")),
+            collection: None,
+            options: None,
+        }
+    }
+
     fn with_host(host: Host) -> ConnectionString {
         ConnectionString {
             hosts: ConnectionProtocol::Hosts(vec![host]),
@@ -157,6 +312,35 @@ impl ConnectionString {
             options: None,
         }
     }
+
+    /// Discovers the hosts of a `mongodb+srv://` connection string using the system DNS
+    /// configuration. See `resolve_hosts_with_resolver`.
+    pub fn resolve_hosts(&mut self) -> Result<()> {
+        self.resolve_hosts_with_resolver(&SystemResolver)
+    }
+
+    /// Discovers the hosts of a `mongodb+srv://` connection string, and merges the options found
+    /// in the TXT record of the seed hostname into the connection options. Options specified in
+    /// the connection string take precedence. Does nothing for other connection strings.
+    pub fn resolve_hosts_with_resolver<R: SrvResolver + ?Sized>(
+        &mut self,
+        resolver: &R,
+    ) -> Result<()> {
+        let txt_options = match self.hosts {
+            ConnectionProtocol::DNS(ref mut dns) => dns.discover_hosts(resolver)?,
+            ConnectionProtocol::Hosts(_) => return Ok(()),
+        };
+
+        if !txt_options.options.is_empty() {
+            let mut options = self.options.take().unwrap_or_default();
+            for (key, value) in txt_options.options {
+                options.options.entry(key).or_insert(value);
+            }
+            self.options = Some(options);
+        }
+
+        Ok(())
+    }
 }
 
 /// Parses a MongoDB connection string URI as defined by
@@ -23,27 +23,78 @@
 //! }
 //! # }
 //! ```
-use {Client, CommandType, Error, ErrorCode, Result, ThreadedClient};
+use {acquire_cursor_stream, acquire_hedged_streams, acquire_stream_for, acquire_write_stream_for,
+     command_ok, pinned_to_primary, queue_cursor_kill, Client, CommandType, Error, ErrorCode,
+     OpenCursor, Result, ThreadedClient};
 use apm::{CommandStarted, CommandResult, EventRunner};
 
 use bson::{self, bson, doc, Bson};
 use common::{merge_options, ReadMode, ReadPreference};
-use coll::options::FindOptions;
+use coll::encryption::FieldEncryptor;
+use coll::options::{CursorType, FindOptions};
+use connstring::Host;
+use error::{query_failure_error, CommandFailure};
+use logging::LogLevel;
+use op_ctx::OpCtx;
 use pool::PooledStream;
+use routing::operation_namespace;
+use session::ClientSession;
 use time;
-use wire_protocol::flags::OpQueryFlags;
-use wire_protocol::operations::Message;
+use topology::capabilities::ServerCapabilities;
+use topology::server::ServerType;
+use wire_protocol::flags::{OpQueryFlags, OpReplyFlags};
+use wire_protocol::operations::{Message, ReplyDocuments};
 
-use std::{ i32, usize };
+use std::{ fmt, i32, io, usize };
 use std::mem::size_of;
 use std::collections::vec_deque::VecDeque;
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
 
 // Allows the server to decide the batch size.
 pub const DEFAULT_BATCH_SIZE: i32 = 0;
 
+// How long to wait before resending an operation a mongos rejected with a stale config error.
+const STALE_CONFIG_RETRY_DELAY_MS: u64 = 100;
+
+/// The server that answered a query, and the role it had when it was selected.
+#[derive(Clone, Debug, PartialEq)]
+pub struct QueryResultMeta {
+    pub host: Host,
+    pub server_type: ServerType,
+}
+
+impl QueryResultMeta {
+    /// Returns the address of the server as `host:port`.
+    pub fn address(&self) -> String {
+        self.host.address()
+    }
+
+    /// Returns whether the server was the primary of a replica set when it was selected.
+    pub fn is_primary(&self) -> bool {
+        self.server_type == ServerType::RSPrimary
+    }
+
+    /// Returns whether the server was a secondary of a replica set when it was selected.
+    pub fn is_secondary(&self) -> bool {
+        self.server_type == ServerType::RSSecondary
+    }
+}
+
+/// The position of the last batch the server returned for a cursor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchInfo {
+    /// The number of documents the server returned in earlier batches, i.e. the position of the
+    /// first document of the batch in the results.
+    pub starting_from: i64,
+    /// The number of documents in the batch.
+    pub number_returned: usize,
+}
+
 /// Maintains a connection to the server and lazily returns documents from a
 /// query.
-#[derive(Debug)]
 pub struct Cursor {
     // The client to read from.
     client: Client,
@@ -59,16 +110,68 @@ pub struct Cursor {
     count: i32,
     // A cache for documents received from the query that have not yet been returned.
     buffer: VecDeque<bson::Document>,
+    // Documents of the last reply that have not yet been returned, decoded as they are taken.
+    raw: ReplyDocuments,
+    read_preference: ReadPreference,
+    cmd_type: CommandType,
+    // The connection reserved by an exhaust query, over which the server streams further batches.
+    exhaust_stream: Option<PooledStream>,
+    // The id of the last reply received, which the next batch of an exhaust query responds to.
+    reply_id: i32,
+    // The comment attached to the originating operation, reported with every getMore and sent
+    // with it to servers that accept one.
+    comment: Option<String>,
+    // The server the cursor is open on, if known, which every getMore and kill is sent to.
+    host: Option<Host>,
+    // The role of that server when it was selected.
+    server_type: ServerType,
+    // Whether the cursor is tailable and waits for new documents on the server.
+    await_data: bool,
+    // How long each getMore waits for new documents, if limited.
+    max_await_time_ms: Option<i64>,
+    // Whether the next batch is requested in the background before the buffer runs out.
+    prefetch: bool,
+    // The reply to a getMore sent in the background, and whether it was sent as a command.
+    pending: Option<Receiver<Result<(Message, bool)>>>,
+    // How many documents the last batch held.
+    last_batch_len: usize,
+    // How many documents the batches before the last one held.
+    batch_start: i64,
+    // Decrypts the encrypted fields of the documents returned, if set.
+    decryptor: Option<Arc<FieldEncryptor>>,
+    // The context the cursor was opened with, which every getMore is sent within.
+    ctx: Option<OpCtx>,
+    // The fields tying every getMore to the logical session the cursor was opened under, and
+    // to its transaction if one was in progress.
+    session_fields: Option<bson::Document>,
+}
+
+// Everything needed to send a getMore, so that it can be sent from another thread.
+struct GetMore {
+    client: Client,
+    namespace: String,
+    cursor_id: i64,
+    batch_size: i32,
     read_preference: ReadPreference,
     cmd_type: CommandType,
+    comment: Option<String>,
+    max_await_time_ms: Option<i64>,
+    host: Option<Host>,
+    ctx: Option<OpCtx>,
+    session_fields: Option<bson::Document>,
 }
 
 macro_rules! try_or_emit {
-    ($cmd_type:expr, $cmd_name:expr, $req_id:expr, $connstring:expr, $result:expr, $client:expr) =>
+    ($cmd_type:expr, $cmd_name:expr, $req_id:expr, $connstring:expr, $host:expr, $result:expr,
+     $client:expr) =>
     {
         match $result {
             Ok(val) => val,
             Err(e) => {
+                $client.log(LogLevel::Error, "operation", || {
+                    format!("{} failed: {}", $cmd_name, e)
+                });
+
                 if $cmd_type != CommandType::Suppressed {
                     let hook_result = $client.run_completion_hooks(&CommandResult::Failure {
                         duration: 0,
@@ -76,6 +179,7 @@ macro_rules! try_or_emit {
                         failure: &e,
                         request_id: $req_id as i64,
                         connection_string: $connstring,
+                        host: $host,
                     });
 
                     if hook_result.is_err() {
@@ -109,11 +213,24 @@ impl Cursor {
         doc: bson::Document,
         cmd_type: CommandType,
         read_pref: ReadPreference,
+    ) -> Result<Cursor> {
+        Cursor::command_cursor_with_ctx(client, db, doc, cmd_type, read_pref, None)
+    }
+
+    /// Constructs a new Cursor for a database command like `command_cursor`, within `ctx` if
+    /// one is given.
+    pub fn command_cursor_with_ctx(
+        client: Client,
+        db: &str,
+        doc: bson::Document,
+        cmd_type: CommandType,
+        read_pref: ReadPreference,
+        ctx: Option<&OpCtx>,
     ) -> Result<Cursor> {
         let mut options = FindOptions::new();
         options.batch_size = Some(1);
 
-        Cursor::query(
+        Cursor::query_with_ctx(
             client.clone(),
             format!("{}.$cmd", db),
             OpQueryFlags::empty(),
@@ -122,36 +239,44 @@ impl Cursor {
             cmd_type,
             true,
             read_pref,
+            ctx,
         )
     }
 
+    // Decodes only the first document of a reply, which holds the error of a failed query or
+    // the result of a command; the rest are left to be decoded as the cursor reaches them.
     fn get_bson_and_cid_from_message(
         message: Message,
-    ) -> Result<(bson::Document, VecDeque<bson::Document>, i64)> {
+    ) -> Result<(Option<bson::Document>, ReplyDocuments, i64)> {
         match message {
             Message::OpReply {
                 cursor_id: cid,
-                documents: docs,
+                documents: mut docs,
+                flags,
                 ..
             } => {
-                let out_doc = if let Some(out_doc) = docs.get(0) {
-                    if let Some(&Bson::I32(code)) = out_doc.get("code") {
-                        // If command doesn't exist or namespace not found, return
-                        // an empty array instead of throwing an error.
-                        if code != ErrorCode::CommandNotFound as i32 &&
-                            code != ErrorCode::NamespaceNotFound as i32
-                        {
-                            if let Some(&Bson::String(ref msg)) = out_doc.get("errmsg") {
-                                return Err(Error::OperationError(msg.to_owned()));
-                            }
-                        }
-                    }
-                    out_doc.clone()
-                } else {
-                    bson::Document::new()
+                let out_doc = match docs.next() {
+                    Some(out_doc) => out_doc?,
+                    None => return Ok((None, docs, cid)),
                 };
 
-                Ok((out_doc, docs.into_iter().collect(), cid))
+                // Legacy queries report failures in a `$err` document.
+                if flags.contains(OpReplyFlags::QUERY_FAILURE) {
+                    return Err(query_failure_error(&out_doc));
+                }
+
+                if command_ok(&out_doc) == Some(false) {
+                    let failure = CommandFailure::from_reply(&out_doc);
+
+                    // If command doesn't exist or namespace not found, return
+                    // an empty array instead of throwing an error.
+                    let ignored = [ErrorCode::CommandNotFound, ErrorCode::NamespaceNotFound];
+                    if failure.code.is_some() && !failure.has_code(&ignored) {
+                        return Err(Error::CommandError(failure));
+                    }
+                }
+
+                Ok((Some(out_doc), docs, cid))
             }
             _ => Err(Error::CursorNotFoundError),
         }
@@ -161,14 +286,24 @@ impl Cursor {
         message: Message,
     ) -> Result<(bson::Document, VecDeque<bson::Document>, i64, String)> {
 
-        let (first, mut v, _) = Cursor::get_bson_and_cid_from_message(message)?;
+        let first = match Cursor::get_bson_and_cid_from_message(message)? {
+            (Some(first), _, _) => first,
+            _ => return Err(Error::CursorNotFoundError),
+        };
 
         // Extract cursor information
-        let mut cursor = match v.remove(0).and_then(|mut doc| doc.remove("cursor")) {
-            Some(Bson::Document(cursor)) => cursor,
+        let cursor = match first.get("cursor") {
+            Some(&Bson::Document(ref cursor)) => cursor.clone(),
             _ => return Err(Error::CursorNotFoundError),
         };
 
+        let (map, id, ns) = Cursor::get_cursor_info_from_document(cursor)?;
+        Ok((first, map, id, ns))
+    }
+
+    fn get_cursor_info_from_document(
+        mut cursor: bson::Document,
+    ) -> Result<(VecDeque<bson::Document>, i64, String)> {
         match (cursor.remove("id"), cursor.remove("ns"), cursor.remove("firstBatch")) {
             (Some(Bson::I64(id)),
              Some(Bson::String(ns)),
@@ -183,12 +318,66 @@ impl Cursor {
                     })
                     .collect();
 
-                Ok((first, map, id, ns))
+                Ok((map, id, ns))
             }
             _ => Err(Error::CursorNotFoundError)
         }
     }
 
+    /// Constructs a Cursor from a cursor document returned by a server command, such as one of
+    /// the entries of a `parallelCollectionScan` reply.
+    ///
+    /// # Arguments
+    ///
+    /// `client` - The client to read further batches from.
+    /// `cursor` - The document containing the cursor's `id`, `ns`, and `firstBatch`.
+    /// `cmd_type` - The type of command, which will be used for monitoring events.
+    /// `read_pref` - The read preference used to select a server for further batches.
+    ///
+    /// # Return value
+    ///
+    /// Returns the newly created Cursor on success, or an Error if the document does not
+    /// describe a cursor.
+    pub fn from_cursor_document(
+        client: Client,
+        cursor: bson::Document,
+        cmd_type: CommandType,
+        read_pref: ReadPreference,
+    ) -> Result<Cursor> {
+        let (buf, cursor_id, namespace) = Cursor::get_cursor_info_from_document(cursor)?;
+        let batch_len = buf.len();
+
+        let cursor = Cursor {
+            client: client,
+            namespace: namespace,
+            batch_size: batch_len as i32,
+            cursor_id: cursor_id,
+            limit: 0,
+            count: 0,
+            buffer: buf,
+            raw: ReplyDocuments::default(),
+            read_preference: read_pref,
+            cmd_type: cmd_type,
+            exhaust_stream: None,
+            reply_id: 0,
+            comment: None,
+            host: None,
+            server_type: ServerType::Unknown,
+            await_data: false,
+            max_await_time_ms: None,
+            prefetch: false,
+            pending: None,
+            last_batch_len: batch_len,
+            batch_start: 0,
+            decryptor: None,
+            ctx: None,
+            session_fields: None,
+        };
+
+        cursor.track(false);
+        Ok(cursor)
+    }
+
     /// Executes a query where the batch size of the returned cursor is
     /// specified.
     ///
@@ -217,12 +406,141 @@ impl Cursor {
         is_cmd_cursor: bool,
         read_pref: ReadPreference,
     ) -> Result<Cursor> {
+        Cursor::query_with_ctx(
+            client,
+            namespace,
+            flags,
+            query,
+            options,
+            cmd_type,
+            is_cmd_cursor,
+            read_pref,
+            None,
+        )
+    }
+
+    /// Executes a query like `query`, within `ctx` if one is given: the query and every
+    /// getMore of the returned cursor fail once the context is cancelled or its deadline has
+    /// passed. The deadline is not added to the query; callers set `maxTimeMS` themselves.
+    pub fn query_with_ctx(
+        client: Client,
+        namespace: String,
+        flags: OpQueryFlags,
+        query: bson::Document,
+        options: FindOptions,
+        cmd_type: CommandType,
+        is_cmd_cursor: bool,
+        read_pref: ReadPreference,
+        ctx: Option<&OpCtx>,
+    ) -> Result<Cursor> {
+
+        // Reads shortly after a write go to the primary if the client pins reads to it, so that
+        // they observe the write.
+        let read_pref = if !cmd_type.is_write_command() && pinned_to_primary(&client) {
+            read_pref.prefer_primary()
+        } else {
+            read_pref
+        };
+
+        // Hedged reads race the query on two members. Writes never hedge, and neither do
+        // exhaust queries, whose batches keep arriving over the connection they were sent on.
+        if read_pref.hedge && read_pref.mode == ReadMode::Nearest &&
+            !cmd_type.is_write_command() && !flags.contains(OpQueryFlags::EXHAUST)
+        {
+            let streams = acquire_hedged_streams(&client, &read_pref)?;
+            if streams.len() > 1 {
+                return Cursor::hedged_query(
+                    streams,
+                    client,
+                    namespace,
+                    flags | OpQueryFlags::SLAVE_OK,
+                    query,
+                    options,
+                    cmd_type,
+                    is_cmd_cursor,
+                    read_pref,
+                    ctx,
+                );
+            }
+        }
+
+        // Reads that fail with a transient error before returning any documents are retried
+        // once, after server selection runs again with the same read preference. A cursor's
+        // getMores are never retried, since the cursor lives on the server that opened it.
+        if !client.retry_reads || !is_retryable_read(cmd_type, &query) {
+            return Cursor::query_selected(
+                client,
+                namespace,
+                flags,
+                query,
+                options,
+                cmd_type,
+                is_cmd_cursor,
+                read_pref,
+                ctx,
+            );
+        }
+
+        let result = Cursor::query_selected(
+            client.clone(),
+            namespace.clone(),
+            flags,
+            query.clone(),
+            options.clone(),
+            cmd_type,
+            is_cmd_cursor,
+            read_pref.clone(),
+            ctx,
+        );
+
+        match result {
+            Err(ref err) if err.is_retryable_read() => {
+                client.log(LogLevel::Warn, "retry", || {
+                    format!("Retrying {} after error: {}", cmd_type.to_str(), err)
+                });
+
+                client.topology.request_updates()?;
+                Cursor::query_selected(
+                    client,
+                    namespace,
+                    flags,
+                    query,
+                    options,
+                    cmd_type,
+                    is_cmd_cursor,
+                    read_pref,
+                    ctx,
+                )
+            }
+            result => result,
+        }
+    }
+
+    // Selects a server for the query and sends it there.
+    fn query_selected(
+        client: Client,
+        namespace: String,
+        flags: OpQueryFlags,
+        query: bson::Document,
+        options: FindOptions,
+        cmd_type: CommandType,
+        is_cmd_cursor: bool,
+        read_pref: ReadPreference,
+        ctx: Option<&OpCtx>,
+    ) -> Result<Cursor> {
+        // Select a server stream from the topology, within the limits of the routing profile
+        // of the collection the query acts on.
+        let route_namespace = if client.routing.is_empty() {
+            None
+        } else {
+            operation_namespace(&namespace, &query)
+        };
+        let route_namespace = route_namespace.as_ref().map(String::as_str);
 
-        // Select a server stream from the topology.
         let (mut stream, slave_ok, send_read_pref) = if cmd_type.is_write_command() {
-            (client.acquire_write_stream()?, false, false)
+            (acquire_write_stream_for(&client, route_namespace)?, false, false)
         } else {
-            client.acquire_stream(read_pref.to_owned())?
+            acquire_stream_for(&client, route_namespace, read_pref.to_owned())?
         };
 
         // Set slave_ok flag based on the result from server selection.
@@ -248,17 +566,177 @@ impl Cursor {
             }
         };
 
-        Cursor::query_with_stream(
-            &mut stream,
-            client,
-            namespace,
-            new_flags,
-            new_query,
-            options,
-            cmd_type,
-            is_cmd_cursor,
-            Some(read_pref),
-        )
+        let exhaust = new_flags.contains(OpQueryFlags::EXHAUST);
+
+        let send = |stream: &mut PooledStream, query: bson::Document, options: FindOptions| {
+            let round_trip = |stream: &mut PooledStream| {
+                Cursor::query_with_stream(
+                    stream,
+                    client.clone(),
+                    namespace.clone(),
+                    new_flags,
+                    query,
+                    options,
+                    cmd_type,
+                    is_cmd_cursor,
+                    Some(read_pref.clone()),
+                )
+            };
+
+            match ctx {
+                Some(ctx) => ctx.round_trip(stream, round_trip),
+                None => round_trip(stream),
+            }
+        };
+
+        // A mongos whose routing table is stale, as while a chunk migrates, rejects the
+        // operation and refreshes its table, so the operation is sent once more after a short
+        // delay if sending it twice can't apply it twice.
+        let stale_config_retry = if stream.server_type() == ServerType::Mongos &&
+            can_resend_after_stale_config(cmd_type, &new_query)
+        {
+            Some((new_query.clone(), options.clone()))
+        } else {
+            None
+        };
+
+        let mut result = send(&mut stream, new_query, options);
+
+        if let Some((query, options)) = stale_config_retry {
+            let stale_config = match result {
+                Err(ref err) => err.is_stale_config(),
+                Ok(_) => false,
+            };
+
+            if stale_config {
+                client.log(LogLevel::Warn, "retry", || {
+                    format!("Retrying {} after a stale config error.", cmd_type.to_str())
+                });
+                thread::sleep(Duration::from_millis(STALE_CONFIG_RETRY_DELAY_MS));
+                result = send(&mut stream, query, options);
+            }
+        }
+
+        let mut cursor = match result {
+            Ok(cursor) => cursor,
+            Err(err) => {
+                // Stop selecting a server that can't be reached until its monitor checks it again.
+                // A read that timed out under a routing profile's socket timeout only says that
+                // the operation was slow.
+                if err.is_network_error() && !is_timeout(&err) {
+                    client.log(LogLevel::Warn, "connection", || {
+                        format!(
+                            "Network error on {}:{}: {}; marking the server unknown.",
+                            stream.host().host_name,
+                            stream.host().port,
+                            err
+                        )
+                    });
+                    let _ = client.topology.reset_server(stream.host());
+                }
+                return Err(err);
+            }
+        };
+
+        // The server will keep sending batches over this connection until the cursor is
+        // exhausted, so it cannot be returned to the pool until then.
+        if exhaust && cursor.cursor_id != 0 {
+            stream.set_dirty(true);
+            cursor.exhaust_stream = Some(stream);
+        }
+
+        cursor.ctx = ctx.cloned();
+        Ok(cursor)
+    }
+
+    // Sends the query over every stream at once and returns the cursor of the first successful
+    // reply. The queries still waiting for a reply are then interrupted, and their connections
+    // closed; a cursor opened by a reply that arrives too late is killed when it is dropped.
+    fn hedged_query(
+        streams: Vec<PooledStream>,
+        client: Client,
+        namespace: String,
+        flags: OpQueryFlags,
+        query: bson::Document,
+        options: FindOptions,
+        cmd_type: CommandType,
+        is_cmd_cursor: bool,
+        read_pref: ReadPreference,
+        ctx: Option<&OpCtx>,
+    ) -> Result<Cursor> {
+        if let Some(ctx) = ctx {
+            ctx.check()?;
+        }
+
+        // Each query gets a context of its own, so that the losers can be cancelled.
+        let legs: Vec<_> = streams
+            .iter()
+            .map(|_| match ctx.and_then(OpCtx::deadline) {
+                Some(deadline) => OpCtx::with_deadline(deadline),
+                None => OpCtx::new(),
+            })
+            .collect();
+
+        let (sender, receiver) = mpsc::channel();
+        for (index, mut stream) in streams.into_iter().enumerate() {
+            let leg = legs[index].clone();
+            let sender = sender.clone();
+            let client = client.clone();
+            let namespace = namespace.clone();
+            let query = query.clone();
+            let options = options.clone();
+            let cmd_type = cmd_type.clone();
+            let read_pref = read_pref.clone();
+
+            thread::spawn(move || {
+                let host = stream.host().clone();
+                let result = leg.round_trip(&mut stream, |stream| {
+                    Cursor::query_with_stream(
+                        stream,
+                        client,
+                        namespace,
+                        flags,
+                        query,
+                        options,
+                        cmd_type,
+                        is_cmd_cursor,
+                        Some(read_pref),
+                    )
+                });
+                let _ = sender.send((index, host, result));
+            });
+        }
+        drop(sender);
+
+        let mut last_err = None;
+        for (index, host, result) in receiver.iter() {
+            match result {
+                Ok(mut cursor) => {
+                    for (other, leg) in legs.iter().enumerate() {
+                        if other != index {
+                            leg.cancel();
+                        }
+                    }
+
+                    client.log(LogLevel::Debug, "operation", || {
+                        format!("Hedged read answered first by {}:{}.", host.host_name, host.port)
+                    });
+
+                    cursor.ctx = ctx.cloned();
+                    return Ok(cursor);
+                }
+                Err(err) => {
+                    if err.is_network_error() {
+                        let _ = client.topology.reset_server(&host);
+                    }
+                    last_err = Some(err);
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| {
+            Error::OperationError(String::from("Hedged read task panicked."))
+        }))
     }
 
     pub fn query_with_stream(
@@ -273,6 +751,8 @@ impl Cursor {
         read_pref: Option<ReadPreference>,
     ) -> Result<Cursor> {
 
+        let host = stream.host().clone();
+        let server_type = stream.server_type();
         let socket = stream.get_socket();
         let req_id = client.get_req_id();
 
@@ -287,14 +767,40 @@ impl Cursor {
             _ => query.clone(),
         };
 
+        let no_cursor_timeout = flags.contains(OpQueryFlags::NO_CURSOR_TIMEOUT) ||
+            (is_cmd_cursor && filter.get("noCursorTimeout") == Some(&Bson::Boolean(true)));
+
+        // Find commands request an awaiting cursor in the command document itself.
+        let await_data = options.cursor_type == CursorType::TailableAwait ||
+            (is_cmd_cursor && filter.get("awaitData") == Some(&Bson::Boolean(true)));
+        let max_await_time_ms = if await_data {
+            options.max_await_time_ms
+        } else {
+            None
+        };
+
+        // Commands carry their comment in the command document itself.
+        let comment = match options.comment {
+            Some(ref comment) => Some(comment.to_owned()),
+            None if is_cmd_cursor => {
+                match filter.get("comment") {
+                    Some(&Bson::String(ref comment)) => Some(comment.to_owned()),
+                    _ => None,
+                }
+            }
+            None => None,
+        };
+
+        // Legacy queries are reported as the find command they stand for; find commands are
+        // reported as sent.
         let command = match cmd_type {
-            CommandType::Find => {
+            CommandType::Find if !is_cmd_cursor => {
                 let document = doc! {
                     "find": coll_name,
                     "filter": filter
                 };
 
-                merge_options(document, options.clone())
+                merge_options(document, options.to_document())
             }
             _ => query.clone(),
         };
@@ -317,6 +823,7 @@ impl Cursor {
                 command_name: String::from(cmd_name),
                 request_id: req_id as i64,
                 connection_string: connstring.clone(),
+                host: host.clone(),
             });
 
             if hook_result.is_err() {
@@ -324,65 +831,87 @@ impl Cursor {
             }
         }
 
-        try_or_emit!(
-            cmd_type,
-            cmd_name,
-            req_id,
-            connstring,
-            message.write(socket),
-            client
-        );
-        let reply = try_or_emit!(
-            cmd_type,
-            cmd_name,
-            req_id,
-            connstring,
-            Message::read(socket),
-            client
-        );
+        client.log_message(true, &host, &message);
+
+        let start = Instant::now();
+        let written = message.write(socket);
+        if let Err(ref err) = written {
+            client.metrics.record_error(err);
+        }
+        try_or_emit!(cmd_type, cmd_name, req_id, connstring, host.clone(), written, client);
+
+        // A connection whose replies can't be matched to requests must not be reused.
+        let result = Message::read_reply_to(socket, req_id, client.max_bson_depth);
+        client.record_round_trip(start, &result);
+        if let Ok(ref reply) = result {
+            client.slow_ops.record(start, &message, reply, &host);
+        }
+        if result.is_err() {
+            stream.set_dirty(true);
+        }
+
+        let reply =
+            try_or_emit!(cmd_type, cmd_name, req_id, connstring, host.clone(), result, client);
+        client.log_message(false, &host, &reply);
+        let reply_id = match reply {
+            Message::OpReply { ref header, .. } => header.request_id,
+            _ => 0,
+        };
 
         let fin_time = time::precise_time_ns();
 
-        let (doc, buf, cursor_id, namespace) = if is_cmd_cursor {
-            try_or_emit!(
+        let (doc, buf, raw, cursor_id, namespace) = if is_cmd_cursor {
+            let (doc, buf, id, namespace) = try_or_emit!(
                 cmd_type,
                 cmd_name,
                 req_id,
                 connstring,
+                host.clone(),
                 Cursor::get_bson_and_cursor_info_from_command_message(reply),
                 client
-            )
+            );
+            (doc, buf, ReplyDocuments::default(), id, namespace)
         } else {
-            let (doc, buf, id) = try_or_emit!(
+            let (first, raw, id) = try_or_emit!(
                 cmd_type,
                 cmd_name,
                 req_id,
                 connstring,
+                host.clone(),
                 Cursor::get_bson_and_cid_from_message(reply),
                 client
             );
-            (doc, buf, id, namespace)
+            let doc = first.clone().unwrap_or_else(bson::Document::new);
+            (doc, first.into_iter().collect::<VecDeque<_>>(), raw, id, namespace)
         };
 
-        let reply = match cmd_type {
-            CommandType::Find => doc! {
-                "cursor": {
-                    "id": cursor_id,
-                    "ns": &namespace,
-                    "firstBatch": buf.iter().cloned().map(Bson::from).collect::<Vec<_>>(),
-                },
-                "ok": 1
-            },
-            _ => doc,
-        };
+        // Reporting the first batch requires decoding all of it, so it's skipped when there is
+        // nobody to report it to.
+        if cmd_type != CommandType::Suppressed && client.listener.has_completion_hooks() {
+            let reply = match cmd_type {
+                CommandType::Find => {
+                    let mut batch: Vec<_> = buf.iter().cloned().map(Bson::from).collect();
+                    batch.extend(raw.to_documents()?.into_iter().map(Bson::from));
+
+                    doc! {
+                        "cursor": {
+                            "id": cursor_id,
+                            "ns": &namespace,
+                            "firstBatch": batch,
+                        },
+                        "ok": 1
+                    }
+                }
+                _ => doc,
+            };
 
-        if cmd_type != CommandType::Suppressed {
             let _hook_result = client.run_completion_hooks(&CommandResult::Success {
                 duration: fin_time - init_time,
                 reply: reply,
                 command_name: String::from(cmd_name),
                 request_id: req_id as i64,
                 connection_string: connstring,
+                host: host.clone(),
             });
         }
 
@@ -390,73 +919,297 @@ impl Cursor {
             read_pref.unwrap_or_else(|| ReadPreference::new(ReadMode::Primary, None));
 
         // Check if actual batch size fits into an `i32`.
-        if size_of::<i32>() <= size_of::<usize>() && buf.len() > i32::MAX as usize {
+        let batch_len = buf.len() + raw.len();
+        if size_of::<i32>() <= size_of::<usize>() && batch_len > i32::MAX as usize {
             return Err(Error::DefaultError(
-                format!("Batch buffer size {} overflows i32", buf.len())
+                format!("Batch buffer size {} overflows i32", batch_len)
             ));
         }
 
-        Ok(Cursor {
+        let cursor = Cursor {
             client: client,
             namespace: namespace,
-            batch_size: buf.len() as i32,
+            batch_size: batch_len as i32,
             cursor_id: cursor_id,
             limit: options.limit.unwrap_or(0) as i32,
             count: 0,
             buffer: buf,
+            raw: raw,
             read_preference: read_preference,
             cmd_type: cmd_type.clone(),
-        })
+            exhaust_stream: None,
+            reply_id: reply_id,
+            comment: comment,
+            host: Some(host),
+            server_type: server_type,
+            await_data: await_data,
+            max_await_time_ms: max_await_time_ms,
+            prefetch: false,
+            pending: None,
+            last_batch_len: batch_len,
+            batch_start: 0,
+            decryptor: None,
+            ctx: None,
+            session_fields: None,
+        };
+
+        cursor.track(no_cursor_timeout);
+        Ok(cursor)
+    }
+
+    // Records the server-side cursor as open, so that it can be killed if the client shuts down
+    // before the cursor is exhausted, and reported if it was opened with noCursorTimeout and is
+    // left idle.
+    fn track(&self, no_timeout: bool) {
+        if self.cursor_id == 0 {
+            return;
+        }
+
+        if let Ok(mut open_cursors) = self.client.open_cursors.lock() {
+            open_cursors.insert(self.cursor_id, OpenCursor {
+                namespace: self.namespace.to_owned(),
+                no_timeout: no_timeout,
+                last_used: Instant::now(),
+                warned: false,
+            });
+        }
+    }
+
+    // Updates the server-side cursor id after a batch, forgetting the cursor once it is
+    // exhausted.
+    fn set_cursor_id(&mut self, cursor_id: i64) {
+        if cursor_id == 0 && self.cursor_id != 0 {
+            if let Ok(mut open_cursors) = self.client.open_cursors.lock() {
+                open_cursors.remove(&self.cursor_id);
+            }
+        } else if cursor_id != 0 {
+            if let Ok(mut open_cursors) = self.client.open_cursors.lock() {
+                if let Some(cursor) = open_cursors.get_mut(&cursor_id) {
+                    cursor.last_used = Instant::now();
+                    cursor.warned = false;
+                }
+            }
+        }
+
+        self.cursor_id = cursor_id;
+    }
+
+    fn get_from_exhaust_stream(&mut self) -> Result<()> {
+        let reply = match self.exhaust_stream {
+            Some(ref mut stream) => {
+                let host = stream.host().clone();
+                let socket = stream.get_socket().get_mut();
+                let reply =
+                    Message::read_reply_to(socket, self.reply_id, self.client.max_bson_depth)?;
+                self.client.log_message(false, &host, &reply);
+                reply
+            }
+            None => return Ok(()),
+        };
+
+        if let Message::OpReply { ref header, .. } = reply {
+            self.reply_id = header.request_id;
+        }
+
+        let (first, raw, cursor_id) = Cursor::get_bson_and_cid_from_message(reply)?;
+        self.buffer.extend(first);
+        self.raw = raw;
+        self.set_cursor_id(cursor_id);
+
+        // Once the server has sent the final batch, the connection can be reused.
+        if cursor_id == 0 {
+            if let Some(mut stream) = self.exhaust_stream.take() {
+                stream.set_dirty(false);
+            }
+        }
+
+        Ok(())
     }
 
     fn get_from_stream(&mut self) -> Result<()> {
-        let (mut stream, _, _) = self.client.acquire_stream(self.read_preference.to_owned())?;
-        let socket = stream.get_socket();
+        if let Some(ref ctx) = self.ctx {
+            ctx.check()?;
+        }
 
-        let req_id = self.client.get_req_id();
-        let get_more = Message::new_get_more(
-            req_id,
-            self.namespace.to_owned(),
-            self.batch_size,
-            self.cursor_id,
-        );
+        if self.exhaust_stream.is_some() {
+            self.get_from_exhaust_stream()?;
+        } else {
+            // A batch already requested in the background is waited for rather than requested
+            // again.
+            let reply = match self.pending.take() {
+                Some(receiver) => {
+                    receiver.recv().unwrap_or_else(|_| {
+                        Err(Error::OperationError(String::from("Prefetch task panicked.")))
+                    })
+                }
+                None => self.get_more().send(),
+            };
 
-        let index = self.namespace.rfind('.').unwrap_or_else(
-            || self.namespace.len(),
-        );
-        let db_name = String::from(&self.namespace[..index]);
-        let cmd_name = String::from("get_more");
-        let connstring = socket.get_ref().peer_addr()?.to_string();
+            let (reply, is_command) = reply?;
+            self.read_batch(reply, is_command)?;
+        }
 
-        if self.cmd_type != CommandType::Suppressed {
-            let hook_result = self.client.run_start_hooks(&CommandStarted {
-                command: doc! { "cursor_id": self.cursor_id },
-                database_name: db_name,
-                command_name: cmd_name.clone(),
-                request_id: req_id as i64,
-                connection_string: connstring.clone(),
-            });
+        // A batch is only requested once the previous one has been returned.
+        self.batch_start += self.last_batch_len as i64;
+        self.last_batch_len = self.buffer.len() + self.raw.len();
+        Ok(())
+    }
 
-            if hook_result.is_err() {
-                return Err(Error::EventListenerError(None));
+    // Captures the state needed to request the next batch.
+    fn get_more(&self) -> GetMore {
+        GetMore {
+            client: self.client.clone(),
+            namespace: self.namespace.to_owned(),
+            cursor_id: self.cursor_id,
+            batch_size: self.batch_size,
+            read_preference: self.read_preference.to_owned(),
+            cmd_type: self.cmd_type.clone(),
+            comment: self.comment.clone(),
+            max_await_time_ms: self.max_await_time_ms,
+            host: self.host.clone(),
+            ctx: self.ctx.clone(),
+            session_fields: self.session_fields.clone(),
+        }
+    }
+
+    // Requests the next batch in the background once fewer than half of the documents of the
+    // last batch remain, so that it has usually arrived by the time the buffer runs out.
+    fn prefetch_if_low(&mut self) {
+        if !self.prefetch || self.pending.is_some() || self.cursor_id == 0 ||
+            self.exhaust_stream.is_some()
+        {
+            return;
+        }
+
+        let remaining = self.buffer.len() + self.raw.len();
+        if remaining * 2 >= self.last_batch_len.max(1) {
+            return;
+        }
+
+        // Every document left to return under the limit has already been received.
+        if self.limit > 0 && i64::from(self.count) + remaining as i64 >= i64::from(self.limit) {
+            return;
+        }
+
+        let get_more = self.get_more();
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            // The cursor may have been dropped in the meantime, in which case nobody is
+            // waiting for the batch.
+            let _ = sender.send(get_more.send());
+        });
+
+        self.pending = Some(receiver);
+    }
+
+    // Adds the batch of a getMore reply to the buffer.
+    fn read_batch(&mut self, reply: Message, is_command: bool) -> Result<()> {
+        // The server no longer knows about the cursor, e.g. because it timed out.
+        if let Message::OpReply { ref flags, .. } = reply {
+            if flags.contains(OpReplyFlags::CURSOR_NOT_FOUND) {
+                self.set_cursor_id(0);
+                return Err(Error::CursorNotFoundError);
             }
         }
 
-        try_or_emit!(
-            self.cmd_type,
-            cmd_name,
-            req_id,
-            connstring,
-            get_more.write(socket.get_mut()),
-            self.client
-        );
-        let reply = Message::read(socket.get_mut())?;
+        if is_command {
+            return self.read_get_more_reply(reply);
+        }
+
+        let (first, raw, cursor_id) = Cursor::get_bson_and_cid_from_message(reply)?;
+        self.buffer.extend(first);
+        self.raw = raw;
+        self.set_cursor_id(cursor_id);
+        Ok(())
+    }
+
+    // Reads the next batch from the reply to a getMore command.
+    fn read_get_more_reply(&mut self, reply: Message) -> Result<()> {
+        let reply = match Cursor::get_bson_and_cid_from_message(reply) {
+            Ok((Some(reply), _, _)) => reply,
+            Ok((None, _, _)) => return Err(Error::CursorNotFoundError),
+            Err(err) => {
+                // The server no longer knows about the cursor, e.g. because it timed out.
+                if let Error::CommandError(ref failure) = err {
+                    if failure.has_code(&[ErrorCode::CursorNotFound]) {
+                        self.set_cursor_id(0);
+                        return Err(Error::CursorNotFoundError);
+                    }
+                }
+                return Err(err);
+            }
+        };
+
+        let mut cursor = match reply.get("cursor") {
+            Some(&Bson::Document(ref cursor)) => cursor.clone(),
+            _ => return Err(Error::CursorNotFoundError),
+        };
+
+        match (cursor.remove("id"), cursor.remove("nextBatch")) {
+            (Some(Bson::I64(cursor_id)), Some(Bson::Array(batch))) => {
+                self.buffer.extend(batch.into_iter().filter_map(|doc| match doc {
+                    Bson::Document(doc) => Some(doc),
+                    _ => None,
+                }));
+                self.raw = ReplyDocuments::default();
+                self.set_cursor_id(cursor_id);
+                Ok(())
+            }
+            _ => Err(Error::CursorNotFoundError),
+        }
+    }
 
-        let (_, v, _) = Cursor::get_bson_and_cid_from_message(reply)?;
-        self.buffer.extend(v);
+    /// Sets how long each getMore waits for new documents before returning an empty batch, in
+    /// which case `next` returns `None` while the cursor stays alive. Only `TailableAwait`
+    /// cursors can wait, so setting a limit for other cursors fails with an `ArgumentError`.
+    pub fn set_max_await_time_ms(&mut self, max_await_time_ms: Option<i64>) -> Result<()> {
+        if max_await_time_ms.is_some() && !self.await_data {
+            return Err(Error::ArgumentError(String::from(
+                "max_await_time_ms can only be set for TailableAwait cursors.",
+            )));
+        }
+
+        self.max_await_time_ms = max_await_time_ms;
         Ok(())
     }
 
+    /// Sets whether the next batch is requested in the background once fewer than half of the
+    /// documents of the last batch remain, so that iterating doesn't wait for the server in
+    /// steady state. Each prefetch sends its getMore over a pooled connection of its own; if it
+    /// fails, the error is returned by the call to `next` that needs the batch.
+    pub fn with_prefetch(mut self, prefetch: bool) -> Cursor {
+        self.prefetch = prefetch;
+        self
+    }
+
+    /// Decrypts the fields encrypted with the keys of `decryptor` in every document returned.
+    pub fn with_decryptor(mut self, decryptor: Arc<FieldEncryptor>) -> Cursor {
+        self.decryptor = Some(decryptor);
+        self
+    }
+
+    /// Sends every getMore under `session`, for cursors opened by a command run under it. If
+    /// the session is in a transaction, the getMores are sent as part of the transaction.
+    pub fn with_session(mut self, session: &ClientSession) -> Cursor {
+        self.session_fields = Some(session.get_more_fields());
+        self
+    }
+
+    /// Sends every getMore to `host`, for cursors built from the reply of a command that ran
+    /// there, since no other server knows about the cursor.
+    pub fn with_host(mut self, host: Host) -> Cursor {
+        self.host = Some(host);
+        self
+    }
+
+    fn decrypt(&self, doc: bson::Document) -> Result<bson::Document> {
+        match self.decryptor {
+            Some(ref decryptor) => decryptor.decrypt_document(doc),
+            None => Ok(doc),
+        }
+    }
+
     /// Attempts to read a specified number of BSON documents from the cursor.
     ///
     /// # Arguments
@@ -508,11 +1261,82 @@ impl Cursor {
     ///
     /// Returns a vector containing the BSON documents that were read.
     pub fn drain_current_batch(&mut self) -> Result<Vec<bson::Document>> {
-        if self.buffer.is_empty() {
+        if self.is_buffer_empty() {
             self.get_from_stream()?;
         }
 
-        Ok(self.buffer.drain(..).collect())
+        let mut batch: Vec<_> = self.buffer.drain(..).collect();
+        for doc in &mut self.raw {
+            batch.push(doc?);
+        }
+
+        if self.decryptor.is_some() {
+            batch = batch.into_iter().map(|doc| self.decrypt(doc)).collect::<Result<_>>()?;
+        }
+
+        self.count = self.count.saturating_add(batch.len() as i32);
+        self.prefetch_if_low();
+        Ok(batch)
+    }
+
+    /// Returns the id of the server-side cursor, or 0 once the server has exhausted it.
+    pub fn cursor_id(&self) -> i64 {
+        self.cursor_id
+    }
+
+    /// Returns the server the cursor was opened on, which every getMore is sent to, or `None`
+    /// for cursors built from a command reply without `with_host`.
+    pub fn host(&self) -> Option<&Host> {
+        self.host.as_ref()
+    }
+
+    /// Returns the address of the server the cursor was opened on as `host:port`, or `None` for
+    /// cursors built from a command reply without `with_host`.
+    pub fn server_address(&self) -> Option<String> {
+        self.host.as_ref().map(Host::address)
+    }
+
+    /// Returns the server the cursor was opened on and the role it had when it was selected, or
+    /// `None` for cursors built from a command reply without `with_host`.
+    pub fn meta(&self) -> Option<QueryResultMeta> {
+        self.host.as_ref().map(|host| {
+            QueryResultMeta {
+                host: host.clone(),
+                server_type: self.server_type,
+            }
+        })
+    }
+
+    /// Returns whether the server may still return further documents for the cursor. A tailable
+    /// cursor that is no longer alive must be reopened to receive new documents.
+    pub fn is_alive(&self) -> bool {
+        self.cursor_id != 0
+    }
+
+    /// Returns the number of documents received from the server but not yet returned.
+    pub fn buffered_count(&self) -> usize {
+        self.buffer.len() + self.raw.len()
+    }
+
+    /// Returns true if the cursor has no documents left to return: its limit was reached, or the
+    /// server closed it and every document received was returned. Unlike `has_next`, this
+    /// never requests a batch.
+    pub fn is_exhausted(&self) -> bool {
+        (self.limit > 0 && self.count >= self.limit) ||
+            (self.cursor_id == 0 && self.is_buffer_empty())
+    }
+
+    /// Returns the number of documents the cursor has returned so far.
+    pub fn docs_returned_so_far(&self) -> i64 {
+        i64::from(self.count)
+    }
+
+    /// Returns the position and size of the last batch the server returned.
+    pub fn batch_info(&self) -> BatchInfo {
+        BatchInfo {
+            starting_from: self.batch_start,
+            number_returned: self.last_batch_len,
+        }
     }
 
     /// Checks whether there are any more documents for the cursor to return.
@@ -524,12 +1348,221 @@ impl Cursor {
         if self.limit > 0 && self.count >= self.limit {
             Ok(false)
         } else {
-            if self.buffer.is_empty() && self.limit != 1 && self.cursor_id != 0 {
+            if self.is_buffer_empty() && self.limit != 1 && self.cursor_id != 0 {
                 self.get_from_stream()?;
             }
-            Ok(!self.buffer.is_empty())
+            Ok(!self.is_buffer_empty())
         }
     }
+
+    fn is_buffer_empty(&self) -> bool {
+        self.buffer.is_empty() && self.raw.is_empty()
+    }
+}
+
+impl GetMore {
+    // Sends the getMore over a pooled connection of its own and reads the reply, returning
+    // whether it was sent as a command. Servers that support the getMore command (MongoDB 3.2
+    // and later) are sent the command, and older ones the legacy OP_GET_MORE message; the
+    // cursor reads either reply into the same buffer.
+    fn send(self) -> Result<(Message, bool)> {
+        // Only the server the cursor was opened on knows about it, so selecting a server again
+        // could pick another one. That server may be a secondary, hence slaveOk.
+        let (mut stream, slave_ok) = match self.host {
+            Some(ref host) => (acquire_cursor_stream(&self.client, host, &self.namespace)?, true),
+            None => {
+                let (stream, slave_ok, _) = acquire_stream_for(
+                    &self.client,
+                    Some(&self.namespace),
+                    self.read_preference.to_owned(),
+                )?;
+                (stream, slave_ok)
+            }
+        };
+
+        match self.ctx {
+            Some(ref ctx) => {
+                ctx.round_trip(&mut stream, |stream| self.send_with_stream(stream, slave_ok))
+            }
+            None => self.send_with_stream(&mut stream, slave_ok),
+        }
+    }
+
+    // Sends the getMore over `stream` and reads the reply.
+    fn send_with_stream(
+        &self,
+        stream: &mut PooledStream,
+        slave_ok: bool,
+    ) -> Result<(Message, bool)> {
+        let host = stream.host().clone();
+        let socket = stream.get_socket();
+
+        // Collection names may contain dots, and command cursors are named e.g.
+        // `db.$cmd.listIndexes.coll`, so only the first dot ends the database name.
+        let index = self.namespace.find('.').unwrap_or_else(|| self.namespace.len());
+        let db_name = String::from(&self.namespace[..index]);
+
+        // The legacy message can't limit how long the server waits, so the command is used
+        // whenever a limit is set.
+        let is_command = self.max_await_time_ms.is_some() ||
+            self.client.server_supports(&host, ServerCapabilities::supports_find_command)?;
+
+        let req_id = self.client.get_req_id();
+        let get_more = if is_command {
+            let mut command = doc! {
+                "getMore": self.cursor_id,
+                "collection": &self.namespace[index + 1..],
+            };
+
+            if self.batch_size > 0 {
+                command.insert("batchSize", self.batch_size);
+            }
+
+            if let Some(max_await_time_ms) = self.max_await_time_ms {
+                command.insert("maxTimeMS", max_await_time_ms);
+            }
+
+            if let Some(ref session_fields) = self.session_fields {
+                for (key, value) in session_fields {
+                    command.insert(key.to_owned(), value.clone());
+                }
+            }
+
+            if let Some(ref comment) = self.comment {
+                let supported = ServerCapabilities::supports_get_more_comment;
+                if self.client.server_supports(&host, supported)? {
+                    command.insert("comment", comment.to_owned());
+                }
+            }
+
+            let flags = if slave_ok {
+                OpQueryFlags::SLAVE_OK
+            } else {
+                OpQueryFlags::empty()
+            };
+
+            Message::new_query(
+                req_id,
+                flags,
+                format!("{}.$cmd", db_name),
+                0,
+                -1,
+                command,
+                None,
+            )?
+        } else {
+            Message::new_get_more(
+                req_id,
+                self.namespace.to_owned(),
+                self.batch_size,
+                self.cursor_id,
+            )
+        };
+        let cmd_name = String::from("get_more");
+        let connstring = socket.get_ref().peer_addr()?.to_string();
+
+        if self.cmd_type != CommandType::Suppressed {
+            let mut command = doc! { "cursor_id": self.cursor_id };
+
+            if let Some(ref comment) = self.comment {
+                command.insert("comment", comment.to_owned());
+            }
+
+            let hook_result = self.client.run_start_hooks(&CommandStarted {
+                command: command,
+                database_name: db_name,
+                command_name: cmd_name.clone(),
+                request_id: req_id as i64,
+                connection_string: connstring.clone(),
+                host: host.clone(),
+            });
+
+            if hook_result.is_err() {
+                return Err(Error::EventListenerError(None));
+            }
+        }
+
+        self.client.log_message(true, &host, &get_more);
+
+        let start = Instant::now();
+        let written = get_more.write(socket.get_mut());
+        if let Err(ref err) = written {
+            self.client.metrics.record_error(err);
+        }
+
+        try_or_emit!(
+            self.cmd_type,
+            cmd_name,
+            req_id,
+            connstring,
+            host.clone(),
+            written,
+            self.client
+        );
+
+        let result = Message::read_reply_to(socket.get_mut(), req_id, self.client.max_bson_depth);
+        self.client.record_round_trip(start, &result);
+        if let Ok(ref reply) = result {
+            self.client.slow_ops.record(start, &get_more, reply, &host);
+        }
+
+        match result {
+            Ok(reply) => {
+                self.client.log_message(false, &host, &reply);
+                Ok((reply, is_command))
+            }
+            Err(err) => {
+                stream.set_dirty(true);
+                Err(err)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cursor")
+            .field("namespace", &self.namespace)
+            .field("cursor_id", &self.cursor_id)
+            .field("host", &self.server_address())
+            .field("docs_returned", &self.count)
+            .field("buffered", &self.buffered_count())
+            .field("exhausted", &self.is_exhausted())
+            .field("last_batch", &self.batch_info())
+            .finish()
+    }
+}
+
+impl fmt::Display for Cursor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "cursor {} on {}: {} documents returned, {} buffered",
+            self.cursor_id,
+            self.namespace,
+            self.count,
+            self.buffered_count()
+        )?;
+
+        if self.is_exhausted() {
+            write!(f, ", exhausted")?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Cursor {
+    fn drop(&mut self) {
+        // Kill the server-side cursor if it wasn't exhausted, so that it doesn't linger until
+        // it times out.
+        if self.cursor_id != 0 {
+            if let Some(host) = self.host.take() {
+                queue_cursor_kill(&self.client, host, self.namespace.to_owned(), self.cursor_id);
+            }
+        }
+
+        self.set_cursor_id(0);
+    }
 }
 
 impl Iterator for Cursor {
@@ -546,10 +1579,72 @@ impl Iterator for Cursor {
         match self.has_next() {
             Ok(true) => {
                 self.count += 1;
-                self.buffer.pop_front().map(Ok)
+                let doc = match self.buffer.pop_front() {
+                    Some(doc) => Some(Ok(doc)),
+                    None => self.raw.next(),
+                };
+                self.prefetch_if_low();
+                doc.map(|doc| doc.and_then(|doc| self.decrypt(doc)))
             }
             Ok(false) => None,
             Err(err) => Some(Err(err)),
         }
     }
 }
+
+// Returns true if a query of the given type may be retried once after a transient error. Reads in
+// a transaction are retried by running the transaction again, and aggregations ending in an
+// `$out` or `$merge` stage write, so neither is retried.
+fn is_retryable_read(cmd_type: CommandType, query: &bson::Document) -> bool {
+    if !cmd_type.is_retryable_read() {
+        return false;
+    }
+
+    let command = match query.get("$query") {
+        Some(&Bson::Document(ref command)) => command,
+        _ => query,
+    };
+
+    if command.contains_key("txnNumber") {
+        return false;
+    }
+
+    match command.get("pipeline") {
+        Some(&Bson::Array(ref stages)) => {
+            match stages.last() {
+                Some(&Bson::Document(ref stage)) => {
+                    !stage.contains_key("$out") && !stage.contains_key("$merge")
+                }
+                _ => true,
+            }
+        }
+        _ => true,
+    }
+}
+
+// Returns true if an operation a mongos rejected with a stale config error may be sent again:
+// retryable reads, and writes the server recognizes as a retry by their session and transaction
+// number. Statements of a multi-document transaction are retried with the whole transaction.
+fn can_resend_after_stale_config(cmd_type: CommandType, query: &bson::Document) -> bool {
+    if is_retryable_read(cmd_type, query) {
+        return true;
+    }
+
+    let command = match query.get("$query") {
+        Some(&Bson::Document(ref command)) => command,
+        _ => query,
+    };
+
+    command.contains_key("lsid") && command.contains_key("txnNumber") &&
+        !command.contains_key("autocommit")
+}
+
+// Returns true if the error is a read that timed out.
+fn is_timeout(err: &Error) -> bool {
+    match *err {
+        Error::IoError(ref err) => {
+            err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut
+        }
+        _ => false,
+    }
+}
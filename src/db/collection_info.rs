@@ -0,0 +1,97 @@
+//! Collections and views as described by `listCollections`.
+use bson::{self, Bson};
+
+use Error::ResponseError;
+use Result;
+
+/// The kind of namespace a `listCollections` entry describes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CollectionType {
+    /// A collection storing documents.
+    Collection,
+    /// A read-only view, which runs a pipeline over another collection or view when queried
+    /// (MongoDB 3.4).
+    View,
+    /// A type added by a later server version, such as `timeseries`.
+    Other(String),
+}
+
+/// A collection or view of a database, as returned by `list_collection_infos`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CollectionInfo {
+    /// The name of the collection or view.
+    pub name: String,
+    /// Whether the namespace is a collection or a view. Servers before MongoDB 3.4 report no
+    /// type, and only have collections.
+    pub collection_type: CollectionType,
+    /// The collection or view a view reads from.
+    pub view_on: Option<String>,
+    /// The pipeline a view runs over the namespace it reads from.
+    pub pipeline: Vec<bson::Document>,
+    /// Whether writes to the namespace are rejected, as they are for every view.
+    pub read_only: bool,
+    /// The options the collection or view was created with.
+    pub options: bson::Document,
+}
+
+impl CollectionInfo {
+    /// Parses an entry returned by `listCollections` or read from `system.namespaces`.
+    pub fn from_document(doc: &bson::Document) -> Result<CollectionInfo> {
+        let name = match doc.get("name") {
+            Some(&Bson::String(ref name)) => name.to_owned(),
+            _ => return Err(ResponseError(String::from("Collection entry is missing 'name'."))),
+        };
+
+        let collection_type = match doc.get("type") {
+            Some(&Bson::String(ref kind)) if kind == "collection" => CollectionType::Collection,
+            Some(&Bson::String(ref kind)) if kind == "view" => CollectionType::View,
+            Some(&Bson::String(ref kind)) => CollectionType::Other(kind.to_owned()),
+            _ => CollectionType::Collection,
+        };
+
+        let options = match doc.get("options") {
+            Some(&Bson::Document(ref options)) => options.clone(),
+            _ => bson::Document::new(),
+        };
+
+        let view_on = match options.get("viewOn") {
+            Some(&Bson::String(ref view_on)) => Some(view_on.to_owned()),
+            _ => None,
+        };
+
+        let pipeline = match options.get("pipeline") {
+            Some(&Bson::Array(ref stages)) => {
+                stages
+                    .iter()
+                    .filter_map(|stage| match *stage {
+                        Bson::Document(ref stage) => Some(stage.clone()),
+                        _ => None,
+                    })
+                    .collect()
+            }
+            _ => Vec::new(),
+        };
+
+        let read_only = collection_type == CollectionType::View ||
+            match doc.get("info") {
+                Some(&Bson::Document(ref info)) => {
+                    info.get("readOnly") == Some(&Bson::Boolean(true))
+                }
+                _ => false,
+            };
+
+        Ok(CollectionInfo {
+            name: name,
+            collection_type: collection_type,
+            view_on: view_on,
+            pipeline: pipeline,
+            read_only: read_only,
+            options: options,
+        })
+    }
+
+    /// Returns true if the namespace is a view.
+    pub fn is_view(&self) -> bool {
+        self.collection_type == CollectionType::View
+    }
+}
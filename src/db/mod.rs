@@ -55,20 +55,43 @@
 //! }
 //! # }
 //! ```
+pub mod collection_info;
 pub mod options;
+pub mod pinned;
+pub mod profiler;
 pub mod roles;
 
 use auth::Authenticator;
 use bson::{self, bson, doc, Bson};
+use chrono::{self, Utc};
 use {Client, CommandType, ThreadedClient, Result};
-use Error::{CursorNotFoundError, OperationError, ResponseError};
+use error::{check_command_ok, CommandFailure};
+use Error::{self, ArgumentError, CodedError, CommandError, CursorNotFoundError,
+            OperationError, ResponseError, RetriesExhaustedError, UnsupportedByServerError};
+use ErrorCode;
 use coll::Collection;
-use coll::options::FindOptions;
-use common::{ReadPreference, merge_options, WriteConcern};
+use coll::index_stats::UnusedIndex;
+use coll::validator::WriteValidators;
+use coll::options::{Collation, FindOptions, ReplaceOptions};
+use common::{ReadConcern, ReadMode, ReadPreference, merge_options, RetryPolicy, WriteConcern};
+use connstring::Host;
 use cursor::{Cursor, DEFAULT_BATCH_SIZE};
-use self::options::{CreateCollectionOptions, CreateUserOptions, UserInfoOptions};
+use logging::LogLevel;
+use op_ctx::OpCtx;
+use self::collection_info::CollectionInfo;
+use self::options::{CollectionNamesOptions, CreateCollectionOptions, CreateUserOptions,
+                    ListCollectionsOptions, NameFilter, UserInfoOptions};
+use self::pinned::PinnedConnection;
+use self::profiler::{ProfileEntry, ProfilingLevel};
+use session::ClientSession;
 use semver::Version;
+use topology::capabilities::ServerCapabilities;
+use wire_protocol::flags::OpQueryFlags;
+use wire_protocol::operations::Message;
+use std::result;
 use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// Interfaces with a MongoDB database.
 #[derive(Debug)]
@@ -79,28 +102,105 @@ pub struct DatabaseInner {
     pub client: Client,
     /// Indicates how a server should be selected for read operations.
     pub read_preference: ReadPreference,
+    /// The consistency and isolation guarantees requested for read operations.
+    pub read_concern: Option<ReadConcern>,
     /// Describes the guarantees provided by MongoDB when reporting the success of a write
     /// operation.
     pub write_concern: WriteConcern,
+    /// The checks run on documents written through the database's collections, before those
+    /// registered on the collections themselves.
+    pub write_validators: WriteValidators,
 }
 
 pub type Database = Arc<DatabaseInner>;
 
+/// The unprocessed outcome of `run_raw_command`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RawCommandResult {
+    /// The reply exactly as sent by the server, including a failed `ok`.
+    pub reply: bson::Document,
+    /// The time from sending the command to receiving the reply.
+    pub duration: Duration,
+    /// The request id the command was sent with.
+    pub request_id: i32,
+    /// The server the command ran against.
+    pub host: Host,
+}
+
+// The maximum length of a database name, in bytes.
+const MAX_DATABASE_NAME_BYTES: usize = 64;
+
+// Characters that database names cannot contain on any platform.
+const INVALID_DATABASE_NAME_CHARS: &[char] =
+    &['/', '\\', '.', '"', '$', '*', '<', '>', ':', '|', '?', '\0'];
+
+/// Checks that a database name is valid on every platform: it must not be empty, be longer than
+/// 64 bytes, or contain any of `/\. "$*<>:|?` or a null byte.
+pub fn validate_database_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        return Err(ArgumentError(String::from("Database names cannot be empty.")));
+    }
+
+    if name.len() > MAX_DATABASE_NAME_BYTES {
+        return Err(ArgumentError(format!(
+            "Database name '{}' is {} bytes long, but can be at most {} bytes.",
+            name,
+            name.len(),
+            MAX_DATABASE_NAME_BYTES
+        )));
+    }
+
+    if let Some(c) = name.chars().find(|c| INVALID_DATABASE_NAME_CHARS.contains(c)) {
+        return Err(ArgumentError(format!(
+            "Database name {:?} cannot contain {:?}.",
+            name,
+            c
+        )));
+    }
+
+    Ok(())
+}
+
 pub trait ThreadedDatabase {
-    /// Creates a database representation with optional read and write controls.
+    /// Creates a database representation with optional read and write controls, after checking
+    /// the database name with `validate_database_name`.
     fn open(
         client: Client,
         name: &str,
         read_preference: Option<ReadPreference>,
         write_concern: Option<WriteConcern>,
+    ) -> Result<Database>;
+    /// Creates a database representation with optional read and write controls, without
+    /// checking the database name.
+    fn open_unchecked(
+        client: Client,
+        name: &str,
+        read_preference: Option<ReadPreference>,
+        write_concern: Option<WriteConcern>,
     ) -> Database;
+    /// Creates a copy of the database representation with a different read concern.
+    fn with_read_concern(&self, read_concern: Option<ReadConcern>) -> Database;
+    /// Creates a copy of the database representation that also runs `validator` on every
+    /// document written through its collections; see `Collection::set_write_validator`.
+    /// Database validators run before the collection's own, in the order they were added.
+    fn with_write_validator<F>(&self, validator: F) -> Database
+    where
+        F: Fn(&bson::Document) -> result::Result<(), String> + Send + Sync + 'static;
     // Returns the version of the MongoDB instance.
     fn version(&self) -> Result<Version>;
     /// Logs in a user using the SCRAM-SHA-1 mechanism.
     fn auth(&self, user: &str, password: &str) -> Result<()>;
     /// Creates a collection representation with inherited read and write controls.
+    ///
+    /// Panics if the name is invalid according to `coll::validate_collection_name`; use
+    /// `get_collection` to handle invalid names.
     fn collection(&self, coll_name: &str) -> Collection;
+    /// Creates a collection representation with inherited read and write controls, after
+    /// checking the collection name with `coll::validate_collection_name`.
+    fn get_collection(&self, coll_name: &str) -> Result<Collection>;
     /// Creates a collection representation with custom read and write controls.
+    ///
+    /// Panics if the name is invalid, like `collection`.
     fn collection_with_prefs(
         &self,
         coll_name: &str,
@@ -124,6 +224,63 @@ pub trait ThreadedDatabase {
         cmd_type: CommandType,
         read_preference: Option<ReadPreference>,
     ) -> Result<bson::Document>;
+    /// Sends an administrative command, failing with a `CommandError` describing the failure if
+    /// the reply's `ok` field reports that the command failed.
+    fn run_command_checked(
+        &self,
+        spec: bson::Document,
+        cmd_type: CommandType,
+        read_preference: Option<ReadPreference>,
+    ) -> Result<bson::Document>;
+    /// Sends a command to a server selected with the database's read preference, and returns
+    /// the reply along with when and where it ran, without interpreting it. No command events
+    /// are emitted, and a reply reporting a failure is still returned as `Ok`; pass it to
+    /// `error::check_command_ok` to interpret it.
+    fn run_raw_command(&self, spec: bson::Document) -> Result<RawCommandResult>;
+    /// Sends an administrative command within `ctx`. The time left before the deadline is sent
+    /// as `maxTimeMS`, unless the spec already sets a shorter one.
+    fn run_command_with_ctx(
+        &self,
+        spec: bson::Document,
+        cmd_type: CommandType,
+        read_preference: Option<ReadPreference>,
+        ctx: &OpCtx,
+    ) -> Result<bson::Document>;
+    /// Sends an administrative command under a logical session.
+    fn command_with_session(
+        &self,
+        spec: bson::Document,
+        cmd_type: CommandType,
+        read_preference: Option<ReadPreference>,
+        session: &mut ClientSession,
+    ) -> Result<bson::Document>;
+    /// Runs a command returning a cursor under a logical session. The cursor sends its getMores
+    /// to the server that ran the command, under the session and, while the session is in a
+    /// transaction, as part of the transaction.
+    fn command_cursor_with_session(
+        &self,
+        spec: bson::Document,
+        cmd_type: CommandType,
+        read_preference: ReadPreference,
+        session: &mut ClientSession,
+    ) -> Result<Cursor>;
+    /// Sends a write command, optionally under a logical session. If the client has retryable
+    /// writes enabled and the deployment supports them, a transaction number is attached to
+    /// supported commands and they are retried once on a transient error.
+    fn retryable_write_command(
+        &self,
+        spec: bson::Document,
+        cmd_type: CommandType,
+        session: Option<&mut ClientSession>,
+    ) -> Result<bson::Document>;
+    /// Sends an administrative command, retrying it according to `policy` if the server
+    /// reports that it is not the primary or the connection fails.
+    fn run_command_retry(
+        &self,
+        spec: bson::Document,
+        cmd_type: CommandType,
+        policy: RetryPolicy,
+    ) -> Result<bson::Document>;
     /// Returns a list of collections within the database.
     fn list_collections(&self, filter: Option<bson::Document>) -> Result<Cursor>;
     /// Returns a list of collections within the database with a custom batch size.
@@ -132,14 +289,52 @@ pub trait ThreadedDatabase {
         filter: Option<bson::Document>,
         batch_size: i32,
     ) -> Result<Cursor>;
+    /// Returns the collections within the database matching the filter, e.g.
+    /// `{ "name": { "$regex": "^logs\\." } }`. The collections are fetched in batches as the
+    /// cursor is read, so databases with any number of collections can be listed.
+    ///
+    /// Servers older than MongoDB 2.8 have no `listCollections` command, so their
+    /// `system.namespaces` collection is read instead, leaving out index namespaces and internal
+    /// `$` entries. On these servers, a filter on `name` must be a plain string.
+    fn list_collections_with_options(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<ListCollectionsOptions>,
+    ) -> Result<Cursor>;
     /// Returns a list of collection names within the database.
     fn collection_names(&self, filter: Option<bson::Document>) -> Result<Vec<String>>;
+    /// Returns the sorted names of the collections within the database, leaving out the
+    /// `system.*` collections unless `include_system` is set. A name filter is sent to the
+    /// server as part of the `listCollections` filter, or of the query on `system.namespaces`
+    /// for servers older than MongoDB 2.8, which can only filter by prefix.
+    fn collection_names_with_options(
+        &self,
+        options: Option<CollectionNamesOptions>,
+    ) -> Result<Vec<String>>;
+    /// Returns the collections and views within the database matching the filter, with their
+    /// type and, for views, the namespace and pipeline they read through.
+    fn list_collection_infos(&self, filter: Option<bson::Document>)
+        -> Result<Vec<CollectionInfo>>;
+    /// Returns true if the database has a collection or view with the given name. The server
+    /// only returns the named collection, so this doesn't list the whole database.
+    fn collection_exists(&self, name: &str) -> Result<bool>;
     /// Creates a new collection.
     ///
     /// Note that due to the implicit creation of collections during insertion, this
     /// method should only be used to instantiate capped collections.
     fn create_collection(&self, name: &str, options: Option<CreateCollectionOptions>)
         -> Result<()>;
+    /// Creates a read-only view named `name`, which runs `pipeline` over the collection or
+    /// view `view_on` when it is queried, comparing strings with `collation` if set. Finds,
+    /// aggregations and counts on the view work as on a collection, while writes fail with a
+    /// `ViewWriteError`. Requires MongoDB 3.4 or later.
+    fn create_view(
+        &self,
+        name: &str,
+        view_on: &str,
+        pipeline: Vec<bson::Document>,
+        collation: Option<Collation>,
+    ) -> Result<()>;
     /// Creates a new user.
     fn create_user(
         &self,
@@ -165,6 +360,48 @@ pub trait ThreadedDatabase {
         users: Vec<&str>,
         options: Option<UserInfoOptions>,
     ) -> Result<Vec<bson::Document>>;
+    /// Sets which operations the database profiler records and, optionally, the threshold in
+    /// milliseconds above which operations are considered slow.
+    fn set_profiling_level(&self, level: ProfilingLevel, slow_ms: Option<i32>) -> Result<()>;
+    /// Returns which operations the database profiler currently records.
+    fn get_profiling_level(&self) -> Result<ProfilingLevel>;
+    /// Returns the most recent entries of `system.profile` matching the filter, newest first.
+    fn get_profiling_info(
+        &self,
+        filter: Option<bson::Document>,
+        limit: Option<i64>,
+    ) -> Result<Vec<ProfileEntry>>;
+    /// Runs JavaScript code on the primary with the deprecated `eval` command, passing `args`
+    /// as the function's arguments, and returns its return value. Unless `nolock` is set, the
+    /// server holds a global write lock while the code runs. Fails with an
+    /// `UnsupportedByServerError` on MongoDB 4.2 and later, which removed `eval`.
+    fn eval(&self, code: &str, args: Vec<Bson>, nolock: bool) -> Result<Bson>;
+    /// Copies the collection `namespace`, given as `db.collection`, from the server at `from`,
+    /// given as `host:port`, into this database with the `cloneCollection` command, optionally
+    /// only the documents matching `filter`. The server copies the documents and indexes
+    /// itself, without them passing through the client, but can't copy from itself. Fails
+    /// with an `UnsupportedByServerError` on MongoDB 4.2 and later, which removed the command;
+    /// use `Collection::copy_to` there.
+    fn clone_collection(
+        &self,
+        from: &str,
+        namespace: &str,
+        filter: Option<bson::Document>,
+    ) -> Result<()>;
+    /// Takes a connection to the primary out of the pool for a sequence of legacy writes
+    /// followed by `getLastError` or `getPrevError`, which only report on the writes sent over
+    /// the same connection.
+    fn pin_connection(&self) -> Result<PinnedConnection>;
+    /// Stores a JavaScript function in the `system.js` collection under `name`, replacing any
+    /// function with the same name, so that it can be called by server-side JavaScript.
+    fn save_function(&self, name: &str, code: &str) -> Result<()>;
+    /// Returns the names of the functions stored in the `system.js` collection.
+    fn list_functions(&self) -> Result<Vec<String>>;
+    /// Returns the indexes of every collection that no operation has used for at least
+    /// `min_age`, according to `Collection::index_stats`. The `_id` index is never reported,
+    /// since it can't be dropped. Statistics reset when a server restarts, so a recently
+    /// restarted deployment reports no candidates until `min_age` has passed.
+    fn unused_indexes(&self, min_age: chrono::Duration) -> Result<Vec<UnusedIndex>>;
 }
 
 impl ThreadedDatabase for Database {
@@ -173,15 +410,55 @@ impl ThreadedDatabase for Database {
         name: &str,
         read_preference: Option<ReadPreference>,
         write_concern: Option<WriteConcern>,
+    ) -> Result<Database> {
+        validate_database_name(name)?;
+        Ok(Database::open_unchecked(client, name, read_preference, write_concern))
+    }
+
+    fn open_unchecked(
+        client: Client,
+        name: &str,
+        read_preference: Option<ReadPreference>,
+        write_concern: Option<WriteConcern>,
     ) -> Database {
         let rp = read_preference.unwrap_or_else(|| client.read_preference.to_owned());
         let wc = write_concern.unwrap_or_else(|| client.write_concern.to_owned());
 
         Arc::new(DatabaseInner {
             name: String::from(name),
+            read_concern: client.read_concern,
             client: client,
             read_preference: rp,
             write_concern: wc,
+            write_validators: WriteValidators::new(),
+        })
+    }
+
+    fn with_read_concern(&self, read_concern: Option<ReadConcern>) -> Database {
+        Arc::new(DatabaseInner {
+            name: self.name.to_owned(),
+            client: self.client.clone(),
+            read_preference: self.read_preference.to_owned(),
+            read_concern: read_concern,
+            write_concern: self.write_concern.to_owned(),
+            write_validators: self.write_validators.clone(),
+        })
+    }
+
+    fn with_write_validator<F>(&self, validator: F) -> Database
+    where
+        F: Fn(&bson::Document) -> result::Result<(), String> + Send + Sync + 'static,
+    {
+        let mut write_validators = self.write_validators.clone();
+        write_validators.push(Arc::new(validator));
+
+        Arc::new(DatabaseInner {
+            name: self.name.to_owned(),
+            client: self.client.clone(),
+            read_preference: self.read_preference.to_owned(),
+            read_concern: self.read_concern,
+            write_concern: self.write_concern.to_owned(),
+            write_validators: write_validators,
         })
     }
 
@@ -191,6 +468,15 @@ impl ThreadedDatabase for Database {
     }
 
     fn collection(&self, coll_name: &str) -> Collection {
+        self.collection_with_prefs(
+            coll_name,
+            false,
+            Some(self.read_preference.to_owned()),
+            Some(self.write_concern.to_owned()),
+        )
+    }
+
+    fn get_collection(&self, coll_name: &str) -> Result<Collection> {
         Collection::new(
             self.clone(),
             coll_name,
@@ -207,13 +493,10 @@ impl ThreadedDatabase for Database {
         read_preference: Option<ReadPreference>,
         write_concern: Option<WriteConcern>,
     ) -> Collection {
-        Collection::new(
-            self.clone(),
-            coll_name,
-            create,
-            read_preference,
-            write_concern,
-        )
+        match Collection::new(self.clone(), coll_name, create, read_preference, write_concern) {
+            Ok(coll) => coll,
+            Err(err) => panic!("{}", err),
+        }
     }
 
     fn get_req_id(&self) -> i32 {
@@ -241,23 +524,286 @@ impl ThreadedDatabase for Database {
         cmd_type: CommandType,
         read_preference: Option<ReadPreference>,
     ) -> Result<bson::Document> {
+        match self.client.retry_policy {
+            Some(policy) if cmd_type.is_write_command() && cmd_type.is_idempotent() => {
+                self.run_command_retry(spec, cmd_type, policy)
+            }
+            _ => run_command(self, spec, cmd_type, read_preference),
+        }
+    }
+
+    fn run_command_checked(
+        &self,
+        spec: bson::Document,
+        cmd_type: CommandType,
+        read_preference: Option<ReadPreference>,
+    ) -> Result<bson::Document> {
+        let reply = self.command(spec, cmd_type, read_preference)?;
+        check_command_ok(&reply)?;
+        Ok(reply)
+    }
+
+    fn run_raw_command(&self, spec: bson::Document) -> Result<RawCommandResult> {
+        let read_preference = self.read_preference.clone();
+        let (mut stream, slave_ok, send_read_pref) =
+            self.client.acquire_stream(read_preference.clone())?;
+
+        let flags = if slave_ok {
+            OpQueryFlags::SLAVE_OK
+        } else {
+            OpQueryFlags::empty()
+        };
+
+        let spec = if send_read_pref {
+            doc! { "$query": spec, "$readPreference": read_preference.to_document() }
+        } else {
+            spec
+        };
+
+        let host = stream.host().clone();
+        let request_id = self.client.get_req_id();
+        let message = Message::new_query(
+            request_id,
+            flags,
+            format!("{}.$cmd", self.name),
+            0,
+            -1,
+            spec,
+            None,
+        )?;
+
+        self.client.log_message(true, &host, &message);
+
+        let start = Instant::now();
+        let result = {
+            let socket = stream.get_socket();
+            message.write(socket).and_then(|()| {
+                Message::read_reply_to(socket, request_id, self.client.max_bson_depth)
+            })
+        };
+        let duration = start.elapsed();
+        self.client.record_round_trip(start, &result);
+
+        if let Ok(ref reply) = result {
+            self.client.log_message(false, &host, reply);
+            self.client.slow_ops.record(start, &message, reply, &host);
+        }
+
+        // A connection left in the middle of an exchange must not be reused.
+        if result.is_err() {
+            stream.set_dirty(true);
+        }
+
+        let reply = match result? {
+            Message::OpReply { mut documents, .. } => {
+                match documents.next() {
+                    Some(reply) => reply?,
+                    None => return Err(ResponseError(String::from("Empty command reply."))),
+                }
+            }
+            _ => return Err(ResponseError(String::from("Invalid command reply."))),
+        };
+
+        Ok(RawCommandResult {
+            reply: reply,
+            duration: duration,
+            request_id: request_id,
+            host: host,
+        })
+    }
+
+    fn run_command_with_ctx(
+        &self,
+        mut spec: bson::Document,
+        cmd_type: CommandType,
+        read_preference: Option<ReadPreference>,
+        ctx: &OpCtx,
+    ) -> Result<bson::Document> {
+        let max_time_ms = match spec.get("maxTimeMS") {
+            Some(&Bson::I32(max_time_ms)) => Some(i64::from(max_time_ms)),
+            Some(&Bson::I64(max_time_ms)) => Some(max_time_ms),
+            _ => None,
+        };
+
+        if let Some(max_time_ms) = ctx.limit_max_time_ms(max_time_ms) {
+            spec.insert("maxTimeMS", max_time_ms);
+        }
 
-        let coll = self.collection("$cmd");
         let options = FindOptions {
             batch_size: Some(1),
-            read_preference,
+            limit: Some(1),
             ..FindOptions::new()
         };
-        let res = coll.find_one_with_command_type(
-            Some(spec.clone()),
-            Some(options),
+
+        let mut cursor = Cursor::query_with_ctx(
+            self.client.clone(),
+            format!("{}.$cmd", self.name),
+            OpQueryFlags::with_find_options(&options),
+            spec.clone(),
+            options,
             cmd_type,
+            false,
+            read_preference.unwrap_or_else(|| self.read_preference.clone()),
+            Some(ctx),
         )?;
-        res.ok_or_else(|| {
-            OperationError(format!("Failed to execute command with spec {:?}.", spec))
+
+        match cursor.next() {
+            Some(result) => result,
+            None => Err(OperationError(
+                format!("Failed to execute command with spec {:?}.", spec),
+            )),
+        }
+    }
+
+    fn command_with_session(
+        &self,
+        spec: bson::Document,
+        cmd_type: CommandType,
+        read_preference: Option<ReadPreference>,
+        session: &mut ClientSession,
+    ) -> Result<bson::Document> {
+        let mut spec = spec;
+        let read_preference =
+            prepare_session_command(self, &mut spec, cmd_type, read_preference, session)?;
+
+        let reply = self.command(spec, cmd_type, read_preference)?;
+        session.process_reply(&reply);
+
+        if cmd_type.is_write_command() {
+            session.record_write();
+        }
+
+        Ok(reply)
+    }
+
+    fn command_cursor_with_session(
+        &self,
+        spec: bson::Document,
+        cmd_type: CommandType,
+        read_preference: ReadPreference,
+        session: &mut ClientSession,
+    ) -> Result<Cursor> {
+        let mut spec = spec;
+        let read_preference =
+            prepare_session_command(self, &mut spec, cmd_type, Some(read_preference), session)?
+                .unwrap_or_else(|| self.read_preference.clone());
+
+        let (reply, host) =
+            run_command_with_host(self, spec, cmd_type, Some(read_preference.clone()))?;
+        session.process_reply(&reply);
+
+        let cursor = match reply.get("cursor") {
+            Some(&Bson::Document(ref cursor)) => cursor.clone(),
+            _ => return Err(ResponseError(String::from("No cursor received from server."))),
+        };
+
+        let cursor = Cursor::from_cursor_document(
+            self.client.clone(),
+            cursor,
+            cmd_type,
+            read_preference,
+        )?.with_session(session);
+
+        // The cursor only exists on the server that ran the command, whichever server the read
+        // preference would select next.
+        Ok(match host {
+            Some(host) => cursor.with_host(host),
+            None => cursor,
         })
     }
 
+    fn retryable_write_command(
+        &self,
+        spec: bson::Document,
+        cmd_type: CommandType,
+        session: Option<&mut ClientSession>,
+    ) -> Result<bson::Document> {
+        // Writes in a transaction are retried by committing the transaction again.
+        let in_transaction = session.as_ref().map_or(false, |session| session.in_transaction());
+
+        let retryable = !in_transaction && self.client.retry_writes &&
+            cmd_type.is_retryable_write_command(&spec) &&
+            self.client.topology.supports_retryable_writes()?;
+
+        if !retryable {
+            return match session {
+                Some(session) => self.command_with_session(spec, cmd_type, None, session),
+                None => self.command(spec, cmd_type, None),
+            };
+        }
+
+        // Retryable writes require a session, so start an implicit one if none was provided.
+        let mut implicit_session;
+        let session = match session {
+            Some(session) => session,
+            None => {
+                implicit_session = self.client.start_session()?;
+                &mut implicit_session
+            }
+        };
+
+        let mut spec = spec;
+        session.apply(&mut spec)?;
+        spec.insert("txnNumber", session.next_txn_number());
+
+        let reply = match self.command(spec.clone(), cmd_type, None) {
+            Err(ref err) if err.is_retryable_write() => {
+                self.client.log(LogLevel::Warn, "retry", || {
+                    format!("Retrying {} after error: {}", cmd_type.to_str(), err)
+                });
+
+                // Retry exactly once against the newly selected primary with the same
+                // transaction number, so the server applies the write at most once.
+                self.client.topology.request_updates()?;
+                self.command(spec, cmd_type, None)?
+            }
+            result => result?,
+        };
+
+        session.process_reply(&reply);
+        session.record_write();
+        Ok(reply)
+    }
+
+    fn run_command_retry(
+        &self,
+        spec: bson::Document,
+        cmd_type: CommandType,
+        policy: RetryPolicy,
+    ) -> Result<bson::Document> {
+        let mut attempt = 1;
+
+        loop {
+            let err = match run_command(self, spec.clone(), cmd_type, None) {
+                Ok(doc) => return Ok(doc),
+                Err(err) => err,
+            };
+
+            if !err.is_retryable_write() {
+                return Err(err);
+            }
+
+            if attempt >= policy.max_attempts {
+                return Err(RetriesExhaustedError(attempt, Box::new(err)));
+            }
+
+            self.client.log(LogLevel::Warn, "retry", || {
+                format!(
+                    "Attempt {} of {} for {} failed: {}",
+                    attempt,
+                    policy.max_attempts,
+                    cmd_type.to_str(),
+                    err
+                )
+            });
+
+            // Give the topology a chance to discover the new primary before trying again.
+            self.client.topology.request_updates()?;
+            thread::sleep(policy.backoff(attempt));
+            attempt += 1;
+        }
+    }
+
     fn list_collections(&self, filter: Option<bson::Document>) -> Result<Cursor> {
         self.list_collections_with_batch_size(filter, DEFAULT_BATCH_SIZE)
     }
@@ -267,6 +813,26 @@ impl ThreadedDatabase for Database {
         filter: Option<bson::Document>,
         batch_size: i32,
     ) -> Result<Cursor> {
+        let options = ListCollectionsOptions {
+            batch_size: Some(batch_size),
+            ..Default::default()
+        };
+
+        self.list_collections_with_options(filter, Some(options))
+    }
+
+    fn list_collections_with_options(
+        &self,
+        filter: Option<bson::Document>,
+        options: Option<ListCollectionsOptions>,
+    ) -> Result<Cursor> {
+        let options = options.unwrap_or_default();
+        let batch_size = options.batch_size.unwrap_or(DEFAULT_BATCH_SIZE);
+
+        // listCollections was added in MongoDB 2.8.
+        if !self.client.topology.supports(ServerCapabilities::supports_list_commands)? {
+            return list_legacy_collections(self, filter, None, batch_size);
+        }
 
         let mut spec = doc!{
             "listCollections": 1,
@@ -278,6 +844,13 @@ impl ThreadedDatabase for Database {
             spec.insert("filter", f);
         }
 
+        let topology = &self.client.topology;
+        if options.name_only &&
+            topology.supports(ServerCapabilities::supports_list_collections_name_only)?
+        {
+            spec.insert("nameOnly", true);
+        }
+
         self.command_cursor(
             spec,
             CommandType::ListCollections,
@@ -285,8 +858,22 @@ impl ThreadedDatabase for Database {
         )
     }
 
-    fn collection_names(&self, filter: Option<bson::Document>) -> Result<Vec<String>> {
+    fn list_collection_infos(
+        &self,
+        filter: Option<bson::Document>,
+    ) -> Result<Vec<CollectionInfo>> {
         self.list_collections(filter)?
+            .map(|doc| doc.and_then(|doc| CollectionInfo::from_document(&doc)))
+            .collect()
+    }
+
+    fn collection_names(&self, filter: Option<bson::Document>) -> Result<Vec<String>> {
+        let options = ListCollectionsOptions {
+            name_only: true,
+            ..Default::default()
+        };
+
+        self.list_collections_with_options(filter, Some(options))?
             .filter_map(|result| match result {
                 Err(err) => Some(Err(err)),
                 Ok(mut doc) => match doc.remove("name") {
@@ -297,9 +884,99 @@ impl ThreadedDatabase for Database {
             .collect()
     }
 
+    fn collection_names_with_options(
+        &self,
+        options: Option<CollectionNamesOptions>,
+    ) -> Result<Vec<String>> {
+        let options = options.unwrap_or_default();
+        let list_options = ListCollectionsOptions {
+            name_only: true,
+            ..Default::default()
+        };
+
+        let supports_list_commands =
+            self.client.topology.supports(ServerCapabilities::supports_list_commands)?;
+
+        let cursor = if supports_list_commands {
+            let mut conditions = Vec::new();
+            match options.name_filter {
+                Some(NameFilter::Prefix(ref prefix)) => {
+                    let pattern = format!("^{}", escape_regex(prefix));
+                    conditions.push(doc! { "name": Bson::RegExp(pattern, String::new()) });
+                }
+                Some(NameFilter::Regex(ref pattern)) => {
+                    let pattern = pattern.to_owned();
+                    conditions.push(doc! { "name": Bson::RegExp(pattern, String::new()) });
+                }
+                None => (),
+            }
+
+            if !options.include_system {
+                let system = Bson::RegExp(String::from("^system\\."), String::new());
+                conditions.push(doc! { "name": { "$not": system } });
+            }
+
+            let filter = if conditions.len() > 1 {
+                let conditions: Vec<_> = conditions.into_iter().map(Bson::Document).collect();
+                Some(doc! { "$and": conditions })
+            } else {
+                conditions.pop()
+            };
+
+            self.list_collections_with_options(filter, Some(list_options))?
+        } else {
+            let prefix = match options.name_filter {
+                Some(NameFilter::Prefix(ref prefix)) => Some(prefix.as_str()),
+                Some(NameFilter::Regex(_)) => {
+                    return Err(ArgumentError(String::from(
+                        "Servers older than MongoDB 2.8 can't filter collections by regular \
+                         expression.",
+                    )))
+                }
+                None => None,
+            };
+
+            list_legacy_collections(self, None, prefix, DEFAULT_BATCH_SIZE)?
+        };
+
+        let mut names = Vec::new();
+        for result in cursor {
+            let name = match result?.remove("name") {
+                Some(Bson::String(name)) => name,
+                _ => continue,
+            };
+
+            // Entries for indexes and internal namespaces contain a `$`.
+            if name.contains('$') || (!options.include_system && name.starts_with("system.")) {
+                continue;
+            }
+            names.push(name);
+        }
+
+        names.sort();
+        Ok(names)
+    }
+
+    fn collection_exists(&self, name: &str) -> Result<bool> {
+        let options = ListCollectionsOptions {
+            batch_size: Some(1),
+            name_only: true,
+        };
+
+        let mut cursor = self.list_collections_with_options(
+            Some(doc! { "name": name }),
+            Some(options),
+        )?;
+
+        match cursor.next() {
+            Some(result) => result.map(|_| true),
+            None => Ok(false),
+        }
+    }
+
     fn version(&self) -> Result<Version> {
         let doc = doc! { "buildinfo": 1 };
-        let out = self.command(doc, CommandType::BuildInfo, None)?;
+        let out = self.run_command_checked(doc, CommandType::BuildInfo, None)?;
 
         match out.get("version") {
             Some(&Bson::String(ref s)) => {
@@ -321,15 +998,48 @@ impl ThreadedDatabase for Database {
     ) -> Result<()> {
         let mut doc = doc! { "create": name };
 
+        let clustered = options.as_ref().map_or(false, |options| options.clustered_index.is_some());
+        let topology = &self.client.topology;
+        if clustered && !topology.supports(ServerCapabilities::supports_clustered_collections)? {
+            return Err(ArgumentError(
+                String::from("Clustered collections require MongoDB 5.3 or later."),
+            ));
+        }
+
         if let Some(create_collection_options) = options {
             doc = merge_options(doc, create_collection_options);
         }
 
-        self.command(doc, CommandType::CreateCollection, None)?;
+        self.run_command_checked(doc, CommandType::CreateCollection, None)?;
 
         Ok(())
     }
 
+    fn create_view(
+        &self,
+        name: &str,
+        view_on: &str,
+        pipeline: Vec<bson::Document>,
+        collation: Option<Collation>,
+    ) -> Result<()> {
+        if !self.client.topology.supports(ServerCapabilities::supports_views)? {
+            return Err(ArgumentError(String::from("Views require MongoDB 3.4 or later.")));
+        }
+
+        let stages: Vec<_> = pipeline.into_iter().map(Bson::Document).collect();
+        let mut spec = doc! {
+            "create": name,
+            "viewOn": view_on,
+            "pipeline": stages,
+        };
+
+        if let Some(collation) = collation {
+            spec.insert("collation", collation.to_document());
+        }
+
+        self.run_command_checked(spec, CommandType::CreateCollection, None).map(drop)
+    }
+
     fn create_user(
         &self,
         name: &str,
@@ -350,7 +1060,7 @@ impl ThreadedDatabase for Database {
             }
         };
 
-        self.command(doc, CommandType::CreateUser, None).map(drop)
+        self.run_command_checked(doc, CommandType::CreateUser, None).map(drop)
     }
 
     fn drop_all_users(&self, write_concern: Option<WriteConcern>) -> Result<i32> {
@@ -360,7 +1070,7 @@ impl ThreadedDatabase for Database {
             doc.insert("writeConcern", concern.to_bson());
         }
 
-        let response = self.command(doc, CommandType::DropAllUsers, None)?;
+        let response = self.run_command_checked(doc, CommandType::DropAllUsers, None)?;
 
         match response.get("n") {
             Some(&Bson::I32(i)) => Ok(i),
@@ -371,12 +1081,16 @@ impl ThreadedDatabase for Database {
 
     fn drop_collection(&self, name: &str) -> Result<()> {
         let spec = doc!{ "drop": name };
-        self.command(spec, CommandType::DropCollection, None).map(drop)
+        match self.run_command_checked(spec, CommandType::DropCollection, None) {
+            // Dropping a collection that doesn't exist succeeds.
+            Err(CommandError(ref failure)) if is_namespace_not_found(failure) => Ok(()),
+            result => result.map(drop),
+        }
     }
 
     fn drop_database(&self) -> Result<()> {
         let spec = doc!{ "dropDatabase": 1 };
-        self.command(spec, CommandType::DropDatabase, None).map(drop)
+        self.run_command_checked(spec, CommandType::DropDatabase, None).map(drop)
     }
 
     fn drop_user(&self, name: &str, write_concern: Option<WriteConcern>) -> Result<()> {
@@ -386,7 +1100,7 @@ impl ThreadedDatabase for Database {
             doc.insert("writeConcern", concern.to_bson());
         }
 
-        self.command(doc, CommandType::DropUser, None).map(drop)
+        self.run_command_checked(doc, CommandType::DropUser, None).map(drop)
     }
 
     fn get_all_users(&self, show_credentials: bool) -> Result<Vec<bson::Document>> {
@@ -395,7 +1109,7 @@ impl ThreadedDatabase for Database {
             "showCredentials": show_credentials
         };
 
-        let out = self.command(doc, CommandType::GetUsers, None)?;
+        let out = self.run_command_checked(doc, CommandType::GetUsers, None)?;
 
         let vec = match out.get("users") {
             Some(&Bson::Array(ref vec)) => vec.clone(),
@@ -422,7 +1136,7 @@ impl ThreadedDatabase for Database {
             doc = merge_options(doc, user_info_options);
         }
 
-        let out = self.command(doc, CommandType::GetUser, None)?;
+        let out = self.run_command_checked(doc, CommandType::GetUser, None)?;
         let users = match out.get("users") {
             Some(&Bson::Array(ref v)) => v.clone(),
             _ => return Err(CursorNotFoundError),
@@ -453,7 +1167,7 @@ impl ThreadedDatabase for Database {
             doc = merge_options(doc, user_info_options);
         }
 
-        let out = self.command(doc, CommandType::GetUsers, None)?;
+        let out = self.run_command_checked(doc, CommandType::GetUsers, None)?;
         let vec = match out.get("users") {
             Some(&Bson::Array(ref vec)) => vec.clone(),
             _ => return Err(CursorNotFoundError),
@@ -466,4 +1180,344 @@ impl ThreadedDatabase for Database {
             })
             .collect()
     }
+
+    fn set_profiling_level(&self, level: ProfilingLevel, slow_ms: Option<i32>) -> Result<()> {
+        let mut doc = doc! { "profile": level.to_i32() };
+
+        if let Some(slow_ms) = slow_ms {
+            doc.insert("slowms", slow_ms);
+        }
+
+        self.run_command_checked(doc, CommandType::Profile, None).map(drop)
+    }
+
+    fn get_profiling_level(&self) -> Result<ProfilingLevel> {
+        let doc = doc! { "profile": -1 };
+        let out = self.run_command_checked(doc, CommandType::Profile, None)?;
+
+        match out.get("was") {
+            Some(&Bson::I32(level)) => ProfilingLevel::from_i32(level),
+            Some(&Bson::I64(level)) => ProfilingLevel::from_i32(level as i32),
+            Some(&Bson::FloatingPoint(level)) => ProfilingLevel::from_i32(level as i32),
+            _ => Err(ResponseError(
+                String::from("No profiling level received from server"),
+            )),
+        }
+    }
+
+    fn get_profiling_info(
+        &self,
+        filter: Option<bson::Document>,
+        limit: Option<i64>,
+    ) -> Result<Vec<ProfileEntry>> {
+        let options = FindOptions {
+            sort: Some(doc! { "$natural": -1 }),
+            limit: limit,
+            ..FindOptions::new()
+        };
+
+        let cursor = self.collection("system.profile").find(filter, Some(options))?;
+
+        cursor
+            .map(|doc| doc.and_then(ProfileEntry::from_document))
+            .collect()
+    }
+
+    fn eval(&self, code: &str, args: Vec<Bson>, nolock: bool) -> Result<Bson> {
+        let spec = doc! {
+            "eval": Bson::JavaScriptCode(String::from(code)),
+            "args": args,
+            "nolock": nolock,
+        };
+
+        let primary = ReadPreference::new(ReadMode::Primary, None);
+        let mut reply = self.run_command_checked(spec, CommandType::Eval, Some(primary))
+            .map_err(|err| {
+                removed_command_error(
+                    err,
+                    "The eval command was removed in MongoDB 4.2; run the code in the \
+                     application or rewrite it as an aggregation pipeline instead.",
+                )
+            })?;
+
+        reply.remove("retval").ok_or_else(|| {
+            ResponseError(String::from("Server reply does not contain 'retval'."))
+        })
+    }
+
+    fn clone_collection(
+        &self,
+        from: &str,
+        namespace: &str,
+        filter: Option<bson::Document>,
+    ) -> Result<()> {
+        let mut spec = doc! {
+            "cloneCollection": namespace,
+            "from": from,
+        };
+
+        if let Some(filter) = filter {
+            spec.insert("query", filter);
+        }
+
+        self.run_command_checked(spec, CommandType::CloneCollection, None)
+            .map_err(|err| {
+                removed_command_error(
+                    err,
+                    "The cloneCollection command was removed in MongoDB 4.2; use \
+                     Collection::copy_to instead.",
+                )
+            })
+            .map(drop)
+    }
+
+    fn pin_connection(&self) -> Result<PinnedConnection> {
+        PinnedConnection::new(self)
+    }
+
+    fn save_function(&self, name: &str, code: &str) -> Result<()> {
+        let mut options = ReplaceOptions::new();
+        options.upsert = Some(true);
+
+        let function = doc! {
+            "_id": name,
+            "value": Bson::JavaScriptCode(String::from(code)),
+        };
+
+        // Stored functions are the one system collection applications write to.
+        Collection::new_unchecked(self.clone(), "system.js", false, None, None)
+            .replace_one(doc! { "_id": name }, function, Some(options))
+            .map(drop)
+    }
+
+    fn list_functions(&self) -> Result<Vec<String>> {
+        let options = FindOptions {
+            projection: Some(doc! { "_id": 1 }),
+            sort: Some(doc! { "_id": 1 }),
+            ..FindOptions::new()
+        };
+
+        self.collection("system.js")
+            .find(None, Some(options))?
+            .filter_map(|result| match result {
+                Err(err) => Some(Err(err)),
+                Ok(mut doc) => match doc.remove("_id") {
+                    Some(Bson::String(name)) => Some(Ok(name)),
+                    _ => None,
+                },
+            })
+            .collect()
+    }
+
+    fn unused_indexes(&self, min_age: chrono::Duration) -> Result<Vec<UnusedIndex>> {
+        let cutoff = Utc::now() - min_age;
+        let mut unused = Vec::new();
+
+        // Views have no indexes of their own; servers before 3.4 don't report a type at all.
+        let filter = doc! { "type": { "$ne": "view" } };
+
+        for name in self.collection_names(Some(filter))? {
+            if name.starts_with("system.") {
+                continue;
+            }
+
+            let coll = self.collection(&name);
+            let stats = coll.index_stats()?;
+
+            // Statistics may mention an index that was dropped since, so only report the ones
+            // that are still listed.
+            for index in coll.list_indexes()? {
+                let index_name = match index?.remove("name") {
+                    Some(Bson::String(index_name)) => index_name,
+                    _ => continue,
+                };
+
+                if index_name == "_id_" {
+                    continue;
+                }
+
+                if let Some(index_stats) = stats.iter().find(|stats| stats.name == index_name) {
+                    if index_stats.ops == 0 && index_stats.since <= cutoff {
+                        unused.push(UnusedIndex {
+                            collection: name.clone(),
+                            stats: index_stats.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(unused)
+    }
+}
+
+// Explains that a command was removed when the server doesn't recognize it.
+fn removed_command_error(err: Error, explanation: &str) -> Error {
+    let removed = match err {
+        CommandError(ref err) => err.has_code(&[ErrorCode::CommandNotFound]),
+        CodedError(ErrorCode::CommandNotFound) => true,
+        _ => false,
+    };
+
+    if !removed {
+        return err;
+    }
+
+    UnsupportedByServerError(String::from(explanation))
+}
+
+// Lists the collections of a server older than MongoDB 2.8 from its `system.namespaces`
+// collection, whose entries are named after whole namespaces and also include each index, as
+// `db.coll.$index`, and internal entries such as `db.$freelist`. If `name_prefix` is given, only
+// the collections whose names start with it are listed.
+fn list_legacy_collections(
+    db: &Database,
+    filter: Option<bson::Document>,
+    name_prefix: Option<&str>,
+    batch_size: i32,
+) -> Result<Cursor> {
+    let prefix = format!("{}.", db.name);
+    let mut filter = filter.unwrap_or_else(bson::Document::new);
+
+    if let Some(name_prefix) = name_prefix {
+        let pattern = format!("^{}", escape_regex(&format!("{}{}", prefix, name_prefix)));
+        filter.insert("name", Bson::RegExp(pattern, String::new()));
+        return list_legacy_namespaces(db, filter, &prefix, batch_size);
+    }
+
+    match filter.remove("name") {
+        Some(Bson::String(name)) => {
+            filter.insert("name", format!("{}{}", prefix, name));
+        }
+        Some(_) => {
+            return Err(ArgumentError(String::from(
+                "Servers older than MongoDB 2.8 can only filter collections by exact name.",
+            )))
+        }
+        None => (),
+    }
+
+    list_legacy_namespaces(db, filter, &prefix, batch_size)
+}
+
+// Reads the entries of `system.namespaces` matching `filter`, keeping those of collections in
+// the database, whose namespaces start with `prefix`, under their collection names.
+fn list_legacy_namespaces(
+    db: &Database,
+    filter: bson::Document,
+    prefix: &str,
+    batch_size: i32,
+) -> Result<Cursor> {
+    let options = FindOptions {
+        batch_size: Some(batch_size),
+        ..FindOptions::new()
+    };
+
+    let mut collections = Vec::new();
+    for result in db.collection("system.namespaces").find(Some(filter), Some(options))? {
+        let mut doc = result?;
+        let name = match doc.get("name") {
+            Some(&Bson::String(ref name)) if name.starts_with(prefix) && !name.contains('$') => {
+                name[prefix.len()..].to_owned()
+            }
+            _ => continue,
+        };
+
+        doc.insert("name", name);
+        collections.push(Bson::Document(doc));
+    }
+
+    let cursor = doc! {
+        "id": 0i64,
+        "ns": format!("{}$cmd.listCollections", prefix),
+        "firstBatch": collections,
+    };
+
+    Cursor::from_cursor_document(
+        db.client.clone(),
+        cursor,
+        CommandType::ListCollections,
+        db.read_preference.to_owned(),
+    )
+}
+
+// Escapes the characters of `s` that have a meaning in regular expressions.
+fn escape_regex(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Returns true if a command failed because its namespace doesn't exist, which servers before
+// MongoDB 3.2 report without a code.
+fn is_namespace_not_found(failure: &CommandFailure) -> bool {
+    failure.has_code(&[ErrorCode::NamespaceNotFound]) ||
+        (failure.code.is_none() && failure.message == "ns not found")
+}
+
+// Sends a single command to the server over find_one.
+fn run_command(
+    db: &Database,
+    spec: bson::Document,
+    cmd_type: CommandType,
+    read_preference: Option<ReadPreference>,
+) -> Result<bson::Document> {
+    run_command_with_host(db, spec, cmd_type, read_preference).map(|(reply, _)| reply)
+}
+
+// Runs a command like `run_command`, and also returns the server it ran on.
+fn run_command_with_host(
+    db: &Database,
+    spec: bson::Document,
+    cmd_type: CommandType,
+    read_preference: Option<ReadPreference>,
+) -> Result<(bson::Document, Option<Host>)> {
+    let coll = Collection::new_unchecked(db.clone(), "$cmd", false, None, None);
+    let options = FindOptions {
+        batch_size: Some(1),
+        read_preference,
+        ..FindOptions::new()
+    };
+    let (reply, meta) = coll.find_one_with_meta_and_command_type(
+        Some(spec.clone()),
+        Some(options),
+        cmd_type,
+    )?;
+
+    match reply {
+        Some(reply) => Ok((reply, meta.map(|meta| meta.host))),
+        None => Err(OperationError(format!("Failed to execute command with spec {:?}.", spec))),
+    }
+}
+
+// Prepares a command to run under `session`: reads observe the session's earlier operations
+// and prefer the primary within its pinning window, and the session and transaction fields are
+// attached. Returns the read preference to run the command with.
+fn prepare_session_command(
+    db: &Database,
+    spec: &mut bson::Document,
+    cmd_type: CommandType,
+    read_preference: Option<ReadPreference>,
+    session: &mut ClientSession,
+) -> Result<Option<ReadPreference>> {
+    let mut read_preference = read_preference;
+
+    if !cmd_type.is_write_command() {
+        session.apply_read_concern(spec);
+
+        if session.pinned_to_primary() {
+            let pinned = read_preference
+                .unwrap_or_else(|| db.read_preference.clone())
+                .prefer_primary();
+            read_preference = Some(pinned);
+        }
+    }
+
+    session.apply(spec)?;
+    Ok(read_preference)
 }
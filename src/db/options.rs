@@ -3,7 +3,7 @@ use bson::{Bson, Document};
 use common::WriteConcern;
 use db::roles::Role;
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct CreateCollectionOptions {
     pub capped: Option<bool>,
     pub auto_index_id: Option<bool>,
@@ -11,6 +11,10 @@ pub struct CreateCollectionOptions {
     pub max: Option<i64>,
     pub use_power_of_two_sizes: Option<bool>,
     pub no_padding: Option<bool>,
+    /// Stores the documents ordered by the given key, such as
+    /// `{ "key": { "_id": 1 }, "unique": true }`, instead of in a separate `_id` index.
+    /// Requires MongoDB 5.3 or later.
+    pub clustered_index: Option<Document>,
 }
 
 impl CreateCollectionOptions {
@@ -53,10 +57,55 @@ impl From<CreateCollectionOptions> for Document {
             document.insert("flags", flags);
         }
 
+        if let Some(clustered_index) = options.clustered_index {
+            document.insert("clusteredIndex", clustered_index);
+        }
+
         document
     }
 }
 
+/// Options for listing the collections of a database.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ListCollectionsOptions {
+    /// How many collections each reply holds; the rest are fetched with getMore.
+    pub batch_size: Option<i32>,
+    /// Only return the name and type of each collection, which MongoDB 4.0 and later can do
+    /// without locking the collections. Older servers return the full documents.
+    pub name_only: bool,
+}
+
+impl ListCollectionsOptions {
+    pub fn new() -> ListCollectionsOptions {
+        Default::default()
+    }
+}
+
+/// A condition on the names of the collections returned by `collection_names_with_options`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum NameFilter {
+    /// Names starting with the given string.
+    Prefix(String),
+    /// Names matching the given regular expression. Servers older than MongoDB 2.8 can't
+    /// filter collections by regular expression.
+    Regex(String),
+}
+
+/// Options for listing the names of the collections of a database.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct CollectionNamesOptions {
+    /// Include the `system.*` collections, such as `system.profile`.
+    pub include_system: bool,
+    /// Only return the names matching the filter, which is applied by the server.
+    pub name_filter: Option<NameFilter>,
+}
+
+impl CollectionNamesOptions {
+    pub fn new() -> CollectionNamesOptions {
+        Default::default()
+    }
+}
+
 #[derive(Default, Clone, Debug, PartialEq)]
 pub struct CreateUserOptions {
     pub custom_data: Option<Document>,
@@ -0,0 +1,255 @@
+//! A connection held for the legacy pattern of sending writes and then asking the server about
+//! their outcome with `getLastError` or `getPrevError`.
+use bson::{self, bson, doc, Bson};
+
+use {acquire_write_stream_for, run_command_with_stream, send_unacknowledged, CommandType,
+     Result, ThreadedClient};
+use common::WriteConcern;
+use error::{check_command_ok, check_get_last_error};
+use Error::UnsupportedByServerError;
+use pool::PooledStream;
+use wire_protocol::flags::{OpDeleteFlags, OpInsertFlags, OpUpdateFlags};
+use wire_protocol::operations::Message;
+
+use super::{removed_command_error, Database};
+
+/// A connection taken from the pool for a sequence of writes and the reports on them. Servers
+/// keep the errors of each connection, and other operations take any connection from the
+/// pool, so `getLastError`, `getPrevError` and `resetError` only report on the writes sent
+/// over the same connection. The connection returns to the pool when this is dropped.
+///
+/// Writes are sent as legacy write messages without waiting for a reply, as drivers did
+/// before write commands. MongoDB 5.1 removed the messages along with `getLastError`, so
+/// every write then fails with an `UnsupportedByServerError`.
+pub struct PinnedConnection {
+    db: Database,
+    stream: PooledStream,
+}
+
+impl PinnedConnection {
+    /// Takes a connection to the primary from the pool.
+    pub fn new(db: &Database) -> Result<PinnedConnection> {
+        let stream = acquire_write_stream_for(&db.client, None)?;
+        Ok(PinnedConnection {
+            db: db.clone(),
+            stream: stream,
+        })
+    }
+
+    /// Inserts the documents into the collection. Unless `continue_on_error` is set, the server
+    /// stops at the first document that fails to be inserted.
+    pub fn insert(
+        &mut self,
+        coll: &str,
+        docs: Vec<bson::Document>,
+        continue_on_error: bool,
+    ) -> Result<()> {
+        let flags = if continue_on_error {
+            OpInsertFlags::CONTINUE_ON_ERROR
+        } else {
+            OpInsertFlags::empty()
+        };
+
+        let documents: Option<Vec<_>> =
+            self.monitored(|| docs.iter().cloned().map(Bson::Document).collect());
+        let req_id = self.db.client.get_req_id();
+        let message = Message::new_insert(req_id, flags, self.ns(coll), docs);
+        let command = || {
+            doc! {
+                "insert": coll,
+                "documents": documents.unwrap_or_default(),
+                "ordered": !continue_on_error,
+                "writeConcern": { "w": 0 },
+            }
+        };
+
+        self.send(message, command, CommandType::InsertMany)
+    }
+
+    /// Updates the first document matching the filter, or every one if `multi` is set,
+    /// inserting one if none matches and `upsert` is set.
+    pub fn update(
+        &mut self,
+        coll: &str,
+        filter: bson::Document,
+        update: bson::Document,
+        upsert: bool,
+        multi: bool,
+    ) -> Result<()> {
+        let mut flags = OpUpdateFlags::empty();
+        if upsert {
+            flags.insert(OpUpdateFlags::UPSERT);
+        }
+        if multi {
+            flags.insert(OpUpdateFlags::MULTI_UPDATE);
+        }
+
+        let statement = self.monitored(|| {
+            doc! {
+                "q": filter.clone(),
+                "u": update.clone(),
+                "upsert": upsert,
+                "multi": multi,
+            }
+        });
+        let message = Message::new_update(
+            self.db.client.get_req_id(),
+            self.ns(coll),
+            flags,
+            filter,
+            update,
+        );
+        let command = || {
+            let statement = statement.unwrap_or_else(bson::Document::new);
+            doc! {
+                "update": coll,
+                "updates": [statement],
+                "writeConcern": { "w": 0 },
+            }
+        };
+
+        let cmd_type = if multi {
+            CommandType::UpdateMany
+        } else {
+            CommandType::UpdateOne
+        };
+        self.send(message, command, cmd_type)
+    }
+
+    /// Deletes the first document matching the filter, or every one if `multi` is set.
+    pub fn delete(&mut self, coll: &str, filter: bson::Document, multi: bool) -> Result<()> {
+        let flags = if multi {
+            OpDeleteFlags::empty()
+        } else {
+            OpDeleteFlags::SINGLE_REMOVE
+        };
+
+        let statement =
+            self.monitored(|| doc! { "q": filter.clone(), "limit": if multi { 0 } else { 1 } });
+        let req_id = self.db.client.get_req_id();
+        let message = Message::new_delete(req_id, self.ns(coll), flags, filter);
+        let command = || {
+            let statement = statement.unwrap_or_else(bson::Document::new);
+            doc! {
+                "delete": coll,
+                "deletes": [statement],
+                "writeConcern": { "w": 0 },
+            }
+        };
+
+        let cmd_type = if multi {
+            CommandType::DeleteMany
+        } else {
+            CommandType::DeleteOne
+        };
+        self.send(message, command, cmd_type)
+    }
+
+    /// Waits with `getLastError` until the last write sent over the connection satisfies
+    /// `write_concern`, or the database's write concern if `None`, including its `fsync`, `j`
+    /// and `wtimeout` settings, and returns the server's report on that write. Whether the
+    /// write itself failed is reported in the `err` and `code` fields. Fails with a
+    /// `WriteConcernTimeoutError` if replication didn't finish within `wtimeout`, with a
+    /// `JournalingDisabledError` if `j` is set and the server runs without a journal, and with
+    /// an `UnsupportedByServerError` on MongoDB 5.1 and later, which removed the command.
+    pub fn get_last_error(
+        &mut self,
+        write_concern: Option<WriteConcern>,
+    ) -> Result<bson::Document> {
+        let write_concern = write_concern.unwrap_or_else(|| self.db.write_concern.clone());
+        let mut spec = doc! { "getLastError": 1, "w": write_concern.w };
+
+        if write_concern.w_timeout > 0 {
+            spec.insert("wtimeout", write_concern.w_timeout);
+        }
+        if write_concern.j {
+            spec.insert("j", true);
+        }
+        if write_concern.fsync {
+            spec.insert("fsync", true);
+        }
+
+        let reply = self.command(spec, CommandType::GetLastError)?;
+        check_get_last_error(&reply, &write_concern).map_err(|err| {
+            removed_command_error(
+                err,
+                "The getLastError command was removed in MongoDB 5.1; write commands report \
+                 their errors and write concern outcome in their replies.",
+            )
+        })?;
+        Ok(reply)
+    }
+
+    /// Returns the last error reported by a write over the connection since the last call to
+    /// `reset_error_history`, with the number of operations sent since in `nPrev`. Fails with
+    /// an `UnsupportedByServerError` on MongoDB 4.2 and later, which removed `getPrevError`.
+    pub fn get_prev_error(&mut self) -> Result<bson::Document> {
+        let reply = self.command(doc! { "getPrevError": 1 }, CommandType::GetPrevError)?;
+        check_command_ok(&reply).map_err(|err| {
+            removed_command_error(
+                err,
+                "The getPrevError command was removed in MongoDB 4.2; check the reply of each \
+                 write instead.",
+            )
+        })?;
+        Ok(reply)
+    }
+
+    /// Clears the errors the server keeps for the connection with `resetError`, so that
+    /// `get_last_error` and `get_prev_error` only report later writes. Fails with an
+    /// `UnsupportedByServerError` on MongoDB 5.0 and later, which removed the command.
+    pub fn reset_error_history(&mut self) -> Result<()> {
+        let reply = self.command(doc! { "resetError": 1 }, CommandType::ResetError)?;
+        check_command_ok(&reply).map_err(|err| {
+            removed_command_error(
+                err,
+                "The resetError command was removed in MongoDB 5.0; check the reply of each \
+                 write instead.",
+            )
+        })
+    }
+
+    fn ns(&self, coll: &str) -> String {
+        format!("{}.{}", self.db.name, coll)
+    }
+
+    // Builds a part of the command that monitoring sees only when a start hook will see it, so
+    // unmonitored writes don't copy their documents.
+    fn monitored<T, F>(&self, build: F) -> Option<T>
+    where
+        F: FnOnce() -> T,
+    {
+        if self.db.client.listener.has_start_hooks() {
+            Some(build())
+        } else {
+            None
+        }
+    }
+
+    fn command(&mut self, spec: bson::Document, cmd_type: CommandType) -> Result<bson::Document> {
+        run_command_with_stream(&self.db.client, &mut self.stream, &self.db.name, spec, cmd_type)
+    }
+
+    // Sends a legacy write message, which monitoring sees as the write command built by
+    // `command`.
+    fn send<F>(
+        &mut self,
+        message: Result<Message>,
+        command: F,
+        cmd_type: CommandType,
+    ) -> Result<()>
+    where
+        F: FnOnce() -> bson::Document,
+    {
+        let client = &self.db.client;
+        if !self.stream.capabilities().supports_legacy_writes() {
+            return Err(UnsupportedByServerError(String::from(
+                "Legacy write messages were removed in MongoDB 5.1; use the write methods of \
+                 `Collection` instead.",
+            )));
+        }
+
+        let stream = &mut self.stream;
+        send_unacknowledged(client, stream, &self.db.name, Some(message), command, cmd_type)
+    }
+}
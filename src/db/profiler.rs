@@ -0,0 +1,87 @@
+//! Database profiler levels and `system.profile` entries.
+use bson::{self, Bson};
+use chrono::{DateTime, Utc};
+
+use Error::{ArgumentError, ResponseError};
+use Result;
+
+/// Which operations the database profiler records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProfilingLevel {
+    /// The profiler is disabled.
+    Off,
+    /// Only operations slower than the slow operation threshold are recorded.
+    SlowOnly,
+    /// Every operation is recorded.
+    All,
+}
+
+impl ProfilingLevel {
+    /// Returns the level as the integer used by the `profile` command.
+    pub fn to_i32(&self) -> i32 {
+        match *self {
+            ProfilingLevel::Off => 0,
+            ProfilingLevel::SlowOnly => 1,
+            ProfilingLevel::All => 2,
+        }
+    }
+
+    /// Parses the integer level used by the `profile` command.
+    pub fn from_i32(level: i32) -> Result<ProfilingLevel> {
+        match level {
+            0 => Ok(ProfilingLevel::Off),
+            1 => Ok(ProfilingLevel::SlowOnly),
+            2 => Ok(ProfilingLevel::All),
+            _ => Err(ArgumentError(format!("Invalid profiling level {}.", level))),
+        }
+    }
+}
+
+/// An operation recorded by the database profiler.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProfileEntry {
+    /// The type of operation, e.g. `query`, `insert`, `command` or `getmore`.
+    pub op: String,
+    /// The namespace the operation ran against.
+    pub ns: String,
+    /// How long the operation took, in milliseconds.
+    pub millis: i64,
+    /// When the operation ran.
+    pub timestamp: Option<DateTime<Utc>>,
+    /// The full profiler document.
+    pub doc: bson::Document,
+}
+
+impl ProfileEntry {
+    /// Parses a profiler entry from a `system.profile` document.
+    pub fn from_document(doc: bson::Document) -> Result<ProfileEntry> {
+        let op = match doc.get("op") {
+            Some(&Bson::String(ref op)) => op.to_owned(),
+            _ => return Err(ResponseError(String::from("Profiler entry is missing 'op'."))),
+        };
+
+        let ns = match doc.get("ns") {
+            Some(&Bson::String(ref ns)) => ns.to_owned(),
+            _ => return Err(ResponseError(String::from("Profiler entry is missing 'ns'."))),
+        };
+
+        let millis = match doc.get("millis") {
+            Some(&Bson::I32(millis)) => i64::from(millis),
+            Some(&Bson::I64(millis)) => millis,
+            _ => return Err(ResponseError(String::from("Profiler entry is missing 'millis'."))),
+        };
+
+        let timestamp = match doc.get("ts") {
+            Some(&Bson::UtcDatetime(ts)) => Some(ts),
+            _ => None,
+        };
+
+        Ok(ProfileEntry {
+            op: op,
+            ns: ns,
+            millis: millis,
+            timestamp: timestamp,
+            doc: doc,
+        })
+    }
+}
@@ -1,6 +1,8 @@
 //! MongoDB Errors and Error Codes.
-use bson::{self, oid};
+use bson::{self, oid, Bson};
 use coll::error::{WriteException, BulkWriteException};
+use common::WriteConcern;
+use command_ok;
 use data_encoding;
 use std::{error, fmt, io, result, sync};
 use trust_dns_resolver::error::ResolveError;
@@ -33,6 +35,194 @@ impl fmt::Display for MaliciousServerErrorType {
     }
 }
 
+/// A command that the server reported as failed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommandFailure {
+    /// The numeric error code, if the server sent one.
+    pub code: Option<i32>,
+    /// The name of the error code, sent by MongoDB 3.4 and later.
+    pub code_name: Option<String>,
+    /// The error message sent by the server.
+    pub message: String,
+    // The labels the server attached to the error, such as `TransientTransactionError`. Kept
+    // private so that code building failures by hand doesn't depend on every field.
+    labels: Vec<String>,
+}
+
+impl CommandFailure {
+    /// Creates a failure with the given code and message, without a code name or labels.
+    pub fn new(code: Option<i32>, message: &str) -> CommandFailure {
+        CommandFailure {
+            code: code,
+            code_name: None,
+            message: String::from(message),
+            labels: Vec::new(),
+        }
+    }
+
+    /// Returns the labels the server attached to the error, such as
+    /// `TransientTransactionError`. See `Error::labels` for those derived by the driver.
+    pub fn labels(&self) -> &[String] {
+        &self.labels
+    }
+
+    /// Reads the `errmsg`, `code`, `codeName` and `errorLabels` fields of a failed command
+    /// reply.
+    pub fn from_reply(reply: &bson::Document) -> CommandFailure {
+        let code = match reply.get("code") {
+            Some(&Bson::I32(code)) => Some(code),
+            Some(&Bson::I64(code)) => Some(code as i32),
+            Some(&Bson::FloatingPoint(code)) => Some(code as i32),
+            _ => None,
+        };
+
+        let code_name = match reply.get("codeName") {
+            Some(&Bson::String(ref name)) => Some(name.to_owned()),
+            _ => None,
+        };
+
+        let message = match reply.get("errmsg") {
+            Some(&Bson::String(ref msg)) => msg.to_owned(),
+            _ => String::from("Command failed without an error message."),
+        };
+
+        let mut labels: Vec<String> = Vec::new();
+        if let Some(&Bson::Array(ref array)) = reply.get("errorLabels") {
+            for label in array {
+                if let Bson::String(ref label) = *label {
+                    if !labels.contains(label) {
+                        labels.push(label.to_owned());
+                    }
+                }
+            }
+        }
+
+        CommandFailure {
+            code: code,
+            code_name: code_name,
+            message: message,
+            labels: labels,
+        }
+    }
+
+    /// Reads the `$err`, `code` and `codeName` fields of the document a legacy query replies
+    /// with when it fails, falling back to `$errmsg` for the message.
+    pub fn from_query_failure(reply: &bson::Document) -> CommandFailure {
+        let mut failure = CommandFailure::from_reply(reply);
+
+        failure.message = match (reply.get("$err"), reply.get("$errmsg")) {
+            (Some(&Bson::String(ref msg)), _) |
+            (_, Some(&Bson::String(ref msg))) => msg.to_owned(),
+            _ => String::from("Query failed without an error message."),
+        };
+
+        failure
+    }
+
+    /// Returns true if the server reported one of the given error codes.
+    pub fn has_code(&self, codes: &[ErrorCode]) -> bool {
+        codes.iter().any(|&code| self.code == Some(code as i32))
+    }
+}
+
+impl fmt::Display for CommandFailure {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match (self.code, self.code_name.as_ref()) {
+            (Some(code), Some(name)) => write!(fmt, "{} ({}, code {})", self.message, name, code),
+            (Some(code), None) => write!(fmt, "{} (code {})", self.message, code),
+            _ => fmt.write_str(&self.message),
+        }
+    }
+}
+
+/// The label of errors after which a transaction may succeed if it is run again from the start.
+pub const TRANSIENT_TRANSACTION_ERROR: &str = "TransientTransactionError";
+
+/// The label of transaction commit errors after which the transaction may or may not have been
+/// committed, so that the commit can safely be retried.
+pub const UNKNOWN_TRANSACTION_COMMIT_RESULT: &str = "UnknownTransactionCommitResult";
+
+/// The label of errors after which a retryable write may be retried.
+pub const RETRYABLE_WRITE_ERROR: &str = "RetryableWriteError";
+
+/// The codes of server errors after which a read may be retried, as listed by the retryable
+/// reads specification.
+pub const RETRYABLE_READ_CODES: &[i32] =
+    &[11600, 11602, 10107, 13435, 13436, 189, 91, 7, 6, 89, 9001, 134];
+
+/// The codes of server errors after which a retryable write may be retried, as listed by the
+/// retryable writes specification.
+pub const RETRYABLE_WRITE_CODES: &[i32] =
+    &[11600, 11602, 10107, 13435, 13436, 189, 91, 7, 6, 89, 9001, 262];
+
+/// The codes of commit errors after which the transaction may or may not have been committed,
+/// as listed by the transactions specification: MaxTimeMSExpired and WriteConcernFailed.
+pub const UNKNOWN_COMMIT_RESULT_CODES: &[i32] = &[50, 64];
+
+/// The codes of the errors returned through a mongos when its routing information for a
+/// collection or database is older than a shard's, as after a chunk migration: StaleConfig,
+/// StaleShardVersion, StaleEpoch and StaleDbVersion.
+pub const STALE_CONFIG_CODES: &[i32] = &[13388, 63, 150, 249];
+
+/// The code of the CommandNotSupportedOnView error, returned for writes and other commands
+/// that views don't support.
+pub const COMMAND_NOT_SUPPORTED_ON_VIEW_CODE: i32 = 166;
+
+/// Fails with a `CommandError` if the `ok` field of a command reply reports that the command
+/// failed, or with a `ProtocolError` if the reply has no `ok` field.
+pub fn check_command_ok(reply: &bson::Document) -> Result<()> {
+    match command_ok(reply) {
+        Some(true) => Ok(()),
+        Some(false) => Err(Error::CommandError(CommandFailure::from_reply(reply))),
+        None => Err(Error::ProtocolError(String::from("Command reply has no 'ok' field."))),
+    }
+}
+
+/// Returns the error reported by a legacy query reply with the `QUERY_FAILURE` flag set.
+/// Authorization failures and cursors the server no longer knows about fail as an
+/// `UnauthorizedError` and a `CursorNotFoundError`; other failures, such as a document too
+/// large to return, fail as a `CommandError` holding the server's code.
+pub fn query_failure_error(reply: &bson::Document) -> Error {
+    let failure = CommandFailure::from_query_failure(reply);
+
+    if failure.has_code(&[ErrorCode::Unauthorized]) {
+        Error::UnauthorizedError(failure.message)
+    } else if failure.has_code(&[ErrorCode::CursorNotFound, ErrorCode::LegacyCursorNotFound]) {
+        Error::CursorNotFoundError
+    } else {
+        Error::CommandError(failure)
+    }
+}
+
+/// Returns the error reported by a `getLastError` reply to a request for `write_concern`: a
+/// `WriteConcernTimeoutError` if the write wasn't replicated within `wtimeout`, a
+/// `JournalingDisabledError` if `j` was requested from a server without a journal, and
+/// otherwise the outcome of `check_command_ok`. The `err` field reporting the failure of the
+/// write itself is left to the caller.
+pub fn check_get_last_error(
+    reply: &bson::Document,
+    write_concern: &WriteConcern,
+) -> Result<()> {
+    // MongoDB 2.6 ignores `j` without a journal and says so in `jnote`; later versions reject
+    // the option as a bad value.
+    if let Some(&Bson::String(ref note)) = reply.get("jnote") {
+        return Err(Error::JournalingDisabledError(note.to_owned()));
+    }
+
+    if let Some(false) = command_ok(reply) {
+        let failure = CommandFailure::from_reply(reply);
+        if write_concern.j && failure.has_code(&[ErrorCode::BadValue]) {
+            return Err(Error::JournalingDisabledError(failure.message));
+        }
+    }
+
+    if let Some(&Bson::Boolean(true)) = reply.get("wtimeout") {
+        return Err(Error::WriteConcernTimeoutError(reply.clone()));
+    }
+
+    check_command_ok(reply)
+}
+
 /// The error type for MongoDB operations.
 #[derive(Debug)]
 pub enum Error {
@@ -56,12 +246,17 @@ pub enum Error {
     OperationError(String),
     /// A database operation returned an invalid reply.
     ResponseError(String),
+    /// A reply did not answer the request it was read for, leaving the connection out of sync.
+    ProtocolError(String),
     /// A cursor operation failed to return a cursor.
     CursorNotFoundError,
     /// The application failed to secure a mutex due to a poisoned lock.
     PoisonLockError,
     /// A server error with a given code.
     CodedError(ErrorCode),
+    /// A command failed on the server, which reported why in its reply. Such failures used to
+    /// be reported as `OperationError`s holding only the server's message.
+    CommandError(CommandFailure),
     /// The client was unable to emit the events to the listeners due to a poisoned lock;
     /// all event listeners were dropped, so they will have to be registered again. If the
     /// client is unable to emit a failure result, the error it failed to report is bundled
@@ -75,6 +270,230 @@ pub enum Error {
     DefaultError(String),
     /// Error related to DNS resolution
     DNSResolutionError(ResolveError),
+    /// The lookup of the DNS record with the given name failed while discovering the hosts of a
+    /// `mongodb+srv://` connection string; the underlying error is bundled into the
+    /// `DNSLookupError`.
+    DNSLookupError(String, Box<Error>),
+    /// An operation was retried the given number of times without succeeding; the last
+    /// underlying error is bundled into the `RetriesExhaustedError`.
+    RetriesExhaustedError(u32, Box<Error>),
+    /// A collection copy failed after writing the given number of documents to the target
+    /// collection; the underlying error is bundled into the `CopyError`.
+    CopyError(i64, Box<Error>),
+    /// A change stream was invalidated, e.g. because the watched collection was dropped; the
+    /// invalidate event is bundled into the `ChangeStreamInvalidatedError`.
+    ChangeStreamInvalidatedError(bson::Document),
+    /// The oplog no longer contains the entry with the given timestamp, so tailing cannot
+    /// resume from it without missing operations.
+    OplogRolloverError(i64),
+    /// No suitable server was found within the server selection timeout. The message describes
+    /// the state of every known server.
+    ServerSelectionTimeoutError(String),
+    /// The client is shutting down and no longer accepts new operations.
+    ShuttingDownError,
+    /// The server could not be unlocked because it is not locked against writes.
+    NotLockedError,
+    /// A replica set command was sent to a server that is not a replica set member.
+    NotReplicaSetMemberError,
+    /// The server no longer supports the requested operation; the message explains why and
+    /// what to use instead.
+    UnsupportedByServerError(String),
+    /// The session was killed on the server, so it can no longer be used.
+    SessionEndedError,
+    /// The authenticated user is not allowed to run the command; the server's message is
+    /// bundled into the `UnauthorizedError`.
+    UnauthorizedError(String),
+    /// The server a cursor was opened on, given as `host:port`, is down or no longer part of
+    /// the topology. No other server knows about the cursor, so it can't be continued.
+    CursorServerUnavailableError(String),
+    /// A field could not be encrypted or decrypted, e.g. because its ciphertext was tampered
+    /// with or written with another key.
+    EncryptionError(String),
+    /// The operation's context was cancelled, either before the operation was sent or while
+    /// its reply was awaited.
+    CancelledError,
+    /// The deadline of the operation's context passed before the operation completed.
+    DeadlineExceededError,
+    /// A write was sent to a view, which is read-only; the server's message is bundled into
+    /// the `ViewWriteError`.
+    ViewWriteError(String),
+    /// The client was already running its limit of concurrent operations, given here, and
+    /// none finished within the wait queue timeout.
+    OverloadedError(usize),
+    /// The operation is forbidden by the client's configuration, e.g. creating an index while
+    /// `forbid_index_creation` is set. Nothing was sent to the server.
+    PolicyViolationError(String),
+    /// A write was not replicated to as many servers as its write concern asked for within
+    /// `wtimeout`; the `getLastError` reply, which says where the write got to, is bundled into
+    /// the `WriteConcernTimeoutError`.
+    WriteConcernTimeoutError(bson::Document),
+    /// A journaled write concern was requested from a server that runs without a journal; the
+    /// server's message is bundled into the `JournalingDisabledError`.
+    JournalingDisabledError(String),
+    /// The MD5 checksum stored for the GridFS file with the given id doesn't match the chunks
+    /// read for it, which were changed or lost since the file was written.
+    ChecksumMismatchError(oid::ObjectId),
+}
+
+impl Error {
+    /// Returns true if the error was caused by sending an operation to a server that is not
+    /// (or is no longer) the replica set primary, going by the server's error code alone.
+    pub fn is_not_master(&self) -> bool {
+        match *self {
+            Error::CodedError(ref code) => code.is_not_master(),
+            Error::CommandError(ref err) => {
+                err.has_code(&[
+                    ErrorCode::NotMaster,
+                    ErrorCode::NotMasterNoSlaveOkCode,
+                    ErrorCode::NotMasterOrSecondaryCode,
+                ])
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns true if the error was caused by a failure to communicate with the server.
+    pub fn is_network_error(&self) -> bool {
+        match *self {
+            Error::IoError(_) => true,
+            Error::CodedError(ref code) => code.is_network_error(),
+            Error::CommandError(ref err) => {
+                err.has_code(&[
+                    ErrorCode::HostUnreachable,
+                    ErrorCode::HostNotFound,
+                    ErrorCode::NetworkTimeout,
+                ])
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the labels of the error: those the server attached to it, followed by the ones
+    /// the driver derives from its classification, e.g. `TransientTransactionError` for network
+    /// errors. Each label is listed once. `UnknownTransactionCommitResult` is only derived for
+    /// commits, by `commit_labels`.
+    pub fn labels(&self) -> Vec<String> {
+        let mut labels = self.server_labels().to_vec();
+
+        let mut derived = Vec::new();
+        if self.is_network_error() || self.is_not_master() {
+            derived.push(TRANSIENT_TRANSACTION_ERROR);
+        }
+        if self.is_retryable_write() {
+            derived.push(RETRYABLE_WRITE_ERROR);
+        }
+
+        for label in derived {
+            if !labels.iter().any(|existing| existing == label) {
+                labels.push(label.to_owned());
+            }
+        }
+
+        labels
+    }
+
+    /// Returns the labels of an error returned by `commitTransaction`. Besides those listed by
+    /// `labels`, the driver adds `UnknownTransactionCommitResult` when the server may have
+    /// committed the transaction: after network errors, retryable write errors, and write
+    /// concern timeouts.
+    pub fn commit_labels(&self) -> Vec<String> {
+        let mut labels = self.labels();
+
+        let unknown = self.is_retryable_write() || self.has_code(UNKNOWN_COMMIT_RESULT_CODES);
+        if unknown && !labels.iter().any(|label| label == UNKNOWN_TRANSACTION_COMMIT_RESULT) {
+            labels.push(UNKNOWN_TRANSACTION_COMMIT_RESULT.to_owned());
+        }
+
+        labels
+    }
+
+    /// Returns true if the error has the given label, whether the server attached it or the
+    /// driver derived it.
+    pub fn has_label(&self, label: &str) -> bool {
+        self.labels().iter().any(|existing| existing == label)
+    }
+
+    /// Returns true if a read that failed with this error may be retried once against a newly
+    /// selected server.
+    pub fn is_retryable_read(&self) -> bool {
+        self.is_network_error() || self.is_not_master() || self.has_code(RETRYABLE_READ_CODES)
+    }
+
+    /// Returns true if a retryable write that failed with this error may be retried once
+    /// against a newly selected primary. Servers from MongoDB 4.4 label these errors
+    /// themselves; the codes of the retryable writes specification are checked for older
+    /// ones.
+    pub fn is_retryable_write(&self) -> bool {
+        self.server_labels().iter().any(|label| label == RETRYABLE_WRITE_ERROR) ||
+            self.is_network_error() || self.is_not_master() ||
+            self.has_code(RETRYABLE_WRITE_CODES)
+    }
+
+    /// Returns true if the server rejected the operation because the routing information it was
+    /// sent with is stale. The mongos refreshes its routing table on such errors, so the
+    /// operation may succeed if it is sent again.
+    pub fn is_stale_config(&self) -> bool {
+        self.has_code(STALE_CONFIG_CODES)
+    }
+
+    /// The same as `is_retryable_write`.
+    pub fn is_retryable_write_error(&self) -> bool {
+        self.is_retryable_write()
+    }
+
+    /// Returns true if a transaction that failed with this error may succeed if it is run
+    /// again from the start.
+    pub fn is_transient_transaction_error(&self) -> bool {
+        self.has_label(TRANSIENT_TRANSACTION_ERROR)
+    }
+
+    /// Returns true if a transaction commit that failed with this error may or may not have
+    /// been applied, in which case the commit can safely be retried.
+    pub fn is_unknown_transaction_commit_result(&self) -> bool {
+        self.has_label(UNKNOWN_TRANSACTION_COMMIT_RESULT)
+    }
+
+    /// Returns true if a change stream that failed with this error can be resumed by
+    /// reopening it from its last resume token.
+    pub fn is_resumable_change_stream_error(&self) -> bool {
+        if self.is_network_error() || self.is_not_master() {
+            return true;
+        }
+
+        match *self {
+            Error::CursorNotFoundError => true,
+            Error::CodedError(ref code) => {
+                *code == ErrorCode::CursorNotFound || *code == ErrorCode::ShutdownInProgress ||
+                    *code == ErrorCode::InterruptedAtShutdown
+            }
+            Error::CommandError(ref err) => {
+                err.has_code(&[
+                    ErrorCode::CursorNotFound,
+                    ErrorCode::ShutdownInProgress,
+                    ErrorCode::InterruptedAtShutdown,
+                ])
+            }
+            _ => false,
+        }
+    }
+
+    // The labels the server attached to the error.
+    fn server_labels(&self) -> &[String] {
+        match *self {
+            Error::CommandError(ref err) => &err.labels[..],
+            Error::RetriesExhaustedError(_, ref inner) => inner.server_labels(),
+            _ => &[],
+        }
+    }
+
+    // Returns true if the server reported one of the given error codes.
+    fn has_code(&self, codes: &[i32]) -> bool {
+        match *self {
+            Error::CodedError(code) => codes.contains(&(code as i32)),
+            Error::CommandError(ref err) => err.code.map_or(false, |code| codes.contains(&code)),
+            _ => false,
+        }
+    }
 }
 
 impl<'a> From<Error> for io::Error {
@@ -162,9 +581,12 @@ impl fmt::Display for Error {
             Error::ArgumentError(ref inner) => inner.fmt(fmt),
             Error::OperationError(ref inner) => inner.fmt(fmt),
             Error::ResponseError(ref inner) => inner.fmt(fmt),
+            Error::ProtocolError(ref inner) => inner.fmt(fmt),
+            Error::EncryptionError(ref inner) => inner.fmt(fmt),
             Error::CursorNotFoundError => fmt.write_str("No cursor found for cursor operation."),
             Error::PoisonLockError => fmt.write_str("Socket lock poisoned while attempting to access."),
             Error::CodedError(ref err) => write!(fmt, "{}", err),
+            Error::CommandError(ref err) => err.fmt(fmt),
             Error::EventListenerError(ref err) => {
                 match *err {
                     Some(ref e) => {
@@ -180,6 +602,49 @@ impl fmt::Display for Error {
             Error::MaliciousServerError(ref err) => write!(fmt, "{}", err),
             Error::DefaultError(ref inner) => inner.fmt(fmt),
             Error::DNSResolutionError(ref inner) => inner.fmt(fmt),
+            Error::DNSLookupError(ref name, ref inner) => {
+                write!(fmt, "DNS lookup of '{}' failed: {}", name, inner)
+            }
+            Error::RetriesExhaustedError(attempts, ref inner) => {
+                write!(fmt, "Operation failed after {} attempts; last error: {}", attempts, inner)
+            }
+            Error::CopyError(copied, ref inner) => {
+                write!(fmt, "Copy failed after writing {} documents: {}", copied, inner)
+            }
+            Error::ChangeStreamInvalidatedError(_) => fmt.write_str("Change stream was invalidated."),
+            Error::OplogRolloverError(ts) => {
+                write!(fmt, "Oplog no longer contains timestamp {}; a full resync is required.", ts)
+            }
+            Error::ServerSelectionTimeoutError(ref inner) => inner.fmt(fmt),
+            Error::ShuttingDownError => {
+                fmt.write_str("The client is shutting down and no longer accepts operations.")
+            }
+            Error::NotLockedError => fmt.write_str("The server is not locked against writes."),
+            Error::NotReplicaSetMemberError => {
+                fmt.write_str("The server is not a replica set member.")
+            }
+            Error::UnsupportedByServerError(ref inner) => inner.fmt(fmt),
+            Error::SessionEndedError => fmt.write_str("The session has been ended."),
+            Error::UnauthorizedError(ref inner) => write!(fmt, "Not authorized: {}", inner),
+            Error::CursorServerUnavailableError(ref host) => {
+                write!(fmt, "The cursor's server {} is no longer available.", host)
+            }
+            Error::CancelledError => fmt.write_str("The operation was cancelled."),
+            Error::DeadlineExceededError => fmt.write_str("The operation's deadline has passed."),
+            Error::ViewWriteError(ref inner) => write!(fmt, "Views are read-only: {}", inner),
+            Error::OverloadedError(max) => {
+                write!(fmt, "The client is running its limit of {} concurrent operations.", max)
+            }
+            Error::PolicyViolationError(ref inner) => inner.fmt(fmt),
+            Error::WriteConcernTimeoutError(_) => {
+                fmt.write_str("Timed out waiting for the write to replicate.")
+            }
+            Error::JournalingDisabledError(ref inner) => {
+                write!(fmt, "The server does not journal writes: {}", inner)
+            }
+            Error::ChecksumMismatchError(ref id) => {
+                write!(fmt, "The chunks of GridFS file {} don't match its MD5 checksum.", id)
+            }
         }
     }
 }
@@ -197,6 +662,7 @@ impl error::Error for Error {
             Error::CursorNotFoundError => "No cursor found for cursor operation.",
             Error::PoisonLockError => "Socket lock poisoned while attempting to access.",
             Error::CodedError(ref err) => err.to_str(),
+            Error::CommandError(ref err) => &err.message,
             Error::EventListenerError(ref err) => {
                 match *err {
                     Some(_) => "Due to a poisoned lock on the listeners, unable to emit failure",
@@ -207,8 +673,31 @@ impl error::Error for Error {
             Error::ArgumentError(ref inner) |
             Error::OperationError(ref inner) |
             Error::ResponseError(ref inner) |
+            Error::ProtocolError(ref inner) |
+            Error::UnsupportedByServerError(ref inner) |
+            Error::UnauthorizedError(ref inner) |
+            Error::ViewWriteError(ref inner) |
+            Error::PolicyViolationError(ref inner) |
+            Error::JournalingDisabledError(ref inner) |
+            Error::EncryptionError(ref inner) |
             Error::DefaultError(ref inner) => inner,
             Error::DNSResolutionError(_) => "couldn't resolve DNS",
+            Error::DNSLookupError(..) => "DNS lookup failed",
+            Error::RetriesExhaustedError(..) => "Operation failed after exhausting all retries",
+            Error::CopyError(..) => "Copy failed after writing some documents",
+            Error::ChangeStreamInvalidatedError(_) => "Change stream was invalidated.",
+            Error::OplogRolloverError(_) => "Oplog rolled over past the requested timestamp",
+            Error::ServerSelectionTimeoutError(_) => "No suitable server found within the timeout",
+            Error::ShuttingDownError => "The client is shutting down",
+            Error::NotLockedError => "The server is not locked",
+            Error::NotReplicaSetMemberError => "The server is not a replica set member",
+            Error::SessionEndedError => "The session has been ended",
+            Error::CursorServerUnavailableError(_) => "The cursor's server is no longer available",
+            Error::CancelledError => "The operation was cancelled",
+            Error::DeadlineExceededError => "The operation's deadline has passed",
+            Error::OverloadedError(_) => "The client is running too many concurrent operations",
+            Error::WriteConcernTimeoutError(_) => "Timed out waiting for the write to replicate",
+            Error::ChecksumMismatchError(_) => "The chunks of a GridFS file don't match its MD5",
         }
     }
 
@@ -221,13 +710,37 @@ impl error::Error for Error {
             Error::OIDError(ref inner) => Some(inner),
             Error::FromHexError(ref inner) => Some(inner),
             Error::IoError(ref inner) => Some(inner),
+            Error::RetriesExhaustedError(_, ref inner) |
+            Error::CopyError(_, ref inner) |
+            Error::DNSLookupError(_, ref inner) => Some(inner.as_ref()),
             Error::DNSResolutionError(_) |
             Error::ArgumentError(_) |
             Error::OperationError(_) |
             Error::ResponseError(_) |
+            Error::ProtocolError(_) |
             Error::CursorNotFoundError |
+            Error::ChangeStreamInvalidatedError(_) |
+            Error::OplogRolloverError(_) |
+            Error::ServerSelectionTimeoutError(_) |
+            Error::ShuttingDownError |
+            Error::NotLockedError |
+            Error::NotReplicaSetMemberError |
+            Error::UnsupportedByServerError(_) |
+            Error::SessionEndedError |
+            Error::UnauthorizedError(_) |
+            Error::CursorServerUnavailableError(_) |
+            Error::EncryptionError(_) |
+            Error::CancelledError |
+            Error::DeadlineExceededError |
+            Error::ViewWriteError(_) |
+            Error::OverloadedError(_) |
+            Error::PolicyViolationError(_) |
+            Error::WriteConcernTimeoutError(_) |
+            Error::JournalingDisabledError(_) |
+            Error::ChecksumMismatchError(_) |
             Error::PoisonLockError |
             Error::CodedError(_) |
+            Error::CommandError(_) |
             Error::EventListenerError(_) |
             Error::MaliciousServerError(_) |
             Error::DefaultError(_) => None,
@@ -378,12 +891,14 @@ pub enum ErrorCode {
     RemoteOplogStale = 138,
     JSInterpreterFailure = 139,
     NotMaster = 10107,
+    BSONObjectTooLarge = 10334,
     DuplicateKey = 11000,
     InterruptedAtShutdown = 11600,
     Interrupted = 11601,
     BackgroundOperationInProgressForDatabase = 12586,
     BackgroundOperationInProgressForNamespace = 12587,
     PrepareConfigsFailedCode = 13104,
+    LegacyCursorNotFound = 13127,
     DatabaseDifferCase = 13297,
     ShardKeyTooBig = 13334,
     SendStaleConfig = 13388,
@@ -400,6 +915,11 @@ impl ErrorCode {
             *self == ErrorCode::NetworkTimeout
     }
 
+    pub fn is_not_master(&self) -> bool {
+        *self == ErrorCode::NotMaster || *self == ErrorCode::NotMasterNoSlaveOkCode ||
+            *self == ErrorCode::NotMasterOrSecondaryCode
+    }
+
     pub fn is_interruption(&self) -> bool {
         *self == ErrorCode::Interrupted || *self == ErrorCode::InterruptedAtShutdown ||
             *self == ErrorCode::ExceededTimeLimit
@@ -557,6 +1077,7 @@ impl ErrorCode {
             ErrorCode::RemoteOplogStale => "RemoteOplogStale",
             ErrorCode::JSInterpreterFailure => "JSInterpreterFailure",
             ErrorCode::NotMaster => "NotMaster",
+            ErrorCode::BSONObjectTooLarge => "BSONObjectTooLarge",
             ErrorCode::DuplicateKey => "DuplicateKey",
             ErrorCode::InterruptedAtShutdown => "InterruptedAtShutdown",
             ErrorCode::Interrupted => "Interrupted",
@@ -567,6 +1088,7 @@ impl ErrorCode {
                 "BackgroundOperationInProgressForNamespace"
             }
             ErrorCode::PrepareConfigsFailedCode => "PrepareConfigsFailedCode",
+            ErrorCode::LegacyCursorNotFound => "LegacyCursorNotFound",
             ErrorCode::DatabaseDifferCase => "DatabaseDifferCase",
             ErrorCode::ShardKeyTooBig => "ShardKeyTooBig",
             ErrorCode::SendStaleConfig => "SendStaleConfig",
@@ -0,0 +1,335 @@
+//! Conversion between BSON and MongoDB Extended JSON (version 2).
+//!
+//! Canonical Extended JSON keeps every value's exact type, e.g. writing an int64 as
+//! `{ "$numberLong": "1" }`, while relaxed Extended JSON writes numbers and recent dates in
+//! their natural JSON form and can lose the distinction between numeric types. The legacy
+//! `$binary`/`$type`, `$regex`/`$options` and numeric `$date` forms are also accepted when
+//! parsing.
+//!
+//! ```
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::extjson;
+//! # fn main() {
+//! let doc = extjson::document_from_extjson(r#"{ "n": { "$numberLong": "42" } }"#).unwrap();
+//! assert_eq!(doc, doc! { "n": 42i64 });
+//! assert_eq!(extjson::document_to_extjson(&doc, false), r#"{"n":42}"#);
+//! # }
+//! ```
+use bson::{self, Bson};
+use bson::oid::ObjectId;
+use bson::spec::BinarySubtype;
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use data_encoding::BASE64;
+use serde_json::{self, Map, Number, Value};
+
+use Error::ArgumentError;
+use Result;
+
+/// Parses an Extended JSON document, such as one line of a JSON Lines file.
+pub fn document_from_extjson(json: &str) -> Result<bson::Document> {
+    let value: Value = serde_json::from_str(json)
+        .map_err(|err| ArgumentError(format!("Invalid JSON: {}", err)))?;
+
+    match from_extjson(value)? {
+        Bson::Document(doc) => Ok(doc),
+        _ => Err(ArgumentError(String::from("Extended JSON value is not a document."))),
+    }
+}
+
+/// Writes a document as Extended JSON on a single line, in canonical form if `canonical` is
+/// set and in relaxed form otherwise.
+pub fn document_to_extjson(doc: &bson::Document, canonical: bool) -> String {
+    to_document(doc, canonical).to_string()
+}
+
+/// Converts a parsed Extended JSON value to BSON.
+pub fn from_extjson(value: Value) -> Result<Bson> {
+    match value {
+        Value::Null => Ok(Bson::Null),
+        Value::Bool(b) => Ok(Bson::Boolean(b)),
+        Value::String(s) => Ok(Bson::String(s)),
+        Value::Number(n) => from_number(&n),
+        Value::Array(values) => {
+            values.into_iter().map(from_extjson).collect::<Result<_>>().map(Bson::Array)
+        }
+        Value::Object(map) => from_object(map),
+    }
+}
+
+/// Converts a BSON value to Extended JSON, in canonical form if `canonical` is set and in
+/// relaxed form otherwise.
+pub fn to_extjson(bson: &Bson, canonical: bool) -> Value {
+    match *bson {
+        Bson::FloatingPoint(f) => {
+            match Number::from_f64(f) {
+                Some(n) if !canonical => Value::Number(n),
+                _ => wrap("$numberDouble", Value::String(double_to_string(f))),
+            }
+        }
+        Bson::String(ref s) => Value::String(s.to_owned()),
+        Bson::Array(ref values) => {
+            Value::Array(values.iter().map(|value| to_extjson(value, canonical)).collect())
+        }
+        Bson::Document(ref doc) => to_document(doc, canonical),
+        Bson::Boolean(b) => Value::Bool(b),
+        Bson::Null => Value::Null,
+        Bson::RegExp(ref pattern, ref options) => {
+            let mut regex = Map::new();
+            regex.insert(String::from("pattern"), Value::String(pattern.to_owned()));
+            regex.insert(String::from("options"), Value::String(sorted_options(options)));
+            wrap("$regularExpression", Value::Object(regex))
+        }
+        Bson::JavaScriptCode(ref code) => wrap("$code", Value::String(code.to_owned())),
+        Bson::JavaScriptCodeWithScope(ref code, ref scope) => {
+            let mut map = Map::new();
+            map.insert(String::from("$code"), Value::String(code.to_owned()));
+            map.insert(String::from("$scope"), to_document(scope, canonical));
+            Value::Object(map)
+        }
+        Bson::I32(n) if canonical => wrap("$numberInt", Value::String(n.to_string())),
+        Bson::I32(n) => Value::Number(Number::from(n)),
+        Bson::I64(n) if canonical => wrap("$numberLong", Value::String(n.to_string())),
+        Bson::I64(n) => Value::Number(Number::from(n)),
+        Bson::TimeStamp(ts) => {
+            let mut timestamp = Map::new();
+            timestamp.insert(String::from("t"), Value::Number(Number::from((ts >> 32) as u32)));
+            timestamp.insert(String::from("i"), Value::Number(Number::from(ts as u32)));
+            wrap("$timestamp", Value::Object(timestamp))
+        }
+        Bson::Binary(subtype, ref bytes) => {
+            let mut binary = Map::new();
+            binary.insert(String::from("base64"), Value::String(BASE64.encode(bytes)));
+            binary.insert(
+                String::from("subType"),
+                Value::String(format!("{:02x}", u8::from(subtype))),
+            );
+            wrap("$binary", Value::Object(binary))
+        }
+        Bson::ObjectId(ref oid) => wrap("$oid", Value::String(oid.to_hex())),
+        Bson::UtcDatetime(ref date) => {
+            // Relaxed dates are written as ISO-8601 strings, but only for the years that can be
+            // written with four digits and don't precede the epoch.
+            if !canonical && date.year() >= 1970 && date.year() <= 9999 {
+                let iso = date.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string();
+                wrap("$date", Value::String(iso))
+            } else {
+                let millis = date.timestamp() * 1000 + i64::from(date.timestamp_subsec_millis());
+                wrap("$date", wrap("$numberLong", Value::String(millis.to_string())))
+            }
+        }
+        Bson::Symbol(ref symbol) => wrap("$symbol", Value::String(symbol.to_owned())),
+    }
+}
+
+fn to_document(doc: &bson::Document, canonical: bool) -> Value {
+    Value::Object(
+        doc.iter()
+            .map(|(key, value)| (key.to_owned(), to_extjson(value, canonical)))
+            .collect(),
+    )
+}
+
+fn wrap(key: &str, value: Value) -> Value {
+    let mut map = Map::new();
+    map.insert(String::from(key), value);
+    Value::Object(map)
+}
+
+// Writes a double so that it parses back to the same value, with a fractional part so that it
+// reads as a double, and the names Extended JSON uses for values JSON can't represent.
+fn double_to_string(f: f64) -> String {
+    if f.is_nan() {
+        String::from("NaN")
+    } else if f.is_infinite() {
+        String::from(if f > 0.0 { "Infinity" } else { "-Infinity" })
+    } else {
+        format!("{:?}", f)
+    }
+}
+
+// Regular expression options are written in alphabetical order.
+fn sorted_options(options: &str) -> String {
+    let mut chars: Vec<_> = options.chars().collect();
+    chars.sort();
+    chars.into_iter().collect()
+}
+
+// Integers become an int32 if they fit and an int64 otherwise, and other numbers a double.
+fn from_number(n: &Number) -> Result<Bson> {
+    if let Some(n) = n.as_i64() {
+        if n >= i64::from(i32::min_value()) && n <= i64::from(i32::max_value()) {
+            return Ok(Bson::I32(n as i32));
+        }
+        return Ok(Bson::I64(n));
+    }
+
+    match n.as_f64() {
+        Some(f) if n.is_f64() => Ok(Bson::FloatingPoint(f)),
+        _ => Err(ArgumentError(format!("Number {} does not fit in an int64.", n))),
+    }
+}
+
+fn invalid(key: &str, value: &Value) -> ::Error {
+    ArgumentError(format!("Invalid Extended JSON {}: {}", key, value))
+}
+
+fn string_field<'a>(map: &'a Map<String, Value>, key: &str) -> Option<&'a str> {
+    map.get(key).and_then(Value::as_str)
+}
+
+fn from_object(mut map: Map<String, Value>) -> Result<Bson> {
+    let owned_keys: Vec<_> = map.keys().cloned().collect();
+    let keys: Vec<_> = owned_keys.iter().map(String::as_str).collect();
+    let is_legacy_regex = string_field(&map, "$regex").is_some() &&
+        string_field(&map, "$options").is_some();
+
+    match keys.as_slice() {
+        ["$oid"] => {
+            let value = &map["$oid"];
+            value
+                .as_str()
+                .and_then(|hex| ObjectId::with_string(hex).ok())
+                .map(Bson::ObjectId)
+                .ok_or_else(|| invalid("$oid", value))
+        }
+        ["$symbol"] => {
+            let value = &map["$symbol"];
+            value
+                .as_str()
+                .map(|symbol| Bson::Symbol(symbol.to_owned()))
+                .ok_or_else(|| invalid("$symbol", value))
+        }
+        ["$numberInt"] => {
+            let value = &map["$numberInt"];
+            value
+                .as_str()
+                .and_then(|n| n.parse().ok())
+                .map(Bson::I32)
+                .ok_or_else(|| invalid("$numberInt", value))
+        }
+        ["$numberLong"] => {
+            let value = &map["$numberLong"];
+            value
+                .as_str()
+                .and_then(|n| n.parse().ok())
+                .map(Bson::I64)
+                .ok_or_else(|| invalid("$numberLong", value))
+        }
+        ["$numberDouble"] => {
+            let value = &map["$numberDouble"];
+            let parsed = match value.as_str() {
+                Some("Infinity") => Some(::std::f64::INFINITY),
+                Some("-Infinity") => Some(::std::f64::NEG_INFINITY),
+                Some("NaN") => Some(::std::f64::NAN),
+                Some(n) => n.parse().ok(),
+                None => None,
+            };
+            parsed.map(Bson::FloatingPoint).ok_or_else(|| invalid("$numberDouble", value))
+        }
+        ["$numberDecimal"] => {
+            Err(ArgumentError(String::from("Decimal128 values are not supported.")))
+        }
+        ["$code"] => {
+            let value = &map["$code"];
+            value
+                .as_str()
+                .map(|code| Bson::JavaScriptCode(code.to_owned()))
+                .ok_or_else(|| invalid("$code", value))
+        }
+        ["$code", "$scope"] | ["$scope", "$code"] => {
+            let code = match map.remove("$code") {
+                Some(Value::String(code)) => code,
+                Some(value) => return Err(invalid("$code", &value)),
+                None => unreachable!(),
+            };
+            match from_extjson(map.remove("$scope").unwrap())? {
+                Bson::Document(scope) => Ok(Bson::JavaScriptCodeWithScope(code, scope)),
+                _ => Err(ArgumentError(String::from("Extended JSON $scope is not a document."))),
+            }
+        }
+        ["$timestamp"] => {
+            let value = &map["$timestamp"];
+            let t = value.get("t").and_then(Value::as_u64);
+            let i = value.get("i").and_then(Value::as_u64);
+            match (t, i) {
+                (Some(t), Some(i)) if t <= u64::from(u32::max_value()) &&
+                                      i <= u64::from(u32::max_value()) => {
+                    Ok(Bson::TimeStamp(((t << 32) | i) as i64))
+                }
+                _ => Err(invalid("$timestamp", value)),
+            }
+        }
+        ["$regularExpression"] => {
+            let value = &map["$regularExpression"];
+            let pattern = value.get("pattern").and_then(Value::as_str);
+            let options = value.get("options").and_then(Value::as_str);
+            match (pattern, options) {
+                (Some(pattern), Some(options)) => {
+                    Ok(Bson::RegExp(pattern.to_owned(), sorted_options(options)))
+                }
+                _ => Err(invalid("$regularExpression", value)),
+            }
+        }
+        // A `$regex` query operator is only a legacy regular expression if it comes with
+        // `$options`.
+        ["$regex", "$options"] | ["$options", "$regex"] if is_legacy_regex => {
+            let pattern = string_field(&map, "$regex").unwrap().to_owned();
+            let options = sorted_options(string_field(&map, "$options").unwrap());
+            Ok(Bson::RegExp(pattern, options))
+        }
+        ["$binary"] => {
+            let value = &map["$binary"];
+            let base64 = value.get("base64").and_then(Value::as_str);
+            let subtype = value.get("subType").and_then(Value::as_str);
+            binary(base64, subtype).ok_or_else(|| invalid("$binary", value))
+        }
+        ["$binary", "$type"] | ["$type", "$binary"] => {
+            let base64 = string_field(&map, "$binary");
+            let subtype = string_field(&map, "$type");
+            binary(base64, subtype).ok_or_else(|| invalid("$binary", &map["$binary"]))
+        }
+        ["$date"] => {
+            let value = &map["$date"];
+            let millis = match *value {
+                Value::String(ref iso) => {
+                    return DateTime::parse_from_rfc3339(iso)
+                        .map(|date| Bson::UtcDatetime(date.with_timezone(&Utc)))
+                        .map_err(|_| invalid("$date", value));
+                }
+                Value::Number(ref n) => n.as_i64(),
+                Value::Object(ref long) if long.len() == 1 => {
+                    string_field(long, "$numberLong").and_then(|n| n.parse().ok())
+                }
+                _ => None,
+            };
+            millis.and_then(datetime).ok_or_else(|| invalid("$date", value))
+        }
+        ["$minKey"] | ["$maxKey"] | ["$undefined"] | ["$dbPointer"] => {
+            Err(ArgumentError(format!("Extended JSON {} values are not supported.", keys[0])))
+        }
+        _ => {
+            let mut doc = bson::Document::new();
+            for (key, value) in map {
+                doc.insert(key, from_extjson(value)?);
+            }
+            Ok(Bson::Document(doc))
+        }
+    }
+}
+
+fn binary(base64: Option<&str>, subtype: Option<&str>) -> Option<Bson> {
+    let bytes = BASE64.decode(base64?.as_bytes()).ok()?;
+    let subtype = u8::from_str_radix(subtype?, 16).ok()?;
+    Some(Bson::Binary(BinarySubtype::from(subtype), bytes))
+}
+
+fn datetime(millis: i64) -> Option<Bson> {
+    let (mut secs, mut rem) = (millis / 1000, millis % 1000);
+    if rem < 0 {
+        secs -= 1;
+        rem += 1000;
+    }
+    Utc.timestamp_opt(secs, rem as u32 * 1_000_000).single().map(Bson::UtcDatetime)
+}
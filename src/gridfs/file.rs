@@ -1,13 +1,13 @@
 //! Lower-level file and chunk representations in GridFS.
 use bson::{self, bson, Bson, doc, oid};
-use bson::spec::BinarySubtype;
 
 use chrono::{DateTime, Utc};
 use md5::{Md5, Digest};
 use hex;
 
-use Error::{self, ArgumentError, OperationError, PoisonLockError};
-use Result;
+use Error::{self, ArgumentError, ChecksumMismatchError, OperationError, PoisonLockError};
+use {Result, ThreadedClient};
+use wire_protocol::binary;
 
 use super::Store;
 use coll::options::IndexOptions;
@@ -55,8 +55,14 @@ pub struct File {
     wbuf: Vec<u8>,
     // The file md5 hash builder.
     wsum: Md5,
+    // Whether the md5 hash of written chunks is computed and stored.
+    compute_md5: bool,
     // The read buffer.
     rbuf: Vec<u8>,
+    // The md5 hash builder of read chunks.
+    rsum: Md5,
+    // Whether read chunks are checked against the stored md5 hash.
+    verify_md5: bool,
     // Holds a pre-cached chunk.
     rcache: Option<Arc<Mutex<CachedChunk>>>,
     // The file read/write mode.
@@ -133,6 +139,9 @@ impl File {
 
     // Generic new file stream.
     fn with_gfs_file(gfs: Store, file: GfsFile, mode: Mode) -> File {
+        let compute_md5 = !gfs.md5_disabled.load(Ordering::SeqCst);
+        let verify_md5 = gfs.verify_md5.load(Ordering::SeqCst);
+
         File {
             mutex: Arc::new(Mutex::new(())),
             condvar: Arc::new(Condvar::new()),
@@ -143,7 +152,10 @@ impl File {
             wpending: Arc::new(AtomicIsize::new(0)),
             wbuf: Vec::new(),
             wsum: Md5::new(),
+            compute_md5: compute_md5,
             rbuf: Vec::new(),
+            rsum: Md5::new(),
+            verify_md5: verify_md5,
             rcache: None,
             doc: file,
             err: Arc::new(RwLock::new(InnerError { inner: None })),
@@ -160,6 +172,15 @@ impl File {
         self.len == 0
     }
 
+    /// Returns the hex-encoded MD5 checksum of the file, if one was stored.
+    pub fn md5(&self) -> Option<&str> {
+        if self.md5.is_empty() {
+            None
+        } else {
+            Some(&self.md5[..])
+        }
+    }
+
     /// Retrieves the description of the threaded error, if one occurred.
     pub fn err_description(&self) -> Result<Option<String>> {
         let err = self.err.read()?;
@@ -203,21 +224,26 @@ impl File {
                 if self.doc.upload_date.is_none() {
                     self.doc.upload_date = Some(Utc::now());
                 }
-                self.doc.md5 = hex::encode(self.wsum.result());
+                if self.compute_md5 {
+                    self.doc.md5 = hex::encode(self.wsum.result());
+                }
                 self.gfs.files.insert_one(self.doc.to_bson(), None)?;
 
-                // Ensure indexes
-                self.gfs.files.create_index(doc!{ "filename": 1 }, None)?;
-
-                let mut opts = IndexOptions::new();
-                opts.unique = Some(true);
-                self.gfs.chunks.create_index(
-                    doc! {
-                        "files_id": 1,
-                        "n": 1,
-                    },
-                    Some(opts),
-                )?;
+                // Ensure indexes, unless the client forbids creating them, in which case they
+                // are expected to have been created beforehand.
+                if !self.gfs.files.db.client.is_index_creation_forbidden() {
+                    self.gfs.files.create_index(doc!{ "filename": 1 }, None)?;
+
+                    let mut opts = IndexOptions::new();
+                    opts.unique = Some(true);
+                    self.gfs.chunks.create_index(
+                        doc! {
+                            "files_id": 1,
+                            "n": 1,
+                        },
+                        Some(opts),
+                    )?;
+                }
             } else {
                 self.gfs.chunks.delete_many(
                     doc! { "files_id": self.doc.id.clone() },
@@ -257,7 +283,7 @@ impl File {
             "_id": oid::ObjectId::new()?,
             "files_id": self.doc.id.clone(),
             "n": n,
-            "data": (BinarySubtype::Generic, vec_buf)
+            "data": binary::generic(vec_buf)
         };
 
         // Insert chunk asynchronously into the database.
@@ -323,6 +349,18 @@ impl File {
 
         self.chunk_num += 1;
 
+        if self.verify_md5 {
+            self.rsum.input(&data);
+
+            // Check the whole file once its last chunk has been read.
+            if (self.chunk_num as i64) * (self.doc.chunk_size as i64) >= self.doc.len &&
+                !self.doc.md5.is_empty() &&
+                !hex::encode(self.rsum.result()).eq_ignore_ascii_case(&self.doc.md5)
+            {
+                return Err(ChecksumMismatchError(self.doc.id.clone()));
+            }
+        }
+
         // Pre-load the next file chunk for GridFS.
         if (self.chunk_num as i64) * (self.doc.chunk_size as i64) < self.doc.len {
             let cache = Arc::new(Mutex::new(CachedChunk::new(self.chunk_num)));
@@ -418,7 +456,9 @@ impl io::Write for File {
 
             let curr_chunk_num = self.chunk_num;
             self.chunk_num += 1;
-            self.wsum.input(buf);
+            if self.compute_md5 {
+                self.wsum.input(&self.wbuf);
+            }
 
             // If over a megabyte is being written at once, wait for the load to reduce.
             while self.doc.chunk_size * self.wpending.load(Ordering::SeqCst) as i32 >=
@@ -451,7 +491,9 @@ impl io::Write for File {
 
             let curr_chunk_num = self.chunk_num;
             self.chunk_num += 1;
-            self.wsum.input(buf);
+            if self.compute_md5 {
+                self.wsum.input(part1);
+            }
 
             // Pending megabyte
             while self.doc.chunk_size * self.wpending.load(Ordering::SeqCst) as i32 >=
@@ -492,7 +534,9 @@ impl io::Write for File {
         if !self.wbuf.is_empty() && self.err_description()?.is_none() {
             let chunk_num = self.chunk_num;
             self.chunk_num += 1;
-            self.wsum.input(&self.wbuf);
+            if self.compute_md5 {
+                self.wsum.input(&self.wbuf);
+            }
 
             // Pending megabyte
             while self.doc.chunk_size * self.wpending.load(Ordering::SeqCst) as i32 >=
@@ -656,10 +700,13 @@ impl GfsFile {
             "_id": self.id.clone(),
             "chunkSize": self.chunk_size,
             "length": self.len,
-            "md5": self.md5.to_owned(),
             "uploadDate": self.upload_date.as_ref().unwrap().clone()
         };
 
+        if !self.md5.is_empty() {
+            doc.insert("md5", self.md5.to_owned());
+        }
+
         if let Some(name) = self.name.as_ref() {
             doc.insert("filename", name);
         }
@@ -669,7 +716,7 @@ impl GfsFile {
         }
 
         if let Some(metadata) = self.metadata.as_ref() {
-            doc.insert("metadata", (BinarySubtype::Generic, metadata.clone()));
+            doc.insert("metadata", binary::generic(metadata.clone()));
         }
 
         doc
@@ -43,6 +43,7 @@ use self::file::{File, Mode};
 
 use std::{io, fs};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 /// A default cursor wrapper that maps bson documents into GridFS file representations.
 #[derive(Debug)]
@@ -97,13 +98,23 @@ pub type Store = Arc<StoreInner>;
 pub struct StoreInner {
     files: Collection,
     chunks: Collection,
+    // Whether files created afterwards skip computing their MD5 checksum.
+    md5_disabled: AtomicBool,
+    // Whether files opened afterwards check the chunks they read against their MD5 checksum.
+    verify_md5: AtomicBool,
 }
 
 pub trait ThreadedStore {
     /// A new GridFS store within the database with prefix 'fs'.
     fn with_db(db: Database) -> Store;
     /// A new GridFS store within the database with a specified prefix.
+    ///
+    /// Panics if the prefix makes an invalid collection name, e.g. if it contains '$' or a null
+    /// byte; use `try_with_prefix` to handle invalid prefixes.
     fn with_prefix(db: Database, prefix: String) -> Store;
+    /// A new GridFS store within the database with a specified prefix, after checking the
+    /// names of its collections with `coll::validate_collection_name`.
+    fn try_with_prefix(db: Database, prefix: String) -> Result<Store>;
     /// Creates a new file.
     fn create(&self, name: String) -> Result<File>;
     /// Opens a file by filename.
@@ -124,6 +135,14 @@ pub trait ThreadedStore {
     fn put(&self, name: String) -> Result<()>;
     /// Retrieves a file from GridFS into local storage.
     fn get(&self, name: String) -> Result<()>;
+    /// Stops storing the MD5 checksum of files created afterwards, or resumes it. MD5 is
+    /// deprecated for GridFS as of MongoDB 4.2, and unavailable where FIPS compliance is
+    /// required; the checksum is stored by default.
+    fn set_md5_disabled(&self, disabled: bool);
+    /// Makes files opened afterwards check their chunks against the stored MD5 checksum once
+    /// all of them have been read, failing the read with a `ChecksumMismatchError` if they
+    /// don't match. Files stored without a checksum are read unchecked. Off by default.
+    fn set_verify_md5(&self, verify: bool);
 }
 
 impl ThreadedStore for Store {
@@ -132,10 +151,19 @@ impl ThreadedStore for Store {
     }
 
     fn with_prefix(db: Database, prefix: String) -> Store {
-        Arc::new(StoreInner {
-            files: db.collection(&format!("{}.files", prefix)),
-            chunks: db.collection(&format!("{}.chunks", prefix)),
-        })
+        match Store::try_with_prefix(db, prefix) {
+            Ok(store) => store,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    fn try_with_prefix(db: Database, prefix: String) -> Result<Store> {
+        Ok(Arc::new(StoreInner {
+            files: db.get_collection(&format!("{}.files", prefix))?,
+            chunks: db.get_collection(&format!("{}.chunks", prefix))?,
+            md5_disabled: AtomicBool::new(false),
+            verify_md5: AtomicBool::new(false),
+        }))
     }
 
     fn create(&self, name: String) -> Result<File> {
@@ -215,4 +243,12 @@ impl ThreadedStore for Store {
         file.close()?;
         Ok(())
     }
+
+    fn set_md5_disabled(&self, disabled: bool) {
+        self.md5_disabled.store(disabled, Ordering::SeqCst);
+    }
+
+    fn set_verify_md5(&self, verify: bool) {
+        self.verify_md5.store(verify, Ordering::SeqCst);
+    }
 }
@@ -54,6 +54,51 @@
 //! # }
 //! ```
 //!
+//! ## Building Documents
+//!
+//! Commands, filters and update documents are built with the `doc!` macro from the `bson` crate.
+//! Fields are kept in the order they are written, which matters for commands since the server
+//! reads the command name from the first field. Values can be any expression convertible into
+//! `Bson`, including nested documents, arrays, ObjectIds and dates; no user data is ever
+//! interpolated into a string.
+//!
+//! ```
+//! # #[macro_use] extern crate bson;
+//! # extern crate chrono;
+//! # extern crate mongodb;
+//! # use bson::Bson;
+//! # use bson::oid::ObjectId;
+//! # use chrono::Utc;
+//! #
+//! # fn main() {
+//! // A command, with the command name first.
+//! let shard = "shard'0001";
+//! let command = doc! {
+//!     "removeShard": shard,
+//!     "comment": { "requestedBy": "admin" },
+//! };
+//! let keys: Vec<_> = command.keys().cloned().collect();
+//! assert_eq!(vec!["removeShard", "comment"], keys);
+//! assert_eq!(Some(&Bson::String(String::from("shard'0001"))), command.get("removeShard"));
+//!
+//! // A filter with an ObjectId, an array and a date.
+//! let id = ObjectId::new().unwrap();
+//! let filter = doc! {
+//!     "_id": id.clone(),
+//!     "tags": { "$in": ["rust", "mongodb"] },
+//!     "created": { "$lt": Utc::now() },
+//! };
+//! assert_eq!(Some(&Bson::ObjectId(id)), filter.get("_id"));
+//!
+//! // An update document.
+//! let update = doc! {
+//!     "$set": { "title": "Back to the Future" },
+//!     "$push": { "ratings": { "$each": [4, 5] } },
+//! };
+//! assert_eq!(Some("$set"), update.keys().next().map(String::as_str));
+//! # }
+//! ```
+//!
 //! ## Command Monitoring
 //!
 //! The driver provides an intuitive interface for monitoring and responding to runtime information
@@ -71,10 +116,24 @@
 //!     }
 //! }
 //!
-//! let mut client = Client::connect("localhost", 27017).unwrap();
+//! let client = Client::connect("localhost", 27017).unwrap();
 //! client.add_completion_hook(log_query_duration).unwrap();
 //! ```
 //!
+//! ## Logging
+//!
+//! A `Logger` set on the client receives records of connections being opened and closed, the
+//! messages exchanged with servers, topology changes, retries and errors. The contents of
+//! authentication commands are never logged.
+//!
+//! ```no_run
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::logging::{LogLevel, StderrLogger};
+//! # use std::sync::Arc;
+//! let client = Client::connect("localhost", 27017).unwrap();
+//! client.set_logger(Arc::new(StderrLogger::new(LogLevel::Info))).unwrap();
+//! ```
+//!
 //! ## Topology Monitoring
 //!
 //! Each server within a MongoDB server set is monitored asynchronously for changes in status, and
@@ -131,7 +190,7 @@ extern crate bufstream;
 extern crate byteorder;
 extern crate chrono;
 extern crate data_encoding;
-#[cfg(feature = "ssl")]
+#[cfg(any(feature = "ssl", feature = "encryption"))]
 extern crate openssl;
 extern crate rand;
 #[macro_use]
@@ -140,86 +199,245 @@ extern crate semver;
 extern crate serde;
 #[macro_use(Serialize, Deserialize)]
 extern crate serde_derive;
+extern crate serde_json;
 extern crate separator;
 extern crate textnonce;
 extern crate time;
 extern crate md5;
+extern crate net2;
 extern crate sha1;
 extern crate hmac;
 extern crate pbkdf2;
 extern crate hex;
 extern crate trust_dns_resolver;
 
+pub mod admin;
 pub mod db;
 pub mod coll;
 pub mod common;
 pub mod connstring;
 pub mod cursor;
 pub mod error;
+pub mod extjson;
 pub mod gridfs;
+pub mod logging;
+pub mod merge;
+pub mod metrics;
+pub mod op_ctx;
+pub mod oplog;
 pub mod pool;
 pub mod r2d2_mongo;
+pub mod routing;
+pub mod session;
+pub mod slow_ops;
 pub mod stream;
 pub mod topology;
+pub mod two_phase;
 pub mod wire_protocol;
 
 mod apm;
 mod auth;
 mod command_type;
+mod limiter;
 
 pub use bson::*;
 
-pub use apm::{CommandStarted, CommandResult};
+pub use apm::{CommandStarted, CommandResult, SdamEvent};
 pub use command_type::CommandType;
 pub use error::{Error, ErrorCode, Result};
 
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::fs::{File, OpenOptions};
 use std::io::Write;
+use std::mem;
 use std::ops::DerefMut;
-use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicIsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+use std::thread;
 
-use apm::Listener;
-use common::{ReadPreference, ReadMode, WriteConcern};
-use connstring::{ConnectionString, ConnectionProtocol};
+use admin::{CurrentOp, DatabaseInfo, MemberLag, OpId, ReplicaSetConfig};
+use apm::{EventRunner, Listener};
+use coll::Collection;
+use coll::options::FindOptions;
+use common::{ReadConcern, ReadPreference, ReadMode, RetryPolicy, WriteConcern};
+use connstring::{ConnectionString, Host, SrvResolver};
+use cursor::Cursor;
 use db::{Database, ThreadedDatabase};
-use error::Error::ResponseError;
-use pool::PooledStream;
-use stream::StreamConnector;
-use topology::{Topology, TopologyDescription, TopologyType, DEFAULT_HEARTBEAT_FREQUENCY_MS,
-               DEFAULT_LOCAL_THRESHOLD_MS, DEFAULT_SERVER_SELECTION_TIMEOUT_MS};
-use topology::server::Server;
-use std::time::Duration;
+use error::check_command_ok;
+use error::Error::{ArgumentError, CodedError, CommandError, CursorServerUnavailableError,
+                   NotLockedError, NotReplicaSetMemberError, OperationError, ResponseError,
+                   ShuttingDownError, UnauthorizedError};
+use logging::{LogLevel, Logger, NoopLogger};
+use limiter::{OperationLimiter, OperationPermit};
+use metrics::{ClientMetrics, MetricsSnapshot};
+use pool::{ConnectionStats, PooledStream};
+use routing::{NamespaceRoute, RoutingProfile, RoutingTable};
+use session::{ClientSession, SessionOptions, SessionPool};
+use slow_ops::{SlowOpCapture, SlowOpRecord};
+use stream::{HostMapper, StreamConnector};
+use topology::{Topology, TopologyDescription, TopologyInfo, TopologyType,
+               DEFAULT_HEARTBEAT_FREQUENCY_MS, DEFAULT_LOCAL_THRESHOLD_MS,
+               DEFAULT_SERVER_SELECTION_TIMEOUT_MS, MIN_HEARTBEAT_FREQUENCY_MS};
+use topology::capabilities::ServerCapabilities;
+use topology::server::{Server, ServerType};
+use wire_protocol::flags::OpQueryFlags;
+use wire_protocol::validation::DEFAULT_MAX_BSON_DEPTH;
+use wire_protocol::operations::Message;
+use std::time::{Duration, Instant};
 
 pub const DRIVER_NAME: &str = "mongodb-cwal-rs";
 
+/// The maximum length, in bytes, of an application name sent during the connection handshake.
+pub const MAX_APP_NAME_BYTES: usize = 128;
+
+// How often a shutting down client checks whether in-flight operations have completed.
+const SHUTDOWN_POLL_INTERVAL_MS: u64 = 50;
+
+/// The number of dropped cursors queued for killing after which the kills are sent right away,
+/// rather than with the next operation.
+pub const CURSOR_KILL_BATCH_SIZE: usize = 100;
+
+/// How long a cursor opened with `noCursorTimeout` may go without fetching a batch before a
+/// warning is logged by default, which is when the server would have closed a regular cursor.
+pub const DEFAULT_CURSOR_IDLE_WARNING: Duration = Duration::from_secs(10 * 60);
+
+/// How long the ping that checks a connection idle for longer than `max_idle_time` waits for a
+/// reply by default before the connection is considered dead.
+pub const DEFAULT_LIVENESS_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// A server-side cursor that hasn't been exhausted.
+#[derive(Debug)]
+struct OpenCursor {
+    namespace: String,
+    // Whether the cursor was opened with noCursorTimeout, so that the server never closes it.
+    no_timeout: bool,
+    // When the cursor was opened or last fetched a batch, which restarts the server's timeout.
+    last_used: Instant,
+    // Whether the cursor was reported as idle since it last fetched a batch.
+    warned: bool,
+}
+
 /// Interfaces with a MongoDB server or replica set.
 pub struct ClientInner {
     /// Indicates how a server should be selected for read operations.
     pub read_preference: ReadPreference,
+    /// The consistency and isolation guarantees requested for read operations.
+    pub read_concern: Option<ReadConcern>,
     /// Describes the guarantees provided by MongoDB when reporting the success of a write
     /// operation.
     pub write_concern: WriteConcern,
+    /// If set, idempotent write operations are retried on "not master" and network errors.
+    pub retry_policy: Option<RetryPolicy>,
+    /// If true, single-document write operations are retried once on transient errors when the
+    /// deployment supports retryable writes.
+    pub retry_writes: bool,
+    /// If true, reads are retried once against a newly selected server when they fail with a
+    /// transient error before returning any documents.
+    pub retry_reads: bool,
+    /// The application name sent to the server during the connection handshake.
+    pub app_name: Option<String>,
+    /// The name of the operating system sent during the connection handshake, read once when
+    /// the client is built.
+    pub os_name: Option<String>,
+    /// For this many milliseconds after a write, reads that may go to a secondary are sent to
+    /// the primary instead; 0 disables pinning.
+    pub primary_pin_window_ms: u64,
+    /// How long a connection may be idle before TCP keepalive probes are sent over it; None
+    /// leaves keepalive to the operating system's default.
+    pub keep_alive: Option<Duration>,
+    /// How long a pooled connection may be idle before it is ping-checked on checkout; None
+    /// never checks idle connections.
+    pub max_idle_time: Option<Duration>,
+    /// How long the ping checking an idle connection waits for a reply.
+    pub liveness_check_timeout: Duration,
+    /// How long a cursor opened with `noCursorTimeout` may go without fetching a batch before a
+    /// warning is logged; None never warns.
+    pub cursor_idle_warning: Option<Duration>,
+    /// The deepest nesting of documents and arrays accepted in server replies.
+    pub max_bson_depth: usize,
+    // The state below is shared with the handles created by `with_options`.
     req_id: Arc<AtomicIsize>,
+    session_pool: Arc<SessionPool>,
     topology: Topology,
-    listener: Listener,
-    log_file: Option<Mutex<File>>,
+    listener: Arc<Listener>,
+    logger: Arc<RwLock<Arc<Logger>>>,
+    log_file: Option<Arc<Mutex<File>>>,
+    // Server-side cursors that haven't been exhausted, by id.
+    open_cursors: Arc<Mutex<HashMap<i64, OpenCursor>>>,
+    // Cursors dropped before being exhausted, with the server they are open on and their
+    // namespace, waiting to be killed in a batch.
+    pending_cursor_kills: Arc<Mutex<Vec<(Host, String, i64)>>>,
+    shutting_down: Arc<AtomicBool>,
+    index_creation_forbidden: Arc<AtomicBool>,
+    // When the latest write was sent, which starts the primary pinning window.
+    last_write: Arc<Mutex<Option<Instant>>>,
+    metrics: Arc<ClientMetrics>,
+    limiter: Arc<OperationLimiter>,
+    routing: Arc<RoutingTable>,
+    slow_ops: Arc<SlowOpCapture>,
+    // The handle this one was created from by `with_options`, or None for a connected client.
+    root: Option<Client>,
 }
 
 impl fmt::Debug for ClientInner {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("ClientInner")
             .field("read_preference", &self.read_preference)
+            .field("read_concern", &self.read_concern)
             .field("write_concern", &self.write_concern)
+            .field("retry_policy", &self.retry_policy)
+            .field("retry_writes", &self.retry_writes)
+            .field("retry_reads", &self.retry_reads)
+            .field("app_name", &self.app_name)
+            .field("os_name", &self.os_name)
+            .field("primary_pin_window_ms", &self.primary_pin_window_ms)
+            .field("keep_alive", &self.keep_alive)
+            .field("max_idle_time", &self.max_idle_time)
+            .field("liveness_check_timeout", &self.liveness_check_timeout)
+            .field("cursor_idle_warning", &self.cursor_idle_warning)
+            .field("max_bson_depth", &self.max_bson_depth)
             .field("req_id", &self.req_id)
+            .field("session_pool", &self.session_pool)
             .field("topology", &self.topology)
             .field("listener", &"Listener { .. }")
+            .field("logger", &self.logger)
             .field("log_file", &self.log_file)
+            .field("open_cursors", &self.open_cursors)
+            .field("pending_cursor_kills", &self.pending_cursor_kills)
+            .field("shutting_down", &self.shutting_down)
+            .field("index_creation_forbidden", &self.index_creation_forbidden)
+            .field("last_write", &self.last_write)
+            .field("limiter", &self.limiter)
+            .field("routing", &self.routing)
+            .field("slow_ops", &self.slow_ops)
+            .field("is_root", &self.root.is_none())
             .finish()
     }
 }
 
+impl Drop for ClientInner {
+    // Once every handle to a client is gone, its pooled sessions are ended on the server rather
+    // than left to time out, unless the client was shut down, which ended them already.
+    fn drop(&mut self) {
+        if self.root.is_some() || self.shutting_down.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        // Commands are run through a handle, so a root one sharing this client's state is made;
+        // the client is now shutting down, so dropping it does nothing more.
+        let client = Arc::new(self.derive(None, None, None, None));
+        if !client.is_connected() {
+            return;
+        }
+
+        for cmd in session::end_sessions_commands(&client) {
+            let _ = run_shutdown_command(&client, "admin", cmd, CommandType::EndSessions);
+        }
+    }
+}
+
 /// Configuration options for a client.
 #[derive(Default, Debug, Clone)]
 pub struct ClientOptions {
@@ -231,9 +449,69 @@ pub struct ClientOptions {
     pub log_file: Option<String>,
     /// Client-level server selection preferences for read operations.
     pub read_preference: Option<ReadPreference>,
+    /// Client-level consistency guarantees for read operations.
+    pub read_concern: Option<ReadConcern>,
     /// Client-level write guarantees when reporting a write success.
     pub write_concern: Option<WriteConcern>,
-    /// Frequency of server monitor updates; default 10000 ms.
+    /// Client-level retry behavior for idempotent write operations; disabled by default.
+    pub retry_policy: Option<RetryPolicy>,
+    /// Whether to retry supported single-document writes once on transient errors; overrides
+    /// the `retryWrites` connection string option. Disabled by default.
+    pub retry_writes: Option<bool>,
+    /// Whether to retry reads once on transient errors; overrides the `retryReads` connection
+    /// string option. Enabled by default.
+    pub retry_reads: Option<bool>,
+    /// The application name to report to the server, which shows up in server logs and
+    /// `currentOp`; overrides the `appName` connection string option.
+    pub app_name: Option<String>,
+    /// How long after a write reads that may go to a secondary are sent to the primary
+    /// instead, so that they observe the write; default 0 ms, which disables pinning.
+    pub primary_pin_window_ms: u64,
+    /// How long a connection may be idle before TCP keepalive probes are sent over it, so that
+    /// connections dropped by a firewall or NAT gateway are detected; overrides the
+    /// `socketKeepAliveMS` connection string option. Keepalive is left to the operating
+    /// system's default if neither is set.
+    pub keep_alive: Option<Duration>,
+    /// How long a pooled connection may be idle before it is checked with a `ping` on checkout,
+    /// since firewalls and NAT gateways may silently drop idle connections; overrides the
+    /// `maxIdleTimeMS` connection string option. The check runs without holding up other
+    /// checkouts. A connection that fails it is closed, and the operation goes on with the
+    /// next idle connection or a new one instead of hanging on a dead connection; it only
+    /// fails with the check's error if no new connection can be opened either. Idle
+    /// connections are reused unchecked if neither is set.
+    pub max_idle_time: Option<Duration>,
+    /// How long the `ping` checking a connection idle for longer than `max_idle_time` waits for
+    /// a reply before the connection is considered dead and closed. Default 5 seconds.
+    pub liveness_check_timeout: Option<Duration>,
+    /// How long a cursor opened with `no_cursor_timeout` may go without fetching a batch before
+    /// a warning is logged, since the server never closes such cursors and a leaked one holds
+    /// server resources until it is killed. Cursors are checked whenever the client selects a
+    /// server; None disables the warning. `ClientOptions::new` sets 10 minutes.
+    pub cursor_idle_warning: Option<Duration>,
+    /// The most operations the client runs at once; further operations wait for one to finish
+    /// before checking out a connection. An operation runs until it returns its connection, so
+    /// an exhaust cursor counts until it is exhausted or dropped. Unlimited by default.
+    pub max_concurrent_operations: Option<usize>,
+    /// How long an operation waits for one of `max_concurrent_operations` to finish before
+    /// failing with an `OverloadedError`; zero fails it at once. Overrides the
+    /// `waitQueueTimeoutMS` connection string option. Operations wait indefinitely if neither
+    /// is set.
+    pub wait_queue_timeout: Option<Duration>,
+    /// Makes every attempt to create an index fail with a `PolicyViolationError` before
+    /// anything is sent, e.g. to keep index builds out of a production request path. Copies
+    /// that would copy indexes are refused too, while GridFS skips creating its indexes after
+    /// an upload, expecting them to exist. Can be changed later with
+    /// `ThreadedClient::forbid_index_creation`.
+    pub forbid_index_creation: bool,
+    /// The deepest nesting of documents and arrays accepted in server replies; replies nested
+    /// more deeply are rejected instead of being decoded. Default 200.
+    pub max_bson_depth: Option<usize>,
+    /// Treats servers as supporting at most this wire version in every feature decision, e.g.
+    /// to use the legacy OP_GET_MORE and OP_KILL_CURSORS messages against MongoDB 3.2 and
+    /// later. Servers' reported versions are used if unset.
+    pub max_wire_version: Option<i64>,
+    /// Frequency of server monitor updates; default 10000 ms, and at least 500 ms. The
+    /// `heartbeatFrequencyMS` connection string option is used if this is left at the default.
     pub heartbeat_frequency_ms: u32,
     /// Timeout for selecting an appropriate server for operations; default 30000 ms.
     pub server_selection_timeout_ms: i64,
@@ -241,6 +519,14 @@ pub struct ClientOptions {
     pub local_threshold_ms: i64,
     /// Options for how to connect to the server.
     pub stream_connector: StreamConnector,
+    /// Maps the addresses of servers, from the seed list or as advertised by replica set
+    /// members, to the addresses to connect to; see `HostMapper`.
+    pub host_mapper: Option<Arc<HostMapper>>,
+    /// Looks up the SRV and TXT records of a `mongodb+srv://` connection string instead of the
+    /// system DNS configuration.
+    pub srv_resolver: Option<Arc<SrvResolver + Send + Sync>>,
+    /// Receives records of the client's internal events; nothing is logged by default.
+    pub logger: Option<Arc<Logger>>,
 }
 
 impl ClientOptions {
@@ -251,11 +537,37 @@ impl ClientOptions {
             idle_connection_timeout: None,
             log_file: None,
             read_preference: None,
+            read_concern: None,
             write_concern: None,
+            retry_policy: None,
+            retry_writes: None,
+            retry_reads: None,
+            app_name: None,
+            primary_pin_window_ms: 0,
+            keep_alive: None,
+            max_idle_time: None,
+            liveness_check_timeout: None,
+            cursor_idle_warning: Some(DEFAULT_CURSOR_IDLE_WARNING),
+            max_concurrent_operations: None,
+            wait_queue_timeout: None,
+            forbid_index_creation: false,
+            max_bson_depth: None,
+            max_wire_version: None,
             heartbeat_frequency_ms: DEFAULT_HEARTBEAT_FREQUENCY_MS,
             server_selection_timeout_ms: DEFAULT_SERVER_SELECTION_TIMEOUT_MS,
             local_threshold_ms: DEFAULT_LOCAL_THRESHOLD_MS,
             stream_connector: StreamConnector::default(),
+            host_mapper: None,
+            srv_resolver: None,
+            logger: None,
+        }
+    }
+
+    /// Returns the stream connector, connecting through the host mapper if one is set.
+    pub fn connector(&self) -> StreamConnector {
+        match self.host_mapper {
+            Some(ref mapper) => self.stream_connector.clone().with_host_mapper(mapper.clone()),
+            None => self.stream_connector.clone(),
         }
     }
 
@@ -294,12 +606,21 @@ pub trait ThreadedClient: Sync + Sized {
     fn connect(host: &str, port: u16) -> Result<Self>;
     /// Creates a new Client directly connected to a single MongoDB server with options.
     fn connect_with_options(host: &str, port: u16, ClientOptions) -> Result<Self>;
+    /// Creates a new Client connected to the mongos routers of a sharded cluster. Each
+    /// operation is sent to one of the reachable routers within the latency window of the
+    /// nearest one, taking turns between them.
+    fn connect_to_mongos(hosts: &[(&str, u16)]) -> Result<Self>;
+    /// Creates a new Client connected to the mongos routers of a sharded cluster with options.
+    fn connect_to_mongos_with_options(hosts: &[(&str, u16)], ClientOptions) -> Result<Self>;
     /// Creates a new Client connected to a complex topology, such as a
     /// replica set or sharded cluster.
     fn with_uri(uri: &str) -> Result<Self>;
     /// Creates a new Client connected to a complex topology, such as a
     /// replica set or sharded cluster, with options.
     fn with_uri_and_options(uri: &str, options: ClientOptions) -> Result<Self>;
+    /// Creates a new Client connected to the topology described by `uri`, which identifies
+    /// itself to the servers as `app_name`, e.g. in their logs and in `currentOp`.
+    fn with_app_name(uri: &str, app_name: &str) -> Result<Self>;
     /// Create a new Client with manual connection configurations.
     /// `connect` and `with_uri` should generally be used as higher-level constructors.
     fn with_config(
@@ -308,14 +629,35 @@ pub trait ThreadedClient: Sync + Sized {
         description: Option<TopologyDescription>,
     ) -> Result<Self>;
     /// Creates a database representation.
+    ///
+    /// Panics if the name is invalid according to `db::validate_database_name`; use `get_db`
+    /// to handle invalid names.
     fn db(&self, db_name: &str) -> Database;
+    /// Creates a database representation, after checking the database name with
+    /// `db::validate_database_name`.
+    fn get_db(&self, db_name: &str) -> Result<Database>;
     /// Creates a database representation with custom read and write controls.
+    ///
+    /// Panics if the name is invalid, like `db`.
     fn db_with_prefs(
         &self,
         db_name: &str,
         read_preference: Option<ReadPreference>,
         write_concern: Option<WriteConcern>,
     ) -> Database;
+    /// Creates a handle to the same deployment with its own default read preference, write
+    /// concern and read concern, which its databases and collections inherit; `None` keeps the
+    /// default of this handle. The handle shares this client's connections, server monitors,
+    /// sessions, hooks and metrics, so creating one does no I/O. Only the client that was
+    /// connected can be shut down, which shuts down every handle created from it.
+    fn with_options(
+        &self,
+        read_preference: Option<ReadPreference>,
+        write_concern: Option<WriteConcern>,
+        read_concern: Option<ReadConcern>,
+    ) -> Client;
+    /// Returns true if this client was connected rather than created by `with_options`.
+    fn is_root(&self) -> bool;
     /// Acquires a connection stream from the pool, along with slave_ok and should_send_read_pref.
     fn acquire_stream(&self, read_pref: ReadPreference) -> Result<(PooledStream, bool, bool)>;
     /// Acquires a connection stream from the pool for write operations.
@@ -324,14 +666,151 @@ pub trait ThreadedClient: Sync + Sized {
     fn get_req_id(&self) -> i32;
     /// Returns a list of all database names that exist on the server.
     fn database_names(&self) -> Result<Vec<String>>;
+    /// Returns the databases matching `filter`, e.g. `{ "empty": false }`, with their sizes
+    /// unless `name_only` is set. The filter is sent to servers from MongoDB 3.6 on, and
+    /// `name_only` from MongoDB 4.0 on, which then skips the locks needed to compute sizes.
+    /// Older servers list every database, which is filtered by exact `name`, `sizeOnDisk` and
+    /// `empty` values only.
+    fn list_databases(
+        &self,
+        filter: Option<bson::Document>,
+        name_only: bool,
+    ) -> Result<Vec<DatabaseInfo>>;
     /// Drops the database defined by `db_name`.
     fn drop_database(&self, db_name: &str) -> Result<()>;
     /// Reports whether this instance is a primary, master, mongos, or standalone mongod instance.
     fn is_master(&self) -> Result<bool>;
+    /// Returns a snapshot of the driver's current view of the topology and its servers.
+    fn topology_info(&self) -> Result<TopologyInfo>;
+    /// Returns the number of sockets open to each known server, including monitoring sockets,
+    /// along with how often and how long operations waited to check one out.
+    fn connection_stats(&self) -> Result<Vec<ConnectionStats>>;
+    /// Returns the capabilities shared by every known data-bearing server, so that a
+    /// mixed-version replica set reports what its oldest member supports, or `None` if no
+    /// server has been checked yet. The wire version is capped by
+    /// `ClientOptions::max_wire_version`.
+    fn capabilities(&self) -> Result<Option<ServerCapabilities>>;
+    /// Replaces the cap set by `ClientOptions::max_wire_version`, treating every server as
+    /// speaking at most wire version `max`, or as what it reports if `max` is `None`, for
+    /// testing the paths taken against older servers with a newer one. E.g. wire version 2
+    /// sends queries as legacy OP_QUERY messages, iterates cursors with OP_GET_MORE and rejects
+    /// collations as MongoDB 2.6 would. Handles sharing the client's state share the cap.
+    #[cfg(feature = "wire-version-override")]
+    fn force_max_wire_version(&self, max: Option<i64>) -> Result<()>;
+    /// Returns the client's counters of requests by type, bytes exchanged with servers, errors
+    /// by kind and request latencies, as counted since the client was created or the counters
+    /// were last reset.
+    fn metrics(&self) -> MetricsSnapshot;
+    /// Sets the client's counters back to zero.
+    fn reset_metrics(&self);
+    /// Starts recording every round trip to a server taking at least `threshold_ms`
+    /// milliseconds, with its command, reply size and server, keeping the latest `capacity`
+    /// records. Calling it again changes the threshold and capacity; a capacity of 0 stops
+    /// recording. See the `slow_ops` module.
+    fn enable_slow_op_capture(&self, threshold_ms: u64, capacity: usize);
+    /// Stops recording slow round trips. Records already captured are kept until drained.
+    fn disable_slow_op_capture(&self);
+    /// Removes and returns the slow round trips recorded so far, oldest first.
+    fn drain_slow_ops(&self) -> Vec<SlowOpRecord>;
+    /// Forbids or allows creating indexes through this client and the handles sharing its
+    /// state; see `ClientOptions::forbid_index_creation`.
+    fn forbid_index_creation(&self, forbid: bool);
+    /// Returns true if creating indexes is forbidden.
+    fn is_index_creation_forbidden(&self) -> bool;
+    /// Runs a `ping` command against the admin database and returns how long it took, e.g. for
+    /// a liveness probe. The command uses a pooled connection of its own, so it can run while
+    /// other operations are in flight.
+    fn ping(&self) -> Result<Duration>;
+    /// Pings every known server, such as each member of a replica set, over a connection to
+    /// that server, returning the time each ping took or the error it failed with.
+    fn ping_all(&self) -> Result<HashMap<Host, Result<Duration>>>;
+    /// Returns true if a connection is established to a server its monitor reached on its last
+    /// check. No I/O is done, so the answer may be as old as the heartbeat frequency.
+    fn is_connected(&self) -> bool;
+    /// Starts a logical session. Fails if the deployment does not support sessions.
+    fn start_session(&self) -> Result<ClientSession>;
+    /// Starts a logical session with the given options.
+    fn start_session_with_options(&self, options: SessionOptions) -> Result<ClientSession>;
+    /// Ends all pooled sessions on the server. Dropping the last handle to the client, or
+    /// shutting it down, ends them too.
+    fn end_sessions(&self) -> Result<()>;
+    /// Resets the idle timeouts of the sessions with the given ids, as returned by
+    /// `ClientSession::id` or built from a UUID by `session::id_from_hex`.
+    fn refresh_sessions(&self, ids: &[bson::Document]) -> Result<()>;
+    /// Kills the sessions with the given ids, along with their running operations and open
+    /// cursors. Sessions of this client that are killed fail with a `SessionEndedError` when
+    /// they are used again.
+    fn kill_sessions(&self, ids: &[bson::Document]) -> Result<()>;
+    /// Kills every session owned by the given users, each given as a user name and the name of
+    /// its authentication database.
+    fn kill_all_sessions_by_pattern(&self, users: &[(&str, &str)]) -> Result<()>;
     /// Sets a function to be run every time a command starts.
-    fn add_start_hook(&mut self, hook: fn(Client, &CommandStarted)) -> Result<()>;
+    fn add_start_hook(&self, hook: fn(Client, &CommandStarted)) -> Result<()>;
     /// Sets a function to be run every time a command completes.
-    fn add_completion_hook(&mut self, hook: fn(Client, &CommandResult)) -> Result<()>;
+    fn add_completion_hook(&self, hook: fn(Client, &CommandResult)) -> Result<()>;
+    /// Sets the logger that receives records of the client's internal events, replacing the
+    /// previous one.
+    fn set_logger(&self, logger: Arc<Logger>) -> Result<()>;
+    /// Sets the function to be run for every server discovery and monitoring event, replacing
+    /// any previous one. It is immediately sent a `ServerOpened` event for every known server.
+    fn set_sdam_listener(&self, listener: fn(Client, &SdamEvent)) -> Result<()>;
+    /// Attaches `profile` to a collection's namespace, given as `db.collection`, so that
+    /// operations on it run within the profile's limits instead of the client's, or detaches
+    /// the namespace's profile if `None`. The profile applies to every handle to the
+    /// collection from this client and the handles sharing its state. See the `routing` module.
+    fn set_routing_profile(&self, namespace: &str, profile: Option<RoutingProfile>) -> Result<()>;
+    /// Shuts the client down. New operations immediately fail with a `ShuttingDownError`, while
+    /// operations already using a connection are given up to `timeout_ms` milliseconds to
+    /// complete. Open cursors are then killed, pooled sessions are ended, and all connections
+    /// are closed. Calling this more than once has no further effect. Fails with an
+    /// `ArgumentError` on a handle created by `with_options`.
+    fn shutdown(&self, timeout_ms: u64) -> Result<()>;
+    /// Returns the operations in progress on the primary, or the mongos, matching `filter`.
+    /// Unless `all_users` is set, only the operations of the authenticated user are returned;
+    /// servers before 3.2 always return every user's operations.
+    fn current_ops(
+        &self,
+        filter: Option<bson::Document>,
+        all_users: bool,
+    ) -> Result<Vec<CurrentOp>>;
+    /// Terminates an operation returned by `current_ops`. Operations on a sharded cluster must
+    /// be identified by their `shard:opid` string, and others by their numeric id.
+    fn kill_op(&self, opid: OpId) -> Result<()>;
+    /// Flushes pending writes to disk and locks the primary, or the mongod the client is
+    /// connected to, against writes, e.g. while taking a backup. Locks are counted, so every
+    /// call must be matched by a call to `fsync_unlock`.
+    fn fsync_lock(&self) -> Result<()>;
+    /// Releases a lock taken by `fsync_lock`. Fails with a `NotLockedError` if the server is
+    /// not locked.
+    fn fsync_unlock(&self) -> Result<()>;
+    /// Returns true if the server is locked against writes by `fsync_lock`.
+    fn is_locked(&self) -> Result<bool>;
+    /// Asks the primary to step down and not seek reelection for `secs` seconds. The primary
+    /// closes every connection when it steps down, which is not reported as an error. Fails
+    /// with a `NotReplicaSetMemberError` if the server is not a replica set member.
+    fn step_down(&self, secs: i64) -> Result<()>;
+    /// Prevents a secondary from seeking election for `secs` seconds; 0 unfreezes it. The
+    /// command is sent according to the client's read preference, so the client should be
+    /// connected directly to the member to freeze. Fails with a `NotReplicaSetMemberError` if
+    /// the server is not a replica set member.
+    fn freeze(&self, secs: i64) -> Result<()>;
+    /// Returns the configuration of the replica set together with the current state and optime
+    /// of each member, as reported by the primary. Fails with a `NotReplicaSetMemberError` if
+    /// the server is not a replica set member.
+    fn replica_set_config(&self) -> Result<ReplicaSetConfig>;
+    /// Returns how many seconds each member of the replica set lags behind the primary,
+    /// according to `replSetGetStatus` on the primary. Members that are neither primary nor
+    /// secondary, such as arbiters, are listed without a lag. Fails with a
+    /// `NotReplicaSetMemberError` if the server is not a replica set member.
+    fn replication_lag(&self) -> Result<Vec<MemberLag>>;
+    /// Asks every server monitor to check its server immediately instead of waiting for the
+    /// next heartbeat, and waits for the checks to complete, for at most the server selection
+    /// timeout.
+    fn refresh_topology(&self) -> Result<()>;
+    /// Kills the server-side cursors of the cursors dropped before being exhausted. Kills are
+    /// otherwise queued and sent in batches, once `CURSOR_KILL_BATCH_SIZE` cursors have been
+    /// dropped, before the next operation, or when the client shuts down.
+    fn flush_cursor_kills(&self) -> Result<()>;
 }
 
 pub type Client = Arc<ClientInner>;
@@ -346,12 +825,33 @@ impl ThreadedClient for Client {
 
     fn connect_with_options(host: &str, port: u16, options: ClientOptions) -> Result<Client> {
         let config = ConnectionString::new(host, port);
-        let mut description = TopologyDescription::new(options.stream_connector.clone());
+        let mut description = TopologyDescription::new(options.connector());
 
         description.topology_type = TopologyType::Single;
         Client::with_config(config, Some(options), Some(description))
     }
 
+    fn connect_to_mongos(hosts: &[(&str, u16)]) -> Result<Client> {
+        Client::connect_to_mongos_with_options(hosts, ClientOptions::new())
+    }
+
+    fn connect_to_mongos_with_options(
+        hosts: &[(&str, u16)],
+        options: ClientOptions,
+    ) -> Result<Client> {
+        if hosts.is_empty() {
+            return Err(ArgumentError(
+                String::from("At least one mongos address is required."),
+            ));
+        }
+
+        let config = ConnectionString::with_hosts(hosts);
+        let mut description = TopologyDescription::new(options.connector());
+
+        description.topology_type = TopologyType::Sharded;
+        Client::with_config(config, Some(options), Some(description))
+    }
+
     fn with_uri(uri: &str) -> Result<Client> {
         let config = connstring::parse(uri)?;
         Client::with_config(config, None, None)
@@ -362,6 +862,12 @@ impl ThreadedClient for Client {
         Client::with_config(config, Some(options), None)
     }
 
+    fn with_app_name(uri: &str, app_name: &str) -> Result<Client> {
+        let mut options = ClientOptions::new();
+        options.app_name = Some(String::from(app_name));
+        Client::with_uri_and_options(uri, options)
+    }
+
     fn with_config(
         mut config: ConnectionString,
         options: Option<ClientOptions>,
@@ -370,6 +876,15 @@ impl ThreadedClient for Client {
 
         let client_options = options.unwrap_or_else(ClientOptions::new);
 
+        // Options from the TXT record of a seed hostname must be known before the topology is
+        // created.
+        match client_options.srv_resolver {
+            Some(ref resolver) => config.resolve_hosts_with_resolver(&**resolver)?,
+            None => config.resolve_hosts()?,
+        }
+
+        let connector = client_options.connector();
+
         let rp = client_options.read_preference.unwrap_or_else(|| {
             ReadPreference::new(ReadMode::Primary, None)
         });
@@ -377,54 +892,167 @@ impl ThreadedClient for Client {
             WriteConcern::new,
         );
 
+        let retry_writes = client_options.retry_writes.unwrap_or_else(|| {
+            config
+                .options
+                .as_ref()
+                .and_then(|options| options.get("retryWrites"))
+                .map_or(false, |value| value == "true")
+        });
+
+        let retry_reads = client_options.retry_reads.unwrap_or_else(|| {
+            config
+                .options
+                .as_ref()
+                .and_then(|options| options.get("retryReads"))
+                .map_or(true, |value| value != "false")
+        });
+
+        let app_name = client_options.app_name.or_else(|| {
+            config
+                .options
+                .as_ref()
+                .and_then(|options| options.get("appName"))
+                .cloned()
+        });
+
+        let heartbeat_frequency_ms = match config
+            .options
+            .as_ref()
+            .and_then(|options| options.get("heartbeatFrequencyMS"))
+        {
+            Some(value) if client_options.heartbeat_frequency_ms ==
+                DEFAULT_HEARTBEAT_FREQUENCY_MS => {
+                value.parse::<u32>().map_err(|_| {
+                    ArgumentError(format!("Invalid heartbeatFrequencyMS '{}'.", value))
+                })?
+            }
+            _ => client_options.heartbeat_frequency_ms,
+        };
+
+        let keep_alive = match client_options.keep_alive {
+            Some(keep_alive) => Some(keep_alive),
+            None => duration_option(&config, "socketKeepAliveMS")?,
+        };
+
+        let max_idle_time = match client_options.max_idle_time {
+            Some(max_idle_time) => Some(max_idle_time),
+            None => duration_option(&config, "maxIdleTimeMS")?,
+        };
+
+        let wait_queue_timeout = match client_options.wait_queue_timeout {
+            Some(wait_queue_timeout) => Some(wait_queue_timeout),
+            None => duration_option(&config, "waitQueueTimeoutMS")?,
+        };
+
+        if client_options.max_concurrent_operations == Some(0) {
+            return Err(ArgumentError(
+                String::from("max_concurrent_operations must be at least 1."),
+            ));
+        }
+
+        let metrics = Arc::new(ClientMetrics::new());
+        let limiter = OperationLimiter::new(
+            client_options.max_concurrent_operations,
+            wait_queue_timeout,
+            Some(metrics.clone()),
+        );
+
+        if heartbeat_frequency_ms < MIN_HEARTBEAT_FREQUENCY_MS {
+            return Err(ArgumentError(format!(
+                "Heartbeat frequency must be at least {} ms, but is {} ms.",
+                MIN_HEARTBEAT_FREQUENCY_MS,
+                heartbeat_frequency_ms
+            )));
+        }
+
+        if let Some(ref name) = app_name {
+            if name.len() > MAX_APP_NAME_BYTES {
+                return Err(ArgumentError(format!(
+                    "Application name must be at most {} bytes, but is {} bytes.",
+                    MAX_APP_NAME_BYTES,
+                    name.len()
+                )));
+            }
+        }
+
         let listener = Listener::new();
         let file = match client_options.log_file {
             Some(string) => {
                 let _ = listener.add_start_hook(log_command_started);
                 let _ = listener.add_completion_hook(log_command_completed);
-                Some(Mutex::new(
+                Some(Arc::new(Mutex::new(
                     OpenOptions::new()
                         .write(true)
                         .append(true)
                         .create(true)
                         .open(&string)?
-                ))
+                )))
             }
             None => None,
         };
 
         let client = Arc::new(ClientInner {
             req_id: Arc::new(AtomicIsize::new(0)),
+            session_pool: Arc::new(SessionPool::new()),
             topology: Topology::new(
                 config.clone(),
                 description,
-                client_options.stream_connector.clone(),
+                connector.clone(),
             )?,
-            listener: listener,
+            listener: Arc::new(listener),
+            logger: Arc::new(RwLock::new(
+                client_options.logger.unwrap_or_else(|| Arc::new(NoopLogger)),
+            )),
             read_preference: rp,
+            read_concern: client_options.read_concern,
             write_concern: wc,
+            retry_policy: client_options.retry_policy,
+            retry_writes: retry_writes,
+            retry_reads: retry_reads,
+            app_name: app_name,
+            os_name: pool::os_name(),
+            primary_pin_window_ms: client_options.primary_pin_window_ms,
+            keep_alive: keep_alive,
+            max_idle_time: max_idle_time,
+            liveness_check_timeout: client_options
+                .liveness_check_timeout
+                .unwrap_or(DEFAULT_LIVENESS_CHECK_TIMEOUT),
+            cursor_idle_warning: client_options.cursor_idle_warning,
+            max_bson_depth: client_options.max_bson_depth.unwrap_or(DEFAULT_MAX_BSON_DEPTH),
             log_file: file,
+            open_cursors: Arc::new(Mutex::new(HashMap::new())),
+            pending_cursor_kills: Arc::new(Mutex::new(Vec::new())),
+            shutting_down: Arc::new(AtomicBool::new(false)),
+            index_creation_forbidden: Arc::new(
+                AtomicBool::new(client_options.forbid_index_creation),
+            ),
+            last_write: Arc::new(Mutex::new(None)),
+            metrics: metrics,
+            limiter: Arc::new(limiter),
+            routing: Arc::new(RoutingTable::new(connector.clone())),
+            slow_ops: Arc::new(SlowOpCapture::new()),
+            root: None,
         });
 
+        // The cap has to be in place before the first server check.
+        client.topology.set_max_wire_version(client_options.max_wire_version)?;
+
         // Fill servers array and set options
         {
             let top_description = &client.topology.description;
             let mut top = top_description.write()?;
-            top.heartbeat_frequency_ms = client_options.heartbeat_frequency_ms;
+            top.heartbeat_frequency_ms = heartbeat_frequency_ms;
             top.server_selection_timeout_ms = client_options.server_selection_timeout_ms;
             top.local_threshold_ms = client_options.local_threshold_ms;
 
-            if let ConnectionProtocol::DNS(dns) = &mut config.hosts {
-                dns.discover_hosts()?;
-            }
-
             for host in config.hosts.into_iter() {
                 let server = Server::new(
                     client.clone(),
                     host.clone(),
                     top_description.clone(),
                     true,
-                    client_options.stream_connector.clone(),
+                    connector.clone(),
                     client_options.pool_size,
                     client_options.idle_connection_timeout,
                 );
@@ -437,6 +1065,10 @@ impl ThreadedClient for Client {
     }
 
     fn db(&self, db_name: &str) -> Database {
+        self.db_with_prefs(db_name, None, None)
+    }
+
+    fn get_db(&self, db_name: &str) -> Result<Database> {
         Database::open(self.clone(), db_name, None, None)
     }
 
@@ -446,18 +1078,39 @@ impl ThreadedClient for Client {
         read_preference: Option<ReadPreference>,
         write_concern: Option<WriteConcern>,
     ) -> Database {
-        Database::open(self.clone(), db_name, read_preference, write_concern)
+        match Database::open(self.clone(), db_name, read_preference, write_concern) {
+            Ok(db) => db,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    fn with_options(
+        &self,
+        read_preference: Option<ReadPreference>,
+        write_concern: Option<WriteConcern>,
+        read_concern: Option<ReadConcern>,
+    ) -> Client {
+        let root = match self.root {
+            Some(ref root) => root.clone(),
+            None => self.clone(),
+        };
+
+        Arc::new(self.derive(read_preference, write_concern, read_concern, Some(root)))
+    }
+
+    fn is_root(&self) -> bool {
+        self.root.is_none()
     }
 
     fn acquire_stream(
         &self,
         read_preference: ReadPreference,
     ) -> Result<(PooledStream, bool, bool)> {
-        self.topology.acquire_stream(self.clone(), read_preference)
+        acquire_stream_for(self, None, read_preference)
     }
 
     fn acquire_write_stream(&self) -> Result<PooledStream> {
-        self.topology.acquire_write_stream(self.clone())
+        acquire_write_stream_for(self, None)
     }
 
     fn get_req_id(&self) -> i32 {
@@ -465,40 +1118,81 @@ impl ThreadedClient for Client {
     }
 
     fn database_names(&self) -> Result<Vec<String>> {
-        let doc = doc!{ "listDatabases": 1 };
-        let db = self.db("admin");
-        let res = db.command(doc, CommandType::ListDatabases, None)?;
+        let databases = self.list_databases(None, true)?;
+        Ok(databases.into_iter().map(|database| database.name).collect())
+    }
 
-        if let Some(&Bson::Array(ref batch)) = res.get("databases") {
-            // Extract database names
-            let map = batch
-                .iter()
-                .filter_map(|bdoc| {
-                    if let Bson::Document(ref doc) = *bdoc {
-                        if let Some(&Bson::String(ref name)) = doc.get("name") {
-                            return Some(name.to_owned());
-                        }
-                    }
-                    None
-                })
-                .collect();
+    fn list_databases(
+        &self,
+        filter: Option<bson::Document>,
+        name_only: bool,
+    ) -> Result<Vec<DatabaseInfo>> {
+        let capabilities = self.capabilities()?;
+        let supports = |feature: fn(&ServerCapabilities) -> bool| {
+            capabilities.as_ref().map_or(true, feature)
+        };
 
-            Ok(map)
-        } else {
-            Err(ResponseError(
-                String::from("Server reply does not contain 'databases'."),
-            ))
+        let mut spec = doc! { "listDatabases": 1 };
+        let mut local_filter = None;
+
+        if let Some(filter) = filter {
+            if supports(ServerCapabilities::supports_list_databases_filter) {
+                spec.insert("filter", filter);
+            } else {
+                local_filter = Some(filter);
+            }
+        }
+
+        if name_only && supports(ServerCapabilities::supports_list_databases_name_only) {
+            spec.insert("nameOnly", true);
+        }
+
+        let admin = self.db("admin");
+        let res = admin.run_command_checked(spec, CommandType::ListDatabases, None)?;
+        let entries = match res.get("databases") {
+            Some(&Bson::Array(ref entries)) => entries,
+            _ => {
+                return Err(ResponseError(
+                    String::from("Server reply does not contain 'databases'."),
+                ))
+            }
+        };
+
+        let mut databases = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let mut database = match *entry {
+                Bson::Document(ref doc) => DatabaseInfo::from_document(doc)?,
+                _ => {
+                    return Err(ResponseError(
+                        format!("Received a non-document database entry: {}.", entry),
+                    ))
+                }
+            };
+
+            if let Some(ref filter) = local_filter {
+                if !database.matches(filter)? {
+                    continue;
+                }
+            }
+
+            // Servers before MongoDB 4.0 compute sizes regardless.
+            if name_only {
+                database.size_on_disk = None;
+                database.empty = None;
+            }
+            databases.push(database);
         }
+        Ok(databases)
     }
 
     fn drop_database(&self, db_name: &str) -> Result<()> {
-        self.db(db_name).drop_database()
+        self.get_db(db_name)?.drop_database()
     }
 
     fn is_master(&self) -> Result<bool> {
         let doc = doc!{ "isMaster": 1 };
         let db = self.db("local");
-        let res = db.command(doc, CommandType::IsMaster, None)?;
+        let res = db.run_command_checked(doc, CommandType::IsMaster, None)?;
 
         match res.get("ismaster") {
             Some(&Bson::Boolean(is_master)) => Ok(is_master),
@@ -508,13 +1202,1093 @@ impl ThreadedClient for Client {
         }
     }
 
-    fn add_start_hook(&mut self, hook: fn(Client, &CommandStarted)) -> Result<()> {
+    fn topology_info(&self) -> Result<TopologyInfo> {
+        self.topology.info()
+    }
+
+    fn connection_stats(&self) -> Result<Vec<ConnectionStats>> {
+        self.topology.connection_stats()
+    }
+
+    fn capabilities(&self) -> Result<Option<ServerCapabilities>> {
+        self.topology.capabilities()
+    }
+
+    #[cfg(feature = "wire-version-override")]
+    fn force_max_wire_version(&self, max: Option<i64>) -> Result<()> {
+        self.topology.set_max_wire_version(max)
+    }
+
+    fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    fn reset_metrics(&self) {
+        self.metrics.reset()
+    }
+
+    fn enable_slow_op_capture(&self, threshold_ms: u64, capacity: usize) {
+        self.slow_ops.enable(Duration::from_millis(threshold_ms), capacity)
+    }
+
+    fn disable_slow_op_capture(&self) {
+        self.slow_ops.disable()
+    }
+
+    fn drain_slow_ops(&self) -> Vec<SlowOpRecord> {
+        self.slow_ops.drain()
+    }
+
+    fn forbid_index_creation(&self, forbid: bool) {
+        self.index_creation_forbidden.store(forbid, Ordering::SeqCst)
+    }
+
+    fn is_index_creation_forbidden(&self) -> bool {
+        self.index_creation_forbidden.load(Ordering::SeqCst)
+    }
+
+    fn ping(&self) -> Result<Duration> {
+        let start = Instant::now();
+        self.db("admin").run_command_checked(doc! { "ping": 1 }, CommandType::Ping, None)?;
+        Ok(start.elapsed())
+    }
+
+    fn ping_all(&self) -> Result<HashMap<Host, Result<Duration>>> {
+        if self.shutting_down.load(Ordering::SeqCst) {
+            return Err(ShuttingDownError);
+        }
+
+        let servers: Vec<_> = self.topology.description.read()?.servers.values().cloned().collect();
+
+        Ok(servers
+            .into_iter()
+            .map(|server| (server.host.clone(), ping_server(self, &server)))
+            .collect())
+    }
+
+    fn is_connected(&self) -> bool {
+        let description = match self.topology.description.read() {
+            Ok(description) => description,
+            Err(_) => return false,
+        };
+
+        description.servers.values().any(|server| {
+            let reachable = match server.description.read() {
+                Ok(description) => description.server_type != ServerType::Unknown,
+                Err(_) => false,
+            };
+
+            reachable && server.connection_stats().map(|stats| stats.open > 0).unwrap_or(false)
+        })
+    }
+
+    fn start_session(&self) -> Result<ClientSession> {
+        self.start_session_with_options(SessionOptions::new())
+    }
+
+    fn start_session_with_options(&self, options: SessionOptions) -> Result<ClientSession> {
+        // Select a server first so that the topology reflects whether sessions are supported.
+        self.acquire_stream(self.read_preference.to_owned())?;
+
+        match self.topology.logical_session_timeout_minutes()? {
+            Some(timeout) => Ok(ClientSession::new(self.clone(), timeout, options)),
+            None => Err(OperationError(
+                String::from("Sessions are not supported by this deployment."),
+            )),
+        }
+    }
+
+    fn end_sessions(&self) -> Result<()> {
+        session::end_sessions(self)
+    }
+
+    fn refresh_sessions(&self, ids: &[bson::Document]) -> Result<()> {
+        let ids: Vec<_> = ids.iter().cloned().map(Bson::Document).collect();
+        let spec = doc! { "refreshSessions": ids };
+
+        self.db("admin")
+            .run_command_checked(spec, CommandType::RefreshSessions, None)
+            .map(|_| ())
+            .map_err(unauthorized_error)
+    }
+
+    fn kill_sessions(&self, ids: &[bson::Document]) -> Result<()> {
+        let killed: Vec<_> = ids.iter().cloned().map(Bson::Document).collect();
+        let spec = doc! { "killSessions": killed };
+
+        self.db("admin")
+            .run_command_checked(spec, CommandType::KillSessions, None)
+            .map_err(unauthorized_error)?;
+
+        self.session_pool.kill(ids);
+        Ok(())
+    }
+
+    fn kill_all_sessions_by_pattern(&self, users: &[(&str, &str)]) -> Result<()> {
+        // An empty pattern list would kill every session in the deployment.
+        if users.is_empty() {
+            return Err(ArgumentError(String::from("No users given to kill the sessions of.")));
+        }
+
+        let users: Vec<_> = users
+            .iter()
+            .map(|&(user, db)| Bson::Document(doc! { "user": user, "db": db }))
+            .collect();
+        let spec = doc! { "killAllSessionsByPattern": [{ "users": users }] };
+
+        self.db("admin")
+            .run_command_checked(spec, CommandType::KillAllSessionsByPattern, None)
+            .map(|_| ())
+            .map_err(unauthorized_error)
+    }
+
+    fn add_start_hook(&self, hook: fn(Client, &CommandStarted)) -> Result<()> {
         self.listener.add_start_hook(hook)
     }
 
-    fn add_completion_hook(&mut self, hook: fn(Client, &CommandResult)) -> Result<()> {
+    fn add_completion_hook(&self, hook: fn(Client, &CommandResult)) -> Result<()> {
         self.listener.add_completion_hook(hook)
     }
+
+    fn set_logger(&self, logger: Arc<Logger>) -> Result<()> {
+        *self.logger.write()? = logger;
+        Ok(())
+    }
+
+    fn set_sdam_listener(&self, listener: fn(Client, &SdamEvent)) -> Result<()> {
+        self.listener.set_sdam_listener(listener)?;
+
+        let hosts: Vec<_> = self.topology.description.read()?.servers.keys().cloned().collect();
+        for host in hosts {
+            self.run_sdam_listener(&SdamEvent::ServerOpened { host: host })?;
+        }
+
+        Ok(())
+    }
+
+    fn set_routing_profile(&self, namespace: &str, profile: Option<RoutingProfile>) -> Result<()> {
+        self.routing.set(namespace, profile)
+    }
+
+    fn shutdown(&self, timeout_ms: u64) -> Result<()> {
+        if !self.is_root() {
+            return Err(ArgumentError(String::from(
+                "Only the client that was connected can be shut down, not a handle created by \
+                 with_options.",
+            )));
+        }
+
+        if self.shutting_down.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+
+        while self.topology.connections_in_use()? > 0 {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+
+            let interval = Duration::from_millis(SHUTDOWN_POLL_INTERVAL_MS);
+            thread::sleep(cmp::min(deadline - now, interval));
+        }
+
+        // Cleanup is best effort, since the servers may already be unreachable.
+        let _ = send_cursor_kills(self, true);
+        let _ = kill_open_cursors(self);
+
+        for cmd in session::end_sessions_commands(self) {
+            let _ = run_shutdown_command(self, "admin", cmd, CommandType::EndSessions);
+        }
+
+        self.topology.close()
+    }
+
+    fn current_ops(
+        &self,
+        filter: Option<bson::Document>,
+        all_users: bool,
+    ) -> Result<Vec<CurrentOp>> {
+        let res = current_op_reply(self, filter, all_users)?;
+
+        match res.get("inprog") {
+            Some(&Bson::Array(ref ops)) => {
+                ops.iter()
+                    .map(|op| match *op {
+                        Bson::Document(ref doc) => CurrentOp::from_document(doc.clone()),
+                        _ => Err(ResponseError(
+                            String::from("Received a non-document operation from the server."),
+                        )),
+                    })
+                    .collect()
+            }
+            _ => Err(ResponseError(
+                String::from("No operations received from the server."),
+            )),
+        }
+    }
+
+    fn kill_op(&self, opid: OpId) -> Result<()> {
+        // Operation ids are only unique per server, and a mongos prefixes them with the shard.
+        let info = self.topology.info()?;
+        let sharded = info.topology_type == TopologyType::Sharded ||
+            info.servers.iter().any(|server| server.server_type == ServerType::Mongos);
+
+        match opid {
+            OpId::Int(_) if sharded => {
+                return Err(ArgumentError(format!(
+                    "Operation id {} did not come from a mongos; expected a 'shard:opid' string.",
+                    opid
+                )))
+            }
+            OpId::Sharded(_) if !sharded => {
+                return Err(ArgumentError(format!(
+                    "Operation id '{}' came from a mongos, but the client isn't connected to one.",
+                    opid
+                )))
+            }
+            _ => (),
+        }
+
+        let admin = self.db("admin");
+        let primary = ReadPreference::new(ReadMode::Primary, None);
+
+        if self.topology.supports(ServerCapabilities::supports_current_op_command)? {
+            let spec = doc! { "killOp": 1, "op": opid.to_bson() };
+            admin.run_command_checked(spec, CommandType::KillOp, Some(primary))?;
+        } else {
+            let mut options = FindOptions::new();
+            options.read_preference = Some(primary);
+
+            Collection::new_unchecked(admin, "$cmd.sys.killop", false, None, None).find_one(
+                Some(doc! { "op": opid.to_bson() }),
+                Some(options),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn fsync_lock(&self) -> Result<()> {
+        let spec = doc! { "fsync": 1, "lock": true };
+        let primary = ReadPreference::new(ReadMode::Primary, None);
+
+        self.db("admin")
+            .run_command_checked(spec, CommandType::Fsync, Some(primary))
+            .map(|_| ())
+            .map_err(maintenance_error)
+    }
+
+    fn fsync_unlock(&self) -> Result<()> {
+        let admin = self.db("admin");
+        let primary = ReadPreference::new(ReadMode::Primary, None);
+
+        let res = if self.topology.supports(ServerCapabilities::supports_current_op_command)? {
+            let spec = doc! { "fsyncUnlock": 1 };
+            admin.run_command_checked(spec, CommandType::FsyncUnlock, Some(primary)).map(drop)
+        } else {
+            // Older servers are unlocked through a query on a virtual collection, whose reply
+            // has the fields of a command reply.
+            let mut options = FindOptions::new();
+            options.read_preference = Some(primary);
+
+            Collection::new_unchecked(admin, "$cmd.sys.unlock", false, None, None)
+                .find_one(None, Some(options))
+                .and_then(|res| check_command_ok(&res.unwrap_or_else(bson::Document::new)))
+        };
+
+        match res {
+            Err(CommandError(ref err)) if err.has_code(&[ErrorCode::IllegalOperation]) => {
+                Err(NotLockedError)
+            }
+            // Servers before MongoDB 4.0 fail without a code, so the lock is checked instead.
+            Err(CommandError(ref err)) if err.code.is_none() && !self.is_locked()? => {
+                Err(NotLockedError)
+            }
+            res => res,
+        }
+    }
+
+    fn is_locked(&self) -> Result<bool> {
+        let res = current_op_reply(self, None, true)?;
+
+        match res.get("fsyncLock") {
+            Some(&Bson::Boolean(locked)) => Ok(locked),
+            Some(&Bson::I32(locked)) => Ok(locked != 0),
+            _ => Ok(false),
+        }
+    }
+
+    fn step_down(&self, secs: i64) -> Result<()> {
+        let spec = doc! { "replSetStepDown": secs };
+        let primary = ReadPreference::new(ReadMode::Primary, None);
+
+        match self.db("admin").run_command_checked(
+            spec,
+            CommandType::ReplSetStepDown,
+            Some(primary),
+        ) {
+            Ok(_) => (),
+            // The primary closes every connection once it has stepped down.
+            Err(ref err) if err.is_network_error() => (),
+            Err(err) => return Err(maintenance_error(err)),
+        }
+
+        // Look for the newly elected primary right away.
+        self.topology.request_updates()
+    }
+
+    fn freeze(&self, secs: i64) -> Result<()> {
+        let spec = doc! { "replSetFreeze": secs };
+
+        self.db("admin")
+            .run_command_checked(spec, CommandType::ReplSetFreeze, None)
+            .map(|_| ())
+            .map_err(maintenance_error)
+    }
+
+    fn replica_set_config(&self) -> Result<ReplicaSetConfig> {
+        let admin = self.db("admin");
+        let primary = ReadPreference::new(ReadMode::Primary, None);
+
+        let config = admin
+            .run_command_checked(
+                doc! { "replSetGetConfig": 1 },
+                CommandType::ReplSetGetConfig,
+                Some(primary.clone()),
+            )
+            .map_err(maintenance_error)?;
+
+        let status = admin
+            .run_command_checked(
+                doc! { "replSetGetStatus": 1 },
+                CommandType::ReplSetGetStatus,
+                Some(primary),
+            )
+            .map_err(maintenance_error)?;
+
+        match config.get("config") {
+            Some(&Bson::Document(ref config)) => ReplicaSetConfig::from_documents(config, &status),
+            _ => Err(ResponseError(
+                String::from("Server reply does not contain 'config'."),
+            )),
+        }
+    }
+
+    fn replication_lag(&self) -> Result<Vec<MemberLag>> {
+        let status = self.db("admin")
+            .run_command_checked(
+                doc! { "replSetGetStatus": 1 },
+                CommandType::ReplSetGetStatus,
+                Some(ReadPreference::new(ReadMode::Primary, None)),
+            )
+            .map_err(maintenance_error)?;
+
+        MemberLag::from_status(&status)
+    }
+
+    fn refresh_topology(&self) -> Result<()> {
+        self.topology.refresh()
+    }
+
+    fn flush_cursor_kills(&self) -> Result<()> {
+        send_cursor_kills(self, true)
+    }
+}
+
+// Runs `currentOp` against the primary, falling back to the virtual collection used by servers
+// before 3.2.
+fn current_op_reply(
+    client: &Client,
+    filter: Option<bson::Document>,
+    all_users: bool,
+) -> Result<bson::Document> {
+    let admin = client.db("admin");
+    let primary = ReadPreference::new(ReadMode::Primary, None);
+
+    if client.topology.supports(ServerCapabilities::supports_current_op_command)? {
+        let mut spec = doc! { "currentOp": 1 };
+        if !all_users {
+            spec.insert("$ownOps", true);
+        }
+
+        if let Some(filter) = filter {
+            for (key, value) in filter {
+                spec.insert(key, value);
+            }
+        }
+
+        admin.run_command_checked(spec, CommandType::CurrentOp, Some(primary))
+    } else {
+        let mut options = FindOptions::new();
+        options.read_preference = Some(primary);
+
+        let inprog = Collection::new_unchecked(admin, "$cmd.sys.inprog", false, None, None);
+        let res = inprog.find_one(filter, Some(options))?;
+        Ok(res.unwrap_or_else(bson::Document::new))
+    }
+}
+
+impl ClientInner {
+    // Returns a handle sharing this client's state, with the given defaults and root.
+    fn derive(
+        &self,
+        read_preference: Option<ReadPreference>,
+        write_concern: Option<WriteConcern>,
+        read_concern: Option<ReadConcern>,
+        root: Option<Client>,
+    ) -> ClientInner {
+        ClientInner {
+            read_preference: read_preference.unwrap_or_else(|| self.read_preference.clone()),
+            read_concern: read_concern.or(self.read_concern),
+            write_concern: write_concern.unwrap_or(self.write_concern),
+            retry_policy: self.retry_policy,
+            retry_writes: self.retry_writes,
+            retry_reads: self.retry_reads,
+            app_name: self.app_name.clone(),
+            os_name: self.os_name.clone(),
+            primary_pin_window_ms: self.primary_pin_window_ms,
+            keep_alive: self.keep_alive,
+            max_idle_time: self.max_idle_time,
+            liveness_check_timeout: self.liveness_check_timeout,
+            cursor_idle_warning: self.cursor_idle_warning,
+            max_bson_depth: self.max_bson_depth,
+            req_id: self.req_id.clone(),
+            session_pool: self.session_pool.clone(),
+            topology: self.topology.clone(),
+            listener: self.listener.clone(),
+            logger: self.logger.clone(),
+            log_file: self.log_file.clone(),
+            open_cursors: self.open_cursors.clone(),
+            pending_cursor_kills: self.pending_cursor_kills.clone(),
+            shutting_down: self.shutting_down.clone(),
+            index_creation_forbidden: self.index_creation_forbidden.clone(),
+            last_write: self.last_write.clone(),
+            metrics: self.metrics.clone(),
+            limiter: self.limiter.clone(),
+            routing: self.routing.clone(),
+            slow_ops: self.slow_ops.clone(),
+            root: root,
+        }
+    }
+
+    // Passes a record to the client's logger, only formatting the message if the logger wants
+    // records of its level.
+    fn log<F>(&self, level: LogLevel, target: &str, message: F)
+    where
+        F: FnOnce() -> String,
+    {
+        if let Ok(logger) = self.logger.read() {
+            if logger.enabled(level) {
+                logger.log(level, target, &message());
+            }
+        }
+    }
+
+    // Logs a message sent to or received from a server, and counts it in the client's metrics.
+    fn log_message(&self, sent: bool, host: &Host, message: &Message) {
+        self.metrics.record_message(sent, message);
+
+        self.log(LogLevel::Trace, "wire", || {
+            let (verb, preposition) = if sent {
+                ("Sent", "to")
+            } else {
+                ("Received", "from")
+            };
+
+            format!(
+                "{} {} bytes {} {}:{}: {}",
+                verb,
+                message.length(),
+                preposition,
+                host.host_name,
+                host.port,
+                message.summary()
+            )
+        });
+    }
+
+    // Counts the outcome of a request sent at `start` in the client's metrics: how long it took
+    // to be answered, or the error it failed with.
+    fn record_round_trip<T>(&self, start: Instant, result: &Result<T>) {
+        match *result {
+            Ok(_) => self.metrics.record_latency(start.elapsed()),
+            Err(ref err) => self.metrics.record_error(err),
+        }
+    }
+
+    // Returns whether the server at `host` supports `feature`, such as
+    // `ServerCapabilities::supports_find_command`, within the configured wire version cap.
+    // Servers no longer part of the topology support nothing.
+    fn server_supports<F>(&self, host: &Host, feature: F) -> Result<bool>
+    where
+        F: Fn(&ServerCapabilities) -> bool,
+    {
+        let server = match self.topology.description.read()?.servers.get(host) {
+            Some(server) => server.clone(),
+            None => return Ok(false),
+        };
+
+        let capabilities = server.description.read()?.capabilities;
+        Ok(feature(&self.topology.capped(capabilities)?))
+    }
+}
+
+// Reads the `ok` field of a command reply, which servers send as a double, an integer or a
+// boolean depending on the command and version.
+fn command_ok(res: &bson::Document) -> Option<bool> {
+    match res.get("ok") {
+        Some(&Bson::I32(ok)) => Some(ok != 0),
+        Some(&Bson::I64(ok)) => Some(ok != 0),
+        Some(&Bson::FloatingPoint(ok)) => Some(ok != 0.0),
+        Some(&Bson::Boolean(ok)) => Some(ok),
+        _ => None,
+    }
+}
+
+// Maps the failures of server maintenance commands on servers that don't run with replication
+// to `NotReplicaSetMemberError`s.
+fn maintenance_error(err: Error) -> Error {
+    match err {
+        CommandError(ref err) if err.has_code(&[ErrorCode::NoReplicationEnabled]) => {
+            NotReplicaSetMemberError
+        }
+        CodedError(ErrorCode::NoReplicationEnabled) => NotReplicaSetMemberError,
+        err => err,
+    }
+}
+
+// Reports commands the authenticated user is not allowed to run as `UnauthorizedError`s.
+fn unauthorized_error(err: Error) -> Error {
+    match err {
+        CommandError(ref err) if err.has_code(&[ErrorCode::Unauthorized]) => {
+            UnauthorizedError(err.message.clone())
+        }
+        CodedError(ErrorCode::Unauthorized) => UnauthorizedError(String::from("Unauthorized")),
+        err => err,
+    }
+}
+
+// Reads a connection string option given in milliseconds, where 0 means the same as leaving
+// the option out.
+fn duration_option(config: &ConnectionString, name: &str) -> Result<Option<Duration>> {
+    let value = match config.options.as_ref().and_then(|options| options.get(name)) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    match value.parse::<u64>() {
+        Ok(0) => Ok(None),
+        Ok(ms) => Ok(Some(Duration::from_millis(ms))),
+        Err(_) => Err(ArgumentError(format!("Invalid {} '{}'.", name, value))),
+    }
+}
+
+// Starts the primary pinning window, if pinning is enabled.
+fn record_write(client: &Client) {
+    if client.primary_pin_window_ms == 0 {
+        return;
+    }
+
+    if let Ok(mut last_write) = client.last_write.lock() {
+        *last_write = Some(Instant::now());
+    }
+}
+
+// Returns true if reads should prefer the primary because a write was sent within the primary
+// pinning window.
+fn pinned_to_primary(client: &Client) -> bool {
+    match client.last_write.lock() {
+        Ok(last_write) => within_pin_window(*last_write, client.primary_pin_window_ms),
+        Err(_) => false,
+    }
+}
+
+// Returns true if a write was sent less than `window_ms` milliseconds ago.
+fn within_pin_window(last_write: Option<Instant>, window_ms: u64) -> bool {
+    match last_write {
+        Some(time) if window_ms != 0 => time.elapsed() < Duration::from_millis(window_ms),
+        _ => false,
+    }
+}
+
+// Acquires a stream for a read on `namespace`, within the limits of the routing profile
+// attached to the namespace if there is one, or else of the client.
+fn acquire_stream_for(
+    client: &Client,
+    namespace: Option<&str>,
+    read_preference: ReadPreference,
+) -> Result<(PooledStream, bool, bool)> {
+    if client.shutting_down.load(Ordering::SeqCst) {
+        return Err(ShuttingDownError);
+    }
+
+    let _ = send_cursor_kills(client, false);
+    warn_idle_cursors(client);
+    let route = client.routing.route(namespace)?;
+    let permit = acquire_permit(client, route.as_ref())?;
+    let (mut stream, slave_ok, send_read_pref) =
+        client.topology.acquire_stream(client.clone(), read_preference)?;
+    if let Some(ref route) = route {
+        stream = route.route_stream(client, stream)?;
+    }
+    stream.hold_permit(permit);
+    Ok((stream, slave_ok, send_read_pref))
+}
+
+// Acquires a stream to the primary for a write on `namespace`, within the limits of the
+// routing profile attached to the namespace if there is one, or else of the client.
+fn acquire_write_stream_for(client: &Client, namespace: Option<&str>) -> Result<PooledStream> {
+    if client.shutting_down.load(Ordering::SeqCst) {
+        return Err(ShuttingDownError);
+    }
+
+    let _ = send_cursor_kills(client, false);
+    warn_idle_cursors(client);
+    let route = client.routing.route(namespace)?;
+    let permit = acquire_permit(client, route.as_ref())?;
+    let mut stream = client.topology.acquire_write_stream(client.clone())?;
+    if let Some(ref route) = route {
+        stream = route.route_stream(client, stream)?;
+    }
+    stream.hold_permit(permit);
+    record_write(client);
+    Ok(stream)
+}
+
+// Takes a permit from the limiter of the route if it limits operations, or else from the
+// client's.
+fn acquire_permit(
+    client: &Client,
+    route: Option<&Arc<NamespaceRoute>>,
+) -> Result<OperationPermit> {
+    match route.and_then(|route| route.limiter()) {
+        Some(limiter) => OperationLimiter::acquire(limiter),
+        None => OperationLimiter::acquire(&client.limiter),
+    }
+}
+
+// Acquires a connection to the server a cursor was opened on, which is the only server that
+// knows about the cursor. Rather than selecting another server, fails with a
+// `CursorServerUnavailableError` if that server is down or has left the topology. GetMores
+// aren't limited by the client, but are by the routing profile of the cursor's namespace.
+fn acquire_cursor_stream(client: &Client, host: &Host, namespace: &str) -> Result<PooledStream> {
+    if client.shutting_down.load(Ordering::SeqCst) {
+        return Err(ShuttingDownError);
+    }
+
+    let unavailable = || CursorServerUnavailableError(format!("{}:{}", host.host_name, host.port));
+
+    let server = match client.topology.description.read()?.servers.get(host) {
+        Some(server) => server.clone(),
+        None => return Err(unavailable()),
+    };
+
+    if server.description.read()?.server_type == ServerType::Unknown {
+        return Err(unavailable());
+    }
+
+    let _ = send_cursor_kills(client, false);
+
+    let route = client.routing.route(Some(namespace))?;
+    let permit = match route.as_ref().and_then(|route| route.limiter()) {
+        Some(limiter) => Some(OperationLimiter::acquire(limiter)?),
+        None => None,
+    };
+
+    let stream = match server.acquire_stream(client.clone()) {
+        Ok(stream) => stream,
+        Err(ref err) if err.is_network_error() => return Err(unavailable()),
+        Err(err) => return Err(err),
+    };
+
+    let mut stream = match route {
+        Some(ref route) => route.route_stream(client, stream)?,
+        None => stream,
+    };
+    if let Some(permit) = permit {
+        stream.hold_permit(permit);
+    }
+    Ok(stream)
+}
+
+// Acquires connections to the members a hedged read is sent to, or fewer than two if the read
+// can't be hedged.
+fn acquire_hedged_streams(
+    client: &Client,
+    read_pref: &ReadPreference,
+) -> Result<Vec<PooledStream>> {
+    if client.shutting_down.load(Ordering::SeqCst) {
+        return Err(ShuttingDownError);
+    }
+
+    let _ = send_cursor_kills(client, false);
+    warn_idle_cursors(client);
+
+    // A hedged read is a single operation, so its streams share one permit.
+    let permit = OperationLimiter::acquire(&client.limiter)?;
+    let mut streams = client.topology.acquire_hedged_streams(client.clone(), read_pref)?;
+    if let Some(stream) = streams.first_mut() {
+        stream.hold_permit(permit);
+    }
+    Ok(streams)
+}
+
+// Queues the kill of a cursor dropped before being exhausted, sending the queued kills once
+// there are enough of them.
+fn queue_cursor_kill(client: &Client, host: Host, namespace: String, cursor_id: i64) {
+    if client.shutting_down.load(Ordering::SeqCst) {
+        return;
+    }
+
+    let len = match client.pending_cursor_kills.lock() {
+        Ok(mut pending) => {
+            pending.push((host, namespace, cursor_id));
+            pending.len()
+        }
+        Err(_) => return,
+    };
+
+    if len >= CURSOR_KILL_BATCH_SIZE {
+        let _ = send_cursor_kills(client, false);
+    }
+}
+
+// Sends the queued cursor kills, with a killCursors command per namespace to servers that
+// support it and a single OP_KILL_CURSORS message per server otherwise. Unless `wait` is set,
+// the kills for servers without an idle connection stay queued rather than waiting for one,
+// since the caller may itself be holding the connection it would wait for.
+fn send_cursor_kills(client: &Client, wait: bool) -> Result<()> {
+    let pending = mem::replace(&mut *client.pending_cursor_kills.lock()?, Vec::new());
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let mut hosts = HashMap::new();
+    for (host, namespace, cursor_id) in pending {
+        hosts
+            .entry(host)
+            .or_insert_with(BTreeMap::new)
+            .entry(namespace)
+            .or_insert_with(Vec::new)
+            .push(cursor_id);
+    }
+
+    let mut result = Ok(());
+
+    for (host, namespaces) in hosts {
+        // Cursors on servers that are no longer part of the topology are gone with them.
+        let server = match client.topology.description.read()?.servers.get(&host) {
+            Some(server) => server.clone(),
+            None => continue,
+        };
+
+        let stream = if wait {
+            server.acquire_stream(client.clone()).map(Some)
+        } else {
+            server.try_acquire_stream(client.clone())
+        };
+
+        let mut stream = match stream {
+            Ok(Some(stream)) => stream,
+            Ok(None) => {
+                let mut pending = client.pending_cursor_kills.lock()?;
+                for (namespace, cursor_ids) in namespaces {
+                    pending.extend(cursor_ids.into_iter().map(|cursor_id| {
+                        (host.clone(), namespace.clone(), cursor_id)
+                    }));
+                }
+                continue;
+            }
+            Err(err) => {
+                result = Err(err);
+                continue;
+            }
+        };
+
+        let commands = client.server_supports(&host, ServerCapabilities::supports_find_command);
+        if commands.unwrap_or(false) {
+            for (namespace, cursor_ids) in namespaces {
+                let killed = kill_cursors_with_stream(client, &mut stream, &namespace, cursor_ids);
+                if let Err(err) = killed {
+                    result = Err(err);
+                }
+            }
+            continue;
+        }
+
+        let cursor_ids = namespaces.into_iter().flat_map(|(_, cursor_ids)| cursor_ids).collect();
+        let message = Message::new_kill_cursors(client.get_req_id(), cursor_ids);
+        client.log_message(true, &host, &message);
+        if let Err(err) = message.write(stream.get_socket()) {
+            client.metrics.record_error(&err);
+            stream.set_dirty(true);
+            result = Err(err);
+        }
+    }
+
+    result
+}
+
+// Kills cursors of a namespace with a killCursors command sent over `stream`.
+fn kill_cursors_with_stream(
+    client: &Client,
+    stream: &mut PooledStream,
+    namespace: &str,
+    cursor_ids: Vec<i64>,
+) -> Result<()> {
+    let index = match namespace.find('.') {
+        Some(index) => index,
+        None => return Ok(()),
+    };
+
+    let cmd = doc! {
+        "killCursors": &namespace[index + 1..],
+        "cursors": cursor_ids.iter().map(|&cursor_id| Bson::I64(cursor_id)).collect::<Vec<_>>(),
+    };
+
+    let reply = run_command_with_stream(
+        client,
+        stream,
+        &namespace[..index],
+        cmd,
+        CommandType::KillCursors,
+    )?;
+
+    check_cursors_killed(&reply, &cursor_ids)
+}
+
+// Checks that the reply to a killCursors command lists every cursor as either killed or not
+// found, since servers report cursors they failed to kill as alive or unknown.
+fn check_cursors_killed(reply: &bson::Document, cursor_ids: &[i64]) -> Result<()> {
+    // A dropped namespace took its cursors with it.
+    if command_ok(reply) == Some(false) {
+        return Ok(());
+    }
+
+    let killed = listed_cursor_ids(reply, "cursorsKilled");
+    let not_found = listed_cursor_ids(reply, "cursorsNotFound");
+
+    let remaining: Vec<_> = cursor_ids
+        .iter()
+        .filter(|cursor_id| !killed.contains(cursor_id) && !not_found.contains(cursor_id))
+        .map(|cursor_id| cursor_id.to_string())
+        .collect();
+
+    if remaining.is_empty() {
+        Ok(())
+    } else {
+        Err(OperationError(format!("Failed to kill cursors {}.", remaining.join(", "))))
+    }
+}
+
+// Reads an array of cursor ids from a killCursors reply.
+fn listed_cursor_ids(reply: &bson::Document, field: &str) -> Vec<i64> {
+    match reply.get(field) {
+        Some(&Bson::Array(ref cursor_ids)) => {
+            cursor_ids
+                .iter()
+                .filter_map(|cursor_id| match *cursor_id {
+                    Bson::I64(cursor_id) => Some(cursor_id),
+                    Bson::I32(cursor_id) => Some(i64::from(cursor_id)),
+                    _ => None,
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+// Pings a server over a connection from its own pool, returning how long the ping took.
+fn ping_server(client: &Client, server: &Server) -> Result<Duration> {
+    let start = Instant::now();
+    let mut stream = server.acquire_stream(client.clone())?;
+    let cmd = doc! { "ping": 1 };
+    run_command_with_stream(client, &mut stream, "admin", cmd, CommandType::Ping)?;
+    Ok(start.elapsed())
+}
+
+// Logs a warning for every cursor opened with noCursorTimeout that hasn't fetched a batch for
+// longer than the client's threshold, once until it fetches another.
+fn warn_idle_cursors(client: &Client) {
+    let threshold = match client.cursor_idle_warning {
+        Some(threshold) => threshold,
+        None => return,
+    };
+
+    let mut open_cursors = match client.open_cursors.lock() {
+        Ok(open_cursors) => open_cursors,
+        Err(_) => return,
+    };
+
+    for (cursor_id, cursor) in open_cursors.iter_mut() {
+        let idle = cursor.last_used.elapsed();
+        if !cursor.no_timeout || cursor.warned || idle < threshold {
+            continue;
+        }
+
+        cursor.warned = true;
+        client.log(LogLevel::Warn, "operation", || {
+            format!(
+                "Cursor {} on {} was opened with noCursorTimeout and hasn't fetched a batch for \
+                 {} s; the server keeps it open until it is exhausted or killed.",
+                cursor_id,
+                cursor.namespace,
+                idle.as_secs()
+            )
+        });
+    }
+}
+
+// Kills every server-side cursor that hasn't been exhausted, with one command per namespace.
+fn kill_open_cursors(client: &Client) -> Result<()> {
+    let open_cursors = mem::replace(&mut *client.open_cursors.lock()?, HashMap::new());
+
+    let mut namespaces = BTreeMap::new();
+    for (cursor_id, cursor) in open_cursors {
+        namespaces.entry(cursor.namespace).or_insert_with(Vec::new).push(cursor_id);
+    }
+
+    let (mut stream, _, _) = client
+        .topology
+        .acquire_stream(client.clone(), client.read_preference.to_owned())?;
+
+    for (namespace, cursor_ids) in namespaces {
+        kill_cursors_with_stream(client, &mut stream, &namespace, cursor_ids)?;
+    }
+
+    Ok(())
+}
+
+// Runs a cleanup command while shutting down, bypassing the check that rejects new operations.
+fn run_shutdown_command(
+    client: &Client,
+    db: &str,
+    cmd: bson::Document,
+    cmd_type: CommandType,
+) -> Result<bson::Document> {
+    let (mut stream, _, _) = client
+        .topology
+        .acquire_stream(client.clone(), client.read_preference.to_owned())?;
+
+    run_command_with_stream(client, &mut stream, db, cmd, cmd_type)
+}
+
+// Runs a command over a connection that has already been acquired, returning its reply.
+fn run_command_with_stream(
+    client: &Client,
+    stream: &mut PooledStream,
+    db: &str,
+    cmd: bson::Document,
+    cmd_type: CommandType,
+) -> Result<bson::Document> {
+    let mut options = FindOptions::new();
+    options.batch_size = Some(1);
+
+    let mut cursor = Cursor::query_with_stream(
+        stream,
+        client.clone(),
+        format!("{}.$cmd", db),
+        OpQueryFlags::empty(),
+        cmd,
+        options,
+        cmd_type,
+        false,
+        None,
+    )?;
+
+    match cursor.next() {
+        Some(Ok(reply)) => Ok(reply),
+        Some(Err(err)) => Err(err),
+        None => Err(OperationError(String::from("Command returned no response."))),
+    }
+}
+
+// Sends legacy write messages over a connection without reading replies. Command monitoring
+// sees them as the command they stand for, which is only built if a start hook is registered.
+fn send_unacknowledged<I, F>(
+    client: &Client,
+    stream: &mut PooledStream,
+    db_name: &str,
+    messages: I,
+    command: F,
+    cmd_type: CommandType,
+) -> Result<()>
+where
+    I: IntoIterator<Item = Result<Message>>,
+    F: FnOnce() -> bson::Document,
+{
+    let host = stream.host().clone();
+    let connstring = stream.get_socket().get_ref().peer_addr()?.to_string();
+    let cmd_name = cmd_type.to_str();
+    let monitored = cmd_type != CommandType::Suppressed;
+
+    let init_time = time::precise_time_ns();
+    let mut command = Some(command);
+    let mut req_id = None;
+
+    for message in messages {
+        let message = message?;
+
+        // The command is reported under the id of its first message.
+        if let Some(command) = command.take() {
+            req_id = Some(message.request_id());
+            if monitored && client.listener.has_start_hooks() {
+                let hook_result = client.run_start_hooks(&CommandStarted {
+                    command: command(),
+                    database_name: db_name.to_owned(),
+                    command_name: String::from(cmd_name),
+                    request_id: message.request_id() as i64,
+                    connection_string: connstring.clone(),
+                    host: host.clone(),
+                });
+
+                if hook_result.is_err() {
+                    return Err(Error::EventListenerError(None));
+                }
+            }
+        }
+
+        client.log_message(true, &host, &message);
+        if let Err(err) = message.write(stream.get_socket()) {
+            // Part of the message may have been sent, so the connection can't be reused.
+            client.metrics.record_error(&err);
+            stream.set_dirty(true);
+
+            if monitored {
+                let hook_result = client.run_completion_hooks(&CommandResult::Failure {
+                    duration: time::precise_time_ns() - init_time,
+                    command_name: String::from(cmd_name),
+                    failure: &err,
+                    request_id: message.request_id() as i64,
+                    connection_string: connstring,
+                    host: host,
+                });
+
+                if hook_result.is_err() {
+                    return Err(Error::EventListenerError(Some(Box::new(err))));
+                }
+            }
+
+            return Err(err);
+        }
+    }
+
+    if let Some(req_id) = req_id {
+        if monitored {
+            let _hook_result = client.run_completion_hooks(&CommandResult::Success {
+                duration: time::precise_time_ns() - init_time,
+                reply: doc! { "ok": 1 },
+                command_name: String::from(cmd_name),
+                request_id: req_id as i64,
+                connection_string: connstring,
+                host: host,
+            });
+        }
+    }
+
+    Ok(())
 }
 
 fn log_command_started(client: Client, command_started: &CommandStarted) {
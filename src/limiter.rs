@@ -0,0 +1,105 @@
+//! A limit on how many operations a client runs at once.
+//!
+//! Every operation takes a permit before checking out a connection, and holds it on the stream
+//! it checked out until the stream is returned to its pool. Once the limit is reached, further
+//! operations queue for a permit, and fail with an `OverloadedError` if none is released within
+//! the wait queue timeout.
+use metrics::ClientMetrics;
+use Error::OverloadedError;
+use Result;
+
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default)]
+struct Counts {
+    in_flight: usize,
+    queued: usize,
+}
+
+/// Hands out permits to run operations, at most `max` at a time if set.
+#[derive(Debug)]
+pub struct OperationLimiter {
+    max: Option<usize>,
+    wait_timeout: Option<Duration>,
+    counts: Mutex<Counts>,
+    released: Condvar,
+    // Reports the counts as gauges, if the limiter is the client's.
+    metrics: Option<Arc<ClientMetrics>>,
+}
+
+/// Allows an operation to run until it is dropped.
+#[derive(Debug)]
+pub struct OperationPermit {
+    limiter: Arc<OperationLimiter>,
+}
+
+impl OperationLimiter {
+    /// Creates a limiter allowing `max` operations at once, or any number if None. Operations
+    /// over the limit wait up to `wait_timeout` for a permit, or indefinitely if None; a zero
+    /// timeout fails them at once. The counts are reported to `metrics`, if given.
+    pub fn new(
+        max: Option<usize>,
+        wait_timeout: Option<Duration>,
+        metrics: Option<Arc<ClientMetrics>>,
+    ) -> OperationLimiter {
+        OperationLimiter {
+            max: max,
+            wait_timeout: wait_timeout,
+            counts: Mutex::new(Counts::default()),
+            released: Condvar::new(),
+            metrics: metrics,
+        }
+    }
+
+    /// Takes a permit, waiting for one to be released if the limit is reached.
+    pub fn acquire(limiter: &Arc<OperationLimiter>) -> Result<OperationPermit> {
+        let mut counts = limiter.counts.lock()?;
+
+        if let Some(max) = limiter.max {
+            if counts.in_flight >= max {
+                let deadline = limiter.wait_timeout.map(|timeout| Instant::now() + timeout);
+                counts.queued += 1;
+                limiter.report(&counts);
+
+                while counts.in_flight >= max {
+                    counts = match deadline {
+                        Some(deadline) => {
+                            let now = Instant::now();
+                            if now >= deadline {
+                                counts.queued -= 1;
+                                limiter.report(&counts);
+                                return Err(OverloadedError(max));
+                            }
+                            limiter.released.wait_timeout(counts, deadline - now)?.0
+                        }
+                        None => limiter.released.wait(counts)?,
+                    };
+                }
+
+                counts.queued -= 1;
+            }
+        }
+
+        counts.in_flight += 1;
+        limiter.report(&counts);
+
+        Ok(OperationPermit { limiter: limiter.clone() })
+    }
+
+    fn report(&self, counts: &Counts) {
+        if let Some(ref metrics) = self.metrics {
+            metrics.set_operation_gauges(counts.in_flight as u64, counts.queued as u64);
+        }
+    }
+}
+
+impl Drop for OperationPermit {
+    fn drop(&mut self) {
+        if let Ok(mut counts) = self.limiter.counts.lock() {
+            counts.in_flight -= 1;
+            self.limiter.report(&counts);
+        }
+        self.limiter.released.notify_one();
+    }
+}
@@ -0,0 +1,130 @@
+//! Structured logging of driver internals.
+//!
+//! A `Logger` set on the client receives records for connection lifecycle events, the messages
+//! sent to and received from servers, topology changes, retries and errors. Clients log nothing
+//! by default; `StderrLogger` writes records to standard error.
+use bson;
+
+use std::fmt;
+use std::io::{self, Write};
+
+/// The severity of a log record, from most to least verbose.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LogLevel {
+    /// Every message sent to or received from a server.
+    Trace,
+    /// Connections being opened and closed, and server state changes.
+    Debug,
+    /// Topology changes.
+    Info,
+    /// Failures the driver recovers from, such as a retried operation.
+    Warn,
+    /// Failures returned to the application.
+    Error,
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            LogLevel::Trace => "TRACE",
+            LogLevel::Debug => "DEBUG",
+            LogLevel::Info => "INFO",
+            LogLevel::Warn => "WARN",
+            LogLevel::Error => "ERROR",
+        };
+
+        fmt.write_str(name)
+    }
+}
+
+/// Receives the driver's log records.
+pub trait Logger: fmt::Debug + Send + Sync {
+    /// Returns whether records of the given level should be passed to `log`. Records are only
+    /// formatted if they are enabled.
+    fn enabled(&self, level: LogLevel) -> bool;
+
+    /// Records an event. `target` names the component that emitted it: `connection`,
+    /// `wire`, `topology`, `retry` or `operation`.
+    fn log(&self, level: LogLevel, target: &str, message: &str);
+}
+
+/// Discards every record; the default logger of a client.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopLogger;
+
+impl Logger for NoopLogger {
+    fn enabled(&self, _level: LogLevel) -> bool {
+        false
+    }
+
+    fn log(&self, _level: LogLevel, _target: &str, _message: &str) {}
+}
+
+/// Writes records at or above a minimum level to standard error.
+#[derive(Clone, Copy, Debug)]
+pub struct StderrLogger {
+    /// The least severe level that is written.
+    pub level: LogLevel,
+}
+
+impl StderrLogger {
+    /// Creates a logger that writes records at or above `level`.
+    pub fn new(level: LogLevel) -> StderrLogger {
+        StderrLogger { level: level }
+    }
+}
+
+impl Logger for StderrLogger {
+    fn enabled(&self, level: LogLevel) -> bool {
+        level >= self.level
+    }
+
+    fn log(&self, level: LogLevel, target: &str, message: &str) {
+        let _ = writeln!(io::stderr(), "[{} {}] {}", level, target, message);
+    }
+}
+
+// Commands whose contents may include credentials, as listed by the command monitoring spec.
+const REDACTED_COMMANDS: &[&str] = &[
+    "authenticate",
+    "saslstart",
+    "saslcontinue",
+    "getnonce",
+    "createuser",
+    "updateuser",
+    "copydbgetnonce",
+    "copydbsaslstart",
+    "copydb",
+];
+
+/// Formats a command for logging, hiding the contents of authentication commands.
+pub fn redact(command: &bson::Document) -> String {
+    match redacted_name(command) {
+        Some(name) => format!("{{ {}: <redacted> }}", name),
+        None => command.to_string(),
+    }
+}
+
+/// Returns a copy of a command to be kept for diagnostics, in which authentication commands
+/// are replaced by their name.
+pub fn redact_document(command: &bson::Document) -> bson::Document {
+    match redacted_name(command) {
+        Some(name) => {
+            let mut redacted = bson::Document::new();
+            redacted.insert(name.to_owned(), "<redacted>");
+            redacted
+        }
+        None => command.clone(),
+    }
+}
+
+// Returns the name of an authentication command, whose contents must not be recorded.
+fn redacted_name(command: &bson::Document) -> Option<&String> {
+    // Queries wrapped for a read preference name the command inside `$query`.
+    let name = match command.get("$query") {
+        Some(&bson::Bson::Document(ref query)) => query.keys().next(),
+        _ => command.keys().next(),
+    };
+
+    name.filter(|name| REDACTED_COMMANDS.contains(&&name.to_ascii_lowercase()[..]))
+}
@@ -0,0 +1,338 @@
+//! Client-side merging of cursors that are each sorted on the same field.
+//!
+//! Reading several collections, or several partitions of one collection such as the values of
+//! an `$in` split across queries, gives one sorted cursor per source. `Cursor::merge_sorted`
+//! combines them into a single stream in the same order, by holding the next document of each
+//! cursor and always returning the one that sorts first. Cursors fetch further batches as they
+//! run out, so only one batch per cursor is held in memory.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::coll::options::FindOptions;
+//! # use mongodb::cursor::Cursor;
+//! # use mongodb::db::ThreadedDatabase;
+//! # use mongodb::merge::SortOrder;
+//! #
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! # let db = client.db("test");
+//! let options = FindOptions::builder().sort(doc! { "created": 1 }).build();
+//!
+//! let cursors = vec![
+//!     db.collection("orders_2019").find(None, Some(options.clone())).unwrap(),
+//!     db.collection("orders_2020").find(None, Some(options)).unwrap(),
+//! ];
+//! for doc in Cursor::merge_sorted(cursors, "created", SortOrder::Ascending) {
+//!     println!("{}", doc.unwrap());
+//! }
+//! # }
+//! ```
+use bson::{self, Bson};
+use coll::shard_key::value_at;
+use cursor::Cursor;
+use Result;
+
+use std::cmp::Ordering;
+
+/// The order documents are sorted in on the merge key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SortOrder {
+    /// Smallest values first, as sorted by `{ key: 1 }`.
+    Ascending,
+    /// Largest values first, as sorted by `{ key: -1 }`.
+    Descending,
+}
+
+/// The documents of several cursors sorted on the same key, merged into a single sorted stream.
+///
+/// Documents with equal keys are returned in the order of their cursors. Once every cursor is
+/// exhausted, each has been read to its end, so none is left open on the server.
+#[derive(Debug)]
+pub struct SortedMerge {
+    cursors: Vec<Cursor>,
+    // The next document of each cursor, read ahead to compare them.
+    heads: Vec<Option<bson::Document>>,
+    // Whether each cursor has returned its last document.
+    exhausted: Vec<bool>,
+    sort_key: String,
+    order: SortOrder,
+}
+
+impl SortedMerge {
+    /// Merges cursors that are each sorted on `sort_key`, which may be a dotted path, in the
+    /// given order.
+    pub fn new(cursors: Vec<Cursor>, sort_key: &str, order: SortOrder) -> SortedMerge {
+        let count = cursors.len();
+        SortedMerge {
+            cursors: cursors,
+            heads: vec![None; count],
+            exhausted: vec![false; count],
+            sort_key: String::from(sort_key),
+            order: order,
+        }
+    }
+
+    /// Returns the cursors being merged.
+    pub fn cursors(&self) -> &[Cursor] {
+        &self.cursors
+    }
+
+    /// Returns true once every cursor has returned its last document, and no document read
+    /// ahead is waiting to be returned.
+    pub fn is_exhausted(&self) -> bool {
+        self.exhausted.iter().all(|&exhausted| exhausted) &&
+            self.heads.iter().all(|head| head.is_none())
+    }
+
+    // Reads the next document of every cursor that has none waiting.
+    fn fill_heads(&mut self) -> Result<()> {
+        for i in 0..self.cursors.len() {
+            if self.heads[i].is_some() || self.exhausted[i] {
+                continue;
+            }
+
+            match self.cursors[i].next() {
+                Some(doc) => self.heads[i] = Some(doc?),
+                None => self.exhausted[i] = true,
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the position of the cursor whose next document sorts first, preferring earlier
+    // cursors among equal keys.
+    fn winner(&self) -> Option<usize> {
+        let mut winner: Option<(usize, &Bson)> = None;
+
+        for (i, head) in self.heads.iter().enumerate() {
+            let doc = match *head {
+                Some(ref doc) => doc,
+                None => continue,
+            };
+
+            // A missing key sorts as null, as it does on the server.
+            let key = value_at(doc, &self.sort_key).unwrap_or(&Bson::Null);
+            let wins = match winner {
+                None => true,
+                Some((_, best)) => {
+                    let ordering = compare_bson(key, best);
+                    match self.order {
+                        SortOrder::Ascending => ordering == Ordering::Less,
+                        SortOrder::Descending => ordering == Ordering::Greater,
+                    }
+                }
+            };
+
+            if wins {
+                winner = Some((i, key));
+            }
+        }
+
+        winner.map(|(i, _)| i)
+    }
+}
+
+impl Iterator for SortedMerge {
+    type Item = Result<bson::Document>;
+
+    /// Returns the document that sorts first among the next documents of every cursor. An
+    /// error reading a cursor is returned once, and the merge can be resumed after it.
+    fn next(&mut self) -> Option<Result<bson::Document>> {
+        if let Err(err) = self.fill_heads() {
+            return Some(Err(err));
+        }
+
+        let winner = self.winner()?;
+        self.heads[winner].take().map(Ok)
+    }
+}
+
+impl Cursor {
+    /// Merges cursors that are each sorted on `sort_key` in the given order into one sorted
+    /// stream, comparing keys of different types in the order the server sorts them in.
+    pub fn merge_sorted(cursors: Vec<Cursor>, sort_key: &str, order: SortOrder) -> SortedMerge {
+        SortedMerge::new(cursors, sort_key, order)
+    }
+}
+
+/// Compares two values in the order the server sorts them in: first by type, in the order
+/// null, numbers, strings and symbols, documents, arrays, binary data, ObjectIds, booleans,
+/// dates, timestamps, regular expressions, then JavaScript code, and then by value. Numbers of
+/// different types compare by their numeric value, with NaN before every other number.
+///
+/// Arrays compare element by element, as in an aggregation `$cmp`. Sorting on a field holding
+/// arrays instead sorts by their smallest or largest element, so cursors sorted on such a field
+/// may not merge in the server's order.
+pub fn compare_bson(a: &Bson, b: &Bson) -> Ordering {
+    let by_type = type_rank(a).cmp(&type_rank(b));
+    if by_type != Ordering::Equal {
+        return by_type;
+    }
+
+    match (a, b) {
+        (&Bson::I32(_), _) |
+        (&Bson::I64(_), _) |
+        (&Bson::FloatingPoint(_), _) => compare_numbers(a, b),
+        (&Bson::String(ref a), &Bson::String(ref b)) |
+        (&Bson::String(ref a), &Bson::Symbol(ref b)) |
+        (&Bson::Symbol(ref a), &Bson::String(ref b)) |
+        (&Bson::Symbol(ref a), &Bson::Symbol(ref b)) => a.as_bytes().cmp(b.as_bytes()),
+        (&Bson::Document(ref a), &Bson::Document(ref b)) => compare_documents(a, b),
+        (&Bson::Array(ref a), &Bson::Array(ref b)) => {
+            for (a, b) in a.iter().zip(b.iter()) {
+                let ordering = compare_bson(a, b);
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            a.len().cmp(&b.len())
+        }
+        (&Bson::Binary(a_subtype, ref a), &Bson::Binary(b_subtype, ref b)) => {
+            a.len()
+                .cmp(&b.len())
+                .then(u8::from(a_subtype).cmp(&u8::from(b_subtype)))
+                .then_with(|| a.cmp(b))
+        }
+        (&Bson::ObjectId(ref a), &Bson::ObjectId(ref b)) => a.bytes().cmp(&b.bytes()),
+        (&Bson::Boolean(a), &Bson::Boolean(b)) => a.cmp(&b),
+        (&Bson::UtcDatetime(ref a), &Bson::UtcDatetime(ref b)) => a.cmp(b),
+        (&Bson::TimeStamp(a), &Bson::TimeStamp(b)) => (a as u64).cmp(&(b as u64)),
+        (&Bson::RegExp(ref a_pattern, ref a_options),
+         &Bson::RegExp(ref b_pattern, ref b_options)) => {
+            a_pattern.cmp(b_pattern).then_with(|| a_options.cmp(b_options))
+        }
+        (&Bson::JavaScriptCode(ref a), &Bson::JavaScriptCode(ref b)) => a.cmp(b),
+        (&Bson::JavaScriptCodeWithScope(ref a_code, ref a_scope),
+         &Bson::JavaScriptCodeWithScope(ref b_code, ref b_scope)) => {
+            a_code.cmp(b_code).then_with(|| compare_documents(a_scope, b_scope))
+        }
+        _ => Ordering::Equal,
+    }
+}
+
+// The position of a type in the server's sort order, where types of equal rank compare by
+// value.
+fn type_rank(value: &Bson) -> u8 {
+    match *value {
+        Bson::Null => 5,
+        Bson::I32(_) | Bson::I64(_) | Bson::FloatingPoint(_) => 10,
+        Bson::String(_) | Bson::Symbol(_) => 15,
+        Bson::Document(_) => 20,
+        Bson::Array(_) => 25,
+        Bson::Binary(..) => 30,
+        Bson::ObjectId(_) => 35,
+        Bson::Boolean(_) => 40,
+        Bson::UtcDatetime(_) => 45,
+        Bson::TimeStamp(_) => 47,
+        Bson::RegExp(..) => 50,
+        Bson::JavaScriptCode(_) => 60,
+        Bson::JavaScriptCodeWithScope(..) => 65,
+    }
+}
+
+fn compare_numbers(a: &Bson, b: &Bson) -> Ordering {
+    match (integer(a), integer(b)) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        _ => {
+            let (a, b) = (double(a), double(b));
+            match (a.is_nan(), b.is_nan()) {
+                (true, true) => Ordering::Equal,
+                (true, false) => Ordering::Less,
+                (false, true) => Ordering::Greater,
+                (false, false) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+            }
+        }
+    }
+}
+
+fn integer(value: &Bson) -> Option<i64> {
+    match *value {
+        Bson::I32(n) => Some(n as i64),
+        Bson::I64(n) => Some(n),
+        _ => None,
+    }
+}
+
+fn double(value: &Bson) -> f64 {
+    match *value {
+        Bson::I32(n) => n as f64,
+        Bson::I64(n) => n as f64,
+        Bson::FloatingPoint(n) => n,
+        _ => 0.0,
+    }
+}
+
+// Compares documents field by field, by the type of each value, then its name, then the value
+// itself; a document that is a prefix of another sorts first.
+fn compare_documents(a: &bson::Document, b: &bson::Document) -> Ordering {
+    for ((a_key, a_value), (b_key, b_value)) in a.iter().zip(b.iter()) {
+        let ordering = type_rank(a_value)
+            .cmp(&type_rank(b_value))
+            .then_with(|| a_key.cmp(b_key))
+            .then_with(|| compare_bson(a_value, b_value));
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{self, bson, doc, Bson};
+    use bson::oid::ObjectId;
+    use super::compare_bson;
+
+    use std::cmp::Ordering;
+    use std::f64;
+
+    #[test]
+    fn orders_types() {
+        let ordered = vec![
+            Bson::Null,
+            Bson::FloatingPoint(f64::NAN),
+            Bson::I32(-5),
+            Bson::FloatingPoint(1.5),
+            Bson::I64(2),
+            Bson::String(String::from("a")),
+            Bson::Symbol(String::from("b")),
+            Bson::Document(doc! { "a": 1 }),
+            Bson::Array(vec![Bson::I32(1)]),
+            Bson::Binary(bson::spec::BinarySubtype::Generic, vec![1]),
+            Bson::ObjectId(ObjectId::new().unwrap()),
+            Bson::Boolean(false),
+            Bson::Boolean(true),
+            Bson::TimeStamp(1),
+        ];
+
+        for (i, a) in ordered.iter().enumerate() {
+            for (j, b) in ordered.iter().enumerate() {
+                assert_eq!(compare_bson(a, b), i.cmp(&j), "{} vs {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn compares_numbers_across_types() {
+        assert_eq!(compare_bson(&Bson::I32(3), &Bson::I64(3)), Ordering::Equal);
+        assert_eq!(compare_bson(&Bson::I64(3), &Bson::FloatingPoint(3.0)), Ordering::Equal);
+        assert_eq!(compare_bson(&Bson::I64(3), &Bson::FloatingPoint(3.5)), Ordering::Less);
+        assert_eq!(compare_bson(&Bson::I64(i64::max_value()), &Bson::I64(0)), Ordering::Greater);
+    }
+
+    #[test]
+    fn compares_documents_and_arrays() {
+        let a = Bson::Document(doc! { "a": 1, "b": 2 });
+        let b = Bson::Document(doc! { "a": 1, "b": "x" });
+        let c = Bson::Document(doc! { "a": 1 });
+        assert_eq!(compare_bson(&a, &b), Ordering::Less);
+        assert_eq!(compare_bson(&c, &a), Ordering::Less);
+
+        let short = Bson::Array(vec![Bson::I32(1)]);
+        let long = Bson::Array(vec![Bson::I32(1), Bson::I32(0)]);
+        assert_eq!(compare_bson(&short, &long), Ordering::Less);
+    }
+}
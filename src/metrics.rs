@@ -0,0 +1,509 @@
+//! Counters of the requests a client sends, the bytes it exchanges with servers, the errors its
+//! requests fail with, and how long they take.
+//!
+//! Counters are updated with relaxed atomic operations on every message, and read through
+//! `ThreadedClient::metrics`, which returns a `MetricsSnapshot`. A snapshot's fields map onto
+//! Prometheus metrics, and `MetricsSnapshot::to_prometheus` writes them in its text format.
+//!
+//! ```no_run
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::metrics::OperationType;
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! let metrics = client.metrics();
+//! println!("{} queries, p99 latency {:?}", metrics.operations[&OperationType::Query],
+//!          metrics.latency.quantile(0.99));
+//! print!("{}", metrics.to_prometheus("mongodb_client"));
+//! # }
+//! ```
+use bson::Bson;
+use wire_protocol::flags::OpReplyFlags;
+use wire_protocol::operations::Message;
+use Error;
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// The upper bounds of the latency histogram's buckets, in milliseconds. Round trips that take
+/// longer fall in a final, unbounded bucket.
+pub const LATENCY_BUCKETS_MS: [u64; 10] = [1, 2, 5, 10, 25, 50, 100, 250, 500, 1000];
+
+const BUCKET_COUNT: usize = 11;
+
+/// The type of operation a request performs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OperationType {
+    Query,
+    GetMore,
+    Insert,
+    Update,
+    Delete,
+    /// Any other command, or a request to kill cursors.
+    Command,
+}
+
+impl OperationType {
+    /// Every operation type, in order.
+    pub const ALL: [OperationType; 6] = [
+        OperationType::Query,
+        OperationType::GetMore,
+        OperationType::Insert,
+        OperationType::Update,
+        OperationType::Delete,
+        OperationType::Command,
+    ];
+
+    /// The name of the operation type, for use as a metric label.
+    pub fn name(self) -> &'static str {
+        match self {
+            OperationType::Query => "query",
+            OperationType::GetMore => "getmore",
+            OperationType::Insert => "insert",
+            OperationType::Update => "update",
+            OperationType::Delete => "delete",
+            OperationType::Command => "command",
+        }
+    }
+
+    /// Returns the type of operation performed by a request, or `None` for a reply. Commands
+    /// that read or write documents, such as `find` or `insert`, count as those operations.
+    pub fn of(message: &Message) -> Option<OperationType> {
+        match *message {
+            Message::OpReply { .. } => None,
+            Message::OpQuery { ref namespace, ref query, .. } => {
+                if !namespace.ends_with(".$cmd") {
+                    return Some(OperationType::Query);
+                }
+
+                // Commands sent with a read preference are wrapped in `$query`.
+                let command = match query.get("$query") {
+                    Some(&Bson::Document(ref command)) => command,
+                    _ => query,
+                };
+                Some(OperationType::of_command(command.keys().next().map(String::as_str)))
+            }
+            Message::OpQueryRaw { ref namespace, ref query, .. } => {
+                if namespace.ends_with(".$cmd") {
+                    Some(OperationType::of_command(first_key(query)))
+                } else {
+                    Some(OperationType::Query)
+                }
+            }
+            Message::OpGetMore { .. } => Some(OperationType::GetMore),
+            Message::OpInsert { .. } => Some(OperationType::Insert),
+            Message::OpUpdate { .. } => Some(OperationType::Update),
+            Message::OpDelete { .. } => Some(OperationType::Delete),
+            Message::OpKillCursors { .. } => Some(OperationType::Command),
+        }
+    }
+
+    fn of_command(name: Option<&str>) -> OperationType {
+        match name {
+            Some("find") => OperationType::Query,
+            Some("getMore") => OperationType::GetMore,
+            Some("insert") => OperationType::Insert,
+            Some("update") => OperationType::Update,
+            Some("delete") => OperationType::Delete,
+            _ => OperationType::Command,
+        }
+    }
+}
+
+// Reads the first key of an encoded document, which follows its length and the type of the
+// first element.
+fn first_key(doc: &[u8]) -> Option<&str> {
+    let key = doc.get(5..)?;
+    let end = key.iter().position(|&b| b == 0)?;
+    ::std::str::from_utf8(&key[..end]).ok()
+}
+
+/// The kind of error a request failed with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorKind {
+    /// The connection failed or was closed.
+    Network,
+    /// No reply arrived in time.
+    Timeout,
+    /// A reply couldn't be read or didn't match its request.
+    Protocol,
+    /// The server flagged the reply as a query failure.
+    Server,
+    Other,
+}
+
+impl ErrorKind {
+    /// Every kind of error, in order.
+    pub const ALL: [ErrorKind; 5] = [
+        ErrorKind::Network,
+        ErrorKind::Timeout,
+        ErrorKind::Protocol,
+        ErrorKind::Server,
+        ErrorKind::Other,
+    ];
+
+    /// The name of the kind of error, for use as a metric label.
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorKind::Network => "network",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Protocol => "protocol",
+            ErrorKind::Server => "server",
+            ErrorKind::Other => "other",
+        }
+    }
+
+    /// Classifies an error that a request failed with.
+    pub fn of(err: &Error) -> ErrorKind {
+        match *err {
+            Error::DeadlineExceededError => ErrorKind::Timeout,
+            Error::IoError(ref err) if err.kind() == io::ErrorKind::TimedOut ||
+                                       err.kind() == io::ErrorKind::WouldBlock => {
+                ErrorKind::Timeout
+            }
+            Error::ProtocolError(_) |
+            Error::ResponseError(_) => ErrorKind::Protocol,
+            ref err if err.is_network_error() => ErrorKind::Network,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// The counters of a client, updated as it exchanges messages with servers.
+#[derive(Debug, Default)]
+pub struct ClientMetrics {
+    operations: [AtomicU64; 6],
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    errors: [AtomicU64; 5],
+    latency_buckets: [AtomicU64; BUCKET_COUNT],
+    latency_count: AtomicU64,
+    latency_sum_us: AtomicU64,
+    in_flight_operations: AtomicU64,
+    queued_operations: AtomicU64,
+}
+
+impl ClientMetrics {
+    pub fn new() -> ClientMetrics {
+        Default::default()
+    }
+
+    /// Counts a message sent to, or received from, a server.
+    pub fn record_message(&self, sent: bool, message: &Message) {
+        let length = message.length().max(0) as u64;
+
+        if sent {
+            self.bytes_sent.fetch_add(length, Ordering::Relaxed);
+            if let Some(operation) = OperationType::of(message) {
+                self.operations[operation as usize].fetch_add(1, Ordering::Relaxed);
+            }
+            return;
+        }
+
+        self.bytes_received.fetch_add(length, Ordering::Relaxed);
+        if let Message::OpReply { flags, .. } = *message {
+            if flags.contains(OpReplyFlags::QUERY_FAILURE) {
+                self.errors[ErrorKind::Server as usize].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Counts an error that a request failed with.
+    pub fn record_error(&self, err: &Error) {
+        self.errors[ErrorKind::of(err) as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Adds the time a request took to be answered to the latency histogram.
+    pub fn record_latency(&self, latency: Duration) {
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&bound| latency <= Duration::from_millis(bound))
+            .unwrap_or(BUCKET_COUNT - 1);
+        let micros = latency.as_secs() * 1_000_000 + u64::from(latency.subsec_micros());
+
+        self.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.latency_count.fetch_add(1, Ordering::Relaxed);
+        self.latency_sum_us.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    /// Records how many operations hold a permit to run, and how many are waiting for one.
+    pub fn set_operation_gauges(&self, in_flight: u64, queued: u64) {
+        self.in_flight_operations.store(in_flight, Ordering::Relaxed);
+        self.queued_operations.store(queued, Ordering::Relaxed);
+    }
+
+    /// Returns the current values of the counters. Counters are read one at a time while
+    /// requests may be updating them, so a snapshot can be off by the requests in flight.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+
+        let buckets = self.latency_buckets
+            .iter()
+            .enumerate()
+            .map(|(i, count)| {
+                let bound = LATENCY_BUCKETS_MS.get(i).map(|&ms| Duration::from_millis(ms));
+                (bound, load(count))
+            })
+            .collect();
+
+        MetricsSnapshot {
+            operations: OperationType::ALL
+                .iter()
+                .map(|&operation| (operation, load(&self.operations[operation as usize])))
+                .collect(),
+            bytes_sent: load(&self.bytes_sent),
+            bytes_received: load(&self.bytes_received),
+            errors: ErrorKind::ALL
+                .iter()
+                .map(|&kind| (kind, load(&self.errors[kind as usize])))
+                .collect(),
+            latency: LatencyHistogram {
+                buckets: buckets,
+                count: load(&self.latency_count),
+                sum: Duration::from_micros(load(&self.latency_sum_us)),
+            },
+            in_flight_operations: load(&self.in_flight_operations),
+            queued_operations: load(&self.queued_operations),
+        }
+    }
+
+    /// Sets every counter back to zero. The numbers of operations in flight and queued are
+    /// current values rather than counters, so they are kept.
+    pub fn reset(&self) {
+        let counters = self.operations
+            .iter()
+            .chain(&self.errors)
+            .chain(&self.latency_buckets)
+            .chain(vec![
+                &self.bytes_sent,
+                &self.bytes_received,
+                &self.latency_count,
+                &self.latency_sum_us,
+            ]);
+
+        for counter in counters {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The values of a client's counters at one point in time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricsSnapshot {
+    /// The number of requests sent, by the type of operation they perform.
+    pub operations: BTreeMap<OperationType, u64>,
+    /// The number of bytes sent in messages to servers.
+    pub bytes_sent: u64,
+    /// The number of bytes received in messages from servers.
+    pub bytes_received: u64,
+    /// The number of errors requests failed with, by kind.
+    pub errors: BTreeMap<ErrorKind, u64>,
+    /// How long requests took to be answered.
+    pub latency: LatencyHistogram,
+    /// The number of operations running, i.e. holding one of the permits limited by
+    /// `ClientOptions::max_concurrent_operations`.
+    pub in_flight_operations: u64,
+    /// The number of operations waiting for a permit to run.
+    pub queued_operations: u64,
+}
+
+impl MetricsSnapshot {
+    /// Writes the snapshot in the Prometheus text exposition format, naming each metric with
+    /// the given prefix. Latencies are written in seconds.
+    pub fn to_prometheus(&self, prefix: &str) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(out, "# TYPE {}_operations_total counter", prefix);
+        for (operation, count) in &self.operations {
+            let _ = writeln!(
+                out,
+                "{}_operations_total{{type=\"{}\"}} {}",
+                prefix,
+                operation.name(),
+                count
+            );
+        }
+
+        let _ = writeln!(out, "# TYPE {}_sent_bytes_total counter", prefix);
+        let _ = writeln!(out, "{}_sent_bytes_total {}", prefix, self.bytes_sent);
+        let _ = writeln!(out, "# TYPE {}_received_bytes_total counter", prefix);
+        let _ = writeln!(out, "{}_received_bytes_total {}", prefix, self.bytes_received);
+
+        let _ = writeln!(out, "# TYPE {}_errors_total counter", prefix);
+        for (kind, count) in &self.errors {
+            let _ = writeln!(out, "{}_errors_total{{kind=\"{}\"}} {}", prefix, kind.name(), count);
+        }
+
+        // Prometheus buckets count every observation up to their bound.
+        let _ = writeln!(out, "# TYPE {}_latency_seconds histogram", prefix);
+        let mut cumulative = 0;
+        for &(bound, count) in &self.latency.buckets {
+            cumulative += count;
+            let le = bound.map_or(String::from("+Inf"), |bound| seconds(bound).to_string());
+            let _ = writeln!(
+                out,
+                "{}_latency_seconds_bucket{{le=\"{}\"}} {}",
+                prefix,
+                le,
+                cumulative
+            );
+        }
+        let _ = writeln!(out, "{}_latency_seconds_sum {}", prefix, seconds(self.latency.sum));
+        let _ = writeln!(out, "{}_latency_seconds_count {}", prefix, self.latency.count);
+
+        let _ = writeln!(out, "# TYPE {}_in_flight_operations gauge", prefix);
+        let _ = writeln!(out, "{}_in_flight_operations {}", prefix, self.in_flight_operations);
+        let _ = writeln!(out, "# TYPE {}_queued_operations gauge", prefix);
+        let _ = writeln!(out, "{}_queued_operations {}", prefix, self.queued_operations);
+
+        out
+    }
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+/// A histogram of how long requests took to be answered.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LatencyHistogram {
+    /// The upper bound of each bucket, with `None` for the final, unbounded one, and the number
+    /// of requests that took longer than the previous bucket's bound and at most this one's.
+    pub buckets: Vec<(Option<Duration>, u64)>,
+    /// The number of requests timed.
+    pub count: u64,
+    /// The total time the requests took.
+    pub sum: Duration,
+}
+
+impl LatencyHistogram {
+    /// Estimates the latency that the given fraction of requests, between 0 and 1, didn't
+    /// exceed, by interpolating within the bucket the quantile falls in. Quantiles that fall in
+    /// the unbounded bucket are estimated as the largest bound. Returns `None` if no request was
+    /// timed.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+
+        let rank = q.max(0.0).min(1.0) * self.count as f64;
+        let mut below = 0;
+        let mut lower = Duration::from_millis(0);
+
+        for &(bound, count) in &self.buckets {
+            let upper = match bound {
+                Some(upper) => upper,
+                None => return Some(lower),
+            };
+
+            if count > 0 && (below + count) as f64 >= rank {
+                let fraction = (rank - below as f64) / count as f64;
+                let secs = seconds(lower) + (seconds(upper) - seconds(lower)) * fraction;
+                return Some(Duration::from_nanos((secs * 1e9) as u64));
+            }
+
+            below += count;
+            lower = upper;
+        }
+
+        Some(lower)
+    }
+
+    /// Estimates the median latency.
+    pub fn p50(&self) -> Option<Duration> {
+        self.quantile(0.5)
+    }
+
+    /// Estimates the 95th percentile latency.
+    pub fn p95(&self) -> Option<Duration> {
+        self.quantile(0.95)
+    }
+
+    /// Estimates the 99th percentile latency.
+    pub fn p99(&self) -> Option<Duration> {
+        self.quantile(0.99)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bson::{self, bson, doc};
+    use wire_protocol::flags::OpQueryFlags;
+
+    fn query(namespace: &str, query: bson::Document) -> Message {
+        Message::new_query(1, OpQueryFlags::empty(), namespace.to_owned(), 0, 0, query, None)
+            .unwrap()
+    }
+
+    #[test]
+    fn classifies_requests() {
+        let cases = vec![
+            (query("db.coll", doc! { "x": 1 }), OperationType::Query),
+            (query("db.$cmd", doc! { "find": "coll" }), OperationType::Query),
+            (query("db.$cmd", doc! { "getMore": 1i64 }), OperationType::GetMore),
+            (query("db.$cmd", doc! { "insert": "coll" }), OperationType::Insert),
+            (query("db.$cmd", doc! { "update": "coll" }), OperationType::Update),
+            (query("db.$cmd", doc! { "delete": "coll" }), OperationType::Delete),
+            (query("db.$cmd", doc! { "ping": 1 }), OperationType::Command),
+            (
+                query("db.$cmd", doc! { "$query": { "find": "coll" }, "$readPreference": {} }),
+                OperationType::Query,
+            ),
+            (Message::new_get_more(1, "db.coll".into(), 0, 5), OperationType::GetMore),
+            (Message::new_kill_cursors(1, vec![5]), OperationType::Command),
+        ];
+
+        for (message, operation) in cases {
+            assert_eq!(OperationType::of(&message), Some(operation), "{:?}", message);
+        }
+    }
+
+    #[test]
+    fn classifies_errors() {
+        let timed_out = io::Error::new(io::ErrorKind::WouldBlock, "timed out");
+        assert_eq!(ErrorKind::of(&Error::IoError(timed_out)), ErrorKind::Timeout);
+        let reset = io::Error::new(io::ErrorKind::ConnectionReset, "reset");
+        assert_eq!(ErrorKind::of(&Error::IoError(reset)), ErrorKind::Network);
+        assert_eq!(ErrorKind::of(&Error::DeadlineExceededError), ErrorKind::Timeout);
+        assert_eq!(ErrorKind::of(&Error::ProtocolError(String::new())), ErrorKind::Protocol);
+        assert_eq!(ErrorKind::of(&Error::CancelledError), ErrorKind::Other);
+    }
+
+    #[test]
+    fn latency_quantiles() {
+        let metrics = ClientMetrics::new();
+        assert_eq!(metrics.snapshot().latency.p50(), None);
+
+        // 90 requests take under a millisecond, 9 take 20 and 1 takes 2 seconds.
+        for _ in 0..90 {
+            metrics.record_latency(Duration::from_micros(500));
+        }
+        for _ in 0..9 {
+            metrics.record_latency(Duration::from_millis(20));
+        }
+        metrics.record_latency(Duration::from_secs(2));
+
+        let latency = metrics.snapshot().latency;
+        assert_eq!(latency.count, 100);
+        assert_eq!(latency.sum, Duration::from_micros(90 * 500 + 9 * 20_000 + 2_000_000));
+        assert_eq!(latency.buckets[0], (Some(Duration::from_millis(1)), 90));
+        assert_eq!(latency.buckets[4], (Some(Duration::from_millis(25)), 9));
+        assert_eq!(latency.buckets[10], (None, 1));
+
+        // The median falls in the first bucket, and the 95th percentile in the 10-25ms one.
+        let p50 = latency.p50().unwrap();
+        assert!(p50 > Duration::from_micros(500) && p50 < Duration::from_millis(1));
+        let p95 = latency.p95().unwrap();
+        assert!(p95 > Duration::from_millis(10) && p95 < Duration::from_millis(25));
+        assert_eq!(latency.quantile(1.0), Some(Duration::from_secs(1)));
+
+        metrics.reset();
+        assert_eq!(metrics.snapshot().latency.count, 0);
+        assert!(metrics.snapshot().latency.buckets.iter().all(|&(_, count)| count == 0));
+    }
+}
@@ -0,0 +1,197 @@
+//! Deadlines and cancellation shared by the operations of a unit of work.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::db::ThreadedDatabase;
+//! # use mongodb::op_ctx::OpCtx;
+//! # use std::time::Duration;
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! # let coll = client.db("test").collection("orders");
+//! #
+//! // Every operation of the request shares what remains of its two seconds.
+//! let ctx = OpCtx::with_timeout(Duration::from_secs(2));
+//! let user = coll.find_one_with_ctx(Some(doc! { "name": "Jo" }), None, &ctx).unwrap();
+//! let orders = coll.find_with_ctx(Some(doc! { "user": "Jo" }), None, &ctx).unwrap();
+//! # }
+//! ```
+use Error::{self, CancelledError, DeadlineExceededError};
+use Result;
+use pool::PooledStream;
+
+use std::net::{Shutdown, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// The state shared by the clones of a context.
+#[derive(Debug, Default)]
+struct Shared {
+    cancelled: AtomicBool,
+    // The sockets of the round trips in progress, shut down when the context is cancelled.
+    sockets: Mutex<Vec<(usize, TcpStream)>>,
+    next_id: AtomicUsize,
+}
+
+/// A deadline and a cancellation flag for the operations of a unit of work, such as a request
+/// handler, so that they all give up once its time budget is spent or it is abandoned.
+///
+/// The time left before the deadline is sent as `maxTimeMS` with each operation, so that the
+/// server stops working on it, and also limits how long each reply is waited for. Operations
+/// that fail because the deadline passed fail with a `DeadlineExceededError`.
+///
+/// Cancelling a context fails its operations that have not been sent yet with a
+/// `CancelledError`, and interrupts those waiting for a reply; their connections are closed
+/// rather than reused, since the reply would still arrive. Clones share the cancellation flag,
+/// so a context can be cancelled from another thread.
+#[derive(Clone, Debug, Default)]
+pub struct OpCtx {
+    deadline: Option<Instant>,
+    shared: Arc<Shared>,
+}
+
+impl OpCtx {
+    /// Returns a context without a deadline, which only ends when it is cancelled.
+    pub fn new() -> OpCtx {
+        Default::default()
+    }
+
+    /// Returns a context whose operations must complete by `deadline`.
+    pub fn with_deadline(deadline: Instant) -> OpCtx {
+        OpCtx {
+            deadline: Some(deadline),
+            shared: Arc::default(),
+        }
+    }
+
+    /// Returns a context whose operations must complete within `timeout` from now.
+    pub fn with_timeout(timeout: Duration) -> OpCtx {
+        OpCtx::with_deadline(Instant::now() + timeout)
+    }
+
+    /// Returns the deadline of the context, if it has one.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Returns the time left before the deadline, which is zero once it has passed, or `None`
+    /// if the context has no deadline.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline.map(|deadline| {
+            let now = Instant::now();
+            if deadline > now {
+                deadline - now
+            } else {
+                Duration::from_secs(0)
+            }
+        })
+    }
+
+    /// Returns the smaller of `max_time_ms` and the time left before the deadline, in
+    /// milliseconds. The time left is rounded up, so that the server doesn't give up before the
+    /// deadline, and is at least 1, since 0 would mean no limit.
+    pub fn limit_max_time_ms(&self, max_time_ms: Option<i64>) -> Option<i64> {
+        let remaining = match self.remaining() {
+            Some(remaining) => {
+                let nanos = i64::from(remaining.subsec_nanos());
+                let millis = remaining.as_secs() as i64 * 1000 + (nanos + 999_999) / 1_000_000;
+                millis.max(1)
+            }
+            None => return max_time_ms,
+        };
+
+        Some(max_time_ms.map_or(remaining, |max_time_ms| max_time_ms.min(remaining)))
+    }
+
+    /// Cancels the operations using this context or any of its clones, interrupting those
+    /// waiting for a reply.
+    pub fn cancel(&self) {
+        self.shared.cancelled.store(true, Ordering::SeqCst);
+
+        if let Ok(sockets) = self.shared.sockets.lock() {
+            for &(_, ref socket) in sockets.iter() {
+                let _ = socket.shutdown(Shutdown::Both);
+            }
+        }
+    }
+
+    /// Returns true if the context was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.shared.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fails with a `CancelledError` if the context was cancelled, or with a
+    /// `DeadlineExceededError` if its deadline has passed.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(CancelledError);
+        }
+
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(DeadlineExceededError),
+            _ => Ok(()),
+        }
+    }
+
+    /// Runs `round_trip`, which sends a request over `stream` and reads its reply, within the
+    /// context: it isn't run if the context has already ended, and the reply is only waited for
+    /// until the deadline or until the context is cancelled. If the reply isn't read because
+    /// the context ended, the stream is marked dirty so that it isn't reused.
+    pub fn round_trip<T, F>(&self, stream: &mut PooledStream, round_trip: F) -> Result<T>
+    where
+        F: FnOnce(&mut PooledStream) -> Result<T>,
+    {
+        // The socket is watched before the flag is checked, so that a cancellation from another
+        // thread either is seen here or shuts the socket down.
+        let id = self.watch(stream)?;
+        if let Err(err) = self.check() {
+            self.unwatch(id);
+            return Err(err);
+        }
+
+        let result = match stream.get_socket().get_ref().set_read_timeout(self.remaining()) {
+            Ok(()) => round_trip(stream),
+            Err(err) => Err(Error::from(err)),
+        };
+        self.unwatch(id);
+
+        if stream.get_socket().get_ref().set_read_timeout(None).is_err() {
+            stream.set_dirty(true);
+        }
+
+        match result {
+            Ok(value) => Ok(value),
+            Err(err) => {
+                match self.check() {
+                    Ok(()) => Err(err),
+                    Err(ctx_err) => {
+                        // Unless the server replied with an error, its reply may still arrive.
+                        match err {
+                            Error::CommandError(_) => (),
+                            _ => stream.set_dirty(true),
+                        }
+                        Err(ctx_err)
+                    }
+                }
+            }
+        }
+    }
+
+    // Registers the socket of a round trip in progress, to be shut down if the context is
+    // cancelled, and returns the id to unregister it with.
+    fn watch(&self, stream: &mut PooledStream) -> Result<usize> {
+        let socket = stream.get_socket().get_ref().try_clone_socket()?;
+        let id = self.shared.next_id.fetch_add(1, Ordering::SeqCst);
+        self.shared.sockets.lock()?.push((id, socket));
+        Ok(id)
+    }
+
+    fn unwatch(&self, id: usize) {
+        if let Ok(mut sockets) = self.shared.sockets.lock() {
+            sockets.retain(|&(watched, _)| watched != id);
+        }
+    }
+}
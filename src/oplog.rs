@@ -0,0 +1,178 @@
+//! Helpers for following the replica set oplog on servers without change streams.
+use bson::{self, Bson, bson, doc};
+
+use {Client, Result, ThreadedClient};
+use Error::{OplogRolloverError, ResponseError};
+use coll::Collection;
+use coll::options::{CursorType, FindOptions};
+use cursor::Cursor;
+use db::ThreadedDatabase;
+
+/// A single operation recorded in the oplog.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OplogEntry {
+    /// The timestamp of the operation, encoded as a BSON timestamp.
+    pub ts: i64,
+    /// The type of operation: `i`, `u`, `d`, `c`, or `n`.
+    pub op: String,
+    /// The namespace the operation applied to.
+    pub ns: String,
+    /// The operation document.
+    pub o: bson::Document,
+    /// The query selecting the updated document, for update operations.
+    pub o2: Option<bson::Document>,
+}
+
+impl OplogEntry {
+    /// Parses an oplog entry from a raw document.
+    pub fn from_document(doc: bson::Document) -> Result<OplogEntry> {
+        let ts = match doc.get("ts") {
+            Some(&Bson::TimeStamp(ts)) => ts,
+            _ => return Err(ResponseError(String::from("Oplog entry is missing 'ts'."))),
+        };
+
+        let op = match doc.get("op") {
+            Some(&Bson::String(ref op)) => op.to_owned(),
+            _ => return Err(ResponseError(String::from("Oplog entry is missing 'op'."))),
+        };
+
+        let ns = match doc.get("ns") {
+            Some(&Bson::String(ref ns)) => ns.to_owned(),
+            _ => return Err(ResponseError(String::from("Oplog entry is missing 'ns'."))),
+        };
+
+        let o = match doc.get("o") {
+            Some(&Bson::Document(ref o)) => o.clone(),
+            _ => return Err(ResponseError(String::from("Oplog entry is missing 'o'."))),
+        };
+
+        let o2 = match doc.get("o2") {
+            Some(&Bson::Document(ref o2)) => Some(o2.clone()),
+            _ => None,
+        };
+
+        Ok(OplogEntry {
+            ts: ts,
+            op: op,
+            ns: ns,
+            o: o,
+            o2: o2,
+        })
+    }
+}
+
+/// Follows `local.oplog.rs` with a tailable, awaiting cursor, yielding every entry after a
+/// starting timestamp.
+///
+/// If the cursor dies, e.g. because it timed out or the server stepped down, it is reopened
+/// from the timestamp of the last entry returned. If the oplog has rolled over past that
+/// timestamp, an `OplogRolloverError` is returned and the consumer must perform a full resync.
+#[derive(Debug)]
+pub struct OplogTailer {
+    client: Client,
+    last_ts: i64,
+    cursor: Option<Cursor>,
+}
+
+impl OplogTailer {
+    /// Creates a tailer returning every oplog entry after `start_ts`. A `start_ts` of 0 starts
+    /// from the beginning of the oplog.
+    pub fn new(client: Client, start_ts: i64) -> Result<OplogTailer> {
+        let mut tailer = OplogTailer {
+            client: client,
+            last_ts: start_ts,
+            cursor: None,
+        };
+
+        tailer.reopen()?;
+        Ok(tailer)
+    }
+
+    /// Returns the timestamp of the last entry returned, or the starting timestamp if no entries
+    /// have been returned yet.
+    pub fn last_ts(&self) -> i64 {
+        self.last_ts
+    }
+
+    fn oplog(&self) -> Collection {
+        self.client.db("local").collection("oplog.rs")
+    }
+
+    fn reopen(&mut self) -> Result<()> {
+        self.cursor = None;
+
+        let oplog = self.oplog();
+
+        // Make sure that no entries were lost between the requested timestamp and the oldest
+        // entry still in the oplog.
+        if self.last_ts != 0 {
+            let mut options = FindOptions::new();
+            options.sort = Some(doc! { "$natural": 1 });
+
+            if let Some(oldest) = oplog.find_one(None, Some(options))? {
+                match oldest.get("ts") {
+                    Some(&Bson::TimeStamp(ts)) if ts > self.last_ts => {
+                        return Err(OplogRolloverError(self.last_ts));
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        let filter = doc! { "ts": { "$gt": Bson::TimeStamp(self.last_ts) } };
+
+        let mut options = FindOptions::new();
+        options.cursor_type = CursorType::TailableAwait;
+        options.oplog_replay = true;
+        options.no_cursor_timeout = true;
+
+        self.cursor = Some(oplog.find(Some(filter), Some(options))?);
+        Ok(())
+    }
+}
+
+impl Iterator for OplogTailer {
+    type Item = Result<OplogEntry>;
+
+    /// Blocks until the next oplog entry is available, reopening the cursor once if it dies.
+    fn next(&mut self) -> Option<Result<OplogEntry>> {
+        let mut reopened = false;
+
+        loop {
+            if self.cursor.is_none() {
+                if let Err(err) = self.reopen() {
+                    return Some(Err(err));
+                }
+                reopened = true;
+            }
+
+            let result = match self.cursor {
+                Some(ref mut cursor) => cursor.next(),
+                None => continue,
+            };
+
+            match result {
+                Some(Ok(doc)) => {
+                    let entry = OplogEntry::from_document(doc);
+                    if let Ok(ref entry) = entry {
+                        self.last_ts = entry.ts;
+                    }
+                    return Some(entry);
+                }
+                Some(Err(err)) => {
+                    self.cursor = None;
+
+                    if reopened || !err.is_resumable_change_stream_error() {
+                        return Some(Err(err));
+                    }
+                }
+                None => {
+                    let alive = self.cursor.as_ref().map_or(false, Cursor::is_alive);
+                    if !alive {
+                        self.cursor = None;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,6 +1,8 @@
 //! Connection pooling for a single MongoDB server.
 use std::collections::VecDeque;
 use std::fmt;
+use std::fs;
+use std::io;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Condvar, Mutex};
 use std::time::{Duration, Instant};
@@ -15,13 +17,37 @@ use connstring::Host;
 use cursor::Cursor;
 use error::Error::{self, ArgumentError, OperationError};
 use error::Result;
+use limiter::OperationPermit;
+use logging::LogLevel;
 use stream::{Stream, StreamConnector};
+use topology::capabilities::ServerCapabilities;
+use topology::monitor::IsMasterResult;
+use topology::server::ServerType;
 use wire_protocol::flags::OpQueryFlags;
 use Client;
 
 pub static DEFAULT_POOL_SIZE: usize = 5;
 pub static DEFAULT_TIMEOUT_ON_IDLE: Duration = Duration::from_secs(30);
 
+/// A snapshot of the sockets open to a single server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// The server the sockets are connected to.
+    pub host: Host,
+    /// The number of sockets open to the server, including the monitor's dedicated socket.
+    pub open: usize,
+    /// The number of pooled sockets currently checked out by operations, not counting one
+    /// borrowed by the monitor.
+    pub in_use: usize,
+    /// The number of dedicated monitoring sockets. The monitor only keeps one open while no
+    /// pooled socket is idle, and otherwise borrows one from the pool.
+    pub monitor_sockets: usize,
+    /// The number of times a socket was checked out of the pool by an operation.
+    pub checkouts: u64,
+    /// The total time operations spent waiting for a socket to be returned to a full pool.
+    pub checkout_wait: Duration,
+}
+
 /// Handles threaded connections to a MongoDB server.
 #[derive(Clone)]
 pub struct ConnectionPool {
@@ -49,11 +75,28 @@ struct Pool {
     pub size: usize,
     // The current number of open connections.
     pub len: Arc<AtomicUsize>,
-    // The idle socket pool.
-    sockets: VecDeque<(BufStream<Stream>, Instant)>,
+    // The idle socket pool, with when each socket was returned and the capabilities reported
+    // by its handshake.
+    sockets: VecDeque<(BufStream<Stream>, Instant, ServerCapabilities)>,
     // The pool iteration. When a server monitor fails to execute ismaster,
     // the connection pool is cleared and the iteration is incremented.
     iteration: usize,
+    // The number of sockets checked out by operations.
+    checkouts: u64,
+    // The number of idle sockets currently borrowed by the server monitor, which aren't in use
+    // by operations.
+    monitor_checkouts: usize,
+    // The total time spent waiting for a socket to be returned.
+    checkout_wait: Duration,
+}
+
+impl Pool {
+    // Returns the number of sockets checked out by operations.
+    fn in_use(&self) -> usize {
+        self.len
+            .load(Ordering::SeqCst)
+            .saturating_sub(self.sockets.len() + self.monitor_checkouts)
+    }
 }
 
 /// Holds an available socket, with logic to return the socket
@@ -70,6 +113,31 @@ pub struct PooledStream {
     iteration: usize,
     // Whether the handshake occurred successfully.
     successful_handshake: bool,
+    // Whether the server may still send unsolicited replies over the socket, such as during
+    // an exhaust query.
+    dirty: bool,
+    // The server the socket is connected to.
+    host: Host,
+    // The role of the server when it was selected, or Unknown if it wasn't selected from the
+    // topology.
+    server_type: ServerType,
+    // The capabilities the server reported in the socket's handshake.
+    capabilities: ServerCapabilities,
+    // The permit of the operation using the socket, released once the socket is returned.
+    permit: Option<OperationPermit>,
+    // Whether a read timeout was set on the socket, which is cleared before it is returned.
+    read_timeout_set: bool,
+    // Whether the socket was borrowed by the server monitor rather than an operation.
+    monitor: bool,
+}
+
+impl fmt::Debug for PooledStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PooledStream")
+            .field("iteration", &self.iteration)
+            .field("dirty", &self.dirty)
+            .finish()
+    }
 }
 
 impl PooledStream {
@@ -77,6 +145,50 @@ impl PooledStream {
     pub fn get_socket(&mut self) -> &mut BufStream<Stream> {
         self.socket.as_mut().unwrap()
     }
+
+    /// Marks whether the socket is in an indeterminate state. Dirty sockets are closed instead
+    /// of being returned to the pool when the stream is dropped.
+    pub fn set_dirty(&mut self, dirty: bool) {
+        self.dirty = dirty;
+    }
+
+    /// Returns whether the socket is in an indeterminate state.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Returns the server the socket is connected to.
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+
+    /// Returns the role the server had when it was selected for this stream.
+    pub fn server_type(&self) -> ServerType {
+        self.server_type
+    }
+
+    /// Records the role the server had when it was selected for this stream.
+    pub fn set_server_type(&mut self, server_type: ServerType) {
+        self.server_type = server_type;
+    }
+
+    /// Returns the capabilities the server reported when the socket was opened.
+    pub fn capabilities(&self) -> &ServerCapabilities {
+        &self.capabilities
+    }
+
+    /// Holds the permit of the operation using the socket until the stream is dropped.
+    pub fn hold_permit(&mut self, permit: OperationPermit) {
+        self.permit = Some(permit);
+    }
+
+    /// Fails reads from the socket that wait longer than `timeout` for as long as the stream is
+    /// checked out. The socket goes back to the pool without the timeout.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.get_socket().get_ref().set_read_timeout(timeout)?;
+        self.read_timeout_set = timeout.is_some();
+        Ok(())
+    }
 }
 
 impl Drop for PooledStream {
@@ -86,13 +198,42 @@ impl Drop for PooledStream {
             return;
         }
 
+        // A socket whose read timeout can't be cleared would time out the next operation using
+        // it, so it is closed instead.
+        if self.read_timeout_set {
+            let cleared = self.socket.as_ref().map_or(false, |socket| {
+                socket.get_ref().set_read_timeout(None).is_ok()
+            });
+            if !cleared {
+                self.dirty = true;
+            }
+        }
+
+        // Close dirty sockets rather than attempting to resynchronize them, freeing up
+        // their slot in the pool for a new connection.
+        if self.dirty {
+            if let Ok(mut locked) = self.pool.lock() {
+                if self.iteration == locked.iteration {
+                    if self.monitor {
+                        locked.monitor_checkouts -= 1;
+                    }
+                    let _ = locked.len.fetch_sub(1, Ordering::SeqCst);
+                    self.wait_lock.notify_one();
+                }
+            }
+            return;
+        }
+
         // Attempt to lock and return the socket to the pool,
         // or give up if the pool lock has been poisoned.
         if let Ok(mut locked) = self.pool.lock() {
             if self.iteration == locked.iteration {
+                if self.monitor {
+                    locked.monitor_checkouts -= 1;
+                }
                 locked
                     .sockets
-                    .push_back((self.socket.take().unwrap(), Instant::now()));
+                    .push_back((self.socket.take().unwrap(), Instant::now(), self.capabilities));
                 // Notify waiting threads that the pool has been repopulated.
                 self.wait_lock.notify_one();
             }
@@ -126,6 +267,9 @@ impl ConnectionPool {
                 size,
                 sockets: VecDeque::with_capacity(size),
                 iteration: 0,
+                checkouts: 0,
+                monitor_checkouts: 0,
+                checkout_wait: Duration::from_secs(0),
             })),
             stream_connector: connector,
             idle_connection_timeout,
@@ -145,11 +289,44 @@ impl ConnectionPool {
         }
     }
 
+    /// Returns the number of connections currently checked out of the pool by operations.
+    /// Sockets borrowed by the server monitor aren't counted.
+    pub fn in_use(&self) -> usize {
+        match self.inner.lock() {
+            Ok(locked) => locked.in_use(),
+            Err(_) => 0,
+        }
+    }
+
+    /// Returns the number of sockets currently open to the server.
+    pub fn open_connections(&self) -> usize {
+        match self.inner.lock() {
+            Ok(locked) => locked.len.load(Ordering::SeqCst),
+            Err(_) => 0,
+        }
+    }
+
+    /// Returns a snapshot of the pool's socket usage.
+    pub fn stats(&self) -> Result<ConnectionStats> {
+        let locked = self.inner.lock()?;
+        let open = locked.len.load(Ordering::SeqCst);
+
+        Ok(ConnectionStats {
+            host: self.host.clone(),
+            open: open,
+            in_use: locked.in_use(),
+            monitor_sockets: 0,
+            checkouts: locked.checkouts,
+            checkout_wait: locked.checkout_wait,
+        })
+    }
+
     // Clear all open socket connections.
     pub fn clear(&self) {
         if let Ok(mut locked) = self.inner.lock() {
             locked.iteration += 1;
             locked.sockets.clear();
+            locked.monitor_checkouts = 0;
             locked.len.store(0, Ordering::SeqCst);
         }
     }
@@ -181,65 +358,265 @@ impl ConnectionPool {
     /// the pool has not reached its maximum size, a new socket will connect.
     /// Otherwise, the function will block until a socket is returned to the pool.
     pub fn acquire_stream(&self, client: Client) -> Result<PooledStream> {
-        let mut locked = self.inner.lock()?;
-        if locked.size == 0 {
-            return Err(OperationError(String::from(
-                "The connection pool does not allow connections; increase the size of the pool.",
-            )));
-        }
+        let mut liveness_err = None;
 
         loop {
-            // Acquire available existing socket
-            if let Some((stream, _)) = locked.sockets.pop_back() {
-                return Ok(PooledStream {
-                    socket: Some(stream),
-                    pool: self.inner.clone(),
-                    wait_lock: self.wait_lock.clone(),
-                    iteration: locked.iteration,
-                    successful_handshake: true,
-                });
+            let (stream, idle) = {
+                let mut locked = self.inner.lock()?;
+                if locked.size == 0 {
+                    return Err(OperationError(String::from(
+                        "The connection pool does not allow connections; increase the size of \
+                         the pool.",
+                    )));
+                }
+
+                loop {
+                    match self.acquire_available_stream(&mut locked, client.clone()) {
+                        Ok(Some(found)) => break found,
+                        Ok(None) => (),
+                        Err(err) => return Err(liveness_err.unwrap_or(err)),
+                    }
+
+                    // Release lock and wait for pool to be repopulated
+                    let start = Instant::now();
+                    locked = self.wait_lock.wait(locked)?;
+                    locked.checkout_wait += start.elapsed();
+                }
+            };
+
+            match self.check_liveness(&client, stream, idle) {
+                Ok(stream) => return Ok(stream),
+                Err(err) => liveness_err = Some(err),
             }
+        }
+    }
 
-            // Attempt to make a new connection
-            let len = locked.len.load(Ordering::SeqCst);
-            if len < locked.size {
-                let socket = self.connect()?;
-                let mut stream = PooledStream {
-                    socket: Some(socket),
-                    pool: self.inner.clone(),
-                    wait_lock: self.wait_lock.clone(),
-                    iteration: locked.iteration,
-                    successful_handshake: false,
-                };
-
-                self.handshake(client.clone(), &mut stream)?;
-
-                // authentication
-                if let (Some(user), Some(password)) = (
-                    client.topology.config.user.clone(),
-                    client.topology.config.password.clone(),
-                ) {
-                    let _ = Authenticator::new(&mut stream, client).auth(&user, &password);
+    /// Attempts to acquire a connected socket without blocking. Returns `None` if no socket
+    /// is available and the pool has reached its maximum size.
+    pub fn try_acquire_stream(&self, client: Client) -> Result<Option<PooledStream>> {
+        let mut liveness_err = None;
+
+        loop {
+            let found = {
+                let mut locked = self.inner.lock()?;
+                match self.acquire_available_stream(&mut locked, client.clone()) {
+                    Ok(found) => found,
+                    Err(err) => return Err(liveness_err.unwrap_or(err)),
                 }
+            };
+
+            let (stream, idle) = match found {
+                Some(found) => found,
+                None => return Ok(None),
+            };
+
+            match self.check_liveness(&client, stream, idle) {
+                Ok(stream) => return Ok(Some(stream)),
+                Err(err) => liveness_err = Some(err),
+            }
+        }
+    }
+
+    /// Takes an idle socket from the pool without connecting a new one or blocking. Used by
+    /// the server monitor, so it isn't counted as a checkout.
+    pub fn try_acquire_idle_stream(
+        &self,
+        max_idle_time: Option<Duration>,
+    ) -> Result<Option<PooledStream>> {
+        let mut locked = self.inner.lock()?;
+
+        // Sockets idle for too long are left for the liveness check of the next checkout.
+        if let (Some(max_idle_time), Some(idle)) = (max_idle_time, idle_time(&locked)) {
+            if idle > max_idle_time {
+                return Ok(None);
+            }
+        }
+
+        let mut stream = self.take_idle_stream(&mut locked);
+        if let Some(ref mut stream) = stream {
+            stream.monitor = true;
+            locked.monitor_checkouts += 1;
+        }
+
+        Ok(stream)
+    }
+
+    // Pings a socket that has been idle for `idle`, longer than `max_idle_time`, before reusing
+    // it, since a firewall or NAT gateway may have dropped it without either end noticing,
+    // leaving the operation to hang. The pool isn't locked meanwhile. A socket that doesn't
+    // answer within the client's liveness check timeout is closed and the check's error is
+    // returned, so that the caller can take another socket.
+    fn check_liveness(
+        &self,
+        client: &Client,
+        mut stream: PooledStream,
+        idle: Option<Duration>,
+    ) -> Result<PooledStream> {
+        let idle = match idle {
+            Some(idle) => idle,
+            None => return Ok(stream),
+        };
+
+        let timeout = client.liveness_check_timeout;
+        let err = match ping_with_timeout(client, &mut stream, timeout) {
+            Ok(()) => return Ok(stream),
+            Err(err) => err,
+        };
+
+        // Dropping the dirty socket closes it and frees its slot for a new connection.
+        stream.dirty = true;
+        drop(stream);
 
-                let _ = locked.len.fetch_add(1, Ordering::SeqCst);
-                return Ok(stream);
+        client.log(LogLevel::Warn, "connection", || {
+            format!(
+                "Closed connection to {}:{} idle for {:?}, which failed its liveness check: {} \
+                 ({} open); trying another connection.",
+                self.host.host_name,
+                self.host.port,
+                idle,
+                err,
+                self.open_connections()
+            )
+        });
+
+        match err {
+            Error::IoError(ref io_err) if io_err.kind() == io::ErrorKind::WouldBlock ||
+                                          io_err.kind() == io::ErrorKind::TimedOut => {
+                Err(Error::IoError(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "{}:{} did not answer a ping within {:?} after being idle for {:?}.",
+                        self.host.host_name,
+                        self.host.port,
+                        timeout,
+                        idle
+                    ),
+                )))
             }
+            err => Err(err),
+        }
+    }
+
+    // Takes the most recently returned idle socket, if any.
+    fn take_idle_stream(&self, pool: &mut Pool) -> Option<PooledStream> {
+        let (stream, _, capabilities) = pool.sockets.pop_back()?;
+
+        Some(PooledStream {
+            socket: Some(stream),
+            pool: self.inner.clone(),
+            wait_lock: self.wait_lock.clone(),
+            iteration: pool.iteration,
+            successful_handshake: true,
+            dirty: false,
+            host: self.host.clone(),
+            server_type: ServerType::Unknown,
+            capabilities: capabilities,
+            permit: None,
+            read_timeout_set: false,
+            monitor: false,
+        })
+    }
+
+    // Takes an idle socket from the pool, or connects a new one if the pool has room for it.
+    // Idle sockets are returned with how long they were idle if that is longer than the
+    // client's `max_idle_time`, in which case they must pass `check_liveness` before use.
+    fn acquire_available_stream(
+        &self,
+        pool: &mut Pool,
+        client: Client,
+    ) -> Result<Option<(PooledStream, Option<Duration>)>> {
+        let idle = idle_time(pool);
+
+        // Acquire available existing socket
+        if let Some(stream) = self.take_idle_stream(pool) {
+            let stale = match (client.max_idle_time, idle) {
+                (Some(max_idle_time), Some(idle)) if idle > max_idle_time => Some(idle),
+                _ => None,
+            };
+
+            pool.checkouts += 1;
+            return Ok(Some((stream, stale)));
+        }
+
+        // Attempt to make a new connection
+        let len = pool.len.load(Ordering::SeqCst);
+        if len >= pool.size {
+            return Ok(None);
+        }
+
+        let socket = self.connect(client.keep_alive)?;
+        let mut stream = PooledStream {
+            socket: Some(socket),
+            pool: self.inner.clone(),
+            wait_lock: self.wait_lock.clone(),
+            iteration: pool.iteration,
+            successful_handshake: false,
+            dirty: false,
+            host: self.host.clone(),
+            server_type: ServerType::Unknown,
+            capabilities: ServerCapabilities::new(),
+            permit: None,
+            read_timeout_set: false,
+            monitor: false,
+        };
+
+        if let Err(err) = self.handshake(client.clone(), &mut stream) {
+            client.log(LogLevel::Warn, "connection", || {
+                format!(
+                    "Handshake with {}:{} failed: {}",
+                    self.host.host_name,
+                    self.host.port,
+                    err
+                )
+            });
+            return Err(err);
+        }
 
-            // Release lock and wait for pool to be repopulated
-            locked = self.wait_lock.wait(locked)?;
+        // authentication
+        if let (Some(user), Some(password)) = (
+            client.topology.config.user.clone(),
+            client.topology.config.password.clone(),
+        ) {
+            let result = Authenticator::new(&mut stream, client.clone()).auth(&user, &password);
+            if let Err(err) = result {
+                client.log(LogLevel::Warn, "connection", || {
+                    format!(
+                        "Authentication to {}:{} failed: {}",
+                        self.host.host_name,
+                        self.host.port,
+                        err
+                    )
+                });
+            }
         }
+
+        let open = pool.len.fetch_add(1, Ordering::SeqCst) + 1;
+        pool.checkouts += 1;
+
+        client.log(LogLevel::Debug, "connection", || {
+            format!(
+                "Opened connection to {}:{} ({} open).",
+                self.host.host_name,
+                self.host.port,
+                open
+            )
+        });
+
+        Ok(Some((stream, None)))
     }
 
     // Connects to a MongoDB server as defined by the initial configuration.
-    fn connect(&self) -> Result<BufStream<Stream>> {
-        match self
+    fn connect(&self, keep_alive: Option<Duration>) -> Result<BufStream<Stream>> {
+        let stream = self
             .stream_connector
             .connect(&self.host.host_name[..], self.host.port)
-        {
-            Ok(s) => Ok(BufStream::new(s)),
-            Err(e) => Err(Error::from(e)),
+            .map_err(Error::from)?;
+
+        if let Some(keep_alive) = keep_alive {
+            stream.set_keep_alive(keep_alive)?;
         }
+
+        Ok(BufStream::new(stream))
     }
 
     // This sends the client metadata to the server as described by the handshake spec.
@@ -252,23 +629,36 @@ impl ConnectionPool {
 
         let flags = OpQueryFlags::with_find_options(&options);
 
-        Cursor::query_with_stream(
+        let mut os = doc! {
+            "type": ::std::env::consts::OS,
+            "architecture": ::std::env::consts::ARCH,
+        };
+
+        if let Some(ref name) = client.os_name {
+            os.insert("name", name.to_owned());
+        }
+
+        let mut metadata = doc! {
+            "driver": {
+                "name": ::DRIVER_NAME,
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+            "os": os,
+            "platform": env!("MONGODB_RUSTC_VERSION"),
+        };
+
+        if let Some(ref name) = client.app_name {
+            metadata.insert("application", doc! { "name": name.to_owned() });
+        }
+
+        let mut cursor = Cursor::query_with_stream(
             stream,
             client,
             String::from("local.$cmd"),
             flags,
             doc! {
                 "isMaster": 1i32,
-                "client": {
-                    "driver": {
-                        "name": ::DRIVER_NAME,
-                        "version": env!("CARGO_PKG_VERSION"),
-                    },
-                    "os": {
-                        "type": ::std::env::consts::OS,
-                        "architecture": ::std::env::consts::ARCH
-                    }
-                },
+                "client": metadata,
             },
             options,
             CommandType::IsMaster,
@@ -276,8 +666,40 @@ impl ConnectionPool {
             None,
         )?;
 
+        if let Some(Ok(reply)) = cursor.next() {
+            if let Ok(ismaster) = IsMasterResult::new(reply) {
+                stream.capabilities = ServerCapabilities::from_is_master(&ismaster);
+            }
+        }
+
         stream.successful_handshake = true;
 
         Ok(())
     }
 }
+
+// Returns how long the socket that `take_idle_stream` would take has been idle.
+fn idle_time(pool: &Pool) -> Option<Duration> {
+    pool.sockets.back().map(|&(_, returned, _)| returned.elapsed())
+}
+
+// Runs a ping over the socket, failing if no reply arrives within `timeout`.
+fn ping_with_timeout(client: &Client, stream: &mut PooledStream, timeout: Duration) -> Result<()> {
+    stream.set_read_timeout(Some(timeout))?;
+    ::run_command_with_stream(client, stream, "admin", doc! { "ping": 1 }, CommandType::Ping)?;
+    stream.set_read_timeout(None)
+}
+
+/// Returns a human-readable name of the operating system, if one can be determined.
+pub fn os_name() -> Option<String> {
+    if cfg!(target_os = "linux") {
+        let release = fs::read_to_string("/etc/os-release").ok()?;
+
+        release
+            .lines()
+            .find(|line| line.starts_with("PRETTY_NAME="))
+            .map(|line| line["PRETTY_NAME=".len()..].trim_matches('"').to_owned())
+    } else {
+        None
+    }
+}
@@ -37,7 +37,7 @@ impl r2d2::ManageConnection for MongoConnectionManager {
         // Try to acquire a stream to establish a connection. If we can't, the connection can't be used.
         client.acquire_stream(client.read_preference.clone())?;
 
-        Ok(client.db(&self.db_name))
+        client.get_db(&self.db_name)
     }
 
     fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Self::Error> {
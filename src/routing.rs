@@ -0,0 +1,249 @@
+//! Per-collection limits, so that a busy or slow collection can't starve the operations on the
+//! others of permits and connections.
+//!
+//! A `RoutingProfile` attached to a collection's namespace with
+//! `ThreadedClient::set_routing_profile` gives the operations on it their own limit of operations
+//! running at once, in place of the client's `max_concurrent_operations`, their own socket
+//! timeout, and optionally connections of their own to each server, apart from the client's
+//! pools. Operations are matched to a collection by the namespace they act on: queries and legacy
+//! writes by their namespace, commands such as `count` or `insert` by the collection named in
+//! their first field, and getMores by the namespace of their cursor. Database commands and hedged
+//! reads use the client's limits.
+//!
+//! Servers are still selected from the client's pools, so a routed operation briefly checks
+//! out a connection of the client's pool to the selected server before swapping it for a
+//! dedicated one.
+use bson::{self, Bson};
+use connstring::Host;
+use limiter::OperationLimiter;
+use pool::{ConnectionPool, PooledStream};
+use stream::StreamConnector;
+use Error::ArgumentError;
+use {Client, Result};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// Overrides of the client's limits for the operations on one collection.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RoutingProfile {
+    /// The most operations on the collection that run at once. They are counted apart from the
+    /// client's `max_concurrent_operations`, which they no longer count against. The client's
+    /// limit applies if unset.
+    pub max_concurrent_operations: Option<usize>,
+    /// How long an operation waits for one of `max_concurrent_operations` to finish before
+    /// failing with an `OverloadedError`; zero fails it at once. Waits indefinitely if unset.
+    pub wait_queue_timeout: Option<Duration>,
+    /// How long each read of a reply may wait for data before the operation fails with an
+    /// `IoError`. Waits indefinitely if unset.
+    pub socket_timeout: Option<Duration>,
+    /// The number of connections to each server reserved for the collection. Its operations
+    /// only use these, and wait for one to be returned while all are in use. The client's
+    /// pools are used if unset.
+    pub dedicated_connections: Option<usize>,
+}
+
+impl RoutingProfile {
+    pub fn new() -> RoutingProfile {
+        Default::default()
+    }
+}
+
+/// The limiter and dedicated connections of a profile attached to a namespace.
+#[derive(Debug)]
+pub struct NamespaceRoute {
+    profile: RoutingProfile,
+    limiter: Option<Arc<OperationLimiter>>,
+    connector: StreamConnector,
+    // The dedicated pools by server, opened when first used.
+    pools: Mutex<HashMap<Host, ConnectionPool>>,
+}
+
+impl NamespaceRoute {
+    fn new(profile: RoutingProfile, connector: StreamConnector) -> NamespaceRoute {
+        let limiter = profile.max_concurrent_operations.map(|max| {
+            Arc::new(OperationLimiter::new(Some(max), profile.wait_queue_timeout, None))
+        });
+
+        NamespaceRoute {
+            profile: profile,
+            limiter: limiter,
+            connector: connector,
+            pools: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the profile the route was created from.
+    pub fn profile(&self) -> &RoutingProfile {
+        &self.profile
+    }
+
+    /// Returns the limiter of the namespace's operations, if the profile limits them.
+    pub fn limiter(&self) -> Option<&Arc<OperationLimiter>> {
+        self.limiter.as_ref()
+    }
+
+    /// Swaps `stream`, checked out of the client's pool to the selected server, for a dedicated
+    /// connection to the same server if the profile reserves any, waiting for one to be
+    /// returned while all are in use, and applies the profile's socket timeout.
+    pub fn route_stream(&self, client: &Client, stream: PooledStream) -> Result<PooledStream> {
+        let mut stream = match self.profile.dedicated_connections {
+            Some(size) => {
+                let host = stream.host().clone();
+                let server_type = stream.server_type();
+                drop(stream);
+
+                let pool = {
+                    let mut pools = self.pools.lock()?;
+                    pools
+                        .entry(host.clone())
+                        .or_insert_with(|| {
+                            ConnectionPool::with_size(host, self.connector.clone(), size)
+                        })
+                        .clone()
+                };
+
+                let mut dedicated = pool.acquire_stream(client.clone())?;
+                dedicated.set_server_type(server_type);
+                dedicated
+            }
+            None => stream,
+        };
+
+        if self.profile.socket_timeout.is_some() {
+            stream.set_read_timeout(self.profile.socket_timeout)?;
+        }
+
+        Ok(stream)
+    }
+}
+
+/// The routing profiles attached to namespaces, shared by a client and the handles created from
+/// it.
+#[derive(Debug)]
+pub struct RoutingTable {
+    connector: StreamConnector,
+    routes: RwLock<HashMap<String, Arc<NamespaceRoute>>>,
+}
+
+impl RoutingTable {
+    /// Creates a table without profiles, whose dedicated connections are opened with
+    /// `connector`.
+    pub fn new(connector: StreamConnector) -> RoutingTable {
+        RoutingTable {
+            connector: connector,
+            routes: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Attaches `profile` to `namespace`, replacing its previous profile, or detaches it if
+    /// `None`. Operations already running keep the limits and connections they started with.
+    pub fn set(&self, namespace: &str, profile: Option<RoutingProfile>) -> Result<()> {
+        let profile = match profile {
+            Some(profile) => profile,
+            None => {
+                self.routes.write()?.remove(namespace);
+                return Ok(());
+            }
+        };
+
+        if profile.max_concurrent_operations == Some(0) {
+            return Err(ArgumentError(
+                String::from("max_concurrent_operations must be at least 1."),
+            ));
+        }
+
+        if profile.dedicated_connections == Some(0) {
+            return Err(ArgumentError(String::from("dedicated_connections must be at least 1.")));
+        }
+
+        let route = NamespaceRoute::new(profile, self.connector.clone());
+        self.routes.write()?.insert(namespace.to_owned(), Arc::new(route));
+        Ok(())
+    }
+
+    /// Returns the profile attached to `namespace`, if any.
+    pub fn profile(&self, namespace: &str) -> Result<Option<RoutingProfile>> {
+        let routes = self.routes.read()?;
+        Ok(routes.get(namespace).map(|route| route.profile.clone()))
+    }
+
+    /// Returns the route of the operations on `namespace`, if a profile is attached to it.
+    pub fn route(&self, namespace: Option<&str>) -> Result<Option<Arc<NamespaceRoute>>> {
+        let namespace = match namespace {
+            Some(namespace) => namespace,
+            None => return Ok(None),
+        };
+
+        Ok(self.routes.read()?.get(namespace).cloned())
+    }
+
+    /// Returns true if no profile is attached to any namespace.
+    pub fn is_empty(&self) -> bool {
+        self.routes.read().map(|routes| routes.is_empty()).unwrap_or(true)
+    }
+}
+
+/// Returns the namespace a request sent to `namespace` acts on: the collection named by the
+/// first field of a command, or the namespace itself for a query. Commands that don't name a
+/// collection act on none.
+pub fn operation_namespace(namespace: &str, query: &bson::Document) -> Option<String> {
+    if !namespace.ends_with(".$cmd") {
+        return Some(namespace.to_owned());
+    }
+
+    let db_name = &namespace[..namespace.len() - ".$cmd".len()];
+    let command = match query.get("$query") {
+        Some(&Bson::Document(ref command)) => command,
+        _ => query,
+    };
+
+    match command.iter().next() {
+        Some((_, &Bson::String(ref coll))) => Some(format!("{}.{}", db_name, coll)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{bson, doc};
+    use stream::StreamConnector;
+    use super::{operation_namespace, RoutingProfile, RoutingTable};
+
+    #[test]
+    fn commands_are_routed_by_collection() {
+        let count = doc! { "count": "hot", "query": {} };
+        assert_eq!(operation_namespace("test.$cmd", &count), Some(String::from("test.hot")));
+
+        let wrapped = doc! { "$query": { "aggregate": "hot" }, "$readPreference": {} };
+        assert_eq!(operation_namespace("test.$cmd", &wrapped), Some(String::from("test.hot")));
+
+        assert_eq!(operation_namespace("test.$cmd", &doc! { "ping": 1 }), None);
+        assert_eq!(operation_namespace("test.hot", &doc! {}), Some(String::from("test.hot")));
+    }
+
+    #[test]
+    fn profiles_are_attached_by_namespace() {
+        let table = RoutingTable::new(StreamConnector::Tcp);
+        assert!(table.is_empty());
+
+        let mut profile = RoutingProfile::new();
+        profile.max_concurrent_operations = Some(2);
+        table.set("test.hot", Some(profile.clone())).unwrap();
+
+        let route = table.route(Some("test.hot")).unwrap().unwrap();
+        assert_eq!(route.profile(), &profile);
+        assert!(route.limiter().is_some());
+        assert!(table.route(Some("test.cold")).unwrap().is_none());
+        assert!(table.route(None).unwrap().is_none());
+
+        profile.max_concurrent_operations = Some(0);
+        assert!(table.set("test.hot", Some(profile)).is_err());
+        assert!(table.profile("test.hot").unwrap().is_some());
+
+        table.set("test.hot", None).unwrap();
+        assert!(table.profile("test.hot").unwrap().is_none());
+        assert!(table.is_empty());
+    }
+}
@@ -0,0 +1,726 @@
+//! Logical sessions.
+use bson::{self, Bson, bson, doc};
+use bson::spec::BinarySubtype;
+use hex;
+use rand::{thread_rng, Rng};
+
+use {within_pin_window, Client, CommandType, Error, Result, ThreadedClient};
+use Error::{ArgumentError, OperationError, SessionEndedError};
+use error::{TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT};
+use common::{ReadConcern, WriteConcern};
+use db::ThreadedDatabase;
+
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The maximum number of sessions that can be ended with a single endSessions command.
+const MAX_END_SESSIONS_BATCH_SIZE: usize = 10000;
+
+// How long `with_transaction` keeps retrying a transaction before giving up.
+const WITH_TRANSACTION_TIMEOUT_SECS: u64 = 120;
+
+// How long to wait before retrying a commit whose outcome is unknown. The delay doubles with
+// each attempt made by `with_transaction`, up to `MAX_COMMIT_RETRY_BACKOFF_MS`.
+const COMMIT_RETRY_BACKOFF_MS: u64 = 50;
+const MAX_COMMIT_RETRY_BACKOFF_MS: u64 = 1000;
+
+/// Options for a logical session.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionOptions {
+    /// Whether reads run under the session should observe the session's earlier operations.
+    /// Defaults to true.
+    pub causal_consistency: bool,
+    /// For this many milliseconds after a write run under the session, reads run under the
+    /// session that may go to a secondary are sent to the primary instead. Unlike the client's
+    /// `primary_pin_window_ms`, this only affects the session. Defaults to 0, which disables
+    /// pinning.
+    pub primary_pin_window_ms: u64,
+}
+
+impl SessionOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        SessionOptions {
+            causal_consistency: true,
+            primary_pin_window_ms: 0,
+        }
+    }
+}
+
+/// Options for a multi-document transaction.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TransactionOptions {
+    /// The read concern to use for every read in the transaction.
+    pub read_concern: Option<ReadConcern>,
+    /// The write concern to use when committing or aborting the transaction.
+    pub write_concern: Option<WriteConcern>,
+}
+
+impl TransactionOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+/// The state of the current transaction on a session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TransactionState {
+    /// No transaction has been started.
+    None,
+    /// A transaction has been started, but no command has been run in it yet.
+    Starting,
+    /// At least one command has been run in the transaction.
+    InProgress,
+    /// The transaction was committed.
+    Committed,
+    /// The transaction was aborted.
+    Aborted,
+}
+
+/// A logical session allocated by the driver and tracked by the server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerSession {
+    /// The logical session id, of the form `{ id: <UUID> }`.
+    pub id: bson::Document,
+    /// The last time the session was used to run a command.
+    pub last_use: Instant,
+    /// The transaction number of the last retryable write run under the session.
+    pub txn_number: i64,
+}
+
+impl ServerSession {
+    /// Allocates a new server session with a random UUID.
+    pub fn new() -> ServerSession {
+        let mut bytes = [0u8; 16];
+        thread_rng().fill_bytes(&mut bytes);
+
+        // Mark the bytes as a version 4, variant 1 UUID.
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+        ServerSession {
+            id: doc! { "id": Bson::Binary(BinarySubtype::Uuid, bytes.to_vec()) },
+            last_use: Instant::now(),
+            txn_number: 0,
+        }
+    }
+
+    /// Returns true if at least half of the server's session timeout has passed since the
+    /// session was last used, in which case it should not be reused.
+    pub fn is_about_to_expire(&self, timeout_minutes: i64) -> bool {
+        let timeout = Duration::from_secs(timeout_minutes.max(0) as u64 * 60);
+        self.last_use.elapsed() >= timeout / 2
+    }
+}
+
+/// Builds the id document of a session from the 16 bytes of its UUID, as used by
+/// `Client::refresh_sessions` and `Client::kill_sessions`.
+pub fn id_from_bytes(uuid: &[u8]) -> Result<bson::Document> {
+    if uuid.len() != 16 {
+        return Err(ArgumentError(format!(
+            "Session UUIDs must be 16 bytes long, got {}.",
+            uuid.len()
+        )));
+    }
+
+    Ok(doc! { "id": Bson::Binary(BinarySubtype::Uuid, uuid.to_vec()) })
+}
+
+/// Builds the id document of a session from its UUID written as 32 hexadecimal digits,
+/// optionally separated by hyphens, e.g. `00112233-4455-6677-8899-aabbccddeeff`.
+pub fn id_from_hex(uuid: &str) -> Result<bson::Document> {
+    let digits: String = uuid.chars().filter(|&c| c != '-').collect();
+    let bytes = hex::decode(&digits)
+        .map_err(|_| ArgumentError(format!("Invalid session UUID: {}", uuid)))?;
+
+    id_from_bytes(&bytes)
+}
+
+/// Compares two BSON timestamps. Timestamps are ordered by their seconds and then by their
+/// increment, which matches the ordering of their unsigned 64-bit representation.
+pub fn compare_timestamps(a: i64, b: i64) -> Ordering {
+    (a as u64).cmp(&(b as u64))
+}
+
+// Extracts the timestamp from a `$clusterTime` document.
+fn cluster_timestamp(cluster_time: &bson::Document) -> Option<i64> {
+    match cluster_time.get("clusterTime") {
+        Some(&Bson::TimeStamp(ts)) => Some(ts),
+        _ => None,
+    }
+}
+
+/// Holds server sessions that have been released by their `ClientSession` so that they can be
+/// reused by later sessions.
+#[derive(Debug, Default)]
+pub struct SessionPool {
+    sessions: Mutex<VecDeque<ServerSession>>,
+    // The ids of sessions checked out by a `ClientSession`.
+    in_use: Mutex<Vec<bson::Document>>,
+    // The ids of checked out sessions that were killed on the server.
+    killed: Mutex<Vec<bson::Document>>,
+}
+
+impl SessionPool {
+    /// Creates an empty session pool.
+    pub fn new() -> SessionPool {
+        Default::default()
+    }
+
+    /// Returns the most recently used session that is not about to expire, or allocates a new
+    /// one. Expired sessions encountered along the way are discarded.
+    pub fn check_out(&self, timeout_minutes: i64) -> ServerSession {
+        let session = self.pop_session(timeout_minutes).unwrap_or_else(ServerSession::new);
+
+        if let Ok(mut in_use) = self.in_use.lock() {
+            in_use.push(session.id.clone());
+        }
+
+        session
+    }
+
+    fn pop_session(&self, timeout_minutes: i64) -> Option<ServerSession> {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            while let Some(session) = sessions.pop_front() {
+                if !session.is_about_to_expire(timeout_minutes) {
+                    return Some(session);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Returns a session to the pool, unless it is about to expire or was killed.
+    pub fn check_in(&self, session: ServerSession, timeout_minutes: i64) {
+        if let Ok(mut in_use) = self.in_use.lock() {
+            in_use.retain(|id| *id != session.id);
+
+            if let Ok(mut killed) = self.killed.lock() {
+                if let Some(index) = killed.iter().position(|id| *id == session.id) {
+                    killed.remove(index);
+                    return;
+                }
+            }
+        }
+
+        if let Ok(mut sessions) = self.sessions.lock() {
+            // Prune stale sessions from the back of the pool, where the least recently used
+            // sessions are kept.
+            while sessions.back().map_or(false, |s| s.is_about_to_expire(timeout_minutes)) {
+                sessions.pop_back();
+            }
+
+            if !session.is_about_to_expire(timeout_minutes) {
+                sessions.push_front(session);
+            }
+        }
+    }
+
+    /// Records that the sessions with the given ids were killed on the server. Pooled sessions
+    /// are discarded, and checked out sessions are marked as ended so that they are not pooled
+    /// again.
+    pub fn kill(&self, ids: &[bson::Document]) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.retain(|session| !ids.contains(&session.id));
+        }
+
+        if let Ok(in_use) = self.in_use.lock() {
+            if let Ok(mut killed) = self.killed.lock() {
+                killed.extend(ids.iter().filter(|id| in_use.contains(id)).cloned());
+            }
+        }
+    }
+
+    /// Returns true if the checked out session with the given id was killed on the server.
+    pub fn is_killed(&self, id: &bson::Document) -> bool {
+        self.killed.lock().map(|killed| killed.contains(id)).unwrap_or(false)
+    }
+
+    /// Removes and returns every session in the pool.
+    pub fn drain(&self) -> Vec<ServerSession> {
+        match self.sessions.lock() {
+            Ok(mut sessions) => sessions.drain(..).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// A session started by the application, under which commands can be run.
+///
+/// The underlying server session is returned to the client's session pool when the
+/// `ClientSession` is dropped.
+pub struct ClientSession {
+    client: Client,
+    server_session: Option<ServerSession>,
+    timeout_minutes: i64,
+    transaction_state: TransactionState,
+    transaction_options: TransactionOptions,
+    options: SessionOptions,
+    operation_time: Option<i64>,
+    cluster_time: Option<bson::Document>,
+    last_write: Option<Instant>,
+}
+
+impl fmt::Debug for ClientSession {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ClientSession")
+            .field("server_session", &self.server_session)
+            .field("timeout_minutes", &self.timeout_minutes)
+            .field("transaction_state", &self.transaction_state)
+            .field("transaction_options", &self.transaction_options)
+            .field("options", &self.options)
+            .field("operation_time", &self.operation_time)
+            .field("cluster_time", &self.cluster_time)
+            .field("last_write", &self.last_write)
+            .finish()
+    }
+}
+
+impl ClientSession {
+    /// Starts a session on the given client, reusing a pooled server session if possible.
+    pub fn new(client: Client, timeout_minutes: i64, options: SessionOptions) -> ClientSession {
+        let server_session = client.session_pool.check_out(timeout_minutes);
+
+        ClientSession {
+            client: client,
+            server_session: Some(server_session),
+            timeout_minutes: timeout_minutes,
+            transaction_state: TransactionState::None,
+            transaction_options: TransactionOptions::new(),
+            options: options,
+            operation_time: None,
+            cluster_time: None,
+            last_write: None,
+        }
+    }
+
+    /// Returns the logical session id.
+    pub fn id(&self) -> &bson::Document {
+        &self.server_session().id
+    }
+
+    /// Returns the client that started the session.
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Returns the options the session was started with.
+    pub fn options(&self) -> &SessionOptions {
+        &self.options
+    }
+
+    /// Returns the operation time of the latest operation run under the session.
+    pub fn operation_time(&self) -> Option<i64> {
+        self.operation_time
+    }
+
+    /// Returns the highest `$clusterTime` document seen by the session.
+    pub fn cluster_time(&self) -> Option<&bson::Document> {
+        self.cluster_time.as_ref()
+    }
+
+    /// Advances the session's operation time. Times older than the current one are ignored.
+    pub fn advance_operation_time(&mut self, operation_time: i64) {
+        let newer = self.operation_time.map_or(true, |current| {
+            compare_timestamps(operation_time, current) == Ordering::Greater
+        });
+
+        if newer {
+            self.operation_time = Some(operation_time);
+        }
+    }
+
+    /// Advances the session's cluster time. The document is kept as is, signature included, so
+    /// that it can be sent back to the server; documents whose timestamp is not newer than the
+    /// current one are ignored.
+    pub fn advance_cluster_time(&mut self, cluster_time: &bson::Document) {
+        let ts = match cluster_timestamp(cluster_time) {
+            Some(ts) => ts,
+            None => return,
+        };
+
+        let newer = match self.cluster_time.as_ref().and_then(cluster_timestamp) {
+            Some(current) => compare_timestamps(ts, current) == Ordering::Greater,
+            None => true,
+        };
+
+        if newer {
+            self.cluster_time = Some(cluster_time.clone());
+        }
+    }
+
+    /// Records the `operationTime` and `$clusterTime` of a command reply.
+    pub fn process_reply(&mut self, reply: &bson::Document) {
+        if let Some(&Bson::TimeStamp(ts)) = reply.get("operationTime") {
+            self.advance_operation_time(ts);
+        }
+
+        if let Some(&Bson::Document(ref cluster_time)) = reply.get("$clusterTime") {
+            self.advance_cluster_time(cluster_time);
+        }
+    }
+
+    /// Adds `afterClusterTime` to the read concern of a read command so that it observes
+    /// every earlier operation run under the session. This has no effect on sessions without
+    /// causal consistency, before the first operation, or inside a transaction after its first
+    /// command.
+    pub fn apply_read_concern(&self, command: &mut bson::Document) {
+        if !self.options.causal_consistency {
+            return;
+        }
+
+        if self.transaction_state == TransactionState::InProgress {
+            return;
+        }
+
+        let operation_time = match self.operation_time {
+            Some(operation_time) => operation_time,
+            None => return,
+        };
+
+        let mut read_concern = match command.remove("readConcern") {
+            Some(Bson::Document(read_concern)) => read_concern,
+            _ => bson::Document::new(),
+        };
+
+        read_concern.insert("afterClusterTime", Bson::TimeStamp(operation_time));
+        command.insert("readConcern", read_concern);
+    }
+
+    /// Records that a write was run under the session, which starts its primary pinning window.
+    pub fn record_write(&mut self) {
+        self.last_write = Some(Instant::now());
+    }
+
+    /// Returns true if reads run under the session should prefer the primary, because a write
+    /// was run under it within its primary pinning window.
+    pub fn pinned_to_primary(&self) -> bool {
+        within_pin_window(self.last_write, self.options.primary_pin_window_ms)
+    }
+
+    fn server_session(&self) -> &ServerSession {
+        // Only taken when the session is dropped.
+        self.server_session.as_ref().unwrap()
+    }
+
+    /// Returns the state of the session's current transaction.
+    pub fn transaction_state(&self) -> TransactionState {
+        self.transaction_state
+    }
+
+    /// Returns true if a transaction has been started and not yet committed or aborted.
+    pub fn in_transaction(&self) -> bool {
+        self.transaction_state == TransactionState::Starting ||
+            self.transaction_state == TransactionState::InProgress
+    }
+
+    /// Attaches the session id to a command document and marks the session as used. If a
+    /// transaction is in progress, the transaction fields are attached as well.
+    ///
+    /// Returns a `SessionEndedError` if the session was killed with `Client::kill_sessions`.
+    pub fn apply(&mut self, command: &mut bson::Document) -> Result<()> {
+        if self.client.session_pool.is_killed(self.id()) {
+            return Err(SessionEndedError);
+        }
+
+        if command.contains_key("lsid") {
+            return Err(ArgumentError(String::from(
+                "Command already specifies a logical session id.",
+            )));
+        }
+
+        {
+            let session = self.server_session.as_mut().unwrap();
+            session.last_use = Instant::now();
+            command.insert("lsid", session.id.clone());
+        }
+
+        // Gossip the highest cluster time seen, so that the server never sees time go
+        // backwards.
+        if let Some(ref cluster_time) = self.cluster_time {
+            command.insert("$clusterTime", cluster_time.clone());
+        }
+
+        if !self.in_transaction() {
+            return Ok(());
+        }
+
+        // Write concerns may only be given when committing or aborting a transaction.
+        command.remove("writeConcern");
+        command.insert("txnNumber", self.server_session().txn_number);
+        command.insert("autocommit", false);
+
+        if self.transaction_state == TransactionState::Starting {
+            command.insert("startTransaction", true);
+
+            if let Some(read_concern) = self.transaction_options.read_concern {
+                command.insert("readConcern", read_concern.to_document());
+            }
+
+            // The first command of a transaction establishes its snapshot, so it must observe
+            // every earlier operation run under the session.
+            self.apply_read_concern(command);
+            self.transaction_state = TransactionState::InProgress;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the fields to send with the getMores of a cursor opened under the session: its
+    /// id and, while a transaction is in progress, the transaction number and `autocommit`,
+    /// without which the server rejects getMores on cursors opened in the transaction.
+    pub fn get_more_fields(&self) -> bson::Document {
+        let mut fields = doc! { "lsid": self.id().clone() };
+
+        if self.in_transaction() {
+            fields.insert("txnNumber", self.server_session().txn_number);
+            fields.insert("autocommit", false);
+        }
+
+        fields
+    }
+
+    /// Increments and returns the session's transaction number.
+    pub fn next_txn_number(&mut self) -> i64 {
+        let session = self.server_session.as_mut().unwrap();
+        session.txn_number += 1;
+        session.txn_number
+    }
+
+    /// Starts a multi-document transaction. Every command run under the session until the
+    /// transaction is committed or aborted is part of the transaction.
+    ///
+    /// Only commands are sent with the session id; legacy queries such as `Collection::find`
+    /// do not participate in the transaction.
+    pub fn start_transaction(&mut self, options: Option<TransactionOptions>) -> Result<()> {
+        if self.in_transaction() {
+            return Err(OperationError(String::from("Transaction already in progress.")));
+        }
+
+        if !self.client.topology.supports_transactions()? {
+            return Err(OperationError(String::from(
+                "Transactions are only supported by replica sets running MongoDB 4.0 or later \
+                 and sharded clusters running MongoDB 4.2 or later.",
+            )));
+        }
+
+        self.next_txn_number();
+        self.transaction_options = options.unwrap_or_default();
+        self.transaction_state = TransactionState::Starting;
+        Ok(())
+    }
+
+    /// Commits the current transaction. The commit is retried once, after a short delay, if it
+    /// fails with the `UnknownTransactionCommitResult` label.
+    pub fn commit_transaction(&mut self) -> Result<()> {
+        match self.commit_once() {
+            Err(ref err) if is_unknown_commit_result(err) => {
+                thread::sleep(commit_retry_backoff(1));
+                self.commit_once()
+            }
+            result => result,
+        }
+    }
+
+    // Sends commitTransaction once, unless nothing was sent in the transaction.
+    fn commit_once(&mut self) -> Result<()> {
+        match self.transaction_state {
+            TransactionState::None => {
+                return Err(OperationError(String::from("No transaction started.")))
+            }
+            TransactionState::Aborted => {
+                return Err(OperationError(String::from(
+                    "Cannot commit a transaction after it has been aborted.",
+                )))
+            }
+            // Nothing was sent to the server, so there is nothing to commit.
+            TransactionState::Starting => {
+                self.transaction_state = TransactionState::Committed;
+                return Ok(());
+            }
+            TransactionState::InProgress | TransactionState::Committed => (),
+        }
+
+        self.transaction_state = TransactionState::Committed;
+        self.run_transaction_command("commitTransaction", CommandType::CommitTransaction)
+    }
+
+    /// Aborts the current transaction.
+    pub fn abort_transaction(&mut self) -> Result<()> {
+        match self.transaction_state {
+            TransactionState::None => {
+                return Err(OperationError(String::from("No transaction started.")))
+            }
+            TransactionState::Committed => {
+                return Err(OperationError(String::from(
+                    "Cannot abort a transaction after it has been committed.",
+                )))
+            }
+            TransactionState::Aborted => {
+                return Err(OperationError(String::from(
+                    "Cannot abort a transaction twice.",
+                )))
+            }
+            TransactionState::Starting => {
+                self.transaction_state = TransactionState::Aborted;
+                return Ok(());
+            }
+            TransactionState::InProgress => (),
+        }
+
+        self.transaction_state = TransactionState::Aborted;
+
+        // The server aborts transactions on its own when they time out, so failures to abort
+        // are not reported.
+        let _ = self.run_transaction_command("abortTransaction", CommandType::AbortTransaction);
+        Ok(())
+    }
+
+    /// Runs `callback` in a transaction and commits it, retrying the whole transaction on
+    /// transient errors and the commit on unknown commit results until 120 seconds have
+    /// passed. Commit retries back off exponentially, waiting at most a second between them.
+    ///
+    /// If the callback returns an error, the transaction is aborted and the error is returned.
+    pub fn with_transaction<F, T>(
+        &mut self,
+        options: Option<TransactionOptions>,
+        mut callback: F,
+    ) -> Result<T>
+    where
+        F: FnMut(&mut ClientSession) -> Result<T>,
+    {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(WITH_TRANSACTION_TIMEOUT_SECS);
+
+        'transaction: loop {
+            self.start_transaction(options.clone())?;
+
+            let value = match callback(self) {
+                Ok(value) => value,
+                Err(err) => {
+                    if self.in_transaction() {
+                        let _ = self.abort_transaction();
+                    }
+
+                    if err.has_label(TRANSIENT_TRANSACTION_ERROR) && start.elapsed() < timeout {
+                        continue 'transaction;
+                    }
+
+                    return Err(err);
+                }
+            };
+
+            // The callback committed or aborted the transaction itself.
+            if !self.in_transaction() {
+                return Ok(value);
+            }
+
+            // Commits are retried here rather than by `commit_transaction`, so that each retry
+            // waits out the schedule of `commit_retry_backoff`.
+            let mut attempt = 1;
+
+            loop {
+                let err = match self.commit_once() {
+                    Ok(()) => return Ok(value),
+                    Err(err) => err,
+                };
+
+                let elapsed = start.elapsed();
+                if elapsed >= timeout {
+                    return Err(err);
+                }
+
+                if is_unknown_commit_result(&err) {
+                    thread::sleep(commit_retry_backoff(attempt).min(timeout - elapsed));
+                    attempt += 1;
+                    continue;
+                }
+
+                if err.has_label(TRANSIENT_TRANSACTION_ERROR) {
+                    continue 'transaction;
+                }
+
+                return Err(err);
+            }
+        }
+    }
+
+    fn run_transaction_command(&mut self, name: &str, cmd_type: CommandType) -> Result<()> {
+        let mut cmd = bson::Document::new();
+        cmd.insert(name, 1);
+        cmd.insert("lsid", self.id().clone());
+        cmd.insert("txnNumber", self.server_session().txn_number);
+        cmd.insert("autocommit", false);
+
+        if let Some(ref write_concern) = self.transaction_options.write_concern {
+            cmd.insert("writeConcern", write_concern.to_bson());
+        }
+
+        if let Some(ref cluster_time) = self.cluster_time {
+            cmd.insert("$clusterTime", cluster_time.clone());
+        }
+
+        let reply = self.client.db("admin").command(cmd, cmd_type, None)?;
+        self.process_reply(&reply);
+        Ok(())
+    }
+}
+
+// Returns how long to wait after the given (1-indexed) commit attempt failed with an unknown
+// result: `COMMIT_RETRY_BACKOFF_MS`, doubled with each attempt up to
+// `MAX_COMMIT_RETRY_BACKOFF_MS`.
+fn commit_retry_backoff(attempt: u32) -> Duration {
+    let factor = 1u64.checked_shl(attempt.saturating_sub(1)).unwrap_or(::std::u64::MAX);
+    let backoff_ms = COMMIT_RETRY_BACKOFF_MS.saturating_mul(factor);
+    Duration::from_millis(backoff_ms.min(MAX_COMMIT_RETRY_BACKOFF_MS))
+}
+
+// Returns true if a commit failed without telling whether the transaction was committed.
+fn is_unknown_commit_result(err: &Error) -> bool {
+    err.commit_labels().iter().any(|label| label == UNKNOWN_TRANSACTION_COMMIT_RESULT)
+}
+
+impl Drop for ClientSession {
+    fn drop(&mut self) {
+        if let Some(session) = self.server_session.take() {
+            self.client.session_pool.check_in(session, self.timeout_minutes);
+        }
+    }
+}
+
+/// Ends every pooled session on the server, batching them into as few `endSessions`
+/// commands as possible.
+pub fn end_sessions(client: &Client) -> Result<()> {
+    let db = client.db("admin");
+
+    for cmd in end_sessions_commands(client) {
+        db.command(cmd, CommandType::EndSessions, None)?;
+    }
+
+    Ok(())
+}
+
+/// Removes every session from the pool, returning the `endSessions` commands that end them on
+/// the server.
+pub fn end_sessions_commands(client: &Client) -> Vec<bson::Document> {
+    let ids: Vec<_> = client
+        .session_pool
+        .drain()
+        .into_iter()
+        .map(|session| Bson::Document(session.id))
+        .collect();
+
+    ids.chunks(MAX_END_SESSIONS_BATCH_SIZE)
+        .map(|batch| doc! { "endSessions": batch.to_vec() })
+        .collect()
+}
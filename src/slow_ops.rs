@@ -0,0 +1,272 @@
+//! Capture of operations slower than a threshold, for investigating slow queries after the fact.
+//!
+//! Capture is off until `ThreadedClient::enable_slow_op_capture` is called, and costs a single
+//! atomic load per round trip while off. Once on, every round trip taking at least the threshold
+//! is recorded with its command, reply size and server into a ring buffer of bounded capacity,
+//! which drops its oldest records to make room. `ThreadedClient::drain_slow_ops` takes the
+//! records out of the buffer.
+//!
+//! ```no_run
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! client.enable_slow_op_capture(100, 1000);
+//! // ...
+//! for op in client.drain_slow_ops() {
+//!     println!("{} on {} took {:?}: {}", op.namespace, op.server.host_name, op.duration,
+//!              op.command);
+//! }
+//! # }
+//! ```
+use bson::{self, Bson};
+use connstring::Host;
+use logging::redact_document;
+use wire_protocol::operations::Message;
+
+use std::cmp;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+// The threshold while capture is disabled, which no round trip reaches.
+const DISABLED: u64 = u64::MAX;
+
+/// A round trip that took at least the capture threshold.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlowOpRecord {
+    /// The command or query sent, with the contents of authentication commands removed. Legacy
+    /// getMore requests are described as a `getMore` command.
+    pub command: bson::Document,
+    /// The name of the command, or `find` for a legacy query.
+    pub command_name: String,
+    /// The collection the operation ran against, or the database for database commands.
+    pub namespace: String,
+    /// The server the request was sent to.
+    pub server: Host,
+    /// The time between sending the request and reading the whole reply.
+    pub duration: Duration,
+    /// The size of the reply in bytes, as recorded in its header.
+    pub reply_size: usize,
+}
+
+/// A ring buffer of slow operations, shared by a client and the handles created from it.
+#[derive(Debug)]
+pub struct SlowOpCapture {
+    threshold_ns: AtomicU64,
+    capacity: AtomicUsize,
+    records: Mutex<VecDeque<SlowOpRecord>>,
+}
+
+impl SlowOpCapture {
+    /// Creates a disabled capture.
+    pub fn new() -> SlowOpCapture {
+        SlowOpCapture {
+            threshold_ns: AtomicU64::new(DISABLED),
+            capacity: AtomicUsize::new(0),
+            records: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Starts recording round trips that take at least `threshold`, keeping the latest
+    /// `capacity` of them. Records already captured beyond the new capacity are dropped, oldest
+    /// first. A capacity of 0 disables capture.
+    pub fn enable(&self, threshold: Duration, capacity: usize) {
+        if capacity == 0 {
+            self.disable();
+            return;
+        }
+
+        let threshold_ns = threshold.as_secs() * 1_000_000_000 + threshold.subsec_nanos() as u64;
+
+        if let Ok(mut records) = self.records.lock() {
+            while records.len() > capacity {
+                records.pop_front();
+            }
+            self.capacity.store(capacity, Ordering::SeqCst);
+        }
+        self.threshold_ns.store(cmp::min(threshold_ns, DISABLED - 1), Ordering::SeqCst);
+    }
+
+    /// Stops recording round trips. Records already captured are kept until drained.
+    pub fn disable(&self) {
+        self.threshold_ns.store(DISABLED, Ordering::SeqCst);
+    }
+
+    /// Returns true if round trips are being recorded.
+    pub fn is_enabled(&self) -> bool {
+        self.threshold_ns.load(Ordering::Relaxed) != DISABLED
+    }
+
+    /// Removes and returns the captured records, oldest first.
+    pub fn drain(&self) -> Vec<SlowOpRecord> {
+        match self.records.lock() {
+            Ok(mut records) => records.drain(..).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Records the round trip of `request`, started at `start` and answered by `reply`, if it
+    /// took at least the threshold.
+    pub fn record(&self, start: Instant, request: &Message, reply: &Message, server: &Host) {
+        let threshold_ns = self.threshold_ns.load(Ordering::Relaxed);
+        if threshold_ns == DISABLED {
+            return;
+        }
+
+        let duration = start.elapsed();
+        let duration_ns = duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64;
+        if duration_ns < threshold_ns {
+            return;
+        }
+
+        let (command, command_name, namespace) = describe(request);
+        let record = SlowOpRecord {
+            command: command,
+            command_name: command_name,
+            namespace: namespace,
+            server: server.clone(),
+            duration: duration,
+            reply_size: reply.length().max(0) as usize,
+        };
+
+        if let Ok(mut records) = self.records.lock() {
+            let capacity = self.capacity.load(Ordering::SeqCst);
+            while records.len() >= capacity && !records.is_empty() {
+                records.pop_front();
+            }
+            records.push_back(record);
+        }
+    }
+}
+
+impl Default for SlowOpCapture {
+    fn default() -> Self {
+        SlowOpCapture::new()
+    }
+}
+
+// Returns the redacted command, the command name and the namespace of a request.
+fn describe(request: &Message) -> (bson::Document, String, String) {
+    match *request {
+        Message::OpQuery { ref namespace, ref query, .. } => describe_query(namespace, query),
+        Message::OpQueryRaw { ref namespace, ref query, .. } => {
+            match bson::decode_document(&mut &query[..]) {
+                Ok(query) => describe_query(namespace, &query),
+                Err(_) => (bson::Document::new(), String::new(), namespace.to_owned()),
+            }
+        }
+        Message::OpGetMore { ref namespace, cursor_id, .. } => {
+            let mut command = bson::Document::new();
+            command.insert("getMore", cursor_id);
+            (command, String::from("getMore"), namespace.to_owned())
+        }
+        Message::OpInsert { ref namespace, .. } |
+        Message::OpUpdate { ref namespace, .. } |
+        Message::OpDelete { ref namespace, .. } => {
+            (bson::Document::new(), String::new(), namespace.to_owned())
+        }
+        _ => (bson::Document::new(), String::new(), String::new()),
+    }
+}
+
+fn describe_query(namespace: &str, query: &bson::Document) -> (bson::Document, String, String) {
+    let command = redact_document(query);
+    if !namespace.ends_with(".$cmd") {
+        return (command, String::from("find"), namespace.to_owned());
+    }
+
+    // Commands name their collection in their first field.
+    let db_name = &namespace[..namespace.len() - ".$cmd".len()];
+    let inner = match query.get("$query") {
+        Some(&Bson::Document(ref inner)) => inner,
+        _ => query,
+    };
+    let (name, target) = match inner.iter().next() {
+        Some((name, &Bson::String(ref coll))) => {
+            (name.to_owned(), format!("{}.{}", db_name, coll))
+        }
+        Some((name, _)) => (name.to_owned(), db_name.to_owned()),
+        None => (String::new(), db_name.to_owned()),
+    };
+    (command, name, target)
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{self, bson, doc};
+    use connstring::{self, Host};
+    use wire_protocol::flags::OpQueryFlags;
+    use wire_protocol::operations::Message;
+    use super::SlowOpCapture;
+
+    use std::time::{Duration, Instant};
+
+    fn query(namespace: &str, query: bson::Document) -> Message {
+        Message::new_query(1, OpQueryFlags::empty(), namespace.to_owned(), 0, -1, query, None)
+            .unwrap()
+    }
+
+    // Only the length of the reply is recorded, so any message will do.
+    fn reply() -> Message {
+        query("test.$cmd", doc! { "ok": 1 })
+    }
+
+    fn host() -> Host {
+        connstring::parse_host("localhost:27017").unwrap()
+    }
+
+    #[test]
+    fn disabled_capture_records_nothing() {
+        let capture = SlowOpCapture::new();
+        let host = host();
+        let start = Instant::now() - Duration::from_secs(1);
+
+        capture.record(start, &query("test.coll", doc! {}), &reply(), &host);
+        assert!(!capture.is_enabled());
+        assert!(capture.drain().is_empty());
+    }
+
+    #[test]
+    fn oldest_records_are_evicted() {
+        let capture = SlowOpCapture::new();
+        capture.enable(Duration::from_millis(10), 2);
+        let host = host();
+        let slow = Instant::now() - Duration::from_secs(1);
+
+        capture.record(Instant::now(), &query("test.coll", doc! { "fast": 1 }), &reply(), &host);
+        for i in 0..3 {
+            capture.record(slow, &query("test.coll", doc! { "n": i }), &reply(), &host);
+        }
+
+        let records = capture.drain();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].command, doc! { "n": 1 });
+        assert_eq!(records[1].command, doc! { "n": 2 });
+        assert_eq!(records[0].namespace, "test.coll");
+        assert!(records[0].duration >= Duration::from_secs(1));
+        assert!(records[0].reply_size > 0);
+        assert!(capture.drain().is_empty());
+    }
+
+    #[test]
+    fn commands_are_redacted() {
+        let capture = SlowOpCapture::new();
+        capture.enable(Duration::from_millis(0), 10);
+        let host = host();
+
+        let auth = doc! { "saslStart": 1, "payload": "secret" };
+        capture.record(Instant::now(), &query("admin.$cmd", auth), &reply(), &host);
+        let count = doc! { "count": "coll", "query": { "x": 1 } };
+        capture.record(Instant::now(), &query("test.$cmd", count.clone()), &reply(), &host);
+
+        let records = capture.drain();
+        assert_eq!(records[0].command, doc! { "saslStart": "<redacted>" });
+        assert_eq!(records[0].namespace, "admin");
+        assert_eq!(records[1].command, count);
+        assert_eq!(records[1].command_name, "count");
+        assert_eq!(records[1].namespace, "test.coll");
+    }
+}
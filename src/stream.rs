@@ -1,11 +1,74 @@
+use std::collections::HashMap;
+use std::fmt;
 use std::io::{BufReader, Read, Result, Write};
 #[cfg(feature = "ssl")]
 use std::io::{Error, ErrorKind};
 use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+
+use net2::TcpStreamExt;
 
 #[cfg(feature = "ssl")]
 use openssl::ssl::{Ssl, SslContext, SslFiletype, SslMethod, SslOptions, SslStream, SslVerifyMode};
 
+/// Maps the address a server is known by, as given in the seed list or advertised by replica
+/// set members, to the address to connect to, e.g. when members advertise hostnames that don't
+/// resolve from the client's network. The server keeps its advertised address in the topology,
+/// and TLS connections verify the certificate against it.
+///
+/// Closures of type `Fn(&str, u16) -> Option<(String, u16)>` are host mappers.
+pub trait HostMapper: Send + Sync {
+    /// Returns the host and port to connect to for a server known as `host:port`, or None to
+    /// connect to the server's own address.
+    fn map_host(&self, host: &str, port: u16) -> Option<(String, u16)>;
+}
+
+impl<F> HostMapper for F
+where
+    F: Fn(&str, u16) -> Option<(String, u16)> + Send + Sync,
+{
+    fn map_host(&self, host: &str, port: u16) -> Option<(String, u16)> {
+        self(host, port)
+    }
+}
+
+impl fmt::Debug for HostMapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("HostMapper { .. }")
+    }
+}
+
+/// A table of host aliases, mapping `host:port` addresses to the addresses to connect to.
+/// Hostnames are matched case-insensitively.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HostAliases {
+    aliases: HashMap<String, (String, u16)>,
+}
+
+impl HostAliases {
+    /// Creates an empty table.
+    pub fn new() -> HostAliases {
+        HostAliases::default()
+    }
+
+    /// Connects to `target_host:target_port` for the server known as `host:port`.
+    pub fn add(&mut self, host: &str, port: u16, target_host: &str, target_port: u16) {
+        self.aliases.insert(
+            format!("{}:{}", host.to_ascii_lowercase(), port),
+            (String::from(target_host), target_port),
+        );
+    }
+}
+
+impl HostMapper for HostAliases {
+    fn map_host(&self, host: &str, port: u16) -> Option<(String, u16)> {
+        self.aliases
+            .get(&format!("{}:{}", host.to_ascii_lowercase(), port))
+            .cloned()
+    }
+}
+
 /// Encapsulates the functionality for how to connect to the server.
 #[derive(Clone, Debug)]
 pub enum StreamConnector {
@@ -21,6 +84,11 @@ pub enum StreamConnector {
         key_file: Option<String>,
         verify_peer: bool,
     },
+    /// Connect to the address a host mapper gives for each server, through the inner connector.
+    Mapped {
+        connector: Box<StreamConnector>,
+        mapper: Arc<HostMapper>,
+    },
 }
 
 impl Default for StreamConnector {
@@ -89,10 +157,37 @@ impl StreamConnector {
         }
     }
 
+    /// Returns a connector that connects to the addresses given by `mapper`, and otherwise
+    /// connects like this one.
+    pub fn with_host_mapper(self, mapper: Arc<HostMapper>) -> Self {
+        let connector = match self {
+            StreamConnector::Mapped { connector, .. } => connector,
+            connector => Box::new(connector),
+        };
+
+        StreamConnector::Mapped {
+            connector: connector,
+            mapper: mapper,
+        }
+    }
+
     pub fn connect(&self, hostname: &str, port: u16) -> Result<Stream> {
+        if let StreamConnector::Mapped { ref connector, ref mapper } = *self {
+            if let Some((address, address_port)) = mapper.map_host(hostname, port) {
+                return connector.connect_to(hostname, &address, address_port);
+            }
+        }
+
+        self.connect_to(hostname, hostname, port)
+    }
+
+    // Connects to `address:port` for the server known as `hostname`, whose name is used to
+    // verify its certificate.
+    #[cfg_attr(not(feature = "ssl"), allow(unused_variables))]
+    fn connect_to(&self, hostname: &str, address: &str, port: u16) -> Result<Stream> {
         match *self {
             StreamConnector::Tcp => {
-                let stream = TcpStream::connect((hostname, port))?;
+                let stream = TcpStream::connect((address, port))?;
                 stream.set_nodelay(true)?;
                 Ok(Stream::Tcp {
                     read_half: BufReader::new(stream.try_clone()?),
@@ -106,7 +201,7 @@ impl StreamConnector {
                 ref key_file,
                 verify_peer,
             } => {
-                let inner_stream = TcpStream::connect((hostname, port))?;
+                let inner_stream = TcpStream::connect((address, port))?;
                 inner_stream.set_nodelay(true)?;
 
                 let mut ssl_context = SslContext::builder(SslMethod::tls())?;
@@ -141,6 +236,9 @@ impl StreamConnector {
                     Err(e) => Err(Error::new(ErrorKind::Other, e)),
                 }
             }
+            StreamConnector::Mapped { ref connector, .. } => {
+                connector.connect_to(hostname, address, port)
+            }
         }
     }
 }
@@ -196,4 +294,33 @@ impl Stream {
             Stream::Ssl(ref stream) => stream.get_ref().peer_addr(),
         }
     }
+
+    /// Enables TCP keepalive, sending probes once the connection has been idle for
+    /// `keep_alive`.
+    pub fn set_keep_alive(&self, keep_alive: Duration) -> Result<()> {
+        match *self {
+            Stream::Tcp { ref write_half, .. } => write_half.set_keepalive(Some(keep_alive)),
+            #[cfg(feature = "ssl")]
+            Stream::Ssl(ref stream) => stream.get_ref().set_keepalive(Some(keep_alive)),
+        }
+    }
+
+    /// Sets how long reads wait for data before failing, or lets them wait indefinitely.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        match *self {
+            Stream::Tcp { ref write_half, .. } => write_half.set_read_timeout(timeout),
+            #[cfg(feature = "ssl")]
+            Stream::Ssl(ref stream) => stream.get_ref().set_read_timeout(timeout),
+        }
+    }
+
+    /// Returns another handle to the underlying socket, through which it can be shut down from
+    /// another thread to interrupt a blocked read.
+    pub fn try_clone_socket(&self) -> Result<TcpStream> {
+        match *self {
+            Stream::Tcp { ref write_half, .. } => write_half.try_clone(),
+            #[cfg(feature = "ssl")]
+            Stream::Ssl(ref stream) => stream.get_ref().try_clone(),
+        }
+    }
 }
@@ -0,0 +1,259 @@
+//! The features supported by a server, as reported by its isMaster response.
+//!
+//! Feature decisions, like choosing between write commands and legacy write messages, are made
+//! by wire version rather than by server version, so that they can be made from the handshake
+//! without running `buildInfo`.
+use super::monitor::IsMasterResult;
+
+/// The largest document accepted by servers that don't report `maxBsonObjectSize`.
+pub const DEFAULT_MAX_BSON_OBJECT_SIZE: i64 = 16 * 1024 * 1024;
+/// The largest message accepted by servers that don't report `maxMessageSizeBytes`.
+pub const DEFAULT_MAX_MESSAGE_SIZE_BYTES: i64 = 48000000;
+/// The most writes per batch accepted by servers that don't report `maxWriteBatchSize`.
+pub const DEFAULT_MAX_WRITE_BATCH_SIZE: i64 = 1000;
+
+// Write commands were added in MongoDB 2.6.
+const WRITE_COMMANDS_WIRE_VERSION: i64 = 2;
+// listCollections and listIndexes were added in MongoDB 3.0.
+const LIST_COMMANDS_WIRE_VERSION: i64 = 3;
+// The find, getMore, killCursors, currentOp, killOp and fsyncUnlock commands, read concerns,
+// partial indexes, and the $sample and $indexStats stages were added in MongoDB 3.2.
+const FIND_COMMAND_WIRE_VERSION: i64 = 4;
+// Collations and views were added in MongoDB 3.4, and aggregations accept a write concern
+// since then.
+const COLLATION_WIRE_VERSION: i64 = 5;
+// OP_MSG, sessions and array filters were added in MongoDB 3.6, and the snapshot query option
+// removed.
+const OP_MSG_WIRE_VERSION: i64 = 6;
+// listDatabases and listCollections accept nameOnly since MongoDB 4.0.
+const NAME_ONLY_WIRE_VERSION: i64 = 7;
+// Update hints were added in MongoDB 4.2, and delete hints in MongoDB 4.4.
+const UPDATE_HINT_WIRE_VERSION: i64 = 8;
+const DELETE_HINT_WIRE_VERSION: i64 = 9;
+// Hidden indexes, and compound indexes with a hashed key, were added in MongoDB 4.4.
+const HIDDEN_INDEX_WIRE_VERSION: i64 = 9;
+// getMore accepts a comment since MongoDB 4.4.
+const GET_MORE_COMMENT_WIRE_VERSION: i64 = 9;
+// Keys containing '.' or starting with '$' are stored since MongoDB 5.0.
+const DOTTED_KEYS_WIRE_VERSION: i64 = 13;
+// Legacy write messages were removed in MongoDB 5.1.
+const LEGACY_WRITES_REMOVED_WIRE_VERSION: i64 = 14;
+// Clustered collections were added in MongoDB 5.3.
+const CLUSTERED_WIRE_VERSION: i64 = 16;
+
+/// The limits and wire versions of a server, from which the features it supports follow.
+///
+/// The capabilities of several servers are combined with `common`, so that a mixed-version
+/// deployment only reports what all of its members support.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ServerCapabilities {
+    /// The oldest wire version the server speaks, or -1 if it didn't report one.
+    pub min_wire_version: i64,
+    /// The newest wire version the server speaks, or -1 if it didn't report one, as servers
+    /// older than MongoDB 2.6 don't.
+    pub max_wire_version: i64,
+    /// The largest document the server accepts, in bytes.
+    pub max_bson_size: i64,
+    /// The largest message the server accepts, in bytes.
+    pub max_message_size: i64,
+    /// The most writes the server accepts in a single write command.
+    pub max_write_batch_size: i64,
+    /// How long the server keeps idle logical sessions alive, if it supports sessions.
+    pub logical_session_timeout_minutes: Option<i64>,
+}
+
+impl Default for ServerCapabilities {
+    fn default() -> ServerCapabilities {
+        ServerCapabilities {
+            min_wire_version: -1,
+            max_wire_version: -1,
+            max_bson_size: DEFAULT_MAX_BSON_OBJECT_SIZE,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE_BYTES,
+            max_write_batch_size: DEFAULT_MAX_WRITE_BATCH_SIZE,
+            logical_session_timeout_minutes: None,
+        }
+    }
+}
+
+impl ServerCapabilities {
+    /// Returns the capabilities of a server that hasn't been checked, which support nothing
+    /// that depends on the wire version.
+    pub fn new() -> ServerCapabilities {
+        Default::default()
+    }
+
+    /// Returns the capabilities reported by an isMaster response.
+    pub fn from_is_master(ismaster: &IsMasterResult) -> ServerCapabilities {
+        ServerCapabilities {
+            min_wire_version: ismaster.min_wire_version,
+            max_wire_version: ismaster.max_wire_version,
+            max_bson_size: ismaster.max_bson_object_size,
+            max_message_size: ismaster.max_message_size_bytes,
+            max_write_batch_size: ismaster.max_write_batch_size,
+            logical_session_timeout_minutes: ismaster.logical_session_timeout_minutes,
+        }
+    }
+
+    /// Returns the capabilities shared with `other`: the wire versions both servers speak, the
+    /// smaller of each limit, and sessions only if both support them.
+    pub fn common(&self, other: &ServerCapabilities) -> ServerCapabilities {
+        let logical_session_timeout_minutes = match (
+            self.logical_session_timeout_minutes,
+            other.logical_session_timeout_minutes,
+        ) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            _ => None,
+        };
+
+        ServerCapabilities {
+            min_wire_version: self.min_wire_version.max(other.min_wire_version),
+            max_wire_version: self.max_wire_version.min(other.max_wire_version),
+            max_bson_size: self.max_bson_size.min(other.max_bson_size),
+            max_message_size: self.max_message_size.min(other.max_message_size),
+            max_write_batch_size: self.max_write_batch_size.min(other.max_write_batch_size),
+            logical_session_timeout_minutes: logical_session_timeout_minutes,
+        }
+    }
+
+    /// Returns true if the server speaks `version` or a newer wire version.
+    pub fn supports_wire_version(&self, version: i64) -> bool {
+        self.max_wire_version >= version
+    }
+
+    /// Returns true if writes can be sent as `insert`, `update` and `delete` commands rather
+    /// than as legacy write messages (MongoDB 2.6).
+    pub fn supports_write_commands(&self) -> bool {
+        self.supports_wire_version(WRITE_COMMANDS_WIRE_VERSION)
+    }
+
+    /// Returns true if collections and indexes are listed with the `listCollections` and
+    /// `listIndexes` commands rather than by querying system collections (MongoDB 3.0).
+    pub fn supports_list_commands(&self) -> bool {
+        self.supports_wire_version(LIST_COMMANDS_WIRE_VERSION)
+    }
+
+    /// Returns true if queries can be sent as `find`, `getMore` and `killCursors` commands
+    /// rather than as legacy query messages (MongoDB 3.2).
+    pub fn supports_find_command(&self) -> bool {
+        self.supports_wire_version(FIND_COMMAND_WIRE_VERSION)
+    }
+
+    /// Returns true if the currentOp, killOp and fsyncUnlock commands are used rather than
+    /// queries on the virtual `$cmd.sys` collections (MongoDB 3.2).
+    pub fn supports_current_op_command(&self) -> bool {
+        self.supports_wire_version(FIND_COMMAND_WIRE_VERSION)
+    }
+
+    /// Returns true if reads accept a read concern (MongoDB 3.2).
+    pub fn supports_read_concern(&self) -> bool {
+        self.supports_wire_version(FIND_COMMAND_WIRE_VERSION)
+    }
+
+    /// Returns true if aggregations accept the `$sample` stage (MongoDB 3.2).
+    pub fn supports_sample(&self) -> bool {
+        self.supports_wire_version(FIND_COMMAND_WIRE_VERSION)
+    }
+
+    /// Returns true if aggregations accept the `$indexStats` stage (MongoDB 3.2).
+    pub fn supports_index_stats(&self) -> bool {
+        self.supports_wire_version(FIND_COMMAND_WIRE_VERSION)
+    }
+
+    /// Returns true if indexes can be restricted to the documents matching a partial filter
+    /// expression (MongoDB 3.2).
+    pub fn supports_partial_indexes(&self) -> bool {
+        self.supports_wire_version(FIND_COMMAND_WIRE_VERSION)
+    }
+
+    /// Returns true if operations accept a collation (MongoDB 3.4). Older servers ignore it.
+    pub fn supports_collation(&self) -> bool {
+        self.supports_wire_version(COLLATION_WIRE_VERSION)
+    }
+
+    /// Returns true if read-only views can be created over a collection (MongoDB 3.4).
+    pub fn supports_views(&self) -> bool {
+        self.supports_wire_version(COLLATION_WIRE_VERSION)
+    }
+
+    /// Returns true if aggregations writing their output accept a write concern (MongoDB 3.4).
+    pub fn supports_aggregate_write_concern(&self) -> bool {
+        self.supports_wire_version(COLLATION_WIRE_VERSION)
+    }
+
+    /// Returns true if queries accept the `snapshot` option, which MongoDB 3.6 removed.
+    pub fn supports_snapshot_queries(&self) -> bool {
+        !self.supports_wire_version(OP_MSG_WIRE_VERSION)
+    }
+
+    /// Returns true if the server accepts OP_MSG messages (MongoDB 3.6).
+    pub fn supports_op_msg(&self) -> bool {
+        self.supports_wire_version(OP_MSG_WIRE_VERSION)
+    }
+
+    /// Returns true if updates accept array filters (MongoDB 3.6).
+    pub fn supports_array_filters(&self) -> bool {
+        self.supports_wire_version(OP_MSG_WIRE_VERSION)
+    }
+
+    /// Returns true if the server supports logical sessions (MongoDB 3.6), which it advertises
+    /// with a session timeout.
+    pub fn supports_sessions(&self) -> bool {
+        self.logical_session_timeout_minutes.is_some() &&
+            self.supports_wire_version(OP_MSG_WIRE_VERSION)
+    }
+
+    /// Returns true if `listDatabases` accepts a filter (MongoDB 3.6).
+    pub fn supports_list_databases_filter(&self) -> bool {
+        self.supports_wire_version(OP_MSG_WIRE_VERSION)
+    }
+
+    /// Returns true if `listDatabases` can return names only, without taking the locks needed
+    /// to compute database sizes (MongoDB 4.0).
+    pub fn supports_list_databases_name_only(&self) -> bool {
+        self.supports_wire_version(NAME_ONLY_WIRE_VERSION)
+    }
+
+    /// Returns true if `listCollections` can return names and types only, without taking
+    /// collection locks (MongoDB 4.0).
+    pub fn supports_list_collections_name_only(&self) -> bool {
+        self.supports_wire_version(NAME_ONLY_WIRE_VERSION)
+    }
+
+    /// Returns true if updates accept an index hint (MongoDB 4.2).
+    pub fn supports_update_hint(&self) -> bool {
+        self.supports_wire_version(UPDATE_HINT_WIRE_VERSION)
+    }
+
+    /// Returns true if deletes accept an index hint (MongoDB 4.4).
+    pub fn supports_delete_hint(&self) -> bool {
+        self.supports_wire_version(DELETE_HINT_WIRE_VERSION)
+    }
+
+    /// Returns true if indexes can be hidden from the query planner, and compound indexes can
+    /// have a hashed key (MongoDB 4.4).
+    pub fn supports_hidden_indexes(&self) -> bool {
+        self.supports_wire_version(HIDDEN_INDEX_WIRE_VERSION)
+    }
+
+    /// Returns true if the getMore command accepts a comment (MongoDB 4.4).
+    pub fn supports_get_more_comment(&self) -> bool {
+        self.supports_wire_version(GET_MORE_COMMENT_WIRE_VERSION)
+    }
+
+    /// Returns true if the server stores keys containing '.' or starting with '$' (MongoDB
+    /// 5.0).
+    pub fn supports_dotted_keys(&self) -> bool {
+        self.supports_wire_version(DOTTED_KEYS_WIRE_VERSION)
+    }
+
+    /// Returns true if the server accepts legacy insert, update and delete messages, which
+    /// MongoDB 5.1 removed.
+    pub fn supports_legacy_writes(&self) -> bool {
+        !self.supports_wire_version(LEGACY_WRITES_REMOVED_WIRE_VERSION)
+    }
+
+    /// Returns true if collections can be clustered by their `_id` (MongoDB 5.3).
+    pub fn supports_clustered_collections(&self) -> bool {
+        self.supports_wire_version(CLUSTERED_WIRE_VERSION)
+    }
+}
@@ -1,34 +1,60 @@
 //! MongoDB server set topology and asynchronous monitoring.
+pub mod capabilities;
 pub mod server;
 pub mod monitor;
 
 use {Client, Result};
-use Error::{self, ArgumentError, OperationError};
+use Error::{self, ArgumentError, OperationError, ServerSelectionTimeoutError};
 
 use bson::oid;
 
 use common::{ReadPreference, ReadMode};
 use connstring::{ConnectionString, Host};
-use pool::PooledStream;
+use pool::{ConnectionStats, PooledStream};
 use stream::StreamConnector;
 
 use rand::{thread_rng, Rng};
 
-use std::collections::HashMap;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::i64;
 use std::str::FromStr;
 use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use time;
 
+use self::capabilities::ServerCapabilities;
 use self::server::{Server, ServerDescription, ServerType};
 
 pub const DEFAULT_HEARTBEAT_FREQUENCY_MS: u32 = 10000;
+pub const MIN_HEARTBEAT_FREQUENCY_MS: u32 = 500;
 pub const DEFAULT_LOCAL_THRESHOLD_MS: i64 = 15;
 pub const DEFAULT_SERVER_SELECTION_TIMEOUT_MS: i64 = 30000;
 
+// The smallest allowed maximum staleness for secondary reads.
+const SMALLEST_MAX_STALENESS_SECONDS: i64 = 90;
+
+// How often an idle primary writes a no-op to the oplog, bounding the staleness estimate
+// of idle replica sets.
+const IDLE_WRITE_PERIOD_SECONDS: i64 = 10;
+
+// How long to wait between server selection attempts.
+const SERVER_SELECTION_RETRY_INTERVAL_MS: u64 = 500;
+
+// How often `refresh` checks whether every monitor has completed its check.
+const REFRESH_POLL_INTERVAL_MS: u64 = 10;
+
+// How many members a hedged read is sent to.
+const HEDGED_READ_FANOUT: usize = 2;
+
+// Converts a duration to whole milliseconds.
+fn duration_ms(duration: Duration) -> i64 {
+    duration.as_secs() as i64 * 1000 + i64::from(duration.subsec_nanos()) / 1_000_000
+}
+
 /// Describes the type of topology for a server set.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum TopologyType {
@@ -66,6 +92,9 @@ pub struct TopologyDescription {
     max_set_version: Option<i64>,
     compat_error: String,
     stream_connector: StreamConnector,
+    // The number of operations routed to a mongos, used to take turns between the routers
+    // within the latency window.
+    mongos_rotation: Arc<AtomicUsize>,
 }
 
 impl fmt::Debug for TopologyDescription {
@@ -86,6 +115,36 @@ impl fmt::Debug for TopologyDescription {
     }
 }
 
+/// A snapshot of a single server as seen by its monitor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerInfo {
+    /// The server's address.
+    pub host: Host,
+    /// The server's role, as reported by its last handshake.
+    pub server_type: ServerType,
+    /// The average round-trip time of recent checks, in milliseconds.
+    pub round_trip_time: Option<i64>,
+    /// The maximum wire version supported by the server.
+    pub max_wire_version: i64,
+    /// The server's replica set tags.
+    pub tags: BTreeMap<String, String>,
+    /// The error encountered by the last check, if it failed.
+    pub error: Option<String>,
+}
+
+/// A read-only snapshot of the driver's view of the topology, for diagnostics.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TopologyInfo {
+    /// The type of the topology.
+    pub topology_type: TopologyType,
+    /// The replica set name, if the topology is a replica set.
+    pub set_name: Option<String>,
+    /// The largest election id reported by a primary, if the topology is a replica set.
+    pub election_id: Option<oid::ObjectId>,
+    /// Every known server, sorted by address.
+    pub servers: Vec<ServerInfo>,
+}
+
 /// Holds status and connection information about a server set.
 #[derive(Clone, Debug)]
 pub struct Topology {
@@ -93,6 +152,8 @@ pub struct Topology {
     pub config: ConnectionString,
     /// Monitored topology information.
     pub description: Arc<RwLock<TopologyDescription>>,
+    // The wire version every server is treated as speaking at most, if capped.
+    max_wire_version: Arc<RwLock<Option<i64>>>,
 }
 
 impl FromStr for TopologyType {
@@ -123,6 +184,7 @@ impl Default for TopologyDescription {
             compat_error: String::new(),
             max_set_version: None,
             stream_connector: StreamConnector::Tcp,
+            mongos_rotation: Arc::new(AtomicUsize::new(0)),
         }
     }
 }
@@ -133,6 +195,91 @@ impl TopologyDescription {
         TopologyDescription { stream_connector, ..Default::default() }
     }
 
+    /// Returns a snapshot of the topology and every known server.
+    pub fn info(&self) -> TopologyInfo {
+        let mut servers: Vec<_> = self.servers
+            .iter()
+            .filter_map(|(host, server)| {
+                let description = server.description.read().ok()?;
+
+                let error = match *description.err {
+                    Some(ref err) => Some(err.to_string()),
+                    None => None,
+                };
+
+                Some(ServerInfo {
+                    host: host.clone(),
+                    server_type: description.server_type,
+                    round_trip_time: description.round_trip_time,
+                    max_wire_version: description.max_wire_version,
+                    tags: description.tags.clone(),
+                    error: error,
+                })
+            })
+            .collect();
+
+        servers.sort_by(|a, b| {
+            (&a.host.host_name, a.host.port, &a.host.ipc).cmp(
+                &(&b.host.host_name, b.host.port, &b.host.ipc),
+            )
+        });
+
+        let set_name = if self.set_name.is_empty() {
+            None
+        } else {
+            Some(self.set_name.clone())
+        };
+
+        TopologyInfo {
+            topology_type: self.topology_type,
+            set_name: set_name,
+            election_id: self.max_election_id.clone(),
+            servers: servers,
+        }
+    }
+
+    /// Describes every known server, its last error or type, and how long ago it was last
+    /// checked, e.g. `localhost:27017: connection refused (checked 1.2s ago)`.
+    pub fn describe_servers(&self) -> String {
+        if self.servers.is_empty() {
+            return String::from("no servers are known");
+        }
+
+        let mut descriptions: Vec<_> = self.servers
+            .iter()
+            .map(|(host, server)| {
+                let name = if host.ipc.is_empty() {
+                    format!("{}:{}", host.host_name, host.port)
+                } else {
+                    host.ipc.to_owned()
+                };
+
+                let description = match server.description.read() {
+                    Ok(description) => description,
+                    Err(_) => return format!("{}: description unavailable", name),
+                };
+
+                let state = match *description.err {
+                    Some(ref err) => err.to_string(),
+                    None => format!("{:?}", description.server_type),
+                };
+
+                match description.last_update_time {
+                    Some(time) => {
+                        let elapsed = time.elapsed();
+                        let secs = elapsed.as_secs() as f64 +
+                            f64::from(elapsed.subsec_nanos()) / 1_000_000_000.0;
+                        format!("{}: {} (checked {:.1}s ago)", name, state, secs)
+                    }
+                    None => format!("{}: {} (never checked)", name, state),
+                }
+            })
+            .collect();
+
+        descriptions.sort();
+        descriptions.join(", ")
+    }
+
     /// Returns the nearest server stream, calculated by round trip time.
     fn get_nearest_from_vec(&self, client: Client, servers: &mut Vec<Host>) -> Result<(PooledStream, ServerType)> {
         servers.sort_by(|a, b| {
@@ -169,6 +316,45 @@ impl TopologyDescription {
         )))
     }
 
+    /// Returns a stream to one of the reachable mongos routers within the latency window,
+    /// taking turns between them. Routers that can't be connected to are skipped.
+    fn get_round_robin_from_vec(
+        &self,
+        client: Client,
+        servers: &mut Vec<Host>,
+    ) -> Result<(PooledStream, ServerType)> {
+        servers.retain(|host| match self.servers.get(host) {
+            Some(server) => {
+                server.description.read().ok().map_or(false, |description| {
+                    description.server_type == ServerType::Mongos
+                })
+            }
+            None => false,
+        });
+
+        self.filter_latency_hosts(servers);
+
+        // Sort the routers so that the rotation doesn't depend on the order of the map.
+        servers.sort_by(|a, b| {
+            (&a.host_name, a.port, &a.ipc).cmp(&(&b.host_name, b.port, &b.ipc))
+        });
+
+        if !servers.is_empty() {
+            let start = self.mongos_rotation.fetch_add(1, Ordering::SeqCst);
+
+            for i in 0..servers.len() {
+                let host = &servers[(start + i) % servers.len()];
+                if let Some(server) = self.servers.get(host) {
+                    if let Ok(stream) = server.acquire_stream(client.clone()) {
+                        return Ok((stream, ServerType::Mongos));
+                    }
+                }
+            }
+        }
+
+        Err(OperationError(String::from("No mongos router is available.")))
+    }
+
     /// Returns a random server stream from the vector.
     fn get_rand_from_vec(&self, client: Client, servers: &mut Vec<Host>) -> Result<(PooledStream, ServerType)> {
         while !servers.is_empty() {
@@ -225,12 +411,16 @@ impl TopologyDescription {
         self.filter_latency_hosts(&mut hosts);
 
         // Retrieve a server stream from the list of acceptable hosts.
-        let (pooled_stream, server_type) = if rand {
+        let (mut pooled_stream, server_type) = if self.topology_type == TopologyType::Sharded {
+            self.get_round_robin_from_vec(client, &mut hosts)?
+        } else if rand {
             self.get_rand_from_vec(client, &mut hosts)?
         } else {
             self.get_nearest_from_vec(client, &mut hosts)?
         };
 
+        pooled_stream.set_server_type(server_type);
+
         // Determine how to handle server-side logic based on ReadMode and TopologyType.
         let (slave_ok, send_read_pref) = match self.topology_type {
             TopologyType::Unknown => (false, false),
@@ -271,6 +461,48 @@ impl TopologyDescription {
         Ok((pooled_stream, slave_ok, send_read_pref))
     }
 
+    /// Returns streams to the two nearest replica set members eligible for a hedged read, or
+    /// to fewer if fewer can be reached. Other topologies aren't hedged by the driver, so no
+    /// streams are returned for them.
+    pub fn acquire_hedged_streams(
+        &self,
+        client: Client,
+        read_preference: &ReadPreference,
+    ) -> Result<Vec<PooledStream>> {
+        match self.topology_type {
+            TopologyType::ReplicaSetWithPrimary | TopologyType::ReplicaSetNoPrimary => (),
+            _ => return Ok(Vec::new()),
+        }
+
+        let (mut hosts, _) = self.choose_hosts(read_preference)?;
+        self.filter_hosts(&mut hosts, read_preference);
+        self.filter_latency_hosts(&mut hosts);
+
+        hosts.sort_by_key(|host| {
+            self.servers
+                .get(host)
+                .and_then(|server| server.description.read().ok())
+                .and_then(|description| description.round_trip_time)
+                .unwrap_or(i64::MAX)
+        });
+
+        let mut streams = Vec::new();
+        for host in hosts {
+            if streams.len() == HEDGED_READ_FANOUT {
+                break;
+            }
+
+            if let Some(server) = self.servers.get(&host) {
+                if let Ok(mut stream) = server.acquire_stream(client.clone()) {
+                    stream.set_server_type(server.description.read()?.server_type);
+                    streams.push(stream);
+                }
+            }
+        }
+
+        Ok(streams)
+    }
+
     /// Returns a server stream for write operations.
     pub fn acquire_write_stream(&self, client: Client) -> Result<PooledStream> {
         let (mut hosts, rand) = self.choose_write_hosts();
@@ -282,11 +514,134 @@ impl TopologyDescription {
             }
         }
 
-        if rand {
-            Ok(self.get_rand_from_vec(client, &mut hosts)?.0)
+        let (mut pooled_stream, server_type) = if self.topology_type == TopologyType::Sharded {
+            self.get_round_robin_from_vec(client, &mut hosts)?
+        } else if rand {
+            self.get_rand_from_vec(client, &mut hosts)?
         } else {
-            Ok(self.get_nearest_from_vec(client, &mut hosts)?.0)
+            self.get_nearest_from_vec(client, &mut hosts)?
+        };
+
+        pooled_stream.set_server_type(server_type);
+        Ok(pooled_stream)
+    }
+
+    /// Checks that a read preference's maximum staleness is allowed: it can't be combined with
+    /// the primary read mode, and must be at least 90 seconds and at least 10 seconds longer
+    /// than the heartbeat frequency.
+    pub fn validate_read_preference(&self, read_preference: &ReadPreference) -> Result<()> {
+        if read_preference.hedge && read_preference.mode != ReadMode::Nearest {
+            return Err(ArgumentError(String::from(
+                "Hedged reads can only be used with the nearest read mode.",
+            )));
+        }
+
+        let max_staleness_seconds = match read_preference.max_staleness_seconds {
+            Some(seconds) => seconds,
+            None => return Ok(()),
+        };
+
+        if read_preference.mode == ReadMode::Primary {
+            return Err(ArgumentError(String::from(
+                "maxStalenessSeconds cannot be used with the primary read mode.",
+            )));
+        }
+
+        let heartbeat_seconds = i64::from(self.heartbeat_frequency_ms) / 1000;
+        let min_seconds = SMALLEST_MAX_STALENESS_SECONDS.max(
+            heartbeat_seconds + IDLE_WRITE_PERIOD_SECONDS,
+        );
+
+        if max_staleness_seconds < min_seconds {
+            return Err(ArgumentError(format!(
+                "maxStalenessSeconds must be at least {} seconds, but {} was given.",
+                min_seconds,
+                max_staleness_seconds
+            )));
         }
+
+        Ok(())
+    }
+
+    /// Removes secondaries whose estimated staleness exceeds the read preference's maximum
+    /// staleness.
+    ///
+    /// With a known primary, a secondary's staleness is how far its last write lags behind the
+    /// primary's, adjusted for when each was last checked. Without one, it is measured against
+    /// the secondary with the most recent write. The heartbeat frequency is added in both
+    /// cases to account for writes since the last check.
+    pub fn filter_stale_hosts(&self, hosts: &mut Vec<Host>, read_preference: &ReadPreference) {
+        let max_staleness_ms = match read_preference.max_staleness_seconds {
+            Some(seconds) => seconds * 1000,
+            None => return,
+        };
+
+        let heartbeat_ms = i64::from(self.heartbeat_frequency_ms);
+
+        let mut primary = None;
+        let mut max_last_write_date = None;
+
+        for server in self.servers.values() {
+            let description = server.description.read().unwrap();
+
+            match description.server_type {
+                ServerType::RSPrimary => {
+                    if let (Some(update), Some(write)) =
+                        (description.last_update_time, description.last_write_date)
+                    {
+                        primary = Some((update, write));
+                    }
+                }
+                ServerType::RSSecondary => {
+                    if let Some(write) = description.last_write_date {
+                        max_last_write_date =
+                            Some(max_last_write_date.map_or(write, |max: i64| max.max(write)));
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        hosts.retain(|host| {
+            let server = match self.servers.get(host) {
+                Some(server) => server,
+                None => return false,
+            };
+
+            let description = server.description.read().unwrap();
+            if description.server_type != ServerType::RSSecondary {
+                return true;
+            }
+
+            let last_write_date = match description.last_write_date {
+                Some(write) => write,
+                None => return false,
+            };
+
+            let staleness_ms = match primary {
+                Some((primary_update, primary_write)) => {
+                    let last_update_time = match description.last_update_time {
+                        Some(update) => update,
+                        None => return false,
+                    };
+
+                    // The difference between the two check times, which may be negative.
+                    let update_diff_ms = if last_update_time >= primary_update {
+                        duration_ms(last_update_time.duration_since(primary_update))
+                    } else {
+                        -duration_ms(primary_update.duration_since(last_update_time))
+                    };
+
+                    update_diff_ms - (last_write_date - primary_write) + heartbeat_ms
+                }
+                None => {
+                    max_last_write_date.unwrap_or(last_write_date) - last_write_date +
+                        heartbeat_ms
+                }
+            };
+
+            staleness_ms <= max_staleness_ms
+        });
     }
 
     /// Filters a given set of hosts based on the provided read preference tag sets.
@@ -498,6 +853,7 @@ impl TopologyDescription {
                         hosts.push(host.clone());
                     }
 
+                    self.filter_stale_hosts(&mut hosts, read_preference);
                     return Ok((hosts, false));
                 }
 
@@ -514,6 +870,10 @@ impl TopologyDescription {
                     }
                 }
 
+                // Exclude secondaries that lag too far behind before falling back to the
+                // primary according to the read preference.
+                self.filter_stale_hosts(&mut secondaries, read_preference);
+
                 // Choose an appropriate server at random based on the read preference.
                 match read_preference.mode {
                     ReadMode::Primary => Ok((primaries, true)),
@@ -901,6 +1261,7 @@ impl Topology {
         Ok(Topology {
             config: config,
             description: top_description,
+            max_wire_version: Arc::new(RwLock::new(None)),
         })
     }
 
@@ -915,6 +1276,10 @@ impl Topology {
         let time = time::get_time();
         let start_ms = time.sec * 1000 + (time.nsec as i64) / 1000000;
 
+        if let Some(ref read_preference) = read_preference {
+            self.description.read()?.validate_read_preference(read_preference)?;
+        }
+
         loop {
             let result = if write {
                 match self.description.read()?.acquire_write_stream(client.clone()) {
@@ -928,21 +1293,40 @@ impl Topology {
                 )
             };
 
-            match result {
+            let err = match result {
                 Ok(stream) => return Ok(stream),
-                Err(err) => {
-                    // Check duration of current server selection and return an error if
-                    // overdue.
-                    let end_time = time::get_time();
-                    let end_ms = end_time.sec * 1000 + (end_time.nsec as i64) / 1000000;
-                    if end_ms - start_ms >= self.description.read()?.server_selection_timeout_ms {
-                        return Err(err);
-                    }
+                Err(err) => err,
+            };
+
+            // Check duration of current server selection and return an error describing every
+            // known server if overdue.
+            let end_time = time::get_time();
+            let end_ms = end_time.sec * 1000 + (end_time.nsec as i64) / 1000000;
+            let elapsed_ms = end_ms - start_ms;
+
+            let remaining_ms = {
+                let description = self.description.read()?;
+                let timeout_ms = description.server_selection_timeout_ms;
+
+                if elapsed_ms >= timeout_ms {
+                    return Err(ServerSelectionTimeoutError(format!(
+                        "No suitable server found for {} after {} ms ({}). Known servers: {}.",
+                        if write { "a write" } else { "a read" },
+                        timeout_ms,
+                        err,
+                        description.describe_servers(),
+                    )));
                 }
+
+                (timeout_ms - elapsed_ms) as u64
             };
 
-            // Otherwise, sleep for a little while.
-            thread::sleep(Duration::from_millis(500));
+            // Otherwise, ask the monitors to check their servers again and wait for a little
+            // while.
+            self.request_updates()?;
+            thread::sleep(Duration::from_millis(
+                remaining_ms.min(SERVER_SELECTION_RETRY_INTERVAL_MS),
+            ));
         }
     }
 
@@ -955,9 +1339,264 @@ impl Topology {
         self.acquire_stream_private(client, Some(read_preference), false)
     }
 
+    /// Returns streams to the members a hedged read is sent to, which are fewer than two if
+    /// the read can't be hedged.
+    pub fn acquire_hedged_streams(
+        &self,
+        client: Client,
+        read_preference: &ReadPreference,
+    ) -> Result<Vec<PooledStream>> {
+        let description = self.description.read()?;
+        description.validate_read_preference(read_preference)?;
+        description.acquire_hedged_streams(client, read_preference)
+    }
+
     /// Returns a server stream for write operations.
     pub fn acquire_write_stream(&self, client: Client) -> Result<PooledStream> {
         let (stream, _, _) = self.acquire_stream_private(client, None, true)?;
         Ok(stream)
     }
+
+    /// Returns a snapshot of the monitors' current view of the topology.
+    pub fn info(&self) -> Result<TopologyInfo> {
+        Ok(self.description.read()?.info())
+    }
+
+    /// Requests an immediate update from every server monitor in the topology.
+    pub fn request_updates(&self) -> Result<()> {
+        for server in self.description.read()?.servers.values() {
+            server.request_update();
+        }
+        Ok(())
+    }
+
+    /// Requests an immediate update from every server monitor, and waits until each server has
+    /// been checked, or for at most the server selection timeout.
+    pub fn refresh(&self) -> Result<()> {
+        let start = Instant::now();
+        self.request_updates()?;
+
+        let timeout_ms = self.description.read()?.server_selection_timeout_ms.max(0) as u64;
+        let deadline = start + Duration::from_millis(timeout_ms);
+
+        loop {
+            let checked = self.description.read()?.servers.values().all(|server| {
+                match server.description.read() {
+                    Ok(description) => description.last_update_time.map_or(false, |time| {
+                        time >= start
+                    }),
+                    Err(_) => true,
+                }
+            });
+
+            let now = Instant::now();
+            if checked || now >= deadline {
+                return Ok(());
+            }
+
+            let interval = Duration::from_millis(REFRESH_POLL_INTERVAL_MS);
+            thread::sleep(cmp::min(deadline - now, interval));
+        }
+    }
+
+    /// Returns the number of connections currently used by operations across all servers.
+    pub fn connections_in_use(&self) -> Result<usize> {
+        Ok(self.description
+            .read()?
+            .servers
+            .values()
+            .map(Server::connections_in_use)
+            .sum())
+    }
+
+    /// Closes the idle connections to a server that an operation failed to reach, and asks its
+    /// monitor to check it right away, so that other servers are selected in the meantime.
+    pub fn reset_server(&self, host: &Host) -> Result<()> {
+        if let Some(server) = self.description.read()?.servers.get(host) {
+            server.reset();
+        }
+        Ok(())
+    }
+
+    /// Returns the socket usage of every known server, sorted by host.
+    pub fn connection_stats(&self) -> Result<Vec<ConnectionStats>> {
+        let mut stats = self.description
+            .read()?
+            .servers
+            .values()
+            .map(Server::connection_stats)
+            .collect::<Result<Vec<_>>>()?;
+
+        stats.sort_by(|a, b| {
+            (&a.host.host_name, a.host.port, &a.host.ipc).cmp(
+                &(&b.host.host_name, b.host.port, &b.host.ipc),
+            )
+        });
+
+        Ok(stats)
+    }
+
+    /// Stops monitoring every server, closes all connections and forgets every server.
+    pub fn close(&self) -> Result<()> {
+        let mut description = self.description.write()?;
+
+        for server in description.servers.values() {
+            server.close();
+        }
+
+        description.servers.clear();
+        Ok(())
+    }
+
+    /// Returns the capabilities shared by every known data-bearing server in the topology, or
+    /// `None` if none is known yet.
+    pub fn capabilities(&self) -> Result<Option<ServerCapabilities>> {
+        let mut capabilities: Option<ServerCapabilities> = None;
+
+        for server in self.description.read()?.servers.values() {
+            let description = server.description.read()?;
+
+            match description.server_type {
+                ServerType::Standalone |
+                ServerType::Mongos |
+                ServerType::RSPrimary |
+                ServerType::RSSecondary => (),
+                _ => continue,
+            }
+
+            capabilities = Some(match capabilities {
+                Some(capabilities) => capabilities.common(&description.capabilities),
+                None => description.capabilities,
+            });
+        }
+
+        match capabilities {
+            Some(capabilities) => self.capped(capabilities).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the capabilities with their wire version capped by `set_max_wire_version`.
+    pub fn capped(&self, mut capabilities: ServerCapabilities) -> Result<ServerCapabilities> {
+        if let Some(max) = *self.max_wire_version.read()? {
+            capabilities.max_wire_version = capabilities.max_wire_version.min(max);
+        }
+
+        Ok(capabilities)
+    }
+
+    /// Treats every server as speaking at most wire version `max`, so that the driver takes the
+    /// paths it takes against older servers, or stops doing so if `max` is `None`. Features the
+    /// cap rules out fail the same way they do against a server that lacks them.
+    pub fn set_max_wire_version(&self, max: Option<i64>) -> Result<()> {
+        *self.max_wire_version.write()? = max;
+        Ok(())
+    }
+
+    /// Returns true unless the wire version is capped below `version`.
+    pub fn allows_wire_version(&self, version: i64) -> Result<bool> {
+        Ok(self.max_wire_version.read()?.map_or(true, |max| max >= version))
+    }
+
+    /// Returns true unless a known data-bearing server in the topology lacks `feature`, such
+    /// as `ServerCapabilities::supports_collation`.
+    pub fn supports<F>(&self, feature: F) -> Result<bool>
+    where
+        F: Fn(&ServerCapabilities) -> bool,
+    {
+        Ok(self.capabilities()?.map_or(true, |capabilities| feature(&capabilities)))
+    }
+
+    /// Returns true unless a known data-bearing server in the topology has a maximum wire
+    /// version below `version`.
+    pub fn supports_wire_version(&self, version: i64) -> Result<bool> {
+        self.supports(|capabilities| capabilities.supports_wire_version(version))
+    }
+
+    /// Returns true if every data-bearing server in the topology supports retryable writes,
+    /// which requires sessions and wire version 6 (MongoDB 3.6) on a replica set or sharded
+    /// cluster.
+    pub fn supports_retryable_writes(&self) -> Result<bool> {
+        if self.logical_session_timeout_minutes()?.is_none() {
+            return Ok(false);
+        }
+
+        for server in self.description.read()?.servers.values() {
+            let description = server.description.read()?;
+
+            match description.server_type {
+                ServerType::Mongos |
+                ServerType::RSPrimary |
+                ServerType::RSSecondary => {
+                    if description.max_wire_version < 6 {
+                        return Ok(false);
+                    }
+                }
+                ServerType::Standalone => return Ok(false),
+                _ => (),
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Returns true if every data-bearing server in the topology supports multi-document
+    /// transactions, which requires wire version 7 (MongoDB 4.0) on a replica set or wire
+    /// version 8 (MongoDB 4.2) on a sharded cluster.
+    pub fn supports_transactions(&self) -> Result<bool> {
+        if self.logical_session_timeout_minutes()?.is_none() {
+            return Ok(false);
+        }
+
+        for server in self.description.read()?.servers.values() {
+            let description = server.description.read()?;
+
+            let min_wire_version = match description.server_type {
+                ServerType::RSPrimary | ServerType::RSSecondary => 7,
+                ServerType::Mongos => 8,
+                ServerType::Standalone => return Ok(false),
+                _ => continue,
+            };
+
+            if description.max_wire_version < min_wire_version ||
+                !self.allows_wire_version(min_wire_version)?
+            {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Returns the smallest logical session timeout reported by the data-bearing servers in
+    /// the topology, or `None` if any of them does not support sessions.
+    pub fn logical_session_timeout_minutes(&self) -> Result<Option<i64>> {
+        // Sessions were added in MongoDB 3.6.
+        if !self.allows_wire_version(6)? {
+            return Ok(None);
+        }
+
+        let mut timeout: Option<i64> = None;
+
+        for server in self.description.read()?.servers.values() {
+            let description = server.description.read()?;
+
+            match description.server_type {
+                ServerType::Standalone |
+                ServerType::Mongos |
+                ServerType::RSPrimary |
+                ServerType::RSSecondary => (),
+                _ => continue,
+            }
+
+            match description.logical_session_timeout_minutes {
+                Some(minutes) => {
+                    timeout = Some(timeout.map_or(minutes, |current| current.min(minutes)))
+                }
+                None => return Ok(None),
+            }
+        }
+
+        Ok(timeout)
+    }
 }
@@ -1,7 +1,9 @@
 //! Asynchronous server and topology discovery and monitoring using isMaster results.
-use {Client, Result};
+use {command_ok, Client, Result};
 use Error::{self, ArgumentError, OperationError};
 
+use apm::{EventRunner, SdamEvent};
+
 use bson::{self, bson, Bson, doc, oid};
 use chrono::{DateTime, Utc};
 
@@ -9,6 +11,7 @@ use coll::options::FindOptions;
 use command_type::CommandType;
 use connstring::{self, Host};
 use cursor::Cursor;
+use logging::LogLevel;
 use pool::ConnectionPool;
 use stream::StreamConnector;
 use wire_protocol::flags::OpQueryFlags;
@@ -17,15 +20,14 @@ use std::fmt;
 use std::collections::BTreeMap;
 use std::sync::{Arc, Weak, Condvar, Mutex, RwLock};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use ::{time, ClientInner};
 
+use super::capabilities::{DEFAULT_MAX_BSON_OBJECT_SIZE, DEFAULT_MAX_MESSAGE_SIZE_BYTES,
+                          DEFAULT_MAX_WRITE_BATCH_SIZE};
 use super::server::{ServerDescription, ServerType};
-use super::{DEFAULT_HEARTBEAT_FREQUENCY_MS, TopologyDescription};
-
-const DEFAULT_MAX_BSON_OBJECT_SIZE: i64 = 16 * 1024 * 1024;
-const DEFAULT_MAX_MESSAGE_SIZE_BYTES: i64 = 48000000;
+use super::{DEFAULT_HEARTBEAT_FREQUENCY_MS, TopologyDescription, TopologyInfo};
 
 /// The result of an isMaster operation.
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -34,6 +36,7 @@ pub struct IsMasterResult {
     pub is_master: bool,
     pub max_bson_object_size: i64,
     pub max_message_size_bytes: i64,
+    pub max_write_batch_size: i64,
     pub local_time: Option<DateTime<Utc>>,
     pub min_wire_version: i64,
     pub max_wire_version: i64,
@@ -56,6 +59,9 @@ pub struct IsMasterResult {
     pub primary: Option<Host>,
     pub hidden: bool,
     pub set_version: Option<i64>,
+    pub logical_session_timeout_minutes: Option<i64>,
+    /// The time of the server's last write, in milliseconds since the epoch.
+    pub last_write_date: Option<i64>,
 }
 
 /// Monitors and updates server and topology information.
@@ -98,11 +104,9 @@ impl fmt::Debug for Monitor {
 impl IsMasterResult {
     /// Parses an isMaster response document from the server.
     pub fn new(doc: bson::Document) -> Result<IsMasterResult> {
-        let ok = match doc.get("ok") {
-            Some(&Bson::I32(v)) => v != 0,
-            Some(&Bson::I64(v)) => v != 0,
-            Some(&Bson::FloatingPoint(v)) => v != 0.0,
-            _ => return Err(ArgumentError(String::from("result does not contain `ok`."))),
+        let ok = match command_ok(&doc) {
+            Some(ok) => ok,
+            None => return Err(ArgumentError(String::from("result does not contain `ok`."))),
         };
 
         let mut result = IsMasterResult {
@@ -110,6 +114,7 @@ impl IsMasterResult {
             is_master: false,
             max_bson_object_size: DEFAULT_MAX_BSON_OBJECT_SIZE,
             max_message_size_bytes: DEFAULT_MAX_MESSAGE_SIZE_BYTES,
+            max_write_batch_size: DEFAULT_MAX_WRITE_BATCH_SIZE,
             local_time: None,
             min_wire_version: -1,
             max_wire_version: -1,
@@ -127,6 +132,8 @@ impl IsMasterResult {
             primary: None,
             hidden: false,
             set_version: None,
+            logical_session_timeout_minutes: None,
+            last_write_date: None,
         };
 
         if let Some(&Bson::Boolean(b)) = doc.get("ismaster") {
@@ -137,14 +144,26 @@ impl IsMasterResult {
             result.local_time = Some(datetime);
         }
 
-        if let Some(&Bson::I64(v)) = doc.get("minWireVersion") {
+        if let Some(v) = get_integer(&doc, "minWireVersion") {
             result.min_wire_version = v;
         }
 
-        if let Some(&Bson::I64(v)) = doc.get("maxWireVersion") {
+        if let Some(v) = get_integer(&doc, "maxWireVersion") {
             result.max_wire_version = v;
         }
 
+        if let Some(v) = get_integer(&doc, "maxBsonObjectSize") {
+            result.max_bson_object_size = v;
+        }
+
+        if let Some(v) = get_integer(&doc, "maxMessageSizeBytes") {
+            result.max_message_size_bytes = v;
+        }
+
+        if let Some(v) = get_integer(&doc, "maxWriteBatchSize") {
+            result.max_write_batch_size = v;
+        }
+
         if let Some(&Bson::String(ref s)) = doc.get("msg") {
             result.msg = s.to_owned();
         }
@@ -208,6 +227,20 @@ impl IsMasterResult {
             result.set_version = Some(v);
         }
 
+        match doc.get("logicalSessionTimeoutMinutes") {
+            Some(&Bson::I32(v)) => result.logical_session_timeout_minutes = Some(v as i64),
+            Some(&Bson::I64(v)) => result.logical_session_timeout_minutes = Some(v),
+            _ => (),
+        }
+
+        if let Some(&Bson::Document(ref last_write)) = doc.get("lastWrite") {
+            if let Some(&Bson::UtcDatetime(datetime)) = last_write.get("lastWriteDate") {
+                let millis = datetime.timestamp() * 1000 +
+                    i64::from(datetime.timestamp_subsec_millis());
+                result.last_write_date = Some(millis);
+            }
+        }
+
         if let Some(&Bson::Document(ref doc)) = doc.get("tags") {
             for (k, v) in doc {
                 if let Bson::String(ref tag) = *v {
@@ -230,6 +263,16 @@ impl IsMasterResult {
     }
 }
 
+// Reads an integer field, which servers send as a 32-bit integer and test fixtures may write as
+// a 64-bit one.
+fn get_integer(doc: &bson::Document, key: &str) -> Option<i64> {
+    match doc.get(key) {
+        Some(&Bson::I32(v)) => Some(i64::from(v)),
+        Some(&Bson::I64(v)) => Some(v),
+        _ => None,
+    }
+}
+
 impl Monitor {
     /// Returns a new monitor connected to the server.
     pub fn new(
@@ -274,7 +317,17 @@ impl Monitor {
         let filter = doc!{ "isMaster": 1_i32 };
         let time_start = time::get_time();
         if let Some(client_arc) = self.client.upgrade() {
-            let mut stream = self.personal_pool.acquire_stream(client_arc.clone())?;
+            // Borrow an idle pooled socket when there is one, so that monitoring doesn't keep
+            // a second connection to the server. The dedicated socket is only used while every
+            // pooled socket is busy or none is open yet.
+            let idle_stream = self.server_pool.try_acquire_idle_stream(client_arc.max_idle_time)?;
+            let mut stream = match idle_stream {
+                Some(stream) => {
+                    self.personal_pool.clear();
+                    stream
+                }
+                None => self.personal_pool.acquire_stream(client_arc.clone())?,
+            };
 
             let cursor = Cursor::query_with_stream(
                 &mut stream,
@@ -307,6 +360,11 @@ impl Monitor {
         self.condvar.notify_one();
     }
 
+    /// Returns the number of sockets opened by the monitor in addition to the pooled ones.
+    pub fn dedicated_connections(&self) -> usize {
+        self.personal_pool.open_connections()
+    }
+
     // Updates the server description associated with this monitor using an isMaster server
     // response.
     fn update_server_description(
@@ -340,63 +398,179 @@ impl Monitor {
             return
         };
 
-        let mut top_description = top_description_arc.write().unwrap();
+        let client_arc = match self.client.upgrade() {
+            Some(client_arc) => client_arc,
+            None => return,
+        };
+
+        let (previous, new) = {
+            let mut top_description = top_description_arc.write().unwrap();
+            let previous = top_description.info();
 
-        if let Some(client_arc) = self.client.upgrade() {
             top_description.update(
                 self.host.clone(),
                 description,
-                client_arc,
+                client_arc.clone(),
                 top_description_arc.clone(),
             );
+
+            (previous, top_description.info())
+        };
+
+        // Listeners are run after releasing the lock, so that they can inspect the topology.
+        for server in &new.servers {
+            if !previous.servers.iter().any(|old| old.host == server.host) {
+                client_arc.log(LogLevel::Info, "topology", || {
+                    format!("Added server {}:{}.", server.host.host_name, server.host.port)
+                });
+                let _ = client_arc.run_sdam_listener(
+                    &SdamEvent::ServerOpened { host: server.host.clone() },
+                );
+            }
+        }
+
+        for server in &previous.servers {
+            if !new.servers.iter().any(|current| current.host == server.host) {
+                client_arc.log(LogLevel::Info, "topology", || {
+                    format!("Removed server {}:{}.", server.host.host_name, server.host.port)
+                });
+                let _ = client_arc.run_sdam_listener(
+                    &SdamEvent::ServerClosed { host: server.host.clone() },
+                );
+            }
+        }
+
+        if previous.topology_type != new.topology_type {
+            client_arc.log(LogLevel::Info, "topology", || {
+                format!(
+                    "Topology changed from {:?} to {:?}.",
+                    previous.topology_type,
+                    new.topology_type
+                )
+            });
+        }
+
+        if !same_topology(&previous, &new) {
+            let _ = client_arc.run_sdam_listener(&SdamEvent::TopologyDescriptionChanged {
+                previous_description: previous,
+                new_description: new,
+            });
         }
     }
 
-    // Updates server and topology descriptions using a successful isMaster cursor result.
-    fn update_with_is_master_cursor(&self, cursor: &mut Cursor, round_trip_time: i64) {
-        match cursor.next() {
-            Some(Ok(doc)) => {
-                if let Ok(description) = self.update_server_description(doc, round_trip_time) {
-                    self.update_top_description(description);
-                }
+    // Logs a topology record, if the client is still alive.
+    fn log<F>(&self, level: LogLevel, message: F)
+    where
+        F: FnOnce() -> String,
+    {
+        if let Some(client_arc) = self.client.upgrade() {
+            client_arc.log(level, "topology", message);
+        }
+    }
+
+    // Reports a heartbeat event, if the client is still alive.
+    fn emit(&self, event: &SdamEvent) {
+        if let Some(client_arc) = self.client.upgrade() {
+            let _ = client_arc.run_sdam_listener(event);
+        }
+    }
+
+    // Runs isMaster, reporting heartbeat events, and returns the reply with its round trip time.
+    fn heartbeat(&self) -> Result<(bson::Document, i64)> {
+        self.emit(&SdamEvent::ServerHeartbeatStarted { host: self.host.clone() });
+
+        let start = Instant::now();
+        let result = self.is_master().and_then(|(mut cursor, round_trip_time)| {
+            match cursor.next() {
+                Some(Ok(doc)) => Ok((doc, round_trip_time)),
+                Some(Err(err)) => Err(err),
+                None => Err(OperationError(String::from("ismaster returned no response."))),
             }
-            Some(Err(err)) => {
-                self.set_err(err);
+        });
+
+        let elapsed = start.elapsed();
+        let duration = elapsed.as_secs() * 1_000_000_000 + u64::from(elapsed.subsec_nanos());
+
+        match result {
+            Ok((ref doc, _)) => {
+                self.emit(&SdamEvent::ServerHeartbeatSucceeded {
+                    host: self.host.clone(),
+                    duration: duration,
+                    reply: doc.clone(),
+                })
             }
-            None => {
-                self.set_err(OperationError(
-                    String::from("ismaster returned no response."),
-                ));
+            Err(ref err) => {
+                self.emit(&SdamEvent::ServerHeartbeatFailed {
+                    host: self.host.clone(),
+                    duration: duration,
+                    failure: err,
+                })
             }
         }
+
+        result
+    }
+
+    // Updates server and topology descriptions using a successful isMaster reply.
+    fn update_with_is_master_reply(&self, doc: bson::Document, round_trip_time: i64) {
+        if let Ok(description) = self.update_server_description(doc, round_trip_time) {
+            self.update_top_description(description);
+        }
     }
 
     /// Execute isMaster and update the server and topology.
     fn execute_update(&self) {
+        let previous = self.server_description.read().unwrap().clone();
 
-        match self.is_master() {
-            Ok((mut cursor, rtt)) => {
-                self.update_with_is_master_cursor(&mut cursor, rtt);
+        match self.heartbeat() {
+            Ok((doc, rtt)) => {
+                self.update_with_is_master_reply(doc, rtt);
                 self.server_pool.prune_idle();
                 self.personal_pool.prune_idle();
             },
             Err(err) => {
+                self.log(LogLevel::Warn, || {
+                    format!(
+                        "Heartbeat to {}:{} failed: {}; closing its connections.",
+                        self.host.host_name,
+                        self.host.port,
+                        err
+                    )
+                });
+
                 // Refresh all connections
                 self.server_pool.clear();
                 self.personal_pool.clear();
 
                 if self.server_description.read().unwrap().server_type == ServerType::Unknown {
                     self.set_err(err);
-                    return;
-                }
-
-                // Retry once
-                match self.is_master() {
-                    Ok((mut cursor, rtt)) => self.update_with_is_master_cursor(&mut cursor, rtt),
-                    Err(err) => self.set_err(err),
+                } else {
+                    // Retry once
+                    match self.heartbeat() {
+                        Ok((doc, rtt)) => self.update_with_is_master_reply(doc, rtt),
+                        Err(err) => self.set_err(err),
+                    }
                 }
             }
         }
+
+        let new = self.server_description.read().unwrap().clone();
+        if !same_server_description(&previous, &new) {
+            self.log(LogLevel::Debug, || {
+                format!(
+                    "Server {}:{} is now {:?}.",
+                    self.host.host_name,
+                    self.host.port,
+                    new.server_type
+                )
+            });
+
+            self.emit(&SdamEvent::ServerDescriptionChanged {
+                host: self.host.clone(),
+                previous_description: previous,
+                new_description: new,
+            });
+        }
     }
 
     /// Starts server monitoring.
@@ -433,3 +607,33 @@ impl Monitor {
         }
     }
 }
+
+// Compares two server descriptions, ignoring round trip times and check times.
+fn same_server_description(a: &ServerDescription, b: &ServerDescription) -> bool {
+    a.server_type == b.server_type && error_message(a) == error_message(b) &&
+        a.min_wire_version == b.min_wire_version &&
+        a.max_wire_version == b.max_wire_version && a.me == b.me && a.hosts == b.hosts &&
+        a.passives == b.passives && a.arbiters == b.arbiters && a.tags == b.tags &&
+        a.set_name == b.set_name && a.election_id == b.election_id &&
+        a.primary == b.primary && a.set_version == b.set_version &&
+        a.logical_session_timeout_minutes == b.logical_session_timeout_minutes
+}
+
+// Returns the error of the last check of a server, if it failed.
+fn error_message(description: &ServerDescription) -> Option<String> {
+    match *description.err {
+        Some(ref err) => Some(err.to_string()),
+        None => None,
+    }
+}
+
+// Compares two topology snapshots, ignoring round trip times.
+fn same_topology(a: &TopologyInfo, b: &TopologyInfo) -> bool {
+    a.topology_type == b.topology_type && a.set_name == b.set_name &&
+        a.election_id == b.election_id && a.servers.len() == b.servers.len() &&
+        a.servers.iter().zip(&b.servers).all(|(a, b)| {
+            a.host == b.host && a.server_type == b.server_type &&
+                a.max_wire_version == b.max_wire_version && a.tags == b.tags &&
+                a.error == b.error
+        })
+}
@@ -4,7 +4,7 @@ use Error::{self, OperationError};
 
 use bson::oid;
 use connstring::Host;
-use pool::{ConnectionPool, PooledStream, DEFAULT_TIMEOUT_ON_IDLE};
+use pool::{ConnectionPool, ConnectionStats, PooledStream, DEFAULT_TIMEOUT_ON_IDLE};
 use stream::StreamConnector;
 
 use std::collections::BTreeMap;
@@ -13,9 +13,10 @@ use std::sync::{Arc, RwLock};
 use std::sync::atomic::Ordering;
 use std::thread;
 
+use super::capabilities::ServerCapabilities;
 use super::monitor::{IsMasterResult, Monitor};
 use super::TopologyDescription;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 /// Server round trip time is calculated as an exponentially-weighted moving
 /// averaging formula with a weighting factor. A factor of 0.2 places approximately
@@ -63,6 +64,8 @@ pub struct ServerDescription {
     pub min_wire_version: i64,
     /// The maximum wire version supported by this server.
     pub max_wire_version: i64,
+    /// The limits and wire versions reported by the server's last isMaster response.
+    pub capabilities: ServerCapabilities,
     /// The server's host information, if it is part of a replica set.
     pub me: Option<Host>,
     /// All hosts in the replica set known by this server.
@@ -81,6 +84,13 @@ pub struct ServerDescription {
     pub primary: Option<Host>,
     /// The current replica set version number.
     pub set_version: Option<i64>,
+    /// How long the server keeps idle logical sessions alive, if it supports sessions.
+    pub logical_session_timeout_minutes: Option<i64>,
+    /// When the server was last checked by its monitor, if it has been checked.
+    pub last_update_time: Option<Instant>,
+    /// The time of the server's last write, in milliseconds since the epoch, as reported by
+    /// replica set members.
+    pub last_write_date: Option<i64>,
 }
 
 /// Holds status and connection information about a single server.
@@ -128,6 +138,10 @@ impl ServerDescription {
             return;
         }
 
+        self.err = Arc::new(None);
+        self.last_update_time = Some(Instant::now());
+
+        self.capabilities = ServerCapabilities::from_is_master(&ismaster);
         self.min_wire_version = ismaster.min_wire_version;
         self.max_wire_version = ismaster.max_wire_version;
         self.me = ismaster.me;
@@ -139,6 +153,8 @@ impl ServerDescription {
         self.election_id = ismaster.election_id;
         self.primary = ismaster.primary;
         self.set_version = ismaster.set_version;
+        self.logical_session_timeout_minutes = ismaster.logical_session_timeout_minutes;
+        self.last_write_date = ismaster.last_write_date;
         self.round_trip_time = match self.round_trip_time {
             Some(old_rtt) => {
                 // (rtt / div) + (old_rtt * (div-1)/div)
@@ -175,6 +191,7 @@ impl ServerDescription {
     // Sets an encountered error and reverts the server type to Unknown.
     pub fn set_err(&mut self, err: Error) {
         self.err = Arc::new(Some(err));
+        self.last_update_time = Some(Instant::now());
         self.clear();
     }
 
@@ -246,8 +263,41 @@ impl Server {
         self.pool.acquire_stream(client)
     }
 
+    /// Returns a server stream from the connection pool, or `None` if the pool has no
+    /// connection available without waiting.
+    pub fn try_acquire_stream(&self, client: Client) -> Result<Option<PooledStream>> {
+        self.pool.try_acquire_stream(client)
+    }
+
     /// Request an update from the monitor on the server status.
     pub fn request_update(&self) {
         self.monitor.request_update();
     }
+
+    /// Closes the idle connections to the server and requests an update from the monitor.
+    pub fn reset(&self) {
+        self.pool.clear();
+        self.monitor.request_update();
+    }
+
+    /// Returns the number of connections to the server currently used by operations.
+    pub fn connections_in_use(&self) -> usize {
+        self.pool.in_use()
+    }
+
+    /// Returns the socket usage of the connection pool and monitor.
+    pub fn connection_stats(&self) -> Result<ConnectionStats> {
+        let mut stats = self.pool.stats()?;
+        stats.monitor_sockets = self.monitor.dedicated_connections();
+        stats.open += stats.monitor_sockets;
+        Ok(stats)
+    }
+
+    /// Stops monitoring the server and closes its idle connections. Connections still in use
+    /// are closed instead of being returned to the pool.
+    pub fn close(&self) {
+        self.monitor.running.store(false, Ordering::SeqCst);
+        self.monitor.request_update();
+        self.pool.clear();
+    }
 }
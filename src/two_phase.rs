@@ -0,0 +1,461 @@
+//! Two-phase commits across documents, for servers without multi-document transactions.
+//!
+//! This follows the pattern described in the MongoDB manual. A transaction document records
+//! the writes. It moves from `initial` through `pending` and `applied` to `done`, or through
+//! `canceling` to `cancelled` when it is rolled back. While the transaction is in progress,
+//! each written document holds the transaction's id in its `pendingTransactions` array. The
+//! marker makes applying and rolling back each write idempotent, so `recover_pending` can
+//! resume a transaction interrupted at any point.
+//!
+//! ```no_run
+//! # #[macro_use] extern crate bson;
+//! # extern crate mongodb;
+//! #
+//! # use mongodb::{Client, ThreadedClient};
+//! # use mongodb::db::ThreadedDatabase;
+//! # use mongodb::two_phase::{TwoPhaseCommit, TwoPhaseOp};
+//! # use bson::Bson;
+//! # use std::time::Duration;
+//! # fn main() {
+//! # let client = Client::connect("localhost", 27017).unwrap();
+//! let db = client.db("bank");
+//! let commit = TwoPhaseCommit::new(db.collection("transactions"));
+//!
+//! let amount = |op: &TwoPhaseOp| op.data.get_i32("amount").unwrap_or(0);
+//! let ops = vec![
+//!     TwoPhaseOp::new("accounts", doc! { "_id": "A" }, doc! { "amount": -100 }),
+//!     TwoPhaseOp::new("accounts", doc! { "_id": "B" }, doc! { "amount": 100 }),
+//! ];
+//!
+//! let apply = |_id: &Bson, op: &TwoPhaseOp| Ok(doc! { "$inc": { "balance": amount(op) } });
+//! let roll_back = |_id: &Bson, op: &TwoPhaseOp| Ok(doc! { "$inc": { "balance": -amount(op) } });
+//! commit.apply(ops, &apply, &roll_back).unwrap();
+//!
+//! // After a crash, complete or undo the transactions left behind.
+//! commit.recover_pending(Duration::from_secs(60), &apply, &roll_back).unwrap();
+//! # }
+//! ```
+use bson::{self, oid, Bson, bson, doc};
+use chrono::{self, Utc};
+
+use {Error, Result};
+use Error::{ArgumentError, OperationError, ResponseError, WriteError};
+use coll::Collection;
+use coll::results::UpdateResult;
+use db::ThreadedDatabase;
+
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The array field marking the documents written by transactions in progress.
+pub const PENDING_TRANSACTIONS_FIELD: &str = "pendingTransactions";
+
+/// The states a transaction document moves through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TransactionState {
+    /// Recorded, with none of its writes applied.
+    Initial,
+    /// Its writes are being applied.
+    Pending,
+    /// Its writes are applied, and their markers are being removed.
+    Applied,
+    /// Committed.
+    Done,
+    /// Its writes are being rolled back.
+    Canceling,
+    /// Rolled back.
+    Cancelled,
+}
+
+impl TransactionState {
+    /// Returns the name of the state stored in transaction documents.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            TransactionState::Initial => "initial",
+            TransactionState::Pending => "pending",
+            TransactionState::Applied => "applied",
+            TransactionState::Done => "done",
+            TransactionState::Canceling => "canceling",
+            TransactionState::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl FromStr for TransactionState {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "initial" => TransactionState::Initial,
+            "pending" => TransactionState::Pending,
+            "applied" => TransactionState::Applied,
+            "done" => TransactionState::Done,
+            "canceling" => TransactionState::Canceling,
+            "cancelled" => TransactionState::Cancelled,
+            _ => {
+                return Err(ArgumentError(
+                    format!("Could not convert '{}' to TransactionState.", s),
+                ))
+            }
+        })
+    }
+}
+
+/// A write to a single document, applied as part of a transaction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TwoPhaseOp {
+    /// The collection written to, in the database of the transaction collection.
+    pub collection: String,
+    /// Selects the document written to. The writes must not change the fields it matches,
+    /// since the same filter finds the document again to remove the marker or roll back.
+    pub filter: bson::Document,
+    /// Describes the write to the callbacks, and is recorded in the transaction document so
+    /// that the write can be redone or undone during recovery.
+    pub data: bson::Document,
+}
+
+impl TwoPhaseOp {
+    pub fn new(collection: &str, filter: bson::Document, data: bson::Document) -> TwoPhaseOp {
+        TwoPhaseOp {
+            collection: String::from(collection),
+            filter: filter,
+            data: data,
+        }
+    }
+
+    fn to_document(&self) -> bson::Document {
+        doc! {
+            "collection": &self.collection,
+            "filter": self.filter.clone(),
+            "data": self.data.clone(),
+        }
+    }
+
+    fn from_document(doc: &bson::Document) -> Result<TwoPhaseOp> {
+        match (doc.get_str("collection"), doc.get_document("filter"), doc.get_document("data")) {
+            (Ok(collection), Ok(filter), Ok(data)) => {
+                Ok(TwoPhaseOp::new(collection, filter.clone(), data.clone()))
+            }
+            _ => Err(ResponseError(format!("Invalid two-phase commit operation: {}", doc))),
+        }
+    }
+}
+
+/// The transactions completed or rolled back by `TwoPhaseCommit::recover_pending`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RecoveryReport {
+    pub rolled_forward: Vec<Bson>,
+    pub rolled_back: Vec<Bson>,
+}
+
+// How an attempt to apply a transaction's writes ended.
+enum Outcome {
+    Committed,
+    RolledBack(Error),
+}
+
+/// Applies writes to several documents so that either all or none of them persist, recording
+/// each transaction in a collection of its own.
+///
+/// The callbacks turn an operation into the update applied to its document: `on_apply` the
+/// write itself, and `on_rollback` its inverse. They receive the id of the transaction and must
+/// only return update operators. The marker is added to and removed from the update by the
+/// commit, and both may be called again for the same operation during recovery.
+#[derive(Debug)]
+pub struct TwoPhaseCommit {
+    txn_coll: Collection,
+}
+
+impl TwoPhaseCommit {
+    /// Records transactions in `txn_coll`; the written collections are looked up in its
+    /// database.
+    pub fn new(txn_coll: Collection) -> TwoPhaseCommit {
+        TwoPhaseCommit { txn_coll: txn_coll }
+    }
+
+    /// Returns the collection transactions are recorded in.
+    pub fn collection(&self) -> &Collection {
+        &self.txn_coll
+    }
+
+    /// Applies every operation, and returns the id of the committed transaction. If an
+    /// operation fails, the ones already applied are rolled back and the error is returned.
+    pub fn apply<F, G>(&self, ops: Vec<TwoPhaseOp>, on_apply: F, on_rollback: G) -> Result<Bson>
+    where
+        F: Fn(&Bson, &TwoPhaseOp) -> Result<bson::Document>,
+        G: Fn(&Bson, &TwoPhaseOp) -> Result<bson::Document>,
+    {
+        if ops.is_empty() {
+            return Err(ArgumentError(
+                String::from("A two-phase commit must contain at least one operation."),
+            ));
+        }
+
+        let id = Bson::ObjectId(oid::ObjectId::new()?);
+        let txn = doc! {
+            "_id": id.clone(),
+            "state": TransactionState::Initial.as_str(),
+            "ops": ops.iter().map(|op| Bson::Document(op.to_document())).collect::<Vec<_>>(),
+            "lastModified": Bson::UtcDatetime(Utc::now()),
+        };
+
+        let result = self.txn_coll.insert_one(txn, None)?;
+        if let Some(exception) = result.write_exception {
+            return Err(WriteError(exception));
+        }
+
+        self.transition(&id, TransactionState::Initial, TransactionState::Pending)?;
+
+        match self.roll_forward(&id, &ops, &on_apply, &on_rollback)? {
+            Outcome::Committed => Ok(id),
+            Outcome::RolledBack(err) => Err(err),
+        }
+    }
+
+    /// Returns the state of a transaction, or None if it isn't recorded.
+    pub fn state(&self, id: &Bson) -> Result<Option<TransactionState>> {
+        match self.txn_coll.find_one_by_id(id.clone(), None)? {
+            Some(txn) => TwoPhaseCommit::parse_state(&txn).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Finishes the transactions that were last modified longer than `older_than` ago and
+    /// haven't completed, e.g. because the process applying them stopped. Transactions whose
+    /// writes were being applied are completed, and those that were being rolled back or hadn't
+    /// started are rolled back.
+    ///
+    /// Transactions still in progress elsewhere must not be recovered, so `older_than` should
+    /// exceed the time any transaction takes.
+    pub fn recover_pending<F, G>(
+        &self,
+        older_than: Duration,
+        on_apply: F,
+        on_rollback: G,
+    ) -> Result<RecoveryReport>
+    where
+        F: Fn(&Bson, &TwoPhaseOp) -> Result<bson::Document>,
+        G: Fn(&Bson, &TwoPhaseOp) -> Result<bson::Document>,
+    {
+        let older_than = chrono::Duration::from_std(older_than)
+            .map_err(|_| ArgumentError(String::from("The recovery threshold is too long.")))?;
+
+        let unfinished: Vec<_> = [
+            TransactionState::Initial,
+            TransactionState::Pending,
+            TransactionState::Applied,
+            TransactionState::Canceling,
+        ].iter()
+            .map(|state| Bson::String(String::from(state.as_str())))
+            .collect();
+
+        let filter = doc! {
+            "state": { "$in": unfinished },
+            "lastModified": { "$lt": Bson::UtcDatetime(Utc::now() - older_than) },
+        };
+
+        let txns = self.txn_coll.find(Some(filter), None)?.collect::<Result<Vec<_>>>()?;
+        let mut report = RecoveryReport::default();
+
+        for txn in txns {
+            let id = match txn.get("_id") {
+                Some(id) => id.clone(),
+                None => return Err(ResponseError(String::from("Transaction without an _id."))),
+            };
+
+            let ops = match txn.get("ops") {
+                Some(&Bson::Array(ref ops)) => {
+                    ops.iter()
+                        .map(|op| match *op {
+                            Bson::Document(ref op) => TwoPhaseOp::from_document(op),
+                            _ => Err(ResponseError(format!("Invalid transaction {}.", id))),
+                        })
+                        .collect::<Result<Vec<_>>>()?
+                }
+                _ => return Err(ResponseError(format!("Transaction {} has no ops.", id))),
+            };
+
+            match TwoPhaseCommit::parse_state(&txn)? {
+                TransactionState::Initial => {
+                    self.transition(&id, TransactionState::Initial, TransactionState::Canceling)?;
+                    self.roll_back(&id, &ops, &on_rollback)?;
+                    report.rolled_back.push(id);
+                }
+                TransactionState::Pending => {
+                    match self.roll_forward(&id, &ops, &on_apply, &on_rollback)? {
+                        Outcome::Committed => report.rolled_forward.push(id),
+                        Outcome::RolledBack(_) => report.rolled_back.push(id),
+                    }
+                }
+                TransactionState::Applied => {
+                    self.complete(&id, &ops)?;
+                    report.rolled_forward.push(id);
+                }
+                TransactionState::Canceling => {
+                    self.roll_back(&id, &ops, &on_rollback)?;
+                    report.rolled_back.push(id);
+                }
+                TransactionState::Done | TransactionState::Cancelled => (),
+            }
+        }
+
+        Ok(report)
+    }
+
+    // Applies every write of a pending transaction and commits it, or rolls it back if a
+    // write fails.
+    fn roll_forward<F, G>(
+        &self,
+        id: &Bson,
+        ops: &[TwoPhaseOp],
+        on_apply: &F,
+        on_rollback: &G,
+    ) -> Result<Outcome>
+    where
+        F: Fn(&Bson, &TwoPhaseOp) -> Result<bson::Document>,
+        G: Fn(&Bson, &TwoPhaseOp) -> Result<bson::Document>,
+    {
+        for op in ops {
+            if let Err(err) = self.apply_op(id, op, on_apply) {
+                self.transition(id, TransactionState::Pending, TransactionState::Canceling)?;
+                self.roll_back(id, ops, on_rollback)?;
+                return Ok(Outcome::RolledBack(err));
+            }
+        }
+
+        self.transition(id, TransactionState::Pending, TransactionState::Applied)?;
+        self.complete(id, ops)?;
+        Ok(Outcome::Committed)
+    }
+
+    // Removes the markers of an applied transaction and marks it done.
+    fn complete(&self, id: &Bson, ops: &[TwoPhaseOp]) -> Result<()> {
+        for op in ops {
+            let mut filter = op.filter.clone();
+            filter.insert(PENDING_TRANSACTIONS_FIELD, id.clone());
+
+            let update = doc! { "$pull": { PENDING_TRANSACTIONS_FIELD: id.clone() } };
+            self.write(op, filter, update)?;
+        }
+
+        self.transition(id, TransactionState::Applied, TransactionState::Done)
+    }
+
+    // Undoes the writes of a canceling transaction that were applied, and marks it cancelled.
+    fn roll_back<G>(&self, id: &Bson, ops: &[TwoPhaseOp], on_rollback: &G) -> Result<()>
+    where
+        G: Fn(&Bson, &TwoPhaseOp) -> Result<bson::Document>,
+    {
+        for op in ops {
+            let mut update = on_rollback(id, op)?;
+            add_marker_operator(&mut update, "$pull", id)?;
+
+            // Only documents holding the marker were written by the transaction.
+            let mut filter = op.filter.clone();
+            filter.insert(PENDING_TRANSACTIONS_FIELD, id.clone());
+            self.write(op, filter, update)?;
+        }
+
+        self.transition(id, TransactionState::Canceling, TransactionState::Cancelled)
+    }
+
+    // Applies a single write, unless an earlier attempt already did.
+    fn apply_op<F>(&self, id: &Bson, op: &TwoPhaseOp, on_apply: &F) -> Result<()>
+    where
+        F: Fn(&Bson, &TwoPhaseOp) -> Result<bson::Document>,
+    {
+        let mut update = on_apply(id, op)?;
+        add_marker_operator(&mut update, "$push", id)?;
+
+        let mut filter = op.filter.clone();
+        filter.insert(PENDING_TRANSACTIONS_FIELD, doc! { "$ne": id.clone() });
+
+        if self.write(op, filter, update)?.matched_count > 0 {
+            return Ok(());
+        }
+
+        let mut applied = op.filter.clone();
+        applied.insert(PENDING_TRANSACTIONS_FIELD, id.clone());
+
+        let coll = self.txn_coll.db.get_collection(&op.collection)?;
+        match coll.find_one(Some(applied), None)? {
+            Some(_) => Ok(()),
+            None => Err(OperationError(format!(
+                "No document in {} matches {} for transaction {}.",
+                coll.namespace,
+                op.filter,
+                id
+            ))),
+        }
+    }
+
+    fn write(
+        &self,
+        op: &TwoPhaseOp,
+        filter: bson::Document,
+        update: bson::Document,
+    ) -> Result<UpdateResult> {
+        let coll = self.txn_coll.db.get_collection(&op.collection)?;
+        let result = coll.update_one(filter, update, None)?;
+
+        match result.write_exception {
+            Some(exception) => Err(WriteError(exception)),
+            None => Ok(result),
+        }
+    }
+
+    // Moves a transaction from one state to the next, failing if it is no longer in the
+    // expected state, e.g. because another process recovered it.
+    fn transition(&self, id: &Bson, from: TransactionState, to: TransactionState) -> Result<()> {
+        let filter = doc! { "_id": id.clone(), "state": from.as_str() };
+        let update = doc! {
+            "$set": { "state": to.as_str() },
+            "$currentDate": { "lastModified": true },
+        };
+
+        let result = self.txn_coll.update_one(filter, update, None)?;
+        if let Some(exception) = result.write_exception {
+            return Err(WriteError(exception));
+        }
+
+        if result.matched_count == 0 {
+            return Err(OperationError(
+                format!("Transaction {} is no longer {}.", id, from.as_str()),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn parse_state(txn: &bson::Document) -> Result<TransactionState> {
+        match txn.get_str("state").map(str::parse) {
+            Ok(Ok(state)) => Ok(state),
+            _ => Err(ResponseError(format!("Transaction with an invalid state: {}", txn))),
+        }
+    }
+}
+
+// Adds the transaction's marker to an update under the given operator.
+fn add_marker_operator(update: &mut bson::Document, operator: &str, id: &Bson) -> Result<()> {
+    if update.keys().any(|key| !key.starts_with('$')) {
+        return Err(ArgumentError(
+            String::from("Two-phase commit updates must only contain update operators."),
+        ));
+    }
+
+    let mut fields = match update.remove(operator) {
+        Some(Bson::Document(fields)) => fields,
+        Some(_) => return Err(ArgumentError(format!("Invalid {} in update.", operator))),
+        None => bson::Document::new(),
+    };
+
+    if fields.contains_key(PENDING_TRANSACTIONS_FIELD) {
+        return Err(ArgumentError(format!(
+            "Two-phase commit updates must not change {}.",
+            PENDING_TRANSACTIONS_FIELD
+        )));
+    }
+
+    fields.insert(PENDING_TRANSACTIONS_FIELD, id.clone());
+    update.insert(operator, fields);
+    Ok(())
+}
@@ -0,0 +1,100 @@
+//! Constructors for BSON binary values of each subtype.
+//!
+//! The subtype only tells readers how to interpret the bytes; the server stores and compares
+//! them alike. The deprecated old binary subtype is the exception, as its bytes are preceded by
+//! their own length within the value.
+use bson::Bson;
+use bson::spec::BinarySubtype;
+use byteorder::{ByteOrder, LittleEndian};
+use Error::ArgumentError;
+use Result;
+
+/// The length of an MD5 digest in bytes.
+pub const MD5_LENGTH: usize = 16;
+
+/// The lowest subtype set aside for user-defined binary data.
+pub const USER_DEFINED_MIN: u8 = 0x80;
+
+/// Returns a value holding arbitrary bytes.
+pub fn generic(bytes: Vec<u8>) -> Bson {
+    Bson::Binary(BinarySubtype::Generic, bytes)
+}
+
+/// Returns a value holding compiled code.
+pub fn function(bytes: Vec<u8>) -> Bson {
+    Bson::Binary(BinarySubtype::Function, bytes)
+}
+
+/// Returns a value of the deprecated old binary subtype, which older drivers may still expect,
+/// with the length prefix the subtype requires before the bytes.
+pub fn old_binary(bytes: Vec<u8>) -> Bson {
+    let mut data = vec![0; 4];
+    LittleEndian::write_i32(&mut data, bytes.len() as i32);
+    data.extend(bytes);
+    Bson::Binary(BinarySubtype::BinaryOld, data)
+}
+
+/// Returns a value holding an MD5 digest, which must be `MD5_LENGTH` bytes long.
+pub fn md5(digest: Vec<u8>) -> Result<Bson> {
+    if digest.len() != MD5_LENGTH {
+        return Err(ArgumentError(format!(
+            "an MD5 digest is {} bytes long, not {}",
+            MD5_LENGTH,
+            digest.len()
+        )));
+    }
+
+    Ok(Bson::Binary(BinarySubtype::Md5, digest))
+}
+
+/// Returns a value of an application-specific subtype, which must be at least
+/// `USER_DEFINED_MIN`; the lower subtypes are reserved.
+pub fn user_defined(subtype: u8, bytes: Vec<u8>) -> Result<Bson> {
+    if subtype < USER_DEFINED_MIN {
+        return Err(ArgumentError(format!(
+            "binary subtype {:#x} is reserved; user-defined subtypes start at {:#x}",
+            subtype,
+            USER_DEFINED_MIN
+        )));
+    }
+
+    Ok(Bson::Binary(BinarySubtype::UserDefined(subtype), bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use bson::{self, bson, doc, Bson};
+    use bson::spec::BinarySubtype;
+    use super::{function, generic, md5, old_binary, user_defined};
+
+    use std::io::Cursor;
+
+    #[test]
+    fn values_have_their_subtype() {
+        assert_eq!(generic(vec![1]), Bson::Binary(BinarySubtype::Generic, vec![1]));
+        assert_eq!(function(vec![1]), Bson::Binary(BinarySubtype::Function, vec![1]));
+        assert_eq!(
+            md5(vec![0; 16]).unwrap(),
+            Bson::Binary(BinarySubtype::Md5, vec![0; 16])
+        );
+        assert_eq!(
+            user_defined(0x80, vec![1]).unwrap(),
+            Bson::Binary(BinarySubtype::UserDefined(0x80), vec![1])
+        );
+
+        assert!(md5(vec![0; 15]).is_err());
+        assert!(user_defined(0x05, vec![1]).is_err());
+    }
+
+    #[test]
+    fn old_binary_is_length_prefixed() {
+        let value = old_binary(vec![7, 8, 9]);
+        assert_eq!(value, Bson::Binary(BinarySubtype::BinaryOld, vec![3, 0, 0, 0, 7, 8, 9]));
+
+        // The subtypes survive a round trip through the encoder.
+        let doc = doc! { "old": value, "udef": user_defined(0xff, vec![1]).unwrap() };
+        let mut bytes = Vec::new();
+        bson::encode_document(&mut bytes, &doc).unwrap();
+        assert_eq!(bson::decode_document(&mut Cursor::new(bytes)).unwrap(), doc);
+    }
+}
@@ -25,6 +25,13 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// Represents the bit vector of flags for an OP_DELETE message.
+    pub struct OpDeleteFlags: i32 {
+        const SINGLE_REMOVE = 0b00000001;
+    }
+}
+
 bitflags! {
     /// Represents the bit vector of flags for an OP_QUERY message.
     pub struct OpQueryFlags: i32 {
@@ -68,6 +75,10 @@ impl OpQueryFlags {
             flags.insert(Self::AWAIT_DATA);
         }
 
+        if options.exhaust {
+            flags.insert(Self::EXHAUST);
+        }
+
         if options.allow_partial_results {
             flags.insert(Self::PARTIAL);
         }
@@ -15,6 +15,8 @@ pub enum OpCode {
     Insert = 2002,
     Query = 2004,
     GetMore = 2005,
+    Delete = 2006,
+    KillCursors = 2007,
 }
 
 impl OpCode {
@@ -35,6 +37,8 @@ impl OpCode {
             2002 => Some(OpCode::Insert),
             2004 => Some(OpCode::Query),
             2005 => Some(OpCode::GetMore),
+            2006 => Some(OpCode::Delete),
+            2007 => Some(OpCode::KillCursors),
             _ => None,
         }
     }
@@ -48,6 +52,8 @@ impl fmt::Display for OpCode {
             OpCode::Insert => fmt.write_str("OP_INSERT"),
             OpCode::Query => fmt.write_str("OP_QUERY"),
             OpCode::GetMore => fmt.write_str("OP_GET_MORE"),
+            OpCode::Delete => fmt.write_str("OP_DELETE"),
+            OpCode::KillCursors => fmt.write_str("OP_KILL_CURSORS"),
         }
     }
 }
@@ -77,6 +83,11 @@ impl Header {
         }
     }
 
+    /// Returns the id of the request that this message is a response to.
+    pub fn response_to(&self) -> i32 {
+        self.response_to
+    }
+
     /// Constructs a new Header for a request, with `response_to` set to 0.
     fn new_request(message_length: i32, request_id: i32, op_code: OpCode) -> Header {
         Header::new(message_length, request_id, 0, op_code)
@@ -106,6 +117,18 @@ impl Header {
         Header::new_request(message_length, request_id, OpCode::GetMore)
     }
 
+    /// Constructs a new Header for an OP_DELETE, with `response_to` set to 0 and
+    /// `op_code` set to `Delete`.
+    pub fn new_delete(message_length: i32, request_id: i32) -> Header {
+        Header::new_request(message_length, request_id, OpCode::Delete)
+    }
+
+    /// Constructs a new Header for an OP_KILL_CURSORS, with `response_to` set to 0 and
+    /// `op_code` set to `KillCursors`.
+    pub fn new_kill_cursors(message_length: i32, request_id: i32) -> Header {
+        Header::new_request(message_length, request_id, OpCode::KillCursors)
+    }
+
     /// Writes the serialized Header to a buffer.
     ///
     /// # Arguments
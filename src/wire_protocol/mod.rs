@@ -1,5 +1,8 @@
 //! Low-level client-server communication over the MongoDB wire protocol.
 
 mod header;
+pub mod binary;
 pub mod flags;
 pub mod operations;
+pub mod raw;
+pub mod validation;
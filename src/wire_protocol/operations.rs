@@ -1,16 +1,30 @@
 //! Wire protocol operational client-server communication logic.
 use bson;
-use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
-use Error::{ArgumentError, ResponseError};
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+use Error::{ArgumentError, ProtocolError, ResponseError};
+use logging::redact;
 use Result;
 use wire_protocol::header::{Header, OpCode};
-use wire_protocol::flags::{OpInsertFlags, OpQueryFlags, OpReplyFlags, OpUpdateFlags};
+use wire_protocol::flags::{OpDeleteFlags, OpInsertFlags, OpQueryFlags, OpReplyFlags,
+                           OpUpdateFlags};
+use wire_protocol::validation::{validate_document, DEFAULT_MAX_BSON_DEPTH};
 
-use std::io::{Read, Write};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
 use std::mem;
+use std::ops::Range;
 use std::result::Result::{Ok, Err};
 
-trait ByteLength {
+/// The number of replies to other requests that are discarded while waiting for the reply to a
+/// request before giving up on the connection.
+pub const MAX_STRAY_REPLIES: usize = 8;
+
+/// The largest reply accepted from a server, which matches the largest message a server sends.
+/// Longer replies are rejected before anything is allocated for them.
+pub const MAX_REPLY_LENGTH: i32 = 48_000_000;
+
+/// Computes the size of a value once serialized to BSON, without serializing it.
+pub trait ByteLength {
     /// Calculates the number of bytes in the serialized version of the struct.
     fn byte_length(&self) -> Result<i32>;
 }
@@ -131,6 +145,159 @@ mod byte_length_test {
 }
 
 
+/// The documents of a reply, kept serialized until they are taken so that callers only pay for
+/// decoding the documents they actually use.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReplyDocuments {
+    bytes: Vec<u8>,
+    // The byte ranges of the documents that haven't been taken yet.
+    ranges: VecDeque<Range<usize>>,
+}
+
+impl ReplyDocuments {
+    /// Splits a buffer of consecutive serialized documents nested at most
+    /// `DEFAULT_MAX_BSON_DEPTH` levels deep; see `with_max_depth`.
+    pub fn new(bytes: Vec<u8>) -> Result<ReplyDocuments> {
+        ReplyDocuments::with_max_depth(bytes, DEFAULT_MAX_BSON_DEPTH)
+    }
+
+    /// Splits a buffer of consecutive serialized documents. Every document is checked up front
+    /// with `validation::validate_document`, so a corrupt reply is rejected before any document
+    /// is returned, and the documents that are returned decode safely.
+    pub fn with_max_depth(bytes: Vec<u8>, max_depth: usize) -> Result<ReplyDocuments> {
+        let mut ranges = VecDeque::new();
+        let mut start = 0;
+
+        while start < bytes.len() {
+            let remaining = bytes.len() - start;
+            if remaining < 5 {
+                return Err(ResponseError(
+                    format!("Reply has a truncated document at offset {}.", start),
+                ));
+            }
+
+            let length = LittleEndian::read_i32(&bytes[start..start + 4]);
+            if length < 5 || length as usize > remaining {
+                return Err(ResponseError(format!(
+                    "Reply has a document with invalid length {} at offset {}.",
+                    length,
+                    start
+                )));
+            }
+
+            let end = start + length as usize;
+            if bytes[end - 1] != 0 {
+                return Err(ResponseError(
+                    format!("Reply has an unterminated document at offset {}.", start),
+                ));
+            }
+
+            validate_document(&bytes[start..end], max_depth)?;
+            ranges.push_back(start..end);
+            start = end;
+        }
+
+        Ok(ReplyDocuments {
+            bytes: bytes,
+            ranges: ranges,
+        })
+    }
+
+    /// Returns the number of documents that haven't been taken yet.
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// Returns whether all documents have been taken.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Decodes every document that hasn't been taken yet, without taking them.
+    pub fn to_documents(&self) -> Result<Vec<bson::Document>> {
+        self.ranges.iter().map(|range| self.decode(range)).collect()
+    }
+
+    fn decode(&self, range: &Range<usize>) -> Result<bson::Document> {
+        let mut bytes = &self.bytes[range.start..range.end];
+        Ok(bson::decode_document(&mut bytes)?)
+    }
+}
+
+impl Iterator for ReplyDocuments {
+    type Item = Result<bson::Document>;
+
+    /// Takes and decodes the next document.
+    fn next(&mut self) -> Option<Result<bson::Document>> {
+        let range = self.ranges.pop_front()?;
+        Some(self.decode(&range))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len(), Some(self.len()))
+    }
+}
+
+#[cfg(test)]
+mod reply_documents_test {
+    use bson::{self, bson, doc};
+    use Error::ResponseError;
+    use super::ReplyDocuments;
+
+    fn encode(docs: &[bson::Document]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for doc in docs {
+            bson::encode_document(&mut bytes, doc).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_lazy_decoding() {
+        let docs = vec![doc! { "_id": 1 }, doc! { "_id": 2, "x": "y" }, doc! {}];
+        let mut reply = ReplyDocuments::new(encode(&docs)).unwrap();
+
+        assert_eq!(reply.len(), 3);
+        assert_eq!(reply.next().unwrap().unwrap(), docs[0]);
+        assert_eq!(reply.to_documents().unwrap(), docs[1..].to_vec());
+        assert_eq!(reply.len(), 2);
+        assert_eq!(reply.collect::<Result<Vec<_>, _>>().unwrap(), docs[1..].to_vec());
+
+        assert!(ReplyDocuments::new(Vec::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_corrupt_length_prefix() {
+        let docs = vec![doc! { "_id": 1 }, doc! { "_id": 2 }];
+        let valid = encode(&docs);
+        let second = valid.len() / 2;
+
+        // Overruns the buffer.
+        let mut bytes = valid.clone();
+        bytes[second] = 100;
+        match ReplyDocuments::new(bytes) {
+            Err(ResponseError(_)) => (),
+            other => panic!("Expected ResponseError, got {:?}", other),
+        }
+
+        // Shorter than an empty document.
+        let mut bytes = valid.clone();
+        bytes[second] = 4;
+        assert!(ReplyDocuments::new(bytes).is_err());
+
+        // Ends in the middle of the second document.
+        let mut bytes = valid.clone();
+        bytes[0] += 1;
+        assert!(ReplyDocuments::new(bytes).is_err());
+
+        // Trailing bytes too short to hold a document.
+        let mut bytes = valid.clone();
+        bytes.extend_from_slice(&[5, 0, 0]);
+        assert!(ReplyDocuments::new(bytes).is_err());
+    }
+}
+
+
 /// Represents a message in the MongoDB Wire Protocol.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Message {
@@ -146,7 +313,7 @@ pub enum Message {
         /// The total number of documents being returned.
         number_returned: i32,
         /// The documents being returned.
-        documents: Vec<bson::Document>,
+        documents: ReplyDocuments,
     },
     OpUpdate {
         /// The message header.
@@ -191,6 +358,22 @@ pub enum Message {
         /// documents to be returned by the query.
         return_field_selector: Option<bson::Document>,
     },
+    /// A query whose document was encoded beforehand, e.g. a command holding raw documents.
+    OpQueryRaw {
+        /// The message header.
+        header: Header,
+        /// A bit vector of query options.
+        flags: OpQueryFlags,
+        /// The full qualified name of the collection, beginning with the
+        /// database name and a dot separator.
+        namespace: String,
+        /// The number of initial documents to skip over in the query results.
+        number_to_skip: i32,
+        /// The total number of documents that should be returned by the query.
+        number_to_return: i32,
+        /// The encoded query document.
+        query: Vec<u8>,
+    },
     OpGetMore {
         /// The message header.
         header: Header,
@@ -203,6 +386,25 @@ pub enum Message {
         /// Uniquely identifies the cursor being returned.
         cursor_id: i64,
     },
+    OpDelete {
+        /// The message header.
+        header: Header,
+        // The wire protocol specifies that a 32-bit 0 field goes here
+        /// The full qualified name of the collection, beginning with the
+        /// database name and a dot separator.
+        namespace: String,
+        /// A bit vector of delete options.
+        flags: OpDeleteFlags,
+        /// Identifies the document(s) to be removed.
+        selector: bson::Document,
+    },
+    OpKillCursors {
+        /// The message header.
+        header: Header,
+        // The wire protocol specifies that a 32-bit 0 field goes here
+        /// The cursors to close, which may belong to different collections.
+        cursor_ids: Vec<i64>,
+    },
 }
 
 impl Message {
@@ -213,7 +415,7 @@ impl Message {
         cursor_id: i64,
         starting_from: i32,
         number_returned: i32,
-        documents: Vec<bson::Document>,
+        documents: ReplyDocuments,
     ) -> Message {
         Message::OpReply {
             header: header,
@@ -331,6 +533,30 @@ impl Message {
         })
     }
 
+    /// Constructs a new message request for a query whose document is already encoded.
+    pub fn new_query_raw(
+        request_id: i32,
+        flags: OpQueryFlags,
+        namespace: String,
+        number_to_skip: i32,
+        number_to_return: i32,
+        query: Vec<u8>,
+    ) -> Message {
+        let header_length = mem::size_of::<Header>() as i32;
+        let i32_length = 3 * mem::size_of::<i32>() as i32;
+        let string_length = namespace.len() as i32 + 1;
+        let total_length = header_length + i32_length + string_length + query.len() as i32;
+
+        Message::OpQueryRaw {
+            header: Header::new_query(total_length, request_id),
+            flags: flags,
+            namespace: namespace,
+            number_to_skip: number_to_skip,
+            number_to_return: number_to_return,
+            query: query,
+        }
+    }
+
     /// Constructs a new "get more" request message.
     pub fn new_get_more(
         request_id: i32,
@@ -359,6 +585,51 @@ impl Message {
         }
     }
 
+    /// Constructs a new message for a deletion. The server doesn't reply to it.
+    pub fn new_delete(
+        request_id: i32,
+        namespace: String,
+        flags: OpDeleteFlags,
+        selector: bson::Document,
+    ) -> Result<Message> {
+        let header_length = mem::size_of::<Header>() as i32;
+
+        // Add an extra byte after the string for null-termination.
+        let string_length = namespace.len() as i32 + 1;
+
+        // There are two i32 fields -- the wire protocol-specified ZERO field, and `flags`.
+        let i32_length = mem::size_of::<i32>() as i32 * 2;
+
+        let total_length = header_length + string_length + i32_length + selector.byte_length()?;
+
+        let header = Header::new_delete(total_length, request_id);
+
+        Ok(Message::OpDelete {
+            header: header,
+            namespace: namespace,
+            flags: flags,
+            selector: selector,
+        })
+    }
+
+    /// Constructs a new message closing server-side cursors. The server doesn't reply to it.
+    pub fn new_kill_cursors(request_id: i32, cursor_ids: Vec<i64>) -> Message {
+        let header_length = mem::size_of::<Header>() as i32;
+
+        // The reserved "ZERO" is followed by the number of cursors.
+        let i32_length = 2 * mem::size_of::<i32>() as i32;
+
+        let i64_length = (cursor_ids.len() * mem::size_of::<i64>()) as i32;
+        let total_length = header_length + i32_length + i64_length;
+
+        let header = Header::new_kill_cursors(total_length, request_id);
+
+        Message::OpKillCursors {
+            header: header,
+            cursor_ids: cursor_ids,
+        }
+    }
+
     /// Writes a serialized BSON document to a given buffer.
     ///
     /// # Arguments
@@ -370,11 +641,7 @@ impl Message {
     ///
     /// Returns nothing on success, or an Error on failure.
     fn write_bson_document<W: Write>(buffer: &mut W, bson: &bson::Document) -> Result<()> {
-        let mut temp_buffer = Vec::new();
-
-        bson::encode_document(&mut temp_buffer, bson)?;
-        buffer.write_all(&temp_buffer)?;
-
+        bson::encode_document(buffer, bson)?;
         Ok(())
     }
 
@@ -419,7 +686,6 @@ impl Message {
         Message::write_bson_document(buffer, selector)?;
         Message::write_bson_document(buffer, update)?;
 
-        let _ = buffer.flush();
         Ok(())
     }
 
@@ -459,7 +725,6 @@ impl Message {
             Message::write_bson_document(buffer, doc)?;
         }
 
-        let _ = buffer.flush();
         Ok(())
     }
 
@@ -513,7 +778,34 @@ impl Message {
             Message::write_bson_document(buffer, doc)?;
         }
 
-        let _ = buffer.flush();
+        Ok(())
+    }
+
+    /// Writes a query message whose query document is already encoded to a given buffer.
+    fn write_query_raw<W: Write>(
+        buffer: &mut W,
+        header: &Header,
+        flags: &OpQueryFlags,
+        namespace: &str,
+        number_to_skip: i32,
+        number_to_return: i32,
+        query: &[u8],
+    ) -> Result<()> {
+
+        header.write(buffer)?;
+        buffer.write_i32::<LittleEndian>(flags.bits())?;
+
+        for byte in namespace.bytes() {
+            buffer.write_u8(byte)?;
+        }
+
+        // Writes the null terminator for the collection name string.
+        buffer.write_u8(0)?;
+
+        buffer.write_i32::<LittleEndian>(number_to_skip)?;
+        buffer.write_i32::<LittleEndian>(number_to_return)?;
+        buffer.write_all(query)?;
+
         Ok(())
     }
 
@@ -555,11 +847,83 @@ impl Message {
         buffer.write_i32::<LittleEndian>(number_to_return)?;
         buffer.write_i64::<LittleEndian>(cursor_id)?;
 
-        let _ = buffer.flush();
         Ok(())
     }
 
-    /// Attemps to write the serialized message to a buffer.
+    /// Writes a serialized delete message to a given buffer.
+    ///
+    /// # Arguments
+    ///
+    /// `buffer` - The buffer to write to.
+    /// `header` - The header for the given message.
+    /// `namespace` - The full qualified name of the collection, beginning with
+    ///               the database name and a dot.
+    /// `flags` - Bit vector of delete options.
+    /// `selector` - Identifies the document(s) to be removed.
+    ///
+    /// # Return value
+    ///
+    /// Returns nothing on success, or an Error on failure.
+    pub fn write_delete<W: Write>(
+        buffer: &mut W,
+        header: &Header,
+        namespace: &str,
+        flags: &OpDeleteFlags,
+        selector: &bson::Document,
+    ) -> Result<()> {
+
+        header.write(buffer)?;
+
+        // Write ZERO field
+        buffer.write_i32::<LittleEndian>(0)?;
+
+        for byte in namespace.bytes() {
+            buffer.write_u8(byte)?;
+        }
+
+        // Writes the null terminator for the collection name string.
+        buffer.write_u8(0)?;
+
+        buffer.write_i32::<LittleEndian>(flags.bits())?;
+
+        Message::write_bson_document(buffer, selector)?;
+
+        Ok(())
+    }
+
+    /// Writes a serialized "kill cursors" request to a given buffer.
+    ///
+    /// # Arguments
+    ///
+    /// `buffer` - The buffer to write to.
+    /// `header` - The header for the given message.
+    /// `cursor_ids` - The cursors to close.
+    ///
+    /// # Return value
+    ///
+    /// Returns nothing on success, or an Error on failure.
+    pub fn write_kill_cursors<W: Write>(
+        buffer: &mut W,
+        header: &Header,
+        cursor_ids: &[i64],
+    ) -> Result<()> {
+
+        header.write(buffer)?;
+
+        // Write ZERO field
+        buffer.write_i32::<LittleEndian>(0)?;
+
+        buffer.write_i32::<LittleEndian>(cursor_ids.len() as i32)?;
+
+        for cursor_id in cursor_ids {
+            buffer.write_i64::<LittleEndian>(*cursor_id)?;
+        }
+
+        Ok(())
+    }
+
+    /// Attemps to write the serialized message to a buffer. The message is assembled in
+    /// memory first, so that it is sent with a single write.
     ///
     /// # Arguments
     ///
@@ -569,10 +933,113 @@ impl Message {
     ///
     /// Returns nothing on success, or an error string on failure.
     pub fn write<W: Write>(&self, buffer: &mut W) -> Result<()> {
+        let bytes = self.to_bytes()?;
+        buffer.write_all(&bytes)?;
+
+        // No reply is read for some messages, so a failure to send them would otherwise go
+        // unnoticed.
+        buffer.flush()?;
+        Ok(())
+    }
+
+    /// Returns the length of the serialized message in bytes, as recorded in its header.
+    pub fn length(&self) -> i32 {
+        match *self {
+            Message::OpReply { ref header, .. } |
+            Message::OpUpdate { ref header, .. } |
+            Message::OpInsert { ref header, .. } |
+            Message::OpQuery { ref header, .. } |
+            Message::OpQueryRaw { ref header, .. } |
+            Message::OpGetMore { ref header, .. } |
+            Message::OpDelete { ref header, .. } |
+            Message::OpKillCursors { ref header, .. } => header.message_length,
+        }
+    }
+
+    /// Returns the id of the request, as recorded in its header.
+    pub fn request_id(&self) -> i32 {
+        match *self {
+            Message::OpReply { ref header, .. } |
+            Message::OpUpdate { ref header, .. } |
+            Message::OpInsert { ref header, .. } |
+            Message::OpQuery { ref header, .. } |
+            Message::OpQueryRaw { ref header, .. } |
+            Message::OpGetMore { ref header, .. } |
+            Message::OpDelete { ref header, .. } |
+            Message::OpKillCursors { ref header, .. } => header.request_id,
+        }
+    }
+
+    /// Describes the message for logging, hiding the contents of authentication commands.
+    pub fn summary(&self) -> String {
+        match *self {
+            Message::OpReply { ref header, cursor_id, number_returned, .. } => {
+                format!(
+                    "reply to request {} with {} documents, cursor {}",
+                    header.response_to(),
+                    number_returned,
+                    cursor_id
+                )
+            }
+            Message::OpUpdate { ref header, ref namespace, .. } => {
+                format!("update {} on {}", header.request_id, namespace)
+            }
+            Message::OpInsert { ref header, ref namespace, ref documents, .. } => {
+                format!(
+                    "insert {} of {} documents into {}",
+                    header.request_id,
+                    documents.len(),
+                    namespace
+                )
+            }
+            Message::OpQuery { ref header, ref namespace, ref query, .. } => {
+                format!("query {} on {}: {}", header.request_id, namespace, redact(query))
+            }
+            Message::OpQueryRaw { ref header, ref namespace, ref query, .. } => {
+                format!(
+                    "query {} on {}: {} bytes of encoded BSON",
+                    header.request_id,
+                    namespace,
+                    query.len()
+                )
+            }
+            Message::OpGetMore { ref header, ref namespace, cursor_id, .. } => {
+                format!("getMore {} on {} for cursor {}", header.request_id, namespace, cursor_id)
+            }
+            Message::OpDelete { ref header, ref namespace, .. } => {
+                format!("delete {} on {}", header.request_id, namespace)
+            }
+            Message::OpKillCursors { ref header, ref cursor_ids } => {
+                format!("killCursors {} for cursors {:?}", header.request_id, cursor_ids)
+            }
+        }
+    }
+
+    /// Serializes the message into a single buffer, preallocated from the message length
+    /// computed when the message was constructed. The length in the header is patched once
+    /// the whole message has been written, so that it always matches the bytes sent.
+    ///
+    /// # Return value
+    ///
+    /// Returns the serialized message on success, or an Error on failure.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let capacity = match *self {
+            Message::OpReply { ref header, .. } |
+            Message::OpUpdate { ref header, .. } |
+            Message::OpInsert { ref header, .. } |
+            Message::OpQuery { ref header, .. } |
+            Message::OpQueryRaw { ref header, .. } |
+            Message::OpGetMore { ref header, .. } |
+            Message::OpDelete { ref header, .. } |
+            Message::OpKillCursors { ref header, .. } => header.message_length.max(0) as usize,
+        };
+
+        let mut buffer = Vec::with_capacity(capacity);
+
         match *self {
             // Only the server should send replies
             Message::OpReply { .. } => {
-                Err(ArgumentError(
+                return Err(ArgumentError(
                     String::from("OP_REPLY should not be sent to the client."),
                 ))
             }
@@ -582,13 +1049,13 @@ impl Message {
                 ref flags,
                 ref selector,
                 ref update,
-            } => Message::write_update(buffer, header, namespace, flags, selector, update),
+            } => Message::write_update(&mut buffer, header, namespace, flags, selector, update)?,
             Message::OpInsert {
                 ref header,
                 ref flags,
                 ref namespace,
                 ref documents,
-            } => Message::write_insert(buffer, header, flags, namespace, documents),
+            } => Message::write_insert(&mut buffer, header, flags, namespace, documents)?,
             Message::OpQuery {
                 ref header,
                 ref flags,
@@ -599,7 +1066,7 @@ impl Message {
                 ref return_field_selector,
             } => {
                 Message::write_query(
-                    buffer,
+                    &mut buffer,
                     header,
                     flags,
                     namespace,
@@ -607,15 +1074,55 @@ impl Message {
                     number_to_return,
                     query,
                     return_field_selector,
-                )
+                )?
+            }
+            Message::OpQueryRaw {
+                ref header,
+                ref flags,
+                ref namespace,
+                number_to_skip,
+                number_to_return,
+                ref query,
+            } => {
+                Message::write_query_raw(
+                    &mut buffer,
+                    header,
+                    flags,
+                    namespace,
+                    number_to_skip,
+                    number_to_return,
+                    query,
+                )?
             }
             Message::OpGetMore {
                 ref header,
                 ref namespace,
                 number_to_return,
                 cursor_id,
-            } => Message::write_get_more(buffer, header, namespace, number_to_return, cursor_id),
+            } => {
+                Message::write_get_more(
+                    &mut buffer,
+                    header,
+                    namespace,
+                    number_to_return,
+                    cursor_id,
+                )?
+            }
+            Message::OpDelete {
+                ref header,
+                ref namespace,
+                ref flags,
+                ref selector,
+            } => Message::write_delete(&mut buffer, header, namespace, flags, selector)?,
+            Message::OpKillCursors {
+                ref header,
+                ref cursor_ids,
+            } => Message::write_kill_cursors(&mut buffer, header, cursor_ids)?,
         }
+
+        let length = buffer.len() as i32;
+        LittleEndian::write_i32(&mut buffer[..4], length);
+        Ok(buffer)
     }
 
     /// Reads a serialized reply message from a buffer
@@ -627,7 +1134,15 @@ impl Message {
     /// # Return value
     ///
     /// Returns the reply message on success, or an Error on failure.
-    fn read_reply<R: Read>(buffer: &mut R, header: Header) -> Result<Message> {
+    fn read_reply<R: Read>(buffer: &mut R, header: Header, max_depth: usize) -> Result<Message> {
+        if header.message_length < 0 || header.message_length > MAX_REPLY_LENGTH {
+            return Err(ResponseError(format!(
+                "Reply has an invalid length {}; replies are at most {} bytes long.",
+                header.message_length,
+                MAX_REPLY_LENGTH
+            )));
+        }
+
         let mut length = header.message_length - mem::size_of::<Header>() as i32;
 
         // Read flags
@@ -646,15 +1161,17 @@ impl Message {
         let nr = buffer.read_i32::<LittleEndian>()?;
         length -= mem::size_of::<i32>() as i32;
 
-        let mut v = Vec::new();
-
-        while length > 0 {
-            let bson = bson::decode_document(buffer)?;
-            length -= bson.byte_length()?;
-            v.push(bson);
+        if length < 0 {
+            return Err(ResponseError(
+                format!("Reply has an invalid length {}.", header.message_length),
+            ));
         }
 
-        Ok(Message::new_reply(header, flags, cid, sf, nr, v))
+        let mut bytes = vec![0; length as usize];
+        buffer.read_exact(&mut bytes)?;
+        let documents = ReplyDocuments::with_max_depth(bytes, max_depth)?;
+
+        Ok(Message::new_reply(header, flags, cid, sf, nr, documents))
     }
 
     /// Attempts to read a serialized reply Message from a buffer.
@@ -672,7 +1189,7 @@ impl Message {
     {
         let header = Header::read(buffer)?;
         match header.op_code {
-            OpCode::Reply => Message::read_reply(buffer, header),
+            OpCode::Reply => Message::read_reply(buffer, header, DEFAULT_MAX_BSON_DEPTH),
             opcode => {
                 Err(ResponseError(format!(
                     "Expected to read OpCode::Reply but instead found \
@@ -682,4 +1199,287 @@ impl Message {
             }
         }
     }
+
+    /// Reads the reply to a request from a buffer, discarding up to `MAX_STRAY_REPLIES`
+    /// replies to other requests that were left over on the connection, e.g. by an aborted
+    /// exhaust query.
+    ///
+    /// # Arguments
+    ///
+    /// `buffer` - The buffer to read from.
+    /// `request_id` - The id of the request that the reply should respond to.
+    /// `max_depth` - The deepest nesting of documents and arrays accepted in the reply.
+    ///
+    /// # Return value
+    ///
+    /// Returns the reply message on success, or an Error on failure. A `ProtocolError` means
+    /// that the connection is out of sync and should no longer be used.
+    pub fn read_reply_to<T>(buffer: &mut T, request_id: i32, max_depth: usize) -> Result<Message>
+    where
+        T: Read + Write,
+    {
+        for _ in 0..=MAX_STRAY_REPLIES {
+            let header = Header::read(buffer)?;
+            if header.response_to() == request_id {
+                return match header.op_code {
+                    OpCode::Reply => Message::read_reply(buffer, header, max_depth),
+                    opcode => {
+                        Err(ResponseError(format!(
+                            "Expected to read OpCode::Reply but instead found opcode {}",
+                            opcode
+                        )))
+                    }
+                };
+            }
+
+            // Skip over the body of the stray message without parsing it.
+            let length = header.message_length as i64 - mem::size_of::<Header>() as i64;
+            if length < 0 {
+                return Err(ProtocolError(format!(
+                    "Received a reply to request {} with invalid length {}.",
+                    header.response_to(),
+                    header.message_length
+                )));
+            }
+
+            let length = length as u64;
+            if io::copy(&mut Read::take(&mut *buffer, length), &mut io::sink())? != length {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "Connection closed while discarding a stray reply.",
+                ).into());
+            }
+        }
+
+        Err(ProtocolError(format!(
+            "Did not receive a reply to request {} after discarding {} replies to other \
+             requests.",
+            request_id,
+            MAX_STRAY_REPLIES
+        )))
+    }
+}
+
+#[cfg(test)]
+mod write_test {
+    use bson::{self, bson, doc};
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use wire_protocol::flags::{OpDeleteFlags, OpInsertFlags, OpQueryFlags};
+    use super::Message;
+
+    // Serializes a message field by field, the way messages were written before being
+    // assembled in a single buffer.
+    fn reference_bytes(
+        op_code: i32,
+        request_id: i32,
+        prefix: &[u8],
+        docs: &[bson::Document],
+    ) -> Vec<u8> {
+        let mut body = prefix.to_vec();
+        for doc in docs {
+            let mut encoded = Vec::new();
+            bson::encode_document(&mut encoded, doc).unwrap();
+            body.extend_from_slice(&encoded);
+        }
+
+        let mut bytes = Vec::new();
+        bytes.write_i32::<LittleEndian>(16 + body.len() as i32).unwrap();
+        bytes.write_i32::<LittleEndian>(request_id).unwrap();
+        bytes.write_i32::<LittleEndian>(0).unwrap();
+        bytes.write_i32::<LittleEndian>(op_code).unwrap();
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    #[test]
+    fn test_insert_bytes() {
+        let docs: Vec<_> = (0..1000)
+            .map(|i| doc! { "_id": i, "name": format!("document {}", i), "tags": ["a", "b"] })
+            .collect();
+
+        let flags = OpInsertFlags::CONTINUE_ON_ERROR;
+        let message = Message::new_insert(7, flags, "db.coll".into(), docs.clone()).unwrap();
+        let bytes = message.to_bytes().unwrap();
+
+        let mut prefix = Vec::new();
+        prefix.write_i32::<LittleEndian>(1).unwrap();
+        prefix.extend_from_slice(b"db.coll\0");
+
+        assert_eq!(bytes, reference_bytes(2002, 7, &prefix, &docs));
+
+        // The buffer was sized up front, so it never had to grow.
+        assert_eq!(bytes.capacity(), bytes.len());
+    }
+
+    #[test]
+    fn test_query_bytes() {
+        let query = doc! { "find": "coll", "filter": { "x": { "$gt": 1 } } };
+        let projection = doc! { "x": 1 };
+
+        let message = Message::new_query(
+            3,
+            OpQueryFlags::SLAVE_OK,
+            "db.$cmd".into(),
+            5,
+            -1,
+            query.clone(),
+            Some(projection.clone()),
+        ).unwrap();
+        let bytes = message.to_bytes().unwrap();
+
+        let mut prefix = Vec::new();
+        prefix.write_i32::<LittleEndian>(OpQueryFlags::SLAVE_OK.bits()).unwrap();
+        prefix.extend_from_slice(b"db.$cmd\0");
+        prefix.write_i32::<LittleEndian>(5).unwrap();
+        prefix.write_i32::<LittleEndian>(-1).unwrap();
+
+        assert_eq!(bytes, reference_bytes(2004, 3, &prefix, &[query, projection]));
+        assert_eq!(bytes.capacity(), bytes.len());
+    }
+
+    #[test]
+    fn test_get_more_bytes() {
+        let message = Message::new_get_more(9, "db.coll".into(), 100, 1234);
+        let bytes = message.to_bytes().unwrap();
+
+        let mut prefix = Vec::new();
+        prefix.write_i32::<LittleEndian>(0).unwrap();
+        prefix.extend_from_slice(b"db.coll\0");
+        prefix.write_i32::<LittleEndian>(100).unwrap();
+        prefix.write_i64::<LittleEndian>(1234).unwrap();
+
+        assert_eq!(bytes, reference_bytes(2005, 9, &prefix, &[]));
+    }
+
+    #[test]
+    fn test_delete_bytes() {
+        let selector = doc! { "x": { "$lt": 5 } };
+        let flags = OpDeleteFlags::SINGLE_REMOVE;
+        let message = Message::new_delete(13, "db.coll".into(), flags, selector.clone()).unwrap();
+        let bytes = message.to_bytes().unwrap();
+
+        let mut prefix = Vec::new();
+        prefix.write_i32::<LittleEndian>(0).unwrap();
+        prefix.extend_from_slice(b"db.coll\0");
+        prefix.write_i32::<LittleEndian>(1).unwrap();
+
+        assert_eq!(bytes, reference_bytes(2006, 13, &prefix, &[selector]));
+        assert_eq!(bytes.capacity(), bytes.len());
+    }
+
+    #[test]
+    fn test_kill_cursors_bytes() {
+        let message = Message::new_kill_cursors(11, vec![1234, -5, i64::max_value()]);
+        let bytes = message.to_bytes().unwrap();
+
+        let mut prefix = Vec::new();
+        prefix.write_i32::<LittleEndian>(0).unwrap();
+        prefix.write_i32::<LittleEndian>(3).unwrap();
+        prefix.write_i64::<LittleEndian>(1234).unwrap();
+        prefix.write_i64::<LittleEndian>(-5).unwrap();
+        prefix.write_i64::<LittleEndian>(i64::max_value()).unwrap();
+
+        assert_eq!(bytes, reference_bytes(2007, 11, &prefix, &[]));
+        assert_eq!(bytes.capacity(), bytes.len());
+    }
+}
+
+#[cfg(test)]
+mod read_test {
+    use bson::{self, bson, doc};
+    use byteorder::{LittleEndian, WriteBytesExt};
+    use Error::{IoError, ProtocolError};
+    use super::{Message, MAX_STRAY_REPLIES};
+    use wire_protocol::validation::DEFAULT_MAX_BSON_DEPTH;
+
+    use std::io::{self, Read, Write};
+
+    // A connection that replays canned server replies and swallows everything written to it.
+    struct MockStream {
+        input: io::Cursor<Vec<u8>>,
+    }
+
+    impl MockStream {
+        fn new(input: Vec<u8>) -> MockStream {
+            MockStream { input: io::Cursor::new(input) }
+        }
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn reply_bytes(request_id: i32, response_to: i32, docs: &[bson::Document]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.write_i32::<LittleEndian>(0).unwrap();
+        body.write_i64::<LittleEndian>(0).unwrap();
+        body.write_i32::<LittleEndian>(0).unwrap();
+        body.write_i32::<LittleEndian>(docs.len() as i32).unwrap();
+        for doc in docs {
+            bson::encode_document(&mut body, doc).unwrap();
+        }
+
+        let mut bytes = Vec::new();
+        bytes.write_i32::<LittleEndian>(16 + body.len() as i32).unwrap();
+        bytes.write_i32::<LittleEndian>(request_id).unwrap();
+        bytes.write_i32::<LittleEndian>(response_to).unwrap();
+        bytes.write_i32::<LittleEndian>(1).unwrap();
+        bytes.extend_from_slice(&body);
+        bytes
+    }
+
+    #[test]
+    fn test_discard_stray_reply() {
+        let mut input = reply_bytes(100, 1, &[doc! { "x": "stale" }]);
+        input.extend(reply_bytes(101, 2, &[doc! { "x": "fresh" }]));
+        let mut stream = MockStream::new(input);
+
+        match Message::read_reply_to(&mut stream, 2, DEFAULT_MAX_BSON_DEPTH).unwrap() {
+            Message::OpReply { header, documents, .. } => {
+                assert_eq!(header.response_to(), 2);
+                assert_eq!(documents.to_documents().unwrap(), vec![doc! { "x": "fresh" }]);
+            }
+            other => panic!("Expected OpReply, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_too_many_stray_replies() {
+        let mut input = Vec::new();
+        for i in 0..=MAX_STRAY_REPLIES {
+            input.extend(reply_bytes(100 + i as i32, 1, &[doc! { "x": i as i32 }]));
+        }
+        input.extend(reply_bytes(200, 2, &[doc! { "x": "fresh" }]));
+        let mut stream = MockStream::new(input);
+
+        match Message::read_reply_to(&mut stream, 2, DEFAULT_MAX_BSON_DEPTH) {
+            Err(ProtocolError(_)) => (),
+            other => panic!("Expected ProtocolError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_truncated_stray_reply() {
+        let mut input = reply_bytes(100, 1, &[doc! { "x": "stale" }]);
+        let len = input.len();
+        input.truncate(len - 4);
+        let mut stream = MockStream::new(input);
+
+        match Message::read_reply_to(&mut stream, 2, DEFAULT_MAX_BSON_DEPTH) {
+            Err(IoError(_)) => (),
+            other => panic!("Expected IoError, got {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,177 @@
+//! Pre-encoded BSON documents, sent to the server without being decoded.
+//!
+//! Only the outer length prefix of a raw document and its top-level elements are checked, to
+//! find its `_id`; values are skipped by their length and left for the server to validate.
+use bson::{self, oid, Bson};
+use byteorder::{ByteOrder, LittleEndian};
+use Error::{self, ArgumentError};
+use Result;
+use wire_protocol::validation::{validate_document, DEFAULT_MAX_BSON_DEPTH};
+
+use std::io;
+
+// The bytes added by `prepend_id`: an element type, the "_id" key and an ObjectId.
+const ID_ELEMENT_LENGTH: usize = 1 + 4 + 12;
+
+/// Checks the length prefix and terminator of a raw document, and returns the value of its
+/// top-level `_id` element, if it has one.
+pub fn find_id(doc: &[u8]) -> Result<Option<Bson>> {
+    if doc.len() < 5 {
+        return Err(ArgumentError(format!("{} bytes are too short for a document", doc.len())));
+    }
+
+    let length = LittleEndian::read_i32(&doc[..4]);
+    if length < 0 || length as usize != doc.len() {
+        return Err(ArgumentError(format!(
+            "length prefix {} doesn't match the document's {} bytes",
+            length,
+            doc.len()
+        )));
+    }
+
+    let end = doc.len() - 1;
+    if doc[end] != 0 {
+        return Err(ArgumentError(String::from("the document is not terminated")));
+    }
+
+    let mut pos = 4;
+    while pos < end {
+        let key_end = cstring_end(doc, pos + 1, end)?;
+        let value_end = value_end(doc[pos], doc, key_end + 1, end)?;
+
+        if &doc[pos + 1..key_end] == b"_id" {
+            return decode_element(&doc[pos..value_end]).map(Some);
+        }
+
+        pos = value_end;
+    }
+
+    Ok(None)
+}
+
+/// Returns a copy of a raw document with an `_id` element holding `id` before its other
+/// elements.
+pub fn prepend_id(doc: &[u8], id: &oid::ObjectId) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(doc.len() + ID_ELEMENT_LENGTH);
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.push(0x07);
+    bytes.extend_from_slice(b"_id\0");
+    bytes.extend_from_slice(&id.bytes());
+    bytes.extend_from_slice(&doc[4..]);
+
+    let length = bytes.len() as i32;
+    LittleEndian::write_i32(&mut bytes[..4], length);
+    bytes
+}
+
+/// Encodes `command` followed by an array holding the raw documents, under `key`.
+pub fn encode_command(command: &bson::Document, key: &str, docs: &[Vec<u8>]) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    bson::encode_document(&mut bytes, command)?;
+
+    // Drop the command's terminator to append the array to it.
+    bytes.pop();
+    bytes.push(0x04);
+    bytes.extend_from_slice(key.as_bytes());
+    bytes.push(0);
+
+    let array_start = bytes.len();
+    bytes.extend_from_slice(&[0; 4]);
+
+    for (i, doc) in docs.iter().enumerate() {
+        bytes.push(0x03);
+        bytes.extend_from_slice(i.to_string().as_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(doc);
+    }
+
+    bytes.push(0);
+    let array_length = (bytes.len() - array_start) as i32;
+    LittleEndian::write_i32(&mut bytes[array_start..array_start + 4], array_length);
+
+    bytes.push(0);
+    let length = bytes.len() as i32;
+    LittleEndian::write_i32(&mut bytes[..4], length);
+    Ok(bytes)
+}
+
+// Decodes a single element, checking it first so that a malformed value is reported rather
+// than panicking in the decoder.
+fn decode_element(element: &[u8]) -> Result<Bson> {
+    let mut bytes = Vec::with_capacity(element.len() + 5);
+    bytes.extend_from_slice(&(element.len() as i32 + 5).to_le_bytes());
+    bytes.extend_from_slice(element);
+    bytes.push(0);
+
+    if validate_document(&bytes, DEFAULT_MAX_BSON_DEPTH).is_err() {
+        return Err(ArgumentError(String::from("the _id is malformed")));
+    }
+
+    let mut doc = bson::decode_document(&mut io::Cursor::new(bytes))?;
+    doc.remove("_id").ok_or_else(|| ArgumentError(String::from("the _id could not be read")))
+}
+
+// Returns the offset of the terminator of the string starting at `pos`.
+fn cstring_end(doc: &[u8], pos: usize, end: usize) -> Result<usize> {
+    match doc[pos.min(end)..end].iter().position(|&b| b == 0) {
+        Some(length) => Ok(pos + length),
+        None => Err(truncated(pos)),
+    }
+}
+
+// Returns the offset just past the value of the given type starting at `pos`, which must end
+// by `end`. Only the length of the value is read.
+fn value_end(element_type: u8, doc: &[u8], pos: usize, end: usize) -> Result<usize> {
+    let length = match element_type {
+        // Double, datetime, timestamp and 64-bit integer.
+        0x01 | 0x09 | 0x11 | 0x12 => 8,
+        // String, JavaScript code and symbol, preceded by their length.
+        0x02 | 0x0D | 0x0E => 4 + read_length(doc, pos, end)?,
+        // Embedded document, array and code with scope, whose length includes itself.
+        0x03 | 0x04 | 0x0F => read_length(doc, pos, end)?,
+        // Binary data: a length, a subtype and the data.
+        0x05 => 5 + read_length(doc, pos, end)?,
+        // Undefined, null, min key and max key.
+        0x06 | 0x0A | 0xFF | 0x7F => 0,
+        0x07 => 12,
+        0x08 => 1,
+        // Regular expression: a pattern and options.
+        0x0B => {
+            let pattern_end = cstring_end(doc, pos, end)?;
+            return cstring_end(doc, pattern_end + 1, end).map(|options_end| options_end + 1);
+        }
+        // DBPointer: a namespace and an ObjectId.
+        0x0C => 4 + read_length(doc, pos, end)? + 12,
+        0x10 => 4,
+        0x13 => 16,
+        _ => {
+            return Err(ArgumentError(
+                format!("unknown element type {:#04x} at byte {}", element_type, pos),
+            ))
+        }
+    };
+
+    if pos > end || end - pos < length {
+        return Err(truncated(pos));
+    }
+
+    Ok(pos + length)
+}
+
+// Reads a non-negative length prefix.
+fn read_length(doc: &[u8], pos: usize, end: usize) -> Result<usize> {
+    if pos > end || end - pos < 4 {
+        return Err(truncated(pos));
+    }
+
+    let length = LittleEndian::read_i32(&doc[pos..pos + 4]);
+    if length < 0 {
+        return Err(ArgumentError(format!("negative length {} at byte {}", length, pos)));
+    }
+
+    Ok(length as usize)
+}
+
+fn truncated(pos: usize) -> Error {
+    ArgumentError(format!("the element at byte {} is truncated", pos))
+}
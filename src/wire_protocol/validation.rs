@@ -0,0 +1,179 @@
+//! Strict checking of the BSON documents received from servers.
+//!
+//! Replies are checked before they are decoded, so that a buggy proxy or a truncated stream
+//! yields an error rather than an over-read, a huge allocation or a stack overflow in the
+//! decoder.
+use byteorder::{ByteOrder, LittleEndian};
+use chrono::NaiveDateTime;
+use Error::{self, ResponseError};
+use Result;
+
+use std::str;
+
+/// The deepest nesting of documents and arrays accepted in a reply by default.
+pub const DEFAULT_MAX_BSON_DEPTH: usize = 200;
+
+/// Checks that `bytes` holds exactly one well-formed BSON document. Every length prefix must fit
+/// in the bytes that remain, every key and string must be valid UTF-8, and documents and arrays
+/// may be nested at most `max_depth` levels deep, counting the outermost document.
+pub fn validate_document(bytes: &[u8], max_depth: usize) -> Result<()> {
+    Validator { max_depth: max_depth }.document(bytes, 1)
+}
+
+fn malformed(offset: usize, reason: &str) -> Error {
+    ResponseError(format!("Reply has a malformed document at byte {}: {}.", offset, reason))
+}
+
+struct Validator {
+    max_depth: usize,
+}
+
+impl Validator {
+    // Checks a document spanning the whole of `doc`.
+    fn document(&self, doc: &[u8], depth: usize) -> Result<()> {
+        if depth > self.max_depth {
+            return Err(malformed(0, &format!("nested deeper than {} levels", self.max_depth)));
+        }
+
+        if doc.len() < 5 || read_length(doc, 0, doc.len())? != doc.len() {
+            return Err(malformed(0, "invalid document length"));
+        }
+
+        let end = doc.len() - 1;
+        if doc[end] != 0 {
+            return Err(malformed(end, "unterminated document"));
+        }
+
+        let mut pos = 4;
+        while pos < end {
+            let element_type = doc[pos];
+            let key_end = cstring(doc, pos + 1, end)?;
+            pos = self.value(element_type, doc, key_end, end, depth)?;
+        }
+
+        Ok(())
+    }
+
+    // Checks the value of an element of the given type starting at `pos`, returning the offset
+    // just past it. The value must end by `end`.
+    fn value(
+        &self,
+        element_type: u8,
+        doc: &[u8],
+        pos: usize,
+        end: usize,
+        depth: usize,
+    ) -> Result<usize> {
+        match element_type {
+            // Double, timestamp and 64-bit integer.
+            0x01 | 0x11 | 0x12 => fixed(pos, 8, end),
+            0x09 => datetime(doc, pos, end),
+            // String, JavaScript code and symbol.
+            0x02 | 0x0D | 0x0E => string(doc, pos, end),
+            // Embedded document and array.
+            0x03 | 0x04 => self.embedded(doc, pos, end, depth),
+            // Binary data: a length, a subtype and the data.
+            0x05 => {
+                let length = read_length(doc, pos, end)?;
+                fixed(pos, 5 + length, end)
+            }
+            // Undefined, null, min key and max key.
+            0x06 | 0x0A | 0xFF | 0x7F => Ok(pos),
+            0x07 => fixed(pos, 12, end),
+            0x08 => fixed(pos, 1, end),
+            // Regular expression: a pattern and options.
+            0x0B => cstring(doc, pos, end).and_then(|pos| cstring(doc, pos, end)),
+            // DBPointer: a namespace and an ObjectId.
+            0x0C => string(doc, pos, end).and_then(|pos| fixed(pos, 12, end)),
+            // JavaScript code with scope: a total length, the code and the scope document.
+            0x0F => {
+                let scope_end = fixed(pos, read_length(doc, pos, end)?, end)?;
+                let code_end = string(doc, pos + 4, scope_end)?;
+                let pos = self.embedded(doc, code_end, scope_end, depth)?;
+                if pos != scope_end {
+                    return Err(malformed(pos, "invalid code with scope length"));
+                }
+                Ok(pos)
+            }
+            0x10 => fixed(pos, 4, end),
+            0x13 => fixed(pos, 16, end),
+            _ => Err(malformed(pos, &format!("unknown element type {:#04x}", element_type))),
+        }
+    }
+
+    fn embedded(&self, doc: &[u8], pos: usize, end: usize, depth: usize) -> Result<usize> {
+        let doc_end = fixed(pos, read_length(doc, pos, end)?, end)?;
+        self.document(&doc[pos..doc_end], depth + 1)?;
+        Ok(doc_end)
+    }
+}
+
+// Returns `pos + length` if that many bytes remain before `end`.
+fn fixed(pos: usize, length: usize, end: usize) -> Result<usize> {
+    if pos > end || end - pos < length {
+        return Err(malformed(pos, "truncated value"));
+    }
+
+    Ok(pos + length)
+}
+
+// Checks a datetime, given in milliseconds since the epoch. The decoder converts it to seconds
+// and a non-negative number of nanoseconds, and panics on times chrono can't represent, so
+// those are rejected here instead.
+fn datetime(doc: &[u8], pos: usize, end: usize) -> Result<usize> {
+    let value_end = fixed(pos, 8, end)?;
+    let millis = LittleEndian::read_i64(&doc[pos..value_end]);
+
+    let representable = millis % 1000 >= 0 &&
+        NaiveDateTime::from_timestamp_opt(millis / 1000, (millis % 1000) as u32 * 1_000_000)
+            .is_some();
+
+    if !representable {
+        return Err(malformed(pos, &format!("unsupported datetime {}", millis)));
+    }
+
+    Ok(value_end)
+}
+
+// Reads a non-negative length prefix.
+fn read_length(doc: &[u8], pos: usize, end: usize) -> Result<usize> {
+    fixed(pos, 4, end)?;
+
+    let length = LittleEndian::read_i32(&doc[pos..pos + 4]);
+    if length < 0 {
+        return Err(malformed(pos, &format!("negative length {}", length)));
+    }
+
+    Ok(length as usize)
+}
+
+// Checks a null-terminated UTF-8 string, returning the offset just past the terminator.
+fn cstring(doc: &[u8], pos: usize, end: usize) -> Result<usize> {
+    let length = match doc[pos..end].iter().position(|&b| b == 0) {
+        Some(length) => length,
+        None => return Err(malformed(pos, "unterminated string")),
+    };
+
+    utf8(doc, pos, length)?;
+    Ok(pos + length + 1)
+}
+
+// Checks a length-prefixed, null-terminated UTF-8 string, returning the offset just past it.
+fn string(doc: &[u8], pos: usize, end: usize) -> Result<usize> {
+    let length = read_length(doc, pos, end)?;
+    let string_end = fixed(pos + 4, length, end)?;
+
+    if length == 0 || doc[string_end - 1] != 0 {
+        return Err(malformed(pos, "unterminated string"));
+    }
+
+    utf8(doc, pos + 4, length - 1)?;
+    Ok(string_end)
+}
+
+fn utf8(doc: &[u8], pos: usize, length: usize) -> Result<()> {
+    match str::from_utf8(&doc[pos..pos + length]) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(malformed(pos, "invalid UTF-8")),
+    }
+}
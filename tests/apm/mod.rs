@@ -1,11 +1,47 @@
 use std::fs::{self, File};
 use std::io::{BufRead, BufReader};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
 
 use bson::Bson;
-use mongodb::{Client, ClientOptions, CommandResult, ThreadedClient};
+use mongodb::{Client, ClientOptions, CommandResult, CommandStarted, SdamEvent, ThreadedClient};
+use mongodb::coll::options::FindOptions;
 use mongodb::db::ThreadedDatabase;
 use rand;
 
+static COMMENTED_GET_MORES: AtomicUsize = AtomicUsize::new(0);
+static SERVERS_OPENED: AtomicUsize = AtomicUsize::new(0);
+static HEARTBEATS_STARTED: AtomicUsize = AtomicUsize::new(0);
+static HEARTBEATS_SUCCEEDED: AtomicUsize = AtomicUsize::new(0);
+
+fn count_sdam_events(_client: Client, event: &SdamEvent) {
+    let counter = match *event {
+        SdamEvent::ServerOpened { .. } => &SERVERS_OPENED,
+        SdamEvent::ServerHeartbeatStarted { .. } => &HEARTBEATS_STARTED,
+        SdamEvent::ServerHeartbeatSucceeded { ref reply, .. } => {
+            assert!(reply.contains_key("ismaster"));
+            &HEARTBEATS_SUCCEEDED
+        }
+        _ => return,
+    };
+
+    counter.fetch_add(1, Ordering::SeqCst);
+}
+
+fn count_commented_get_mores(_client: Client, command_started: &CommandStarted) {
+    if command_started.command_name != "get_more" {
+        return;
+    }
+
+    match command_started.command.get("comment") {
+        Some(&Bson::String(ref comment)) if comment == "apm-comment-propagation" => {
+            COMMENTED_GET_MORES.fetch_add(1, Ordering::SeqCst);
+        }
+        _ => (),
+    }
+}
+
 fn timed_query(_client: Client, command_result: &CommandResult) {
     let (command_name, duration) = match *command_result {
         CommandResult::Success {
@@ -25,7 +61,7 @@ fn timed_query(_client: Client, command_result: &CommandResult) {
 
 #[test]
 fn command_duration() {
-    let mut client = Client::connect("localhost", 27017).expect("damn it!");
+    let client = Client::connect("localhost", 27017).expect("damn it!");
     let db = client.db("test-apm-mod");
     let coll = db.collection("command_duration");
     coll.drop().unwrap();
@@ -50,6 +86,30 @@ fn command_duration() {
     coll.find(Some(doc), None).unwrap();
 }
 
+#[test]
+fn comment_propagation() {
+    let client = Client::connect("localhost", 27017).expect("damn it!");
+    let db = client.db("test-apm-mod");
+    let coll = db.collection("comment_propagation");
+    coll.drop().unwrap();
+
+    let docs = (1..6).map(|i| doc! { "_id": i }).collect();
+    coll.insert_many(docs, None).unwrap();
+    client.add_start_hook(count_commented_get_mores).unwrap();
+
+    let options = FindOptions::builder()
+        .batch_size(2)
+        .comment(String::from("apm-comment-propagation"))
+        .build();
+
+    let results: Vec<_> = coll.find(None, Some(options)).unwrap().collect();
+    assert_eq!(results.len(), 5);
+
+    // The first batch holds two documents, so two more batches are needed, each of which must
+    // carry the originating query's comment.
+    assert!(COMMENTED_GET_MORES.load(Ordering::SeqCst) >= 2);
+}
+
 fn read_first_non_monitor_line(file: &mut BufReader<&File>, line: &mut String) {
     loop {
         file.read_line(line).unwrap();
@@ -203,3 +263,20 @@ fn logging() {
 
     fs::remove_file("test_log.txt").unwrap();
 }
+
+#[test]
+fn sdam_events() {
+    let mut options = ClientOptions::new();
+    options.heartbeat_frequency_ms = 500;
+
+    let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+    client.set_sdam_listener(count_sdam_events).unwrap();
+
+    // The listener is told about servers that were known before it was set.
+    assert_eq!(1, SERVERS_OPENED.load(Ordering::SeqCst));
+
+    thread::sleep(Duration::from_millis(1500));
+
+    assert!(HEARTBEATS_STARTED.load(Ordering::SeqCst) >= 2);
+    assert!(HEARTBEATS_SUCCEEDED.load(Ordering::SeqCst) >= 2);
+}
@@ -0,0 +1,357 @@
+use std::thread;
+use std::time::Duration;
+
+use bson::Bson;
+use chrono::{TimeZone, Utc};
+use mongodb::{Client, Error, ThreadedClient};
+use mongodb::admin::{CurrentOp, DatabaseInfo, MemberLag, OpId, ReplicaSetConfig};
+use mongodb::db::ThreadedDatabase;
+use mongodb::topology::server::ServerType;
+
+#[test]
+fn current_op_from_document() {
+    let doc = doc! {
+        "opid": "shard0000:1234",
+        "op": "query",
+        "ns": "test.coll",
+        "secs_running": 3,
+        "query": { "x": 1 },
+        "client_s": "127.0.0.1:50000",
+    };
+
+    let op = CurrentOp::from_document(doc).expect("Failed to parse operation.");
+    assert_eq!(op.opid, OpId::Sharded(String::from("shard0000:1234")));
+    assert_eq!(op.op, "query");
+    assert_eq!(op.ns, "test.coll");
+    assert_eq!(op.secs_running, Some(3));
+    assert_eq!(op.query, Some(doc! { "x": 1 }));
+    assert_eq!(op.client, Some(String::from("127.0.0.1:50000")));
+    assert_eq!(op.opid.to_bson(), Bson::String(String::from("shard0000:1234")));
+
+    let doc = doc! { "opid": 42, "op": "none", "command": { "find": "coll" } };
+    let op = CurrentOp::from_document(doc).expect("Failed to parse idle operation.");
+    assert_eq!(op.opid, OpId::Int(42));
+    assert_eq!(op.ns, "");
+    assert_eq!(op.query, Some(doc! { "find": "coll" }));
+    assert_eq!(op.client, None);
+
+    assert!(CurrentOp::from_document(doc! { "op": "query" }).is_err());
+}
+
+#[test]
+fn database_info_from_document() {
+    // Servers report sizes as doubles before MongoDB 3.6, and as 64-bit integers since.
+    let info = DatabaseInfo::from_document(&doc! {
+        "name": "logs",
+        "sizeOnDisk": 8192.0,
+        "empty": false,
+    }).unwrap();
+    assert_eq!(info.name, "logs");
+    assert_eq!(info.size_on_disk, Some(8192));
+    assert_eq!(info.empty, Some(false));
+
+    let name_only = DatabaseInfo::from_document(&doc! { "name": "logs" }).unwrap();
+    assert_eq!(name_only.size_on_disk, None);
+    assert_eq!(name_only.empty, None);
+
+    assert!(info.matches(&doc! { "name": "logs", "empty": false }).unwrap());
+    assert!(info.matches(&doc! { "sizeOnDisk": 8192i64 }).unwrap());
+    assert!(!info.matches(&doc! { "name": "metrics" }).unwrap());
+    assert!(info.matches(&doc! { "name": { "$in": ["logs"] } }).is_err());
+    assert!(info.matches(&doc! { "shards": {} }).is_err());
+
+    match DatabaseInfo::from_document(&doc! { "name": 1 }) {
+        Err(Error::ResponseError(ref message)) => assert!(message.contains("non-string 'name'")),
+        other => panic!("Expected a response error, got {:?}", other),
+    }
+    assert!(DatabaseInfo::from_document(&doc! { "sizeOnDisk": 1 }).is_err());
+}
+
+#[test]
+fn replica_set_config_from_documents() {
+    let config = doc! {
+        "_id": "rs0",
+        "version": 3,
+        "members": [
+            { "_id": 0, "host": "a:27017", "priority": 2.0, "votes": 1 },
+            { "_id": 1, "host": "b:27017", "priority": 0, "votes": 1, "hidden": true },
+            { "_id": 2, "host": "c:27017", "arbiterOnly": true },
+        ],
+    };
+
+    let status = doc! {
+        "set": "rs0",
+        "members": [
+            {
+                "_id": 0,
+                "name": "a:27017",
+                "health": 1.0,
+                "state": 1,
+                "stateStr": "PRIMARY",
+                "optime": { "ts": Bson::TimeStamp(42), "t": 1i64 },
+                "optimeDate": Utc.timestamp(1000, 0),
+            },
+            {
+                "_id": 1,
+                "name": "b:27017",
+                "health": 1.0,
+                "state": 2,
+                "stateStr": "SECONDARY",
+                "optime": Bson::TimeStamp(40),
+                "optimeDate": Utc.timestamp(995, 0),
+            },
+        ],
+    };
+
+    let rs = ReplicaSetConfig::from_documents(&config, &status)
+        .expect("Failed to parse replica set configuration.");
+    assert_eq!(rs.set_name, "rs0");
+    assert_eq!(rs.version, 3);
+    assert_eq!(rs.members.len(), 3);
+
+    let primary = rs.primary().expect("Failed to find the primary.");
+    assert_eq!(primary.host, "a:27017");
+    assert_eq!(primary.priority, 2.0);
+    assert_eq!(primary.state_str, Some(String::from("PRIMARY")));
+    assert_eq!(primary.healthy, Some(true));
+    assert_eq!(primary.optime, Some(42));
+
+    let secondary = &rs.members[1];
+    assert!(secondary.hidden);
+    assert_eq!(secondary.priority, 0.0);
+    assert_eq!(secondary.optime, Some(40));
+    assert_eq!(rs.replication_lag(secondary).map(|lag| lag.num_seconds()), Some(5));
+
+    // Members missing from the status keep their configuration only.
+    let arbiter = &rs.members[2];
+    assert!(arbiter.arbiter_only);
+    assert_eq!(arbiter.votes, 1);
+    assert_eq!(arbiter.state, None);
+    assert_eq!(rs.replication_lag(arbiter), None);
+
+    assert!(ReplicaSetConfig::from_documents(&doc! { "_id": "rs0" }, &status).is_err());
+}
+
+// Builds the timestamp of the given second, as the server sends optimes.
+fn timestamp(seconds: i64) -> Bson {
+    Bson::TimeStamp(seconds << 32 | 1)
+}
+
+#[test]
+fn replication_lag_from_status() {
+    // Protocol version 1 reports optimes as documents, along with their dates.
+    let status = doc! {
+        "set": "rs0",
+        "members": [
+            {
+                "_id": 0,
+                "name": "a:27017",
+                "health": 1.0,
+                "state": 1,
+                "stateStr": "PRIMARY",
+                "optime": { "ts": timestamp(1000), "t": 3i64 },
+                "optimeDate": Utc.timestamp(1000, 0),
+            },
+            {
+                "_id": 1,
+                "name": "b:27017",
+                "health": 1.0,
+                "state": 2,
+                "stateStr": "SECONDARY",
+                "optime": { "ts": timestamp(988), "t": 3i64 },
+                "optimeDate": Utc.timestamp(988, 500_000_000),
+            },
+            {
+                "_id": 2,
+                "name": "c:27017",
+                "health": 0.0,
+                "state": 8,
+                "stateStr": "(not reachable/healthy)",
+                "optime": { "ts": timestamp(0), "t": -1i64 },
+            },
+            { "_id": 3, "name": "d:27017", "health": 1.0, "state": 7, "stateStr": "ARBITER" },
+        ],
+    };
+
+    let lags = MemberLag::from_status(&status).expect("Failed to parse status.");
+    assert_eq!(lags.len(), 4);
+    assert_eq!(lags[0], MemberLag {
+        host: String::from("a:27017"),
+        state: Some(String::from("PRIMARY")),
+        lag_seconds: Some(0),
+        health: Some(true),
+    });
+    assert_eq!(lags[1].lag_seconds, Some(11));
+    assert_eq!(lags[2].lag_seconds, None);
+    assert_eq!(lags[2].health, Some(false));
+    assert_eq!(lags[3].state, Some(String::from("ARBITER")));
+    assert_eq!(lags[3].lag_seconds, None);
+
+    // Older members report bare timestamps, or pairs of seconds and increment before 2.6,
+    // without a date.
+    let status = doc! {
+        "members": [
+            { "name": "a:27017", "health": 1, "state": 2, "optime": timestamp(2000) },
+            { "name": "b:27017", "health": 1, "state": 1, "optime": timestamp(2030) },
+            { "name": "c:27017", "health": 1, "state": 2, "optime": { "t": 2010, "i": 4 } },
+        ],
+    };
+
+    let lags = MemberLag::from_status(&status).expect("Failed to parse legacy status.");
+    let seconds: Vec<_> = lags.iter().map(|lag| lag.lag_seconds).collect();
+    assert_eq!(seconds, vec![Some(30), Some(0), Some(20)]);
+    assert_eq!(lags[0].state, None);
+
+    // Without a primary, no lag can be computed.
+    let status = doc! {
+        "members": [{ "name": "a:27017", "state": 2, "optime": timestamp(2000) }],
+    };
+    let lags = MemberLag::from_status(&status).unwrap();
+    assert_eq!(lags[0].lag_seconds, None);
+
+    assert!(MemberLag::from_status(&doc! { "set": "rs0" }).is_err());
+}
+
+#[test]
+fn kill_op_validates_opid() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    client.is_master().unwrap();
+
+    let result = client.kill_op(OpId::Sharded(String::from("shard0000:1234")));
+    assert!(result.is_err());
+}
+
+#[test]
+fn current_ops_and_kill_op() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-admin").collection("current_ops");
+    coll.drop().unwrap();
+
+    let docs = (0..100).map(|i| doc! { "_id": i }).collect();
+    coll.insert_many(docs, None).unwrap();
+
+    // Sleeps for 100 ms per document without matching any, checking for interruptions in
+    // between.
+    let query_coll = client.db("test-client-admin").collection("current_ops");
+    let handle = thread::spawn(move || {
+        let filter = doc! { "$where": "sleep(100) || false" };
+        query_coll.find_one(Some(filter), None).map_err(|err| err.to_string())
+    });
+
+    let filter = doc! { "ns": "test-client-admin.current_ops" };
+    let mut op = None;
+
+    for _ in 0..50 {
+        let ops = client.current_ops(Some(filter.clone()), true).expect(
+            "Failed to list operations.",
+        );
+
+        op = ops.into_iter().find(|op| {
+            op.query.as_ref().map_or(false, |query| {
+                format!("{:?}", query).contains("sleep(100)")
+            })
+        });
+
+        if op.is_some() {
+            break;
+        }
+
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    let op = op.expect("Failed to find the running query.");
+    assert_eq!(op.ns, "test-client-admin.current_ops");
+    assert!(op.client.is_some());
+
+    client.kill_op(op.opid).expect("Failed to kill operation.");
+
+    let err = handle.join().unwrap().expect_err("Expected the query to be interrupted.");
+    assert!(err.to_lowercase().contains("interrupted"));
+}
+
+#[test]
+fn fsync_lock_and_unlock() {
+    let client = Client::connect("localhost", 27017).unwrap();
+
+    client.fsync_lock().expect("Failed to lock server.");
+    let locked = client.is_locked();
+    let unlocked = client.fsync_unlock();
+    let relocked = client.is_locked();
+    let not_locked = client.fsync_unlock();
+
+    assert_eq!(locked.expect("Failed to check lock."), true);
+    unlocked.expect("Failed to unlock server.");
+    assert_eq!(relocked.expect("Failed to check lock."), false);
+
+    match not_locked {
+        Err(Error::NotLockedError) => (),
+        other => panic!("Expected NotLockedError, got {:?}", other),
+    }
+}
+
+#[test]
+fn replica_set_commands_on_standalone() {
+    let client = Client::with_uri("mongodb://localhost:27017").unwrap();
+    client.is_master().unwrap();
+
+    let info = client.topology_info().expect("Failed to get topology info.");
+    if !info.servers.iter().any(|server| server.server_type == ServerType::Standalone) {
+        return;
+    }
+
+    match client.step_down(10) {
+        Err(Error::NotReplicaSetMemberError) => (),
+        other => panic!("Expected NotReplicaSetMemberError, got {:?}", other),
+    }
+
+    match client.freeze(10) {
+        Err(Error::NotReplicaSetMemberError) => (),
+        other => panic!("Expected NotReplicaSetMemberError, got {:?}", other),
+    }
+
+    match client.replica_set_config() {
+        Err(Error::NotReplicaSetMemberError) => (),
+        other => panic!("Expected NotReplicaSetMemberError, got {:?}", other),
+    }
+
+    match client.replication_lag() {
+        Err(Error::NotReplicaSetMemberError) => (),
+        other => panic!("Expected NotReplicaSetMemberError, got {:?}", other),
+    }
+}
+
+#[test]
+fn replica_set_config() {
+    let client = Client::with_uri("mongodb://localhost:27017").unwrap();
+    client.is_master().unwrap();
+
+    let info = client.topology_info().expect("Failed to get topology info.");
+    if !info.servers.iter().any(|server| server.server_type == ServerType::RSPrimary) {
+        return;
+    }
+
+    let rs = client.replica_set_config().expect("Failed to get replica set configuration.");
+    let primary = rs.primary().expect("Failed to find the primary.");
+    assert!(primary.optime_date.is_some());
+
+    let lags = client.replication_lag().expect("Failed to get replication lag.");
+    assert_eq!(lags.len(), rs.members.len());
+    assert!(lags.iter().any(|lag| lag.lag_seconds == Some(0)));
+
+    for member in rs.members.iter().filter(|member| !member.arbiter_only) {
+        assert!(info.servers.iter().any(|server| {
+            format!("{}:{}", server.host.host_name, server.host.port) == member.host
+        }));
+    }
+}
+
+#[test]
+fn refresh_topology() {
+    let client = Client::with_uri("mongodb://localhost:27017").unwrap();
+    client.is_master().unwrap();
+
+    client.refresh_topology().expect("Failed to refresh topology.");
+
+    let info = client.topology_info().expect("Failed to get topology info.");
+    assert!(info.servers.iter().all(|server| server.server_type != ServerType::Unknown));
+}
@@ -0,0 +1,108 @@
+use mongodb::{Client, Error, ThreadedClient};
+use mongodb::coll::Collection;
+use mongodb::coll::buffered::{BufferedWriter, BufferedWriterOptions};
+use mongodb::db::ThreadedDatabase;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn collection(name: &str) -> Collection {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-buffered").collection(name);
+    coll.drop().unwrap();
+    coll
+}
+
+#[test]
+fn flush_on_thresholds() {
+    let coll = collection("flush_on_thresholds");
+    let options = BufferedWriterOptions {
+        max_documents: 10,
+        flush_interval: None,
+        ..Default::default()
+    };
+    let writer = BufferedWriter::new(coll, Some(options));
+
+    for i in 0..25 {
+        writer.insert(doc! { "_id": i }).unwrap();
+    }
+
+    assert_eq!(writer.buffered().unwrap(), 5);
+    assert_eq!(writer.collection().count(None, None).unwrap(), 20);
+
+    writer.flush().unwrap();
+    assert_eq!(writer.buffered().unwrap(), 0);
+    assert_eq!(writer.collection().count(None, None).unwrap(), 25);
+
+    // The byte threshold applies as well.
+    let options = BufferedWriterOptions {
+        max_bytes: 1024,
+        flush_interval: None,
+        ..Default::default()
+    };
+    let writer = BufferedWriter::new(collection("flush_on_bytes"), Some(options));
+    let padding = "x".repeat(600);
+
+    writer.insert(doc! { "padding": padding.clone() }).unwrap();
+    assert_eq!(writer.buffered().unwrap(), 1);
+    writer.insert(doc! { "padding": padding }).unwrap();
+    assert_eq!(writer.buffered().unwrap(), 0);
+    assert_eq!(writer.collection().count(None, None).unwrap(), 2);
+}
+
+#[test]
+fn flush_on_interval_and_drop() {
+    let options = BufferedWriterOptions {
+        flush_interval: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let writer = BufferedWriter::new(collection("flush_on_interval"), Some(options));
+    writer.insert(doc! { "x": 1 }).unwrap();
+
+    let start = Instant::now();
+    while writer.buffered().unwrap() > 0 {
+        assert!(start.elapsed() < Duration::from_secs(5), "The buffer was never flushed.");
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert_eq!(writer.collection().count(None, None).unwrap(), 1);
+
+    // Dropping the writer flushes what is left and stops the background flusher.
+    let coll = collection("flush_on_drop");
+    let writer = BufferedWriter::new(coll, None);
+    writer.insert(doc! { "x": 1 }).unwrap();
+    writer.insert(doc! { "x": 2 }).unwrap();
+    drop(writer);
+
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-buffered").collection("flush_on_drop");
+    assert_eq!(coll.count(None, None).unwrap(), 2);
+}
+
+#[test]
+fn errors_are_kept() {
+    let options = BufferedWriterOptions {
+        max_documents: 3,
+        flush_interval: None,
+        ..Default::default()
+    };
+    let writer = BufferedWriter::new(collection("errors_are_kept"), Some(options));
+
+    // The duplicate fails, but the unordered batch still inserts the other documents.
+    let id = writer.insert(doc! { "x": 1 }).unwrap();
+    writer.insert(doc! { "_id": id.clone() }).unwrap();
+    writer.insert(doc! { "x": 2 }).unwrap();
+
+    let errors = writer.take_errors().unwrap();
+    assert_eq!(errors.len(), 1);
+    match errors[0] {
+        Error::BulkWriteError(_) => (),
+        ref other => panic!("Expected BulkWriteError, got {:?}", other),
+    }
+    assert!(writer.take_errors().unwrap().is_empty());
+    assert_eq!(writer.collection().count(None, None).unwrap(), 2);
+
+    // Explicit flushes return their error.
+    writer.insert(doc! { "_id": id }).unwrap();
+    assert!(writer.flush().is_err());
+    assert!(writer.take_errors().unwrap().is_empty());
+}
@@ -1,6 +1,6 @@
 use bson::Bson;
 use mongodb::coll::options::WriteModel;
-use mongodb::{Client, ThreadedClient};
+use mongodb::{Client, Error, ThreadedClient};
 use mongodb::db::ThreadedDatabase;
 
 #[test]
@@ -115,16 +115,23 @@ fn bulk_ordered_mix() {
             filter: doc! { "_id": 3 },
             replacement: doc! { "x": 37 },
             upsert: Some(true),
+            collation: None,
+            hint: None,
         },
         WriteModel::UpdateMany {
             filter: doc! { "_id": { "$lt": 3 } },
             update: doc! { "$inc": { "x": 1 } },
             upsert: Some(false),
+            collation: None,
+            hint: None,
+            array_filters: None,
         },
         WriteModel::DeleteOne {
             filter: doc! {
             "_id": 4
         },
+            collation: None,
+            hint: None,
         },
         WriteModel::InsertOne {
             document: doc! {
@@ -136,6 +143,9 @@ fn bulk_ordered_mix() {
             filter: doc! { "_id": 6 },
             update: doc! { "$set":  { "x": 62 } },
             upsert: Some(true),
+            collation: None,
+            hint: None,
+            array_filters: None,
         },
         WriteModel::InsertOne {
             document: doc! {
@@ -165,6 +175,8 @@ fn bulk_ordered_mix() {
             filter: doc! {
             "_id": { "$gte": 103 }
         },
+            collation: None,
+            hint: None,
         },
     ];
 
@@ -204,3 +216,182 @@ fn bulk_ordered_mix() {
     check_value_in_tree!(result.inserted_ids, 12, 104);
     check_value_in_tree!(result.upserted_ids, 8, 6);
 }
+
+#[test]
+fn bulk_ordered_array_filters() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-bulk");
+    let coll = db.collection("bulk_ordered_array_filters");
+    coll.drop().unwrap();
+
+    let models = vec![
+        WriteModel::InsertOne { document: doc! { "_id": 1, "grades": [95, 102, 90] } },
+        WriteModel::InsertOne { document: doc! { "_id": 2, "grades": [98, 100, 102] } },
+        WriteModel::UpdateOne {
+            filter: doc! { "_id": 1 },
+            update: doc! { "$set": { "grades.$[high]": 100 } },
+            upsert: None,
+            collation: None,
+            hint: None,
+            array_filters: Some(vec![doc! { "high": { "$gt": 100 } }]),
+        },
+        WriteModel::InsertOne { document: doc! { "_id": 3, "grades": [101] } },
+    ];
+
+    let result = coll.bulk_write(models, true);
+
+    // Servers older than 3.6 reject the array filters before anything is sent.
+    if let Some(exception) = result.bulk_write_exception {
+        assert!(exception.message.contains("Array filters require MongoDB 3.6"));
+        assert_eq!(exception.unprocessed_requests.len(), 4);
+        assert_eq!(coll.count(None, None).unwrap(), 0);
+        return;
+    }
+
+    assert_eq!(result.inserted_count, 3);
+    assert_eq!(result.matched_count, 1);
+    assert_eq!(result.modified_count, 1);
+
+    let doc = coll.find_one(Some(doc! { "_id": 1 }), None).unwrap().unwrap();
+    let expected = vec![Bson::I32(95), Bson::I32(100), Bson::I32(90)];
+    assert_eq!(doc.get("grades"), Some(&Bson::Array(expected)));
+
+    // Only the filtered document was updated.
+    let doc = coll.find_one(Some(doc! { "_id": 2 }), None).unwrap().unwrap();
+    let expected = vec![Bson::I32(98), Bson::I32(100), Bson::I32(102)];
+    assert_eq!(doc.get("grades"), Some(&Bson::Array(expected)));
+}
+
+#[test]
+fn bulk_invalid_models() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-bulk");
+    let coll = db.collection("bulk_invalid_models");
+    coll.drop().unwrap();
+
+    let insert = WriteModel::InsertOne { document: doc! { "_id": 1 } };
+    let invalid = vec![
+        WriteModel::UpdateOne {
+            filter: doc! { "_id": 1 },
+            update: doc! { "x": 1 },
+            upsert: None,
+            collation: None,
+            hint: None,
+            array_filters: None,
+        },
+        WriteModel::UpdateMany {
+            filter: doc! {},
+            update: doc! {},
+            upsert: None,
+            collation: None,
+            hint: None,
+            array_filters: None,
+        },
+        WriteModel::ReplaceOne {
+            filter: doc! { "_id": 1 },
+            replacement: doc! { "$set": { "x": 1 } },
+            upsert: None,
+            collation: None,
+            hint: None,
+        },
+        WriteModel::ReplaceOne {
+            filter: doc! {},
+            replacement: doc! { "x": 1 },
+            upsert: None,
+            collation: None,
+            hint: None,
+        },
+    ];
+
+    for model in invalid {
+        for &ordered in &[true, false] {
+            let result = coll.bulk_write(vec![insert.clone(), model.clone()], ordered);
+            let exception = result.bulk_write_exception.expect("Expected a bulk write exception.");
+            assert!(exception.message.contains("Write 1 is invalid"), "{}", exception.message);
+            assert_eq!(exception.unprocessed_requests.len(), 2);
+            assert_eq!(result.inserted_count, 0);
+        }
+    }
+
+    // None of the writes were sent.
+    assert_eq!(coll.count(None, None).unwrap(), 0);
+}
+
+#[test]
+fn bulk_fluent_ordered() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-bulk");
+    let coll = db.collection("bulk_fluent_ordered");
+    coll.drop().unwrap();
+
+    let mut bulk = coll.initialize_ordered_bulk_op();
+    bulk.insert(doc! { "_id": 1, "x": 1 });
+    bulk.insert(doc! { "_id": 2, "x": 2 });
+    bulk.find(doc! { "_id": 3 }).upsert().update_one(doc! { "$set": { "x": 3 } });
+    bulk.find(doc! { "x": { "$lte": 2 } }).update(doc! { "$inc": { "x": 10 } });
+    bulk.find(doc! { "_id": 2 }).replace_one(doc! { "x": 20 });
+    bulk.find(doc! { "_id": 1 }).remove_one();
+    bulk.insert(doc! { "_id": 4, "x": 4 });
+
+    assert!(bulk.is_ordered());
+    assert_eq!(bulk.len(), 7);
+
+    let result = bulk.execute(None).unwrap();
+    assert!(result.bulk_write_exception.is_none());
+    assert_eq!(result.inserted_count, 3);
+    assert_eq!(result.upserted_count, 1);
+    assert_eq!(result.modified_count, 3);
+    assert_eq!(result.deleted_count, 1);
+
+    // Ids are keyed by the position of their write.
+    assert_eq!(result.inserted_ids.get(&0), Some(&Bson::I32(1)));
+    assert_eq!(result.inserted_ids.get(&6), Some(&Bson::I32(4)));
+    assert_eq!(result.upserted_ids.get(&2), Some(&Bson::I32(3)));
+
+    let docs: Vec<_> = coll.find(None, None).unwrap().map(|doc| doc.unwrap()).collect();
+    assert_eq!(docs, vec![
+        doc! { "_id": 2, "x": 20 },
+        doc! { "_id": 3, "x": 3 },
+        doc! { "_id": 4, "x": 4 },
+    ]);
+}
+
+#[test]
+fn bulk_fluent_unordered_error_indexes() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-bulk");
+    let coll = db.collection("bulk_fluent_unordered_error_indexes");
+    coll.drop().unwrap();
+    coll.insert_one(doc! { "_id": 1 }, None).unwrap();
+
+    let mut bulk = coll.initialize_unordered_bulk_op();
+    bulk.find(doc! { "_id": 1 }).remove();
+    bulk.find(doc! { "_id": 5 }).upsert().update_one(doc! { "$set": { "x": 5 } });
+    bulk.insert(doc! { "_id": 2 });
+    bulk.insert(doc! { "_id": 2 });
+
+    let result = bulk.execute(None).unwrap();
+
+    // Unordered writes are grouped by kind, but are still reported by their position.
+    assert_eq!(result.inserted_ids.get(&2), Some(&Bson::I32(2)));
+    assert_eq!(result.upserted_ids.get(&1), Some(&Bson::I32(5)));
+
+    let exception = result.bulk_write_exception.expect("Expected a bulk write exception.");
+    assert_eq!(exception.write_errors.len(), 1);
+    assert_eq!(exception.write_errors[0].index, 3);
+    assert_eq!(exception.write_errors[0].code, 11000);
+}
+
+#[test]
+fn bulk_fluent_empty() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-bulk").collection("bulk_fluent_empty");
+
+    let bulk = coll.initialize_ordered_bulk_op();
+    assert!(bulk.is_empty());
+
+    match bulk.execute(None) {
+        Err(Error::ArgumentError(ref msg)) => assert!(msg.contains("at least one operation")),
+        other => panic!("Expected an ArgumentError, got {:?}", other),
+    }
+}
@@ -0,0 +1,183 @@
+use bson::{self, Bson};
+use mongodb::{Client, ThreadedClient};
+use mongodb::topology::capabilities::ServerCapabilities;
+use mongodb::topology::monitor::IsMasterResult;
+
+// The parts of the isMaster reply of a standalone server of the given version that capabilities
+// are built from, with int32 values as servers send them.
+fn is_master(version: &str) -> bson::Document {
+    let mut doc = doc! {
+        "ismaster": true,
+        "maxBsonObjectSize": 16777216,
+        "maxMessageSizeBytes": 48000000,
+        "ok": 1.0,
+    };
+
+    let max_wire_version = match version {
+        "2.4" => return doc,
+        "2.6" => 2,
+        "3.0" => 3,
+        "3.2" => 4,
+        "3.4" => 5,
+        "3.6" => 6,
+        "4.0" => 7,
+        "4.2" => 8,
+        "4.4" => 9,
+        _ => panic!("No isMaster reply for MongoDB {}", version),
+    };
+
+    doc.insert("minWireVersion", 0);
+    doc.insert("maxWireVersion", max_wire_version);
+
+    if max_wire_version < 6 {
+        doc.insert("maxWriteBatchSize", 1000);
+    } else {
+        doc.insert("maxWriteBatchSize", 100000);
+        doc.insert("logicalSessionTimeoutMinutes", 30);
+    }
+
+    doc
+}
+
+fn capabilities(version: &str) -> ServerCapabilities {
+    ServerCapabilities::from_is_master(&IsMasterResult::new(is_master(version)).unwrap())
+}
+
+// Whether each feature is supported, in the order the features were added.
+fn features(capabilities: &ServerCapabilities) -> Vec<bool> {
+    vec![
+        capabilities.supports_write_commands(),
+        capabilities.supports_list_commands(),
+        capabilities.supports_find_command(),
+        capabilities.supports_current_op_command(),
+        capabilities.supports_read_concern(),
+        capabilities.supports_sample(),
+        capabilities.supports_index_stats(),
+        capabilities.supports_partial_indexes(),
+        capabilities.supports_collation(),
+        capabilities.supports_views(),
+        capabilities.supports_aggregate_write_concern(),
+        capabilities.supports_op_msg(),
+        capabilities.supports_array_filters(),
+        capabilities.supports_sessions(),
+        capabilities.supports_list_databases_filter(),
+        capabilities.supports_list_databases_name_only(),
+        capabilities.supports_list_collections_name_only(),
+        capabilities.supports_update_hint(),
+        capabilities.supports_delete_hint(),
+        capabilities.supports_hidden_indexes(),
+        capabilities.supports_get_more_comment(),
+    ]
+}
+
+#[test]
+fn capabilities_by_server_version() {
+    let versions = ["2.4", "2.6", "3.0", "3.2", "3.4", "3.6", "4.0", "4.2", "4.4"];
+    // How many of `features` each version supports.
+    let supported = [0, 1, 2, 8, 11, 15, 17, 18, 21];
+
+    for (version, &supported) in versions.iter().zip(supported.iter()) {
+        let capabilities = capabilities(version);
+        let expected: Vec<_> = (0..21).map(|i| i < supported).collect();
+        assert_eq!(features(&capabilities), expected, "MongoDB {}", version);
+        assert_eq!(capabilities.max_bson_size, 16 * 1024 * 1024);
+        assert_eq!(capabilities.max_message_size, 48000000);
+    }
+
+    let legacy = capabilities("2.4");
+    assert_eq!(legacy.max_wire_version, -1);
+    assert_eq!(legacy.max_write_batch_size, 1000);
+    assert_eq!(legacy, ServerCapabilities::new());
+
+    assert_eq!(capabilities("3.4").max_write_batch_size, 1000);
+    assert_eq!(capabilities("3.6").max_write_batch_size, 100000);
+    assert_eq!(capabilities("4.4").logical_session_timeout_minutes, Some(30));
+    assert_eq!(capabilities("4.4").max_wire_version, 9);
+}
+
+#[test]
+fn capabilities_from_int64_fields() {
+    let mut doc = is_master("3.6");
+    doc.insert("maxWireVersion", Bson::I64(6));
+    doc.insert("maxBsonObjectSize", Bson::I64(1024));
+
+    let capabilities = ServerCapabilities::from_is_master(&IsMasterResult::new(doc).unwrap());
+    assert_eq!(capabilities.max_wire_version, 6);
+    assert_eq!(capabilities.max_bson_size, 1024);
+}
+
+#[test]
+fn snapshot_queries_removed() {
+    assert!(capabilities("2.6").supports_snapshot_queries());
+    assert!(capabilities("3.4").supports_snapshot_queries());
+    assert!(!capabilities("3.6").supports_snapshot_queries());
+    assert!(!capabilities("4.4").supports_snapshot_queries());
+}
+
+#[test]
+fn features_of_later_versions() {
+    // MongoDB 5.0 stores dotted keys, 5.1 removed legacy writes and 5.3 clusters collections.
+    let mut capabilities = capabilities("4.4");
+    let later_features = |capabilities: &ServerCapabilities| {
+        (
+            capabilities.supports_dotted_keys(),
+            capabilities.supports_legacy_writes(),
+            capabilities.supports_clustered_collections(),
+        )
+    };
+    assert_eq!(later_features(&capabilities), (false, true, false));
+
+    capabilities.max_wire_version = 13;
+    assert_eq!(later_features(&capabilities), (true, true, false));
+    capabilities.max_wire_version = 14;
+    assert_eq!(later_features(&capabilities), (true, false, false));
+    capabilities.max_wire_version = 16;
+    assert_eq!(later_features(&capabilities), (true, false, true));
+}
+
+#[test]
+fn mixed_version_capabilities() {
+    // A replica set being upgraded from 3.4 to 3.6 has neither sessions nor array filters
+    // until every member is upgraded.
+    let mut newer = capabilities("3.6");
+    newer.max_bson_size = 1024;
+    let older = capabilities("3.4");
+
+    let common = newer.common(&older);
+    assert_eq!(common, older.common(&newer));
+    assert_eq!(common.max_wire_version, 5);
+    assert_eq!(common.max_bson_size, 1024);
+    assert_eq!(common.max_write_batch_size, 1000);
+    assert_eq!(common.logical_session_timeout_minutes, None);
+    assert!(common.supports_collation());
+    assert!(!common.supports_sessions());
+    assert!(!common.supports_array_filters());
+
+    let mut shorter = capabilities("4.0");
+    shorter.logical_session_timeout_minutes = Some(10);
+    let common = capabilities("4.4").common(&shorter);
+    assert_eq!(common.max_wire_version, 7);
+    assert_eq!(common.logical_session_timeout_minutes, Some(10));
+    assert!(common.supports_sessions());
+    assert!(!common.supports_update_hint());
+
+    // A server that only speaks newer wire versions raises the common minimum.
+    let mut newest = capabilities("4.4");
+    newest.min_wire_version = 6;
+    assert_eq!(newest.common(&capabilities("4.2")).min_wire_version, 6);
+}
+
+#[test]
+fn client_capabilities() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    client.is_master().expect("Failed to execute is_master.");
+
+    let capabilities = client.capabilities().unwrap().expect("No server was checked.");
+    assert!(capabilities.supports_write_commands());
+    assert!(capabilities.max_bson_size > 0);
+    assert!(capabilities.max_write_batch_size >= 1000);
+
+    let info = client.topology_info().unwrap();
+    let max_wire_version = info.servers.iter().map(|server| server.max_wire_version).min();
+    assert_eq!(Some(capabilities.max_wire_version), max_wire_version);
+}
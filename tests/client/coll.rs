@@ -1,9 +1,27 @@
-use bson::Bson;
-
-use mongodb::{Client, ThreadedClient};
-use mongodb::db::ThreadedDatabase;
-use mongodb::coll::options::{FindOptions, FindOneAndUpdateOptions, IndexModel, IndexOptions,
-                             ReturnDocument};
+use bson::{Bson, Document};
+use bson::oid::ObjectId;
+use bson::spec::BinarySubtype;
+
+use mongodb::{Client, CommandStarted, CommandType, Error, ThreadedClient};
+use chrono::{self, TimeZone, Utc};
+use mongodb::common::{ReadMode, ReadPreference, WriteConcern};
+use mongodb::db::{Database, ThreadedDatabase};
+use mongodb::db::options::CreateCollectionOptions;
+use mongodb::coll::Collection;
+use mongodb::coll::index_stats::IndexStats;
+use mongodb::coll::results::ValidateResult;
+use mongodb::coll::options::{AggregateOptions, Collation, CopyOptions, CountOptions,
+                             DeleteOptions, DistinctOptions, FieldPath, FindOptions,
+                             FindOneAndUpdateOptions, Hint, IndexCopy, IndexModel, IndexOptions,
+                             InsertManyOptions, Projection, ReturnDocument, ShardKeyOptions,
+                             SnapshotOptions, UpdateOptions, WriteModel};
+
+use std::env;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
 
 #[test]
 fn find_sorted() {
@@ -22,8 +40,7 @@ fn find_sorted() {
         .expect("Failed to insert documents.");
 
     // Find document
-    let mut opts = FindOptions::new();
-    opts.sort = Some(doc! { "title": 1 });
+    let opts = FindOptions::builder().sort(doc! { "title": 1 }).build();
 
     let mut cursor = coll.find(None, Some(opts)).expect(
         "Failed to execute find command.",
@@ -200,8 +217,7 @@ fn find_one_and_replace() {
     };
 
     // Replace with 'new' option
-    let mut opts = FindOneAndUpdateOptions::new();
-    opts.return_document = Some(ReturnDocument::After);
+    let opts = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
     let result = coll.find_one_and_replace(doc3.clone(), doc2.clone(), Some(opts))
         .expect("Failed to execute find_one_and_replace command.");
 
@@ -304,6 +320,40 @@ fn aggregate() {
     assert!(vec.contains(&"f".to_owned()));
 }
 
+#[test]
+fn aggregate_into() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("aggregate_into");
+    coll.drop().unwrap();
+    db.collection("aggregate_into_out").drop().unwrap();
+
+    let docs = (0..10).map(|i| doc! { "_id": i, "even": i % 2 == 0 }).collect();
+    coll.insert_many(docs, None).unwrap();
+
+    let pipeline = vec![
+        doc! { "$match": { "even": true } },
+        doc! { "$out": "aggregate_into_out" },
+    ];
+    let out = coll.aggregate_into(pipeline.clone(), None).expect("Failed to aggregate.");
+    assert_eq!(out.namespace, "test-client-coll.aggregate_into_out");
+    assert_eq!(out.count(None, None).unwrap(), 5);
+
+    // Output stages must run on the primary.
+    let options = AggregateOptions::builder()
+        .read_preference(ReadPreference::new(ReadMode::Secondary, None))
+        .build();
+    match coll.aggregate_into(pipeline, Some(options)) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected ArgumentError, got {:?}", other),
+    }
+
+    match coll.aggregate_into(vec![doc! { "$match": { "even": true } }], None) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected ArgumentError, got {:?}", other),
+    }
+}
+
 #[test]
 fn count() {
     let client = Client::connect("localhost", 27017).unwrap();
@@ -344,6 +394,50 @@ fn count() {
     assert_eq!(0, count_none);
 }
 
+#[test]
+fn count_documents() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("count_documents");
+    coll.drop().unwrap();
+
+    let docs: Vec<_> = (0..10)
+        .map(|i| {
+            let even = i % 2 == 0;
+            doc! { "_id": i, "even": even }
+        })
+        .collect();
+    coll.insert_many(docs, None).unwrap();
+
+    assert_eq!(coll.count_documents(None, None).unwrap(), 10);
+    assert_eq!(coll.count_documents(Some(doc! { "even": true }), None).unwrap(), 5);
+    assert_eq!(coll.estimated_document_count(None).unwrap(), 10);
+
+    // Skip and limit apply to the matching documents, skip first.
+    let options = CountOptions::builder().skip(2).limit(2).build();
+    assert_eq!(coll.count_documents(Some(doc! { "even": true }), Some(options)).unwrap(), 2);
+
+    let options = CountOptions::builder().skip(4).limit(3).build();
+    assert_eq!(coll.count_documents(Some(doc! { "even": true }), Some(options)).unwrap(), 1);
+
+    let options = CountOptions::builder().skip(8).limit(0).build();
+    assert_eq!(coll.count_documents(None, Some(options)).unwrap(), 2);
+
+    let options = CountOptions::builder().limit(-3).max_time_ms(10000).build();
+    assert_eq!(coll.count_documents(None, Some(options)).unwrap(), 3);
+
+    // Nothing left to group is a count of 0 rather than an error.
+    let options = CountOptions::builder().skip(20).build();
+    assert_eq!(coll.count_documents(None, Some(options)).unwrap(), 0);
+    assert_eq!(coll.count_documents(Some(doc! { "_id": 100 }), None).unwrap(), 0);
+
+    let options = CountOptions::builder().skip(-1).build();
+    match coll.count_documents(None, Some(options)) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected an ArgumentError, got {:?}", other),
+    }
+}
+
 #[test]
 fn distinct_none() {
     let client = Client::connect("localhost", 27017).unwrap();
@@ -689,8 +783,7 @@ fn create_list_drop_indexes() {
 
     coll.drop().expect("Failed to drop database.");
 
-    let mut opts1 = IndexOptions::new();
-    opts1.name = Some("nid".to_owned());
+    let opts1 = IndexOptions::builder().name("nid".to_owned()).build();
 
     // Test name option
     let index1 = IndexModel::new(
@@ -777,6 +870,103 @@ fn create_list_drop_indexes() {
     assert_eq!(1, results.len());
 }
 
+#[test]
+fn list_indexes_in_batches() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("list_indexes_in_batches");
+    coll.drop().unwrap();
+
+    for i in 0..10 {
+        let mut keys = Document::new();
+        keys.insert(format!("field{}", i), 1);
+        coll.create_index(keys, None).unwrap();
+    }
+
+    client.enable_slow_op_capture(0, 100);
+    let names: Vec<_> = coll.list_indexes_with_batch_size(2)
+        .unwrap()
+        .map(|index| index.unwrap().get_str("name").unwrap().to_owned())
+        .collect();
+    let ops = client.drain_slow_ops();
+    client.disable_slow_op_capture();
+
+    assert_eq!(11, names.len());
+    assert!(ops.iter().any(|op| op.command_name == "getMore"));
+    assert!(names.contains(&String::from("_id_")));
+    assert!(names.contains(&String::from("field9_1")));
+}
+
+#[test]
+fn list_indexes_without_collection() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("list_indexes_without_collection");
+    coll.drop().unwrap();
+
+    assert_eq!(coll.list_indexes().unwrap().count(), 0);
+}
+
+// Checks the helpers that look documents up by `_id` on a collection that may have no `_id`
+// index.
+fn check_without_id_index(coll: &Collection) {
+    coll.create_index(doc! { "x": 1 }, None).unwrap();
+    coll.drop_indexes().unwrap();
+    coll.drop_indexes().unwrap();
+
+    let names: Vec<_> = coll.list_indexes()
+        .unwrap()
+        .map(|index| index.unwrap().get_str("name").unwrap().to_owned())
+        .collect();
+    assert!(!names.contains(&String::from("x_1")));
+
+    let result = coll.save(doc! { "x": 1 }, None).unwrap();
+    let id = result.upserted_id.expect("Expected the saved document to be inserted.");
+
+    let result = coll.save(doc! { "_id": id.clone(), "x": 2 }, None).unwrap();
+    assert_eq!(result.matched_count, 1);
+    assert!(result.upserted_id.is_none());
+
+    let doc = coll.find_one_by_id(id.clone(), None).unwrap().unwrap();
+    assert_eq!(doc.get("x"), Some(&Bson::I32(2)));
+    assert_eq!(coll.count(None, None).unwrap(), 1);
+}
+
+#[test]
+fn collection_without_id_index() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    db.drop_collection("collection_without_id_index").unwrap();
+
+    let mut options = CreateCollectionOptions::new();
+    options.auto_index_id = Some(false);
+
+    // MongoDB 4.0 and later only allow autoIndexId in the local database.
+    if db.create_collection("collection_without_id_index", Some(options)).is_err() {
+        return;
+    }
+
+    let coll = db.collection("collection_without_id_index");
+    assert_eq!(coll.list_indexes().unwrap().count(), 0);
+    check_without_id_index(&coll);
+}
+
+#[test]
+fn clustered_collection() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    db.drop_collection("clustered_collection").unwrap();
+
+    let mut options = CreateCollectionOptions::new();
+    options.clustered_index = Some(doc! { "key": { "_id": 1 }, "unique": true });
+
+    match db.create_collection("clustered_collection", Some(options)) {
+        Ok(()) => check_without_id_index(&db.collection("clustered_collection")),
+        Err(Error::ArgumentError(ref msg)) => assert!(msg.contains("5.3"), "{}", msg),
+        Err(err) => panic!("Expected a version error, got {:?}", err),
+    }
+}
+
 #[test]
 fn create_text_hashed_2d_2dsphere_index() {
     let client = Client::connect("localhost", 27017).unwrap();
@@ -815,6 +1005,62 @@ fn block_ill_formed_index_models() {
     );
 }
 
+#[test]
+fn index_model_builder() {
+    let model = IndexModel::builder()
+        .asc("a")
+        .desc("b")
+        .hashed("c")
+        .partial_filter(doc! { "a": { "$gt": 5 } })
+        .expire_after(3600)
+        .hidden(true)
+        .build();
+
+    assert_eq!(model.keys, doc! { "a": 1, "b": -1, "c": "hashed" });
+    assert_eq!(model.name().unwrap(), "a_1_b_-1_c_hashed");
+    assert!(model.is_compound_hashed());
+    assert!(!IndexModel::builder().hashed("c").build().is_compound_hashed());
+
+    let doc = model.to_bson().unwrap();
+    assert_eq!(doc.get_str("name").unwrap(), "a_1_b_-1_c_hashed");
+    assert_eq!(doc.get_document("partialFilterExpression").unwrap(), &doc! { "a": { "$gt": 5 } });
+    assert_eq!(doc.get_i32("expireAfterSeconds").unwrap(), 3600);
+    assert!(doc.get_bool("hidden").unwrap());
+
+    let named = IndexModel::builder().asc("a").name("by_a").build();
+    assert_eq!(named.name().unwrap(), "by_a");
+}
+
+#[test]
+fn create_indexes_with_builder() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-coll").collection("create_indexes_with_builder");
+    coll.drop().unwrap();
+
+    let compound = IndexModel::builder()
+        .asc("a")
+        .desc("b")
+        .partial_filter(doc! { "a": { "$gt": 5 } })
+        .build();
+    let hashed = IndexModel::builder().hashed("c").build();
+
+    let names = coll.create_indexes(vec![compound.clone(), hashed.clone()]).unwrap();
+    assert_eq!(names, vec!["a_1_b_-1", "c_hashed"]);
+
+    let indexes: Vec<_> = coll.list_indexes().unwrap().map(|index| index.unwrap()).collect();
+    let partial = indexes
+        .iter()
+        .find(|index| index.get_str("name").ok() == Some("a_1_b_-1"))
+        .expect("Compound index not found.");
+    assert_eq!(partial.get_document("key").unwrap(), &doc! { "a": 1, "b": -1 });
+    assert!(partial.contains_key("partialFilterExpression"));
+
+    // The generated names match the server's, so indexes can be dropped by their models.
+    coll.drop_index_model(compound).unwrap();
+    coll.drop_index_model(hashed).unwrap();
+    assert_eq!(coll.list_indexes().unwrap().count(), 1);
+}
+
 #[test]
 fn create_query_text_index() {
     let client = Client::connect("localhost", 27017).unwrap();
@@ -823,11 +1069,12 @@ fn create_query_text_index() {
 
     coll.drop().expect("Failed to drop collection");
 
-    let mut index_opt = IndexOptions::new();
-    index_opt.weights = Some(doc!{
-        "title": 10,
-        "content": 5
-    });
+    let index_opt = IndexOptions::builder()
+        .weights(doc!{
+            "title": 10,
+            "content": 5
+        })
+        .build();
     coll.create_index(
         doc!{
             "title": "text",
@@ -847,9 +1094,10 @@ fn create_query_text_index() {
         .unwrap();
     assert_eq!(count, 2);
 
-    let mut opts = FindOptions::new();
-    opts.projection = Some(doc!{ "score": { "$meta": "textScore" }});
-    opts.sort = Some(doc!{ "score": { "$meta": "textScore" }});
+    let opts = FindOptions::builder()
+        .projection(doc!{ "score": { "$meta": "textScore" }})
+        .sort(doc!{ "score": { "$meta": "textScore" }})
+        .build();
 
     let mut cursor = coll.find(
         Some(doc!{ "$text": { "$search": "keyword" }}),
@@ -882,8 +1130,7 @@ fn drop_all_indexes() {
 
     coll.drop().expect("Failed to drop database.");
 
-    let mut opts1 = IndexOptions::new();
-    opts1.name = Some("nid".to_owned());
+    let opts1 = IndexOptions::builder().name("nid".to_owned()).build();
 
     // Test name option
     let index1 = IndexModel::new(
@@ -910,3 +1157,1244 @@ fn drop_all_indexes() {
 
     assert_eq!(1, results.len());
 }
+
+#[test]
+fn parallel_scan() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("parallel_scan");
+
+    coll.drop().expect("Failed to drop collection");
+
+    let docs = (0..1000).map(|i| doc! { "_id": i }).collect();
+    coll.insert_many(docs, None).expect("Failed to insert documents");
+
+    let cursors = coll.parallel_scan(4).expect("Failed to execute parallel scan");
+    assert!(!cursors.is_empty() && cursors.len() <= 4);
+
+    // On mmapv1 the documents are split between the cursors; every document must be seen
+    // exactly once regardless of how many cursors the server returns.
+    let mut ids: Vec<i32> = cursors
+        .into_iter()
+        .flat_map(|cursor| cursor)
+        .map(|doc| match doc.expect("Failed to get next document").get("_id") {
+            Some(&Bson::I32(id)) => id,
+            _ => panic!("Expected _id to be an i32"),
+        })
+        .collect();
+
+    ids.sort();
+    assert_eq!(ids, (0..1000).collect::<Vec<_>>());
+}
+
+#[test]
+fn scan_with() {
+    use std::sync::{Arc, Mutex};
+
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("scan_with");
+
+    coll.drop().expect("Failed to drop collection");
+
+    let docs = (0..1000).map(|i| doc! { "_id": i }).collect();
+    coll.insert_many(docs, None).expect("Failed to insert documents");
+
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let task_seen = seen.clone();
+
+    let results = coll.scan_with(4, move |doc| {
+        if let Some(&Bson::I32(id)) = doc.get("_id") {
+            task_seen.lock().unwrap().push(id);
+        }
+        Ok(())
+    }).expect("Failed to execute parallel scan");
+
+    for result in results {
+        result.expect("Scan task failed");
+    }
+
+    let mut ids = seen.lock().unwrap().clone();
+    ids.sort();
+    assert_eq!(ids, (0..1000).collect::<Vec<_>>());
+}
+
+#[test]
+fn watch() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("watch");
+
+    coll.drop().expect("Failed to drop collection");
+    coll.insert_one(doc! { "_id": 0 }, None).expect("Failed to insert document");
+
+    // Change streams are only supported on replica sets running MongoDB 3.6 or later.
+    let mut stream = match coll.watch(None, None) {
+        Ok(stream) => stream,
+        Err(_) => return,
+    };
+
+    assert!(stream.resume_token().is_none());
+
+    coll.insert_one(doc! { "_id": 1 }, None).expect("Failed to insert document");
+
+    let event = loop {
+        if let Some(event) = stream.next() {
+            break event.expect("Failed to get change event");
+        }
+    };
+
+    assert_eq!(event.get("operationType"), Some(&Bson::String(String::from("insert"))));
+    match event.get("_id") {
+        Some(&Bson::Document(ref token)) => assert_eq!(stream.resume_token(), Some(token)),
+        _ => panic!("Expected change event to contain a resume token"),
+    }
+
+    coll.drop().expect("Failed to drop collection");
+
+    loop {
+        match stream.next() {
+            Some(Ok(_)) | None => continue,
+            Some(Err(Error::ChangeStreamInvalidatedError(_))) => break,
+            Some(Err(err)) => panic!("Unexpected error from change stream: {}", err),
+        }
+    }
+
+    assert!(stream.next().is_none());
+}
+
+#[test]
+fn find_by_ids() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("find_by_ids");
+
+    coll.drop().expect("Failed to drop collection");
+
+    let oid = Bson::ObjectId(ObjectId::new().unwrap());
+    let binary = Bson::Binary(BinarySubtype::Generic, b"binary id".to_vec());
+    let string = Bson::String(String::from("{ '_id': \"quoted\" }"));
+    let missing = Bson::ObjectId(ObjectId::new().unwrap());
+
+    coll.insert_many(
+        vec![
+            doc! { "_id": oid.clone(), "kind": "oid" },
+            doc! { "_id": binary.clone(), "kind": "binary" },
+            doc! { "_id": string.clone(), "kind": "string" },
+        ],
+        None,
+    ).expect("Failed to insert documents.");
+
+    let doc = coll.find_one_by_id(binary.clone(), None)
+        .expect("Failed to execute find_one_by_id.")
+        .expect("Expected a document.");
+    assert_eq!(Some(&Bson::String(String::from("binary"))), doc.get("kind"));
+
+    assert!(coll.find_one_by_id(missing.clone(), None).unwrap().is_none());
+
+    let ids = vec![string.clone(), missing, oid.clone(), string, binary];
+    let results = coll.find_by_ids(ids, None).expect("Failed to execute find_by_ids.");
+
+    let kinds: Vec<_> = results
+        .iter()
+        .map(|result| {
+            result.as_ref().map(|doc| match doc.get("kind") {
+                Some(&Bson::String(ref kind)) => kind.to_owned(),
+                _ => panic!("Expected Bson::String!"),
+            })
+        })
+        .collect();
+
+    let expected = vec![
+        Some(String::from("string")),
+        None,
+        Some(String::from("oid")),
+        Some(String::from("string")),
+        Some(String::from("binary")),
+    ];
+    assert_eq!(expected, kinds);
+
+    assert!(coll.find_by_ids(Vec::new(), None).unwrap().is_empty());
+}
+
+#[test]
+fn get_collection_validation() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+
+    assert!(db.get_collection("get_collection_validation").is_ok());
+    assert!(db.get_collection("oplog.$main").is_ok());
+    assert!(db.get_collection("nested.collection").is_ok());
+
+    for name in vec!["", "my$coll", "$cmd", "my\0coll"] {
+        assert!(db.get_collection(name).is_err(), "{:?} should be invalid", name);
+    }
+
+    // System collections can be read, but not written to.
+    let profile = db.get_collection("system.profile").unwrap();
+    profile.find_one(None, None).unwrap();
+    match profile.insert_one(doc! { "x": 1 }, None) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected ArgumentError, got {:?}", other),
+    }
+    assert!(profile.delete_many(doc! {}, None).is_err());
+
+    // Special namespaces remain available through the unchecked constructor.
+    let cmd = Collection::new_unchecked(db.clone(), "$cmd", false, None, None);
+    assert_eq!("test-client-coll.$cmd", cmd.namespace);
+}
+
+#[test]
+#[should_panic(expected = "cannot contain '$'")]
+fn collection_panics_on_invalid_names() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    client.db("test-client-coll").collection("my$coll");
+}
+
+fn unacknowledged() -> WriteConcern {
+    let mut write_concern = WriteConcern::new();
+    write_concern.w = 0;
+    write_concern
+}
+
+#[test]
+fn insert_unacknowledged() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("insert_unacknowledged");
+    coll.drop().unwrap();
+
+    for i in 0..1000 {
+        coll.insert_one(doc! { "_id": i }, Some(unacknowledged()))
+            .expect("Failed to insert document.");
+    }
+
+    let docs = (1000..11000).map(|i| doc! { "_id": i }).collect();
+    let options = InsertManyOptions::builder().write_concern(unacknowledged()).build();
+    let result = coll.insert_many(docs, Some(options)).expect(
+        "Failed to insert documents.",
+    );
+    assert_eq!(result.inserted_ids.map(|ids| ids.len()), Some(10000));
+
+    // Nothing waits for the writes, so give the server time to apply them.
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut count = 0;
+    while Instant::now() < deadline {
+        count = coll.count(None, None).unwrap();
+        if count == 11000 {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    assert_eq!(count, 11000);
+}
+
+#[test]
+fn update_and_delete_unacknowledged() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("update_and_delete_unacknowledged");
+    coll.drop().unwrap();
+
+    let docs = (0..100).map(|i| doc! { "_id": i, "even": i % 2 == 0 }).collect();
+    coll.insert_many(docs, None).unwrap();
+
+    let options = UpdateOptions::builder().write_concern(unacknowledged()).build();
+    let result = coll.update_many(
+        doc! { "even": true },
+        doc! { "$set": { "updated": true } },
+        Some(options),
+    ).unwrap();
+    assert!(!result.acknowledged);
+
+    let result = coll.delete_many(doc! { "even": false }, Some(unacknowledged())).unwrap();
+    assert!(!result.acknowledged);
+    assert_eq!(result.deleted_count, 0);
+
+    // Nothing waits for the writes, so give the server time to apply them.
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut counts = (0, 0);
+    while Instant::now() < deadline {
+        counts = (
+            coll.count(None, None).unwrap(),
+            coll.count(Some(doc! { "updated": true }), None).unwrap(),
+        );
+        if counts == (50, 50) {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    assert_eq!(counts, (50, 50));
+}
+
+fn max_wire_version(db: &Database) -> i32 {
+    let is_master = db.command(doc! { "isMaster": 1 }, CommandType::IsMaster, None).unwrap();
+    match is_master.get("maxWireVersion") {
+        Some(&Bson::I32(version)) => version,
+        _ => 0,
+    }
+}
+
+static HINTED_FIND: Mutex<Option<Document>> = Mutex::new(None);
+
+fn record_hinted_find(_client: Client, command_started: &CommandStarted) {
+    if command_started.command_name == "find" &&
+        command_started.command.get_str("find").ok() == Some("find_with_hint")
+    {
+        *HINTED_FIND.lock().unwrap() = Some(command_started.command.clone());
+    }
+}
+
+#[test]
+fn find_with_hint() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("find_with_hint");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let docs = (0..20).map(|i| doc! { "a": i, "b": i % 2 }).collect();
+    coll.insert_many(docs, None).expect("Failed to insert documents.");
+    coll.create_index(doc! { "a": 1 }, None).expect("Failed to create index.");
+    coll.create_index(doc! { "b": 1 }, None).expect("Failed to create index.");
+
+    // The optimizer would pick b_1 for this filter on its own.
+    let filter = doc! { "b": 1 };
+    let hint = Hint::Keys(doc! { "a": 1 });
+
+    client.add_start_hook(record_hinted_find).unwrap();
+
+    let results: Vec<_> = coll.find_with_hint(Some(filter.clone()), hint.clone(), None)
+        .expect("Failed to execute find.")
+        .map(|doc| doc.expect("Failed to get next document."))
+        .collect();
+    assert_eq!(results.len(), 10);
+
+    // Explaining the find the driver sent shows the server used the hinted index.
+    if max_wire_version(&db) >= 4 {
+        let find = HINTED_FIND.lock().unwrap().take().expect("The find wasn't monitored.");
+        assert_eq!(find.get("hint"), Some(&hint.to_bson()));
+
+        let explain = db.command(doc! { "explain": find }, CommandType::Suppressed, None)
+            .expect("Failed to explain query.");
+        let planner = match explain.get("queryPlanner") {
+            Some(&Bson::Document(ref planner)) => planner,
+            _ => panic!("Explain output is missing the query planner."),
+        };
+        let winning_plan = format!("{}", planner.get("winningPlan").unwrap());
+        assert!(winning_plan.contains("a_1"), "Hint not used: {}", winning_plan);
+        assert!(!winning_plan.contains("b_1"), "Hint not used: {}", winning_plan);
+    }
+
+    let count_options = CountOptions::builder().hint(String::from("a_1")).build();
+    let count = coll.count(Some(filter.clone()), Some(count_options))
+        .expect("Failed to execute count.");
+    assert_eq!(count, 10);
+
+    // A hint for an index that doesn't exist is rejected by the server, and the error names it.
+    let bad_hint = Hint::Name(String::from("c_1"));
+    match coll.find_with_hint(Some(filter.clone()), bad_hint.clone(), None) {
+        Err(Error::CommandError(ref err)) => assert!(err.message.contains("c_1"), "{}", err),
+        other => panic!("Expected CommandError, got {:?}", other.map(|_| ())),
+    }
+
+    let count_options = CountOptions::builder().hint_doc(doc! { "c": 1 }).build();
+    assert!(coll.count(Some(filter.clone()), Some(count_options)).is_err());
+
+    if max_wire_version(&db) >= 6 {
+        let aggregate_options = AggregateOptions::builder().hint(bad_hint.clone()).build();
+        let pipeline = vec![doc! { "$match": filter.clone() }];
+        assert!(coll.aggregate(pipeline, Some(aggregate_options)).is_err());
+    }
+}
+
+#[test]
+fn write_with_hint() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("write_with_hint");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let docs = (0..20).map(|i| doc! { "a": i, "b": i % 2 }).collect();
+    coll.insert_many(docs, None).expect("Failed to insert documents.");
+    coll.create_index(doc! { "a": 1 }, None).expect("Failed to create index.");
+
+    let wire_version = max_wire_version(&db);
+
+    let update_options = UpdateOptions::builder().hint(Hint::Name(String::from("a_1"))).build();
+    let update = coll.update_many(doc! { "b": 1 }, doc! { "$set": { "c": 1 } }, Some(update_options));
+
+    let delete_options = DeleteOptions::builder().hint(Hint::Keys(doc! { "a": 1 })).build();
+    let delete = coll.delete_many_with_options(doc! { "c": 1 }, Some(delete_options));
+
+    if wire_version < 8 {
+        match update {
+            Err(Error::ArgumentError(_)) => (),
+            other => panic!("Expected ArgumentError, got {:?}", other),
+        }
+    } else {
+        let result = update.expect("Failed to update documents.");
+        assert_eq!(result.modified_count, 10);
+    }
+
+    if wire_version < 9 {
+        match delete {
+            Err(Error::ArgumentError(_)) => (),
+            other => panic!("Expected ArgumentError, got {:?}", other),
+        }
+    } else {
+        let result = delete.expect("Failed to delete documents.");
+        assert_eq!(result.deleted_count, 10);
+    }
+}
+
+#[test]
+fn collation() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("collation");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let docs = vec![
+        doc! { "_id": 1, "name": "foo" },
+        doc! { "_id": 2, "name": "FOO" },
+        doc! { "_id": 3, "name": "Foo" },
+        doc! { "_id": 4, "name": "bar" },
+    ];
+    coll.insert_many(docs, None).expect("Failed to insert documents.");
+
+    // Strength 2 compares base characters and accents, but not case.
+    let mut collation = Collation::new("en");
+    collation.strength = Some(2);
+
+    let index_options = IndexOptions::builder().collation(collation.clone()).build();
+    let index = coll.create_index(doc! { "name": 1 }, Some(index_options));
+
+    let find_options = FindOptions::builder().collation(collation.clone()).build();
+    let find = coll.find(Some(doc! { "name": "foo" }), Some(find_options));
+
+    if max_wire_version(&db) < 5 {
+        match index {
+            Err(Error::ArgumentError(_)) => (),
+            other => panic!("Expected ArgumentError, got {:?}", other),
+        }
+        match find {
+            Err(Error::ArgumentError(ref msg)) => assert!(msg.contains("3.4")),
+            Err(err) => panic!("Expected ArgumentError, got {:?}", err),
+            Ok(_) => panic!("Expected ArgumentError, got a cursor"),
+        }
+        return;
+    }
+
+    index.expect("Failed to create index.");
+
+    let indexes: Vec<_> = coll.list_index_models()
+        .expect("Failed to list indexes.")
+        .map(|model| model.expect("Failed to parse index."))
+        .collect();
+    let index = indexes
+        .iter()
+        .find(|model| model.keys == doc! { "name": 1 })
+        .expect("Failed to find index.");
+    let index_collation = index.options.collation().expect("Index has no collation.");
+    assert_eq!(index_collation.locale, "en");
+    assert_eq!(index_collation.strength, Some(2));
+
+    let mut ids: Vec<_> = find.expect("Failed to execute find.")
+        .map(|doc| doc.expect("Failed to get next document.").get_i32("_id").unwrap())
+        .collect();
+    ids.sort();
+    assert_eq!(ids, vec![1, 2, 3]);
+
+    // Without a collation the match is case-sensitive.
+    let count = coll.count(Some(doc! { "name": "foo" }), None).expect("Failed to count.");
+    assert_eq!(count, 1);
+
+    let count_options = CountOptions::builder().collation(collation.clone()).build();
+    let count = coll.count(Some(doc! { "name": "foo" }), Some(count_options))
+        .expect("Failed to count.");
+    assert_eq!(count, 3);
+
+    let distinct_options = DistinctOptions::builder().collation(collation.clone()).build();
+    let values = coll.distinct("name", None, Some(distinct_options))
+        .expect("Failed to execute distinct.");
+    assert_eq!(values.len(), 2);
+
+    let find_and_update_options = FindOneAndUpdateOptions::builder()
+        .collation(collation.clone())
+        .sort(doc! { "_id": -1 })
+        .build();
+    let updated = coll.find_one_and_update(
+        doc! { "name": "FOO" },
+        doc! { "$set": { "found": true } },
+        Some(find_and_update_options),
+    ).expect("Failed to execute findOneAndUpdate.");
+    assert_eq!(updated.and_then(|doc| doc.get_i32("_id").ok()), Some(3));
+
+    let update_options = UpdateOptions::builder().collation(collation.clone()).build();
+    let result = coll.update_many(
+        doc! { "name": "fOo" },
+        doc! { "$set": { "updated": true } },
+        Some(update_options),
+    ).expect("Failed to update documents.");
+    assert_eq!(result.modified_count, 3);
+
+    let delete_options = DeleteOptions::builder().collation(collation).build();
+    let result = coll.delete_many_with_options(doc! { "name": "FoO" }, Some(delete_options))
+        .expect("Failed to delete documents.");
+    assert_eq!(result.deleted_count, 3);
+}
+
+#[test]
+fn update_with_array_filters() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("update_with_array_filters");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let doc = doc! {
+        "_id": 1,
+        "matrix": [
+            { "row": 0, "cells": [{ "col": 0, "v": 1 }, { "col": 1, "v": 2 }] },
+            { "row": 1, "cells": [{ "col": 0, "v": 3 }, { "col": 1, "v": 4 }] },
+        ],
+    };
+    coll.insert_one(doc, None).expect("Failed to insert document.");
+
+    // Sets matrix[1].cells[0].v through two levels of filtered positional operators.
+    let target = FieldPath::new("matrix").filtered("r").field("cells").filtered("c").field("v");
+    let update = doc! { "$set": target.to(30) };
+
+    let options = UpdateOptions::builder()
+        .array_filters(vec![
+            FieldPath::new("r").field("row").to(1),
+            FieldPath::new("c").field("col").to(0),
+        ])
+        .build();
+
+    let result = coll.update_one(doc! { "_id": 1 }, update.clone(), Some(options.clone()));
+
+    if max_wire_version(&db) < 6 {
+        match result {
+            Err(Error::ArgumentError(_)) => (),
+            other => panic!("Expected ArgumentError, got {:?}", other),
+        }
+        return;
+    }
+
+    let result = result.expect("Failed to update document.");
+    assert_eq!(result.matched_count, 1);
+    assert_eq!(result.modified_count, 1);
+
+    let expected = doc! {
+        "_id": 1,
+        "matrix": [
+            { "row": 0, "cells": [{ "col": 0, "v": 1 }, { "col": 1, "v": 2 }] },
+            { "row": 1, "cells": [{ "col": 0, "v": 30 }, { "col": 1, "v": 4 }] },
+        ],
+    };
+    let found = coll.find_one(Some(doc! { "_id": 1 }), None).expect("Failed to find document.");
+    assert_eq!(found, Some(expected));
+
+    // The same update through findAndModify, incrementing every matching cell.
+    let options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .array_filters(vec![FieldPath::new("c").field("col").to(1)])
+        .build();
+
+    let target = FieldPath::new("matrix").all_elements().field("cells").filtered("c").field("v");
+    let updated = coll.find_one_and_update(
+        doc! { "_id": 1 },
+        doc! { "$inc": target.to(10) },
+        Some(options),
+    ).expect("Failed to execute findOneAndUpdate.")
+        .expect("Failed to find document.");
+
+    let expected = doc! {
+        "_id": 1,
+        "matrix": [
+            { "row": 0, "cells": [{ "col": 0, "v": 1 }, { "col": 1, "v": 12 }] },
+            { "row": 1, "cells": [{ "col": 0, "v": 30 }, { "col": 1, "v": 14 }] },
+        ],
+    };
+    assert_eq!(updated, expected);
+}
+
+#[test]
+fn find_with_projection() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("find_with_projection");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let doc = doc! {
+        "_id": 1,
+        "a": 1,
+        "b": { "c": 2, "d": 3 },
+        "arr": [{ "x": 1 }, { "x": 2 }, { "x": 3 }],
+    };
+    coll.insert_one(doc, None).expect("Failed to insert document.");
+
+    let options = FindOptions::builder()
+        .projection(
+            Projection::include(vec!["a", "b.c"])
+                .exclude_id()
+                .slice("arr", -2)
+                .build()
+                .expect("Failed to build projection."),
+        )
+        .build();
+    let found = coll.find_one(None, Some(options)).expect("Failed to find document.");
+    assert_eq!(
+        found,
+        Some(doc! { "a": 1, "b": { "c": 2 }, "arr": [{ "x": 2 }, { "x": 3 }] })
+    );
+
+    let options = FindOneAndUpdateOptions::builder()
+        .return_document(ReturnDocument::After)
+        .projection(
+            Projection::new()
+                .elem_match("arr", doc! { "x": { "$gt": 1 } })
+                .build()
+                .expect("Failed to build projection."),
+        )
+        .build();
+    let updated = coll.find_one_and_update(
+        doc! { "_id": 1 },
+        doc! { "$set": { "a": 2 } },
+        Some(options),
+    ).expect("Failed to execute findOneAndUpdate.");
+    assert_eq!(updated, Some(doc! { "_id": 1, "arr": [{ "x": 2 }] }));
+}
+
+#[test]
+fn index_stats_from_documents() {
+    let docs = vec![
+        doc! {
+            "name": "a_1",
+            "key": { "a": 1 },
+            "host": "shard0:27018",
+            "shard": "shard0",
+            "accesses": { "ops": 3i64, "since": Utc.timestamp(1000, 0) },
+        },
+        doc! {
+            "name": "_id_",
+            "key": { "_id": 1 },
+            "host": "shard0:27018",
+            "shard": "shard0",
+            "accesses": { "ops": 0i64, "since": Utc.timestamp(1000, 0) },
+        },
+        doc! {
+            "name": "a_1",
+            "key": { "a": 1 },
+            "host": "shard1:27018",
+            "shard": "shard1",
+            "accesses": { "ops": 4, "since": Utc.timestamp(2000, 0) },
+        },
+    ];
+
+    let stats = IndexStats::from_documents(&docs).expect("Failed to parse index statistics.");
+    assert_eq!(stats.len(), 2);
+    assert_eq!(stats[0].name, "_id_");
+    assert_eq!(stats[0].hosts.len(), 1);
+
+    // Entries of different shards are merged, and counting is known to cover every shard
+    // from the latest start onwards.
+    let index = &stats[1];
+    assert_eq!(index.key, doc! { "a": 1 });
+    assert_eq!(index.ops, 7);
+    assert_eq!(index.since, Utc.timestamp(2000, 0));
+    assert_eq!(index.hosts.len(), 2);
+    assert_eq!(index.hosts[0].shard, Some(String::from("shard0")));
+    assert_eq!(index.hosts[1].ops, 4);
+
+    assert!(IndexStats::from_documents(&[doc! { "name": "a_1" }]).is_err());
+}
+
+#[test]
+fn index_stats_and_unused_indexes() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll-index_stats");
+    db.drop_database().unwrap();
+
+    if max_wire_version(&db) < 4 {
+        return;
+    }
+
+    let coll = db.collection("indexed");
+    coll.insert_one(doc! { "a": 1, "b": 1 }, None).unwrap();
+    coll.create_index(doc! { "a": 1 }, None).unwrap();
+    coll.create_index(doc! { "b": 1 }, None).unwrap();
+
+    let options = FindOptions::builder().hint(Hint::Name(String::from("a_1"))).build();
+    coll.find_one(Some(doc! { "a": 1 }), Some(options)).unwrap();
+
+    let stats = coll.index_stats().expect("Failed to get index statistics.");
+    let names: Vec<_> = stats.iter().map(|index| index.name.as_str()).collect();
+    assert_eq!(names, vec!["_id_", "a_1", "b_1"]);
+    assert!(stats[1].ops >= 1);
+    assert_eq!(stats[2].ops, 0);
+
+    let unused = db.unused_indexes(chrono::Duration::zero())
+        .expect("Failed to find unused indexes.");
+    assert_eq!(unused.len(), 1);
+    assert_eq!(unused[0].collection, "indexed");
+    assert_eq!(unused[0].stats.name, "b_1");
+
+    // The indexes were only just created, so they can't be unused for an hour yet.
+    let unused = db.unused_indexes(chrono::Duration::hours(1)).unwrap();
+    assert!(unused.is_empty());
+}
+
+#[test]
+fn validate_result_from_document() {
+    let doc = doc! {
+        "ns": "test.coll",
+        "nrecords": 3,
+        "nIndexes": 2i64,
+        "keysPerIndex": { "_id_": 3, "a_1": 2i64 },
+        "valid": false,
+        "warnings": [],
+        "errors": ["index a_1 is missing 1 key", 5],
+        "ok": 1.0,
+    };
+
+    let result = ValidateResult::new(doc.clone()).expect("Failed to parse validate reply.");
+    assert!(!result.valid);
+    assert_eq!(result.errors, vec![String::from("index a_1 is missing 1 key")]);
+    assert!(result.warnings.is_empty());
+    assert_eq!(result.nrecords, Some(3));
+    assert_eq!(result.nindexes, Some(2));
+    assert_eq!(result.keys_per_index.get("_id_"), Some(&3));
+    assert_eq!(result.keys_per_index.get("a_1"), Some(&2));
+    assert_eq!(result.doc, doc);
+
+    assert!(ValidateResult::new(doc! { "ns": "test.coll", "ok": 1.0 }).is_err());
+}
+
+#[test]
+fn validate() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("validate");
+    coll.drop().unwrap();
+
+    let docs = (0..10).map(|i| doc! { "_id": i, "a": i }).collect();
+    coll.insert_many(docs, None).unwrap();
+    coll.create_index(doc! { "a": 1 }, None).unwrap();
+
+    for &full in &[false, true] {
+        let result = coll.validate(full).expect("Failed to validate collection.");
+        assert!(result.valid);
+        assert!(result.errors.is_empty());
+        assert_eq!(result.nrecords, Some(10));
+        assert_eq!(result.nindexes, Some(2));
+        assert_eq!(result.keys_per_index.len(), 2);
+        assert!(result.keys_per_index.values().all(|&keys| keys == 10));
+    }
+
+    match db.collection("validate_missing").validate(false) {
+        Err(Error::CommandError(_)) => (),
+        other => panic!("Expected CommandError, got {:?}", other),
+    }
+}
+
+fn require_tenant(doc: &Document) -> Result<(), String> {
+    if doc.contains_key("tenant_id") {
+        Ok(())
+    } else {
+        Err(String::from("missing tenant_id"))
+    }
+}
+
+#[test]
+fn write_validators() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll-validators");
+    let mut coll = db.collection("write_validators");
+    coll.drop().unwrap();
+
+    coll.set_write_validator(require_tenant);
+    coll.set_write_validator(|doc| match doc.get("tenant_id") {
+        Some(&Bson::I32(id)) if id < 0 => Err(String::from("negative tenant_id")),
+        _ => Ok(()),
+    });
+
+    coll.insert_one(doc! { "_id": 1, "tenant_id": 1 }, None).unwrap();
+
+    let docs = vec![doc! { "_id": 2, "tenant_id": 1 }, doc! { "_id": 3 }];
+    match coll.insert_many(docs, None) {
+        Err(Error::ArgumentError(ref message)) => {
+            assert!(message.contains("Write 1"));
+            assert!(message.contains("missing tenant_id"));
+        }
+        other => panic!("Expected ArgumentError, got {:?}", other),
+    }
+
+    // Validators run in order, and rejected batches are not sent at all.
+    match coll.insert_one(doc! { "_id": 4, "tenant_id": -1 }, None) {
+        Err(Error::ArgumentError(ref message)) => assert!(message.contains("negative")),
+        other => panic!("Expected ArgumentError, got {:?}", other),
+    }
+    assert_eq!(coll.count(None, None).unwrap(), 1);
+
+    // Updates are validated through their $set and $setOnInsert payloads.
+    let update = doc! { "$set": { "tenant_id": -1 } };
+    assert!(coll.update_one(doc! { "_id": 1 }, update, None).is_err());
+    let update = doc! { "$inc": { "n": 1 } };
+    coll.update_one(doc! { "_id": 1 }, update, None).unwrap();
+    assert!(coll.replace_one(doc! { "_id": 1 }, doc! { "x": 1 }, None).is_err());
+    assert!(coll.find_one_and_replace(doc! { "_id": 1 }, doc! { "x": 1 }, None).is_err());
+
+    let models = vec![
+        WriteModel::InsertOne { document: doc! { "_id": 5, "tenant_id": 1 } },
+        WriteModel::DeleteOne { filter: doc! { "_id": 1 }, collation: None, hint: None },
+        WriteModel::InsertOne { document: doc! { "_id": 6 } },
+    ];
+    let result = coll.bulk_write(models, true);
+    let exception = result.bulk_write_exception.expect("Expected a bulk write exception.");
+    assert!(exception.message.contains("Write 2"));
+    assert_eq!(exception.unprocessed_requests.len(), 3);
+
+    // Collections inherit the validators of the database they were created from.
+    let validated = db.with_write_validator(|doc| if doc.contains_key("secret") {
+        Err(String::from("secrets are not allowed"))
+    } else {
+        Ok(())
+    });
+
+    let other = validated.collection("write_validators_inherited");
+    other.drop().unwrap();
+    assert!(other.insert_one(doc! { "secret": 1 }, None).is_err());
+    other.insert_one(doc! { "x": 1 }, None).unwrap();
+
+    // Handles from the original database don't run them.
+    db.collection("write_validators_inherited").insert_one(doc! { "secret": 1 }, None).unwrap();
+}
+
+#[test]
+fn stored_keys_are_checked() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let mut coll = db.collection("stored_keys_are_checked");
+    coll.drop().unwrap();
+
+    let expect_invalid = |result: Result<_, Error>, message: &str| match result {
+        Err(Error::ArgumentError(ref reason)) => assert_eq!(reason, message),
+        other => panic!("Expected an ArgumentError, got {:?}", other),
+    };
+
+    let docs = vec![
+        doc! { "_id": 1 },
+        doc! { "_id": 2, "address": { "lines": [{ "zip.code": "12345" }] } },
+    ];
+    expect_invalid(
+        coll.insert_many(docs, None).map(|_| ()),
+        "Document 1 is invalid: key \"zip.code\" at \"address.lines.0.zip.code\" contains '.'.",
+    );
+    expect_invalid(
+        coll.insert_one(doc! { "a": { "$gt": 1 } }, None).map(|_| ()),
+        "Document 0 is invalid: key \"$gt\" at \"a.$gt\" starts with '$'.",
+    );
+    assert_eq!(coll.count(None, None).unwrap(), 0);
+
+    // DBRefs are stored as they are.
+    let owner = doc! { "$ref": "users", "$id": 5 };
+    coll.insert_one(doc! { "_id": 1, "owner": owner }, None).unwrap();
+
+    let replacement = doc! { "nested": { "a.b": 1 } };
+    expect_invalid(
+        coll.replace_one(doc! { "_id": 1 }, replacement.clone(), None).map(|_| ()),
+        "Replacement is invalid: key \"a.b\" at \"nested.a.b\" contains '.'.",
+    );
+    assert!(coll.find_one_and_replace(doc! { "_id": 1 }, replacement, None).is_err());
+
+    // Filters and update operators may use dotted paths and operators.
+    let update = doc! { "$set": { "owner_name.first": "Ann" } };
+    let result = coll.update_one(doc! { "owner.$id": { "$gt": 1 } }, update, None).unwrap();
+    assert_eq!(result.modified_count, 1);
+    expect_invalid(
+        coll.update_one(doc! { "_id": 1 }, doc! { "$set": { "x": 1 }, "y": 2 }, None)
+            .map(|_| ()),
+        "Update only works with $ operators.",
+    );
+
+    let models = vec![
+        WriteModel::InsertOne { document: doc! { "_id": 2 } },
+        WriteModel::InsertOne { document: doc! { "_id": 3, "a.b": 1 } },
+    ];
+    let exception = coll.bulk_write(models, true)
+        .bulk_write_exception
+        .expect("Expected a bulk write exception.");
+    assert_eq!(exception.message, "Write 1 is invalid: key \"a.b\" contains '.'");
+
+    // Servers from MongoDB 5.0 store such keys once they are allowed; older ones never do.
+    coll.set_allow_dotted_keys(true);
+    let result = coll.insert_one(doc! { "_id": 4, "a.b": 1 }, None);
+    if db.version().unwrap().major >= 5 {
+        result.unwrap();
+        assert!(coll.find_one(Some(doc! { "_id": 4 }), None).unwrap().is_some());
+    } else {
+        expect_invalid(result.map(|_| ()), "Document 0 is invalid: key \"a.b\" contains '.'.");
+    }
+}
+
+#[test]
+fn copy_to() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let source = client.db("test-client-coll").collection("copy_to_source");
+    source.drop().unwrap();
+    let target_db = client.db("test-client-coll-copy_to");
+    target_db.drop_database().unwrap();
+
+    let docs = (0..25).map(|i| doc! { "_id": i, "even": i % 2 == 0 }).collect();
+    source.insert_many(docs, None).unwrap();
+    source.create_index(doc! { "even": 1 }, None).unwrap();
+
+    // A dry run only counts.
+    let options = CopyOptions::builder().dry_run(true).build();
+    let filter = Some(doc! { "even": true });
+    let result = source.copy_to(&target_db, "evens", filter.clone(), Some(options)).unwrap();
+    assert_eq!(result.copied_count, 13);
+    assert!(target_db.collection_names(None).unwrap().is_empty());
+
+    let options = CopyOptions::builder().batch_size(5).indexes(IndexCopy::After).build();
+    let mut progress = Vec::new();
+    let result = source
+        .copy_to_with_progress(&target_db, "evens", filter, Some(options), |n| progress.push(n))
+        .unwrap();
+    assert_eq!(result.copied_count, 13);
+    assert_eq!(result.indexes_created, vec![String::from("even_1")]);
+    assert_eq!(progress, vec![5, 10, 13]);
+
+    let target = target_db.collection("evens");
+    assert_eq!(target.count(None, None).unwrap(), 13);
+    assert!(target.find_one(Some(doc! { "_id": 24, "even": true }), None).unwrap().is_some());
+    let index_names: Vec<_> = target
+        .list_indexes()
+        .unwrap()
+        .map(|index| index.unwrap().get_str("name").unwrap().to_owned())
+        .collect();
+    assert!(index_names.contains(&String::from("even_1")));
+
+    // Copying again conflicts on every _id but those of the odd documents.
+    let options = CopyOptions::builder().batch_size(100).build();
+    match source.copy_to(&target_db, "evens", None, Some(options)) {
+        Err(Error::CopyError(written, ref inner)) => {
+            assert_eq!(written, 12);
+            match **inner {
+                Error::BulkWriteError(ref exception) => {
+                    assert_eq!(exception.write_errors.len(), 13);
+                }
+                ref other => panic!("Expected a BulkWriteError, got {:?}", other),
+            }
+        }
+        other => panic!("Expected a CopyError, got {:?}", other),
+    }
+    assert_eq!(target.count(None, None).unwrap(), 25);
+
+    let options = CopyOptions::builder().batch_size(0).build();
+    match source.copy_to(&target_db, "evens", None, Some(options)) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected an ArgumentError, got {:?}", other),
+    }
+}
+
+#[test]
+fn jsonl_round_trip() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let source = db.collection("jsonl_round_trip_source");
+    source.drop().unwrap();
+    let target = db.collection("jsonl_round_trip_target");
+    target.drop().unwrap();
+
+    let mut scope = Document::new();
+    scope.insert("x", 1);
+    let every_type = doc! {
+        "_id": 0,
+        "double": 1.5,
+        "string": "text",
+        "array": [1, "two"],
+        "document": { "nested": true },
+        "boolean": false,
+        "null": Bson::Null,
+        "regex": Bson::RegExp(String::from("^a"), String::from("im")),
+        "code": Bson::JavaScriptCode(String::from("x + 1")),
+        "code_with_scope": Bson::JavaScriptCodeWithScope(String::from("x + 1"), scope),
+        "int32": -7,
+        "int64": 1i64 << 40,
+        "timestamp": Bson::TimeStamp((1 << 32) | 5),
+        "binary": Bson::Binary(BinarySubtype::Generic, vec![0, 1, 2, 255]),
+        "object_id": ObjectId::new().unwrap(),
+        "date": Bson::UtcDatetime(Utc.timestamp(1_500_000_000, 123_000_000)),
+        "old_date": Bson::UtcDatetime(Utc.timestamp(-86_400, 0)),
+        "symbol": Bson::Symbol(String::from("sym")),
+    };
+    let mut docs = vec![every_type];
+    docs.extend((1..10i32).map(|i| doc! { "_id": i, "small": i, "large": i64::from(i) }));
+    source.insert_many(docs.clone(), None).unwrap();
+
+    let path = env::temp_dir().join("mongodb-jsonl_round_trip.jsonl");
+    let file = File::create(&path).unwrap();
+    assert_eq!(source.export_jsonl(file, None, true).unwrap(), 10);
+
+    let file = BufReader::new(File::open(&path).unwrap());
+    let result = target.import_jsonl(file, 3, true).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(result.inserted_count, 10);
+    assert!(result.errors.is_empty());
+
+    let sort = FindOptions::builder().sort(doc! { "_id": 1 }).build();
+    let imported: Vec<_> = target.find(None, Some(sort)).unwrap().map(Result::unwrap).collect();
+    assert_eq!(imported, docs);
+
+    // Relaxed output writes numbers as they are, so an int64 that fits in an int32 reads back
+    // as one.
+    let mut relaxed = Vec::new();
+    source.export_jsonl(&mut relaxed, Some(doc! { "_id": 1 }), false).unwrap();
+    assert_eq!(String::from_utf8(relaxed).unwrap(), "{\"_id\":1,\"small\":1,\"large\":1}\n");
+
+    let mut canonical = Vec::new();
+    source.export_jsonl(&mut canonical, Some(doc! { "_id": 1 }), true).unwrap();
+    assert_eq!(
+        String::from_utf8(canonical).unwrap(),
+        "{\"_id\":{\"$numberInt\":\"1\"},\"small\":{\"$numberInt\":\"1\"},\
+         \"large\":{\"$numberLong\":\"1\"}}\n"
+    );
+}
+
+#[test]
+fn import_jsonl_reports_bad_lines() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-coll").collection("import_jsonl_reports_bad_lines");
+    coll.drop().unwrap();
+
+    let input = "{\"_id\": 1}\n\
+                 not json\n\
+                 \n\
+                 {\"_id\": 1}\n\
+                 {\"_id\": {\"$numberDecimal\": \"1\"}}\n\
+                 {\"_id\": 2}\n";
+
+    // An unordered import carries on past bad lines, both unparseable and failed inserts.
+    let result = coll.import_jsonl(input.as_bytes(), 2, false).unwrap();
+    assert_eq!(result.inserted_count, 2);
+    let lines: Vec<_> = result.errors.iter().map(|error| error.line).collect();
+    assert_eq!(lines, vec![2, 4, 5]);
+    assert_eq!(coll.count(None, None).unwrap(), 2);
+
+    // An ordered import stops at the first, having inserted the lines before it.
+    coll.drop().unwrap();
+    let result = coll.import_jsonl(input.as_bytes(), 2, true).unwrap();
+    assert_eq!(result.inserted_count, 1);
+    assert_eq!(result.errors.len(), 1);
+    assert_eq!(result.errors[0].line, 2);
+
+    coll.drop().unwrap();
+    let input = "{\"_id\": 3}\n{\"_id\": 3}\n{\"_id\": 4}\n";
+    let result = coll.import_jsonl(input.as_bytes(), 10, true).unwrap();
+    assert_eq!(result.inserted_count, 1);
+    assert_eq!(result.errors.len(), 1);
+    assert_eq!(result.errors[0].line, 2);
+    assert_eq!(coll.count(None, None).unwrap(), 1);
+
+    match coll.import_jsonl(input.as_bytes(), 0, true) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected an ArgumentError, got {:?}", other),
+    }
+}
+
+#[test]
+fn cloned_handles_concurrent_use() {
+    const THREADS: i32 = 8;
+    const OPERATIONS: i32 = 50;
+
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-coll").collection("cloned_handles_concurrent_use");
+    coll.drop().unwrap();
+
+    // Each thread owns a clone of the handle, and checks that every reply it gets answers the
+    // request it sent rather than another thread's.
+    let threads: Vec<_> = (0..THREADS)
+        .map(|thread_id| {
+            let coll = coll.clone();
+            thread::spawn(move || for i in 0..OPERATIONS {
+                let id = thread_id * OPERATIONS + i;
+                coll.insert_one(doc! { "_id": id, "thread": thread_id }, None).unwrap();
+
+                let doc = coll.find_one(Some(doc! { "_id": id }), None).unwrap().unwrap();
+                assert_eq!(doc.get_i32("thread").unwrap(), thread_id);
+
+                let count = coll.count(Some(doc! { "thread": thread_id }), None).unwrap();
+                assert_eq!(count, i64::from(i + 1));
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(coll.count(None, None).unwrap(), i64::from(THREADS * OPERATIONS));
+}
+
+#[test]
+fn analyze_shard_key() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-coll").collection("analyze_shard_key");
+    coll.drop().unwrap();
+
+    let docs = (0..300)
+        .map(|i| doc! { "_id": ObjectId::new().unwrap(), "user": i, "status": i % 3 })
+        .collect();
+    coll.insert_many(docs, None).unwrap();
+
+    // ObjectIds increase with every insert.
+    let analysis = coll.analyze_shard_key(doc! { "_id": 1 }, None).unwrap();
+    assert_eq!(analysis.index, Some(String::from("_id_")));
+    assert_eq!(analysis.document_count, 300);
+    assert_eq!(analysis.sampled_count, 300);
+    assert_eq!(analysis.distinct_values, 300);
+    assert_eq!(analysis.max_frequency, 1);
+    assert!(analysis.monotonic);
+    assert_eq!(analysis.warnings.len(), 1);
+
+    // A sample of the collection still finds a value per document.
+    let options = ShardKeyOptions::builder().sample_size(100).build();
+    let analysis = coll.analyze_shard_key(doc! { "user": "hashed" }, Some(options)).unwrap();
+    assert_eq!(analysis.sampled_count, 100);
+    assert_eq!(analysis.distinct_values, 100);
+    assert!(!analysis.monotonic);
+
+    let options = ShardKeyOptions::builder().create_index(true).build();
+    let analysis = coll.analyze_shard_key(doc! { "status": 1 }, Some(options)).unwrap();
+    assert_eq!(analysis.index, Some(String::from("status_1")));
+    assert!(analysis.index_created);
+    assert_eq!(analysis.distinct_values, 3);
+    assert_eq!(analysis.max_frequency, 100);
+    assert!(!analysis.has_enough_cardinality(100));
+    assert_eq!(analysis.warnings.len(), 2);
+
+    // Keys with too few values are refused before the server is asked to shard.
+    match coll.shard_collection(doc! { "status": 1 }, None) {
+        Err(Error::ArgumentError(ref msg)) => assert!(msg.contains("3 distinct values")),
+        other => panic!("Expected an ArgumentError, got {:?}", other),
+    }
+
+    match coll.analyze_shard_key(doc! { "status": -1 }, None) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected an ArgumentError, got {:?}", other),
+    }
+}
+
+#[test]
+fn chunk_distribution_of_unsharded_collection() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-coll").collection("chunk_distribution");
+    coll.drop().unwrap();
+    coll.insert_one(doc! { "_id": 1 }, None).unwrap();
+
+    // Only collections with an entry in config.collections have chunks.
+    match coll.chunk_distribution(Some(10)) {
+        Err(Error::ArgumentError(ref msg)) => assert!(msg.contains("not sharded")),
+        other => panic!("Expected an ArgumentError, got {:?}", other),
+    }
+}
+
+#[test]
+fn insert_many_duplicate_key_details() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-coll").collection("insert_many_duplicate_key_details");
+    coll.drop().unwrap();
+
+    let options = IndexOptions::builder().unique(true).build();
+    coll.create_index(doc! { "a": 1, "b": -1 }, Some(options)).unwrap();
+    coll.insert_one(doc! { "a": 1, "b": 1 }, None).unwrap();
+
+    let docs = vec![
+        doc! { "a": 1, "b": 1 },
+        doc! { "a": 1, "b": 2 },
+        doc! { "a": 1, "b": 2 },
+        doc! { "a": 2, "b": 1 },
+        doc! { "a": 2, "b": 1 },
+    ];
+    let insert_options = InsertManyOptions::builder().ordered(false).build();
+    let result = coll.insert_many(docs, Some(insert_options)).unwrap();
+
+    assert_eq!(result.duplicate_key_indexes(), vec![0, 2, 4]);
+    assert_eq!(result.inserted_ids.as_ref().map(|ids| ids.len()), Some(2));
+
+    let exception = result.bulk_write_exception.unwrap();
+    for error in &exception.write_errors {
+        assert_eq!(error.code, 11000);
+        assert_eq!(error.index_name, Some(String::from("a_1_b_-1")));
+    }
+
+    // Servers from 4.2 also report the index's key pattern and the duplicated values.
+    let error = &exception.write_errors[1];
+    if let Some(ref key_pattern) = error.key_pattern {
+        assert_eq!(key_pattern, &doc! { "a": 1, "b": -1 });
+        assert_eq!(error.key_value, Some(doc! { "a": 1, "b": 2 }));
+    }
+}
+
+#[test]
+fn load_snapshot() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-coll");
+    let coll = db.collection("load_snapshot");
+    coll.drop().unwrap();
+
+    let docs = vec![
+        doc! { "_id": 1, "app": "billing", "env": "prod", "limit": 10, "updated": 1 },
+        doc! { "_id": 2, "app": "billing", "env": "test", "limit": 20, "updated": 2 },
+        doc! { "_id": 3, "app": "search", "env": "prod", "limit": 30, "updated": 1 },
+    ];
+    coll.insert_many(docs, None).unwrap();
+
+    let options = SnapshotOptions::builder().updated_at_field(String::from("updated")).build();
+    let mut snapshot = coll.load_snapshot(&["app", "env"], Some(options)).unwrap();
+    assert_eq!(snapshot.len(), 3);
+    assert!(snapshot.loaded_at() <= Utc::now());
+
+    let key = [Bson::String(String::from("search")), Bson::String(String::from("prod"))];
+    assert_eq!(snapshot.get(&key).unwrap().get("limit"), Some(&Bson::I32(30)));
+    assert!(snapshot.get(&[Bson::String(String::from("search"))]).is_none());
+
+    assert_eq!(snapshot.find_local(&doc! { "app": "billing" }).unwrap().len(), 2);
+    assert_eq!(snapshot.find_local(&doc! { "env": "prod", "app": "search" }).unwrap().len(), 1);
+    assert!(snapshot.find_local(&doc! { "limit": 10 }).is_err());
+    assert!(snapshot.find_local(&doc! { "app": { "$ne": "search" } }).is_err());
+
+    // A refresh only reads the documents updated since the latest update in the snapshot.
+    let loaded_at = snapshot.loaded_at();
+    coll.update_one(doc! { "_id": 2 }, doc! { "$set": { "limit": 25, "updated": 3 } }, None)
+        .unwrap();
+    coll.insert_one(doc! { "_id": 4, "app": "search", "env": "test", "updated": 3 }, None)
+        .unwrap();
+    assert_eq!(snapshot.refresh().unwrap(), 2);
+    assert_eq!(snapshot.len(), 4);
+    assert!(snapshot.loaded_at() >= loaded_at);
+
+    let key = [Bson::String(String::from("billing")), Bson::String(String::from("test"))];
+    assert_eq!(snapshot.get(&key).unwrap().get("limit"), Some(&Bson::I32(25)));
+
+    // Collections larger than the limit aren't loaded.
+    let options = SnapshotOptions::builder().max_documents(3).build();
+    match coll.load_snapshot(&["app"], Some(options)) {
+        Err(Error::OperationError(ref message)) => assert!(message.contains("more than 3")),
+        other => panic!("Expected an operation error, got {:?}", other),
+    }
+
+    let options = SnapshotOptions::builder().filter(doc! { "env": "prod" }).build();
+    let snapshot = coll.load_snapshot(&["app"], Some(options)).unwrap();
+    assert_eq!(snapshot.len(), 2);
+    assert!(coll.load_snapshot(&[], None).is_err());
+}
@@ -1,4 +1,7 @@
-use mongodb::connstring;
+use mongodb::{Client, ClientOptions, Error, Result, ThreadedClient};
+use mongodb::connstring::{self, SrvResolver};
+
+use std::sync::Arc;
 
 #[test]
 fn valid_uri() {
@@ -290,3 +293,121 @@ fn full() {
     assert_eq!("true", options.get("journal").unwrap());
     assert_eq!("50", options.get("wtimeoutMS").unwrap());
 }
+
+struct StubResolver {
+    srv: Vec<(&'static str, u16)>,
+    txt: Vec<&'static str>,
+}
+
+impl SrvResolver for StubResolver {
+    fn lookup_srv(&self, name: &str) -> Result<Vec<(String, u16)>> {
+        assert_eq!("_mongodb._tcp.cluster0.example.com", name);
+        Ok(self.srv.iter().map(|&(host, port)| (String::from(host), port)).collect())
+    }
+
+    fn lookup_txt(&self, name: &str) -> Result<Vec<String>> {
+        assert_eq!("cluster0.example.com", name);
+        Ok(self.txt.iter().map(|txt| String::from(*txt)).collect())
+    }
+}
+
+struct FailingResolver;
+
+impl SrvResolver for FailingResolver {
+    fn lookup_srv(&self, _: &str) -> Result<Vec<(String, u16)>> {
+        Err(Error::DefaultError(String::from("NXDOMAIN")))
+    }
+
+    fn lookup_txt(&self, _: &str) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
+
+#[test]
+fn srv() {
+    let resolver = StubResolver {
+        srv: vec![("host1.example.com.", 27017), ("Host2.Example.com", 27018)],
+        txt: vec!["replicaSet=rs0&authSource=admin"],
+    };
+
+    let mut connstr = connstring::parse("mongodb+srv://cluster0.example.com/?authSource=db")
+        .unwrap();
+    assert_eq!(0, connstr.hosts.num_hosts());
+
+    connstr.resolve_hosts_with_resolver(&resolver).unwrap();
+
+    let hosts: Vec<_> = connstr.hosts.iter().cloned().collect();
+    assert_eq!(2, hosts.len());
+    assert_eq!("host1.example.com", hosts[0].host_name);
+    assert_eq!(27017, hosts[0].port);
+    assert_eq!("host2.example.com", hosts[1].host_name);
+    assert_eq!(27018, hosts[1].port);
+
+    // TLS is enabled by default, and options in the URI take precedence over the TXT record.
+    let options = connstr.options.unwrap();
+    assert_eq!("true", options.get("ssl").unwrap());
+    assert_eq!("rs0", options.get("replicaSet").unwrap());
+    assert_eq!("db", options.get("authSource").unwrap());
+}
+
+#[test]
+fn srv_invalid_records() {
+    let uri = "mongodb+srv://cluster0.example.com/";
+
+    let resolvers = vec![
+        // Host outside the parent domain.
+        StubResolver { srv: vec![("host1.evil.com", 27017)], txt: vec![] },
+        StubResolver { srv: vec![("host1.notexample.com", 27017)], txt: vec![] },
+        // No hosts.
+        StubResolver { srv: vec![], txt: vec![] },
+        // Disallowed TXT option.
+        StubResolver { srv: vec![("host1.example.com", 27017)], txt: vec!["ssl=false"] },
+        // Multiple TXT records.
+        StubResolver {
+            srv: vec![("host1.example.com", 27017)],
+            txt: vec!["replicaSet=rs0", "authSource=admin"],
+        },
+    ];
+
+    for resolver in resolvers {
+        let mut connstr = connstring::parse(uri).unwrap();
+        assert!(connstr.resolve_hosts_with_resolver(&resolver).is_err());
+    }
+}
+
+#[test]
+fn srv_lookup_failure() {
+    let mut connstr = connstring::parse("mongodb+srv://cluster0.example.com/").unwrap();
+
+    match connstr.resolve_hosts_with_resolver(&FailingResolver) {
+        Err(Error::DNSLookupError(name, _)) => {
+            assert_eq!("_mongodb._tcp.cluster0.example.com", name)
+        }
+        other => panic!("Expected DNSLookupError, got {:?}", other),
+    }
+}
+
+#[test]
+fn srv_resolver_and_host_mapper() {
+    // The SRV record names hosts that don't resolve, which the mapper sends to the local server.
+    let mut options = ClientOptions::new();
+    options.srv_resolver = Some(Arc::new(StubResolver {
+        srv: vec![("db0.example.com", 27017)],
+        txt: vec![],
+    }));
+    options.host_mapper = Some(Arc::new(|host: &str, port: u16| if host.ends_with(".example.com") {
+        Some((String::from("localhost"), port))
+    } else {
+        None
+    }));
+
+    let client = Client::with_uri_and_options("mongodb+srv://cluster0.example.com/", options)
+        .unwrap();
+    client.ping().unwrap();
+
+    // The server keeps the address it was discovered by.
+    let info = client.topology_info().unwrap();
+    assert_eq!(1, info.servers.len());
+    assert_eq!("db0.example.com", info.servers[0].host.host_name);
+    assert_eq!(27017, info.servers[0].host.port);
+}
@@ -170,7 +170,7 @@ macro_rules! run_insert_one_test {
 macro_rules! run_replace_one_test {
     ( $db:expr, $coll:expr, $filter:expr, $replacement:expr, $upsert:expr,
         $outcome:expr ) => {{
-            let options = ReplaceOptions { upsert: $upsert, write_concern: None };
+            let options = ReplaceOptions::builder().upsert($upsert).build();
             let actual = $coll.replace_one($filter, $replacement, Some(options)).unwrap();
 
             let (matched, modified, upserted) = match $outcome.result {
@@ -277,9 +277,9 @@ macro_rules! run_suite {
                 Arguments::InsertOne { document } =>
                     run_insert_one_test!(db, coll, document, test.outcome),
                 Arguments::ReplaceOne { filter, replacement, upsert } =>
-                    run_replace_one_test!(db, coll, filter, replacement, Some(upsert), test.outcome),
+                    run_replace_one_test!(db, coll, filter, replacement, upsert, test.outcome),
                 Arguments::Update { filter, update, upsert, many } => {
-                    let options = UpdateOptions { upsert: Some(upsert), ..Default::default() };
+                    let options = UpdateOptions::builder().upsert(upsert).build();
 
                     run_update_test!(db, coll, filter, update, Some(options), many, test.outcome)
 
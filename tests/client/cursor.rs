@@ -1,11 +1,24 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
 use bson::{Bson, Document};
 
-use mongodb::{Client, CommandType, ThreadedClient};
-use mongodb::common::{ReadMode, ReadPreference};
-use mongodb::coll::options::FindOptions;
+use mongodb::{Client, ClientOptions, CommandStarted, CommandType, Error, ThreadedClient};
+use mongodb::common::{ReadConcern, ReadMode, ReadPreference, WriteConcern};
+use mongodb::coll::options::{CursorType, Direction, FindOptions, Hint, InsertManyOptions};
 use mongodb::db::ThreadedDatabase;
-use mongodb::cursor::Cursor;
+use mongodb::db::options::CreateCollectionOptions;
+use mongodb::cursor::{BatchInfo, Cursor};
+use mongodb::logging::{LogLevel, Logger};
+use mongodb::merge::SortOrder;
+use mongodb::topology::TopologyType;
+use mongodb::topology::server::ServerType;
 use mongodb::wire_protocol::flags::OpQueryFlags;
+use mongodb::wire_protocol::operations::Message;
+
+use super::mock_server::{reply, standalone, MockServer, Response};
 
 #[test]
 fn cursor_features() {
@@ -26,8 +39,7 @@ fn cursor_features() {
     let doc = Document::new();
     let flags = OpQueryFlags::empty();
 
-    let mut options = FindOptions::new();
-    options.batch_size = Some(3);
+    let options = FindOptions::builder().batch_size(3).build();
 
     let result = Cursor::query(
         client.clone(),
@@ -82,3 +94,825 @@ fn cursor_features() {
         };
     }
 }
+
+#[test]
+fn exhaust_cursor() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+    let coll = db.collection("exhaust_cursor");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let docs = (0..50)
+        .map(|i| {
+            doc! { "foo": i as i64 }
+        })
+        .collect();
+
+    assert!(coll.insert_many(docs, None).is_ok());
+
+    let options = FindOptions::builder().batch_size(5).exhaust(true).build();
+
+    let cursor = coll.find(None, Some(options.clone())).expect("Failed to execute find.");
+    let results: Vec<_> = cursor.map(|doc| doc.expect("Failed to get next document."))
+        .collect();
+
+    assert_eq!(results.len(), 50);
+
+    for (i, item) in results.iter().enumerate() {
+        match item.get("foo") {
+            Some(&Bson::I64(j)) => assert_eq!(i as i64, j),
+            _ => panic!("Wrong value returned from exhaust cursor"),
+        };
+    }
+
+    // Dropping an exhaust cursor early should discard its connection without affecting
+    // later operations.
+    let mut cursor = coll.find(None, Some(options)).expect("Failed to execute find.");
+    let partial = cursor.next_n(7).expect("Failed to get next 7 results.");
+    assert_eq!(partial.len(), 7);
+    drop(cursor);
+
+    let count = coll.count(None, None).expect("Failed to execute count.");
+    assert_eq!(count, 50);
+}
+
+#[test]
+fn cursor_large_documents() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+    let coll = db.collection("large_documents");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    let padding: String = (0..500000).map(|_| 'x').collect();
+    let docs = (0..20)
+        .map(|i| {
+            doc! { "foo": i as i64, "padding": padding.clone() }
+        })
+        .collect();
+
+    assert!(coll.insert_many(docs, None).is_ok());
+
+    let mut cursor = Cursor::query(
+        client.clone(),
+        "test-client-cursor.large_documents".to_owned(),
+        OpQueryFlags::empty(),
+        Document::new(),
+        FindOptions::new(),
+        CommandType::Find,
+        false,
+        ReadPreference::new(ReadMode::Primary, None),
+    ).expect("Failed to execute query.");
+
+    // Only the first document of the batch is decoded here.
+    let first = cursor.next().expect("Expected a document.").expect(
+        "Failed to get first document.",
+    );
+    assert_eq!(first.get("foo"), Some(&Bson::I64(0)));
+
+    let rest = cursor.drain_current_batch().expect("Failed to drain batch.");
+    assert!(!rest.is_empty());
+
+    for (i, item) in rest.iter().enumerate() {
+        assert_eq!(item.get("foo"), Some(&Bson::I64(i as i64 + 1)));
+        match item.get("padding") {
+            Some(&Bson::String(ref s)) => assert_eq!(s.len(), 500000),
+            _ => panic!("Wrong value returned from Cursor#drain_current_batch"),
+        };
+    }
+
+    let remaining: Vec<_> = cursor.map(|doc| doc.expect("Failed to get next document."))
+        .collect();
+    assert_eq!(1 + rest.len() + remaining.len(), 20);
+}
+
+#[test]
+fn kill_dropped_cursors() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+    let coll = db.collection("kill_dropped_cursors");
+
+    coll.drop().expect("Failed to drop collection.");
+
+    // The getMore command used to check for the cursors requires MongoDB 3.2.
+    let is_master = db.command(doc! { "isMaster": 1 }, CommandType::IsMaster, None).unwrap();
+    match is_master.get("maxWireVersion") {
+        Some(&Bson::I32(version)) if version >= 4 => (),
+        _ => return,
+    }
+
+    let docs = (0..10).map(|i| doc! { "foo": i as i64 }).collect();
+    assert!(coll.insert_many(docs, None).is_ok());
+
+    let options = FindOptions::builder().batch_size(2).build();
+
+    let cursor_ids: Vec<_> = (0..3)
+        .map(|_| {
+            let mut cursor = coll.find(None, Some(options.clone())).expect(
+                "Failed to execute find.",
+            );
+            cursor.next().expect("Expected a document.").expect("Failed to get document.");
+
+            let cursor_id = cursor.cursor_id();
+            assert!(cursor_id != 0);
+            cursor_id
+        })
+        .collect();
+
+    client.flush_cursor_kills().expect("Failed to kill cursors.");
+
+    for cursor_id in cursor_ids {
+        let get_more = doc! { "getMore": cursor_id, "collection": "kill_dropped_cursors" };
+        let result = db.command(get_more, CommandType::Suppressed, None);
+        assert!(result.is_err(), "Cursor {} was not killed.", cursor_id);
+    }
+}
+
+#[test]
+fn max_await_time_ms() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+    db.drop_collection("max_await_time_ms").unwrap();
+
+    let mut create_options = CreateCollectionOptions::new();
+    create_options.capped = Some(true);
+    create_options.size = Some(100000);
+    db.create_collection("max_await_time_ms", Some(create_options)).unwrap();
+
+    let coll = db.collection("max_await_time_ms");
+    coll.insert_one(doc! { "_id": 1 }, None).unwrap();
+
+    // Only tailable, awaiting cursors can wait for new documents.
+    let options = FindOptions::builder().max_await_time_ms(500).build();
+    match coll.find(None, Some(options.clone())) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected ArgumentError, got {:?}", other),
+    }
+
+    let mut cursor = coll.find(None, None).unwrap();
+    assert!(cursor.set_max_await_time_ms(Some(500)).is_err());
+
+    let options = options.to_builder().cursor_type(CursorType::TailableAwait).build();
+    let mut cursor = coll.find(None, Some(options)).expect("Failed to open tailable cursor.");
+    assert_eq!(cursor.next().unwrap().unwrap(), doc! { "_id": 1 });
+
+    // With no new documents, the getMore returns once the limit is reached.
+    let start = Instant::now();
+    assert!(cursor.next().is_none());
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_millis(400));
+    assert!(elapsed < Duration::from_millis(3000));
+    assert!(cursor.is_alive());
+
+    coll.insert_one(doc! { "_id": 2 }, None).unwrap();
+    assert_eq!(cursor.next().unwrap().unwrap(), doc! { "_id": 2 });
+}
+
+#[test]
+fn prefetch() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+    let coll = db.collection("prefetch");
+    coll.drop().unwrap();
+
+    let docs = (0..100).map(|i| doc! { "_id": i }).collect();
+    coll.insert_many(docs, None).unwrap();
+
+    let options = FindOptions::builder().batch_size(10).sort(doc! { "_id": 1 }).build();
+
+    let cursor = coll.find(None, Some(options.clone())).unwrap().with_prefetch(true);
+    let ids: Vec<_> = cursor.map(|doc| doc.unwrap().get_i32("_id").unwrap()).collect();
+    assert_eq!(ids, (0..100).collect::<Vec<_>>());
+
+    // Five documents of the first batch remain, so the next batch isn't requested yet.
+    let mut cursor = coll.find(None, Some(options)).unwrap().with_prefetch(true);
+    for _ in 0..5 {
+        cursor.next().unwrap().unwrap();
+    }
+
+    // Dropping the collection kills the cursor, so the getMore sent in the background once
+    // fewer documents remain fails. The buffered documents are still returned first.
+    coll.drop().unwrap();
+    for _ in 0..5 {
+        cursor.next().unwrap().unwrap();
+    }
+
+    match cursor.next() {
+        Some(Err(_)) => (),
+        other => panic!("Expected the failed getMore to be reported, got {:?}", other),
+    }
+}
+
+static PREFETCH_GET_MORES: AtomicUsize = AtomicUsize::new(0);
+
+fn count_prefetch_get_mores(_client: Client, command_started: &CommandStarted) {
+    if command_started.command_name == "get_more" &&
+        command_started.command.get_str("collection").ok() == Some("prefetch_before_exhaustion")
+    {
+        PREFETCH_GET_MORES.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn prefetch_before_exhaustion() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+    let coll = db.collection("prefetch_before_exhaustion");
+    coll.drop().unwrap();
+
+    let docs = (0..20).map(|i| doc! { "_id": i }).collect();
+    coll.insert_many(docs, None).unwrap();
+    client.add_start_hook(count_prefetch_get_mores).unwrap();
+
+    let options = FindOptions::builder().batch_size(10).sort(doc! { "_id": 1 }).build();
+
+    // Without prefetching, the next batch is only requested once the first one is used up.
+    let mut cursor = coll.find(None, Some(options.clone())).unwrap();
+    for _ in 0..10 {
+        cursor.next().unwrap().unwrap();
+    }
+    assert_eq!(PREFETCH_GET_MORES.load(Ordering::SeqCst), 0);
+    drop(cursor);
+
+    // With prefetching, it is requested once fewer than half of the first batch remain, while
+    // four documents are still buffered.
+    let mut cursor = coll.find(None, Some(options)).unwrap().with_prefetch(true);
+    for _ in 0..6 {
+        cursor.next().unwrap().unwrap();
+    }
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while PREFETCH_GET_MORES.load(Ordering::SeqCst) == 0 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(PREFETCH_GET_MORES.load(Ordering::SeqCst), 1);
+
+    let ids: Vec<_> = cursor.map(|doc| doc.unwrap().get_i32("_id").unwrap()).collect();
+    assert_eq!(ids, (6..20).collect::<Vec<_>>());
+}
+
+// Records the messages a client sends.
+#[derive(Debug, Default)]
+pub struct SentMessages {
+    messages: Mutex<Vec<String>>,
+}
+
+impl SentMessages {
+    // Returns true if a message contains every part of the pattern.
+    pub fn any(&self, pattern: &[&str]) -> bool {
+        self.count(pattern) > 0
+    }
+
+    // Returns the number of messages containing every part of the pattern.
+    pub fn count(&self, pattern: &[&str]) -> usize {
+        self.messages.lock().unwrap().iter().filter(|message| {
+            pattern.iter().all(|part| message.contains(part))
+        }).count()
+    }
+
+    // Forgets the messages recorded so far.
+    pub fn clear(&self) {
+        self.messages.lock().unwrap().clear();
+    }
+}
+
+impl Logger for SentMessages {
+    fn enabled(&self, level: LogLevel) -> bool {
+        level == LogLevel::Trace
+    }
+
+    fn log(&self, _level: LogLevel, target: &str, message: &str) {
+        if target == "wire" && message.starts_with("Sent") {
+            self.messages.lock().unwrap().push(message.to_owned());
+        }
+    }
+}
+
+fn max_wire_version(client: &Client) -> i64 {
+    let db = client.db("admin");
+    let is_master = db.command(doc! { "isMaster": 1 }, CommandType::IsMaster, None).unwrap();
+    match is_master.get("maxWireVersion") {
+        Some(&Bson::I32(version)) => i64::from(version),
+        Some(&Bson::I64(version)) => version,
+        _ => 0,
+    }
+}
+
+// Runs the same iteration scenarios over the getMore and killCursors commands or over the
+// legacy messages, which are forced by capping the wire version below 4.
+fn iterate_with_protocol(commands: bool) {
+    let sent = Arc::new(SentMessages::default());
+    let mut options = ClientOptions::new();
+    options.logger = Some(sent.clone() as Arc<Logger>);
+    if !commands {
+        options.max_wire_version = Some(3);
+    }
+
+    let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+
+    // The commands require MongoDB 3.2, and MongoDB 5.1 no longer accepts the legacy messages.
+    let version = max_wire_version(&client);
+    if (commands && version < 4) || (!commands && version >= 14) {
+        return;
+    }
+
+    let db = client.db("test-client-cursor");
+    let coll = db.collection(if commands { "get_more_commands" } else { "legacy_get_more" });
+    coll.drop().unwrap();
+
+    let docs = (0..10).map(|i| doc! { "_id": i }).collect();
+    coll.insert_many(docs, None).unwrap();
+
+    let options = FindOptions::builder().batch_size(3).sort(doc! { "_id": 1 }).build();
+
+    // Every batch is returned, in order.
+    let cursor = coll.find(None, Some(options.clone())).unwrap();
+    let ids: Vec<_> = cursor.map(|doc| doc.unwrap().get_i32("_id").unwrap()).collect();
+    assert_eq!(ids, (0..10).collect::<Vec<_>>());
+
+    // The limit applies across batches.
+    let limited = options.to_builder().limit(5).build();
+    let cursor = coll.find(None, Some(limited)).unwrap();
+    assert_eq!(cursor.count(), 5);
+
+    // Batches fetched in the background are read the same way.
+    let cursor = coll.find(None, Some(options.clone())).unwrap().with_prefetch(true);
+    assert_eq!(cursor.count(), 10);
+
+    if commands {
+        assert!(sent.any(&["test-client-cursor.$cmd", "getMore"]));
+        assert!(!sent.any(&["getMore", "for cursor"]));
+    } else {
+        assert!(sent.any(&["getMore", "on test-client-cursor.legacy_get_more for cursor"]));
+        assert!(!sent.any(&["$cmd", "getMore"]));
+    }
+
+    // Cursors dropped before being exhausted are killed.
+    let mut cursor = coll.find(None, Some(options)).unwrap();
+    cursor.next().unwrap().unwrap();
+    let cursor_id = cursor.cursor_id();
+    assert!(cursor_id != 0);
+    drop(cursor);
+
+    client.flush_cursor_kills().unwrap();
+
+    if commands {
+        assert!(sent.any(&["test-client-cursor.$cmd", "killCursors"]));
+    } else {
+        let cursor_ids = format!("for cursors [{}]", cursor_id);
+        assert!(sent.any(&["killCursors", cursor_ids.as_str()]));
+    }
+
+    let get_more = doc! { "getMore": cursor_id, "collection": coll.name() };
+    let result = db.command(get_more, CommandType::Suppressed, None);
+    assert!(result.is_err(), "Cursor {} was not killed.", cursor_id);
+}
+
+#[test]
+fn get_more_commands() {
+    iterate_with_protocol(true);
+}
+
+#[test]
+fn legacy_get_more() {
+    iterate_with_protocol(false);
+}
+
+#[test]
+fn get_more_on_dotted_collection_names() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor-dotted");
+
+    for name in &["logs.2024", "fs.chunks"] {
+        let coll = db.collection(name);
+        coll.drop().unwrap();
+
+        let docs = (0..10).map(|i| doc! { "_id": i }).collect();
+        coll.insert_many(docs, None).unwrap();
+
+        let options = FindOptions::builder().batch_size(3).sort(doc! { "_id": 1 }).build();
+
+        let cursor = coll.find(None, Some(options)).unwrap();
+        let ids: Vec<_> = cursor.map(|doc| doc.unwrap().get_i32("_id").unwrap()).collect();
+        assert_eq!(ids, (0..10).collect::<Vec<_>>(), "collection {}", name);
+    }
+}
+
+#[test]
+fn get_more_pinned_to_server() {
+    let sent = Arc::new(SentMessages::default());
+    let mut options = ClientOptions::new();
+    options.logger = Some(sent.clone() as Arc<Logger>);
+
+    let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+    client.is_master().unwrap();
+
+    let info = client.topology_info().unwrap();
+    if info.topology_type != TopologyType::ReplicaSetWithPrimary {
+        return;
+    }
+
+    let secondaries = info.servers
+        .iter()
+        .filter(|server| server.server_type == ServerType::RSSecondary)
+        .count();
+    if secondaries == 0 {
+        return;
+    }
+
+    let mut write_concern = WriteConcern::new();
+    write_concern.w = secondaries as i32 + 1;
+
+    let coll = client.db("test-client-cursor").collection("get_more_pinned_to_server");
+    coll.drop().unwrap();
+
+    let docs = (0..20).map(|i| doc! { "_id": i }).collect();
+    let insert_options = InsertManyOptions::builder().write_concern(write_concern).build();
+    coll.insert_many(docs, Some(insert_options)).unwrap();
+
+    let options = FindOptions::builder()
+        .batch_size(2)
+        .read_preference(ReadPreference::new(ReadMode::Secondary, None))
+        .build();
+
+    // Each getMore must reach the secondary that opened the cursor, even if server selection
+    // would pick another one.
+    let cursor = coll.find(None, Some(options)).unwrap();
+    let address = cursor.server_address().expect("Expected the cursor's server to be known.");
+    assert_eq!(cursor.count(), 20);
+
+    let messages = sent.messages.lock().unwrap();
+    let get_mores: Vec<_> = messages.iter().filter(|message| message.contains("getMore")).collect();
+    assert!(get_mores.len() >= 9);
+    for message in get_mores {
+        assert!(message.contains(&address), "getMore sent elsewhere: {}", message);
+    }
+}
+
+static META_FIND_PORT: AtomicUsize = AtomicUsize::new(0);
+
+fn record_find_port(_client: Client, command_started: &CommandStarted) {
+    if command_started.command_name == "find" &&
+        command_started.command.get_str("find").ok() == Some("find_one_with_meta")
+    {
+        META_FIND_PORT.store(command_started.host.port as usize, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn find_one_with_meta() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-cursor").collection("find_one_with_meta");
+    coll.drop().unwrap();
+    coll.insert_one(doc! { "_id": 1 }, None).unwrap();
+
+    client.add_start_hook(record_find_port).unwrap();
+
+    let (doc, meta) = coll.find_one_with_meta(Some(doc! { "_id": 1 }), None).unwrap();
+    assert_eq!(doc, Some(doc! { "_id": 1 }));
+
+    let meta = meta.expect("Expected the responding server to be known.");
+    assert_eq!(META_FIND_PORT.load(Ordering::SeqCst), meta.host.port as usize);
+    assert!(meta.address().ends_with(&format!(":{}", meta.host.port)));
+
+    // Reads from the primary go to the server that was primary when it was selected.
+    let info = client.topology_info().unwrap();
+    if info.topology_type == TopologyType::ReplicaSetWithPrimary {
+        assert!(meta.is_primary());
+        assert!(!meta.is_secondary());
+    }
+
+    // The server is reported even if nothing matched.
+    let (doc, meta) = coll.find_one_with_meta(Some(doc! { "_id": 2 }), None).unwrap();
+    assert_eq!(doc, None);
+    assert!(meta.is_some());
+
+    let cursor = coll.find(None, None).unwrap();
+    assert_eq!(cursor.server_address(), cursor.meta().map(|meta| meta.address()));
+}
+
+#[test]
+fn no_cursor_timeout_on_the_wire() {
+    let options = FindOptions::builder().no_cursor_timeout(true).build();
+
+    // The flags of a legacy query follow its 16-byte header.
+    let flags = OpQueryFlags::with_find_options(&options);
+    let message = Message::new_query(1, flags, String::from("db.coll"), 0, 0, doc! {}, None)
+        .unwrap();
+    let bytes = message.to_bytes().unwrap();
+    assert_eq!(bytes[16] & OpQueryFlags::NO_CURSOR_TIMEOUT.bits() as u8, 0x10);
+
+    let sent = Arc::new(SentMessages::default());
+    let mut client_options = ClientOptions::new();
+    client_options.logger = Some(sent.clone() as Arc<Logger>);
+
+    let client = Client::connect_with_options("localhost", 27017, client_options).unwrap();
+    if max_wire_version(&client) < 4 {
+        return;
+    }
+
+    let coll = client.db("test-client-cursor").collection("no_cursor_timeout_on_the_wire");
+    coll.drop().unwrap();
+    coll.insert_one(doc! { "_id": 1 }, None).unwrap();
+
+    // A read concern requires the find command, which carries the option itself.
+    let options = options.to_builder().read_concern(ReadConcern::Local).build();
+    coll.find(None, Some(options)).unwrap().count();
+    assert!(sent.any(&["no_cursor_timeout_on_the_wire", "noCursorTimeout"]));
+}
+
+#[derive(Debug, Default)]
+struct Warnings {
+    messages: Mutex<Vec<String>>,
+}
+
+impl Warnings {
+    fn count(&self, pattern: &str) -> usize {
+        let messages = self.messages.lock().unwrap();
+        messages.iter().filter(|message| message.contains(pattern)).count()
+    }
+}
+
+impl Logger for Warnings {
+    fn enabled(&self, level: LogLevel) -> bool {
+        level == LogLevel::Warn
+    }
+
+    fn log(&self, _level: LogLevel, _target: &str, message: &str) {
+        self.messages.lock().unwrap().push(message.to_owned());
+    }
+}
+
+#[test]
+fn idle_no_cursor_timeout_warning() {
+    let warnings = Arc::new(Warnings::default());
+    let mut client_options = ClientOptions::new();
+    client_options.logger = Some(warnings.clone() as Arc<Logger>);
+    client_options.cursor_idle_warning = Some(Duration::from_millis(100));
+
+    let client = Client::connect_with_options("localhost", 27017, client_options).unwrap();
+    let coll = client.db("test-client-cursor").collection("idle_no_cursor_timeout_warning");
+    coll.drop().unwrap();
+
+    let docs = (0..10).map(|i| doc! { "_id": i }).collect();
+    coll.insert_many(docs, None).unwrap();
+
+    let options = FindOptions::builder().batch_size(2).build();
+
+    let mut regular = coll.find(None, Some(options.clone())).unwrap();
+    regular.next().unwrap().unwrap();
+
+    let options = options.to_builder().no_cursor_timeout(true).build();
+    let mut held = coll.find(None, Some(options)).unwrap();
+    held.next().unwrap().unwrap();
+
+    let held_id = format!("Cursor {} ", held.cursor_id());
+    let regular_id = format!("Cursor {} ", regular.cursor_id());
+
+    thread::sleep(Duration::from_millis(200));
+
+    // Cursors are checked when a server is selected, and each idle period is reported once.
+    coll.count(None, None).unwrap();
+    coll.count(None, None).unwrap();
+    assert_eq!(warnings.count(&held_id), 1);
+    assert_eq!(warnings.count(&regular_id), 0);
+
+    // Fetching a batch starts a new idle period.
+    held.next().unwrap().unwrap();
+    held.next().unwrap().unwrap();
+    coll.count(None, None).unwrap();
+    assert_eq!(warnings.count(&held_id), 1);
+
+    thread::sleep(Duration::from_millis(200));
+    coll.count(None, None).unwrap();
+    assert_eq!(warnings.count(&held_id), 2);
+}
+
+// Takes longer than the server's 10-minute cursor timeout; run with `cargo test -- --ignored`.
+#[test]
+#[ignore]
+fn no_cursor_timeout_outlives_server_timeout() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-cursor").collection("no_cursor_timeout_outlives");
+    coll.drop().unwrap();
+
+    let docs = (0..10).map(|i| doc! { "_id": i }).collect();
+    coll.insert_many(docs, None).unwrap();
+
+    let options = FindOptions::builder().batch_size(2).no_cursor_timeout(true).build();
+
+    let mut cursor = coll.find(None, Some(options)).unwrap();
+    cursor.next().unwrap().unwrap();
+
+    thread::sleep(Duration::from_secs(11 * 60));
+    assert_eq!(cursor.count(), 9);
+}
+
+#[test]
+fn cursor_state_across_batches() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-cursor").collection("cursor_state");
+    coll.drop().unwrap();
+
+    let docs = (0..10).map(|i| doc! { "_id": i }).collect();
+    coll.insert_many(docs, None).unwrap();
+
+    let options = FindOptions::builder().batch_size(4).sort(doc! { "_id": 1 }).build();
+
+    let mut cursor = coll.find(None, Some(options)).unwrap();
+    assert!(cursor.cursor_id() != 0);
+    assert_eq!(cursor.buffered_count(), 4);
+    assert_eq!(cursor.docs_returned_so_far(), 0);
+    assert_eq!(cursor.batch_info(), BatchInfo { starting_from: 0, number_returned: 4 });
+    assert!(!cursor.is_exhausted());
+
+    cursor.next_n(4).unwrap();
+    assert_eq!(cursor.buffered_count(), 0);
+    assert_eq!(cursor.docs_returned_so_far(), 4);
+    assert!(!cursor.is_exhausted());
+
+    // Taking the next document sends a getMore.
+    cursor.next().unwrap().unwrap();
+    assert_eq!(cursor.batch_info(), BatchInfo { starting_from: 4, number_returned: 4 });
+    assert_eq!(cursor.buffered_count(), 3);
+    assert_eq!(cursor.docs_returned_so_far(), 5);
+
+    let summary = format!("{:?}", cursor);
+    assert!(summary.contains(&format!("cursor_id: {}", cursor.cursor_id())));
+    assert!(summary.contains("docs_returned: 5"));
+
+    assert_eq!(cursor.next_n(10).unwrap().len(), 5);
+    assert_eq!(cursor.batch_info(), BatchInfo { starting_from: 8, number_returned: 2 });
+    assert_eq!(cursor.cursor_id(), 0);
+    assert_eq!(cursor.docs_returned_so_far(), 10);
+    assert!(cursor.is_exhausted());
+    assert!(cursor.to_string().ends_with("10 documents returned, 0 buffered, exhausted"));
+}
+
+// Consistent scans use the snapshot option on servers capped below MongoDB 3.6, and a hint of
+// the _id index on later ones, which removed it.
+#[test]
+fn consistent_scan_by_wire_version() {
+    let sent = Arc::new(SentMessages::default());
+    let mut options = ClientOptions::new();
+    options.logger = Some(sent.clone() as Arc<Logger>);
+    options.max_wire_version = Some(5);
+
+    let legacy = Client::connect_with_options("localhost", 27017, options).unwrap();
+    let coll = legacy.db("test-client-cursor").collection("consistent_scan");
+    coll.drop().unwrap();
+    coll.insert_many((0..5).map(|i| doc! { "_id": i }).collect(), None).unwrap();
+
+    let capabilities = legacy.capabilities().unwrap().expect("No server was checked.");
+    let scan = FindOptions::consistent_scan(&capabilities);
+    assert!(scan.snapshot());
+    assert_eq!(scan.hint(), None);
+
+    // Servers that removed the option may reject the query, but it was sent as a modifier.
+    let _ = coll.find(None, Some(scan)).map(|cursor| cursor.count());
+    assert!(sent.any(&["consistent_scan", "$snapshot: true"]));
+
+    let client = Client::connect("localhost", 27017).unwrap();
+    if max_wire_version(&client) < 6 {
+        return;
+    }
+
+    let coll = client.db("test-client-cursor").collection("consistent_scan");
+    let capabilities = client.capabilities().unwrap().expect("No server was checked.");
+    let scan = FindOptions::consistent_scan(&capabilities);
+    assert!(!scan.snapshot());
+    assert_eq!(coll.find(None, Some(scan)).unwrap().count(), 5);
+
+    let snapshot = FindOptions::builder().snapshot(true).build();
+    match coll.find(None, Some(snapshot)) {
+        Err(Error::ArgumentError(ref msg)) if msg.contains("_id") => (),
+        other => panic!("Expected ArgumentError, got {:?}", other),
+    }
+
+    let reverse = FindOptions::builder().natural_sort(Direction::Reverse).build();
+    let ids: Vec<_> = coll.find(None, Some(reverse))
+        .unwrap()
+        .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+        .collect();
+    assert_eq!(ids, vec![4, 3, 2, 1, 0]);
+
+    let conflicting = FindOptions::builder()
+        .natural_sort(Direction::Forward)
+        .hint(Hint::Keys(doc! { "_id": 1 }))
+        .build();
+    assert!(coll.find(None, Some(conflicting)).is_err());
+}
+
+#[test]
+fn merge_sorted_cursors() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-cursor");
+
+    // Overlapping ranges, with a document missing the key and one with a string key.
+    let sources = vec![
+        ("merge_a", vec![doc! { "k": 0 }, doc! { "k": 3 }, doc! { "k": 6 }, doc! { "k": 12 }]),
+        ("merge_b", vec![doc! { "k": 1 }, doc! { "k": 4 }, doc! { "k": 10 }, doc! { "n": 1 }]),
+        ("merge_c", vec![doc! { "k": 2.5 }, doc! { "k": 5 }, doc! { "k": "x" }]),
+    ];
+    for &(name, ref docs) in &sources {
+        let coll = db.collection(name);
+        coll.drop().unwrap();
+        coll.insert_many(docs.clone(), None).unwrap();
+    }
+
+    let open = |direction: i32| -> Vec<Cursor> {
+        sources
+            .iter()
+            .map(|&(name, _)| {
+                let options = FindOptions::builder()
+                    .sort(doc! { "k": direction })
+                    .batch_size(2)
+                    .build();
+                db.collection(name).find(None, Some(options)).unwrap()
+            })
+            .collect()
+    };
+
+    let mut expected = vec![
+        Bson::Null,
+        Bson::I32(0),
+        Bson::I32(1),
+        Bson::FloatingPoint(2.5),
+        Bson::I32(3),
+        Bson::I32(4),
+        Bson::I32(5),
+        Bson::I32(6),
+        Bson::I32(10),
+        Bson::I32(12),
+        Bson::String(String::from("x")),
+    ];
+
+    let mut merged = Cursor::merge_sorted(open(1), "k", SortOrder::Ascending);
+    let keys: Vec<_> = merged
+        .by_ref()
+        .map(|doc| doc.unwrap().get("k").cloned().unwrap_or(Bson::Null))
+        .collect();
+    assert_eq!(keys, expected);
+
+    // Every cursor was read to its end, fetching further batches on the way.
+    assert!(merged.is_exhausted());
+    for cursor in merged.cursors() {
+        assert!(cursor.is_exhausted());
+        assert!(!cursor.is_alive());
+    }
+
+    expected.reverse();
+    let keys: Vec<_> = Cursor::merge_sorted(open(-1), "k", SortOrder::Descending)
+        .map(|doc| doc.unwrap().get("k").cloned().unwrap_or(Bson::Null))
+        .collect();
+    assert_eq!(keys, expected);
+}
+
+// Reads a two-batch cursor opened with a comment from a server of the given wire version,
+// returning the getMore commands the server received.
+fn get_mores_with_comment(max_wire_version: i32) -> Vec<Document> {
+    let get_mores = Arc::new(Mutex::new(Vec::new()));
+    let recorded = get_mores.clone();
+    let server = MockServer::start(move |request| {
+        if request.is_is_master() {
+            return Response::Reply(reply(request, standalone(max_wire_version)));
+        }
+
+        let reply_doc = match request.command_name() {
+            Some("find") => doc! {
+                "cursor": { "id": 42i64, "ns": "test.comments", "firstBatch": [{ "x": 1 }] },
+                "ok": 1.0,
+            },
+            Some("getMore") => {
+                recorded.lock().unwrap().push(request.query.clone());
+                doc! {
+                    "cursor": { "id": 0i64, "ns": "test.comments", "nextBatch": [{ "x": 2 }] },
+                    "ok": 1.0,
+                }
+            }
+            _ => doc! { "ok": 1.0 },
+        };
+        Response::Reply(reply(request, reply_doc))
+    });
+
+    let client = Client::connect("127.0.0.1", server.port).unwrap();
+    let coll = client.db("test").collection("comments");
+    let options = FindOptions::builder().comment(String::from("tagged")).build();
+    let cursor = coll.find(None, Some(options)).expect("Failed to find documents.");
+    assert_eq!(cursor.map(|doc| doc.unwrap()).count(), 2);
+
+    let get_mores = get_mores.lock().unwrap();
+    get_mores.clone()
+}
+
+#[test]
+fn get_more_comment_by_wire_version() {
+    let get_mores = get_mores_with_comment(9);
+    assert_eq!(get_mores.len(), 1);
+    assert_eq!(get_mores[0].get_str("comment").ok(), Some("tagged"));
+
+    let get_mores = get_mores_with_comment(8);
+    assert_eq!(get_mores.len(), 1);
+    assert!(!get_mores[0].contains_key("comment"));
+}
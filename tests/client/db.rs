@@ -1,7 +1,11 @@
 use bson::{self, Bson};
-use mongodb::{Client, ThreadedClient};
-use mongodb::db::ThreadedDatabase;
-use mongodb::db::options::CreateUserOptions;
+use mongodb::{Client, CommandType, Error, ErrorCode, ThreadedClient};
+use mongodb::common::{RetryPolicy, WriteConcern};
+use mongodb::db::{Database, ThreadedDatabase};
+use mongodb::db::collection_info::CollectionType;
+use mongodb::db::options::{CollectionNamesOptions, CreateUserOptions, ListCollectionsOptions,
+                           NameFilter};
+use mongodb::db::profiler::ProfilingLevel;
 use mongodb::db::roles::{AllDatabaseRole, SingleDatabaseRole, Role};
 
 #[test]
@@ -96,6 +100,115 @@ fn list_collections() {
     }
 }
 
+#[test]
+fn list_collections_with_options() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-list_collections_with_options");
+    db.drop_database().unwrap();
+
+    for i in 0..25 {
+        db.collection(&format!("logs.{}", i)).insert_one(doc! {}, None).unwrap();
+        db.collection(&format!("data.{}", i)).insert_one(doc! {}, None).unwrap();
+    }
+
+    // Small batches stream the whole catalog over several getMores.
+    let options = ListCollectionsOptions {
+        batch_size: Some(3),
+        ..ListCollectionsOptions::new()
+    };
+
+    client.enable_slow_op_capture(0, 100);
+    let names: Vec<_> = db.list_collections_with_options(None, Some(options))
+        .unwrap()
+        .map(|doc| doc.unwrap().get_str("name").unwrap().to_owned())
+        .filter(|name| !name.starts_with("system."))
+        .collect();
+    let ops = client.drain_slow_ops();
+    client.disable_slow_op_capture();
+
+    assert_eq!(50, names.len());
+
+    // The getMores name the command cursor's namespace, not a collection split off its end.
+    let get_mores: Vec<_> = ops.iter().filter(|op| op.command_name == "getMore").collect();
+    assert!(!get_mores.is_empty());
+    for op in get_mores {
+        // Legacy getMore messages are recorded without the field.
+        if let Ok(collection) = op.command.get_str("collection") {
+            assert_eq!(collection, "$cmd.listCollections");
+        }
+    }
+
+    let options = ListCollectionsOptions {
+        batch_size: Some(2),
+        name_only: true,
+    };
+
+    let db_version = db.version().unwrap();
+    let filter = if db_version.major < 3 {
+        doc! { "name": "logs.7" }
+    } else {
+        doc! { "name": { "$regex": "^logs\\." } }
+    };
+
+    let results: Vec<_> = db.list_collections_with_options(Some(filter), Some(options))
+        .unwrap()
+        .map(|doc| doc.unwrap())
+        .collect();
+
+    assert!(!results.is_empty());
+    for doc in &results {
+        assert!(doc.get_str("name").unwrap().starts_with("logs."));
+    }
+
+    let mut names = db.collection_names(None).unwrap();
+    names.retain(|name| !name.starts_with("system."));
+    assert_eq!(50, names.len());
+    assert!(names.iter().all(|name| !name.contains('$')));
+}
+
+#[test]
+fn collection_names_with_options() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-collection_names_with_options");
+    db.drop_database().unwrap();
+
+    for name in &["b.2", "a.1", "b.1", "b+1", "c"] {
+        db.create_collection(name, None).unwrap();
+    }
+    db.collection("c").create_index(doc! { "x": 1 }, None).unwrap();
+    db.set_profiling_level(ProfilingLevel::All, None).unwrap();
+    db.collection("c").find_one(None, None).unwrap();
+    db.set_profiling_level(ProfilingLevel::Off, None).unwrap();
+
+    let names = db.collection_names_with_options(None).unwrap();
+    assert_eq!(names, vec!["a.1", "b+1", "b.1", "b.2", "c"]);
+
+    let options = CollectionNamesOptions { include_system: true, ..CollectionNamesOptions::new() };
+    let names = db.collection_names_with_options(Some(options)).unwrap();
+    assert!(names.contains(&String::from("system.profile")));
+    assert!(names.iter().all(|name| !name.contains('$')));
+
+    // The prefix is matched literally.
+    let options = CollectionNamesOptions {
+        name_filter: Some(NameFilter::Prefix(String::from("b."))),
+        ..CollectionNamesOptions::new()
+    };
+    assert_eq!(db.collection_names_with_options(Some(options)).unwrap(), vec!["b.1", "b.2"]);
+
+    if db.version().unwrap().major >= 3 {
+        let options = CollectionNamesOptions {
+            name_filter: Some(NameFilter::Regex(String::from("1$"))),
+            ..CollectionNamesOptions::new()
+        };
+        let names = db.collection_names_with_options(Some(options)).unwrap();
+        assert_eq!(names, vec!["a.1", "b+1", "b.1"]);
+    }
+
+    assert!(db.collection_exists("b.1").unwrap());
+    assert!(!db.collection_exists("b").unwrap());
+    assert!(!db.collection_exists("missing").unwrap());
+}
+
 #[test]
 fn create_and_get_users() {
     let client = Client::connect("localhost", 27017).unwrap();
@@ -193,3 +306,288 @@ fn get_version() {
     let db = client.db("test-client-db-get_version");
     let _ = db.version().unwrap();
 }
+
+#[test]
+fn run_command_retry() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-run_command_retry");
+
+    let policy = RetryPolicy::new(3, 10);
+    let result = db.run_command_retry(doc! { "ping": 1 }, CommandType::Suppressed, policy)
+        .expect("Failed to run command with retry policy.");
+
+    assert!(result.contains_key("ok"));
+}
+
+#[test]
+fn run_raw_command() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-run_raw_command");
+
+    let result = db.run_raw_command(doc! { "ping": 1 }).expect("Failed to run raw command.");
+    assert_eq!(result.host.port, 27017);
+    assert!(result.request_id > 0);
+    assert!(result.reply.contains_key("ok"));
+
+    // A failed command is returned as is.
+    let result = db.run_raw_command(doc! { "notARealCommand": 1 })
+        .expect("Failed to run raw command.");
+    assert_eq!(result.reply.get("ok"), Some(&Bson::FloatingPoint(0.0)));
+    assert!(result.reply.contains_key("errmsg"));
+
+    // The request ids of the client are unique.
+    let next = db.run_raw_command(doc! { "ping": 1 }).expect("Failed to run raw command.");
+    assert!(next.request_id > result.request_id);
+}
+
+#[test]
+fn run_command_checked() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("admin");
+
+    let reply = db.run_command_checked(doc! { "ping": 1 }, CommandType::Suppressed, None)
+        .expect("Failed to run command.");
+    assert!(reply.contains_key("ok"));
+
+    // Unknown commands are reported as successful replies by the unchecked `command`.
+    let spec = doc! { "notARealCommand": 1 };
+    assert!(db.command(spec.clone(), CommandType::Suppressed, None).is_ok());
+    match db.run_command_checked(spec, CommandType::Suppressed, None) {
+        Err(Error::CommandError(ref err)) => {
+            assert!(err.has_code(&[ErrorCode::CommandNotFound]));
+            assert!(!err.message.is_empty());
+        }
+        other => panic!("Expected CommandError, got {:?}", other),
+    }
+}
+
+#[test]
+fn drop_missing_collection() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-drop_missing_collection");
+    db.drop_database().unwrap();
+
+    // The wrapper checks the reply, but a collection that doesn't exist is already dropped.
+    db.drop_collection("missing").expect("Failed to drop missing collection.");
+
+    let spec = doc! { "drop": "missing" };
+    match db.run_command_checked(spec, CommandType::DropCollection, None) {
+        Err(Error::CommandError(_)) => (),
+        other => panic!("Expected CommandError, got {:?}", other),
+    }
+}
+
+#[test]
+fn profiling() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-profiling");
+    db.drop_database().unwrap();
+
+    db.set_profiling_level(ProfilingLevel::All, Some(100)).unwrap();
+    assert_eq!(ProfilingLevel::All, db.get_profiling_level().unwrap());
+
+    let coll = db.collection("profiled");
+    coll.insert_one(doc! { "x": 1 }, None).unwrap();
+    coll.find_one(Some(doc! { "x": 1 }), None).unwrap();
+
+    db.set_profiling_level(ProfilingLevel::Off, None).unwrap();
+    assert_eq!(ProfilingLevel::Off, db.get_profiling_level().unwrap());
+
+    let filter = doc! { "ns": "test-client-db-profiling.profiled" };
+    let entries = db.get_profiling_info(Some(filter), Some(10)).unwrap();
+    assert!(!entries.is_empty());
+
+    for entry in &entries {
+        assert_eq!("test-client-db-profiling.profiled", entry.ns);
+        assert!(entry.millis >= 0);
+    }
+
+    // The most recent operation comes first.
+    assert!(entries[0].op == "query" || entries[0].op == "command");
+    assert!(entries.iter().any(|entry| entry.op == "insert"));
+}
+
+#[test]
+fn get_db_validation() {
+    let client = Client::connect("localhost", 27017).unwrap();
+
+    assert!(client.get_db("test-client-db-get_db_validation").is_ok());
+
+    let long_name: String = (0..65).map(|_| 'a').collect();
+    let invalid_names = vec![
+        "", "my/db", "my\\db", "my.db", "my\"db", "my$db", "my*db", "my<db", "my>db", "my:db",
+        "my|db", "my?db", "my\0db", &long_name[..],
+    ];
+
+    for name in invalid_names {
+        assert!(client.get_db(name).is_err(), "{:?} should be invalid", name);
+    }
+
+    // Dropping a database by an invalid name fails instead of panicking.
+    assert!(client.drop_database("my.db").is_err());
+
+    // The unchecked constructor still allows any name.
+    let db = Database::open_unchecked(client.clone(), "my.db", None, None);
+    assert_eq!("my.db", db.name);
+}
+
+#[test]
+#[should_panic(expected = "cannot contain '.'")]
+fn db_panics_on_invalid_names() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    client.db("my.db");
+}
+
+#[test]
+fn eval() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-eval");
+
+    let version = db.version().expect("Failed to get server version.");
+    let code = "function(a, b) { return a.x + b.x; }";
+    let args = vec![Bson::Document(doc! { "x": 1 }), Bson::Document(doc! { "x": 2 })];
+    let result = db.eval(code, args, true);
+
+    if (version.major, version.minor) >= (4, 2) {
+        match result {
+            Err(Error::UnsupportedByServerError(ref msg)) => assert!(msg.contains("4.2")),
+            other => panic!("Expected UnsupportedByServerError, got {:?}", other),
+        }
+    } else {
+        assert_eq!(result.expect("Failed to evaluate code."), Bson::FloatingPoint(3.0));
+    }
+}
+
+#[test]
+fn legacy_error_inspection() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-legacy_error_inspection");
+    db.drop_database().unwrap();
+
+    let version = db.version().expect("Failed to get server version.");
+    let unsupported_since = |result: Result<_, Error>, major, minor, removed: &str| {
+        if (version.major, version.minor) >= (major, minor) {
+            match result {
+                Err(Error::UnsupportedByServerError(ref msg)) => assert!(msg.contains(removed)),
+                other => panic!("Expected UnsupportedByServerError, got {:?}", other),
+            }
+            None
+        } else {
+            Some(result.expect("Failed to run command."))
+        }
+    };
+
+    // The errors are kept per connection, so the writes and the reports share one.
+    let mut conn = db.pin_connection().expect("Failed to pin a connection.");
+    unsupported_since(conn.reset_error_history().map(|_| doc! {}), 5, 0, "5.0");
+
+    let duplicate = conn.insert("errors", vec![doc! { "_id": 1 }, doc! { "_id": 1 }], false);
+    if unsupported_since(duplicate.map(|_| doc! {}), 5, 1, "5.1").is_none() {
+        return;
+    }
+    conn.update("errors", doc! { "_id": 1 }, doc! { "$set": { "x": 1 } }, false, false)
+        .expect("Failed to send update.");
+
+    let mut write_concern = WriteConcern::new();
+    write_concern.fsync = true;
+    let reply = conn.get_last_error(Some(write_concern)).expect("Failed to get last error.");
+    assert_eq!(reply.get("err"), Some(&Bson::Null));
+    assert!(reply.get_bool("updatedExisting").unwrap());
+
+    // The duplicate key error is reported until the history is reset.
+    if let Some(reply) = unsupported_since(conn.get_prev_error(), 4, 2, "4.2") {
+        assert_eq!(reply.get_i32("code").unwrap(), 11000);
+    }
+
+    conn.delete("errors", doc! { "_id": 1 }, false).expect("Failed to send delete.");
+    conn.insert("errors", vec![doc! { "_id": 2 }, doc! { "_id": 2 }], true)
+        .expect("Failed to send insert.");
+    let reply = conn.get_last_error(None).expect("Failed to get last error.");
+    assert_eq!(reply.get_i32("code").unwrap(), 11000);
+
+    let count = db.collection("errors").count(None, None).unwrap();
+    assert_eq!(count, 1);
+}
+
+#[test]
+fn stored_functions() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-stored_functions");
+    db.drop_database().unwrap();
+
+    db.save_function("double", "function(x) { return x * 2; }")
+        .expect("Failed to save function.");
+    db.save_function("add", "function(a, b) { return a + b; }")
+        .expect("Failed to save function.");
+
+    // Saving under an existing name replaces the function.
+    db.save_function("double", "function(x) { return x + x; }")
+        .expect("Failed to replace function.");
+
+    let names = db.list_functions().expect("Failed to list functions.");
+    assert_eq!(names, vec![String::from("add"), String::from("double")]);
+
+    let stored = db.collection("system.js")
+        .find_one(Some(doc! { "_id": "double" }), None)
+        .unwrap()
+        .expect("Failed to find stored function.");
+    assert_eq!(
+        stored.get("value"),
+        Some(&Bson::JavaScriptCode(String::from("function(x) { return x + x; }")))
+    );
+}
+
+#[test]
+fn create_and_query_view() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-db-create_and_query_view");
+    db.drop_database().unwrap();
+
+    let coll = db.collection("numbers");
+    let docs = (0..10).map(|i| doc! { "_id": i, "even": i % 2 == 0 }).collect();
+    coll.insert_many(docs, None).unwrap();
+
+    let pipeline = vec![doc! { "$match": { "even": true } }];
+    db.create_view("evens", "numbers", pipeline.clone(), None).unwrap();
+
+    let infos = db.list_collection_infos(None).unwrap();
+    let view = infos.iter().find(|info| info.name == "evens").expect("View was not listed.");
+    assert_eq!(view.collection_type, CollectionType::View);
+    assert!(view.is_view());
+    assert!(view.read_only);
+    assert_eq!(view.view_on, Some(String::from("numbers")));
+    assert_eq!(view.pipeline, pipeline);
+
+    let numbers = infos.iter().find(|info| info.name == "numbers").unwrap();
+    assert_eq!(numbers.collection_type, CollectionType::Collection);
+    assert!(!numbers.read_only);
+
+    // Reads run the view's pipeline.
+    let evens = db.collection("evens");
+    let ids: Vec<_> = evens
+        .find(Some(doc! { "_id": { "$gte": 4 } }), None)
+        .unwrap()
+        .map(|doc| doc.unwrap().get_i32("_id").unwrap())
+        .collect();
+    assert_eq!(ids, vec![4, 6, 8]);
+    assert_eq!(evens.count(None, None).unwrap(), 5);
+
+    let sum = evens
+        .aggregate(vec![doc! { "$group": { "_id": null, "sum": { "$sum": "$_id" } } }], None)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap();
+    assert_eq!(sum.get_i32("sum").unwrap(), 20);
+
+    // Writes fail fast with a distinct error.
+    match evens.insert_one(doc! { "_id": 10, "even": true }, None) {
+        Err(Error::ViewWriteError(_)) => (),
+        other => panic!("Expected ViewWriteError, got {:?}", other),
+    }
+    match evens.delete_many(doc! {}, None) {
+        Err(Error::ViewWriteError(_)) => (),
+        other => panic!("Expected ViewWriteError, got {:?}", other),
+    }
+    assert_eq!(coll.count(None, None).unwrap(), 10);
+}
@@ -0,0 +1,269 @@
+use bson::{Bson, Document};
+use bson::spec::BinarySubtype;
+use mongodb::{Client, Error, ThreadedClient};
+use mongodb::coll::Collection;
+use mongodb::coll::encryption::{EncryptionAlgorithm, FieldEncryptor, DATA_KEY_LENGTH};
+use mongodb::db::ThreadedDatabase;
+
+fn encryptor() -> FieldEncryptor {
+    let mut encryptor = FieldEncryptor::new();
+    let ssn_key: Vec<u8> = (0..DATA_KEY_LENGTH as u8).collect();
+    let notes_key = vec![7; DATA_KEY_LENGTH];
+
+    encryptor.add_field("ssn", &ssn_key, EncryptionAlgorithm::Deterministic).unwrap();
+    encryptor
+        .add_field("profile.notes", &notes_key, EncryptionAlgorithm::Random)
+        .unwrap();
+    encryptor
+}
+
+// Returns a collection that encrypts fields, and a handle to the same collection that doesn't.
+fn collections(name: &str) -> (Collection, Collection) {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-encryption");
+    let raw = db.collection(name);
+    raw.drop().unwrap();
+
+    let mut coll = db.collection(name);
+    coll.set_field_encryptor(encryptor());
+    (coll, raw)
+}
+
+fn is_ciphertext(value: Option<&Bson>) -> bool {
+    match value {
+        Some(&Bson::Binary(subtype, _)) => u8::from(subtype) == 6,
+        _ => false,
+    }
+}
+
+fn person() -> Document {
+    doc! {
+        "_id": 1,
+        "name": "Ada",
+        "ssn": "123-45-6789",
+        "profile": { "notes": "allergic to peanuts", "city": "London" },
+    }
+}
+
+#[test]
+fn add_field_checks() {
+    let mut encryptor = FieldEncryptor::new();
+    let key = vec![1; DATA_KEY_LENGTH];
+
+    assert!(encryptor.add_field("ssn", &key[..32], EncryptionAlgorithm::Random).is_err());
+    assert!(encryptor.add_field("_id", &key, EncryptionAlgorithm::Random).is_err());
+    assert!(encryptor.add_field("a..b", &key, EncryptionAlgorithm::Random).is_err());
+    assert!(encryptor.add_field("$set", &key, EncryptionAlgorithm::Random).is_err());
+    assert!(encryptor.is_empty());
+
+    // Keys are never printed.
+    encryptor.add_field("ssn", &key, EncryptionAlgorithm::Random).unwrap();
+    assert_eq!(format!("{:?}", encryptor), "{\"ssn\": Random}");
+}
+
+#[test]
+fn encrypt_and_decrypt_documents() {
+    let encryptor = encryptor();
+
+    let encrypted = encryptor.encrypt_document(person()).unwrap();
+    assert!(is_ciphertext(encrypted.get("ssn")));
+    assert_eq!(encrypted.get_str("name").unwrap(), "Ada");
+
+    let profile = encrypted.get_document("profile").unwrap();
+    assert!(is_ciphertext(profile.get("notes")));
+    assert_eq!(profile.get_str("city").unwrap(), "London");
+
+    assert_eq!(encryptor.decrypt_document(encrypted.clone()).unwrap(), person());
+
+    // Deterministic encryption is repeatable, random encryption is not.
+    let again = encryptor.encrypt_document(person()).unwrap();
+    assert_eq!(again.get("ssn"), encrypted.get("ssn"));
+    assert!(again.get_document("profile").unwrap().get("notes") != profile.get("notes"));
+
+    // Values that are already encrypted are left as they are.
+    assert_eq!(encryptor.encrypt_document(encrypted.clone()).unwrap(), encrypted);
+
+    // Types that deterministic encryption can't hide the structure of are rejected.
+    match encryptor.encrypt_document(doc! { "ssn": 1.5 }) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected ArgumentError, got {:?}", other),
+    }
+
+    // A tampered ciphertext fails authentication.
+    let mut tampered = match encrypted.get("ssn") {
+        Some(&Bson::Binary(_, ref bytes)) => bytes.clone(),
+        other => panic!("Expected a binary, got {:?}", other),
+    };
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+
+    let tampered = doc! { "ssn": Bson::Binary(BinarySubtype::from(6), tampered) };
+    match encryptor.decrypt_document(tampered) {
+        Err(Error::EncryptionError(_)) => (),
+        other => panic!("Expected EncryptionError, got {:?}", other),
+    }
+
+    // Ciphertexts of unknown keys are left encrypted.
+    let other = FieldEncryptor::new();
+    assert_eq!(other.decrypt_document(encrypted.clone()).unwrap(), encrypted);
+}
+
+#[test]
+fn encrypt_fields_in_arrays() {
+    let mut encryptor = FieldEncryptor::new();
+    let key = vec![3; DATA_KEY_LENGTH];
+    encryptor.add_field("contacts.phone", &key, EncryptionAlgorithm::Random).unwrap();
+
+    let doc = doc! {
+        "contacts": [
+            { "name": "Ada", "phone": "555-0100" },
+            [{ "phone": "555-0101" }],
+            "no phone",
+        ],
+    };
+
+    let encrypted = encryptor.encrypt_document(doc.clone()).unwrap();
+    let contacts = encrypted.get_array("contacts").unwrap();
+    match contacts[0] {
+        Bson::Document(ref contact) => {
+            assert!(is_ciphertext(contact.get("phone")));
+            assert_eq!(contact.get_str("name").unwrap(), "Ada");
+        }
+        ref other => panic!("Expected a document, got {:?}", other),
+    }
+    match contacts[1] {
+        Bson::Array(ref nested) => {
+            match nested[0] {
+                Bson::Document(ref contact) => assert!(is_ciphertext(contact.get("phone"))),
+                ref other => panic!("Expected a document, got {:?}", other),
+            }
+        }
+        ref other => panic!("Expected an array, got {:?}", other),
+    }
+    assert_eq!(contacts[2], Bson::String(String::from("no phone")));
+
+    assert_eq!(encryptor.decrypt_document(encrypted).unwrap(), doc);
+
+    let update = encryptor
+        .encrypt_update(doc! { "$set": { "contacts": [{ "phone": "555-0102" }] } })
+        .unwrap();
+    match update.get_document("$set").unwrap().get_array("contacts").unwrap()[0] {
+        Bson::Document(ref contact) => assert!(is_ciphertext(contact.get("phone"))),
+        ref other => panic!("Expected a document, got {:?}", other),
+    }
+}
+
+#[test]
+fn encrypt_filters_and_updates() {
+    let encryptor = encryptor();
+
+    let filter = encryptor
+        .encrypt_filter(doc! {
+            "$or": [{ "ssn": "1" }, { "ssn": { "$in": ["2", "3"] } }],
+            "name": "Ada",
+        })
+        .unwrap();
+    let clauses = filter.get_array("$or").unwrap();
+    match clauses[0] {
+        Bson::Document(ref clause) => assert!(is_ciphertext(clause.get("ssn"))),
+        ref other => panic!("Expected a document, got {:?}", other),
+    }
+    assert_eq!(filter.get_str("name").unwrap(), "Ada");
+
+    assert!(encryptor.encrypt_filter(doc! { "ssn": { "$gt": "1" } }).is_err());
+    assert!(encryptor.encrypt_filter(doc! { "profile.notes": "x" }).is_err());
+
+    // Subdocuments holding encrypted fields can't be matched whole, whatever the algorithm.
+    let mut deterministic = FieldEncryptor::new();
+    let key = vec![5; DATA_KEY_LENGTH];
+    deterministic.add_field("address.street", &key, EncryptionAlgorithm::Deterministic).unwrap();
+
+    let whole = doc! { "address": { "street": "Main St", "city": "London" } };
+    match deterministic.encrypt_filter(whole) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected ArgumentError, got {:?}", other),
+    }
+    let nested = doc! { "address": { "$eq": { "street": "Main St" } } };
+    assert!(deterministic.encrypt_filter(nested).is_err());
+    assert!(encryptor.encrypt_filter(doc! { "profile": { "notes": "x" } }).is_err());
+
+    let exists = doc! { "address": { "$exists": true }, "address.street": "Main St" };
+    let filter = deterministic.encrypt_filter(exists).unwrap();
+    assert!(is_ciphertext(filter.get("address.street")));
+
+    let update = encryptor
+        .encrypt_update(doc! { "$set": { "ssn": "1", "profile.notes": "x", "name": "Bob" } })
+        .unwrap();
+    let set = update.get_document("$set").unwrap();
+    assert!(is_ciphertext(set.get("ssn")));
+    assert!(is_ciphertext(set.get("profile.notes")));
+    assert_eq!(set.get_str("name").unwrap(), "Bob");
+
+    assert!(encryptor.encrypt_update(doc! { "$inc": { "ssn": 1 } }).is_err());
+    assert!(encryptor.encrypt_update(doc! { "$push": { "profile": 1 } }).is_err());
+    assert!(encryptor.encrypt_update(doc! { "$unset": { "ssn": "" } }).is_ok());
+}
+
+#[test]
+fn stored_values_are_ciphertext() {
+    let (coll, raw) = collections("stored_values_are_ciphertext");
+    coll.insert_one(person(), None).unwrap();
+
+    let stored = raw.find_one(None, None).unwrap().unwrap();
+    assert!(is_ciphertext(stored.get("ssn")));
+    assert!(is_ciphertext(stored.get_document("profile").unwrap().get("notes")));
+    assert!(!format!("{:?}", stored).contains("123-45-6789"));
+    assert!(!format!("{:?}", stored).contains("peanuts"));
+
+    // The plaintext is not found by the server.
+    assert_eq!(raw.count(Some(doc! { "ssn": "123-45-6789" }), None).unwrap(), 0);
+}
+
+#[test]
+fn round_trip() {
+    let (coll, raw) = collections("round_trip");
+    coll.insert_many(vec![person(), doc! { "_id": 2, "ssn": "987-65-4321" }], None).unwrap();
+
+    // Queries on the deterministically encrypted field match the encrypted values.
+    let found = coll.find_one(Some(doc! { "ssn": "123-45-6789" }), None).unwrap();
+    assert_eq!(found, Some(person()));
+    assert_eq!(coll.count(Some(doc! { "ssn": { "$in": ["987-65-4321"] } }), None).unwrap(), 1);
+
+    let docs: Vec<_> = coll.find(None, None).unwrap().map(|doc| doc.unwrap()).collect();
+    assert_eq!(docs[0], person());
+
+    let docs: Vec<_> = coll
+        .aggregate(vec![doc! { "$match": { "_id": 2 } }], None)
+        .unwrap()
+        .map(|doc| doc.unwrap())
+        .collect();
+    assert_eq!(docs, vec![doc! { "_id": 2, "ssn": "987-65-4321" }]);
+
+    // Updates and replacements encrypt the values they write.
+    let update = doc! { "$set": { "profile.notes": "no allergies" } };
+    coll.update_one(doc! { "ssn": "123-45-6789" }, update, None).unwrap();
+
+    let stored = raw.find_one(Some(doc! { "_id": 1 }), None).unwrap().unwrap();
+    assert!(is_ciphertext(stored.get_document("profile").unwrap().get("notes")));
+
+    let replacement = doc! { "ssn": "555-55-5555" };
+    coll.replace_one(doc! { "ssn": "987-65-4321" }, replacement, None).unwrap();
+
+    let stored = raw.find_one(Some(doc! { "_id": 2 }), None).unwrap().unwrap();
+    assert!(is_ciphertext(stored.get("ssn")));
+
+    let updated = coll
+        .find_one_and_update(doc! { "_id": 1 }, doc! { "$set": { "name": "Ada L." } }, None)
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.get_document("profile").unwrap().get_str("notes").unwrap(), "no allergies");
+
+    let deleted = coll.find_one_and_delete(doc! { "ssn": "555-55-5555" }, None).unwrap();
+    assert_eq!(deleted, Some(doc! { "_id": 2, "ssn": "555-55-5555" }));
+
+    // The randomly encrypted field can't be queried.
+    match coll.find_one(Some(doc! { "profile.notes": "no allergies" }), None) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected ArgumentError, got {:?}", other),
+    }
+}
@@ -1,6 +1,11 @@
-use mongodb::common::WriteConcern;
+use mongodb::common::{RetryPolicy, WriteConcern};
 use mongodb::coll::error::{BulkWriteException, WriteConcernError, WriteError};
-use mongodb::Error;
+use mongodb::db::ThreadedDatabase;
+use mongodb::error::{check_command_ok, check_get_last_error, query_failure_error, CommandFailure,
+                      RETRYABLE_WRITE_ERROR, TRANSIENT_TRANSACTION_ERROR,
+                      UNKNOWN_TRANSACTION_COMMIT_RESULT};
+use mongodb::{Client, ClientOptions, Error, ErrorCode, ThreadedClient};
+use std::time::{Duration, Instant};
 
 #[test]
 fn validate_write_result() {
@@ -71,6 +76,46 @@ fn invalidate_write_result() {
     }
 }
 
+#[test]
+fn parse_duplicate_key_errors() {
+    let modern = doc! {
+        "index": 2,
+        "code": 11000,
+        "errmsg": "E11000 duplicate key error collection: test.c index: a_1_b_-1 dup key: \
+                   { a: 1, b: 2 }",
+        "keyPattern": { "a": 1, "b": -1 },
+        "keyValue": { "a": 1, "b": 2 },
+    };
+    let legacy = doc! {
+        "index": 4,
+        "code": 11000,
+        "errmsg": "E11000 duplicate key error index: test.c.$a_1  dup key: { : 1 }",
+    };
+    let other = doc! { "index": 5, "code": 2, "errmsg": "index: not_an_index" };
+
+    let doc = doc! { "ok": 1, "n": 3, "writeErrors": [modern, legacy, other] };
+    let exception = match BulkWriteException::validate_bulk_write_result(doc, WriteConcern::new()) {
+        Err(Error::BulkWriteError(exception)) => exception,
+        other => panic!("Expected a BulkWriteError, got {:?}", other),
+    };
+
+    let modern = &exception.write_errors[0];
+    assert!(modern.is_duplicate_key());
+    assert_eq!(modern.index_name, Some(String::from("a_1_b_-1")));
+    assert_eq!(modern.key_pattern, Some(doc! { "a": 1, "b": -1 }));
+    assert_eq!(modern.key_value, Some(doc! { "a": 1, "b": 2 }));
+
+    let legacy = &exception.write_errors[1];
+    assert_eq!(legacy.index_name, Some(String::from("a_1")));
+    assert_eq!(legacy.key_pattern, None);
+
+    let other = &exception.write_errors[2];
+    assert!(!other.is_duplicate_key());
+    assert_eq!(other.index_name, None);
+
+    assert_eq!(exception.duplicate_key_indexes(), vec![2, 4]);
+}
+
 #[test]
 fn parse_write_concern_error() {
     let doc =
@@ -129,3 +174,351 @@ fn parse_invalid_write_error() {
     let result = WriteError::parse(doc);
     assert!(result.is_err());
 }
+
+#[test]
+fn classify_not_master_errors() {
+    assert!(Error::CodedError(ErrorCode::NotMaster).is_not_master());
+    assert!(Error::CodedError(ErrorCode::NotMasterNoSlaveOkCode).is_not_master());
+    assert!(Error::CodedError(ErrorCode::NotMasterOrSecondaryCode).is_not_master());
+    assert!(!Error::OperationError(String::from("not master and slaveOk=false")).is_not_master());
+    assert!(!Error::CodedError(ErrorCode::DuplicateKey).is_not_master());
+    assert!(!Error::OperationError(String::from("ns not found")).is_not_master());
+}
+
+#[test]
+fn check_command_ok_encodings() {
+    assert!(check_command_ok(&doc! { "ok": 1.0 }).is_ok());
+    assert!(check_command_ok(&doc! { "ok": 1 }).is_ok());
+    assert!(check_command_ok(&doc! { "ok": 1_i64 }).is_ok());
+    assert!(check_command_ok(&doc! { "ok": true }).is_ok());
+
+    match check_command_ok(&doc! { "n": 1 }) {
+        Err(Error::ProtocolError(_)) => (),
+        other => panic!("Expected ProtocolError, got {:?}", other),
+    }
+
+    let reply = doc! {
+        "ok": 0_i64,
+        "errmsg": "not master",
+        "code": 10107,
+        "codeName": "NotMaster",
+    };
+
+    match check_command_ok(&reply) {
+        Err(Error::CommandError(ref err)) => {
+            assert_eq!(err.code, Some(10107));
+            assert_eq!(err.code_name, Some(String::from("NotMaster")));
+            assert_eq!(err.message, "not master");
+            assert_eq!(format!("{}", err), "not master (NotMaster, code 10107)");
+        }
+        other => panic!("Expected CommandError, got {:?}", other),
+    }
+
+    match check_command_ok(&doc! { "ok": 0.0 }) {
+        Err(Error::CommandError(ref err)) => {
+            assert_eq!(err.code, None);
+            assert_eq!(err.message, "Command failed without an error message.");
+        }
+        other => panic!("Expected CommandError, got {:?}", other),
+    }
+}
+
+#[test]
+fn get_last_error_replies() {
+    let acknowledged = WriteConcern::new();
+    let mut journaled = WriteConcern::new();
+    journaled.j = true;
+
+    let reply = doc! { "connectionId": 1, "n": 0, "syncMillis": 0, "err": null, "ok": 1.0 };
+    assert!(check_get_last_error(&reply, &acknowledged).is_ok());
+
+    // The error of the write itself is left to the caller.
+    let reply = doc! { "err": "E11000 duplicate key error", "code": 11000, "ok": 1.0 };
+    assert!(check_get_last_error(&reply, &acknowledged).is_ok());
+
+    let reply = doc! {
+        "ok": 0.0,
+        "errmsg": "cannot use 'j' option when a host does not have journaling enabled",
+        "code": 2,
+        "codeName": "BadValue",
+    };
+    match check_get_last_error(&reply, &journaled) {
+        Err(Error::JournalingDisabledError(ref msg)) => assert!(msg.contains("'j' option")),
+        other => panic!("Expected JournalingDisabledError, got {:?}", other),
+    }
+
+    // The same code without `j` is some other bad value.
+    match check_get_last_error(&reply, &acknowledged) {
+        Err(Error::CommandError(ref err)) => assert!(err.has_code(&[ErrorCode::BadValue])),
+        other => panic!("Expected CommandError, got {:?}", other),
+    }
+
+    let reply = doc! { "err": null, "jnote": "journaling not enabled on this server", "ok": 1.0 };
+    match check_get_last_error(&reply, &journaled) {
+        Err(Error::JournalingDisabledError(ref msg)) => {
+            assert_eq!(msg, "journaling not enabled on this server")
+        }
+        other => panic!("Expected JournalingDisabledError, got {:?}", other),
+    }
+
+    let reply = doc! {
+        "err": "waiting for replication timed out",
+        "code": 64,
+        "wtimeout": true,
+        "waited": 100,
+        "writtenTo": ["localhost:27017"],
+        "ok": 1.0,
+    };
+    match check_get_last_error(&reply, &acknowledged) {
+        Err(Error::WriteConcernTimeoutError(ref reply)) => {
+            assert_eq!(reply.get_i32("waited").unwrap(), 100)
+        }
+        other => panic!("Expected WriteConcernTimeoutError, got {:?}", other),
+    }
+
+    let reply = doc! { "ok": 0.0, "errmsg": "no such command", "code": 59 };
+    match check_get_last_error(&reply, &acknowledged) {
+        Err(Error::CommandError(ref err)) => assert_eq!(err.code, Some(59)),
+        other => panic!("Expected CommandError, got {:?}", other),
+    }
+}
+
+#[test]
+fn classify_command_errors() {
+    let failure = |code: i32, message: &str| {
+        Error::CommandError(CommandFailure::new(Some(code), message))
+    };
+
+    assert!(failure(10107, "").is_not_master());
+    assert!(failure(13435, "").is_not_master());
+    assert!(!failure(2, "not master").is_not_master());
+    assert!(failure(89, "").is_network_error());
+    assert!(failure(91, "").is_retryable_write_error());
+    assert!(failure(43, "").is_resumable_change_stream_error());
+    assert!(!failure(11000, "duplicate key").is_not_master());
+    assert!(!failure(11000, "duplicate key").is_retryable_write_error());
+}
+
+#[test]
+fn error_labels() {
+    let reply = doc! {
+        "ok": 0,
+        "errmsg": "WriteConflict",
+        "code": 112,
+        "codeName": "WriteConflict",
+        "errorLabels": [TRANSIENT_TRANSACTION_ERROR, TRANSIENT_TRANSACTION_ERROR],
+    };
+
+    let err = match check_command_ok(&reply) {
+        Err(err) => err,
+        Ok(()) => panic!("Expected a CommandError"),
+    };
+
+    // Labels sent by the server are kept once each, and write conflicts aren't retryable.
+    assert_eq!(vec![String::from(TRANSIENT_TRANSACTION_ERROR)], err.labels());
+    assert!(err.has_label(TRANSIENT_TRANSACTION_ERROR));
+    assert!(err.is_transient_transaction_error());
+    assert!(!err.has_label(RETRYABLE_WRITE_ERROR));
+    assert!(!err.is_retryable_write());
+    assert!(!err.is_retryable_read());
+
+    // Servers from 4.4 label retryable writes themselves, whatever the code.
+    let reply = doc! {
+        "ok": 0,
+        "errmsg": "retry me",
+        "code": 2,
+        "errorLabels": [RETRYABLE_WRITE_ERROR],
+    };
+    assert!(check_command_ok(&reply).unwrap_err().is_retryable_write());
+
+    // Network and not master errors are labelled by the driver.
+    let network = Error::CodedError(ErrorCode::HostUnreachable);
+    assert!(network.has_label(TRANSIENT_TRANSACTION_ERROR));
+    assert!(network.has_label(RETRYABLE_WRITE_ERROR));
+    assert!(network.is_retryable_read());
+
+    // Only commits are labelled with an unknown result, see `commit_error_labels`.
+    assert!(!network.has_label(UNKNOWN_TRANSACTION_COMMIT_RESULT));
+
+    let not_master = Error::CodedError(ErrorCode::NotMaster);
+    assert!(not_master.is_retryable_read());
+    assert!(not_master.is_retryable_write());
+    assert!(not_master.has_label(TRANSIENT_TRANSACTION_ERROR));
+
+    let duplicate = Error::CodedError(ErrorCode::DuplicateKey);
+    assert!(duplicate.labels().is_empty());
+    assert!(!duplicate.is_retryable_read());
+    assert!(!duplicate.is_retryable_write());
+}
+
+#[test]
+fn commit_error_labels() {
+    let timeout = doc! { "ok": 0, "errmsg": "operation exceeded time limit", "code": 50 };
+    let timeout = check_command_ok(&timeout).unwrap_err();
+    assert!(!timeout.has_label(UNKNOWN_TRANSACTION_COMMIT_RESULT));
+    assert!(timeout.commit_labels().iter().any(|l| l == UNKNOWN_TRANSACTION_COMMIT_RESULT));
+
+    let network = Error::CodedError(ErrorCode::HostUnreachable);
+    let labels = network.commit_labels();
+    assert_eq!(1, labels.iter().filter(|l| *l == UNKNOWN_TRANSACTION_COMMIT_RESULT).count());
+
+    let duplicate = Error::CodedError(ErrorCode::DuplicateKey);
+    assert!(duplicate.commit_labels().is_empty());
+}
+
+#[test]
+fn retryable_error_codes() {
+    let failure = |code: i32| {
+        Error::CommandError(CommandFailure::new(Some(code), ""))
+    };
+
+    for &code in &[11600, 11602, 10107, 13435, 13436, 189, 91, 7, 6, 89, 9001] {
+        assert!(failure(code).is_retryable_read(), "code {}", code);
+        assert!(failure(code).is_retryable_write(), "code {}", code);
+    }
+
+    assert!(failure(134).is_retryable_read());
+    assert!(!failure(134).is_retryable_write());
+    assert!(failure(262).is_retryable_write());
+    assert!(!failure(262).is_retryable_read());
+    assert!(!failure(11000).is_retryable_read());
+    assert!(!failure(11000).is_retryable_write());
+}
+
+#[test]
+fn classify_resumable_change_stream_errors() {
+    assert!(Error::CursorNotFoundError.is_resumable_change_stream_error());
+    assert!(Error::CodedError(ErrorCode::CursorNotFound).is_resumable_change_stream_error());
+    assert!(Error::CodedError(ErrorCode::NotMaster).is_resumable_change_stream_error());
+    assert!(Error::CodedError(ErrorCode::HostUnreachable).is_resumable_change_stream_error());
+    assert!(!Error::CodedError(ErrorCode::DuplicateKey).is_resumable_change_stream_error());
+    assert!(!Error::ChangeStreamInvalidatedError(doc! {}).is_resumable_change_stream_error());
+}
+
+#[test]
+fn retry_policy_backoff() {
+    let policy = RetryPolicy::new(4, 100);
+    assert_eq!(Duration::from_millis(100), policy.backoff(1));
+    assert_eq!(Duration::from_millis(200), policy.backoff(2));
+    assert_eq!(Duration::from_millis(400), policy.backoff(3));
+}
+
+#[test]
+fn server_selection_timeout() {
+    let mut options = ClientOptions::new();
+    options.server_selection_timeout_ms = 1000;
+
+    // Nothing listens on port 1, so no server can ever be selected.
+    let client = Client::connect_with_options("localhost", 1, options).unwrap();
+
+    let coll = client.db("test-client-error").collection("server_selection");
+
+    let start = Instant::now();
+    let result = coll.count(None, None);
+    assert!(start.elapsed() >= Duration::from_millis(1000));
+
+    match result {
+        Err(Error::ServerSelectionTimeoutError(ref msg)) => {
+            assert!(msg.contains("after 1000 ms"));
+            assert!(msg.contains("localhost:1: "));
+            assert!(msg.contains("s ago)") || msg.contains("(never checked)"));
+        }
+        other => panic!("Expected a server selection timeout error, got {:?}", other),
+    }
+}
+
+// Failure documents of legacy queries, as sent by MongoDB 3.0 and 3.2.
+#[test]
+fn query_failure_errors() {
+    let unauthorized = doc! { "$err": "not authorized for query on test.coll", "code": 13 };
+    match query_failure_error(&unauthorized) {
+        Error::UnauthorizedError(ref msg) => {
+            assert_eq!(msg, "not authorized for query on test.coll")
+        }
+        other => panic!("Expected UnauthorizedError, got {:?}", other),
+    }
+
+    let bad_regex = doc! {
+        "$err": "Can't canonicalize query: BadValue: Regular expression is invalid: missing )",
+        "code": 17287,
+    };
+    match query_failure_error(&bad_regex) {
+        Error::CommandError(ref err) => {
+            assert_eq!(err.code, Some(17287));
+            assert_eq!(err.code_name, None);
+            assert!(err.message.contains("Regular expression is invalid"));
+        }
+        other => panic!("Expected CommandError, got {:?}", other),
+    }
+
+    let large_sort = doc! {
+        "$err": "Executor error: OperationFailed: Sort operation used more than the maximum \
+                 33554432 bytes of RAM. Add an index, or specify a smaller limit.",
+        "code": 17144,
+    };
+    match query_failure_error(&large_sort) {
+        Error::CommandError(ref err) => {
+            assert_eq!(err.code, Some(17144));
+            assert!(err.message.starts_with("Executor error: OperationFailed: Sort operation"));
+        }
+        other => panic!("Expected CommandError, got {:?}", other),
+    }
+
+    let too_large = doc! {
+        "$err": "BSONObj size: 16793600 (0x1003000) is invalid. Size must be between 0 and \
+                 16793600(16MB)",
+        "code": 10334,
+    };
+    match query_failure_error(&too_large) {
+        Error::CommandError(ref err) => {
+            assert!(err.has_code(&[ErrorCode::BSONObjectTooLarge]));
+            assert!(format!("{}", err).ends_with("(code 10334)"));
+        }
+        other => panic!("Expected CommandError, got {:?}", other),
+    }
+
+    let old_cursor = doc! {
+        "$err": "getMore: cursor didn't exist on server, possible restart or timeout?",
+        "code": 13127,
+    };
+    match query_failure_error(&old_cursor) {
+        Error::CursorNotFoundError => (),
+        other => panic!("Expected CursorNotFoundError, got {:?}", other),
+    }
+
+    // Not master errors are still recognized by their code.
+    let not_master = doc! { "$err": "not master and slaveOk=false", "code": 13435 };
+    assert!(query_failure_error(&not_master).is_not_master());
+
+    let with_name = doc! { "$errmsg": "bad hint", "code": 2.0, "codeName": "BadValue" };
+    match query_failure_error(&with_name) {
+        Error::CommandError(ref err) => {
+            assert_eq!(format!("{}", err), "bad hint (BadValue, code 2)");
+        }
+        other => panic!("Expected CommandError, got {:?}", other),
+    }
+
+    match query_failure_error(&doc! {}) {
+        Error::CommandError(ref err) => {
+            assert_eq!(err.code, None);
+            assert_eq!(err.message, "Query failed without an error message.");
+        }
+        other => panic!("Expected CommandError, got {:?}", other),
+    }
+}
+
+#[test]
+fn bad_regex_error() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-error").collection("bad_regex");
+    coll.insert_one(doc! { "name": "a" }, None).unwrap();
+
+    // The server's own message is reported, whether the query was sent as a command or not.
+    match coll.find_one(Some(doc! { "name": { "$regex": "(" } }), None) {
+        Err(Error::CommandError(ref err)) => {
+            assert!(err.code.is_some());
+            assert!(err.message.to_lowercase().contains("regular expression"));
+        }
+        other => panic!("Expected CommandError, got {:?}", other),
+    }
+}
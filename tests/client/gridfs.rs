@@ -1,11 +1,12 @@
 use bson::Bson;
 
-use mongodb::{Client, ThreadedClient};
+use mongodb::{Client, Error, ThreadedClient};
 use mongodb::coll::Collection;
 use mongodb::coll::options::{FindOptions, IndexOptions};
 use mongodb::db::ThreadedDatabase;
 use mongodb::gridfs::{Store, ThreadedStore};
 use mongodb::gridfs::file::DEFAULT_CHUNK_SIZE;
+use mongodb::wire_protocol::binary;
 
 use rand::{thread_rng, Rng};
 use std::io::{Read, Write};
@@ -50,8 +51,7 @@ fn put_get() {
         _ => panic!("Expected to retrieve file from cursor."),
     }
 
-    let mut opts = FindOptions::new();
-    opts.sort = Some(doc!{ "n": 1});
+    let opts = FindOptions::builder().sort(doc!{ "n": 1}).build();
 
     // Check chunks
     let mut cursor = fschunks
@@ -80,8 +80,7 @@ fn put_get() {
     let results = cursor.next_n(10).unwrap();
     assert_eq!(2, results.len());
 
-    let mut opts = IndexOptions::new();
-    opts.unique = Some(true);
+    let opts = IndexOptions::builder().unique(true).build();
     fschunks
         .create_index(doc!{ "files_id": 1, "n": 1}, Some(opts))
         .unwrap();
@@ -209,3 +208,76 @@ fn find() {
     assert_eq!(id, results[0].id);
     assert_eq!(id2, results[1].id);
 }
+
+#[test]
+fn md5_verification() {
+    let (fs, fsfiles, fschunks) = init_gridfs("test-client-gridfs-grid_md5");
+
+    let name = "grid_md5_file";
+    let src_len = (DEFAULT_CHUNK_SIZE as f64 * 2.5) as usize;
+    let src = gen_rand_file(src_len);
+
+    let mut grid_file = fs.create(name.to_owned()).unwrap();
+    let id = grid_file.id.clone();
+    grid_file.write_all(&src).unwrap();
+    grid_file.close().unwrap();
+
+    // The stored checksum matches the chunks as written.
+    fs.set_verify_md5(true);
+    let mut dest = Vec::new();
+    let mut read_file = fs.open(name.to_owned()).unwrap();
+    assert_eq!(32, read_file.md5().unwrap().len());
+    read_file.read_to_end(&mut dest).unwrap();
+    read_file.close().unwrap();
+    assert!(src == dest);
+
+    // Change a byte of the last chunk.
+    let mut data = src[2 * DEFAULT_CHUNK_SIZE as usize..].to_vec();
+    data[0] = data[0].wrapping_add(1);
+    fschunks
+        .update_one(
+            doc!{ "files_id": id.clone(), "n": 2 },
+            doc!{ "$set": { "data": binary::generic(data) } },
+            None,
+        )
+        .unwrap();
+
+    let mut read_file = fs.open(name.to_owned()).unwrap();
+    let err = read_file.read_to_end(&mut Vec::new()).unwrap_err();
+    match err.get_ref().and_then(|inner| inner.downcast_ref::<Error>()) {
+        Some(&Error::ChecksumMismatchError(ref file_id)) => assert_eq!(&id, file_id),
+        _ => panic!("Expected ChecksumMismatchError, got {:?}", err),
+    }
+
+    // Without verification, the changed chunk is read as is.
+    fs.set_verify_md5(false);
+    let mut dest = Vec::new();
+    fs.open(name.to_owned()).unwrap().read_to_end(&mut dest).unwrap();
+    assert_eq!(src_len, dest.len());
+    assert!(src[..2 * DEFAULT_CHUNK_SIZE as usize] == dest[..2 * DEFAULT_CHUNK_SIZE as usize]);
+
+    // Files stored without a checksum are read unchecked.
+    fs.set_md5_disabled(true);
+    fs.set_verify_md5(true);
+    let mut grid_file = fs.create(String::from("grid_md5_disabled_file")).unwrap();
+    let id = grid_file.id.clone();
+    grid_file.write_all(&src).unwrap();
+    grid_file.close().unwrap();
+
+    let doc = fsfiles.find_one(Some(doc!{ "_id": id.clone() }), None).unwrap().unwrap();
+    assert!(!doc.contains_key("md5"));
+
+    let mut dest = Vec::new();
+    fs.open_id(id).unwrap().read_to_end(&mut dest).unwrap();
+    assert!(src == dest);
+}
+
+#[test]
+fn invalid_prefixes() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-gridfs");
+
+    assert!(Store::try_with_prefix(db.clone(), String::from("fs")).is_ok());
+    assert!(Store::try_with_prefix(db.clone(), String::from("my$fs")).is_err());
+    assert!(Store::try_with_prefix(db, String::from("my\0fs")).is_err());
+}
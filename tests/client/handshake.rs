@@ -44,3 +44,29 @@ fn metadata_sent_in_handshake() {
     assert_eq!(metadata.client.driver.name, DRIVER_NAME);
 }
 
+#[test]
+fn app_name_sent_in_handshake() {
+    let client = Client::with_app_name("mongodb://localhost:27017", "handshake-app-name-test")
+        .unwrap();
+    let db = client.db("admin");
+    skip_if_db_version_below!(db, 3, 4);
+
+    let cmd = doc! { "currentOp": 1, "appName": "handshake-app-name-test" };
+    let result = db.command(cmd, CommandType::Suppressed, None).unwrap();
+    let in_prog = match result.get("inprog") {
+        Some(Bson::Array(in_prog)) => in_prog,
+        _ => panic!("no `inprog` array found in response to `currentOp`"),
+    };
+
+    // The currentOp command itself is run over a connection that sent the application name.
+    assert!(!in_prog.is_empty());
+}
+
+#[test]
+fn app_name_too_long() {
+    let name: String = ::std::iter::repeat('a').take(129).collect();
+    assert!(Client::with_app_name("mongodb://localhost:27017", &name).is_err());
+
+    let uri = format!("mongodb://localhost:27017/?appName={}", name);
+    assert!(Client::with_uri(&uri).is_err());
+}
@@ -0,0 +1,171 @@
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bson::Bson;
+use mongodb::{Client, Error, ThreadedClient};
+use mongodb::common::{ReadMode, ReadPreference};
+use mongodb::coll::Collection;
+use mongodb::coll::options::FindOptions;
+use mongodb::db::ThreadedDatabase;
+
+use super::mock_server::{query_failure, reply, MockServer, Response, OP_QUERY};
+
+// A member of a two-member replica set that answers isMaster right away, and every query with
+// a document naming the member after a delay, or with a query failure.
+struct MockMember {
+    server: MockServer,
+    queries: Arc<AtomicUsize>,
+}
+
+impl MockMember {
+    fn start_set(delays_ms: &[u64], failing: &[bool]) -> Vec<MockMember> {
+        let listeners: Vec<_> = delays_ms
+            .iter()
+            .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let hosts: Vec<_> = listeners
+            .iter()
+            .map(|listener| format!("127.0.0.1:{}", listener.local_addr().unwrap().port()))
+            .collect();
+
+        listeners
+            .into_iter()
+            .enumerate()
+            .map(|(index, listener)| {
+                let host_list: Vec<_> = hosts.iter().map(|host| Bson::from(host.as_str()))
+                    .collect();
+                let is_master = doc! {
+                    "ismaster": index == 0,
+                    "secondary": index != 0,
+                    "setName": "rs",
+                    "hosts": host_list,
+                    "minWireVersion": 0,
+                    "maxWireVersion": 3,
+                    "ok": 1.0,
+                };
+                let delay = Duration::from_millis(delays_ms[index]);
+                let fail = failing[index];
+                let queries = Arc::new(AtomicUsize::new(0));
+                let counted = queries.clone();
+
+                let server = MockServer::listen(listener, move |request| {
+                    // Only queries are answered; killCursors has no reply.
+                    if request.op_code != OP_QUERY {
+                        return Response::Silent;
+                    }
+
+                    if request.is_is_master() {
+                        return Response::Reply(reply(request, is_master.clone()));
+                    }
+
+                    if request.command_name().is_some() {
+                        return Response::Reply(reply(request, doc! { "ok": 1.0 }));
+                    }
+
+                    counted.fetch_add(1, Ordering::SeqCst);
+                    if fail {
+                        Response::Delayed(delay, query_failure(request, "injected failure", 2))
+                    } else {
+                        let port = i32::from(request.port);
+                        Response::Delayed(delay, reply(request, doc! { "port": port }))
+                    }
+                });
+
+                MockMember {
+                    server: server,
+                    queries: queries,
+                }
+            })
+            .collect()
+    }
+}
+
+fn connect(members: &[MockMember]) -> Collection {
+    let hosts: Vec<_> = members
+        .iter()
+        .map(|member| format!("127.0.0.1:{}", member.server.port))
+        .collect();
+    let client = Client::with_uri(&format!("mongodb://{}/?replicaSet=rs", hosts.join(",")))
+        .unwrap();
+    client.refresh_topology().unwrap();
+    client.db("test").collection("hedged")
+}
+
+fn hedged() -> ReadPreference {
+    let mut read_preference = ReadPreference::new(ReadMode::Nearest, None);
+    read_preference.hedge = true;
+    read_preference
+}
+
+fn find_options(read_preference: ReadPreference) -> Option<FindOptions> {
+    Some(FindOptions::builder().read_preference(read_preference).build())
+}
+
+#[test]
+fn hedged_read_takes_first_reply() {
+    // The primary is slow to answer queries, though just as fast as the secondary to answer
+    // isMaster, so that both are within the latency window.
+    let members = MockMember::start_set(&[1000, 50], &[false, false]);
+    let coll = connect(&members);
+
+    let start = Instant::now();
+    let doc = coll.find_one(None, find_options(hedged())).unwrap().unwrap();
+    let elapsed = start.elapsed();
+
+    assert_eq!(doc.get_i32("port").unwrap(), i32::from(members[1].server.port));
+    assert!(elapsed < Duration::from_millis(500), "The hedged read took {:?}", elapsed);
+    assert_eq!(members[0].queries.load(Ordering::SeqCst), 1);
+    assert_eq!(members[1].queries.load(Ordering::SeqCst), 1);
+
+    // The losing query's connection is closed rather than returned to the pool, since its
+    // reply would still arrive.
+    thread::sleep(Duration::from_millis(1500));
+    assert_eq!(members[0].server.abandoned(), 1);
+    assert_eq!(members[1].server.abandoned(), 0);
+
+    // Reads that don't hedge only go to the member they select.
+    let nearest = ReadPreference::new(ReadMode::Nearest, None);
+    coll.find_one(None, find_options(nearest)).unwrap().unwrap();
+    let queries: usize = members.iter().map(|m| m.queries.load(Ordering::SeqCst)).sum();
+    assert_eq!(queries, 3);
+}
+
+#[test]
+fn hedged_read_skips_failed_member() {
+    let members = MockMember::start_set(&[300, 10], &[false, true]);
+    let coll = connect(&members);
+
+    let start = Instant::now();
+    let doc = coll.find_one(None, find_options(hedged())).unwrap().unwrap();
+    assert_eq!(doc.get_i32("port").unwrap(), i32::from(members[0].server.port));
+    assert!(start.elapsed() >= Duration::from_millis(300));
+
+    // The query fails once no member succeeds.
+    let members = MockMember::start_set(&[10, 10], &[true, true]);
+    let coll = connect(&members);
+    match coll.find_one(None, find_options(hedged())) {
+        Err(Error::CommandError(ref failure)) => assert_eq!(failure.message, "injected failure"),
+        other => panic!("Expected a CommandError, got {:?}", other),
+    }
+}
+
+#[test]
+fn hedge_requires_nearest() {
+    let members = MockMember::start_set(&[10, 10], &[false, false]);
+    let coll = connect(&members);
+
+    for &mode in &[ReadMode::Primary, ReadMode::Secondary, ReadMode::SecondaryPreferred] {
+        let mut read_preference = hedged();
+        read_preference.mode = mode;
+        match coll.find_one(None, find_options(read_preference)) {
+            Err(Error::ArgumentError(_)) => (),
+            other => panic!("Expected an ArgumentError, got {:?}", other),
+        }
+    }
+
+    let queries: usize = members.iter().map(|m| m.queries.load(Ordering::SeqCst)).sum();
+    assert_eq!(queries, 0);
+}
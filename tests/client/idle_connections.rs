@@ -0,0 +1,110 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use mongodb::{Client, ClientOptions, Error, ThreadedClient};
+
+use super::mock_server::{reply, standalone, MockServer, Response};
+
+// A standalone server that answers every request with an isMaster reply. Dropping its
+// connections makes them stop replying without being closed, the way a NAT gateway silently
+// drops idle connections.
+struct DroppingServer {
+    server: MockServer,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl DroppingServer {
+    fn start() -> DroppingServer {
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let dropping = dropped.clone();
+        let server = MockServer::start(move |request| {
+            if request.connection < dropping.load(Ordering::SeqCst) {
+                Response::Silent
+            } else {
+                Response::Reply(reply(request, standalone(5)))
+            }
+        });
+
+        DroppingServer {
+            server: server,
+            dropped: dropped,
+        }
+    }
+
+    // Stops replying over every connection accepted so far.
+    fn drop_connections(&self) {
+        self.dropped.store(self.server.accepted(), Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn idle_connection_options_from_uri() {
+    let client = Client::with_uri(
+        "mongodb://localhost:27017/?maxIdleTimeMS=100&socketKeepAliveMS=30000",
+    ).unwrap();
+    assert_eq!(client.max_idle_time, Some(Duration::from_millis(100)));
+    assert_eq!(client.keep_alive, Some(Duration::from_millis(30000)));
+
+    // The client options take precedence, and 0 leaves an option unset.
+    let mut options = ClientOptions::new();
+    options.max_idle_time = Some(Duration::from_millis(500));
+    let client = Client::with_uri_and_options(
+        "mongodb://localhost:27017/?maxIdleTimeMS=100&socketKeepAliveMS=0",
+        options,
+    ).unwrap();
+    assert_eq!(client.max_idle_time, Some(Duration::from_millis(500)));
+    assert_eq!(client.keep_alive, None);
+
+    match Client::with_uri("mongodb://localhost:27017/?maxIdleTimeMS=soon") {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected ArgumentError, got {:?}", other),
+    }
+}
+
+#[test]
+fn idle_connections_are_ping_checked() {
+    let server = DroppingServer::start();
+
+    let mut options = ClientOptions::new();
+    options.max_idle_time = Some(Duration::from_millis(100));
+    let client = Client::connect_with_options("127.0.0.1", server.server.port, options).unwrap();
+    client.is_master().expect("Failed to run isMaster.");
+    let accepted = server.server.accepted();
+
+    // A connection that answers its ping is reused.
+    thread::sleep(Duration::from_millis(200));
+    client.is_master().expect("Failed to run isMaster.");
+    assert_eq!(server.server.accepted(), accepted);
+}
+
+#[test]
+fn dropped_idle_connections_are_replaced() {
+    let server = DroppingServer::start();
+
+    let mut options = ClientOptions::new();
+    options.max_idle_time = Some(Duration::from_millis(100));
+    options.liveness_check_timeout = Some(Duration::from_millis(200));
+    options.keep_alive = Some(Duration::from_secs(30));
+    let client = Client::connect_with_options("127.0.0.1", server.server.port, options).unwrap();
+    client.is_master().expect("Failed to run isMaster.");
+
+    // The pooled connection is now dead, but still open as far as the client can tell.
+    server.drop_connections();
+    thread::sleep(Duration::from_millis(200));
+
+    // Reusing the connection unchecked would wait for a reply forever. Instead, the dead
+    // connection fails its ping and the operation goes on over a new one.
+    let accepted = server.server.accepted();
+    let checked = client.clone();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || { let _ = sender.send(checked.is_master()); });
+
+    match receiver.recv_timeout(Duration::from_secs(5)) {
+        Ok(result) => assert!(result.expect("Failed to run isMaster over a new connection.")),
+        Err(_) => panic!("The client reused a connection that was idle for too long."),
+    }
+    assert!(server.server.accepted() > accepted);
+}
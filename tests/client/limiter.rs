@@ -0,0 +1,76 @@
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use mongodb::{Client, ClientOptions, Error, ThreadedClient};
+use mongodb::db::ThreadedDatabase;
+
+use super::mock_server::LoadServer;
+
+#[test]
+fn concurrent_operations_are_limited() {
+    let server = LoadServer::start();
+    let mut options = ClientOptions::new();
+    options.pool_size = Some(20);
+    options.max_concurrent_operations = Some(4);
+    options.wait_queue_timeout = Some(Duration::from_millis(250));
+
+    let client = Client::connect_with_options("127.0.0.1", server.server.port, options).unwrap();
+    let coll = client.db("test").collection("limited");
+
+    let threads: Vec<_> = (0..100)
+        .map(|_| {
+            let coll = coll.clone();
+            thread::spawn(move || coll.find_one(Some(doc! { "slow": true }), None))
+        })
+        .collect();
+
+    // Sample the gauges while the queries run.
+    thread::sleep(Duration::from_millis(50));
+    let metrics = client.metrics();
+    assert!(metrics.in_flight_operations <= 4);
+    assert!(metrics.queued_operations > 0);
+
+    let mut succeeded = 0;
+    for thread in threads {
+        match thread.join().unwrap() {
+            Ok(_) => succeeded += 1,
+            Err(Error::OverloadedError(4)) => (),
+            Err(err) => panic!("Expected an OverloadedError, got {:?}", err),
+        }
+    }
+
+    // Each permit serves a query every 100 ms, so only the first few queries fit within the
+    // wait queue timeout.
+    assert_eq!(server.load.peak.load(Ordering::SeqCst), 4);
+    assert!(succeeded >= 4 && succeeded < 100, "{} queries succeeded", succeeded);
+
+    let metrics = client.metrics();
+    assert_eq!(metrics.in_flight_operations, 0);
+    assert_eq!(metrics.queued_operations, 0);
+}
+
+#[test]
+fn overloaded_operations_fail_fast() {
+    let server = LoadServer::start();
+    let mut options = ClientOptions::new();
+    options.max_concurrent_operations = Some(1);
+    options.wait_queue_timeout = Some(Duration::from_millis(0));
+
+    let client = Client::connect_with_options("127.0.0.1", server.server.port, options).unwrap();
+    let coll = client.db("test").collection("fail_fast");
+
+    let slow = {
+        let coll = coll.clone();
+        thread::spawn(move || coll.find_one(Some(doc! { "slow": true }), None))
+    };
+    thread::sleep(Duration::from_millis(50));
+
+    match coll.find_one(None, None) {
+        Err(Error::OverloadedError(1)) => (),
+        other => panic!("Expected an OverloadedError, got {:?}", other),
+    }
+
+    slow.join().unwrap().unwrap();
+    coll.find_one(None, None).unwrap();
+}
@@ -0,0 +1,78 @@
+use std::sync::{Arc, Mutex};
+
+use mongodb::{Client, ClientOptions, ThreadedClient};
+use mongodb::db::ThreadedDatabase;
+use mongodb::logging::{redact, LogLevel, Logger, StderrLogger};
+
+#[derive(Debug, Default)]
+struct RecordingLogger {
+    records: Mutex<Vec<(LogLevel, String, String)>>,
+}
+
+impl RecordingLogger {
+    fn contains(&self, level: LogLevel, target: &str, pattern: &str) -> bool {
+        self.records.lock().unwrap().iter().any(|&(ref l, ref t, ref message)| {
+            *l == level && t == target && message.contains(pattern)
+        })
+    }
+}
+
+impl Logger for RecordingLogger {
+    fn enabled(&self, _level: LogLevel) -> bool {
+        true
+    }
+
+    fn log(&self, level: LogLevel, target: &str, message: &str) {
+        self.records.lock().unwrap().push((level, target.to_owned(), message.to_owned()));
+    }
+}
+
+#[test]
+fn redact_auth_commands() {
+    let sasl = doc! { "saslStart": 1, "mechanism": "SCRAM-SHA-1", "payload": "secret" };
+    let redacted = redact(&sasl);
+    assert!(redacted.contains("saslStart"));
+    assert!(!redacted.contains("secret"));
+
+    let wrapped = doc! { "$query": { "createUser": "bob", "pwd": "secret" } };
+    assert!(!redact(&wrapped).contains("secret"));
+
+    let find = doc! { "find": "coll", "filter": { "x": 1 } };
+    assert!(redact(&find).contains("filter"));
+}
+
+#[test]
+fn stderr_logger_levels() {
+    let logger = StderrLogger::new(LogLevel::Info);
+    assert!(!logger.enabled(LogLevel::Debug));
+    assert!(logger.enabled(LogLevel::Info));
+    assert!(logger.enabled(LogLevel::Error));
+}
+
+#[test]
+fn find_one_events() {
+    let logger = Arc::new(RecordingLogger::default());
+    let mut options = ClientOptions::new();
+    options.logger = Some(logger.clone() as Arc<Logger>);
+
+    let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+    let coll = client.db("test-client-logging").collection("find_one_events");
+    coll.drop().unwrap();
+    coll.insert_one(doc! { "_id": 1 }, None).unwrap();
+
+    logger.records.lock().unwrap().clear();
+    coll.find_one(Some(doc! { "_id": 1 }), None).unwrap();
+
+    assert!(logger.contains(LogLevel::Trace, "wire", "Sent"));
+    assert!(logger.contains(LogLevel::Trace, "wire", "find_one_events"));
+    assert!(logger.contains(LogLevel::Trace, "wire", "Received"));
+
+    // A fresh client opens its connections while it is being used.
+    let logger = Arc::new(RecordingLogger::default());
+    let client = Client::connect("localhost", 27017).unwrap();
+    client.set_logger(logger.clone()).unwrap();
+    client.db("test-client-logging").collection("find_one_events").find_one(None, None).unwrap();
+
+    assert!(logger.contains(LogLevel::Debug, "connection", "Opened connection"));
+    assert!(logger.contains(LogLevel::Trace, "wire", "query"));
+}
@@ -0,0 +1,213 @@
+use bson::{self, Bson};
+use mongodb::wire_protocol::operations::{Message, ReplyDocuments, MAX_REPLY_LENGTH};
+use mongodb::wire_protocol::validation::{validate_document, DEFAULT_MAX_BSON_DEPTH};
+
+use std::io;
+
+fn i32_bytes(n: i32) -> Vec<u8> {
+    n.to_le_bytes().to_vec()
+}
+
+// Wraps raw elements in a document, with a correct length prefix and terminator.
+fn raw_doc(elements: &[u8]) -> Vec<u8> {
+    let mut doc = i32_bytes(4 + elements.len() as i32 + 1);
+    doc.extend_from_slice(elements);
+    doc.push(0);
+    doc
+}
+
+// A document holding a single element with key "a".
+fn raw_element(element_type: u8, value: &[u8]) -> Vec<u8> {
+    let mut elements = vec![element_type, b'a', 0];
+    elements.extend_from_slice(value);
+    raw_doc(&elements)
+}
+
+// Documents nested `depth` levels deep, counting the outermost one.
+fn nested(depth: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for inner in (1..depth).rev() {
+        // Each level adds a length, an element type, a key and a terminator.
+        bytes.extend(i32_bytes(5 + 8 * inner as i32));
+        bytes.extend_from_slice(&[0x03, b'a', 0]);
+    }
+
+    bytes.extend(raw_doc(&[]));
+    bytes.extend(vec![0; depth - 1]);
+    bytes
+}
+
+fn reply_bytes(message_length: i32, documents: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for n in &[message_length, 1, 0, 1, 0] {
+        bytes.extend(i32_bytes(*n));
+    }
+    bytes.extend(vec![0; 8]);
+    bytes.extend(i32_bytes(0));
+    bytes.extend(i32_bytes(1));
+    bytes.extend_from_slice(documents);
+    bytes
+}
+
+fn reply(documents: &[u8]) -> Vec<u8> {
+    reply_bytes(36 + documents.len() as i32, documents)
+}
+
+fn read(bytes: Vec<u8>) -> mongodb::Result<Message> {
+    Message::read(&mut io::Cursor::new(bytes))
+}
+
+fn valid_document() -> Vec<u8> {
+    let doc = doc! {
+        "_id": 1,
+        "name": "value",
+        "nested": { "array": [1, "two", { "three": 3.0 }] },
+        "binary": Bson::Binary(bson::spec::BinarySubtype::Generic, vec![1, 2, 3]),
+        "regex": Bson::RegExp(String::from("^a"), String::from("i")),
+        "code": Bson::JavaScriptCodeWithScope(String::from("x"), doc! { "x": 1 }),
+    };
+
+    let mut bytes = Vec::new();
+    bson::encode_document(&mut bytes, &doc).unwrap();
+    bytes
+}
+
+// Crafted documents that must all be rejected.
+fn malformed_documents() -> Vec<(&'static str, Vec<u8>)> {
+    let mut overlong_string = i32_bytes(100);
+    overlong_string.extend_from_slice(b"ab\0");
+
+    let mut invalid_utf8 = i32_bytes(3);
+    invalid_utf8.extend_from_slice(&[0xC3, 0x28, 0]);
+
+    let mut unterminated_string = i32_bytes(2);
+    unterminated_string.extend_from_slice(b"ab");
+
+    let mut overlong_binary = i32_bytes(1000);
+    overlong_binary.extend_from_slice(&[0, 1, 2, 3]);
+
+    let mut negative_binary = i32_bytes(-1);
+    negative_binary.push(0);
+
+    let mut short_code_with_scope = i32_bytes(0);
+    short_code_with_scope.extend(raw_doc(&[]));
+
+    let mut overlong_document = raw_doc(&[]);
+    overlong_document[0] = 100;
+
+    let mut negative_document = raw_doc(&[]);
+    negative_document[..4].copy_from_slice(&i32_bytes(-5));
+
+    let mut unterminated_document = raw_element(0x10, &[1, 0, 0, 0]);
+    let last = unterminated_document.len() - 1;
+    unterminated_document[last] = 1;
+
+    vec![
+        ("truncated length", vec![5, 0]),
+        ("length larger than buffer", overlong_document),
+        ("negative length", negative_document),
+        ("unterminated document", unterminated_document),
+        ("truncated int32", raw_element(0x10, &[1, 0])),
+        ("truncated double", raw_element(0x01, &[0; 7])),
+        ("truncated ObjectId", raw_element(0x07, &[0; 11])),
+        ("out of range datetime", raw_element(0x09, &i64::max_value().to_le_bytes())),
+        ("pre-epoch datetime with milliseconds", raw_element(0x09, &(-1i64).to_le_bytes())),
+        ("string longer than document", raw_element(0x02, &overlong_string)),
+        ("negative string length", raw_element(0x02, &i32_bytes(-1))),
+        ("zero string length", raw_element(0x02, &i32_bytes(0))),
+        ("unterminated string", raw_element(0x02, &unterminated_string)),
+        ("invalid UTF-8 string", raw_element(0x02, &invalid_utf8)),
+        ("invalid UTF-8 key", raw_doc(&[0x0A, 0xFF, 0xFE, 0])),
+        ("unterminated key", raw_doc(&[0x0A, b'a', b'b'])),
+        ("unterminated regex", raw_element(0x0B, b"^a")),
+        ("binary longer than document", raw_element(0x05, &overlong_binary)),
+        ("negative binary length", raw_element(0x05, &negative_binary)),
+        ("short code with scope", raw_element(0x0F, &short_code_with_scope)),
+        ("embedded length larger than document", raw_element(0x03, &[100, 0, 0, 0, 0])),
+        ("unknown element type", raw_element(0x42, &[])),
+        ("nested too deeply", nested(DEFAULT_MAX_BSON_DEPTH + 1)),
+        ("nested deeper than the stack", nested(100_000)),
+    ]
+}
+
+#[test]
+fn malformed_documents_are_rejected() {
+    for (name, doc) in malformed_documents() {
+        if validate_document(&doc, DEFAULT_MAX_BSON_DEPTH).is_ok() {
+            panic!("Expected the '{}' document to be rejected.", name);
+        }
+
+        // Documents that are not rejected by their length prefix alone must still be rejected
+        // before they can be decoded.
+        if ReplyDocuments::new(doc.clone()).is_ok() {
+            panic!("Expected a reply with the '{}' document to be rejected.", name);
+        }
+
+        if read(reply(&doc)).is_ok() {
+            panic!("Expected a reply message with the '{}' document to be rejected.", name);
+        }
+    }
+}
+
+#[test]
+fn valid_documents_are_accepted() {
+    let doc = valid_document();
+    validate_document(&doc, DEFAULT_MAX_BSON_DEPTH).unwrap();
+
+    let mut documents = ReplyDocuments::new(doc.clone()).unwrap();
+    assert!(documents.next().unwrap().is_ok());
+
+    validate_document(&nested(DEFAULT_MAX_BSON_DEPTH), DEFAULT_MAX_BSON_DEPTH).unwrap();
+
+    // The maximum depth is configurable.
+    assert!(ReplyDocuments::with_max_depth(nested(5), 5).is_ok());
+    assert!(ReplyDocuments::with_max_depth(nested(6), 5).is_err());
+
+    match read(reply(&doc)).unwrap() {
+        Message::OpReply { documents, .. } => assert_eq!(documents.len(), 1),
+        other => panic!("Expected OpReply, got {:?}", other),
+    }
+}
+
+#[test]
+fn malformed_headers_are_rejected() {
+    let doc = valid_document();
+    let valid = reply(&doc);
+
+    // A truncated header.
+    assert!(read(valid[..8].to_vec()).is_err());
+
+    // A message length larger than the bytes that follow.
+    assert!(read(reply_bytes(valid.len() as i32 + 100, &doc)).is_err());
+
+    // Message lengths too short to hold the reply fields, or negative.
+    assert!(read(reply_bytes(20, &doc)).is_err());
+    assert!(read(reply_bytes(-1, &doc)).is_err());
+    assert!(read(reply_bytes(i32::min_value(), &doc)).is_err());
+
+    // A message length beyond the largest reply is rejected without allocating for it.
+    assert!(read(reply_bytes(MAX_REPLY_LENGTH + 1, &doc)).is_err());
+    assert!(read(reply_bytes(i32::max_value(), &doc)).is_err());
+}
+
+#[test]
+fn truncated_and_corrupted_replies_never_panic() {
+    let valid = reply(&valid_document());
+
+    for len in 0..valid.len() {
+        assert!(read(valid[..len].to_vec()).is_err());
+    }
+
+    // Replacing any single byte may or may not leave a valid reply, but must never panic.
+    for i in 0..valid.len() {
+        for &byte in &[0x00, 0x01, 0x7F, 0x80, 0xFF] {
+            let mut corrupted = valid.clone();
+            corrupted[i] = byte;
+            if let Ok(Message::OpReply { documents, .. }) = read(corrupted) {
+                for doc in documents {
+                    let _ = doc;
+                }
+            }
+        }
+    }
+}
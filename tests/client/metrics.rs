@@ -0,0 +1,153 @@
+use std::time::Duration;
+
+use bson::Bson;
+use mongodb::{Client, ClientOptions, CommandType, Error, ThreadedClient};
+use mongodb::coll::Collection;
+use mongodb::coll::options::IndexModel;
+use mongodb::db::ThreadedDatabase;
+use mongodb::metrics::{ErrorKind, OperationType};
+
+use super::mock_server::LoadServer;
+
+fn connect(server: &LoadServer) -> (Client, Collection) {
+    let client = Client::connect("127.0.0.1", server.server.port).unwrap();
+    let coll = client.db("test").collection("metrics");
+    (client, coll)
+}
+
+#[test]
+fn metrics_count_scripted_operations() {
+    let server = LoadServer::start();
+    let (client, coll) = connect(&server);
+
+    // Open a pooled connection, so that its handshake isn't counted.
+    coll.find_one(None, None).unwrap();
+    client.reset_metrics();
+    let received_before = server.server.received();
+    let sent_before = server.server.sent();
+
+    coll.find_one(None, None).unwrap().unwrap();
+    coll.insert_one(doc! { "x": 1 }, None).unwrap();
+    coll.update_one(doc! { "x": 1 }, doc! { "$set": { "x": 2 } }, None).unwrap();
+    coll.delete_one(doc! { "x": 2 }, None).unwrap();
+    client.db("test").run_command_checked(doc! { "ping": 1 }, CommandType::Ping, None).unwrap();
+
+    // A cursor over two batches, fetched by a query and a getMore.
+    let docs: Vec<_> = coll.find(Some(doc! { "more": true }), None)
+        .unwrap()
+        .map(|doc| doc.unwrap())
+        .collect();
+    assert_eq!(docs.len(), 2);
+
+    match coll.find_one(Some(doc! { "fail": true }), None) {
+        Err(Error::CommandError(ref failure)) => assert_eq!(failure.message, "injected failure"),
+        other => panic!("Expected the query to fail, got {:?}", other),
+    }
+
+    let metrics = client.metrics();
+    let operations = |operation| metrics.operations[&operation];
+    assert_eq!(operations(OperationType::Query), 3);
+    assert_eq!(operations(OperationType::GetMore), 1);
+    assert_eq!(operations(OperationType::Insert), 1);
+    assert_eq!(operations(OperationType::Update), 1);
+    assert_eq!(operations(OperationType::Delete), 1);
+    assert_eq!(operations(OperationType::Command), 1);
+
+    // The client counts exactly the bytes the server saw.
+    let received = server.server.received() - received_before;
+    let sent = server.server.sent() - sent_before;
+    assert_eq!(metrics.bytes_sent, received as u64);
+    assert_eq!(metrics.bytes_received, sent as u64);
+
+    assert_eq!(metrics.errors[&ErrorKind::Server], 1);
+    assert_eq!(metrics.errors.values().sum::<u64>(), 1);
+
+    // Every request was answered, so each round trip was timed.
+    assert_eq!(metrics.latency.count, 8);
+    assert_eq!(metrics.latency.buckets.iter().map(|&(_, count)| count).sum::<u64>(), 8);
+    assert!(metrics.latency.p50().is_some());
+
+    let text = metrics.to_prometheus("mongodb");
+    assert!(text.contains("mongodb_operations_total{type=\"getmore\"} 1\n"));
+    assert!(text.contains("mongodb_errors_total{kind=\"server\"} 1\n"));
+    assert!(text.contains("mongodb_latency_seconds_bucket{le=\"+Inf\"} 8\n"));
+    assert!(text.contains("mongodb_latency_seconds_count 8\n"));
+
+    client.reset_metrics();
+    let metrics = client.metrics();
+    assert!(metrics.operations.values().all(|&count| count == 0));
+    assert_eq!(metrics.bytes_sent, 0);
+    assert_eq!(metrics.latency.count, 0);
+}
+
+#[test]
+fn slow_operations_are_captured() {
+    let server = LoadServer::start();
+    let (client, coll) = connect(&server);
+
+    // Nothing is recorded until capture is enabled.
+    coll.find_one(Some(doc! { "slow": true }), None).unwrap();
+    assert!(client.drain_slow_ops().is_empty());
+
+    client.enable_slow_op_capture(50, 2);
+    coll.find_one(Some(doc! { "slow": 1 }), None).unwrap();
+    coll.find_one(None, None).unwrap();
+
+    let ops = client.drain_slow_ops();
+    assert_eq!(ops.len(), 1);
+    assert_eq!(ops[0].namespace, "test.metrics");
+    assert_eq!(ops[0].server.port, server.server.port);
+    assert!(ops[0].duration >= Duration::from_millis(50));
+    assert!(ops[0].reply_size > 0);
+    match ops[0].command.get("$query") {
+        Some(&Bson::Document(ref query)) => assert!(query.contains_key("slow")),
+        _ => assert!(ops[0].command.contains_key("slow")),
+    }
+    assert!(client.drain_slow_ops().is_empty());
+
+    // The oldest records make room for new ones.
+    for i in 0..3 {
+        coll.find_one(Some(doc! { "slow": i }), None).unwrap();
+    }
+    assert_eq!(client.drain_slow_ops().len(), 2);
+
+    client.disable_slow_op_capture();
+    coll.find_one(Some(doc! { "slow": true }), None).unwrap();
+    assert!(client.drain_slow_ops().is_empty());
+}
+
+#[test]
+fn forbidden_index_creation_sends_nothing() {
+    let server = LoadServer::start();
+    let mut options = ClientOptions::new();
+    options.forbid_index_creation = true;
+    let client = Client::connect_with_options("127.0.0.1", server.server.port, options).unwrap();
+    let coll = client.db("test").collection("indexes");
+
+    // Open a pooled connection, so that its handshake isn't counted.
+    coll.find_one(None, None).unwrap();
+    let received = server.server.received();
+
+    match coll.create_index(doc! { "a": 1 }, None) {
+        Err(Error::PolicyViolationError(ref msg)) => assert!(msg.contains("test.indexes")),
+        other => panic!("Expected a PolicyViolationError, got {:?}", other),
+    }
+    let models = vec![IndexModel::builder().asc("a").build()];
+    assert!(coll.create_indexes(models.clone()).is_err());
+
+    // A dry run shows the command without sending it, even while creation is forbidden.
+    let command = coll.create_indexes_dry_run(models).unwrap();
+    assert_eq!(command.get_str("createIndexes").unwrap(), "indexes");
+    match command.get("indexes") {
+        Some(&Bson::Array(ref indexes)) => assert_eq!(indexes.len(), 1),
+        other => panic!("Expected an array of indexes, got {:?}", other),
+    }
+    assert_eq!(server.server.received(), received);
+
+    // The flag is shared with handles created from the client, and can be lifted.
+    let handle = client.with_options(None, None, None);
+    handle.forbid_index_creation(false);
+    assert!(!client.is_index_creation_forbidden());
+    assert_eq!(coll.create_index(doc! { "a": 1 }, None).unwrap(), "a_1");
+    assert!(server.server.received() > received);
+}
@@ -0,0 +1,317 @@
+//! A scripted server for tests that need replies, failures and delays a real server can't be
+//! made to produce on demand. It reads the legacy wire protocol messages the client sends and
+//! answers each one through a handler, counting the bytes it exchanges.
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use bson::{self, Bson, Document};
+
+pub const OP_REPLY: i32 = 1;
+pub const OP_QUERY: i32 = 2004;
+pub const OP_GET_MORE: i32 = 2005;
+pub const OP_KILL_CURSORS: i32 = 2007;
+pub const QUERY_FAILURE: i32 = 2;
+
+/// A message read from a connection.
+pub struct Request {
+    pub request_id: i32,
+    pub op_code: i32,
+    /// The index of the connection the message arrived on, in the order of their acceptance.
+    pub connection: usize,
+    /// The port the server listens on.
+    pub port: u16,
+    /// The namespace the message acts on, which is empty for killCursors.
+    pub namespace: String,
+    /// The document of a query, without its `$query` wrapper, which is empty for other
+    /// messages.
+    pub query: Document,
+}
+
+impl Request {
+    fn parse(header: &[u8], body: &[u8], connection: usize, port: u16) -> Request {
+        let op_code = i32_at(header, 12);
+
+        // Every message but killCursors starts with its flags or a reserved zero, followed by
+        // the namespace.
+        let (namespace, namespace_end) = if op_code == OP_KILL_CURSORS {
+            (String::new(), 0)
+        } else {
+            let end = 4 + body[4..].iter().position(|&b| b == 0).unwrap();
+            (String::from_utf8_lossy(&body[4..end]).into_owned(), end)
+        };
+
+        // A query skips over the number of documents to skip and to return.
+        let mut query = if op_code == OP_QUERY {
+            bson::decode_document(&mut &body[namespace_end + 9..]).unwrap()
+        } else {
+            Document::new()
+        };
+        if let Some(Bson::Document(inner)) = query.remove("$query") {
+            query = inner;
+        }
+
+        Request {
+            request_id: i32_at(header, 4),
+            op_code: op_code,
+            connection: connection,
+            port: port,
+            namespace: namespace,
+            query: query,
+        }
+    }
+
+    /// Returns the name of the command if the message is one, e.g. `"insert"`.
+    pub fn command_name(&self) -> Option<&str> {
+        if self.op_code != OP_QUERY || !self.namespace.ends_with(".$cmd") {
+            return None;
+        }
+
+        self.query.keys().next().map(String::as_str)
+    }
+
+    /// Returns true if the message is an isMaster handshake or heartbeat.
+    pub fn is_is_master(&self) -> bool {
+        self.command_name().map_or(false, |name| name.to_lowercase() == "ismaster")
+    }
+}
+
+/// What the server does with a message.
+pub enum Response {
+    /// Sends the reply right away.
+    Reply(Vec<u8>),
+    /// Sends the reply once the delay has passed.
+    Delayed(Duration, Vec<u8>),
+    /// Sends nothing, as for messages the server never replies to.
+    Silent,
+}
+
+/// A server listening on a local port, whose connections are each served on their own thread.
+pub struct MockServer {
+    pub port: u16,
+    counters: Arc<Counters>,
+}
+
+#[derive(Default)]
+struct Counters {
+    accepted: AtomicUsize,
+    received: AtomicUsize,
+    sent: AtomicUsize,
+    abandoned: AtomicUsize,
+}
+
+impl MockServer {
+    /// Starts a server on a free port.
+    pub fn start<F>(handler: F) -> MockServer
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        MockServer::listen(TcpListener::bind("127.0.0.1:0").unwrap(), handler)
+    }
+
+    /// Starts a server on a listener bound beforehand, e.g. to know the ports of every member
+    /// of a replica set before starting any of them.
+    pub fn listen<F>(listener: TcpListener, handler: F) -> MockServer
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        let server = MockServer {
+            port: listener.local_addr().unwrap().port(),
+            counters: Arc::new(Counters::default()),
+        };
+
+        let port = server.port;
+        let counters = server.counters.clone();
+        let handler = Arc::new(handler);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let connection = counters.accepted.fetch_add(1, Ordering::SeqCst);
+                let counters = counters.clone();
+                let handler = handler.clone();
+                thread::spawn(move || {
+                    serve(stream.unwrap(), connection, port, &*handler, &counters)
+                });
+            }
+        });
+
+        server
+    }
+
+    /// Returns the number of connections accepted so far.
+    pub fn accepted(&self) -> usize {
+        self.counters.accepted.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of bytes of the messages received so far.
+    pub fn received(&self) -> usize {
+        self.counters.received.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of bytes of the replies sent so far.
+    pub fn sent(&self) -> usize {
+        self.counters.sent.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of connections the client closed while a delayed reply was being
+    /// sent over them, or right after receiving it.
+    pub fn abandoned(&self) -> usize {
+        self.counters.abandoned.load(Ordering::SeqCst)
+    }
+}
+
+fn serve<F>(mut stream: TcpStream, connection: usize, port: u16, handler: &F, counters: &Counters)
+where
+    F: Fn(&Request) -> Response,
+{
+    let mut delayed = false;
+
+    loop {
+        let mut header = [0u8; 16];
+        if stream.read_exact(&mut header).is_err() {
+            if delayed {
+                counters.abandoned.fetch_add(1, Ordering::SeqCst);
+            }
+            return;
+        }
+        delayed = false;
+
+        let length = i32_at(&header, 0) as usize;
+        let mut body = vec![0u8; length - header.len()];
+        if stream.read_exact(&mut body).is_err() {
+            return;
+        }
+        counters.received.fetch_add(length, Ordering::SeqCst);
+
+        let request = Request::parse(&header, &body, connection, port);
+        let response = match handler(&request) {
+            Response::Reply(response) => response,
+            Response::Delayed(delay, response) => {
+                delayed = true;
+                thread::sleep(delay);
+                response
+            }
+            Response::Silent => continue,
+        };
+
+        counters.sent.fetch_add(response.len(), Ordering::SeqCst);
+        if stream.write_all(&response).is_err() {
+            if delayed {
+                counters.abandoned.fetch_add(1, Ordering::SeqCst);
+            }
+            return;
+        }
+    }
+}
+
+fn i32_at(bytes: &[u8], offset: usize) -> i32 {
+    (0..4).fold(0, |n, i| n | (i32::from(bytes[offset + i]) << (8 * i)))
+}
+
+/// Returns an OP_REPLY holding a single document, with the given flags and the id of the
+/// cursor left open.
+pub fn op_reply(response_to: i32, flags: i32, cursor_id: i64, doc: &Document) -> Vec<u8> {
+    let mut document = Vec::new();
+    bson::encode_document(&mut document, doc).unwrap();
+
+    // The header, followed by the flags, cursor id, starting position and number of documents.
+    let mut reply = Vec::new();
+    let length = 36 + document.len() as i32;
+    for n in &[length, 0, response_to, OP_REPLY, flags] {
+        reply.extend_from_slice(&n.to_le_bytes());
+    }
+    reply.extend_from_slice(&cursor_id.to_le_bytes());
+    reply.extend_from_slice(&0i32.to_le_bytes());
+    reply.extend_from_slice(&1i32.to_le_bytes());
+    reply.extend(document);
+    reply
+}
+
+/// Returns a reply to the message holding a single document and no open cursor.
+pub fn reply(request: &Request, doc: Document) -> Vec<u8> {
+    op_reply(request.request_id, 0, 0, &doc)
+}
+
+/// Returns a reply failing the query with the given message and error code.
+pub fn query_failure(request: &Request, message: &str, code: i32) -> Vec<u8> {
+    let failure = doc! { "$err": message, "code": code };
+    op_reply(request.request_id, QUERY_FAILURE, 0, &failure)
+}
+
+/// Returns the isMaster reply of a standalone server.
+pub fn standalone(max_wire_version: i32) -> Document {
+    doc! {
+        "ismaster": true,
+        "minWireVersion": 0,
+        "maxWireVersion": max_wire_version,
+        "ok": 1.0,
+    }
+}
+
+// The id of the cursor `LoadServer` opens for queries with a `more` field.
+const CURSOR_ID: i64 = 42;
+
+/// A standalone server that acknowledges every command, fails queries with a `fail` field, opens
+/// a cursor for queries with a `more` field, answers queries with a `slow` field after 100 ms and
+/// answers every other query with one document. It records how many slow queries it answers at
+/// once.
+pub struct LoadServer {
+    pub server: MockServer,
+    pub load: Arc<Load>,
+}
+
+/// The number of queries being answered, and the most answered at once.
+#[derive(Default)]
+pub struct Load {
+    pub active: AtomicUsize,
+    pub peak: AtomicUsize,
+}
+
+impl LoadServer {
+    pub fn start() -> LoadServer {
+        let load = Arc::new(Load::default());
+        let measured = load.clone();
+        let server = MockServer::start(move |request| match request.op_code {
+            OP_QUERY => LoadServer::answer_query(request, &measured),
+            OP_GET_MORE => Response::Reply(reply(request, doc! { "batch": 2 })),
+            _ => Response::Silent,
+        });
+
+        LoadServer {
+            server: server,
+            load: load,
+        }
+    }
+
+    fn answer_query(request: &Request, load: &Load) -> Response {
+        if request.is_is_master() {
+            return Response::Reply(reply(request, standalone(3)));
+        }
+
+        if request.command_name().is_some() {
+            return Response::Reply(reply(request, doc! { "n": 1, "nModified": 1, "ok": 1.0 }));
+        }
+
+        if request.query.contains_key("slow") {
+            let active = load.active.fetch_add(1, Ordering::SeqCst) + 1;
+            let mut peak = load.peak.load(Ordering::SeqCst);
+            while active > peak {
+                match load.peak.compare_exchange(peak, active, Ordering::SeqCst, Ordering::SeqCst) {
+                    Ok(_) => break,
+                    Err(current) => peak = current,
+                }
+            }
+            thread::sleep(Duration::from_millis(100));
+            load.active.fetch_sub(1, Ordering::SeqCst);
+            Response::Reply(reply(request, doc! { "batch": 1 }))
+        } else if request.query.contains_key("fail") {
+            Response::Reply(query_failure(request, "injected failure", 2))
+        } else if request.query.contains_key("more") {
+            Response::Reply(op_reply(request.request_id, 0, CURSOR_ID, &doc! { "batch": 1 }))
+        } else {
+            Response::Reply(reply(request, doc! { "batch": 1 }))
+        }
+    }
+}
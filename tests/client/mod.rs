@@ -1,18 +1,50 @@
+mod admin;
 mod batch_size;
+mod buffered;
 mod bulk;
+mod capabilities;
 mod coll;
 mod connstring;
 mod crud_spec;
 mod db;
 mod cursor;
+#[cfg(feature = "encryption")]
+mod encryption;
 mod error;
 mod gridfs;
 mod handshake;
+mod hedged_reads;
+mod idle_connections;
+mod limiter;
+mod logging;
+mod malformed_replies;
+mod metrics;
+mod mock_server;
+mod op_ctx;
+mod oplog;
+mod primary_pinning;
+mod raw_insert;
+mod read_concern;
+mod retryable_reads;
+mod retryable_writes;
+mod routing;
+mod session;
+mod shutdown;
+mod stale_config;
+mod two_phase;
 mod wire_protocol;
+#[cfg(feature = "wire-version-override")]
+mod wire_version_override;
 
 use bson;
-use mongodb::{Client, ThreadedClient};
+use mongodb::{Client, ClientOptions, CommandStarted, ThreadedClient};
+use mongodb::common::{ReadConcern, ReadMode, ReadPreference};
 use mongodb::db::ThreadedDatabase;
+use mongodb::logging::{LogLevel, StderrLogger};
+use mongodb::stream::{HostAliases, HostMapper};
+use mongodb::topology::TopologyType;
+use mongodb::topology::server::ServerType;
+use std::sync::Arc;
 use std::thread;
 
 #[test]
@@ -69,6 +101,60 @@ fn database_names() {
     ));
 }
 
+#[test]
+fn list_databases() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-mod-list_databases");
+    db.collection("test").insert_one(doc! { "a": 1 }, None).unwrap();
+
+    // Servers before MongoDB 3.6 are sent no filter, so the client filters their reply.
+    let mut options = ClientOptions::new();
+    options.max_wire_version = Some(5);
+    let legacy = Client::connect_with_options("localhost", 27017, options).unwrap();
+
+    for client in &[client.clone(), legacy.clone()] {
+        let filter = doc! { "name": "test-client-mod-list_databases" };
+        let databases = client.list_databases(Some(filter.clone()), false).unwrap();
+        assert_eq!(databases.len(), 1);
+        assert_eq!(databases[0].name, "test-client-mod-list_databases");
+        assert!(databases[0].size_on_disk.is_some());
+        assert_eq!(databases[0].empty, Some(false));
+
+        let databases = client.list_databases(Some(filter), true).unwrap();
+        assert_eq!(databases.len(), 1);
+        assert_eq!(databases[0].size_on_disk, None);
+        assert_eq!(databases[0].empty, None);
+
+        let all = client.list_databases(None, true).unwrap();
+        assert!(all.iter().any(|database| database.name == "admin"));
+        assert!(all.len() > 1);
+    }
+
+    let filter = doc! { "name": { "$regex": "^test-client-mod-" } };
+    assert!(legacy.list_databases(Some(filter), false).is_err());
+
+    client.drop_database("test-client-mod-list_databases").unwrap();
+}
+
+#[test]
+fn host_aliases() {
+    let mut aliases = HostAliases::new();
+    aliases.add("Mongo.Invalid", 27017, "localhost", 27017);
+    assert_eq!(
+        aliases.map_host("mongo.invalid", 27017),
+        Some((String::from("localhost"), 27017))
+    );
+    assert_eq!(aliases.map_host("mongo.invalid", 27018), None);
+
+    let mut options = ClientOptions::new();
+    options.host_mapper = Some(Arc::new(aliases));
+    let client = Client::connect_with_options("mongo.invalid", 27017, options).unwrap();
+    assert!(client.is_master().unwrap());
+
+    let info = client.topology_info().unwrap();
+    assert_eq!(info.servers[0].host.host_name, "mongo.invalid");
+}
+
 #[test]
 fn is_sync() {
     let client = Client::connect("localhost", 27017).unwrap();
@@ -130,3 +216,190 @@ fn is_sync() {
     assert!(results.contains(&"test-client-mod-is_sync".to_owned()));
     assert!(results.contains(&"test-client-mod-is_sync_2".to_owned()));
 }
+
+#[test]
+fn topology_info() {
+    let client = Client::connect("localhost", 27017).unwrap();
+
+    // Run a command so that the server has been checked at least once.
+    client.is_master().expect("Failed to execute is_master.");
+
+    let info = client.topology_info().expect("Failed to get topology info.");
+    assert_eq!(info.topology_type, TopologyType::Single);
+    assert_eq!(info.servers.len(), 1);
+
+    let server = &info.servers[0];
+    assert_eq!(server.host.host_name, "localhost");
+    assert_eq!(server.host.port, 27017);
+    assert!(server.max_wire_version >= 0);
+    assert!(server.error.is_none());
+}
+
+#[test]
+fn connection_stats() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    client.is_master().expect("Failed to execute is_master.");
+    client.refresh_topology().expect("Failed to refresh topology.");
+
+    let stats = client.connection_stats().expect("Failed to get connection stats.");
+    assert_eq!(stats.len(), 1);
+
+    // Whether the monitor borrowed the idle pooled socket or used its own depends on the
+    // timing of its checks, but a borrowed socket never counts as in use.
+    let server = &stats[0];
+    assert_eq!(server.host.host_name, "localhost");
+    assert!(server.monitor_sockets <= 1);
+    assert!(server.open > server.monitor_sockets);
+    assert_eq!(server.in_use, 0);
+    assert!(server.checkouts >= 1);
+}
+
+#[test]
+fn with_options_shares_connections() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let secondary = ReadPreference::new(ReadMode::SecondaryPreferred, None);
+    let derived = client.with_options(Some(secondary.clone()), None, Some(ReadConcern::Local));
+
+    assert!(client.is_root());
+    assert!(!derived.is_root());
+
+    // Databases inherit the defaults of the handle they were created from.
+    let db = client.db("test-client-with-options");
+    let derived_db = derived.db("test-client-with-options");
+    assert_eq!(db.read_preference.mode, ReadMode::Primary);
+    assert_eq!(derived_db.read_preference, secondary);
+    assert_eq!(db.read_concern, None);
+    assert_eq!(derived_db.read_concern, Some(ReadConcern::Local));
+    assert_eq!(derived_db.write_concern, db.write_concern);
+
+    let coll = db.collection("shared");
+    coll.drop().unwrap();
+    coll.insert_one(doc! { "x": 1 }, None).unwrap();
+
+    let derived_coll = derived_db.collection("shared");
+    assert_eq!(derived_coll.read_concern(), Some(ReadConcern::Local));
+    assert!(derived_coll.find_one(None, None).unwrap().is_some());
+    assert!(coll.find_one(None, None).unwrap().is_some());
+
+    // Both handles report the same pool, which sequential reads served from one socket.
+    client.refresh_topology().expect("Failed to refresh topology.");
+    let stats = client.connection_stats().expect("Failed to get connection stats.");
+    assert_eq!(derived.connection_stats().unwrap(), stats);
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].open, 1);
+
+    // Handles created from a derived handle keep its defaults and share the same root.
+    let nested = derived.with_options(None, None, None);
+    assert_eq!(nested.read_preference, secondary);
+    assert_eq!(nested.read_concern, Some(ReadConcern::Local));
+    assert!(!nested.is_root());
+}
+
+#[test]
+fn ping() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    client.ping().expect("Failed to ping.");
+    assert!(client.is_connected());
+
+    let round_trips = client.ping_all().expect("Failed to ping servers.");
+    assert_eq!(round_trips.len(), 1);
+    for (host, round_trip) in round_trips {
+        assert_eq!(host.port, 27017);
+        round_trip.expect("Failed to ping server.");
+    }
+
+    // Pings use connections of their own, so they don't wait for other operations.
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            let client = client.clone();
+            thread::spawn(move || {
+                let coll = client.db("test-client-mod").collection("ping");
+                for _ in 0..10 {
+                    if i % 2 == 0 {
+                        client.ping().unwrap();
+                    } else {
+                        coll.find_one(None, None).unwrap();
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    // Nothing listens on this port.
+    let client = Client::connect("localhost", 1).unwrap();
+    assert!(!client.is_connected());
+    for (_, round_trip) in client.ping_all().unwrap() {
+        assert!(round_trip.is_err());
+    }
+}
+
+// Application state holding a client, as it would be shared between request handlers.
+struct AppState {
+    client: Client,
+}
+
+fn ignore_command(_client: Client, _command_started: &CommandStarted) {}
+
+#[test]
+fn configure_shared_client() {
+    let state = Arc::new(AppState { client: Client::connect("localhost", 27017).unwrap() });
+
+    // Every client method takes a shared reference, so a client embedded in shared state can
+    // still be configured and used from any thread.
+    let handle = {
+        let state = state.clone();
+        thread::spawn(move || {
+            state.client.add_start_hook(ignore_command).unwrap();
+            state.client.set_logger(Arc::new(StderrLogger::new(LogLevel::Error))).unwrap();
+        })
+    };
+    handle.join().unwrap();
+
+    state.client.is_master().expect("Failed to execute is_master.");
+}
+
+#[test]
+fn connect_to_mongos() {
+    assert!(Client::connect_to_mongos(&[]).is_err());
+
+    let client = Client::with_uri("mongodb://localhost:27017").unwrap();
+    client.is_master().unwrap();
+
+    let info = client.topology_info().expect("Failed to get topology info.");
+    if info.topology_type != TopologyType::Sharded {
+        return;
+    }
+
+    // Nothing listens on the second address, so every operation must go to the first router.
+    let client = Client::connect_to_mongos(&[("localhost", 27017), ("localhost", 1)]).unwrap();
+    for _ in 0..10 {
+        client.is_master().expect("Failed to fail over to the reachable mongos.");
+    }
+
+    client.refresh_topology().expect("Failed to refresh topology.");
+    let info = client.topology_info().expect("Failed to get topology info.");
+    assert_eq!(info.topology_type, TopologyType::Sharded);
+    assert_eq!(
+        info.servers
+            .iter()
+            .filter(|server| server.server_type == ServerType::Mongos)
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn heartbeat_frequency() {
+    let uri = "mongodb://localhost:27017/?heartbeatFrequencyMS=499";
+    assert!(Client::with_uri(uri).is_err());
+
+    let uri = "mongodb://localhost:27017/?heartbeatFrequencyMS=often";
+    assert!(Client::with_uri(uri).is_err());
+
+    let uri = "mongodb://localhost:27017/?heartbeatFrequencyMS=500";
+    assert!(Client::with_uri(uri).is_ok());
+}
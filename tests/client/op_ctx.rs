@@ -0,0 +1,119 @@
+use bson::{Bson, Document};
+use mongodb::{Client, CommandType, Error, ThreadedClient};
+use mongodb::coll::Collection;
+use mongodb::coll::options::FindOptions;
+use mongodb::db::ThreadedDatabase;
+use mongodb::op_ctx::OpCtx;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Each document takes the server 100ms to reject, so a scan takes about two seconds.
+fn slow_filter() -> Option<Document> {
+    Some(doc! { "$where": "sleep(100) || false" })
+}
+
+fn setup(name: &str) -> Collection {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-op_ctx").collection(name);
+    coll.drop().unwrap();
+
+    let docs = (0..20).map(|i| doc! { "_id": i }).collect();
+    coll.insert_many(docs, None).unwrap();
+    coll
+}
+
+#[test]
+fn limit_max_time_ms() {
+    assert_eq!(OpCtx::new().limit_max_time_ms(None), None);
+    assert_eq!(OpCtx::new().limit_max_time_ms(Some(100)), Some(100));
+
+    let ctx = OpCtx::with_timeout(Duration::from_secs(60));
+    assert_eq!(ctx.limit_max_time_ms(Some(100)), Some(100));
+    let max_time_ms = ctx.limit_max_time_ms(None).unwrap();
+    assert!(max_time_ms > 59_000 && max_time_ms <= 60_000);
+
+    // 0 would mean no limit at all.
+    let expired = OpCtx::with_deadline(Instant::now());
+    assert_eq!(expired.limit_max_time_ms(None), Some(1));
+}
+
+#[test]
+fn ended_ctx_fails_before_sending() {
+    let coll = setup("ended");
+
+    let ctx = OpCtx::new();
+    ctx.clone().cancel();
+    assert!(ctx.is_cancelled());
+    match coll.find_one_with_ctx(None, None, &ctx) {
+        Err(Error::CancelledError) => (),
+        other => panic!("Expected a CancelledError, got {:?}", other),
+    }
+
+    let ctx = OpCtx::with_deadline(Instant::now());
+    match coll.find_with_ctx(None, None, &ctx) {
+        Err(Error::DeadlineExceededError) => (),
+        other => panic!("Expected a DeadlineExceededError, got {:?}", other),
+    }
+
+    let db = coll.db.clone();
+    match db.run_command_with_ctx(doc! { "ping": 1 }, CommandType::Suppressed, None, &ctx) {
+        Err(Error::DeadlineExceededError) => (),
+        other => panic!("Expected a DeadlineExceededError, got {:?}", other),
+    }
+
+    // The connection is still usable afterwards.
+    let ctx = OpCtx::new();
+    let reply = db.run_command_with_ctx(doc! { "ping": 1 }, CommandType::Suppressed, None, &ctx)
+        .unwrap();
+    assert_eq!(reply.get("ok"), Some(&Bson::FloatingPoint(1.0)));
+}
+
+#[test]
+fn deadline_aborts_slow_query() {
+    let coll = setup("deadline");
+
+    let start = Instant::now();
+    let ctx = OpCtx::with_timeout(Duration::from_millis(500));
+    let result = coll.find_with_ctx(slow_filter(), None, &ctx)
+        .and_then(|cursor| cursor.collect::<Result<Vec<_>, _>>());
+
+    match result {
+        Err(Error::DeadlineExceededError) => (),
+        other => panic!("Expected a DeadlineExceededError, got {:?}", other),
+    }
+    assert!(start.elapsed() < Duration::from_millis(1500));
+
+    // A deadline shorter than maxTimeMS wins, and a roomy one doesn't get in the way.
+    let options = FindOptions::builder().max_time_ms(60_000).build();
+    let ctx = OpCtx::with_timeout(Duration::from_millis(500));
+    assert!(coll.find_one_with_ctx(slow_filter(), Some(options), &ctx).is_err());
+
+    let ctx = OpCtx::with_timeout(Duration::from_secs(60));
+    assert_eq!(coll.find_with_ctx(None, None, &ctx).unwrap().count(), 20);
+}
+
+#[test]
+fn cancel_interrupts_slow_query() {
+    let coll = setup("cancel");
+    let ctx = OpCtx::new();
+
+    let canceller = {
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            ctx.cancel();
+        })
+    };
+
+    let start = Instant::now();
+    match coll.find_one_with_ctx(slow_filter(), None, &ctx) {
+        Err(Error::CancelledError) => (),
+        other => panic!("Expected a CancelledError, got {:?}", other),
+    }
+    assert!(start.elapsed() < Duration::from_millis(1500));
+    canceller.join().unwrap();
+
+    // The interrupted connection isn't handed out again.
+    assert_eq!(coll.find(None, None).unwrap().count(), 20);
+}
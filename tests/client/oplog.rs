@@ -0,0 +1,75 @@
+use bson::Bson;
+
+use mongodb::{Client, Error, ThreadedClient};
+use mongodb::coll::options::FindOptions;
+use mongodb::db::ThreadedDatabase;
+use mongodb::oplog::{OplogEntry, OplogTailer};
+
+#[test]
+fn parse_oplog_entry() {
+    let doc = doc! {
+        "ts": Bson::TimeStamp((5 << 32) + 1),
+        "op": "u",
+        "ns": "test.coll",
+        "o": { "$set": { "x": 1 } },
+        "o2": { "_id": 1 },
+    };
+
+    let entry = OplogEntry::from_document(doc).expect("Failed to parse oplog entry");
+    assert_eq!(entry.ts, (5 << 32) + 1);
+    assert_eq!(entry.op, "u");
+    assert_eq!(entry.ns, "test.coll");
+    assert_eq!(entry.o, doc! { "$set": { "x": 1 } });
+    assert_eq!(entry.o2, Some(doc! { "_id": 1 }));
+
+    assert!(OplogEntry::from_document(doc! { "op": "n" }).is_err());
+}
+
+#[test]
+fn tail_oplog() {
+    let client = Client::connect("localhost", 27017).unwrap();
+
+    // The oplog only exists on replica set members.
+    let names = client.db("local").collection_names(None).expect(
+        "Failed to list collections.",
+    );
+    if !names.contains(&"oplog.rs".to_owned()) {
+        return;
+    }
+
+    // Timestamp 1 is older than any entry in the oplog.
+    match OplogTailer::new(client.clone(), 1) {
+        Err(Error::OplogRolloverError(1)) => (),
+        _ => panic!("Expected oplog rollover to be detected"),
+    }
+
+    let options = FindOptions::builder().sort(doc! { "$natural": -1 }).build();
+
+    let newest = client
+        .db("local")
+        .collection("oplog.rs")
+        .find_one(None, Some(options))
+        .expect("Failed to find newest oplog entry")
+        .expect("Oplog is empty");
+
+    let start_ts = match newest.get("ts") {
+        Some(&Bson::TimeStamp(ts)) => ts,
+        _ => panic!("Oplog entry is missing 'ts'"),
+    };
+
+    let mut tailer = OplogTailer::new(client.clone(), start_ts).expect("Failed to tail oplog");
+
+    let coll = client.db("test-client-oplog").collection("tail_oplog");
+    coll.insert_one(doc! { "tailed": true }, None).expect("Failed to insert document");
+
+    loop {
+        let entry = tailer.next().expect("Tailer ended").expect("Failed to tail oplog");
+        assert!(entry.ts > start_ts);
+        assert_eq!(entry.ts, tailer.last_ts());
+
+        if entry.op == "i" && entry.ns == "test-client-oplog.tail_oplog" {
+            assert_eq!(entry.o.get("tailed"), Some(&Bson::Boolean(true)));
+            break;
+        }
+    }
+}
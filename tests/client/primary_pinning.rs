@@ -0,0 +1,160 @@
+use mongodb::{Client, ClientOptions, CommandType, ThreadedClient};
+use mongodb::common::{ReadMode, ReadPreference};
+use mongodb::coll::options::FindOptions;
+use mongodb::db::ThreadedDatabase;
+use mongodb::session::SessionOptions;
+use mongodb::topology::TopologyType;
+use mongodb::topology::server::ServerType;
+
+// Connects directly to every secondary of the replica set, or returns None if the deployment
+// isn't a replica set with at least one secondary.
+fn connect_to_secondaries(client: &Client) -> Option<Vec<Client>> {
+    let info = client.topology_info().expect("Failed to get topology info.");
+    if info.topology_type != TopologyType::ReplicaSetWithPrimary {
+        return None;
+    }
+
+    let secondaries: Vec<_> = info.servers
+        .iter()
+        .filter(|server| server.server_type == ServerType::RSSecondary)
+        .map(|server| {
+            Client::connect(&server.host.host_name, server.host.port)
+                .expect("Failed to connect to secondary.")
+        })
+        .collect();
+
+    if secondaries.is_empty() {
+        None
+    } else {
+        Some(secondaries)
+    }
+}
+
+// Stops replication to the secondaries by locking them against writes.
+fn lock(secondaries: &[Client]) {
+    for secondary in secondaries {
+        secondary
+            .db("admin")
+            .command(doc! { "fsync": 1, "lock": true }, CommandType::Suppressed, None)
+            .expect("Failed to lock secondary.");
+    }
+}
+
+fn unlock(secondaries: &[Client]) {
+    for secondary in secondaries {
+        secondary
+            .db("admin")
+            .command(doc! { "fsyncUnlock": 1 }, CommandType::Suppressed, None)
+            .expect("Failed to unlock secondary.");
+    }
+}
+
+fn secondary_read() -> FindOptions {
+    FindOptions::builder()
+        .read_preference(ReadPreference::new(ReadMode::Secondary, None))
+        .build()
+}
+
+#[test]
+fn prefer_primary() {
+    let modes = vec![
+        (ReadMode::Primary, ReadMode::Primary),
+        (ReadMode::PrimaryPreferred, ReadMode::PrimaryPreferred),
+        (ReadMode::Secondary, ReadMode::PrimaryPreferred),
+        (ReadMode::SecondaryPreferred, ReadMode::PrimaryPreferred),
+        (ReadMode::Nearest, ReadMode::PrimaryPreferred),
+    ];
+
+    for (mode, expected) in modes {
+        let mut read_preference = ReadPreference::new(mode, None);
+        read_preference.max_staleness_seconds = Some(120);
+
+        let pinned = read_preference.prefer_primary();
+        assert_eq!(pinned.mode, expected);
+        assert_eq!(pinned.max_staleness_seconds, Some(120));
+    }
+}
+
+#[test]
+fn client_primary_pinning() {
+    let client = Client::with_uri("mongodb://localhost:27017").unwrap();
+    let secondaries = match connect_to_secondaries(&client) {
+        Some(secondaries) => secondaries,
+        None => return,
+    };
+
+    let coll = client.db("test-client-primary_pinning").collection("client");
+    coll.drop().unwrap();
+
+    let mut options = ClientOptions::new();
+    options.primary_pin_window_ms = 60000;
+    let pinned_client = Client::with_uri_and_options("mongodb://localhost:27017", options)
+        .unwrap();
+    let pinned_coll = pinned_client.db("test-client-primary_pinning").collection("client");
+
+    lock(&secondaries);
+
+    // Without pinning, the secondary doesn't see the write yet.
+    let stale = coll.insert_one(doc! { "_id": 1 }, None).and_then(|_| {
+        coll.find_one(Some(doc! { "_id": 1 }), Some(secondary_read()))
+    });
+
+    // With pinning, the read goes to the primary.
+    let fresh = pinned_coll.insert_one(doc! { "_id": 2 }, None).and_then(|_| {
+        pinned_coll.find_one(Some(doc! { "_id": 2 }), Some(secondary_read()))
+    });
+
+    unlock(&secondaries);
+
+    assert_eq!(stale.expect("Failed to read without pinning."), None);
+    assert_eq!(fresh.expect("Failed to read with pinning."), Some(doc! { "_id": 2 }));
+}
+
+#[test]
+fn session_primary_pinning() {
+    let client = Client::with_uri("mongodb://localhost:27017").unwrap();
+    let secondaries = match connect_to_secondaries(&client) {
+        Some(secondaries) => secondaries,
+        None => return,
+    };
+
+    let coll = client.db("test-client-primary_pinning").collection("session");
+    coll.drop().unwrap();
+
+    // Causal consistency would make the secondary wait for the write instead.
+    let mut options = SessionOptions::new();
+    options.causal_consistency = false;
+    let mut session = client.start_session_with_options(options.clone()).unwrap();
+
+    options.primary_pin_window_ms = 60000;
+    let mut pinned_session = client.start_session_with_options(options).unwrap();
+
+    lock(&secondaries);
+
+    let stale = coll.insert_one_with_session(doc! { "_id": 1 }, None, &mut session)
+        .and_then(|_| {
+            coll.find_one_with_session(
+                Some(doc! { "_id": 1 }),
+                Some(secondary_read()),
+                &mut session,
+            )
+        });
+
+    let fresh = coll.insert_one_with_session(doc! { "_id": 2 }, None, &mut pinned_session)
+        .and_then(|_| {
+            coll.find_one_with_session(
+                Some(doc! { "_id": 2 }),
+                Some(secondary_read()),
+                &mut pinned_session,
+            )
+        });
+
+    // Pinning is scoped to the session, so reads outside of it still go to the secondary.
+    let unpinned = coll.find_one(Some(doc! { "_id": 2 }), Some(secondary_read()));
+
+    unlock(&secondaries);
+
+    assert_eq!(stale.expect("Failed to read without pinning."), None);
+    assert_eq!(fresh.expect("Failed to read with pinning."), Some(doc! { "_id": 2 }));
+    assert_eq!(unpinned.expect("Failed to read outside the session."), None);
+}
@@ -0,0 +1,173 @@
+use bson::{self, Bson};
+use bson::oid::ObjectId;
+use mongodb::{Client, CommandResult, CommandStarted, Error, ThreadedClient};
+use mongodb::coll::options::InsertManyOptions;
+use mongodb::db::ThreadedDatabase;
+use mongodb::wire_protocol::raw::{encode_command, find_id, prepend_id};
+
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+fn encode(doc: &bson::Document) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bson::encode_document(&mut bytes, doc).unwrap();
+    bytes
+}
+
+fn decode(bytes: &[u8]) -> bson::Document {
+    bson::decode_document(&mut io::Cursor::new(bytes)).unwrap()
+}
+
+#[test]
+fn find_raw_ids() {
+    let doc = doc! {
+        "name": "a",
+        "nested": { "_id": 5 },
+        "list": [1, 2],
+        "binary": Bson::Binary(bson::spec::BinarySubtype::Generic, vec![1, 2, 3]),
+        "regex": Bson::RegExp(String::from("^a"), String::from("i")),
+        "_id": { "a": 1, "b": "two" },
+    };
+    assert_eq!(find_id(&encode(&doc)).unwrap(), Some(Bson::Document(doc! { "a": 1, "b": "two" })));
+
+    // Only top-level elements are considered.
+    let doc = doc! { "nested": { "_id": 5 } };
+    assert_eq!(find_id(&encode(&doc)).unwrap(), None);
+
+    let id = ObjectId::new().unwrap();
+    let with_id = prepend_id(&encode(&doc), &id);
+    assert_eq!(find_id(&with_id).unwrap(), Some(Bson::ObjectId(id.clone())));
+    assert_eq!(decode(&with_id), doc! { "_id": id, "nested": { "_id": 5 } });
+}
+
+#[test]
+fn reject_malformed_raw_documents() {
+    let valid = encode(&doc! { "_id": 1, "x": "y" });
+
+    let mut short_prefix = valid.clone();
+    short_prefix[0] -= 1;
+
+    let mut long_prefix = valid.clone();
+    long_prefix[0] += 1;
+
+    let mut unterminated = valid.clone();
+    let last = unterminated.len() - 1;
+    unterminated[last] = 1;
+
+    let mut truncated_value = encode(&doc! { "x": "a long string value" });
+    truncated_value[10] = 100;
+
+    for bytes in vec![vec![5, 0], short_prefix, long_prefix, unterminated, truncated_value] {
+        assert!(find_id(&bytes).is_err(), "{:?}", bytes);
+    }
+}
+
+#[test]
+fn encode_raw_command() {
+    let docs = vec![encode(&doc! { "_id": 1 }), encode(&doc! { "_id": 2, "x": "y" })];
+    let bytes = encode_command(&doc! { "insert": "coll", "ordered": true }, "documents", &docs)
+        .unwrap();
+
+    assert_eq!(decode(&bytes), doc! {
+        "insert": "coll",
+        "ordered": true,
+        "documents": [{ "_id": 1 }, { "_id": 2, "x": "y" }],
+    });
+}
+
+#[test]
+fn insert_raw() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-raw-insert").collection("insert_raw");
+    coll.drop().unwrap();
+
+    let docs = vec![encode(&doc! { "_id": 1, "x": 1 }), encode(&doc! { "x": 2 })];
+    let result = coll.insert_raw(docs, None).unwrap();
+    assert!(result.bulk_write_exception.is_none());
+
+    let ids = result.inserted_ids.unwrap();
+    assert_eq!(ids.len(), 2);
+    assert_eq!(ids.get(&0), Some(&Bson::I32(1)));
+
+    let generated = ids.get(&1).unwrap().clone();
+    let doc = coll.find_one(Some(doc! { "x": 2 }), None).unwrap().unwrap();
+    assert_eq!(doc.get("_id"), Some(&generated));
+
+    // Malformed documents are reported by index before anything is sent.
+    let mut malformed = encode(&doc! { "x": 3 });
+    malformed[0] += 1;
+    let docs = vec![encode(&doc! { "x": 4 }), malformed];
+    match coll.insert_raw(docs, None) {
+        Err(Error::ArgumentError(ref msg)) => assert!(msg.contains("Raw document 1"), "{}", msg),
+        other => panic!("Expected an ArgumentError, got {:?}", other),
+    }
+    assert_eq!(coll.count(None, None).unwrap(), 2);
+}
+
+static MONITORED_RAW_DOCUMENTS: AtomicUsize = AtomicUsize::new(0);
+static MONITORED_RAW_SUCCESSES: AtomicUsize = AtomicUsize::new(0);
+
+fn record_raw_insert(_client: Client, command_started: &CommandStarted) {
+    if command_started.command.get_str("insert").ok() == Some("monitored_raw_insert") {
+        let documents = command_started.command.get_array("documents").unwrap();
+        MONITORED_RAW_DOCUMENTS.fetch_add(documents.len(), Ordering::SeqCst);
+    }
+}
+
+fn record_raw_insert_success(_client: Client, command_result: &CommandResult) {
+    if let CommandResult::Success { ref command_name, ref reply, .. } = *command_result {
+        if command_name == "insert_many" && reply.get_i32("n").ok() == Some(3) {
+            MONITORED_RAW_SUCCESSES.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+#[test]
+fn monitored_raw_insert() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-raw-insert").collection("monitored_raw_insert");
+    coll.drop().unwrap();
+
+    client.add_start_hook(record_raw_insert).unwrap();
+    client.add_completion_hook(record_raw_insert_success).unwrap();
+
+    // Monitoring sees the insert command with its documents decoded, and the server's reply.
+    let docs = (0..3).map(|i| encode(&doc! { "_id": i })).collect();
+    coll.insert_raw(docs, None).unwrap();
+    assert_eq!(MONITORED_RAW_DOCUMENTS.load(Ordering::SeqCst), 3);
+    assert_eq!(MONITORED_RAW_SUCCESSES.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn insert_many_raw_in_batches() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-raw-insert").collection("insert_many_raw_in_batches");
+    coll.drop().unwrap();
+    coll.insert_one(doc! { "_id": 1500 }, None).unwrap();
+
+    // More documents than fit in one command, with a duplicate key in the second one.
+    let docs: Vec<_> = (0..3000).map(|i| encode(&doc! { "_id": i })).collect();
+    let options = InsertManyOptions::builder().ordered(true).build();
+
+    let result = coll.insert_many_raw(docs, Some(options)).unwrap();
+    let exception = result.bulk_write_exception.expect("Expected a bulk write exception.");
+    assert_eq!(exception.write_errors.len(), 1);
+    assert_eq!(exception.write_errors[0].index, 1500);
+
+    // Ordered inserts stop at the failed command.
+    let ids = result.inserted_ids.unwrap();
+    assert_eq!(ids.len(), 1500);
+    assert!(!ids.contains_key(&1500));
+    assert_eq!(coll.count(None, None).unwrap(), 1501);
+
+    coll.drop().unwrap();
+    coll.insert_one(doc! { "_id": 1500 }, None).unwrap();
+
+    let docs: Vec<_> = (0..3000).map(|i| encode(&doc! { "_id": i })).collect();
+    let options = InsertManyOptions::builder().ordered(false).build();
+
+    let result = coll.insert_many_raw(docs, Some(options)).unwrap();
+    assert_eq!(result.bulk_write_exception.unwrap().write_errors[0].index, 1500);
+    assert_eq!(result.inserted_ids.unwrap().len(), 2999);
+    assert_eq!(coll.count(None, None).unwrap(), 3000);
+}
@@ -0,0 +1,128 @@
+use bson::{Bson, Document};
+use mongodb::{Client, CommandStarted, ThreadedClient};
+use mongodb::common::{ReadConcern, ReadMode, ReadPreference};
+use mongodb::coll::options::{AggregateOptions, FindOptions};
+use mongodb::db::ThreadedDatabase;
+
+use std::sync::Mutex;
+
+#[test]
+fn parse_read_concern() {
+    assert_eq!("majority".parse::<ReadConcern>().unwrap(), ReadConcern::Majority);
+    assert_eq!("local".parse::<ReadConcern>().unwrap(), ReadConcern::Local);
+    assert!("eventual".parse::<ReadConcern>().is_err());
+
+    assert_eq!(
+        ReadConcern::Linearizable.to_document(),
+        doc! { "level": "linearizable" }
+    );
+}
+
+#[test]
+fn validate_read_concern() {
+    let primary = ReadPreference::new(ReadMode::Primary, None);
+    let secondary = ReadPreference::new(ReadMode::Secondary, None);
+
+    assert!(ReadConcern::Majority.validate(&secondary).is_ok());
+    assert!(ReadConcern::Linearizable.validate(&primary).is_ok());
+    assert!(ReadConcern::Linearizable.validate(&secondary).is_err());
+    assert!(ReadConcern::Snapshot.validate(&primary).is_err());
+}
+
+#[test]
+fn majority_reads() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-read_concern");
+
+    let version = db.version().unwrap();
+    if version.major < 3 || (version.major == 3 && version.minor < 2) {
+        return;
+    }
+
+    let mut coll = db.collection("majority_reads");
+    coll.drop().unwrap();
+    coll.insert_many(vec![doc! { "x": 1 }, doc! { "x": 2 }], None)
+        .unwrap();
+
+    // Servers started without majority read concern support reject the command; skip.
+    coll.set_read_concern(Some(ReadConcern::Majority));
+    let count = match coll.count(None, None) {
+        Ok(count) => count,
+        Err(_) => return,
+    };
+    assert_eq!(count, 2);
+
+    let options = FindOptions::builder().sort(doc! { "x": 1 }).build();
+    let docs: Vec<_> = coll.find(None, Some(options))
+        .unwrap()
+        .map(|doc| doc.unwrap())
+        .collect();
+    assert_eq!(docs.len(), 2);
+    assert_eq!(docs[0].get_i32("x").unwrap(), 1);
+
+    let options = AggregateOptions::builder().read_concern(ReadConcern::Local).build();
+    let pipeline = vec![doc! { "$match": { "x": 2 } }];
+    let results: Vec<_> = coll.aggregate(pipeline, Some(options))
+        .unwrap()
+        .map(|doc| doc.unwrap())
+        .collect();
+    assert_eq!(results.len(), 1);
+
+    let db = db.with_read_concern(Some(ReadConcern::Majority));
+    assert_eq!(
+        db.collection("majority_reads").read_concern(),
+        Some(ReadConcern::Majority)
+    );
+}
+
+// The read concern of each find, aggregate and count sent for the `sent_read_concern`
+// collection, keyed by the command's name.
+static SENT_READ_CONCERNS: Mutex<Vec<(String, Option<Document>)>> = Mutex::new(Vec::new());
+
+fn record_read_concern(_client: Client, command_started: &CommandStarted) {
+    let name = match command_started.command.iter().next() {
+        Some((name, &Bson::String(ref coll))) if coll == "sent_read_concern" => name.clone(),
+        _ => return,
+    };
+
+    let read_concern = command_started.command.get_document("readConcern").ok().cloned();
+    SENT_READ_CONCERNS.lock().unwrap().push((name, read_concern));
+}
+
+#[test]
+fn sent_read_concern() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-read_concern");
+
+    let version = db.version().unwrap();
+    if version.major < 3 || (version.major == 3 && version.minor < 2) {
+        return;
+    }
+
+    let mut coll = db.collection("sent_read_concern");
+    coll.drop().unwrap();
+    coll.insert_one(doc! { "x": 1 }, None).unwrap();
+
+    client.add_start_hook(record_read_concern).unwrap();
+    coll.set_read_concern(Some(ReadConcern::Majority));
+
+    // Servers started without majority read concern support reject the commands, which are
+    // still sent.
+    let _ = coll.find(None, None);
+    let _ = coll.count(None, None);
+
+    let options = AggregateOptions::builder().read_concern(ReadConcern::Local).build();
+    let _ =
+ coll.aggregate(vec![doc! { "$match": { "x": 1 } }], Some(options));
+
+    let majority = Some(doc! { "level": "majority" });
+    let local = Some(doc! { "level": "local" });
+    assert_eq!(
+        *SENT_READ_CONCERNS.lock().unwrap(),
+        vec![
+            (String::from("find"), majority.clone()),
+            (String::from("count"), majority),
+            (String::from("aggregate"), local),
+        ]
+    );
+}
@@ -0,0 +1,108 @@
+use mongodb::{Client, ClientOptions, CommandType, ThreadedClient};
+use mongodb::common::ReadConcern;
+use mongodb::coll::Collection;
+use mongodb::coll::options::FindOptions;
+use mongodb::db::{Database, ThreadedDatabase};
+
+#[test]
+fn retryable_read_command_types() {
+    assert!(CommandType::Find.is_retryable_read());
+    assert!(CommandType::Aggregate.is_retryable_read());
+    assert!(CommandType::Count.is_retryable_read());
+    assert!(CommandType::Distinct.is_retryable_read());
+    assert!(CommandType::ListCollections.is_retryable_read());
+    assert!(!CommandType::InsertOne.is_retryable_read());
+    assert!(!CommandType::FindOneAndUpdate.is_retryable_read());
+}
+
+// Makes the next `find` command fail by closing its connection. Returns false if the server
+// doesn't support the fail point, which needs test commands enabled and MongoDB 4.0.
+fn fail_next_find(admin: &Database) -> bool {
+    let fail_point = doc! {
+        "configureFailPoint": "failCommand",
+        "mode": { "times": 1 },
+        "data": { "failCommands": ["find"], "closeConnection": true },
+    };
+    admin.command(fail_point, CommandType::Suppressed, None).is_ok()
+}
+
+fn disable_fail_point(admin: &Database) {
+    let disable = doc! { "configureFailPoint": "failCommand", "mode": "off" };
+    admin.command(disable, CommandType::Suppressed, None).expect("Failed to disable fail point.");
+}
+
+fn setup(client: &Client, name: &str) -> Collection {
+    let coll = client.db("test-client-retryable-reads").collection(name);
+    coll.drop().expect("Failed to drop collection.");
+    coll.insert_many(vec![doc! { "_id": 1 }, doc! { "_id": 2 }], None)
+        .expect("Failed to insert documents.");
+    coll
+}
+
+// Sends the query as a `find` command rather than a legacy query, which fail points don't see.
+fn find_command_options() -> FindOptions {
+    FindOptions::builder().read_concern(ReadConcern::Local).build()
+}
+
+#[test]
+fn retry_find_after_network_error() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let admin = client.db("admin");
+    let coll = setup(&client, "network_error");
+
+    if !fail_next_find(&admin) {
+        return;
+    }
+
+    let result = coll.find(None, Some(find_command_options()));
+    disable_fail_point(&admin);
+
+    let docs: Vec<_> = result
+        .expect("Failed to retry find.")
+        .map(|doc| doc.expect("Failed to get next document."))
+        .collect();
+    assert_eq!(docs.len(), 2);
+}
+
+#[test]
+fn retry_reads_disabled() {
+    let mut options = ClientOptions::new();
+    options.retry_reads = Some(false);
+
+    let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+    let admin = client.db("admin");
+    let coll = setup(&client, "disabled");
+
+    if !fail_next_find(&admin) {
+        return;
+    }
+
+    let result = coll.find(None, Some(find_command_options()));
+    disable_fail_point(&admin);
+
+    match result {
+        Err(ref err) if err.is_network_error() => (),
+        Err(err) => panic!("Expected a network error, got {:?}", err),
+        Ok(_) => panic!("Expected the find to fail without a retry."),
+    }
+}
+
+#[test]
+fn retry_reads_disabled_by_connection_string() {
+    let client = Client::with_uri("mongodb://localhost:27017/?retryReads=false").unwrap();
+    let admin = client.db("admin");
+    let coll = setup(&client, "disabled_by_uri");
+
+    if !fail_next_find(&admin) {
+        return;
+    }
+
+    let result = coll.find(None, Some(find_command_options()));
+    disable_fail_point(&admin);
+
+    match result {
+        Err(ref err) if err.is_network_error() => (),
+        Err(err) => panic!("Expected a network error, got {:?}", err),
+        Ok(_) => panic!("Expected the find to fail without a retry."),
+    }
+}
@@ -0,0 +1,81 @@
+use bson::Bson;
+
+use mongodb::{Client, ClientOptions, CommandType, Error, ErrorCode, ThreadedClient};
+use mongodb::db::ThreadedDatabase;
+
+#[test]
+fn classify_retryable_write_errors() {
+    assert!(Error::CodedError(ErrorCode::NotMaster).is_retryable_write_error());
+    assert!(Error::CodedError(ErrorCode::HostUnreachable).is_retryable_write_error());
+    assert!(Error::CodedError(ErrorCode::ShutdownInProgress).is_retryable_write_error());
+    assert!(Error::CodedError(ErrorCode::InterruptedAtShutdown).is_retryable_write_error());
+    assert!(!Error::OperationError(String::from("node is recovering")).is_retryable_write_error());
+    assert!(!Error::CodedError(ErrorCode::DuplicateKey).is_retryable_write_error());
+    assert!(!Error::OperationError(String::from("ns not found")).is_retryable_write_error());
+}
+
+#[test]
+fn retryable_command_types() {
+    assert!(CommandType::InsertOne.is_retryable_write());
+    assert!(CommandType::InsertMany.is_retryable_write());
+    assert!(CommandType::UpdateOne.is_retryable_write());
+    assert!(CommandType::DeleteOne.is_retryable_write());
+    assert!(CommandType::FindOneAndUpdate.is_retryable_write());
+    assert!(!CommandType::UpdateMany.is_retryable_write());
+    assert!(!CommandType::DeleteMany.is_retryable_write());
+    assert!(!CommandType::Find.is_retryable_write());
+}
+
+#[test]
+fn retryable_write_commands() {
+    let docs = vec![Bson::Document(doc! { "_id": 1 }), Bson::Document(doc! { "_id": 2 })];
+    let ordered = doc! { "insert": "c", "documents": docs.clone(), "ordered": true };
+    let unordered = doc! { "insert": "c", "documents": docs, "ordered": false };
+    let single = doc! { "insert": "c", "documents": [{ "_id": 1 }], "ordered": false };
+    let unacknowledged = doc! {
+        "insert": "c",
+        "documents": [{ "_id": 1 }],
+        "writeConcern": { "w": 0, "wtimeout": 0, "j": false, "fsync": false },
+    };
+
+    // Only single-document insert batches are retried, whether ordered or not.
+    assert!(CommandType::InsertMany.is_retryable_write_command(&single));
+    assert!(!CommandType::InsertMany.is_retryable_write_command(&ordered));
+    assert!(!CommandType::InsertMany.is_retryable_write_command(&unordered));
+    assert!(!CommandType::InsertOne.is_retryable_write_command(&unacknowledged));
+    assert!(!CommandType::UpdateMany.is_retryable_write_command(&ordered));
+}
+
+#[test]
+fn retry_write_after_network_error() {
+    let mut options = ClientOptions::new();
+    options.retry_writes = Some(true);
+
+    let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+    let admin = client.db("admin");
+    let coll = client.db("test-client-retryable-writes").collection("network_error");
+    coll.drop().expect("Failed to drop collection.");
+
+    coll.insert_one(doc! { "_id": 0 }, None).expect("Failed to insert document.");
+
+    // Fail points are only available when the server enables test commands. The fail point
+    // only triggers for writes sent with a transaction number, so deployments without
+    // retryable write support simply perform a single successful insert.
+    let fail_point = doc! {
+        "configureFailPoint": "onPrimaryTransactionalWrite",
+        "mode": { "times": 1 },
+        "data": { "closeConnection": true },
+    };
+    if admin.command(fail_point, CommandType::Suppressed, None).is_err() {
+        return;
+    }
+
+    let result = coll.insert_one(doc! { "_id": 1 }, None);
+
+    let disable = doc! { "configureFailPoint": "onPrimaryTransactionalWrite", "mode": "off" };
+    admin.command(disable, CommandType::Suppressed, None).expect("Failed to disable fail point.");
+
+    let result = result.expect("Failed to retry insert.");
+    assert_eq!(result.inserted_id, Some(Bson::I32(1)));
+    assert_eq!(coll.count(None, None).expect("Failed to count documents."), 2);
+}
@@ -0,0 +1,84 @@
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mongodb::{Client, ClientOptions, Error, ThreadedClient};
+use mongodb::db::ThreadedDatabase;
+use mongodb::routing::RoutingProfile;
+
+use super::mock_server::LoadServer;
+
+#[test]
+fn routing_profiles_isolate_slow_collections() {
+    let server = LoadServer::start();
+    let mut options = ClientOptions::new();
+    options.pool_size = Some(4);
+    options.max_concurrent_operations = Some(4);
+    options.wait_queue_timeout = Some(Duration::from_millis(0));
+
+    let client = Client::connect_with_options("127.0.0.1", server.server.port, options).unwrap();
+    let hot = client.db("test").collection("hot");
+    let cold = client.db("test").collection("cold");
+
+    let mut profile = RoutingProfile::new();
+    profile.max_concurrent_operations = Some(2);
+    profile.wait_queue_timeout = Some(Duration::from_millis(150));
+    profile.dedicated_connections = Some(2);
+    client.set_routing_profile("test.hot", Some(profile.clone())).unwrap();
+    assert_eq!(hot.routing_profile().unwrap(), Some(profile));
+    assert_eq!(cold.routing_profile().unwrap(), None);
+
+    // More slow queries on the hot collection than the client runs at once.
+    let threads: Vec<_> = (0..8)
+        .map(|_| {
+            let hot = hot.clone();
+            thread::spawn(move || hot.find_one(Some(doc! { "slow": true }), None))
+        })
+        .collect();
+    thread::sleep(Duration::from_millis(50));
+
+    // The hot collection runs on its own permits and connections, so the cold one neither
+    // waits for a permit nor queues behind the slow queries.
+    let start = Instant::now();
+    for _ in 0..10 {
+        cold.find_one(None, None).unwrap().unwrap();
+    }
+    assert!(start.elapsed() < Duration::from_millis(100), "took {:?}", start.elapsed());
+
+    let mut succeeded = 0;
+    for thread in threads {
+        match thread.join().unwrap() {
+            Ok(_) => succeeded += 1,
+            Err(Error::OverloadedError(2)) => (),
+            Err(err) => panic!("Expected an OverloadedError, got {:?}", err),
+        }
+    }
+
+    assert_eq!(server.load.peak.load(Ordering::SeqCst), 2);
+    assert!(succeeded >= 2 && succeeded < 8, "{} queries succeeded", succeeded);
+
+    // Without its profile, the hot collection is limited by the client again.
+    client.set_routing_profile("test.hot", None).unwrap();
+    hot.find_one(Some(doc! { "slow": true }), None).unwrap();
+}
+
+#[test]
+fn routing_profile_socket_timeout() {
+    let server = LoadServer::start();
+    let client = Client::connect("127.0.0.1", server.server.port).unwrap();
+    let hot = client.db("test").collection("timeout");
+    let cold = client.db("test").collection("cold");
+
+    let mut profile = RoutingProfile::new();
+    profile.socket_timeout = Some(Duration::from_millis(20));
+    client.set_routing_profile("test.timeout", Some(profile)).unwrap();
+
+    match hot.find_one(Some(doc! { "slow": true }), None) {
+        Err(Error::IoError(_)) => (),
+        other => panic!("Expected an IoError, got {:?}", other),
+    }
+
+    // The timeout is cleared before the connection goes back to the pool.
+    hot.find_one(None, None).unwrap().unwrap();
+    cold.find_one(Some(doc! { "slow": true }), None).unwrap().unwrap();
+}
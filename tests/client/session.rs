@@ -0,0 +1,419 @@
+use bson::{Bson, Document};
+
+use mongodb::{Client, CommandType, Error, Result, ThreadedClient};
+use mongodb::common::{ReadMode, ReadPreference};
+use mongodb::coll::options::FindOptions;
+use mongodb::db::ThreadedDatabase;
+use mongodb::session::{compare_timestamps, id_from_bytes, id_from_hex, ClientSession,
+                       ServerSession, SessionOptions, SessionPool, TransactionState};
+
+use std::cmp::Ordering;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::mock_server::{reply, standalone, MockServer, Request, Response};
+
+#[test]
+fn server_session_id_is_uuid() {
+    let session = ServerSession::new();
+
+    match session.id.get("id") {
+        Some(&Bson::Binary(_, ref bytes)) => {
+            assert_eq!(bytes.len(), 16);
+            assert_eq!(bytes[6] & 0xf0, 0x40);
+            assert_eq!(bytes[8] & 0xc0, 0x80);
+        }
+        _ => panic!("Expected session id to contain a binary UUID"),
+    }
+
+    assert_ne!(session.id, ServerSession::new().id);
+}
+
+#[test]
+fn session_pool_reuse() {
+    let pool = SessionPool::new();
+
+    let session = pool.check_out(30);
+    let id = session.id.clone();
+    pool.check_in(session, 30);
+
+    // The most recently used session is reused.
+    assert_eq!(pool.check_out(30).id, id);
+
+    // Sessions past half of their timeout are discarded.
+    let mut stale = ServerSession::new();
+    stale.last_use = Instant::now() - Duration::from_secs(16 * 60);
+    assert!(stale.is_about_to_expire(30));
+
+    let stale_id = stale.id.clone();
+    pool.check_in(stale, 30);
+    assert!(pool.drain().is_empty());
+    assert_ne!(pool.check_out(30).id, stale_id);
+}
+
+#[test]
+fn session_id_from_uuid() {
+    let session = ServerSession::new();
+    let bytes = match session.id.get("id") {
+        Some(&Bson::Binary(_, ref bytes)) => bytes.clone(),
+        _ => panic!("Expected session id to contain a binary UUID"),
+    };
+
+    assert_eq!(id_from_bytes(&bytes).unwrap(), session.id);
+
+    let id = id_from_hex("00112233-4455-6677-8899-aabbccddeeff").unwrap();
+    assert_eq!(id_from_hex("00112233445566778899AABBCCDDEEFF").unwrap(), id);
+
+    for uuid in &["0011", "00112233-4455-6677-8899-aabbccddeeXX"] {
+        match id_from_hex(uuid) {
+            Err(Error::ArgumentError(_)) => (),
+            other => panic!("Expected ArgumentError, got {:?}", other),
+        }
+    }
+
+    match id_from_bytes(&[0; 15]) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected ArgumentError, got {:?}", other),
+    }
+}
+
+#[test]
+fn killed_sessions_are_not_pooled() {
+    let pool = SessionPool::new();
+
+    let idle = pool.check_out(30);
+    let idle_id = idle.id.clone();
+    pool.check_in(idle, 30);
+
+    let in_use = pool.check_out(30);
+    assert_eq!(in_use.id, idle_id);
+    let in_use_id = in_use.id.clone();
+    let other = pool.check_out(30);
+    pool.check_in(other, 30);
+
+    pool.kill(&[in_use_id.clone(), ServerSession::new().id]);
+    assert!(pool.is_killed(&in_use_id));
+
+    // Killed sessions are neither reused nor pooled when they are checked in.
+    pool.check_in(in_use, 30);
+    assert!(!pool.is_killed(&in_use_id));
+    assert!(pool.drain().iter().all(|session| session.id != in_use_id));
+}
+
+#[test]
+fn kill_sessions() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-session");
+
+    // Sessions are only supported by MongoDB 3.6 or later.
+    let mut session = match client.start_session() {
+        Ok(session) => session,
+        Err(_) => return,
+    };
+
+    let cmd = doc! { "ping": 1 };
+    db.command_with_session(cmd.clone(), CommandType::Suppressed, None, &mut session)
+        .expect("Failed to run command.");
+
+    let id = session.id().clone();
+    client.refresh_sessions(&[id.clone()]).expect("Failed to refresh session.");
+    client.kill_sessions(&[id.clone()]).expect("Failed to kill session.");
+
+    match db.command_with_session(cmd, CommandType::Suppressed, None, &mut session) {
+        Err(Error::SessionEndedError) => (),
+        other => panic!("Expected SessionEndedError, got {:?}", other),
+    }
+
+    // The killed session is not reused.
+    drop(session);
+    let session = client.start_session().expect("Failed to start session.");
+    assert_ne!(session.id(), &id);
+
+    match client.kill_all_sessions_by_pattern(&[]) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected ArgumentError, got {:?}", other),
+    }
+}
+
+#[test]
+fn start_session() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-session");
+    let coll = db.collection("start_session");
+    coll.drop().expect("Failed to drop collection.");
+
+    // Sessions are only supported by MongoDB 3.6 or later.
+    let mut session = match client.start_session() {
+        Ok(session) => session,
+        Err(_) => return,
+    };
+
+    let id = session.id().clone();
+
+    coll.insert_one_with_session(doc! { "_id": 1 }, None, &mut session)
+        .expect("Failed to insert document.");
+    coll.update_one_with_session(
+        doc! { "_id": 1 },
+        doc! { "$set": { "x": 1 } },
+        None,
+        &mut session,
+    ).expect("Failed to update document.");
+
+    let cmd = doc! { "ping": 1 };
+    let result = db.command_with_session(cmd, CommandType::Suppressed, None, &mut session)
+        .expect("Failed to run command.");
+    assert!(result.contains_key("ok"));
+
+    // Dropping the session returns it to the pool for reuse.
+    drop(session);
+    let session = client.start_session().expect("Failed to start session.");
+    assert_eq!(session.id(), &id);
+    drop(session);
+
+    client.end_sessions().expect("Failed to end sessions.");
+}
+
+#[test]
+fn transaction() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-session");
+    let coll = db.collection("transaction");
+    coll.drop().expect("Failed to drop collection.");
+
+    // Collections can't be created implicitly inside a transaction.
+    db.create_collection("transaction", None).expect("Failed to create collection.");
+
+    let mut session = match client.start_session() {
+        Ok(session) => session,
+        Err(_) => return,
+    };
+
+    assert_eq!(session.transaction_state(), TransactionState::None);
+    assert!(session.commit_transaction().is_err());
+
+    // Standalone servers and older replica sets reject transactions client-side.
+    if session.start_transaction(None).is_err() {
+        assert_eq!(session.transaction_state(), TransactionState::None);
+        return;
+    }
+
+    coll.insert_one_with_session(doc! { "_id": 1 }, None, &mut session)
+        .expect("Failed to insert document.");
+    session.abort_transaction().expect("Failed to abort transaction.");
+    assert_eq!(session.transaction_state(), TransactionState::Aborted);
+    assert_eq!(coll.count(None, None).expect("Failed to count documents."), 0);
+
+    let coll_name = coll.name();
+    let result = session.with_transaction(None, |session| {
+        let coll = session.client().db("test-client-session").collection(&coll_name);
+        coll.insert_one_with_session(doc! { "_id": 2 }, None, session)?;
+        coll.insert_one_with_session(doc! { "_id": 3 }, None, session)?;
+        Ok(())
+    });
+
+    result.expect("Failed to run transaction.");
+    assert_eq!(session.transaction_state(), TransactionState::Committed);
+    assert_eq!(coll.count(None, None).expect("Failed to count documents."), 2);
+}
+
+#[test]
+fn advance_cluster_time() {
+    // Timestamps are ordered by seconds first, even when the high bit is set.
+    let early = 1i64 << 32 | 5;
+    let late = 2i64 << 32 | 1;
+    let far = ((1u64 << 63) | 1) as i64;
+    assert_eq!(compare_timestamps(early, late), Ordering::Less);
+    assert_eq!(compare_timestamps(far, late), Ordering::Greater);
+    assert_eq!(compare_timestamps(late, late), Ordering::Equal);
+
+    // Sessions only need a client to return their server session to, so a mock will do.
+    let server = MockServer::start(|request| Response::Reply(reply(request, standalone(6))));
+    let client = Client::connect("127.0.0.1", server.port).unwrap();
+    let mut session = ClientSession::new(client, 30, SessionOptions::new());
+    assert!(session.operation_time().is_none());
+
+    let signature = doc! { "keyId": 1i64 };
+    session.process_reply(&doc! {
+        "ok": 1,
+        "operationTime": Bson::TimeStamp(late),
+        "$clusterTime": {
+            "clusterTime": Bson::TimeStamp(late),
+            "signature": signature.clone(),
+        },
+    });
+
+    // Older times never replace newer ones.
+    session.process_reply(&doc! {
+        "ok": 1,
+        "operationTime": Bson::TimeStamp(early),
+        "$clusterTime": { "clusterTime": Bson::TimeStamp(early) },
+    });
+
+    assert_eq!(session.operation_time(), Some(late));
+    let cluster_time = session.cluster_time().unwrap().clone();
+    assert_eq!(cluster_time.get("clusterTime"), Some(&Bson::TimeStamp(late)));
+    assert_eq!(cluster_time.get_document("signature").unwrap(), &signature);
+
+    let mut cmd = doc! { "find": "coll", "readConcern": { "level": "majority" } };
+    session.apply_read_concern(&mut cmd);
+    assert_eq!(
+        cmd.get_document("readConcern").unwrap(),
+        &doc! { "level": "majority", "afterClusterTime": Bson::TimeStamp(late) }
+    );
+
+    session.apply(&mut cmd).unwrap();
+    assert_eq!(cmd.get_document("$clusterTime").unwrap(), &cluster_time);
+}
+
+#[test]
+fn causal_consistency() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db("test-client-session");
+    let coll = db.collection("causal_consistency");
+    coll.drop().expect("Failed to drop collection.");
+
+    let mut session = match client.start_session_with_options(SessionOptions::new()) {
+        Ok(session) => session,
+        Err(_) => return,
+    };
+
+    coll.insert_one_with_session(doc! { "_id": 1, "x": 1 }, None, &mut session)
+        .expect("Failed to insert document.");
+
+    // Standalone servers don't report operation times.
+    if session.operation_time().is_none() {
+        return;
+    }
+
+    let options = FindOptions::builder()
+        .read_preference(ReadPreference::new(ReadMode::SecondaryPreferred, None))
+        .build();
+
+    let doc = coll.find_one_with_session(Some(doc! { "_id": 1 }), Some(options), &mut session)
+        .expect("Failed to find document.")
+        .expect("Read did not observe the preceding write.");
+    assert_eq!(doc.get_i32("x").unwrap(), 1);
+}
+
+// Answers a command sent to the session test server, recording it.
+fn answer_session_command(request: &Request, sent: &Mutex<Vec<Document>>) -> Document {
+    sent.lock().unwrap().push(request.query.clone());
+
+    match request.command_name().unwrap_or("") {
+        "find" | "aggregate" => doc! {
+            "cursor": { "id": 42i64, "ns": "test.sessions", "firstBatch": [{ "x": 1 }] },
+            "ok": 1.0,
+        },
+        "getMore" => doc! {
+            "cursor": { "id": 0i64, "ns": "test.sessions", "nextBatch": [{ "x": 2 }] },
+            "ok": 1.0,
+        },
+        "distinct" => doc! { "values": [1, 2], "ok": 1.0 },
+        _ => doc! { "ok": 1.0 },
+    }
+}
+
+#[test]
+fn reads_with_session() {
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let recorded = sent.clone();
+    let server = MockServer::start(move |request| {
+        if request.is_is_master() {
+            let mut is_master = standalone(6);
+            is_master.insert("logicalSessionTimeoutMinutes", 30);
+            return Response::Reply(reply(request, is_master));
+        }
+
+        Response::Reply(reply(request, answer_session_command(request, &recorded)))
+    });
+
+    let client = Client::connect("127.0.0.1", server.port).unwrap();
+    let coll = client.db("test").collection("sessions");
+    let mut session = client.start_session().expect("Failed to start session.");
+    let id = session.id().clone();
+
+    let docs: Vec<_> = coll.find_with_session(None, None, &mut session)
+        .expect("Failed to find documents.")
+        .collect::<Result<_>>()
+        .expect("Failed to read documents.");
+    assert_eq!(docs, vec![doc! { "x": 1 }, doc! { "x": 2 }]);
+
+    let docs: Vec<_> = coll.aggregate_with_session(Vec::new(), None, &mut session)
+        .expect("Failed to aggregate documents.")
+        .collect::<Result<_>>()
+        .expect("Failed to read documents.");
+    assert_eq!(docs.len(), 2);
+
+    let values = coll.distinct_with_session("x", None, None, &mut session)
+        .expect("Failed to find distinct values.");
+    assert_eq!(values, vec![Bson::I32(1), Bson::I32(2)]);
+
+    // Dropping the last handle to the client ends its pooled sessions.
+    drop(coll);
+    drop(session);
+    drop(client);
+
+    let sent = sent.lock().unwrap();
+    let names: Vec<_> = sent.iter().map(|cmd| cmd.keys().next().unwrap().as_str()).collect();
+    assert_eq!(
+        names,
+        vec!["find", "getMore", "aggregate", "getMore", "distinct", "endSessions"]
+    );
+
+    assert!(sent[..5].iter().all(|cmd| cmd.get_document("lsid").ok() == Some(&id)));
+    assert_eq!(sent[5].get_array("endSessions").ok(), Some(&vec![Bson::Document(id)]));
+}
+
+#[test]
+fn get_mores_in_transaction() {
+    // Transactions need a replica set, so the mock is the primary of a single-member set.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let host = format!("127.0.0.1:{}", listener.local_addr().unwrap().port());
+    let members = Bson::Array(vec![Bson::from(host.as_str())]);
+
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let recorded = sent.clone();
+    let _server = MockServer::listen(listener, move |request| {
+        if request.is_is_master() {
+            let is_master = doc! {
+                "ismaster": true,
+                "setName": "rs",
+                "hosts": members.clone(),
+                "minWireVersion": 0,
+                "maxWireVersion": 7,
+                "logicalSessionTimeoutMinutes": 30,
+                "ok": 1.0,
+            };
+            return Response::Reply(reply(request, is_master));
+        }
+
+        Response::Reply(reply(request, answer_session_command(request, &recorded)))
+    });
+
+    let client = Client::with_uri(&format!("mongodb://{}/?replicaSet=rs", host)).unwrap();
+    let coll = client.db("test").collection("sessions");
+    let mut session = client.start_session().expect("Failed to start session.");
+    session.start_transaction(None).expect("Failed to start transaction.");
+
+    // The cursor comes from a command reply, but still knows which server to ask for more.
+    let cursor = coll.find_with_session(None, None, &mut session)
+        .expect("Failed to find documents.");
+    assert_eq!(cursor.server_address(), Some(host.clone()));
+
+    let docs: Vec<_> = cursor.collect::<Result<_>>().expect("Failed to read documents.");
+    assert_eq!(docs, vec![doc! { "x": 1 }, doc! { "x": 2 }]);
+    session.commit_transaction().expect("Failed to commit transaction.");
+
+    let sent = sent.lock().unwrap();
+    let names: Vec<_> = sent.iter().map(|cmd| cmd.keys().next().unwrap().as_str()).collect();
+    assert_eq!(names[..3], ["find", "getMore", "commitTransaction"]);
+
+    // The getMore continues the transaction the find started.
+    let (find, get_more) = (&sent[0], &sent[1]);
+    assert_eq!(find.get_bool("startTransaction").ok(), Some(true));
+    assert_eq!(get_more.get_document("lsid").ok(), find.get_document("lsid").ok());
+    assert_eq!(get_more.get("txnNumber"), find.get("txnNumber"));
+    assert_eq!(get_more.get_bool("autocommit").ok(), Some(false));
+    assert!(!get_more.contains_key("startTransaction"));
+}
@@ -0,0 +1,77 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mongodb::{Client, Error, ThreadedClient};
+use mongodb::db::ThreadedDatabase;
+
+// Starts a query on another thread that takes at least `sleep_ms` milliseconds on the server.
+fn slow_query(client: &Client, coll_name: &str, sleep_ms: u32) -> thread::JoinHandle<bool> {
+    let coll = client.db("test-client-shutdown").collection(coll_name);
+    coll.drop().unwrap();
+    coll.insert_one(doc! { "x": 1 }, None).unwrap();
+
+    let filter = doc! { "$where": format!("sleep({}) || true", sleep_ms) };
+    let handle = thread::spawn(move || coll.find_one(Some(filter), None).is_ok());
+
+    // Give the query time to reach the server.
+    thread::sleep(Duration::from_millis(200));
+    handle
+}
+
+#[test]
+fn shutdown_drains_operations() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let handle = slow_query(&client, "drain", 1000);
+
+    let start = Instant::now();
+    client.shutdown(10000).expect("Failed to shut down.");
+    let elapsed = start.elapsed();
+
+    // The in-flight query completed before the client shut down.
+    assert!(handle.join().unwrap());
+    assert!(elapsed >= Duration::from_millis(500));
+    assert!(elapsed < Duration::from_millis(10000));
+
+    let coll = client.db("test-client-shutdown").collection("drain");
+    match coll.find_one(None, None) {
+        Err(Error::ShuttingDownError) => (),
+        other => panic!("Expected ShuttingDownError, got {:?}", other),
+    }
+
+    // Shutting down again has no effect.
+    client.shutdown(10000).expect("Failed to shut down a second time.");
+}
+
+#[test]
+fn shutdown_timeout() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let handle = slow_query(&client, "timeout", 3000);
+
+    let start = Instant::now();
+    client.shutdown(500).expect("Failed to shut down.");
+    let elapsed = start.elapsed();
+
+    assert!(elapsed >= Duration::from_millis(500));
+    assert!(elapsed < Duration::from_millis(2500));
+
+    handle.join().unwrap();
+}
+
+#[test]
+fn shutdown_only_from_root() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let derived = client.with_options(None, None, None);
+
+    match derived.shutdown(1000) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected ArgumentError, got {:?}", other),
+    }
+    derived.db("test-client-shutdown").collection("derived").find_one(None, None).unwrap();
+
+    // Shutting down the root shuts down the handles created from it.
+    client.shutdown(1000).expect("Failed to shut down.");
+    match derived.db("test-client-shutdown").collection("derived").find_one(None, None) {
+        Err(Error::ShuttingDownError) => (),
+        other => panic!("Expected ShuttingDownError, got {:?}", other),
+    }
+}
@@ -0,0 +1,115 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bson::Bson;
+use mongodb::{Client, Error, ThreadedClient};
+use mongodb::db::ThreadedDatabase;
+use mongodb::error::CommandFailure;
+
+use super::mock_server::{query_failure, reply, MockServer, Response, OP_QUERY};
+
+const STALE_CONFIG: i32 = 13388;
+
+// A mongos that rejects the given number of queries and insert commands with a StaleConfig
+// error, as while a chunk migrates, before answering them. It counts the operations it receives.
+struct MockMongos {
+    server: MockServer,
+    queries: Arc<AtomicUsize>,
+}
+
+impl MockMongos {
+    fn start(stale_replies: usize) -> MockMongos {
+        let stale_replies = AtomicUsize::new(stale_replies);
+        let queries = Arc::new(AtomicUsize::new(0));
+        let counted = queries.clone();
+
+        let server = MockServer::start(move |request| {
+            if request.op_code != OP_QUERY {
+                return Response::Silent;
+            }
+
+            let is_insert = request.command_name() == Some("insert");
+            if !is_insert && request.command_name().is_some() {
+                let is_master = doc! {
+                    "ismaster": true,
+                    "msg": "isdbgrid",
+                    "minWireVersion": 0,
+                    "maxWireVersion": 3,
+                    "ok": 1.0,
+                };
+                return Response::Reply(reply(request, is_master));
+            }
+
+            counted.fetch_add(1, Ordering::SeqCst);
+            let stale = stale_replies
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                .is_ok();
+
+            Response::Reply(match (stale, is_insert) {
+                (true, true) => {
+                    reply(request, doc! { "ok": 0, "errmsg": "stale config", "code": STALE_CONFIG })
+                }
+                (true, false) => query_failure(request, "stale config", STALE_CONFIG),
+                (false, true) => reply(request, doc! { "ok": 1, "n": 1 }),
+                (false, false) => reply(request, doc! { "_id": 1 }),
+            })
+        });
+
+        MockMongos {
+            server: server,
+            queries: queries,
+        }
+    }
+}
+
+#[test]
+fn classify_stale_config_errors() {
+    let failure = |code: i32| {
+        Error::CommandError(CommandFailure::new(Some(code), ""))
+    };
+
+    for &code in &[13388, 63, 150, 249] {
+        assert!(failure(code).is_stale_config(), "code {}", code);
+    }
+    assert!(!failure(11000).is_stale_config());
+    assert!(!Error::OperationError(String::from("stale config")).is_stale_config());
+}
+
+#[test]
+fn retry_once_after_stale_config() {
+    let mongos = MockMongos::start(1);
+    let client = Client::connect("127.0.0.1", mongos.server.port).unwrap();
+    let coll = client.db("test").collection("stale_config");
+
+    let doc = coll.find_one(None, None).expect("Failed to retry the query.");
+    assert_eq!(doc.and_then(|doc| doc.get("_id").cloned()), Some(Bson::I32(1)));
+    assert_eq!(mongos.queries.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn stale_config_surfaces_if_retry_fails() {
+    let mongos = MockMongos::start(2);
+    let client = Client::connect("127.0.0.1", mongos.server.port).unwrap();
+    let coll = client.db("test").collection("stale_config");
+
+    match coll.find_one(None, None) {
+        Err(ref err) if err.is_stale_config() => (),
+        other => panic!("Expected a stale config error, got {:?}", other),
+    }
+    assert_eq!(mongos.queries.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn no_retry_of_writes_after_stale_config() {
+    let mongos = MockMongos::start(1);
+    let client = Client::connect("127.0.0.1", mongos.server.port).unwrap();
+    let coll = client.db("test").collection("stale_config");
+
+    // Without a session and transaction number, the mongos can't tell a resent insert from a
+    // new one.
+    match coll.insert_one(doc! { "_id": 1 }, None) {
+        Err(ref err) if err.is_stale_config() => (),
+        other => panic!("Expected a stale config error, got {:?}", other),
+    }
+    assert_eq!(mongos.queries.load(Ordering::SeqCst), 1);
+}
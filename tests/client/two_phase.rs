@@ -0,0 +1,246 @@
+//! The two-phase commit helper's writes and recovery depend on the documents the server
+//! stores, so most of these tests need a live server on localhost:27017. Failures reported by
+//! the server are injected through the mock server instead.
+use bson::{Bson, Document};
+use bson::oid::ObjectId;
+use chrono::{self, Utc};
+
+use mongodb::{Client, Error, Result, ThreadedClient};
+use mongodb::db::{Database, ThreadedDatabase};
+use mongodb::two_phase::{TransactionState, TwoPhaseCommit, TwoPhaseOp,
+                         PENDING_TRANSACTIONS_FIELD};
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::mock_server::{reply, standalone, MockServer, Response};
+
+fn amount(op: &TwoPhaseOp) -> i32 {
+    op.data.get_i32("amount").unwrap()
+}
+
+fn apply(_id: &Bson, op: &TwoPhaseOp) -> Result<Document> {
+    Ok(doc! { "$inc": { "balance": amount(op) } })
+}
+
+fn roll_back(_id: &Bson, op: &TwoPhaseOp) -> Result<Document> {
+    Ok(doc! { "$inc": { "balance": -amount(op) } })
+}
+
+fn transfer(from: &str, to: &str, value: i32) -> Vec<TwoPhaseOp> {
+    vec![
+        TwoPhaseOp::new("accounts", doc! { "_id": from }, doc! { "amount": -value }),
+        TwoPhaseOp::new("accounts", doc! { "_id": to }, doc! { "amount": value }),
+    ]
+}
+
+// Starts from two accounts holding 1000 each.
+fn setup(name: &str) -> (Database, TwoPhaseCommit) {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let db = client.db(name);
+    db.drop_database().unwrap();
+
+    let accounts = db.collection("accounts");
+    accounts.insert_one(doc! { "_id": "A", "balance": 1000 }, None).unwrap();
+    accounts.insert_one(doc! { "_id": "B", "balance": 1000 }, None).unwrap();
+
+    let commit = TwoPhaseCommit::new(db.collection("transactions"));
+    (db, commit)
+}
+
+// Returns the balance of an account and whether it is marked by a transaction in progress.
+fn account(db: &Database, id: &str) -> (i32, bool) {
+    let doc = db.collection("accounts").find_one_by_id(Bson::from(id), None).unwrap().unwrap();
+    let marked = match doc.get(PENDING_TRANSACTIONS_FIELD) {
+        Some(&Bson::Array(ref ids)) => !ids.is_empty(),
+        _ => false,
+    };
+
+    (doc.get_i32("balance").unwrap(), marked)
+}
+
+// Records a transaction as a process stopped in the given state would have left it, an hour
+// ago, applying the first `applied` writes of a transfer from A to B.
+fn interrupted(db: &Database, state: TransactionState, applied: usize) -> Bson {
+    let id = Bson::ObjectId(ObjectId::new().unwrap());
+    let ops = transfer("A", "B", 100);
+
+    let op_docs: Vec<_> = ops.iter()
+        .map(|op| {
+            Bson::Document(doc! {
+                "collection": &op.collection,
+                "filter": op.filter.clone(),
+                "data": op.data.clone(),
+            })
+        })
+        .collect();
+
+    let txn = doc! {
+        "_id": id.clone(),
+        "state": state.as_str(),
+        "ops": op_docs,
+        "lastModified": Bson::UtcDatetime(Utc::now() - chrono::Duration::hours(1)),
+    };
+    db.collection("transactions").insert_one(txn, None).unwrap();
+
+    for op in ops.iter().take(applied) {
+        let update = doc! {
+            "$inc": { "balance": amount(op) },
+            "$push": { PENDING_TRANSACTIONS_FIELD: id.clone() },
+        };
+        db.collection("accounts").update_one(op.filter.clone(), update, None).unwrap();
+    }
+
+    id
+}
+
+#[test]
+fn apply_transfer() {
+    let (db, commit) = setup("test-client-two-phase-apply");
+
+    let id = commit.apply(transfer("A", "B", 100), apply, roll_back).unwrap();
+    assert_eq!(commit.state(&id).unwrap(), Some(TransactionState::Done));
+    assert_eq!(account(&db, "A"), (900, false));
+    assert_eq!(account(&db, "B"), (1100, false));
+
+    match commit.apply(Vec::new(), apply, roll_back) {
+        Err(Error::ArgumentError(_)) => (),
+        other => panic!("Expected an ArgumentError, got {:?}", other),
+    }
+}
+
+#[test]
+fn apply_rolls_back_failed_writes() {
+    let (db, commit) = setup("test-client-two-phase-roll-back");
+
+    // The second write matches no document.
+    assert!(commit.apply(transfer("A", "C", 100), apply, roll_back).is_err());
+    assert_eq!(account(&db, "A"), (1000, false));
+
+    // The second write fails in the application.
+    let fail_second = |id: &Bson, op: &TwoPhaseOp| if amount(op) > 0 {
+        Err(Error::OperationError(String::from("injected failure")))
+    } else {
+        apply(id, op)
+    };
+
+    match commit.apply(transfer("A", "B", 100), fail_second, roll_back) {
+        Err(Error::OperationError(ref msg)) => assert_eq!(msg, "injected failure"),
+        other => panic!("Expected the injected failure, got {:?}", other),
+    }
+    assert_eq!(account(&db, "A"), (1000, false));
+    assert_eq!(account(&db, "B"), (1000, false));
+
+    let cancelled = doc! { "state": TransactionState::Cancelled.as_str() };
+    assert_eq!(db.collection("transactions").count(Some(cancelled), None).unwrap(), 2);
+}
+
+#[test]
+fn recover_interrupted_transactions() {
+    let (db, commit) = setup("test-client-two-phase-recover");
+    let threshold = Duration::from_secs(60);
+
+    // Stopped before applying anything: rolled back.
+    let id = interrupted(&db, TransactionState::Initial, 0);
+    let report = commit.recover_pending(threshold, apply, roll_back).unwrap();
+    assert_eq!(report.rolled_back, vec![id.clone()]);
+    assert_eq!(commit.state(&id).unwrap(), Some(TransactionState::Cancelled));
+    assert_eq!(account(&db, "A"), (1000, false));
+    assert_eq!(account(&db, "B"), (1000, false));
+
+    // Stopped after the first write: the second is applied, and the first isn't applied again.
+    let id = interrupted(&db, TransactionState::Pending, 1);
+    let report = commit.recover_pending(threshold, apply, roll_back).unwrap();
+    assert_eq!(report.rolled_forward, vec![id.clone()]);
+    assert_eq!(commit.state(&id).unwrap(), Some(TransactionState::Done));
+    assert_eq!(account(&db, "A"), (900, false));
+    assert_eq!(account(&db, "B"), (1100, false));
+
+    // Stopped after applying every write, before removing the markers.
+    let id = interrupted(&db, TransactionState::Applied, 2);
+    let report = commit.recover_pending(threshold, apply, roll_back).unwrap();
+    assert_eq!(report.rolled_forward, vec![id.clone()]);
+    assert_eq!(commit.state(&id).unwrap(), Some(TransactionState::Done));
+    assert_eq!(account(&db, "A"), (800, false));
+    assert_eq!(account(&db, "B"), (1200, false));
+
+    // Stopped while rolling back, with the first write still applied.
+    let id = interrupted(&db, TransactionState::Canceling, 1);
+    let report = commit.recover_pending(threshold, apply, roll_back).unwrap();
+    assert_eq!(report.rolled_back, vec![id.clone()]);
+    assert_eq!(commit.state(&id).unwrap(), Some(TransactionState::Cancelled));
+    assert_eq!(account(&db, "A"), (800, false));
+    assert_eq!(account(&db, "B"), (1200, false));
+
+    // Recent transactions may still be in progress, and finished ones are left alone.
+    let report = commit.recover_pending(Duration::from_secs(2 * 60 * 60), apply, roll_back)
+        .unwrap();
+    assert!(report.rolled_forward.is_empty() && report.rolled_back.is_empty());
+
+    let report = commit.recover_pending(threshold, apply, roll_back).unwrap();
+    assert!(report.rolled_forward.is_empty() && report.rolled_back.is_empty());
+}
+
+#[test]
+fn apply_rolls_back_after_server_failure() {
+    // Acknowledges every write but the one crediting B, recording the updates it receives.
+    let updates = Arc::new(Mutex::new(Vec::new()));
+    let recorded = updates.clone();
+    let server = MockServer::start(move |request| {
+        if request.is_is_master() {
+            return Response::Reply(reply(request, standalone(6)));
+        }
+
+        if request.command_name() == Some("update") {
+            let update = request.query.get_array("updates").unwrap()[0].clone();
+            let update = update.as_document().unwrap().clone();
+            let credits_b = update.get_document("q").unwrap().get_str("_id") == Ok("B") &&
+                update.get_document("u").unwrap().contains_key("$push");
+            recorded.lock().unwrap().push(update);
+
+            if credits_b {
+                let failure = doc! { "ok": 0.0, "errmsg": "injected failure", "code": 8 };
+                return Response::Reply(reply(request, failure));
+            }
+        }
+
+        Response::Reply(reply(request, doc! { "n": 1, "nModified": 1, "ok": 1.0 }))
+    });
+
+    let client = Client::connect("127.0.0.1", server.port).unwrap();
+    let commit = TwoPhaseCommit::new(client.db("test").collection("transactions"));
+
+    match commit.apply(transfer("A", "B", 100), apply, roll_back) {
+        Err(Error::CommandError(ref failure)) => assert_eq!(failure.code, Some(8)),
+        other => panic!("Expected the injected failure, got {:?}", other),
+    }
+
+    // The debit of A is undone, and the transaction is cancelled.
+    let updates = updates.lock().unwrap();
+    let sent: Vec<_> = updates.iter()
+        .map(|update| {
+            let u = update.get_document("u").unwrap();
+            match u.get_document("$set") {
+                Ok(set) => format!("state {}", set.get_str("state").unwrap()),
+                Err(_) => {
+                    let q = update.get_document("q").unwrap();
+                    let operator = if u.contains_key("$push") { "apply" } else { "roll back" };
+                    format!("{} {}", operator, q.get_str("_id").unwrap())
+                }
+            }
+        })
+        .collect();
+
+    assert_eq!(
+        sent,
+        vec![
+            "state pending",
+            "apply A",
+            "apply B",
+            "state canceling",
+            "roll back A",
+            "roll back B",
+            "state cancelled",
+        ]
+    );
+}
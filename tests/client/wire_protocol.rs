@@ -51,7 +51,7 @@ fn insert_single_key_doc() {
             };
 
             let docs = match reply {
-                Message::OpReply { documents: d, .. } => d,
+                Message::OpReply { documents: d, .. } => d.to_documents().unwrap(),
                 _ => panic!("Invalid response read from server"),
             };
 
@@ -116,7 +116,7 @@ fn insert_multi_key_doc() {
             };
 
             let docs = match reply {
-                Message::OpReply { documents: d, .. } => d,
+                Message::OpReply { documents: d, .. } => d.to_documents().unwrap(),
                 _ => panic!("Invalid response read from server"),
             };
 
@@ -192,7 +192,7 @@ fn insert_docs() {
             };
 
             let docs = match reply {
-                Message::OpReply { documents: d, .. } => d,
+                Message::OpReply { documents: d, .. } => d.to_documents().unwrap(),
                 _ => panic!("Invalid response read from server"),
             };
 
@@ -282,7 +282,7 @@ fn insert_update_then_query() {
             };
 
             let docs = match reply {
-                Message::OpReply { documents: d, .. } => d,
+                Message::OpReply { documents: d, .. } => d.to_documents().unwrap(),
                 _ => panic!("Invalid response read from server"),
             };
 
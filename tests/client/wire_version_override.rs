@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use mongodb::{Client, ClientOptions, Error, ThreadedClient};
+use mongodb::coll::options::{Collation, FindOptions};
+use mongodb::db::ThreadedDatabase;
+use mongodb::logging::Logger;
+
+use super::cursor::SentMessages;
+
+// The wire versions of MongoDB 2.4, 2.6, 3.2 and 3.6.
+const WIRE_VERSIONS: [i64; 4] = [0, 2, 4, 6];
+
+// Returns the wire version the server reports, connecting first if needed.
+fn server_wire_version(client: &Client) -> i64 {
+    client.database_names().expect("Failed to list databases.");
+    client
+        .capabilities()
+        .expect("Failed to read capabilities.")
+        .expect("No server was checked.")
+        .max_wire_version
+}
+
+#[test]
+fn crud_at_forced_wire_versions() {
+    let sent = Arc::new(SentMessages::default());
+    let mut options = ClientOptions::new();
+    options.logger = Some(sent.clone() as Arc<Logger>);
+
+    let client = Client::connect_with_options("localhost", 27017, options).unwrap();
+    let coll = client.db("test-client-wire-version-override").collection("crud");
+    let server_version = server_wire_version(&client);
+
+    // MongoDB 5.1 no longer accepts the legacy messages.
+    if server_version >= 14 {
+        return;
+    }
+
+    for &version in WIRE_VERSIONS.iter() {
+        coll.drop().expect("Failed to drop collection.");
+        client.force_max_wire_version(Some(version)).unwrap();
+
+        let effective = version.min(server_version);
+        let capabilities = client.capabilities().unwrap().unwrap();
+        assert_eq!(capabilities.max_wire_version, effective);
+
+        sent.clear();
+        let docs: Vec<_> = (0..10).map(|i| doc! { "_id": i, "wire": version }).collect();
+        coll.insert_many(docs, None).expect("Failed to insert documents.");
+        coll.insert_one(doc! { "_id": 10, "wire": version }, None)
+            .expect("Failed to insert document.");
+
+        // Before wire version 2, each document is sent in its own insert message and
+        // acknowledged by a getLastError; later, the documents go in insert commands.
+        let namespace = "test-client-wire-version-override.crud";
+        let messages = sent.count(&["of 1 documents into", namespace]);
+        let get_last_errors = sent.count(&["getLastError: 1"]);
+        let commands = sent.count(&["test-client-wire-version-override.$cmd", "insert: \"crud\""]);
+        let expected = if effective < 2 { (11, 11, 0) } else { (0, 0, 2) };
+        assert_eq!((messages, get_last_errors, commands), expected, "wire version {}", version);
+
+        // Record every round trip, to see which messages iterating the cursor sent.
+        client.enable_slow_op_capture(0, 100);
+
+        let options = FindOptions::builder().batch_size(3).sort(doc! { "_id": 1 }).build();
+        let ids: Vec<_> = coll.find(Some(doc! { "wire": version }), Some(options))
+            .expect("Failed to execute find.")
+            .map(|doc| doc.expect("Failed to get next document.").get_i32("_id").unwrap())
+            .collect();
+        assert_eq!(ids, (0..11).collect::<Vec<_>>(), "wire version {}", version);
+
+        let ops = client.drain_slow_ops();
+        client.disable_slow_op_capture();
+
+        // Legacy queries and OP_GET_MORE messages are recorded without the fields of the find
+        // and getMore commands.
+        let legacy = effective < 4;
+        let find = ops.iter().find(|op| op.command_name == "find").expect("No find recorded.");
+        assert_eq!(find.command.contains_key("find"), !legacy, "wire version {}", version);
+        let get_more = ops.iter()
+            .find(|op| op.command_name == "getMore")
+            .expect("No getMore recorded.");
+        let has_collection = get_more.command.contains_key("collection");
+        assert_eq!(has_collection, !legacy, "wire version {}", version);
+
+        client.force_max_wire_version(None).unwrap();
+    }
+
+    let capabilities = client.capabilities().unwrap().unwrap();
+    assert_eq!(capabilities.max_wire_version, server_version);
+}
+
+#[test]
+fn forced_wire_version_rejects_features_like_old_servers() {
+    let client = Client::connect("localhost", 27017).unwrap();
+    let coll = client.db("test-client-wire-version-override").collection("features");
+    let server_version = server_wire_version(&client);
+
+    for &version in WIRE_VERSIONS.iter() {
+        client.force_max_wire_version(Some(version)).unwrap();
+
+        let options = FindOptions::builder().collation(Collation::new("en")).build();
+        let find = coll.find(None, Some(options));
+
+        // The same error as the one a MongoDB 3.2 server causes; see `coll::collation`.
+        if version.min(server_version) < 5 {
+            match find {
+                Err(Error::ArgumentError(ref msg)) => assert!(msg.contains("3.4")),
+                Err(err) => panic!("Expected ArgumentError, got {:?}", err),
+                Ok(_) => panic!("Expected ArgumentError, got a cursor"),
+            }
+        } else {
+            find.expect("Failed to execute find.");
+        }
+
+        client.force_max_wire_version(None).unwrap();
+    }
+}
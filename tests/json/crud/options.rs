@@ -8,83 +8,82 @@ use serde_json::{Map, Value};
 
 impl FromValue for AggregateOptions {
     fn from_json(object: &Map<String, Value>) -> AggregateOptions {
-        let mut options = AggregateOptions::new();
+        let mut builder = AggregateOptions::builder();
 
         if let Some(Bson::I64(x)) = object.get("batchSize").map(Value::clone).map(Into::into) {
-            options.batch_size = x as i32;
+            builder = builder.batch_size(x as i32);
         };
 
-        options
+        builder.build()
     }
 }
 
 impl FromValue for CountOptions {
     fn from_json(object: &Map<String, Value>) -> CountOptions {
-        let mut options = CountOptions::new();
+        let mut builder = CountOptions::builder();
 
         if let Some(Bson::I64(x)) = object.get("skip").map(Value::clone).map(Into::into) {
-            options.skip = Some(x);
+            builder = builder.skip(x);
         }
 
         if let Some(Bson::I64(x)) = object.get("limit").map(Value::clone).map(Into::into) {
-            options.limit = Some(x);
+            builder = builder.limit(x);
         }
 
-        options
+        builder.build()
     }
 }
 
 impl FromValue for FindOptions {
     fn from_json(object: &Map<String, Value>) -> FindOptions {
-        let mut options = FindOptions::new();
+        let mut builder = FindOptions::builder();
 
         if let Some(Bson::Document(doc)) = object.get("sort").map(Value::clone).map(Into::into) {
-            options.sort = Some(doc);
+            builder = builder.sort(doc);
         }
 
         if let Some(Bson::I64(x)) = object.get("skip").map(Value::clone).map(Into::into) {
-            options.skip = Some(x);
+            builder = builder.skip(x);
         }
 
         if let Some(Bson::I64(x)) = object.get("limit").map(Value::clone).map(Into::into) {
-            options.limit = Some(x);
+            builder = builder.limit(x);
         }
 
         if let Some(Bson::I64(x)) = object.get("batchSize").map(Value::clone).map(Into::into) {
-            options.batch_size = Some(x as i32);
+            builder = builder.batch_size(x as i32);
         }
 
-        options
-
+        builder.build()
     }
 }
 
 impl FromValue for FindOneAndDeleteOptions {
     fn from_json(object: &Map<String, Value>) -> FindOneAndDeleteOptions {
-        let mut options = FindOneAndDeleteOptions::new();
+        let mut builder = FindOneAndDeleteOptions::builder();
 
         if let Some(Bson::Document(projection)) =
             object.get("projection").map(Value::clone).map(Into::into)
         {
-            options.projection = Some(projection);
+            builder = builder.projection(projection);
         }
 
         if let Some(Bson::Document(sort)) = object.get("sort").map(Value::clone).map(Into::into) {
-            options.sort = Some(sort);
+            builder = builder.sort(sort);
         }
 
-        options
+        builder.build()
     }
 }
 
 impl FromValue for FindOneAndUpdateOptions {
     fn from_json(object: &Map<String, Value>) -> FindOneAndUpdateOptions {
-        let mut options = FindOneAndUpdateOptions::new();
+        let mut builder = FindOneAndUpdateOptions::builder();
 
         if let Some(Bson::Document(projection)) =
             object.get("projection").map(Value::clone).map(Into::into)
         {
-            options.projection = Some(projection);
+            builder = builder.projection(projection);
         }
 
         if let Some(Bson::String(s)) =
@@ -93,23 +92,23 @@ impl FromValue for FindOneAndUpdateOptions {
             )
         {
             match s.as_ref() {
-                "After" => options.return_document = Some(ReturnDocument::After),
-                "Before" => options.return_document = Some(ReturnDocument::Before),
+                "After" => builder = builder.return_document(ReturnDocument::After),
+                "Before" => builder = builder.return_document(ReturnDocument::Before),
                 _ => {}
             };
         }
 
 
         if let Some(Bson::Document(sort)) = object.get("sort").map(Value::clone).map(Into::into) {
-            options.sort = Some(sort);
+            builder = builder.sort(sort);
         }
 
         if let Some(Bson::Boolean(upsert)) =
             object.get("upsert").map(Value::clone).map(Into::into)
         {
-            options.upsert = Some(upsert);
+            builder = builder.upsert(upsert);
         }
 
-        options
+        builder.build()
     }
 }
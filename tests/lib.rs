@@ -42,6 +42,7 @@
 extern crate approx;
 #[macro_use(doc)]
 extern crate bson;
+extern crate chrono;
 extern crate mongodb_cwal as mongodb;
 extern crate rand;
 extern crate semver;
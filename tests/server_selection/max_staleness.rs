@@ -0,0 +1,143 @@
+use mongodb::{Client, ThreadedClient};
+use mongodb::common::{ReadMode, ReadPreference};
+use mongodb::connstring::{self, ConnectionString, Host};
+use mongodb::stream::StreamConnector;
+use mongodb::topology::{TopologyDescription, TopologyType};
+use mongodb::topology::server::{Server, ServerType};
+
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+// Builds a replica set description from (host, type, last write date) triples, all checked at
+// the same instant.
+fn replica_set(servers: Vec<(&str, ServerType, i64)>) -> TopologyDescription {
+    let dummy_config = ConnectionString::new("i-dont-exist", 27017);
+    let dummy_client = Client::with_config(dummy_config, None, None).unwrap();
+    let dummy_top_arc = Arc::new(RwLock::new(
+        TopologyDescription::new(StreamConnector::default()),
+    ));
+
+    let now = Instant::now();
+    let mut topology_description = TopologyDescription::new(StreamConnector::default());
+    topology_description.topology_type = TopologyType::ReplicaSetWithPrimary;
+
+    for (name, server_type, last_write_date) in servers {
+        let host = connstring::parse_host(name).unwrap();
+        let server = Server::new(
+            dummy_client.clone(),
+            host.clone(),
+            dummy_top_arc.clone(),
+            false,
+            StreamConnector::default(),
+            None,
+            None,
+        );
+
+        {
+            let mut description = server.description.write().unwrap();
+            description.round_trip_time = Some(5);
+            description.server_type = server_type;
+            description.last_update_time = Some(now);
+            description.last_write_date = Some(last_write_date);
+        }
+
+        if server_type != ServerType::RSPrimary {
+            topology_description.topology_type = TopologyType::ReplicaSetNoPrimary;
+        }
+
+        topology_description.servers.insert(host, server);
+    }
+
+    topology_description
+}
+
+fn read_preference(mode: ReadMode, max_staleness_seconds: i64) -> ReadPreference {
+    let mut read_preference = ReadPreference::new(mode, None);
+    read_preference.max_staleness_seconds = Some(max_staleness_seconds);
+    read_preference
+}
+
+fn host(name: &str) -> Host {
+    connstring::parse_host(name).unwrap()
+}
+
+#[test]
+fn invalid_max_staleness() {
+    let description = replica_set(vec![("a:27017", ServerType::RSSecondary, 0)]);
+
+    let primary = read_preference(ReadMode::Primary, 120);
+    assert!(description.validate_read_preference(&primary).is_err());
+
+    let too_small = read_preference(ReadMode::Secondary, 89);
+    assert!(description.validate_read_preference(&too_small).is_err());
+
+    let smallest = read_preference(ReadMode::Secondary, 90);
+    assert!(description.validate_read_preference(&smallest).is_ok());
+
+    // The bound must also exceed the heartbeat frequency by at least 10 seconds.
+    let mut slow_heartbeat = replica_set(vec![("a:27017", ServerType::RSSecondary, 0)]);
+    slow_heartbeat.heartbeat_frequency_ms = 100000;
+    assert!(slow_heartbeat.validate_read_preference(&smallest).is_err());
+    let bounded = read_preference(ReadMode::Secondary, 110);
+    assert!(slow_heartbeat.validate_read_preference(&bounded).is_ok());
+}
+
+#[test]
+fn stale_secondaries_without_primary() {
+    let description = replica_set(vec![
+        ("a:27017", ServerType::RSSecondary, 1000000),
+        ("b:27017", ServerType::RSSecondary, 1000000 - 200000),
+    ]);
+
+    // a is 10s stale (the heartbeat frequency), b is 210s stale.
+    let (hosts, _) = description
+        .choose_hosts(&read_preference(ReadMode::Secondary, 120))
+        .unwrap();
+    assert_eq!(hosts, vec![host("a:27017")]);
+
+    let (mut hosts, _) = description
+        .choose_hosts(&read_preference(ReadMode::Secondary, 300))
+        .unwrap();
+    hosts.sort_by(|a, b| a.host_name.cmp(&b.host_name));
+    assert_eq!(hosts, vec![host("a:27017"), host("b:27017")]);
+}
+
+#[test]
+fn stale_secondaries_with_primary() {
+    let description = replica_set(vec![
+        ("p:27017", ServerType::RSPrimary, 1000000),
+        ("a:27017", ServerType::RSSecondary, 1000000 - 5000),
+        ("b:27017", ServerType::RSSecondary, 1000000 - 150000),
+    ]);
+
+    // a is 15s stale and b is 160s stale.
+    let (hosts, _) = description
+        .choose_hosts(&read_preference(ReadMode::Secondary, 90))
+        .unwrap();
+    assert_eq!(hosts, vec![host("a:27017")]);
+
+    // The primary is never considered stale.
+    let (mut hosts, _) = description
+        .choose_hosts(&read_preference(ReadMode::Nearest, 90))
+        .unwrap();
+    hosts.sort_by(|a, b| a.host_name.cmp(&b.host_name));
+    assert_eq!(hosts, vec![host("a:27017"), host("p:27017")]);
+}
+
+#[test]
+fn secondary_preferred_falls_back_to_primary() {
+    let description = replica_set(vec![
+        ("p:27017", ServerType::RSPrimary, 1000000),
+        ("a:27017", ServerType::RSSecondary, 1000000 - 150000),
+    ]);
+
+    let (hosts, _) = description
+        .choose_hosts(&read_preference(ReadMode::SecondaryPreferred, 90))
+        .unwrap();
+    assert_eq!(hosts, vec![host("p:27017")]);
+
+    let (hosts, _) = description
+        .choose_hosts(&read_preference(ReadMode::Secondary, 90))
+        .unwrap();
+    assert!(hosts.is_empty());
+}
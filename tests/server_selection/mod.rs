@@ -1,4 +1,5 @@
 pub mod framework;
+pub mod max_staleness;
 pub mod replicasetnoprimary;
 pub mod replicasetwithprimary;
 pub mod sharded;